@@ -209,12 +209,16 @@ export class APIClient {
     );
   }
 
-  async getNodesByUdt(udtScript: UdtScript): Promise<NodesByUdtResponse> {
+  async getNodesByUdt(
+    udtScript: UdtScript,
+    page: number = 0,
+    pageSize: number = 10
+  ): Promise<NodesByUdtResponse> {
     return this.apiRequest<NodesByUdtResponse>(
       `/nodes_by_udt`,
       {
         method: "POST",
-        body: JSON.stringify({ udt: udtScript }),
+        body: JSON.stringify({ udt: udtScript, page, page_size: pageSize }),
       },
       NodesByUdtResponseSchema
     );
@@ -219,9 +219,7 @@ export const ChannelResponseSchema = z.object({
   total_count: z.number(),
 });
 
-export const NodesByUdtResponseSchema = z.object({
-  nodes: z.array(z.string()),
-});
+export const NodesByUdtResponseSchema = NodeResponseSchema;
 
 export type NodeResponse = z.infer<typeof NodeResponseSchema>;
 export type ChannelResponse = z.infer<typeof ChannelResponseSchema>;
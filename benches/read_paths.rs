@@ -0,0 +1,97 @@
+//! Latency of the storage backed read endpoints over a synthetic graph committed through the
+//! collector's ingest path into a temporary SQLite database, no server or Postgres needed.
+//!
+//! `BENCH_NODES` and `BENCH_CHANNELS` size the graph, 2000 nodes and 6000 channels by default.
+
+use std::str::FromStr;
+
+use chrono::Utc;
+use criterion::{Criterion, criterion_group, criterion_main};
+use fiber_dashbord_backend::{
+    Network,
+    http_server::{channel_info, list_channels_hourly, list_nodes_hourly, node_info},
+    pg_write::commit_page,
+    simulate::{SimConfig, Simulation},
+    use_sqlite,
+};
+use salvo::{Router, Service, test::TestClient};
+
+fn env_or<T: FromStr>(var: &str, default: T) -> T {
+    std::env::var(var)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Commit the graph and return the id of one node and the outpoint of one channel.
+async fn populate(path: &std::path::Path) -> (String, String) {
+    use_sqlite(&format!("sqlite://{}", path.display()))
+        .await
+        .expect("Failed to open SQLite database");
+    let net = Network::Mainnet;
+    let mut simulation = Simulation::new(
+        net,
+        SimConfig {
+            nodes: env_or("BENCH_NODES", 2000),
+            channels: env_or("BENCH_CHANNELS", 6000),
+            node_churn: 0.0,
+            channel_churn: 0.0,
+            seed: 1,
+        },
+    );
+    let time = Utc::now();
+    simulation.step(time);
+    let nodes = simulation.graph_nodes(time);
+    let channels = simulation.graph_channels(time);
+    let node_id = String::from_utf8(nodes[0].node_id.to_vec()).unwrap();
+    let outpoint = faster_hex::hex_string(channels[0].channel_outpoint.as_bytes());
+    for page in nodes.chunks(1000) {
+        commit_page(net, page.to_vec(), Vec::new(), &time)
+            .await
+            .unwrap();
+    }
+    for page in channels.chunks(1000) {
+        commit_page(net, Vec::new(), page.to_vec(), &time)
+            .await
+            .unwrap();
+    }
+    (node_id, outpoint)
+}
+
+fn read_paths(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let db = std::env::temp_dir().join(format!("fiber-dashboard-bench-{}.db", std::process::id()));
+    let (node_id, outpoint) = rt.block_on(populate(&db));
+    let service = Service::new(
+        Router::new()
+            .push(Router::with_path("nodes_hourly").get(list_nodes_hourly))
+            .push(Router::with_path("channels_hourly").get(list_channels_hourly))
+            .push(Router::with_path("node_info").get(node_info))
+            .push(Router::with_path("channel_info").get(channel_info)),
+    );
+
+    let mut group = c.benchmark_group("read_paths");
+    for (name, path) in [
+        (
+            "nodes_hourly",
+            "/nodes_hourly?page=0&sort_by=channel_count&order=desc".to_string(),
+        ),
+        ("channels_hourly", "/channels_hourly?page=0".to_string()),
+        ("node_info", format!("/node_info?node_id=0x{}", node_id)),
+        (
+            "channel_info",
+            format!("/channel_info?channel_outpoint=0x{}", outpoint),
+        ),
+    ] {
+        let url = format!("http://127.0.0.1{}", path);
+        group.bench_function(name, |b| {
+            b.to_async(&rt)
+                .iter(|| async { TestClient::get(url.clone()).send(&service).await })
+        });
+    }
+    group.finish();
+    std::fs::remove_file(&db).ok();
+}
+
+criterion_group!(benches, read_paths);
+criterion_main!(benches);
@@ -0,0 +1,445 @@
+-- Operational tables and sql helpers (admin api, api keys, upstream health, script versions, dead letters, collector runs, channel survival, node address scopes, node cohorts, daily udt stats, maintenance mode, jobs, daily capacity reducers, node rankings, change log), applied on every startup.
+
+create table if not exists audit_log (
+    id bigint generated by default as identity primary key,
+    time timestamptz not null default now(),
+    key_id text, -- hex prefix of sha256(token), never the token itself
+    method text not null,
+    action text not null,
+    params jsonb not null,
+    status integer not null,
+    result text
+);
+
+create index if not exists idx_audit_log_action on audit_log(action, id desc);
+
+alter table audit_log add column if not exists client_ip text;
+
+create table if not exists api_keys (
+    id text primary key,
+    name text not null,
+    token_hash text not null unique, -- hex sha256(token)
+    roles text[] not null,
+    created_at timestamptz not null default now(),
+    rotated_at timestamptz,
+    revoked_at timestamptz
+);
+
+alter table api_keys add column if not exists daily_requests bigint;
+alter table api_keys add column if not exists daily_bytes bigint;
+
+create table if not exists api_key_usage (
+    key_id text not null,
+    day date not null,
+    requests bigint not null default 0,
+    bytes bigint not null default 0,
+    primary key (key_id, day)
+);
+
+create table if not exists upstream_status (
+    endpoint text primary key,
+    kind text not null,
+    latency_ms bigint,
+    error_rate double precision not null,
+    window_calls integer not null,
+    consecutive_failures integer not null,
+    circuit text not null,
+    last_success timestamptz,
+    last_error text,
+    last_error_time timestamptz,
+    updated_at timestamptz not null
+);
+
+-- rows of node_infos / channel_infos whose chain hash belongs to the other network
+create table if not exists quarantined_rows (
+    id bigint generated by default as identity primary key,
+    quarantined_at timestamptz not null default now(),
+    source_table text not null,
+    key text not null,
+    chain_hash text not null,
+    time timestamptz not null,
+    data jsonb not null
+);
+
+-- accepted funding / commitment lock scripts per network, see src/script_versions.rs
+create table if not exists script_versions (
+    net text not null, -- mainnet / testnet
+    kind text not null, -- funding / commitment
+    version text not null,
+    code_hash text not null,
+    hash_type text not null,
+    created_at timestamptz not null default now(),
+    primary key (net, kind, code_hash, hash_type)
+);
+
+-- version of the funding lock per channel, channels tracked before versions existed used v1
+do $$
+begin
+    if not exists (select 1 from information_schema.columns
+        where table_name = 'channel_states' and column_name = 'script_version') then
+        alter table channel_states add column script_version text;
+        update channel_states set script_version = 'v1';
+    end if;
+    if not exists (select 1 from information_schema.columns
+        where table_name = 'channel_states_testnet' and column_name = 'script_version') then
+        alter table channel_states_testnet add column script_version text;
+        update channel_states_testnet set script_version = 'v1';
+    end if;
+end
+$$;
+
+-- channel state writes that failed, retried by the collector, see src/pg_write/dead_letter.rs
+create table if not exists channel_update_dead_letters (
+    id bigint generated by default as identity primary key,
+    created_at timestamptz not null default now(),
+    net text not null,
+    kind text not null, -- new_channel / state_update
+    channel_outpoint text not null,
+    payload jsonb not null,
+    error text not null,
+    attempts integer not null default 0,
+    last_attempt_at timestamptz,
+    resolved_at timestamptz
+);
+
+create index if not exists idx_channel_update_dead_letters_pending
+    on channel_update_dead_letters(id) where resolved_at is null;
+
+-- outcome of every collection cycle per phase, see src/pg_write/collector_runs.rs
+create table if not exists collector_runs (
+    id bigint generated by default as identity primary key,
+    net text not null,
+    started_at timestamptz not null,
+    finished_at timestamptz not null default now(),
+    nodes_ok boolean not null,
+    nodes integer,
+    channels_ok boolean not null,
+    channels integer,
+    monitor_ok boolean not null,
+    handed_off integer,
+    errors text[] not null default '{}'
+);
+
+create index if not exists idx_collector_runs_net_started_at
+    on collector_runs(net, started_at desc);
+
+-- kaplan-meier survival curves per cohort month, recomputed daily, see src/survival.rs
+create table if not exists channel_survival (
+    net text not null,
+    cohort date not null,
+    day integer not null,
+    at_risk integer not null,
+    closed integer not null,
+    survival double precision not null,
+    computed_at timestamptz not null,
+    primary key (net, cohort, day)
+);
+
+-- headline numbers with 7 and 30 day changes, see src/kpis.rs
+create table if not exists network_kpis (
+    net text primary key,
+    day date not null,
+    kpis jsonb not null,
+    computed_at timestamptz not null
+);
+
+-- whether a node announces global, only private / loopback, or no ip addresses,
+-- only global ones are geolocated
+create table if not exists node_address_scopes (
+    net text not null,
+    node_id text not null,
+    scope text not null, -- global / private / no_ip
+    updated_at timestamptz not null,
+    primary key (net, node_id)
+);
+
+-- nodes of each monthly cohort online month_offset months later, recomputed monthly,
+-- see src/cohorts.rs
+create table if not exists node_cohorts (
+    net text not null,
+    cohort date not null,
+    month_offset integer not null,
+    nodes integer not null,
+    computed_at timestamptz not null,
+    primary key (net, cohort, month_offset)
+);
+
+-- nodes supporting and online channels denominated in each udt per day, written with the
+-- daily summary, udt_info_id refers to the udt_infos table of net
+create table if not exists daily_udt_stats (
+    net text not null,
+    day date not null,
+    udt_info_id integer not null,
+    nodes integer not null,
+    channels integer not null,
+    primary key (net, day, udt_info_id)
+);
+
+-- single row, see src/maintenance.rs
+create table if not exists maintenance (
+    id boolean primary key default true check (id),
+    enabled boolean not null,
+    reason text,
+    retry_after_secs integer not null,
+    updated_at timestamptz not null
+);
+
+-- channel capacity distribution per asset and day, written by the daily reducers in
+-- src/pg_write/reducers.rs, capacities in shannons
+create table if not exists daily_capacity_percentiles (
+    net text not null,
+    day date not null,
+    name text not null, -- ckb or the udt name
+    p10 bigint not null,
+    p25 bigint not null,
+    p75 bigint not null,
+    p90 bigint not null,
+    primary key (net, day, name)
+);
+
+create table if not exists daily_capacity_gini (
+    net text not null,
+    day date not null,
+    name text not null,
+    channels integer not null,
+    gini double precision not null,
+    primary key (net, day, name)
+);
+
+-- channels per capacity magnitude (ckb, 10^ik buckets of stats::magnitude_bucket) per asset
+create table if not exists daily_capacity_histograms (
+    net text not null,
+    day date not null,
+    name text not null,
+    counts bigint[] not null,
+    primary key (net, day, name)
+);
+
+-- collector jobs and their runs, see src/scheduler.rs
+create table if not exists jobs (
+    name text primary key,
+    schedule text not null,
+    next_run_at timestamptz,
+    requested_at timestamptz, -- manual run waiting for the collector
+    updated_at timestamptz not null
+);
+
+create table if not exists job_runs (
+    id bigint generated by default as identity primary key,
+    name text not null,
+    manual boolean not null,
+    started_at timestamptz not null,
+    finished_at timestamptz,
+    status text not null, -- running / ok / failed / interrupted
+    error text
+);
+
+create index if not exists idx_job_runs_name_started_at on job_runs(name, started_at desc);
+
+-- big endian hex amounts (u64 / u128 without 0x) as numeric, for aggregating them in sql
+create or replace function hex_to_numeric(hex text) returns numeric
+language sql immutable strict as $$
+    select coalesce(sum(
+        (position(lower(substr(hex, i, 1)) in '0123456789abcdef') - 1)::numeric
+            * power(16::numeric, length(hex) - i)
+    ), 0)
+    from generate_series(1, length(hex)) as i
+$$;
+
+-- node scores per metric (capacity / channels / pagerank) over the online channel graph,
+-- recomputed hourly, see src/rankings.rs
+create table if not exists node_rankings (
+    net text not null,
+    metric text not null,
+    node_id text not null,
+    score double precision not null,
+    rank integer not null,
+    computed_at timestamptz not null,
+    primary key (net, metric, node_id)
+);
+
+create index if not exists idx_node_rankings_net_metric_rank
+    on node_rankings(net, metric, rank);
+
+-- incremental change log of nodes, channels and channel states, see src/changes.rs
+create table if not exists changes (
+    id bigint generated by default as identity primary key,
+    net text not null,
+    kind text not null, -- node / channel / channel_state
+    key text not null,
+    change jsonb not null,
+    recorded_at timestamptz not null default now()
+);
+
+create index if not exists idx_changes_net_id on changes(net, id);
+create index if not exists idx_changes_recorded_at on changes(recorded_at);
+
+-- digest of the last recorded version of every key, unchanged versions are not recorded again
+create table if not exists change_heads (
+    net text not null,
+    kind text not null,
+    key text not null,
+    digest text not null,
+    primary key (net, kind, key)
+);
+
+-- highest id pruned from changes per network, older cursors have to resync
+create table if not exists change_prunes (
+    net text primary key,
+    pruned_through bigint not null
+);
+
+-- gossip channels with a spent funding cell and funding cells never announced, see
+-- src/reconcile.rs
+create table if not exists graph_chain_discrepancies (
+    net text not null,
+    channel_outpoint text not null,
+    kind text not null,
+    first_seen timestamptz not null,
+    last_seen timestamptz not null,
+    resolved_at timestamptz,
+    primary key (net, channel_outpoint, kind)
+);
+create index if not exists idx_graph_chain_discrepancies_open
+    on graph_chain_discrepancies(net, last_seen desc) where resolved_at is null;
+
+-- channels found by the chain scanner that were never announced, see
+-- src/pg_write/chain_scanner.rs
+alter table channel_states add column if not exists unannounced boolean not null default false;
+alter table channel_states_testnet
+    add column if not exists unannounced boolean not null default false;
+
+-- live funding cells and the unannounced ones among them per day, capacities in shannons
+create table if not exists private_channel_share (
+    net text not null,
+    day date not null,
+    funding_cells integer not null,
+    unannounced integer not null,
+    capacity bigint not null,
+    unannounced_capacity bigint not null,
+    updated_at timestamptz not null,
+    primary key (net, day)
+);
+
+-- open channels per funding lock args pattern per day, see src/fingerprints.rs
+create table if not exists daily_funding_fingerprints (
+    net text not null,
+    day date not null,
+    script_version text not null,
+    args_len integer not null,
+    prefix text not null,
+    channels bigint not null,
+    unannounced bigint not null,
+    capacity bigint not null,
+    primary key (net, day, script_version, args_len, prefix)
+);
+
+-- outcome of every hourly node location refresh, see src/geo_refresh.rs
+create table if not exists geo_refresh_runs (
+    id bigint generated by default as identity primary key,
+    started_at timestamptz not null,
+    finished_at timestamptz not null,
+    due integer not null,
+    refreshed integer not null,
+    changed integer not null,
+    failed integer not null,
+    rows_updated bigint not null
+);
+
+-- funding tx hash and output index of the molecule encoded outpoint, to look channels up by
+-- the funding transaction like explorers do
+alter table channel_states add column if not exists funding_tx_hash text
+    generated always as (left(channel_outpoint, 64)) stored;
+alter table channel_states add column if not exists funding_index integer
+    generated always as (('x' || substr(channel_outpoint, 71, 2) || substr(channel_outpoint, 69, 2)
+        || substr(channel_outpoint, 67, 2) || substr(channel_outpoint, 65, 2))::bit(32)::integer) stored;
+alter table channel_states_testnet add column if not exists funding_tx_hash text
+    generated always as (left(channel_outpoint, 64)) stored;
+alter table channel_states_testnet add column if not exists funding_index integer
+    generated always as (('x' || substr(channel_outpoint, 71, 2) || substr(channel_outpoint, 69, 2)
+        || substr(channel_outpoint, 67, 2) || substr(channel_outpoint, 65, 2))::bit(32)::integer) stored;
+create index if not exists idx_channel_states_funding_tx_hash on channel_states(funding_tx_hash);
+create index if not exists idx_channel_states_testnet_funding_tx_hash
+    on channel_states_testnet(funding_tx_hash);
+
+-- graph halves found implausibly small against the last successful cycle, see
+-- src/pg_write/snapshot_guard.rs
+create table if not exists snapshot_anomalies (
+    id bigint generated by default as identity primary key,
+    net text not null,
+    half text not null, -- nodes / channels
+    detected_at timestamptz not null,
+    previous integer not null,
+    observed integer not null,
+    accepted boolean not null -- committed anyway after repeated drops
+);
+
+create index if not exists idx_snapshot_anomalies_net_detected_at
+    on snapshot_anomalies(net, detected_at desc);
+
+-- udts are updated when nodes announce another name or auto accept amount, the latest
+-- announcement wins, see UdtInfos::insert_batch in src/pg_write/types.rs
+alter table udt_infos add column if not exists updated_at timestamptz;
+alter table udt_infos_testnet add column if not exists updated_at timestamptz;
+
+-- missing auto accept amounts used to be stored as the text 'NULL'
+update udt_infos set auto_accept_amount = null where auto_accept_amount = 'NULL';
+update udt_infos_testnet set auto_accept_amount = null where auto_accept_amount = 'NULL';
+
+-- previous values of every udt update, udt_info_id refers to the udt_infos table of net
+create table if not exists udt_info_history (
+    id bigint generated by default as identity primary key,
+    net text not null,
+    udt_info_id integer not null,
+    changed_at timestamptz not null,
+    old_name text not null,
+    new_name text not null,
+    old_auto_accept_amount text,
+    new_auto_accept_amount text
+);
+
+create index if not exists idx_udt_info_history_net_udt
+    on udt_info_history(net, udt_info_id, changed_at desc);
+
+-- udt corrections through the admin api, see src/pg_write/udt_edits.rs
+alter table udt_infos add column if not exists name_locked boolean not null default false;
+alter table udt_infos_testnet add column if not exists name_locked boolean not null default false;
+alter table udt_infos add column if not exists hidden boolean not null default false;
+alter table udt_infos_testnet add column if not exists hidden boolean not null default false;
+
+-- scripts of udts merged into another one, udt_info_id refers to the udt_infos table of net
+create table if not exists udt_info_aliases (
+    net text not null,
+    code_hash text not null,
+    hash_type text not null,
+    args text not null,
+    udt_info_id integer not null,
+    merged_id integer not null, -- id of the deleted row
+    merged_at timestamptz not null,
+    primary key (net, code_hash, hash_type, args)
+);
+
+-- when the node last announced the udt, refreshed every cycle, reads skip relations older than
+-- UDT_RELATION_WINDOW_HOURS and the daily expire_udt_relations job deletes the long unconfirmed
+alter table node_udt_relations add column if not exists last_confirmed timestamptz not null default now();
+alter table node_udt_relations_testnet add column if not exists last_confirmed timestamptz not null default now();
+create index if not exists idx_node_udt_relations_node_udt
+    on node_udt_relations(node_id, udt_info_id);
+create index if not exists idx_node_udt_relations_node_udt_testnet
+    on node_udt_relations_testnet(node_id, udt_info_id);
+
+-- weekly network reports, see src/reports.rs
+create table if not exists weekly_reports (
+    net text not null,
+    week date not null, -- monday the week starts on
+    html text not null,
+    generated_at timestamptz not null,
+    primary key (net, week)
+);
+
+-- digest of the schema files last applied by `fiber-dashbord migrate`, checked at startup
+create table if not exists schema_version (
+    id boolean primary key default true check (id),
+    digest text not null,
+    migrated_at timestamptz not null
+);
@@ -0,0 +1,41 @@
+-- Composite indexes for list/filter query paths, applied on every startup so existing
+-- databases pick up new ones. Keep every statement idempotent.
+
+create index if not exists idx_mv_online_channels_node1_bucket on mv_online_channels(node1, bucket);
+create index if not exists idx_mv_online_channels_node2_bucket on mv_online_channels(node2, bucket);
+create index if not exists idx_mv_online_nodes_bucket_node_id on mv_online_nodes(bucket desc, node_id);
+create index if not exists idx_mv_online_nodes_region_bucket on mv_online_nodes(country_or_region, bucket);
+create index if not exists idx_channel_states_state_capacity on channel_states(state, capacity);
+create index if not exists idx_channel_states_state_create_time on channel_states(state, create_time);
+create index if not exists idx_channel_states_state_last_commit_time on channel_states(state, last_commit_time);
+create index if not exists idx_channel_states_capacity_outpoint on channel_states(capacity desc, channel_outpoint);
+create index if not exists idx_mv_online_channels_udt_bucket on mv_online_channels(udt_type_script, bucket);
+create index if not exists idx_channels_hourly_udt_bucket on online_channels_hourly(udt_type_script, bucket desc);
+
+create index if not exists idx_mv_online_channels_node1_bucket_testnet on mv_online_channels_testnet(node1, bucket);
+create index if not exists idx_mv_online_channels_node2_bucket_testnet on mv_online_channels_testnet(node2, bucket);
+create index if not exists idx_mv_online_nodes_bucket_node_id_testnet on mv_online_nodes_testnet(bucket desc, node_id);
+create index if not exists idx_mv_online_nodes_region_bucket_testnet on mv_online_nodes_testnet(country_or_region, bucket);
+create index if not exists idx_channel_states_state_capacity_testnet on channel_states_testnet(state, capacity);
+create index if not exists idx_channel_states_state_create_time_testnet on channel_states_testnet(state, create_time);
+create index if not exists idx_channel_states_state_last_commit_time_testnet on channel_states_testnet(state, last_commit_time);
+create index if not exists idx_channel_states_capacity_outpoint_testnet on channel_states_testnet(capacity desc, channel_outpoint);
+create index if not exists idx_mv_online_channels_udt_bucket_testnet on mv_online_channels_testnet(udt_type_script, bucket);
+create index if not exists idx_channels_hourly_udt_bucket_testnet on online_channels_hourly_testnet(udt_type_script, bucket desc);
+
+-- Repeated monitor cycles could insert the same channel transaction twice, drop the copies
+-- once before the unique index makes `channel_txs` inserts idempotent.
+do $$
+begin
+    if to_regclass('uniq_channel_txs_outpoint_tx_hash') is null then
+        delete from channel_txs a using channel_txs b
+        where a.channel_outpoint = b.channel_outpoint and a.tx_hash = b.tx_hash and a.ctid > b.ctid;
+        create unique index uniq_channel_txs_outpoint_tx_hash on channel_txs(channel_outpoint, tx_hash);
+    end if;
+    if to_regclass('uniq_channel_txs_outpoint_tx_hash_testnet') is null then
+        delete from channel_txs_testnet a using channel_txs_testnet b
+        where a.channel_outpoint = b.channel_outpoint and a.tx_hash = b.tx_hash and a.ctid > b.ctid;
+        create unique index uniq_channel_txs_outpoint_tx_hash_testnet on channel_txs_testnet(channel_outpoint, tx_hash);
+    end if;
+end
+$$;
@@ -0,0 +1,146 @@
+-- Schema of the SQLite lite mode, a parallel of create_table.sql without TimescaleDB.
+-- Hypertables become plain tables and there are no continuous aggregates or materialized
+-- views, online nodes and channels are computed from the latest row at query time.
+-- Timestamps are stored as text, keep this file in sync with create_table.sql.
+
+create table if not exists udt_infos (
+    id integer primary key,
+    name text not null,
+    code_hash text not null,
+    hash_type text not null,
+    args text not null,
+    auto_accept_amount text -- u64 with hexadecimal format
+);
+
+create table if not exists udt_dep (
+    outpoint_tx_hash text,
+    outpoint_index text,
+    dep_type text,
+    code_hash text,
+    hash_type text,
+    args text,
+    udt_info_id integer not null references udt_infos(id)
+);
+
+create table if not exists node_udt_relations (
+    node_id text not null,
+    udt_info_id integer not null references udt_infos(id)
+);
+
+create unique index if not exists idx_node_udt_relations_pair on node_udt_relations(node_id, udt_info_id);
+
+create table if not exists node_infos (
+    time text not null,
+    node_name text not null,
+    node_id text not null,
+    addresses text not null,
+    announce_timestamp text not null,
+    chain_hash text not null,
+    auto_accept_min_ckb_funding_amount text not null,
+    country_or_region text,
+    city text,
+    region text,
+    loc text
+);
+
+create unique index if not exists idx_node_infos_node_id_time on node_infos(node_id, time desc);
+create index if not exists idx_node_infos_time on node_infos(time);
+
+create table if not exists channel_infos (
+    time text not null,
+    channel_outpoint text not null,
+    node1 text not null,
+    node2 text not null,
+    capacity text not null, -- u128 with hexadecimal format
+    chain_hash text not null,
+    udt_type_script integer, -- foreign key to udt_infos
+    created_timestamp text not null,
+    update_of_node1_timestamp text,
+    update_of_node1_enabled boolean,
+    update_of_node1_outbound_liquidity text,
+    update_of_node1_tlc_expiry_delta text,
+    update_of_node1_tlc_minimum_value text,
+    update_of_node1_fee_rate text,
+    update_of_node2_timestamp text,
+    update_of_node2_enabled boolean,
+    update_of_node2_outbound_liquidity text,
+    update_of_node2_tlc_expiry_delta text,
+    update_of_node2_tlc_minimum_value text,
+    update_of_node2_fee_rate text
+);
+
+create unique index if not exists idx_channel_infos_outpoint_time on channel_infos(channel_outpoint, time desc);
+create index if not exists idx_channel_infos_time on channel_infos(time);
+
+create table if not exists udt_infos_testnet (
+    id integer primary key,
+    name text not null,
+    code_hash text not null,
+    hash_type text not null,
+    args text not null,
+    auto_accept_amount text -- u64 with hexadecimal format
+);
+
+create table if not exists udt_dep_testnet (
+    outpoint_tx_hash text,
+    outpoint_index text,
+    dep_type text,
+    code_hash text,
+    hash_type text,
+    args text,
+    udt_info_id integer not null references udt_infos_testnet(id)
+);
+
+create table if not exists node_udt_relations_testnet (
+    node_id text not null,
+    udt_info_id integer not null references udt_infos_testnet(id)
+);
+
+create unique index if not exists idx_node_udt_relations_testnet_pair on node_udt_relations_testnet(node_id, udt_info_id);
+
+create table if not exists node_infos_testnet (
+    time text not null,
+    node_name text not null,
+    node_id text not null,
+    addresses text not null,
+    announce_timestamp text not null,
+    chain_hash text not null,
+    auto_accept_min_ckb_funding_amount text not null,
+    country_or_region text,
+    city text,
+    region text,
+    loc text
+);
+
+create unique index if not exists idx_node_infos_testnet_node_id_time on node_infos_testnet(node_id, time desc);
+create index if not exists idx_node_infos_testnet_time on node_infos_testnet(time);
+
+create table if not exists channel_infos_testnet (
+    time text not null,
+    channel_outpoint text not null,
+    node1 text not null,
+    node2 text not null,
+    capacity text not null, -- u128 with hexadecimal format
+    chain_hash text not null,
+    udt_type_script integer, -- foreign key to udt_infos
+    created_timestamp text not null,
+    update_of_node1_timestamp text,
+    update_of_node1_enabled boolean,
+    update_of_node1_outbound_liquidity text,
+    update_of_node1_tlc_expiry_delta text,
+    update_of_node1_tlc_minimum_value text,
+    update_of_node1_fee_rate text,
+    update_of_node2_timestamp text,
+    update_of_node2_enabled boolean,
+    update_of_node2_outbound_liquidity text,
+    update_of_node2_tlc_expiry_delta text,
+    update_of_node2_tlc_minimum_value text,
+    update_of_node2_fee_rate text
+);
+
+create unique index if not exists idx_channel_infos_testnet_outpoint_time on channel_infos_testnet(channel_outpoint, time desc);
+create index if not exists idx_channel_infos_testnet_time on channel_infos_testnet(time);
+
+-- missing auto accept amounts used to be stored as the text 'NULL'
+update udt_infos set auto_accept_amount = null where auto_accept_amount = 'NULL';
+update udt_infos_testnet set auto_accept_amount = null where auto_accept_amount = 'NULL';
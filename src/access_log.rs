@@ -0,0 +1,293 @@
+//! Structured access log of the http api.
+//!
+//! One json line per sampled request under the `access` log target (`RUST_LOG=access=info`),
+//! and appended to `ACCESS_LOG_FILE` when set, rotated once it grows past
+//! `ACCESS_LOG_MAX_BYTES`. `ACCESS_LOG_SAMPLE_RATE` keeps that share of requests, server
+//! errors are always kept. Query parameters are only logged as a hash, headers only when
+//! listed in `ACCESS_LOG_HEADERS`, and credentials (`authorization`, `cookie`, ...) are
+//! replaced by `<redacted>` either way. `ACCESS_LOG_ANONYMIZE_IP=true` drops the host part
+//! of client addresses.
+
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{
+        LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+use salvo::{
+    Depot, FlowCtrl, Request, Response, handler,
+    http::{HeaderMap, StatusCode},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::{File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::mpsc,
+};
+
+use crate::{
+    auth::{API_KEY, ApiKey},
+    client_ip::client_ip,
+    config,
+};
+
+/// Lines waiting for the file sink, further lines are dropped while it lags behind.
+const FILE_QUEUE: usize = 10_000;
+const SENSITIVE_HEADERS: [&str; 5] = [
+    "authorization",
+    "cookie",
+    "set-cookie",
+    "proxy-authorization",
+    "x-api-key",
+];
+
+static ACCESS_LOG_SAMPLE_RATE: LazyLock<f64> = LazyLock::new(|| {
+    config::parse_var::<f64>("ACCESS_LOG_SAMPLE_RATE")
+        .unwrap_or(1.0)
+        .clamp(0.0, 1.0)
+});
+static ACCESS_LOG_HEADERS: LazyLock<Vec<String>> = LazyLock::new(|| {
+    config::var("ACCESS_LOG_HEADERS")
+        .unwrap_or("user-agent".to_string())
+        .split(',')
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+});
+static ACCESS_LOG_ANONYMIZE_IP: LazyLock<bool> =
+    LazyLock::new(|| config::parse_var("ACCESS_LOG_ANONYMIZE_IP").unwrap_or(false));
+static ACCESS_LOG_FILE: LazyLock<Option<PathBuf>> =
+    LazyLock::new(|| config::var("ACCESS_LOG_FILE").map(PathBuf::from));
+static ACCESS_LOG_MAX_BYTES: LazyLock<u64> =
+    LazyLock::new(|| config::parse_var("ACCESS_LOG_MAX_BYTES").unwrap_or(100 * 1024 * 1024));
+/// Rotated files kept next to the current one, `access.log.1` being the newest.
+static ACCESS_LOG_MAX_FILES: LazyLock<usize> =
+    LazyLock::new(|| config::parse_var("ACCESS_LOG_MAX_FILES").unwrap_or(5));
+
+static SAMPLED: AtomicU64 = AtomicU64::new(0);
+
+static FILE_SINK: LazyLock<Option<mpsc::Sender<String>>> = LazyLock::new(|| {
+    let path = ACCESS_LOG_FILE.clone()?;
+    let (sender, receiver) = mpsc::channel(FILE_QUEUE);
+    tokio::spawn(write_file(path, receiver));
+    Some(sender)
+});
+
+#[derive(Debug, Serialize)]
+struct AccessRecord<'a> {
+    time: DateTime<Utc>,
+    method: &'a str,
+    path: &'a str,
+    /// Hex prefix of the sha256 of the sorted query, equal queries share it.
+    params_hash: Option<String>,
+    status: u16,
+    latency_ms: f64,
+    client_ip: Option<IpAddr>,
+    key_id: Option<&'a str>,
+    headers: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Whether the `n`th request is kept at `rate`, spreading kept requests evenly.
+fn sampled(n: u64, rate: f64) -> bool {
+    ((n + 1) as f64 * rate).floor() > (n as f64 * rate).floor()
+}
+
+fn params_hash(mut pairs: Vec<(&str, &str)>) -> Option<String> {
+    if pairs.is_empty() {
+        return None;
+    }
+    pairs.sort_unstable();
+    let mut hasher = Sha256::new();
+    for (name, value) in pairs {
+        hasher.update(name.as_bytes());
+        hasher.update(b"=");
+        hasher.update(value.as_bytes());
+        hasher.update(b"&");
+    }
+    let mut hash = faster_hex::hex_string(&hasher.finalize());
+    hash.truncate(16);
+    Some(hash)
+}
+
+/// Headers listed in `names`, credentials redacted.
+fn scrub_headers(
+    headers: &HeaderMap,
+    names: &[String],
+) -> serde_json::Map<String, serde_json::Value> {
+    names
+        .iter()
+        .filter_map(|name| {
+            let value = headers.get(name.as_str())?;
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                "<redacted>".to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            Some((name.clone(), value.into()))
+        })
+        .collect()
+}
+
+/// Zero the host part, keeping the /24 of ipv4 and the /48 of ipv6 addresses.
+fn anonymize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => IpAddr::V4((u32::from(ip) & 0xffff_ff00).into()),
+        IpAddr::V6(ip) => IpAddr::V6((u128::from(ip) & (u128::MAX << 80)).into()),
+    }
+}
+
+async fn open_append(path: &Path) -> std::io::Result<(File, u64)> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    let size = file.metadata().await?.len();
+    Ok((file, size))
+}
+
+/// Shift `path.1 .. path.N-1` up by one and move the current file to `path.1`.
+async fn rotate(path: &Path) -> std::io::Result<()> {
+    let rotated = |n: usize| PathBuf::from(format!("{}.{}", path.display(), n));
+    let max_files = (*ACCESS_LOG_MAX_FILES).max(1);
+    let _ = tokio::fs::remove_file(rotated(max_files)).await;
+    for n in (1..max_files).rev() {
+        let _ = tokio::fs::rename(rotated(n), rotated(n + 1)).await;
+    }
+    tokio::fs::rename(path, rotated(1)).await
+}
+
+async fn write_file(path: PathBuf, mut lines: mpsc::Receiver<String>) {
+    let mut file = None;
+    while let Some(line) = lines.recv().await {
+        if file.is_none() {
+            match open_append(&path).await {
+                Ok(opened) => file = Some(opened),
+                Err(e) => {
+                    log::error!("Failed to open access log {}: {}", path.display(), e);
+                    continue;
+                }
+            }
+        }
+        let (writer, size) = file.as_mut().unwrap();
+        if let Err(e) = writer.write_all(line.as_bytes()).await {
+            log::error!("Failed to write access log {}: {}", path.display(), e);
+            file = None;
+            continue;
+        }
+        *size += line.len() as u64;
+        if *size >= *ACCESS_LOG_MAX_BYTES {
+            let _ = writer.flush().await;
+            file = None;
+            if let Err(e) = rotate(&path).await {
+                log::error!("Failed to rotate access log {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Log the request once answered, must run after [`crate::client_ip::resolve_client_ip`].
+#[handler]
+pub async fn access_log(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let started = Instant::now();
+    ctrl.call_next(req, depot, res).await;
+
+    let status = res.status_code.unwrap_or(StatusCode::OK);
+    let n = SAMPLED.fetch_add(1, Ordering::Relaxed);
+    if !status.is_server_error() && !sampled(n, *ACCESS_LOG_SAMPLE_RATE) {
+        return;
+    }
+    let to_file = FILE_SINK.as_ref();
+    if to_file.is_none() && !log::log_enabled!(target: "access", log::Level::Info) {
+        return;
+    }
+
+    let queries = req.queries();
+    let record = AccessRecord {
+        time: Utc::now(),
+        method: req.method().as_str(),
+        path: req.uri().path(),
+        params_hash: params_hash(
+            queries
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect(),
+        ),
+        status: status.as_u16(),
+        latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+        client_ip: client_ip(depot).map(|ip| {
+            if *ACCESS_LOG_ANONYMIZE_IP {
+                anonymize(ip)
+            } else {
+                ip
+            }
+        }),
+        key_id: depot.get::<ApiKey>(API_KEY).ok().map(|key| key.id.as_str()),
+        headers: scrub_headers(req.headers(), &ACCESS_LOG_HEADERS),
+    };
+    let Ok(line) = serde_json::to_string(&record) else {
+        return;
+    };
+    log::info!(target: "access", "{}", line);
+    if let Some(sink) = to_file
+        && sink.try_send(line + "\n").is_err()
+    {
+        log::debug!("Access log file sink is full, dropping a line");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use salvo::http::{HeaderMap, HeaderValue};
+
+    use super::{anonymize, params_hash, sampled, scrub_headers};
+
+    #[test]
+    fn sampling_keeps_the_configured_share() {
+        assert_eq!((0..1000).filter(|n| sampled(*n, 0.1)).count(), 100);
+        assert_eq!((0..1000).filter(|n| sampled(*n, 1.0)).count(), 1000);
+        assert_eq!((0..1000).filter(|n| sampled(*n, 0.0)).count(), 0);
+    }
+
+    #[test]
+    fn params_hash_ignores_order() {
+        assert_eq!(
+            params_hash(vec![("page", "1"), ("net", "testnet")]),
+            params_hash(vec![("net", "testnet"), ("page", "1")])
+        );
+        assert_ne!(
+            params_hash(vec![("page", "1")]),
+            params_hash(vec![("page", "2")])
+        );
+        assert_eq!(params_hash(vec![]), None);
+    }
+
+    #[test]
+    fn credentials_are_redacted() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "authorization",
+            HeaderValue::from_static("Bearer fdk_secret"),
+        );
+        headers.insert("user-agent", HeaderValue::from_static("curl/8.0"));
+        let names = ["authorization".to_string(), "user-agent".to_string()];
+        let scrubbed = scrub_headers(&headers, &names);
+        assert_eq!(scrubbed["authorization"], "<redacted>");
+        assert_eq!(scrubbed["user-agent"], "curl/8.0");
+        assert_eq!(
+            anonymize("192.0.2.77".parse().unwrap()).to_string(),
+            "192.0.2.0"
+        );
+    }
+}
@@ -0,0 +1,807 @@
+use salvo::{
+    Depot, FlowCtrl, Request, Response, handler,
+    http::{ResBody, StatusCode},
+    macros::Extractible,
+};
+use serde::{Deserialize, Serialize};
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::{
+    ENABLED_NETWORKS, Network, archive, audit,
+    auth::{self, API_KEY, ApiKey, KeyQuota, Role},
+    client_ip::client_ip,
+    doctor, export, get_pg_pool, maintenance,
+    pg_read::{ExplainEndpoint, PAGE_SIZE, explain_endpoint},
+    pg_write::{
+        commit_snapshot, dead_letter, dedup_channels, dedup_nodes, snapshot_guard, udt_edits,
+    },
+    reconcile::{self, DiscrepancyKind},
+    scheduler::{self, RequestOutcome},
+};
+
+/// Depot flag set by handlers whose response carries a secret, keeps it out of `audit_log`.
+const REDACT_RESULT: &str = "audit_redact_result";
+
+/// Record every authorized admin call in `audit_log`, must run after [`auth::authenticate`].
+#[handler]
+pub async fn audit_admin_call(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let method = req.method().to_string();
+    let action = req.uri().path().to_string();
+    let mut params = req
+        .queries()
+        .iter()
+        .map(|(k, v)| (k.clone(), serde_json::Value::from(v.as_str())))
+        .collect::<serde_json::Map<_, _>>();
+    if let Ok(body) = req.payload().await
+        && !body.is_empty()
+    {
+        let body = serde_json::from_slice(body)
+            .unwrap_or_else(|_| String::from_utf8_lossy(body).into_owned().into());
+        params.insert("body".to_string(), body);
+    }
+
+    ctrl.call_next(req, depot, res).await;
+
+    let status = res.status_code.unwrap_or(StatusCode::OK).as_u16();
+    let result = match res.take_body() {
+        body if depot.contains_key(REDACT_RESULT) => {
+            res.body(body);
+            Some("<redacted>".to_string())
+        }
+        ResBody::Once(bytes) => {
+            let result = String::from_utf8_lossy(&bytes).into_owned();
+            res.body(ResBody::Once(bytes));
+            Some(result)
+        }
+        body => {
+            res.body(body);
+            None
+        }
+    };
+    let key_id = depot.get::<ApiKey>(API_KEY).ok().map(|key| key.id.clone());
+    if let Err(e) = audit::record(
+        get_pg_pool(),
+        key_id.as_deref(),
+        &method,
+        &action,
+        &params.into(),
+        status,
+        result.as_deref(),
+        client_ip(depot),
+    )
+    .await
+    {
+        log::error!("Failed to record audit log of {} {}: {}", method, action, e);
+    }
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct DoctorParams {
+    net: Option<Network>,
+}
+
+async fn run_doctor(
+    req: &mut Request,
+    depot: &mut Depot,
+    fix: bool,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<DoctorParams>(depot).await?;
+    let nets = match params.net {
+        Some(net) => vec![net],
+        None => ENABLED_NETWORKS.clone(),
+    };
+    let report = doctor::run(get_pg_pool(), &nets, fix).await.map_err(|e| {
+        log::error!("Failed to run doctor: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to run doctor"))
+    })?;
+    Ok(serde_json::to_string(&report)?)
+}
+
+#[handler]
+pub async fn doctor_report(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    run_doctor(req, depot, false).await
+}
+
+/// Run the doctor and fix what it can, deleting orphan channel states and quarantining rows
+/// of the wrong network.
+#[handler]
+pub async fn doctor_fix(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    run_doctor(req, depot, true).await
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct ArchiveListParams {
+    #[serde(default)]
+    net: Network,
+}
+
+#[handler]
+pub async fn list_archives(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<ArchiveListParams>(depot).await?;
+    let names = archive::list(params.net).await.map_err(|e| {
+        log::error!("Failed to list rpc archives: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to list rpc archives"))
+    })?;
+    Ok(serde_json::to_string(&names)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "body")))]
+struct ReplayParams {
+    #[serde(default)]
+    net: Network,
+    /// Archive name as returned by `/admin/archives`.
+    name: String,
+    /// Commit time of the replayed rows, defaults to the time the payload was fetched.
+    time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReplayResult {
+    name: String,
+    time: DateTime<Utc>,
+    nodes: usize,
+    channels: usize,
+}
+
+/// Feed an archived rpc payload through the normal ingestion pipeline.
+#[handler]
+pub async fn replay_archive(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<ReplayParams>(depot).await?;
+    let snapshot = match archive::read(params.net, &params.name).await {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            log::warn!("Failed to read rpc archive {}: {}", params.name, e);
+            res.status_code(StatusCode::NOT_FOUND);
+            return Ok(String::new());
+        }
+    };
+    let (nodes, channels) = snapshot.parse().map_err(|e| {
+        log::error!("Failed to parse rpc archive {}: {}", params.name, e);
+        salvo::Error::Io(std::io::Error::other(format!(
+            "Failed to parse rpc archive: {}",
+            e
+        )))
+    })?;
+    let nodes = dedup_nodes(params.net, nodes);
+    let channels = dedup_channels(params.net, channels);
+    let time = params.time.unwrap_or(snapshot.time);
+    let (nodes, channels) = commit_snapshot(params.net, nodes, channels, &time)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to replay rpc archive {}: {}", params.name, e);
+            salvo::Error::Io(std::io::Error::other("Failed to replay rpc archive"))
+        })?;
+    Ok(serde_json::to_string(&ReplayResult {
+        name: params.name,
+        time,
+        nodes,
+        channels,
+    })?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct ExportParams {
+    day: NaiveDate,
+    net: Option<Network>,
+}
+
+/// Run the daily export for one day, used to backfill history into the export targets.
+#[handler]
+pub async fn export_day(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<ExportParams>(depot).await?;
+    if !export::enabled() {
+        res.status_code(StatusCode::SERVICE_UNAVAILABLE);
+        return Ok("No export target configured".to_string());
+    }
+    let nets = match params.net {
+        Some(net) => vec![net],
+        None => ENABLED_NETWORKS.clone(),
+    };
+    let keys = export::export_day(get_pg_pool(), params.day, nets.iter())
+        .await
+        .map_err(|e| {
+            log::error!("Failed to export {}: {}", params.day, e);
+            salvo::Error::Io(std::io::Error::other("Failed to export"))
+        })?;
+    Ok(serde_json::to_string(&keys)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct ExplainParams {
+    endpoint: ExplainEndpoint,
+    #[serde(default)]
+    net: Network,
+    #[serde(default)]
+    analyze: bool,
+}
+
+#[handler]
+pub async fn explain(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<ExplainParams>(depot).await?;
+    let explained = explain_endpoint(get_pg_pool(), params.endpoint, params.net, params.analyze)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to explain {:?}: {}", params.endpoint, e);
+            salvo::Error::Io(std::io::Error::other("Failed to explain"))
+        })?;
+    Ok(serde_json::to_string(&explained)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct AuditLogParams {
+    #[serde(default)]
+    page: usize,
+    page_size: Option<usize>,
+    /// Only entries of this path, e.g. `/admin/export`.
+    action: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct AuditLogPage {
+    next_page: usize,
+    entries: Vec<audit::AuditEntry>,
+    total_count: usize,
+}
+
+#[handler]
+pub async fn audit_log(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<AuditLogParams>(depot).await?;
+    let page_size = std::cmp::min(params.page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
+    let (entries, next_page, total_count) = audit::list(
+        get_pg_pool(),
+        params.action.as_deref(),
+        params.page,
+        page_size,
+    )
+    .await
+    .map_err(|e| {
+        log::error!("Failed to read audit log: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to read audit log"))
+    })?;
+    Ok(serde_json::to_string(&AuditLogPage {
+        next_page,
+        entries,
+        total_count,
+    })?)
+}
+
+#[derive(Debug, Serialize)]
+struct IssuedKey {
+    key: ApiKey,
+    /// Only returned here, the server keeps a hash.
+    token: String,
+}
+
+#[handler]
+pub async fn list_keys(
+    _req: &mut Request,
+    _depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let keys = auth::list_keys(get_pg_pool()).await.map_err(|e| {
+        log::error!("Failed to list api keys: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to list api keys"))
+    })?;
+    Ok(serde_json::to_string(&keys)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "body")))]
+struct CreateKeyParams {
+    name: String,
+    roles: Vec<Role>,
+    daily_requests: Option<i64>,
+    daily_bytes: Option<i64>,
+}
+
+#[handler]
+pub async fn create_key(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<CreateKeyParams>(depot).await?;
+    if params.roles.is_empty() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        return Ok(String::new());
+    }
+    let (key, token) = auth::create_key(
+        get_pg_pool(),
+        &params.name,
+        &params.roles,
+        KeyQuota {
+            daily_requests: params.daily_requests,
+            daily_bytes: params.daily_bytes,
+        },
+    )
+    .await
+    .map_err(|e| {
+        log::error!("Failed to create api key {}: {}", params.name, e);
+        salvo::Error::Io(std::io::Error::other("Failed to create api key"))
+    })?;
+    depot.insert(REDACT_RESULT, true);
+    Ok(serde_json::to_string(&IssuedKey { key, token })?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "body")))]
+struct KeyIdParams {
+    id: String,
+}
+
+/// Issue a new token for a key, keeping its id and roles.
+#[handler]
+pub async fn rotate_key(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<KeyIdParams>(depot).await?;
+    let rotated = auth::rotate_key(get_pg_pool(), &params.id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to rotate api key {}: {}", params.id, e);
+            salvo::Error::Io(std::io::Error::other("Failed to rotate api key"))
+        })?;
+    let Some((key, token)) = rotated else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(String::new());
+    };
+    depot.insert(REDACT_RESULT, true);
+    Ok(serde_json::to_string(&IssuedKey { key, token })?)
+}
+
+#[handler]
+pub async fn revoke_key(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<KeyIdParams>(depot).await?;
+    let revoked = auth::revoke_key(get_pg_pool(), &params.id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to revoke api key {}: {}", params.id, e);
+            salvo::Error::Io(std::io::Error::other("Failed to revoke api key"))
+        })?;
+    if !revoked {
+        res.status_code(StatusCode::NOT_FOUND);
+    }
+    Ok(String::new())
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct DeadLetterParams {
+    #[serde(default)]
+    page: usize,
+    page_size: Option<usize>,
+    /// Include entries that were applied since.
+    #[serde(default)]
+    resolved: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct DeadLetterPage {
+    next_page: usize,
+    entries: Vec<dead_letter::DeadLetter>,
+    total_count: usize,
+}
+
+/// Channel state writes that failed and wait for a retry.
+#[handler]
+pub async fn dead_letters(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<DeadLetterParams>(depot).await?;
+    let page_size = std::cmp::min(params.page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
+    let (entries, next_page, total_count) =
+        dead_letter::list(get_pg_pool(), params.resolved, params.page, page_size)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to list dead letters: {}", e);
+                salvo::Error::Io(std::io::Error::other("Failed to list dead letters"))
+            })?;
+    Ok(serde_json::to_string(&DeadLetterPage {
+        next_page,
+        entries,
+        total_count,
+    })?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "body")))]
+struct RequeueParams {
+    /// Every unresolved entry when omitted.
+    ids: Option<Vec<i64>>,
+}
+
+#[derive(Debug, Serialize)]
+struct RequeueResult {
+    requeued: u64,
+    #[serde(flatten)]
+    retry: dead_letter::RetrySummary,
+}
+
+/// Reset the attempts of dead letters and retry them right away.
+#[handler]
+pub async fn requeue_dead_letters(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<RequeueParams>(depot).await?;
+    let pool = get_pg_pool();
+    let requeued = dead_letter::requeue(pool, params.ids.as_deref())
+        .await
+        .map_err(|e| {
+            log::error!("Failed to requeue dead letters: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to requeue dead letters"))
+        })?;
+    let retry = dead_letter::retry(pool).await.map_err(|e| {
+        log::error!("Failed to retry dead letters: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to retry dead letters"))
+    })?;
+    Ok(serde_json::to_string(&RequeueResult { requeued, retry })?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct DiscrepancyParams {
+    #[serde(default)]
+    net: Network,
+    kind: Option<DiscrepancyKind>,
+    #[serde(default)]
+    page: usize,
+    page_size: Option<usize>,
+    /// Include discrepancies a later reconciliation no longer found.
+    #[serde(default)]
+    resolved: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct DiscrepancyPage {
+    next_page: usize,
+    entries: Vec<reconcile::Discrepancy>,
+    total_count: usize,
+}
+
+/// Differences between the gossip graph and the funding cells on chain, for review.
+#[handler]
+pub async fn graph_discrepancies(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<DiscrepancyParams>(depot).await?;
+    let page_size = std::cmp::min(params.page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
+    let (entries, next_page, total_count) = reconcile::list(
+        get_pg_pool(),
+        params.net,
+        params.kind,
+        params.resolved,
+        params.page,
+        page_size,
+    )
+    .await
+    .map_err(|e| {
+        log::error!("Failed to list graph discrepancies: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to list graph discrepancies"))
+    })?;
+    Ok(serde_json::to_string(&DiscrepancyPage {
+        next_page,
+        entries,
+        total_count,
+    })?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct SnapshotAnomalyParams {
+    #[serde(default)]
+    net: Network,
+    #[serde(default)]
+    page: usize,
+    page_size: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct SnapshotAnomalyPage {
+    next_page: usize,
+    entries: Vec<snapshot_guard::SnapshotAnomaly>,
+    total_count: usize,
+}
+
+/// Graph halves the collector found implausibly small, rejected or accepted after repeats.
+#[handler]
+pub async fn snapshot_anomalies(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<SnapshotAnomalyParams>(depot).await?;
+    let page_size = std::cmp::min(params.page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
+    let (entries, next_page, total_count) =
+        snapshot_guard::list(get_pg_pool(), params.net, params.page, page_size)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to list snapshot anomalies: {}", e);
+                salvo::Error::Io(std::io::Error::other("Failed to list snapshot anomalies"))
+            })?;
+    Ok(serde_json::to_string(&SnapshotAnomalyPage {
+        next_page,
+        entries,
+        total_count,
+    })?)
+}
+
+#[handler]
+pub async fn maintenance_status(
+    _req: &mut Request,
+    _depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let state = maintenance::load(get_pg_pool()).await.map_err(|e| {
+        log::error!("Failed to load maintenance state: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to load maintenance state"))
+    })?;
+    Ok(serde_json::to_string(&state)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "body")))]
+struct MaintenanceParams {
+    enabled: bool,
+    reason: Option<String>,
+    /// `Retry-After` of rejected requests, 300 when omitted.
+    retry_after_secs: Option<i32>,
+}
+
+/// Turn maintenance mode on or off for every replica.
+#[handler]
+pub async fn set_maintenance(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<MaintenanceParams>(depot).await?;
+    let state = maintenance::set(
+        get_pg_pool(),
+        params.enabled,
+        params.reason.as_deref(),
+        params.retry_after_secs,
+    )
+    .await
+    .map_err(|e| {
+        log::error!("Failed to set maintenance mode: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to set maintenance mode"))
+    })?;
+    Ok(serde_json::to_string(&state)?)
+}
+
+#[handler]
+pub async fn list_jobs(
+    _req: &mut Request,
+    _depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let jobs = scheduler::list(get_pg_pool()).await.map_err(|e| {
+        log::error!("Failed to list jobs: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to list jobs"))
+    })?;
+    Ok(serde_json::to_string(&jobs)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "body")))]
+struct RunJobParams {
+    name: String,
+}
+
+/// Ask the collector to run a job now, 404 for unknown jobs and 409 while it runs.
+#[handler]
+pub async fn run_job(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<RunJobParams>(depot).await?;
+    let outcome = scheduler::request_run(get_pg_pool(), &params.name)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to request job {}: {}", params.name, e);
+            salvo::Error::Io(std::io::Error::other("Failed to request job"))
+        })?;
+    match outcome {
+        RequestOutcome::Requested => res.status_code(StatusCode::ACCEPTED),
+        RequestOutcome::UnknownJob => res.status_code(StatusCode::NOT_FOUND),
+        RequestOutcome::AlreadyRunning => res.status_code(StatusCode::CONFLICT),
+    };
+    Ok(String::new())
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct UdtListParams {
+    #[serde(default)]
+    net: Network,
+}
+
+/// Every UDT with its admin flags, hidden ones included.
+#[handler]
+pub async fn list_udts(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<UdtListParams>(depot).await?;
+    let udts = udt_edits::list(get_pg_pool(), params.net)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to list udts: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to list udts"))
+        })?;
+    Ok(serde_json::to_string(&udts)?)
+}
+
+/// Have the collector reload its UDT cache after an edit, the next cycle would otherwise
+/// write with the ids and names it knew before.
+async fn reload_udt_cache() {
+    if let Err(e) = scheduler::request_run(get_pg_pool(), "reload_udt_cache").await {
+        log::error!("Failed to request a udt cache reload: {}", e);
+    }
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "body")))]
+struct RenameUdtParams {
+    #[serde(default)]
+    net: Network,
+    id: i32,
+    name: String,
+}
+
+/// Rename a UDT, later announcements keep the name.
+#[handler]
+pub async fn rename_udt(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<RenameUdtParams>(depot).await?;
+    let name = params.name.trim();
+    if name.is_empty() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        return Ok(String::new());
+    }
+    let renamed = udt_edits::rename(get_pg_pool(), params.net, params.id, name, &Utc::now())
+        .await
+        .map_err(|e| {
+            log::error!("Failed to rename udt {}: {}", params.id, e);
+            salvo::Error::Io(std::io::Error::other("Failed to rename udt"))
+        })?;
+    if !renamed {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(String::new());
+    }
+    reload_udt_cache().await;
+    Ok(String::new())
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "body")))]
+struct MergeUdtParams {
+    #[serde(default)]
+    net: Network,
+    /// Duplicate, deleted once its rows point to `into`.
+    from: i32,
+    into: i32,
+}
+
+/// Merge a duplicate UDT into another one, 404 when either is unknown.
+#[handler]
+pub async fn merge_udts(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<MergeUdtParams>(depot).await?;
+    if params.from == params.into {
+        res.status_code(StatusCode::BAD_REQUEST);
+        return Ok(String::new());
+    }
+    let merged = udt_edits::merge(
+        get_pg_pool(),
+        params.net,
+        params.from,
+        params.into,
+        &Utc::now(),
+    )
+    .await
+    .map_err(|e| {
+        log::error!(
+            "Failed to merge udt {} into {}: {}",
+            params.from,
+            params.into,
+            e
+        );
+        salvo::Error::Io(std::io::Error::other("Failed to merge udts"))
+    })?;
+    let Some(summary) = merged else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(String::new());
+    };
+    reload_udt_cache().await;
+    Ok(serde_json::to_string(&summary)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "body")))]
+struct HideUdtParams {
+    #[serde(default)]
+    net: Network,
+    id: i32,
+    hidden: bool,
+}
+
+/// Hide a spam UDT from the public listings or show it again.
+#[handler]
+pub async fn hide_udt(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<HideUdtParams>(depot).await?;
+    let updated = udt_edits::set_hidden(get_pg_pool(), params.net, params.id, params.hidden)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to hide udt {}: {}", params.id, e);
+            salvo::Error::Io(std::io::Error::other("Failed to hide udt"))
+        })?;
+    if !updated {
+        res.status_code(StatusCode::NOT_FOUND);
+    }
+    Ok(String::new())
+}
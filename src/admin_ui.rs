@@ -0,0 +1,362 @@
+//! Minimal server rendered admin page at `/admin/ui`, for operators without psql at hand.
+//!
+//! It shows the data of the admin status apis (maintenance, jobs, collector runs, upstream
+//! health) and runs jobs through the same manual request as `/admin/jobs/run`. Browsers
+//! sign in with an admin token which is kept in an http only cookie scoped to `/admin/ui`.
+
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+use salvo::{Depot, FlowCtrl, Handler, Request, Response, handler, http::StatusCode};
+
+use crate::{
+    auth::{self, API_KEY, ApiKey, Role, UI_TOKEN_COOKIE},
+    get_pg_pool, maintenance,
+    maintenance::Maintenance,
+    pg_write::collector_runs::{self, RecordedRun},
+    scheduler::{self, JobStatus, RequestOutcome},
+    upstream::{self, EndpointStatus},
+};
+
+/// Lifetime of the session cookie.
+const SESSION_SECS: u32 = 12 * 3600;
+const RECENT_RUNS: i64 = 20;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+td,th{border:1px solid #ccc;padding:4px 8px;text-align:left;font-size:14px}\
+.ok{color:#1a7f37}.failed{color:#cf222e}.notice{background:#fff8c5;padding:8px}";
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+fn time(time: Option<DateTime<Utc>>) -> String {
+    time.map_or("-".to_string(), |time| {
+        time.format("%Y-%m-%d %H:%M:%S").to_string()
+    })
+}
+
+fn status(ok: bool) -> &'static str {
+    if ok {
+        "<span class=\"ok\">ok</span>"
+    } else {
+        "<span class=\"failed\">failed</span>"
+    }
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>{}</title>\
+        <style>{}</style></head><body>{}</body></html>",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+fn redirect(res: &mut Response, location: &str) {
+    res.status_code(StatusCode::SEE_OTHER);
+    res.add_header("location", location, true).ok();
+}
+
+fn render_login(error: Option<&str>) -> String {
+    let error = error.map_or(String::new(), |error| {
+        format!("<p class=\"notice\">{}</p>", escape(error))
+    });
+    page(
+        "Sign in",
+        &format!(
+            "<h1>Fiber dashboard admin</h1>{}\
+            <form method=\"post\" action=\"/admin/ui/login\">\
+            <input type=\"password\" name=\"token\" placeholder=\"admin token\" autofocus> \
+            <button>Sign in</button></form>",
+            error
+        ),
+    )
+}
+
+pub fn render_dashboard(
+    maintenance: &Maintenance,
+    jobs: &[JobStatus],
+    runs: &[RecordedRun],
+    endpoints: &[EndpointStatus],
+    notice: Option<&str>,
+) -> String {
+    let mut body = String::from(
+        "<h1>Fiber dashboard admin</h1>\
+        <form method=\"post\" action=\"/admin/ui/logout\"><button>Sign out</button></form>",
+    );
+    if let Some(notice) = notice {
+        let _ = write!(body, "<p class=\"notice\">{}</p>", escape(notice));
+    }
+
+    let _ = write!(
+        body,
+        "<h2>Maintenance</h2><p>{}</p>",
+        if maintenance.enabled {
+            format!(
+                "Enabled since {}: {}",
+                time(maintenance.updated_at),
+                escape(maintenance.reason.as_deref().unwrap_or("no reason given"))
+            )
+        } else {
+            "Disabled".to_string()
+        }
+    );
+
+    body.push_str(
+        "<h2>Jobs</h2><table><tr><th>Job</th><th>Schedule</th><th>Next run</th>\
+        <th>Last run</th><th>Status</th><th>Error</th><th></th></tr>",
+    );
+    for job in jobs {
+        let (started, run_status, error) = match &job.last_run {
+            Some(run) => (
+                format!(
+                    "{} to {}{}",
+                    time(Some(run.started_at)),
+                    time(run.finished_at),
+                    if run.manual { " (manual)" } else { "" }
+                ),
+                escape(&run.status),
+                escape(run.error.as_deref().unwrap_or("")),
+            ),
+            None => ("-".to_string(), "-".to_string(), String::new()),
+        };
+        let action = if job.requested_at.is_some() {
+            "requested".to_string()
+        } else {
+            format!(
+                "<form method=\"post\" action=\"/admin/ui/jobs/run\">\
+                <input type=\"hidden\" name=\"name\" value=\"{}\"><button>Run now</button></form>",
+                escape(&job.name)
+            )
+        };
+        let _ = write!(
+            body,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&job.name),
+            escape(&job.schedule),
+            time(job.next_run_at),
+            started,
+            run_status,
+            error,
+            action
+        );
+    }
+    body.push_str("</table>");
+
+    body.push_str(
+        "<h2>Collector runs</h2><table><tr><th>Network</th><th>Started</th><th>Finished</th>\
+        <th>Nodes</th><th>Channels</th><th>Monitor</th><th>Errors</th></tr>",
+    );
+    let count = |count: Option<i32>| count.map_or("-".to_string(), |count| count.to_string());
+    for run in runs {
+        let _ = write!(
+            body,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{} {}</td><td>{} {}</td><td>{} {}</td>\
+            <td>{}</td></tr>",
+            escape(&run.net),
+            time(Some(run.started_at)),
+            time(Some(run.finished_at)),
+            status(run.nodes_ok),
+            count(run.nodes),
+            status(run.channels_ok),
+            count(run.channels),
+            status(run.monitor_ok),
+            count(run.handed_off),
+            escape(&run.errors.join("; "))
+        );
+    }
+    body.push_str("</table>");
+
+    body.push_str(
+        "<h2>Upstream rpc</h2><table><tr><th>Endpoint</th><th>Kind</th><th>Latency</th>\
+        <th>Error rate</th><th>Circuit</th><th>Last success</th><th>Last error</th></tr>",
+    );
+    for endpoint in endpoints {
+        let _ = write!(
+            body,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.0}%</td><td>{}</td><td>{}</td>\
+            <td>{} {}</td></tr>",
+            escape(&endpoint.endpoint),
+            escape(&endpoint.kind),
+            endpoint
+                .latency_ms
+                .map_or("-".to_string(), |ms| format!("{} ms", ms)),
+            endpoint.error_rate * 100.0,
+            endpoint.circuit.as_str(),
+            time(endpoint.last_success),
+            time(endpoint.last_error_time),
+            escape(endpoint.last_error.as_deref().unwrap_or(""))
+        );
+    }
+    body.push_str("</table>");
+    page("Fiber dashboard admin", &body)
+}
+
+/// Session guard of the ui, redirects to the sign in page instead of answering 401.
+#[handler]
+pub async fn ui_authenticate(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    auth::authenticate.handle(req, depot, res, ctrl).await;
+    let admin = depot
+        .get::<ApiKey>(API_KEY)
+        .is_ok_and(|key| key.has_role(Role::Admin));
+    if !admin {
+        redirect(res, "/admin/ui/login");
+        ctrl.skip_rest();
+    }
+}
+
+#[handler]
+pub async fn ui_login_page(res: &mut Response) {
+    res.render(salvo::writing::Text::Html(render_login(None)));
+}
+
+/// Check the token and keep it in the session cookie.
+#[handler]
+pub async fn ui_login(req: &mut Request, res: &mut Response) {
+    let token = req.form::<String>("token").await.unwrap_or_default();
+    let error = if token.is_empty()
+        || !token
+            .chars()
+            .all(|c| c.is_ascii_graphic() && c != ';' && c != ',')
+    {
+        Some("Invalid token")
+    } else {
+        match auth::lookup(&token).await {
+            Ok(Some(key)) if key.has_role(Role::Admin) => None,
+            Ok(_) => Some("Invalid token or not an admin key"),
+            Err(e) => {
+                log::error!("Failed to look up api key: {}", e);
+                Some("Failed to check the token, try again")
+            }
+        }
+    };
+    if let Some(error) = error {
+        res.status_code(StatusCode::UNAUTHORIZED);
+        res.render(salvo::writing::Text::Html(render_login(Some(error))));
+        return;
+    }
+    let secure = req.uri().scheme_str() == Some("https")
+        || req.header::<String>("x-forwarded-proto").as_deref() == Some("https");
+    res.add_header(
+        "set-cookie",
+        format!(
+            "{}={}; Path=/admin/ui; Max-Age={}; HttpOnly; SameSite=Strict{}",
+            UI_TOKEN_COOKIE,
+            token,
+            SESSION_SECS,
+            if secure { "; Secure" } else { "" }
+        ),
+        true,
+    )
+    .ok();
+    redirect(res, "/admin/ui");
+}
+
+#[handler]
+pub async fn ui_logout(res: &mut Response) {
+    res.add_header(
+        "set-cookie",
+        format!(
+            "{}=; Path=/admin/ui; Max-Age=0; HttpOnly; SameSite=Strict",
+            UI_TOKEN_COOKIE
+        ),
+        true,
+    )
+    .ok();
+    redirect(res, "/admin/ui/login");
+}
+
+#[handler]
+pub async fn admin_ui(req: &mut Request, res: &mut Response) -> Result<(), salvo::Error> {
+    let pool = get_pg_pool();
+    let (maintenance, jobs, runs, endpoints) = tokio::try_join!(
+        maintenance::load(pool),
+        scheduler::list(pool),
+        collector_runs::recent(pool, RECENT_RUNS),
+        upstream::status(pool),
+    )
+    .map_err(|e| {
+        log::error!("Failed to load admin ui: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to load admin ui"))
+    })?;
+    let notice = req.query::<String>("notice");
+    res.render(salvo::writing::Text::Html(render_dashboard(
+        &maintenance,
+        &jobs,
+        &runs,
+        &endpoints,
+        notice.as_deref(),
+    )));
+    Ok(())
+}
+
+/// Request a job run from the ui, back to the page with the outcome.
+#[handler]
+pub async fn ui_run_job(req: &mut Request, res: &mut Response) -> Result<(), salvo::Error> {
+    let name = req.form::<String>("name").await.unwrap_or_default();
+    let outcome = scheduler::request_run(get_pg_pool(), &name)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to request job {}: {}", name, e);
+            salvo::Error::Io(std::io::Error::other("Failed to request job"))
+        })?;
+    let notice = match outcome {
+        RequestOutcome::Requested => format!("{} requested, it starts within 10 seconds", name),
+        RequestOutcome::UnknownJob => format!("Unknown job {}", name),
+        RequestOutcome::AlreadyRunning => format!("{} is already running", name),
+    };
+    redirect(
+        res,
+        &format!("/admin/ui?notice={}", percent_encode(&notice)),
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, percent_encode};
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(
+            escape("<a href=\"x\">Tom & 'Jerry'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn percent_encodes_notice() {
+        assert_eq!(percent_encode("a_b is 100%"), "a_b%20is%20100%25");
+    }
+}
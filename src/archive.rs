@@ -0,0 +1,168 @@
+use std::{io::Read, path::PathBuf, sync::LazyLock};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Network, config,
+    types::{ChannelInfo, GraphChannelsResult, GraphNodesResult, NodeInfo},
+};
+
+const FILE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const FILE_SUFFIX: &str = ".json.gz";
+
+/// Directory raw graph RPC pages are archived to, archiving is disabled when unset.
+pub static RPC_ARCHIVE_DIR: LazyLock<Option<PathBuf>> =
+    LazyLock::new(|| config::var("RPC_ARCHIVE_DIR").map(PathBuf::from));
+
+/// Archives older than this many days are removed after each write.
+static RPC_ARCHIVE_RETENTION_DAYS: LazyLock<i64> =
+    LazyLock::new(|| config::parse_var("RPC_ARCHIVE_RETENTION_DAYS").unwrap_or(14));
+
+pub fn enabled() -> bool {
+    RPC_ARCHIVE_DIR.is_some()
+}
+
+/// The untouched `graph_nodes` / `graph_channels` results of one collection cycle.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RawSnapshot {
+    pub net: Network,
+    pub time: DateTime<Utc>,
+    pub graph_nodes: Vec<serde_json::Value>,
+    pub graph_channels: Vec<serde_json::Value>,
+}
+
+impl RawSnapshot {
+    pub fn new(net: Network, time: DateTime<Utc>) -> Self {
+        RawSnapshot {
+            net,
+            time,
+            graph_nodes: Vec::new(),
+            graph_channels: Vec::new(),
+        }
+    }
+
+    /// Keep a copy of a `graph_nodes` page, no-op when archiving is disabled.
+    pub fn push_nodes(&mut self, page: &serde_json::Value) {
+        if enabled() {
+            self.graph_nodes.push(page.clone());
+        }
+    }
+
+    /// Keep a copy of a `graph_channels` page, no-op when archiving is disabled.
+    pub fn push_channels(&mut self, page: &serde_json::Value) {
+        if enabled() {
+            self.graph_channels.push(page.clone());
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.time.format(FILE_TIME_FORMAT).to_string()
+    }
+
+    /// Parse the archived pages the same way the collector does.
+    pub fn parse(&self) -> Result<(Vec<NodeInfo>, Vec<ChannelInfo>), serde_json::Error> {
+        let mut nodes = Vec::new();
+        for page in &self.graph_nodes {
+            nodes.extend(serde_json::from_value::<GraphNodesResult>(page.clone())?.nodes);
+        }
+        let mut channels = Vec::new();
+        for page in &self.graph_channels {
+            channels.extend(serde_json::from_value::<GraphChannelsResult>(page.clone())?.channels);
+        }
+        Ok((nodes, channels))
+    }
+}
+
+fn net_dir(net: Network) -> Option<PathBuf> {
+    let dir = RPC_ARCHIVE_DIR.as_ref()?;
+    Some(match net {
+        Network::Mainnet => dir.join("mainnet"),
+        Network::Testnet => dir.join("testnet"),
+    })
+}
+
+/// Only names produced by [`RawSnapshot::name`] are accepted, which also rules out path traversal.
+fn archive_path(net: Network, name: &str) -> std::io::Result<PathBuf> {
+    let dir = net_dir(net).ok_or_else(|| std::io::Error::other("RPC_ARCHIVE_DIR is not set"))?;
+    NaiveDateTime::parse_from_str(name, FILE_TIME_FORMAT).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid archive name: {}", name),
+        )
+    })?;
+    Ok(dir.join(format!("{}{}", name, FILE_SUFFIX)))
+}
+
+/// Write the snapshot to the archive directory and prune expired archives.
+///
+/// Failures are only logged, archiving must never break collection.
+pub async fn store(snapshot: &RawSnapshot) {
+    if !enabled() {
+        return;
+    }
+    if let Err(e) = write(snapshot).await {
+        log::warn!("Failed to archive {:?} rpc payload: {}", snapshot.net, e);
+    }
+    if let Err(e) = prune(snapshot.net).await {
+        log::warn!("Failed to prune {:?} rpc archives: {}", snapshot.net, e);
+    }
+}
+
+async fn write(snapshot: &RawSnapshot) -> std::io::Result<()> {
+    let path = archive_path(snapshot.net, &snapshot.name())?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    serde_json::to_writer(&mut encoder, snapshot)?;
+    let compressed = encoder.finish()?;
+    tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+    tokio::fs::write(&path, compressed).await?;
+    log::info!(
+        "Archived {:?} rpc payload to {}",
+        snapshot.net,
+        path.display()
+    );
+    Ok(())
+}
+
+async fn prune(net: Network) -> std::io::Result<()> {
+    let cutoff = Utc::now() - chrono::Duration::days(*RPC_ARCHIVE_RETENTION_DAYS);
+    for name in list(net).await? {
+        let expired = NaiveDateTime::parse_from_str(&name, FILE_TIME_FORMAT)
+            .map(|time| time.and_utc() < cutoff)
+            .unwrap_or(false);
+        if expired {
+            tokio::fs::remove_file(archive_path(net, &name)?).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Names of the archived snapshots of `net`, oldest first.
+pub async fn list(net: Network) -> std::io::Result<Vec<String>> {
+    let dir = net_dir(net).ok_or_else(|| std::io::Error::other("RPC_ARCHIVE_DIR is not set"))?;
+    let mut names = Vec::new();
+    let mut entries = match tokio::fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(names),
+        Err(e) => return Err(e),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        if let Some(name) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.strip_suffix(FILE_SUFFIX))
+        {
+            names.push(name.to_string());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+pub async fn read(net: Network, name: &str) -> std::io::Result<RawSnapshot> {
+    let compressed = tokio::fs::read(archive_path(net, name)?).await?;
+    let mut json = Vec::new();
+    GzDecoder::new(compressed.as_slice()).read_to_end(&mut json)?;
+    Ok(serde_json::from_slice(&json)?)
+}
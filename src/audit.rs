@@ -0,0 +1,105 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres, Row};
+
+/// Stored results are cut to this many bytes, reports like `/admin/doctor` can be large.
+const MAX_RESULT_LEN: usize = 4096;
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub time: DateTime<Utc>,
+    pub key_id: Option<String>,
+    pub method: String,
+    pub action: String,
+    pub params: serde_json::Value,
+    pub status: i32,
+    pub result: Option<String>,
+    pub client_ip: Option<String>,
+}
+
+fn truncate(result: &str) -> &str {
+    if result.len() <= MAX_RESULT_LEN {
+        return result;
+    }
+    let mut end = MAX_RESULT_LEN;
+    while !result.is_char_boundary(end) {
+        end -= 1;
+    }
+    &result[..end]
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn record(
+    pool: &Pool<Postgres>,
+    key_id: Option<&str>,
+    method: &str,
+    action: &str,
+    params: &serde_json::Value,
+    status: u16,
+    result: Option<&str>,
+    client_ip: Option<IpAddr>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO audit_log (key_id, method, action, params, status, result, client_ip)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(key_id)
+    .bind(method)
+    .bind(action)
+    .bind(params)
+    .bind(status as i32)
+    .bind(result.map(truncate))
+    .bind(client_ip.map(|ip| ip.to_string()))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Newest entries first, optionally only those of one action.
+pub async fn list(
+    pool: &Pool<Postgres>,
+    action: Option<&str>,
+    page: usize,
+    page_size: usize,
+) -> Result<(Vec<AuditEntry>, usize, usize), sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, time, key_id, method, action, params, status, result, client_ip,
+            COUNT(*) OVER() AS total_count
+        FROM audit_log
+        WHERE $1::text IS NULL OR action = $1
+        ORDER BY id DESC
+        LIMIT $2 OFFSET $3",
+    )
+    .bind(action)
+    .bind(page_size as i64)
+    .bind(page.saturating_mul(page_size) as i64)
+    .fetch_all(pool)
+    .await?;
+    let total_count = rows
+        .first()
+        .map(|row| row.get::<i64, _>("total_count") as usize)
+        .unwrap_or(0);
+    let entries = rows
+        .iter()
+        .map(AuditEntry::from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((entries, page.saturating_add(1), total_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAX_RESULT_LEN, truncate};
+
+    #[test]
+    fn truncate_keeps_char_boundaries() {
+        let short = "ok";
+        assert_eq!(truncate(short), short);
+        let long = "é".repeat(MAX_RESULT_LEN);
+        let cut = truncate(&long);
+        assert!(cut.len() <= MAX_RESULT_LEN);
+        assert!(cut.chars().all(|c| c == 'é'));
+    }
+}
@@ -0,0 +1,357 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
+};
+
+use arc_swap::ArcSwapOption;
+use chrono::{DateTime, Utc};
+use salvo::{Depot, FlowCtrl, Handler, Request, Response, handler, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{config, get_pg_pool};
+
+/// Depot key of the [`ApiKey`] that authenticated the request.
+pub const API_KEY: &str = "api_key";
+
+/// Keys are re-read from the database at most this often, so replicas pick up
+/// rotations and revocations made elsewhere.
+const KEY_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Bootstrap admin token, used to create the first api keys.
+static ADMIN_TOKEN: LazyLock<Option<String>> = LazyLock::new(|| {
+    let token = config::var("ADMIN_TOKEN");
+    if token.is_none() {
+        log::warn!("ADMIN_TOKEN is not set, admin api is only reachable with api keys");
+    }
+    token
+});
+
+/// Whether the public api requires a key with the `read` role, open by default.
+static API_KEYS_REQUIRED: LazyLock<bool> =
+    LazyLock::new(|| config::parse_var("API_KEYS_REQUIRED").unwrap_or(false));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Public data apis.
+    Read,
+    /// `/admin/export`.
+    Export,
+    /// Every admin route, implies all other roles.
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Read => "read",
+            Role::Export => "export",
+            Role::Admin => "admin",
+        }
+    }
+
+    fn parse(role: &str) -> Option<Self> {
+        match role {
+            "read" => Some(Role::Read),
+            "export" => Some(Role::Export),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiKey {
+    /// Public identifier, recorded in the audit log.
+    pub id: String,
+    pub name: String,
+    pub roles: Vec<Role>,
+    pub created_at: DateTime<Utc>,
+    pub rotated_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub quota: KeyQuota,
+}
+
+/// Daily limits of a key, unset limits fall back to `API_KEY_DAILY_REQUESTS` / `API_KEY_DAILY_BYTES`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct KeyQuota {
+    pub daily_requests: Option<i64>,
+    pub daily_bytes: Option<i64>,
+}
+
+impl ApiKey {
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&Role::Admin) || self.roles.contains(&role)
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    faster_hex::hex_string(&Sha256::digest(token.as_bytes()))
+}
+
+fn admin_token_key(token: &str) -> ApiKey {
+    ApiKey {
+        id: hash_token(token)[..8].to_string(),
+        name: "ADMIN_TOKEN".to_string(),
+        roles: vec![Role::Admin],
+        created_at: DateTime::<Utc>::UNIX_EPOCH,
+        rotated_at: None,
+        revoked_at: None,
+        quota: KeyQuota::default(),
+    }
+}
+
+fn key_from_row(row: &sqlx::postgres::PgRow) -> ApiKey {
+    ApiKey {
+        id: row.get("id"),
+        name: row.get("name"),
+        roles: row
+            .get::<Vec<String>, _>("roles")
+            .iter()
+            .filter_map(|role| Role::parse(role))
+            .collect(),
+        created_at: row.get("created_at"),
+        rotated_at: row.get("rotated_at"),
+        revoked_at: row.get("revoked_at"),
+        quota: KeyQuota {
+            daily_requests: row.get("daily_requests"),
+            daily_bytes: row.get("daily_bytes"),
+        },
+    }
+}
+
+/// Active keys by token hash.
+struct KeyCache {
+    loaded_at: Instant,
+    keys: HashMap<String, ApiKey>,
+}
+
+static KEY_CACHE: ArcSwapOption<KeyCache> = ArcSwapOption::const_empty();
+
+async fn active_keys(pool: &Pool<Postgres>) -> Result<Arc<KeyCache>, sqlx::Error> {
+    if let Some(cache) = KEY_CACHE.load_full()
+        && cache.loaded_at.elapsed() < KEY_CACHE_TTL
+    {
+        return Ok(cache);
+    }
+    let rows = sqlx::query(
+        "SELECT id, name, token_hash, roles, created_at, rotated_at, revoked_at,
+        daily_requests, daily_bytes
+        FROM api_keys WHERE revoked_at IS NULL",
+    )
+    .fetch_all(pool)
+    .await?;
+    let cache = Arc::new(KeyCache {
+        loaded_at: Instant::now(),
+        keys: rows
+            .iter()
+            .map(|row| (row.get("token_hash"), key_from_row(row)))
+            .collect(),
+    });
+    KEY_CACHE.store(Some(cache.clone()));
+    Ok(cache)
+}
+
+/// Cookie holding the token of an admin ui session, only sent by browsers to `/admin/ui`.
+pub(crate) const UI_TOKEN_COOKIE: &str = "admin_ui_token";
+
+fn cookie(req: &Request, name: &str) -> Option<String> {
+    req.headers()
+        .get_all("cookie")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| {
+            let (key, value) = pair.trim().split_once('=')?;
+            (key == name).then(|| value.to_string())
+        })
+}
+
+pub(crate) async fn lookup(token: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+    if let Some(admin_token) = ADMIN_TOKEN.as_ref()
+        && token == admin_token
+    {
+        return Ok(Some(admin_token_key(token)));
+    }
+    let keys = active_keys(get_pg_pool()).await?;
+    Ok(keys.keys.get(&hash_token(token)).cloned())
+}
+
+/// Resolve the `Authorization: Bearer <token>` header, or the admin ui session cookie, into an
+/// [`ApiKey`] in the depot, 401 when it is missing or unknown.
+#[handler]
+pub async fn authenticate(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let token = req
+        .header::<String>("authorization")
+        .and_then(|value| value.strip_prefix("Bearer ").map(str::to_string))
+        .or_else(|| cookie(req, UI_TOKEN_COOKIE));
+    let key = match token {
+        Some(token) => match lookup(&token).await {
+            Ok(key) => key,
+            Err(e) => {
+                log::error!("Failed to look up api key: {}", e);
+                res.status_code(StatusCode::SERVICE_UNAVAILABLE);
+                ctrl.skip_rest();
+                return;
+            }
+        },
+        None => None,
+    };
+    match key {
+        Some(key) => {
+            depot.insert(API_KEY, key);
+        }
+        None => {
+            res.status_code(StatusCode::UNAUTHORIZED);
+            ctrl.skip_rest();
+        }
+    }
+}
+
+/// Reject requests whose key lacks the role with 403, must run after [`authenticate`].
+pub struct RequireRole(pub Role);
+
+#[async_trait::async_trait]
+impl Handler for RequireRole {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let allowed = depot
+            .get::<ApiKey>(API_KEY)
+            .map(|key| key.has_role(self.0))
+            .unwrap_or(false);
+        if !allowed {
+            res.status_code(StatusCode::FORBIDDEN);
+            ctrl.skip_rest();
+        }
+    }
+}
+
+/// Guard of the public data apis, requires a `read` key only when `API_KEYS_REQUIRED=true`.
+/// Requests presenting a key are authenticated either way so their usage is accounted.
+#[handler]
+pub async fn public_auth(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    if !*API_KEYS_REQUIRED && req.headers().get("authorization").is_none() {
+        return;
+    }
+    authenticate.handle(req, depot, res, ctrl).await;
+    if *API_KEYS_REQUIRED && depot.contains_key(API_KEY) {
+        RequireRole(Role::Read).handle(req, depot, res, ctrl).await;
+    }
+}
+
+/// A fresh token, returned to the caller once and only stored hashed.
+async fn new_token(pool: &Pool<Postgres>) -> Result<String, sqlx::Error> {
+    let random: String = sqlx::query_scalar(
+        "SELECT replace(gen_random_uuid()::text || gen_random_uuid()::text, '-', '')",
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(format!("fdk_{}", random))
+}
+
+pub async fn list_keys(pool: &Pool<Postgres>) -> Result<Vec<ApiKey>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT id, name, roles, created_at, rotated_at, revoked_at,
+        daily_requests, daily_bytes
+        FROM api_keys ORDER BY created_at",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.iter().map(key_from_row).collect())
+}
+
+pub async fn create_key(
+    pool: &Pool<Postgres>,
+    name: &str,
+    roles: &[Role],
+    quota: KeyQuota,
+) -> Result<(ApiKey, String), sqlx::Error> {
+    let token = new_token(pool).await?;
+    let row = sqlx::query(
+        "INSERT INTO api_keys (id, name, token_hash, roles, daily_requests, daily_bytes)
+        VALUES (substr(replace(gen_random_uuid()::text, '-', ''), 1, 12), $1, $2, $3, $4, $5)
+        RETURNING id, name, roles, created_at, rotated_at, revoked_at,
+        daily_requests, daily_bytes",
+    )
+    .bind(name)
+    .bind(hash_token(&token))
+    .bind(roles.iter().map(Role::as_str).collect::<Vec<_>>())
+    .bind(quota.daily_requests)
+    .bind(quota.daily_bytes)
+    .fetch_one(pool)
+    .await?;
+    KEY_CACHE.store(None);
+    Ok((key_from_row(&row), token))
+}
+
+/// Replace the token of an active key, the old token stops working immediately on this
+/// replica and within [`KEY_CACHE_TTL`] on the others.
+pub async fn rotate_key(
+    pool: &Pool<Postgres>,
+    id: &str,
+) -> Result<Option<(ApiKey, String)>, sqlx::Error> {
+    let token = new_token(pool).await?;
+    let row = sqlx::query(
+        "UPDATE api_keys SET token_hash = $2, rotated_at = now()
+        WHERE id = $1 AND revoked_at IS NULL
+        RETURNING id, name, roles, created_at, rotated_at, revoked_at,
+        daily_requests, daily_bytes",
+    )
+    .bind(id)
+    .bind(hash_token(&token))
+    .fetch_optional(pool)
+    .await?;
+    KEY_CACHE.store(None);
+    Ok(row.map(|row| (key_from_row(&row), token)))
+}
+
+pub async fn revoke_key(pool: &Pool<Postgres>, id: &str) -> Result<bool, sqlx::Error> {
+    let revoked =
+        sqlx::query("UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND revoked_at IS NULL")
+            .bind(id)
+            .execute(pool)
+            .await?
+            .rows_affected()
+            > 0;
+    KEY_CACHE.store(None);
+    Ok(revoked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiKey, Role, admin_token_key};
+
+    #[test]
+    fn admin_role_implies_every_role() {
+        let admin = admin_token_key("secret");
+        assert!(admin.has_role(Role::Read));
+        assert!(admin.has_role(Role::Export));
+
+        let export = ApiKey {
+            roles: vec![Role::Export],
+            ..admin
+        };
+        assert!(export.has_role(Role::Export));
+        assert!(!export.has_role(Role::Read));
+        assert!(!export.has_role(Role::Admin));
+    }
+}
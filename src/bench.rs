@@ -0,0 +1,362 @@
+//! Latency benchmark of the most used read endpoints, `fiber-dashbord bench`.
+//!
+//! With `--nodes N` the database is first populated with a synthetic graph of N nodes and
+//! `--channels` channels (3N by default) committed through the collector's ingest path, so only
+//! point it at a scratch database. Every endpoint is then requested `--iterations` times from
+//! the api at `--url`, and the p50 of each is compared with the one stored in `--baseline`:
+//! slower than the baseline by more than `--tolerance` counts as a regression. `--save` stores
+//! the run as the new baseline.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{
+    Network, config,
+    pg_write::commit_page,
+    simulate::{SimConfig, Simulation},
+};
+
+/// Rows committed per page while populating, like a graph page.
+const POPULATE_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchOptions {
+    pub url: String,
+    pub net: Network,
+    /// Synthetic nodes and channels to commit before measuring.
+    pub populate: Option<(usize, usize)>,
+    pub iterations: usize,
+    pub baseline: PathBuf,
+    /// Allowed p50 slowdown against the baseline, 0.2 is 20%.
+    pub tolerance: f64,
+    pub save: bool,
+    /// Sent as bearer token, for deployments requiring keys or limiting anonymous requests.
+    pub api_key: Option<String>,
+}
+
+impl BenchOptions {
+    /// Options from the arguments following `bench`.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        fn value<T: std::str::FromStr>(args: &[String], flag: &str) -> Result<Option<T>, String> {
+            let Some(at) = args.iter().position(|arg| arg == flag) else {
+                return Ok(None);
+            };
+            args.get(at + 1)
+                .and_then(|value| value.parse().ok())
+                .map(Some)
+                .ok_or_else(|| format!("{} needs a valid value", flag))
+        }
+        let net = match value::<String>(args, "--net")? {
+            Some(name) => Network::parse(&name).ok_or_else(|| format!("unknown net {}", name))?,
+            None => Network::default(),
+        };
+        let populate = value::<usize>(args, "--nodes")?
+            .map(|nodes| Ok::<_, String>((nodes, value(args, "--channels")?.unwrap_or(nodes * 3))))
+            .transpose()?;
+        Ok(BenchOptions {
+            url: value(args, "--url")?.unwrap_or_else(|| {
+                format!(
+                    "http://127.0.0.1:{}",
+                    config::var("HTTP_PORT").unwrap_or("8000".to_string())
+                )
+            }),
+            net,
+            populate,
+            iterations: value(args, "--iterations")?.unwrap_or(50).max(1),
+            baseline: value(args, "--baseline")?
+                .unwrap_or_else(|| PathBuf::from("bench-baseline.json")),
+            tolerance: value(args, "--tolerance")?.unwrap_or(0.2),
+            save: args.iter().any(|arg| arg == "--save"),
+            api_key: config::var("BENCH_API_KEY"),
+        })
+    }
+}
+
+/// Milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Latency {
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub mean_ms: f64,
+}
+
+impl Latency {
+    fn of(samples: &mut [f64]) -> Latency {
+        samples.sort_by(f64::total_cmp);
+        let percentile = |p: f64| samples[((samples.len() - 1) as f64 * p).round() as usize];
+        Latency {
+            p50_ms: percentile(0.5),
+            p95_ms: percentile(0.95),
+            mean_ms: samples.iter().sum::<f64>() / samples.len() as f64,
+        }
+    }
+}
+
+/// Latency by endpoint name.
+pub type Report = BTreeMap<String, Latency>;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Regression {
+    pub endpoint: String,
+    pub baseline_p50_ms: f64,
+    pub p50_ms: f64,
+    pub change_percent: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BenchSummary {
+    pub latencies: Report,
+    pub regressions: Vec<Regression>,
+    /// Endpoints of the run missing from the baseline.
+    pub new_endpoints: Vec<String>,
+    pub baseline_saved: bool,
+}
+
+/// Endpoints whose p50 grew by more than `tolerance` over the baseline.
+pub fn regressions(baseline: &Report, current: &Report, tolerance: f64) -> Vec<Regression> {
+    current
+        .iter()
+        .filter_map(|(endpoint, latency)| {
+            let before = baseline.get(endpoint)?;
+            (latency.p50_ms > before.p50_ms * (1.0 + tolerance)).then(|| Regression {
+                endpoint: endpoint.clone(),
+                baseline_p50_ms: before.p50_ms,
+                p50_ms: latency.p50_ms,
+                change_percent: (latency.p50_ms / before.p50_ms - 1.0) * 100.0,
+            })
+        })
+        .collect()
+}
+
+/// Commit a synthetic graph of `nodes` nodes and `channels` channels to `net` as a snapshot
+/// of now, then refresh the aggregates and views the endpoints read.
+pub async fn populate(
+    pool: &Pool<Postgres>,
+    net: Network,
+    nodes: usize,
+    channels: usize,
+) -> Result<(), sqlx::Error> {
+    let mut simulation = Simulation::new(
+        net,
+        SimConfig {
+            nodes,
+            channels,
+            node_churn: 0.0,
+            channel_churn: 0.0,
+            seed: 1,
+        },
+    );
+    let time = Utc::now();
+    simulation.step(time);
+    for page in simulation.graph_nodes(time).chunks(POPULATE_PAGE_SIZE) {
+        commit_page(net, page.to_vec(), Vec::new(), &time).await?;
+    }
+    for page in simulation.graph_channels(time).chunks(POPULATE_PAGE_SIZE) {
+        commit_page(net, Vec::new(), page.to_vec(), &time).await?;
+    }
+    for aggregate in [net.online_nodes_hourly(), net.online_channels_hourly()] {
+        sqlx::query(&format!(
+            "CALL refresh_continuous_aggregate('{}', NULL, NULL)",
+            aggregate
+        ))
+        .execute(pool)
+        .await?;
+    }
+    for view in [net.mv_online_nodes(), net.mv_online_channels()] {
+        sqlx::query(&format!("REFRESH MATERIALIZED VIEW CONCURRENTLY {}", view))
+            .execute(pool)
+            .await?;
+    }
+    log::info!(
+        "{:?}, populated {} nodes and {} channels",
+        net,
+        nodes,
+        channels
+    );
+    Ok(())
+}
+
+/// Paths of the measured endpoints by name, the single node and channel ones use an online
+/// node and channel when there is one.
+async fn endpoints(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<Vec<(&'static str, String)>, sqlx::Error> {
+    let net_query = format!("net={}", net.name());
+    let mut endpoints = vec![
+        (
+            "nodes_hourly",
+            format!("/nodes_hourly?page=0&{}", net_query),
+        ),
+        (
+            "channels_hourly",
+            format!("/channels_hourly?page=0&{}", net_query),
+        ),
+        ("graph_snapshot", format!("/graph_snapshot?{}", net_query)),
+        ("analysis_hourly", format!("/analysis_hourly?{}", net_query)),
+        ("all_region", format!("/all_region?{}", net_query)),
+        (
+            "channel_capacity_distribution",
+            format!("/channel_capacity_distribution?{}", net_query),
+        ),
+        ("kpis", format!("/kpis?{}", net_query)),
+    ];
+    let node = sqlx::query(&format!(
+        "SELECT node_id FROM {} LIMIT 1",
+        net.mv_online_nodes()
+    ))
+    .fetch_optional(pool)
+    .await?;
+    if let Some(row) = node {
+        let node_id = row.get::<String, _>("node_id");
+        endpoints.push((
+            "node_info",
+            format!("/node_info?node_id=0x{}&{}", node_id, net_query),
+        ));
+        endpoints.push((
+            "channels_by_node_id",
+            format!(
+                "/channels_by_node_id?node_id=0x{}&page=0&{}",
+                node_id, net_query
+            ),
+        ));
+    }
+    let channel = sqlx::query(&format!(
+        "SELECT channel_outpoint FROM {} LIMIT 1",
+        net.mv_online_channels()
+    ))
+    .fetch_optional(pool)
+    .await?;
+    if let Some(row) = channel {
+        endpoints.push((
+            "channel_info",
+            format!(
+                "/channel_info?channel_outpoint=0x{}&{}",
+                row.get::<String, _>("channel_outpoint"),
+                net_query
+            ),
+        ));
+    }
+    Ok(endpoints)
+}
+
+/// Request every endpoint `options.iterations` times, one request at a time.
+async fn measure(
+    options: &BenchOptions,
+    endpoints: &[(&'static str, String)],
+) -> Result<Report, String> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(60))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut report = Report::new();
+    for (name, path) in endpoints {
+        let url = format!("{}{}", options.url.trim_end_matches('/'), path);
+        let mut samples = Vec::with_capacity(options.iterations);
+        for _ in 0..options.iterations {
+            let mut request = client.get(&url);
+            if let Some(key) = &options.api_key {
+                request = request.bearer_auth(key);
+            }
+            let started = Instant::now();
+            let response = request
+                .send()
+                .await
+                .map_err(|e| format!("{}: {}", name, e))?;
+            let status = response.status();
+            response
+                .bytes()
+                .await
+                .map_err(|e| format!("{}: {}", name, e))?;
+            if !status.is_success() {
+                return Err(format!("{} answered {}", name, status));
+            }
+            samples.push(started.elapsed().as_secs_f64() * 1000.0);
+        }
+        report.insert(name.to_string(), Latency::of(&mut samples));
+    }
+    Ok(report)
+}
+
+/// Populate when asked, measure, and compare with the baseline.
+pub async fn run(pool: &Pool<Postgres>, options: &BenchOptions) -> Result<BenchSummary, String> {
+    if let Some((nodes, channels)) = options.populate {
+        populate(pool, options.net, nodes, channels)
+            .await
+            .map_err(|e| format!("failed to populate: {}", e))?;
+    }
+    let endpoints = endpoints(pool, options.net)
+        .await
+        .map_err(|e| format!("failed to pick endpoints: {}", e))?;
+    let latencies = measure(options, &endpoints).await?;
+
+    let baseline = match std::fs::read(&options.baseline) {
+        Ok(raw) => serde_json::from_slice::<Report>(&raw)
+            .map_err(|e| format!("invalid baseline {}: {}", options.baseline.display(), e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Report::new(),
+        Err(e) => return Err(format!("{}: {}", options.baseline.display(), e)),
+    };
+    let regressions = regressions(&baseline, &latencies, options.tolerance);
+    let new_endpoints = latencies
+        .keys()
+        .filter(|endpoint| !baseline.contains_key(*endpoint))
+        .cloned()
+        .collect();
+    if options.save {
+        std::fs::write(
+            &options.baseline,
+            serde_json::to_vec_pretty(&latencies).unwrap(),
+        )
+        .map_err(|e| format!("{}: {}", options.baseline.display(), e))?;
+    }
+    Ok(BenchSummary {
+        latencies,
+        regressions,
+        new_endpoints,
+        baseline_saved: options.save,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BenchOptions, Latency, Report, regressions};
+
+    #[test]
+    fn slower_p50_beyond_tolerance_regresses() {
+        let latency = |p50_ms| Latency {
+            p50_ms,
+            p95_ms: p50_ms * 2.0,
+            mean_ms: p50_ms,
+        };
+        let baseline = Report::from([
+            ("kpis".to_string(), latency(10.0)),
+            ("nodes_hourly".to_string(), latency(10.0)),
+        ]);
+        let current = Report::from([
+            ("kpis".to_string(), latency(11.0)),
+            ("nodes_hourly".to_string(), latency(15.0)),
+            ("node_info".to_string(), latency(100.0)),
+        ]);
+        let found = regressions(&baseline, &current, 0.2);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].endpoint, "nodes_hourly");
+        assert_eq!(found[0].change_percent.round(), 50.0);
+
+        let mut samples = (1..=100).map(f64::from).collect::<Vec<_>>();
+        let latency = Latency::of(&mut samples);
+        assert_eq!((latency.p50_ms, latency.p95_ms), (51.0, 95.0));
+
+        let args = ["--nodes", "10", "--save"].map(String::from);
+        let options = BenchOptions::from_args(&args).unwrap();
+        assert_eq!(options.populate, Some((10, 30)));
+        assert!(options.save);
+        assert!(BenchOptions::from_args(&["--nodes".to_string()]).is_err());
+    }
+}
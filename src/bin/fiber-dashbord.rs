@@ -1,34 +1,127 @@
 use std::{
+    collections::HashSet,
     sync::{
         LazyLock,
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
     },
     vec,
 };
 
 use ckb_jsonrpc_types::JsonBytes;
 use fiber_dashbord_backend::{
-    CHANNEL_MONITOR_HEARTBEAT, RpcClient,
+    CHANNEL_MONITOR_HEARTBEAT, ENABLED_NETWORKS, RpcClient,
+    archive::{self, RawSnapshot},
+    bench, chain_check, changes, check_schema,
     clock_timer::ClockTimer,
-    create_pg_pool, get_pg_pool, init_db,
+    cohorts, config, create_pg_pool, doctor,
+    events::{self, Event},
+    export, fingerprints, get_pg_pool, hot_snapshot_refresher, http_cache, metrics, migrate,
+    panic_guard,
     pg_write::{
-        ChannelInfoDBSchema, channel_states_monitor, daily_statistics, from_rpc_to_db_schema,
-        init_global_cache, insert_batch,
+        CHANNEL_HANDOFFS_DROPPED, DUPLICATE_CHANNELS_DROPPED, DUPLICATE_NODES_DROPPED,
+        announce_snapshot, channel_states_monitor,
+        collector_runs::{CollectorRun, Phase},
+        commit_page, daily_statistics, dead_letter, dedup_channel_page, dedup_node_page,
+        expire_udt_relations, init_global_cache, rebuild_global_cache,
+        snapshot_guard::{Half, PlausibilityGuard},
+        untracked_outpoints,
     },
-    types::{GraphChannelsParams, GraphNodesParams},
+    rankings, reconcile, reports,
+    scheduler::{self, Scheduler},
+    simulate::{SIM_CONFIG, SIMULATE_INTERVAL_SECS, Simulation},
+    survival,
+    types::{
+        ChannelInfo, GraphChannelsParams, GraphChannelsResult, GraphNodesParams, GraphNodesResult,
+        NodeInfo,
+    },
+    upstream, use_sqlite, warm_up, webhook,
 };
 
+use chrono::{Datelike, NaiveDate, NaiveTime};
+use clap::{CommandFactory, Parser, Subcommand, error::ErrorKind};
 use reqwest::Url;
-use sqlx::{Row, types::chrono::Utc};
+use sqlx::{
+    Row,
+    types::chrono::{DateTime, Utc},
+};
+use tokio::sync::mpsc::error::TrySendError;
+
+/// Fiber network dashboard, the snapshot collectors and the http api.
+#[derive(Debug, Parser)]
+#[command(name = "fiber-dashbord", version, about)]
+struct Cli {
+    /// Settings file, instead of `FIBER_DASHBOARD_CONFIG`.
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<String>,
+    /// Collect a synthetic graph of every enabled network instead of calling rpcs.
+    #[arg(long, global = true)]
+    simulate: bool,
+    /// Collect and serve against SQLite instead of TimescaleDB, for local development.
+    #[arg(long)]
+    lite: bool,
+    /// Without a subcommand the process runs the roles of `FIBER_DASHBOARD_ROLE`.
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Serve the http api, the collectors run in another process.
+    Serve,
+    /// Run the collectors and scheduled jobs without the http api.
+    Collect,
+    /// Summarize the days since `--from` again, recompute the kpis and channel survival and
+    /// render the weekly reports of those days.
+    Backfill {
+        #[arg(long, value_name = "YYYY-MM-DD")]
+        from: NaiveDate,
+    },
+    /// Summarize the last `--days` days once, like the daily job.
+    DailyStats {
+        #[arg(long, default_value_t = 20)]
+        days: i64,
+    },
+    /// Create the tables and apply pending schema changes.
+    Migrate,
+    /// Check the data invariants and print the report, exit code 2 when unhealthy.
+    Doctor {
+        /// Delete orphan channel states.
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Measure the most used endpoints of a running api, exit code 2 on regressions.
+    Bench {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
 
 fn main() {
+    let cli = Cli::parse();
+    if cli.lite && cli.command.is_some() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--lite runs its own collector and api, it takes no subcommand",
+            )
+            .exit();
+    }
+    if let Some(path) = &cli.config {
+        // SAFETY: no other thread is running yet
+        unsafe { std::env::set_var(config::CONFIG_VAR, path) };
+    }
+    SIMULATE.store(cli.simulate, Ordering::Relaxed);
+
     env_logger::init();
-    if std::env::var("ALLOW_EXIT_ON_PANIC")
-        .unwrap_or_default()
-        .parse()
-        .unwrap_or(true)
-    {
+    // a broken settings file stops the process before anything starts
+    LazyLock::force(&config::CONFIG);
+    if config::parse_var("ALLOW_EXIT_ON_PANIC").unwrap_or(true) {
         std::panic::set_hook(Box::new(|info| {
+            // request panics are answered with 500 by `catch_panic`
+            if let Some(id) = panic_guard::request_error_id() {
+                log::error!("Panic occurred in request {}: {:?}", id, info);
+                return;
+            }
             log::error!("Panic occurred: {:?}", info);
             std::process::exit(1);
         }));
@@ -36,30 +129,243 @@ fn main() {
 
     let rt = tokio::runtime::Runtime::new().unwrap();
 
-    rt.block_on(async move {
-        create_pg_pool().await;
-        let pool = get_pg_pool();
-        init_db(pool).await;
-        init_global_cache(pool).await;
-        tokio::spawn(daily_commit());
+    if cli.lite {
+        rt.block_on(async move {
+            let url = config::var("LITE_DATABASE_URL")
+                .unwrap_or("sqlite://fiber-dashboard.db".to_string());
+            use_sqlite(&url)
+                .await
+                .expect("Failed to open SQLite database");
+            log::info!("Running in lite mode on {}", url);
+            tokio::spawn(lite_commit());
+            http_server(true).await;
+        });
+        return;
+    }
+
+    match cli.command {
+        None => rt.block_on(run(*ROLE)),
+        Some(Command::Serve) => rt.block_on(run(Role::Api)),
+        Some(Command::Collect) => rt.block_on(run(Role::Collector)),
+        Some(Command::Backfill { from }) => rt.block_on(one_off(backfill(from))),
+        Some(Command::DailyStats { days }) => rt.block_on(one_off(daily_stats(days))),
+        Some(Command::Migrate) => rt.block_on(async {
+            create_pg_pool().await;
+            if let Err(e) = migrate(get_pg_pool()).await {
+                log::error!("Failed to migrate the database: {}", e);
+                std::process::exit(1);
+            }
+            log::info!("Database schema is up to date");
+        }),
+        Some(Command::Doctor { fix }) => rt.block_on(async move {
+            create_pg_pool().await;
+            let report = doctor::run(get_pg_pool(), &ENABLED_NETWORKS, fix)
+                .await
+                .expect("Failed to run doctor");
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            if !report.healthy {
+                std::process::exit(2);
+            }
+        }),
+        Some(Command::Bench { args }) => {
+            let options = match bench::BenchOptions::from_args(&args) {
+                Ok(options) => options,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            rt.block_on(async move {
+                create_pg_pool().await;
+                let pool = get_pg_pool();
+                check_schema(pool).await;
+                let summary = bench::run(pool, &options)
+                    .await
+                    .expect("Failed to run bench");
+                println!("{}", serde_json::to_string_pretty(&summary).unwrap());
+                if !summary.regressions.is_empty() {
+                    std::process::exit(2);
+                }
+            });
+        }
+    }
+}
+
+/// Run the collectors and jobs of `role` and serve its api until the process stops.
+async fn run(role: Role) {
+    create_pg_pool().await;
+    let pool = get_pg_pool();
+    check_schema(pool).await;
+    init_global_cache(pool).await;
+    if role.collector() {
+        let scheduler = Scheduler::default()
+            .register(
+                "daily_commit",
+                "daily at 00:11",
+                ClockTimer::new_daily(0, 11, true),
+                daily_commit,
+            )
+            .register(
+                "hourly_fresh",
+                "every 5 minutes at :30",
+                ClockTimer::new_interval_with_minute(5, 30, true),
+                hourly_fresh,
+            )
+            .register(
+                "node_rankings",
+                "hourly at :35",
+                ClockTimer::new_hourly(35, 0, true),
+                node_rankings,
+            )
+            .register(
+                "geo_refresh",
+                "hourly at :25",
+                ClockTimer::new_hourly(25, 0, false),
+                geo_refresh,
+            )
+            .register(
+                "expire_udt_relations",
+                "daily at 00:50",
+                ClockTimer::new_daily(0, 50, false),
+                expire_relations,
+            )
+            .register(
+                "weekly_reports",
+                "daily at 01:20, renders the last complete week once summarized",
+                ClockTimer::new_daily(1, 20, false),
+                weekly_reports,
+            )
+            .register_manual(
+                "collect_now",
+                "manual, starts a collection cycle of every idle network",
+                |_| async {
+                    COLLECT_NOW.notify_waiters();
+                    Ok(())
+                },
+            )
+            .register_manual(
+                "reload_udt_cache",
+                "manual, requested by the udt admin endpoints",
+                |_| async {
+                    for net in ENABLED_NETWORKS.iter() {
+                        rebuild_global_cache(get_pg_pool(), *net)
+                            .await
+                            .map_err(|e| format!("{:?}: {}", net, e))?;
+                    }
+                    Ok(())
+                },
+            );
+        // a simulated graph has no funding cells on chain to reconcile with
+        let scheduler = if SIMULATE.load(Ordering::Relaxed) {
+            scheduler
+        } else {
+            scheduler.register(
+                "reconcile_graph",
+                "hourly at :40",
+                ClockTimer::new_hourly(40, 0, false),
+                reconcile_graph,
+            )
+        };
+        scheduler.start(pool).await;
         tokio::spawn(timed_commit_states());
-        tokio::spawn(hourly_fresh());
+        tokio::spawn(upstream::reporter(pool));
+        tokio::spawn(doctor::partition_verifier(pool));
+        tokio::spawn(dead_letter::retrier(pool));
+    }
 
-        http_server().await;
-    });
+    if role.api() {
+        warm_up(pool).await;
+        tokio::spawn(events::listen(pool));
+        tokio::spawn(hot_snapshot_refresher(pool));
+        tokio::spawn(http_cache::track_refreshes(pool));
+        http_server(false).await;
+    } else {
+        // collector only, keep the runtime alive for the spawned tasks
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Run a maintenance `job` on a migrated database and exit, with code 1 when it fails.
+async fn one_off(job: impl Future<Output = Result<(), String>>) {
+    create_pg_pool().await;
+    check_schema(get_pg_pool()).await;
+    if let Err(e) = job.await {
+        log::error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Which half of the service this process runs, so collector and API can be deployed apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    All,
+    Collector,
+    Api,
+}
+
+impl Role {
+    fn collector(self) -> bool {
+        matches!(self, Role::All | Role::Collector)
+    }
+
+    fn api(self) -> bool {
+        matches!(self, Role::All | Role::Api)
+    }
 }
 
-async fn http_server() {
+static ROLE: LazyLock<Role> = LazyLock::new(|| {
+    match config::var("FIBER_DASHBOARD_ROLE")
+        .unwrap_or_default()
+        .to_lowercase()
+        .as_str()
+    {
+        "collector" => Role::Collector,
+        "api" => Role::Api,
+        "" | "all" => Role::All,
+        other => {
+            log::warn!(
+                "Unknown FIBER_DASHBOARD_ROLE {:?}, running all roles",
+                other
+            );
+            Role::All
+        }
+    }
+});
+
+/// Serve the api, `lite` only mounts the routes backed by the storage abstraction since
+/// the rest of the api reads Postgres directly.
+async fn http_server(lite: bool) {
+    use fiber_dashbord_backend::admin::{
+        audit_admin_call, audit_log, create_key, dead_letters, doctor_fix, doctor_report, explain,
+        export_day, graph_discrepancies, hide_udt, list_archives, list_jobs, list_keys, list_udts,
+        maintenance_status, merge_udts, rename_udt, replay_archive, requeue_dead_letters,
+        revoke_key, rotate_key, run_job, set_maintenance, snapshot_anomalies,
+    };
+    use fiber_dashbord_backend::admin_ui::{
+        admin_ui, ui_authenticate, ui_login, ui_login_page, ui_logout, ui_run_job,
+    };
+    use fiber_dashbord_backend::auth::{RequireRole, Role, authenticate, public_auth};
+    use fiber_dashbord_backend::fields::sparse_fields;
+    use fiber_dashbord_backend::freshness::flag_stale_data;
+    use fiber_dashbord_backend::http_cache::{cache_headers, head_as_get};
     use fiber_dashbord_backend::http_server::{
-        all_region, analysis, analysis_hourly, channel_by_state, channel_capacity_distribution,
+        all_region, analysis, analysis_correlate, analysis_hourly, auto_accept_distribution,
+        capacity_histogram_series, changes, channel_by_state, channel_capacity_distribution,
         channel_count_by_asset, channel_count_by_state, channel_info, channel_state,
-        channels_by_node_id, list_channels_hourly, list_channels_monthly, list_nodes_hourly,
-        list_nodes_monthly, node_info, node_udt_infos, nodes_by_region, nodes_by_udt,
-        nodes_fuzzy_by_name_or_id,
+        channel_survival, channels_by_node_id, churn, cohorts, disabled_channels, event_stream,
+        geo_capacity, graph_backbone, graph_snapshot, implementation_fingerprints, ipv6_stats,
+        kpis, list_channels_hourly, list_channels_monthly, list_nodes_hourly, list_nodes_monthly,
+        list_reports, milestone_feed, name_collisions, node_channel_stats, node_info,
+        node_rankings, node_udt_infos, nodes_by_region, nodes_by_udt, nodes_fuzzy_by_name_or_id,
+        nodes_ungeolocated, online_series, port_usage, private_channel_estimate, readyz,
+        require_enabled_network, script_versions, snapshot_channels, snapshot_hours,
+        snapshot_nodes, tlc_params_overview, udt_issuer_stats, udt_trend, upstream_status,
+        weekly_report,
     };
+    use fiber_dashbord_backend::maintenance::reject_during_maintenance;
+    use fiber_dashbord_backend::quota::{enforce_ip_limit, enforce_quota, my_usage};
     use salvo::{
-        Depot, Listener, Request, Response, Router, Server, Service, conn::TcpListener,
-        cors::AllowOrigin, cors::Cors, handler,
+        Depot, Request, Response, Router, Service, cors::AllowOrigin, cors::Cors, handler,
     };
 
     #[handler]
@@ -69,15 +375,22 @@ async fn http_server() {
         _res: &mut Response,
     ) -> Result<String, salvo::Error> {
         let timed_commit_states_heartbeat = TIMED_COMMIT_STATES_HEARTBEAT.load(Ordering::Acquire);
-        let daily_commit_task_heartbeat = DAILY_COMMIT_TASK_HEARTBEAT.load(Ordering::Acquire);
-        let hourly_fresh_task_heartbeat = HOURLY_FRESH_TASK_HEARTBEAT.load(Ordering::Acquire);
+        let daily_commit_task_heartbeat = scheduler::heartbeat("daily_commit");
+        let hourly_fresh_task_heartbeat = scheduler::heartbeat("hourly_fresh");
         let channel_monitor_heartbeat = CHANNEL_MONITOR_HEARTBEAT.load(Ordering::Acquire);
+        let duplicate_nodes_dropped = DUPLICATE_NODES_DROPPED.load(Ordering::Relaxed);
+        let duplicate_channels_dropped = DUPLICATE_CHANNELS_DROPPED.load(Ordering::Relaxed);
+        let channel_handoffs_dropped = CHANNEL_HANDOFFS_DROPPED.load(Ordering::Relaxed);
 
         Ok(serde_json::to_string(&serde_json::json!({
             "timed_commit_states_heartbeat": timed_commit_states_heartbeat,
             "daily_commit_task_heartbeat": daily_commit_task_heartbeat,
             "hourly_fresh_task_heartbeat": hourly_fresh_task_heartbeat,
             "channel_monitor_heartbeat": channel_monitor_heartbeat,
+            "duplicate_nodes_dropped": duplicate_nodes_dropped,
+            "duplicate_channels_dropped": duplicate_channels_dropped,
+            "channel_handoffs_dropped": channel_handoffs_dropped,
+            "chain_checks": chain_check::status(),
         }))
         .unwrap())
     }
@@ -86,44 +399,224 @@ async fn http_server() {
     let cors = Cors::new()
         .allow_origin(AllowOrigin::any())
         .allow_headers(vec!["content-type", "accept", "authorization"])
-        .allow_methods(vec![Method::GET, Method::POST, Method::OPTIONS])
+        .allow_methods(vec![
+            Method::GET,
+            Method::HEAD,
+            Method::POST,
+            Method::OPTIONS,
+        ])
         .into_handler();
-    let router = Router::new()
+    if lite {
+        let router = Router::new()
+            .push(
+                Router::new()
+                    .hoop(require_enabled_network)
+                    .hoop(sparse_fields)
+                    .push(Router::with_path("nodes_hourly").get(list_nodes_hourly))
+                    .push(Router::with_path("channels_hourly").get(list_channels_hourly)),
+            )
+            .push(
+                Router::new()
+                    .hoop(require_enabled_network)
+                    .push(Router::with_path("channel_info").get(channel_info))
+                    .push(Router::with_path("node_info").get(node_info)),
+            )
+            .push(Router::with_path("events").get(event_stream))
+            .push(Router::with_path("health_check").get(health_check))
+            .push(Router::with_path("metrics").get(metrics::metrics));
+        return serve(Service::new(router).hoop(cors)).await;
+    }
+
+    // list endpoints accepting `fields=` sparse fieldsets
+    let lists = Router::new()
+        .hoop(sparse_fields)
         .push(Router::with_path("nodes_hourly").get(list_nodes_hourly))
+        .push(Router::with_path("graph_snapshot").get(graph_snapshot))
         .push(Router::with_path("channels_hourly").get(list_channels_hourly))
-        .push(Router::with_path("node_udt_infos").get(node_udt_infos))
         .push(Router::with_path("nodes_by_udt").post(nodes_by_udt))
         .push(Router::with_path("nodes_nearly_monthly").get(list_nodes_monthly))
         .push(Router::with_path("channels_nearly_monthly").get(list_channels_monthly))
+        .push(Router::with_path("group_channel_by_state").get(channel_by_state))
+        .push(Router::with_path("channels_by_node_id").get(channels_by_node_id))
+        .push(Router::with_path("nodes_by_region").get(nodes_by_region))
+        .push(Router::with_path("nodes_fuzzy_by_name").get(nodes_fuzzy_by_name_or_id));
+    // apis reading one network's data, rejected when that network is not enabled
+    let per_net = Router::new()
+        .filter_fn(head_as_get)
+        .hoop(reject_during_maintenance)
+        .hoop(require_enabled_network)
+        .hoop(flag_stale_data)
+        .hoop(cache_headers)
+        .push(lists)
+        .push(Router::with_path("node_udt_infos").get(node_udt_infos))
         .push(Router::with_path("analysis_hourly").get(analysis_hourly))
-        .push(Router::with_path("analysis").post(analysis))
+        .push(
+            Router::with_path("analysis")
+                .post(analysis)
+                .push(Router::with_path("correlate").get(analysis_correlate)),
+        )
         .push(Router::with_path("channel_state").get(channel_state))
-        .push(Router::with_path("group_channel_by_state").get(channel_by_state))
         .push(Router::with_path("channel_count_by_state").get(channel_count_by_state))
         .push(Router::with_path("channel_count_by_asset").get(channel_count_by_asset))
         .push(Router::with_path("channel_info").get(channel_info))
         .push(Router::with_path("node_info").get(node_info))
-        .push(Router::with_path("channels_by_node_id").get(channels_by_node_id))
-        .push(Router::with_path("nodes_by_region").get(nodes_by_region))
-        .push(Router::with_path("nodes_fuzzy_by_name").get(nodes_fuzzy_by_name_or_id))
         .push(Router::with_path("all_region").get(all_region))
         .push(Router::with_path("channel_capacity_distribution").get(channel_capacity_distribution))
-        .push(Router::with_path("health_check").get(health_check));
+        .push(Router::with_path("feed.xml").get(milestone_feed))
+        .push(
+            Router::with_path("reports")
+                .get(list_reports)
+                .push(Router::with_path("{week}").get(weekly_report)),
+        )
+        .push(Router::with_path("script_versions").get(script_versions))
+        .push(Router::with_path("churn").get(churn))
+        .push(Router::with_path("channel_survival").get(channel_survival))
+        .push(Router::with_path("geo_capacity").get(geo_capacity))
+        .push(Router::with_path("nodes_ungeolocated").get(nodes_ungeolocated))
+        .push(Router::with_path("ipv6_stats").get(ipv6_stats))
+        .push(Router::with_path("port_usage").get(port_usage))
+        .push(Router::with_path("cohorts").get(cohorts))
+        .push(Router::with_path("udt_trend").get(udt_trend))
+        .push(Router::with_path("auto_accept_distribution").get(auto_accept_distribution))
+        .push(Router::with_path("udt_issuer_stats").get(udt_issuer_stats))
+        .push(Router::with_path("tlc_params_overview").get(tlc_params_overview))
+        .push(Router::with_path("disabled_channels").get(disabled_channels))
+        .push(Router::with_path("node_channel_stats").get(node_channel_stats))
+        .push(Router::with_path("node_rankings").get(node_rankings))
+        .push(Router::with_path("graph_backbone").get(graph_backbone))
+        .push(Router::with_path("online_series").get(online_series))
+        .push(Router::with_path("capacity_histogram_series").get(capacity_histogram_series))
+        .push(Router::with_path("kpis").get(kpis))
+        .push(Router::with_path("private_channel_estimate").get(private_channel_estimate))
+        .push(Router::with_path("implementation_fingerprints").get(implementation_fingerprints))
+        .push(Router::with_path("name_collisions").get(name_collisions));
+    // data apis, guarded by the `read` role when API_KEYS_REQUIRED is set
+    let public = Router::new()
+        .hoop(public_auth)
+        .hoop(enforce_quota)
+        .hoop(enforce_ip_limit)
+        .push(per_net)
+        .push(
+            Router::with_path("snapshots/{net}")
+                .hoop(reject_during_maintenance)
+                .get(snapshot_hours)
+                .push(Router::with_path("{hour}/nodes.json").get(snapshot_nodes))
+                .push(Router::with_path("{hour}/channels.json").get(snapshot_channels)),
+        )
+        .push(
+            Router::with_path("changes")
+                .hoop(reject_during_maintenance)
+                .hoop(require_enabled_network)
+                .get(changes),
+        )
+        .push(Router::with_path("events").get(event_stream))
+        .push(Router::with_path("upstream_status").get(upstream_status));
+    let router = Router::new()
+        .push(public)
+        .push(Router::with_path("health_check").get(health_check))
+        .push(Router::with_path("readyz").get(readyz))
+        .push(Router::with_path("metrics").get(metrics::metrics))
+        .push(
+            Router::with_path("me/usage")
+                .hoop(authenticate)
+                .get(my_usage),
+        )
+        .push(
+            Router::with_path("admin/ui")
+                .push(Router::with_path("login").get(ui_login_page).post(ui_login))
+                .push(Router::with_path("logout").post(ui_logout))
+                .push(
+                    Router::new()
+                        .hoop(ui_authenticate)
+                        .hoop(audit_admin_call)
+                        .get(admin_ui)
+                        .push(Router::with_path("jobs/run").post(ui_run_job)),
+                ),
+        )
+        .push(
+            Router::with_path("admin")
+                .hoop(authenticate)
+                .hoop(audit_admin_call)
+                .push(
+                    Router::with_path("export")
+                        .hoop(RequireRole(Role::Export))
+                        .post(export_day),
+                )
+                .push(
+                    Router::new()
+                        .hoop(RequireRole(Role::Admin))
+                        .push(
+                            Router::with_path("doctor")
+                                .get(doctor_report)
+                                .post(doctor_fix),
+                        )
+                        .push(Router::with_path("explain").get(explain))
+                        .push(
+                            Router::with_path("archives")
+                                .get(list_archives)
+                                .push(Router::with_path("replay").post(replay_archive)),
+                        )
+                        .push(Router::with_path("audit_log").get(audit_log))
+                        .push(
+                            Router::with_path("jobs")
+                                .get(list_jobs)
+                                .push(Router::with_path("run").post(run_job)),
+                        )
+                        .push(
+                            Router::with_path("maintenance")
+                                .get(maintenance_status)
+                                .post(set_maintenance),
+                        )
+                        .push(Router::with_path("graph_discrepancies").get(graph_discrepancies))
+                        .push(Router::with_path("snapshot_anomalies").get(snapshot_anomalies))
+                        .push(
+                            Router::with_path("udts")
+                                .get(list_udts)
+                                .push(Router::with_path("rename").post(rename_udt))
+                                .push(Router::with_path("merge").post(merge_udts))
+                                .push(Router::with_path("hide").post(hide_udt)),
+                        )
+                        .push(
+                            Router::with_path("dead_letters")
+                                .get(dead_letters)
+                                .push(Router::with_path("requeue").post(requeue_dead_letters)),
+                        )
+                        .push(
+                            Router::with_path("keys")
+                                .get(list_keys)
+                                .post(create_key)
+                                .push(Router::with_path("rotate").post(rotate_key))
+                                .push(Router::with_path("revoke").post(revoke_key)),
+                        ),
+                ),
+        );
 
-    let service = Service::new(router).hoop(cors);
-    let http_port = std::env::var("HTTP_PORT").unwrap_or("8000".to_string());
+    serve(Service::new(router).hoop(cors)).await;
+}
+
+async fn serve(service: salvo::Service) {
+    use fiber_dashbord_backend::{
+        access_log::access_log, client_ip::resolve_client_ip, panic_guard::catch_panic,
+    };
+    use salvo::{Listener, Server, conn::TcpListener};
+
+    let http_port = config::var("HTTP_PORT").unwrap_or("8000".to_string());
     let listener = TcpListener::new(format!("0.0.0.0:{}", http_port))
         .bind()
         .await;
     log::info!("Starting HTTP server on port {}", http_port);
-    Server::new(listener).serve(service).await;
+    Server::new(listener)
+        .serve(
+            service
+                .hoop(resolve_client_ip)
+                .hoop(access_log)
+                .hoop(catch_panic),
+        )
+        .await;
 }
 
 static MAINNET_FIBER_RPC_URL: LazyLock<Option<Url>> = LazyLock::new(|| {
-    let url = std::env::var("FIBER_MAINNET_RPC_URL")
-        .map(|url| Url::parse(&url).ok())
-        .ok()
-        .flatten();
+    let url = config::var("FIBER_MAINNET_RPC_URL").and_then(|url| Url::parse(&url).ok());
     if url.is_none() {
         log::warn!("FIBER_MAINNET_RPC_URL is not set, mainnet fiber dashbord will be disabled");
     }
@@ -131,12 +624,9 @@ static MAINNET_FIBER_RPC_URL: LazyLock<Option<Url>> = LazyLock::new(|| {
     url
 });
 static MAINNET_FIBER_RPC_BEARER_TOKEN: LazyLock<Option<String>> =
-    LazyLock::new(|| std::env::var("FIBER_MAINNET_RPC_BEARER_TOKEN").ok());
+    LazyLock::new(|| config::var("FIBER_MAINNET_RPC_BEARER_TOKEN"));
 static TESTNET_FIBER_RPC_URL: LazyLock<Option<Url>> = LazyLock::new(|| {
-    let url = std::env::var("FIBER_TESTNET_RPC_URL")
-        .map(|url| Url::parse(&url).ok())
-        .ok()
-        .flatten();
+    let url = config::var("FIBER_TESTNET_RPC_URL").and_then(|url| Url::parse(&url).ok());
     if url.is_none() {
         log::warn!("FIBER_TESTNET_RPC_URL is not set, testnet fiber dashbord will be disabled");
     }
@@ -144,269 +634,651 @@ static TESTNET_FIBER_RPC_URL: LazyLock<Option<Url>> = LazyLock::new(|| {
     url
 });
 static TESTNET_FIBER_RPC_BEARER_TOKEN: LazyLock<Option<String>> =
-    LazyLock::new(|| std::env::var("FIBER_TESTNET_RPC_BEARER_TOKEN").ok());
+    LazyLock::new(|| config::var("FIBER_TESTNET_RPC_BEARER_TOKEN"));
 
+/// `--simulate`, collect a synthetic graph of every enabled network instead of calling rpcs.
+static SIMULATE: AtomicBool = AtomicBool::new(false);
+
+/// Enabled networks the collector can reach, the only ones scheduled jobs work on. All of
+/// them when simulating.
 static NETS: LazyLock<Vec<fiber_dashbord_backend::Network>> = LazyLock::new(|| {
-    MAINNET_FIBER_RPC_URL
-        .as_ref()
-        .map(|_| fiber_dashbord_backend::Network::Mainnet)
-        .into_iter()
-        .chain(
-            TESTNET_FIBER_RPC_URL
-                .as_ref()
-                .map(|_| fiber_dashbord_backend::Network::Testnet),
-        )
+    ENABLED_NETWORKS
+        .iter()
+        .copied()
+        .filter(|net| {
+            if SIMULATE.load(Ordering::Relaxed) {
+                return true;
+            }
+            let url = match net {
+                fiber_dashbord_backend::Network::Mainnet => &*MAINNET_FIBER_RPC_URL,
+                fiber_dashbord_backend::Network::Testnet => &*TESTNET_FIBER_RPC_URL,
+            };
+            if url.is_none() {
+                log::warn!(
+                    "{:?} is enabled but has no fiber rpc url, it is not collected",
+                    net
+                );
+            }
+            url.is_some()
+        })
         .collect::<Vec<_>>()
 });
 
 static TIMED_COMMIT_STATES_HEARTBEAT: AtomicU64 = AtomicU64::new(0);
 
 async fn timed_commit_states() {
-    let mut rpc = RpcClient::new();
-    let (tx, rx) = tokio::sync::mpsc::channel(8);
+    if SIMULATE.load(Ordering::Relaxed) {
+        for net in NETS.iter() {
+            tokio::spawn(simulate_network(*net));
+        }
+    } else {
+        let rpc = RpcClient::new();
+        let (tx, rx) = tokio::sync::mpsc::channel(8);
 
-    tokio::spawn(channel_states_monitor(rpc.clone(), rx));
-    let (mut testnet_init, mut mainnet_init) = (false, false);
+        tokio::spawn(channel_states_monitor(rpc.clone(), rx));
+        for net in NETS.iter() {
+            tokio::spawn(collect_network(*net, rpc.clone(), tx.clone()));
+        }
+    }
 
     let mut heartbeat_timer = tokio::time::interval(tokio::time::Duration::from_secs(60));
     heartbeat_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
-    let mut timed_timer = tokio::time::interval(tokio::time::Duration::from_secs(60 * 30));
+    loop {
+        heartbeat_timer.tick().await;
+        let timestamp = Utc::now().timestamp() as u64;
+        TIMED_COMMIT_STATES_HEARTBEAT.store(timestamp, Ordering::Release);
+    }
+}
+
+/// Wakes every collection loop waiting for its next cycle.
+static COLLECT_NOW: tokio::sync::Notify = tokio::sync::Notify::const_new();
+
+/// Collection loop of one network on its own timer, so a slow or failing RPC of one network
+/// neither delays nor stops the other. The `collect_now` job starts a cycle early.
+async fn collect_network(
+    net: fiber_dashbord_backend::Network,
+    mut rpc: RpcClient,
+    tx: tokio::sync::mpsc::Sender<(fiber_dashbord_backend::Network, Vec<JsonBytes>)>,
+) {
+    let mut initialized = false;
+    let mut timed_timer =
+        tokio::time::interval(tokio::time::Duration::from_secs(*COLLECT_INTERVAL_SECS));
     timed_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            scheduled = timed_timer.tick() => metrics::observe_interval_lag("collect", scheduled),
+            _ = COLLECT_NOW.notified() => log::info!("{:?}, collection requested", net),
+        }
+        if let Err(e) = collect_cycle(net, &mut rpc, &tx, &mut initialized).await {
+            log::error!("{:?}, collection cycle failed: {}", net, e);
+        }
+    }
+}
 
+/// Collection loop of `--simulate`, committing the synthetic graph of `net` every
+/// `SIMULATE_INTERVAL_SECS` through the same pages as a real one. There is no chain to follow,
+/// so the channel state monitor does not run and the monitor phase is recorded as not run.
+async fn simulate_network(net: fiber_dashbord_backend::Network) {
+    let mut simulation = Simulation::new(net, SIM_CONFIG.clone());
+    let mut timed_timer =
+        tokio::time::interval(tokio::time::Duration::from_secs(*SIMULATE_INTERVAL_SECS));
+    timed_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    log::info!("{:?}, simulating {:?}", net, *SIM_CONFIG);
     loop {
         tokio::select! {
-                _ = heartbeat_timer.tick() => {
-                    let timestamp = Utc::now().timestamp() as u64;
-                    TIMED_COMMIT_STATES_HEARTBEAT.store(timestamp, Ordering::Release);
+            scheduled = timed_timer.tick() => metrics::observe_interval_lag("simulate", scheduled),
+            _ = COLLECT_NOW.notified() => log::info!("{:?}, collection requested", net),
+        }
+        let mut run = CollectorRun::start(net);
+        let time = Utc::now();
+        simulation.step(time);
+        let mut committed = HashSet::new();
+        let mut count = 0;
+        let mut result = Ok(());
+        for page in simulation
+            .graph_nodes(time)
+            .chunks(*GRAPH_PAGE_SIZE as usize)
+        {
+            let nodes = dedup_node_page(net, page.to_vec(), &mut committed);
+            match commit_page(net, nodes, Vec::new(), &time).await {
+                Ok((nodes, _)) => count += nodes,
+                Err(e) => {
+                    result = Err(e.to_string());
+                    break;
+                }
+            }
+        }
+        run.nodes = phase(result, count);
+        let mut committed = HashSet::new();
+        let mut count = 0;
+        let mut result = Ok(());
+        for page in simulation
+            .graph_channels(time)
+            .chunks(*GRAPH_PAGE_SIZE as usize)
+        {
+            let channels = dedup_channel_page(net, page.to_vec(), &mut committed);
+            match commit_page(net, Vec::new(), channels, &time).await {
+                Ok((_, channels)) => count += channels,
+                Err(e) => {
+                    result = Err(e.to_string());
+                    break;
                 }
-                _ = timed_timer.tick() => {
-                     timed_commit_states_inner(&mut rpc, &tx, &mut mainnet_init, &mut testnet_init).await;
             }
         }
+        run.channels = phase(result, count);
+        announce_snapshot(
+            net,
+            run.nodes.count.unwrap_or(0),
+            run.channels.count.unwrap_or(0),
+            &time,
+        )
+        .await;
+        for e in [&run.nodes.error, &run.channels.error]
+            .into_iter()
+            .flatten()
+        {
+            log::error!("Failed to commit simulated {:?} snapshot: {}", net, e);
+        }
+        if let Err(e) = run.record(get_pg_pool()).await {
+            log::error!("{:?}, failed to record the simulated cycle: {}", net, e);
+        }
     }
 }
 
-async fn timed_commit_states_inner(
+/// One collection cycle of `net`. Nodes, channels and the hand-off to the state monitor
+/// commit independently, their outcome is recorded in `collector_runs`.
+async fn collect_cycle(
+    net: fiber_dashbord_backend::Network,
     rpc: &mut RpcClient,
     tx: &tokio::sync::mpsc::Sender<(fiber_dashbord_backend::Network, Vec<JsonBytes>)>,
-    mainnet_init: &mut bool,
-    testnet_init: &mut bool,
-) {
-    for net in NETS.iter() {
-        let url = match net {
-            fiber_dashbord_backend::Network::Mainnet => {
-                rpc.set_bearer_token(MAINNET_FIBER_RPC_BEARER_TOKEN.clone());
-                MAINNET_FIBER_RPC_URL.clone().unwrap()
-            }
-            fiber_dashbord_backend::Network::Testnet => {
-                rpc.set_bearer_token(TESTNET_FIBER_RPC_BEARER_TOKEN.clone());
-                TESTNET_FIBER_RPC_URL.clone().unwrap()
+    initialized: &mut bool,
+) -> Result<(), sqlx::Error> {
+    let pool = get_pg_pool();
+    let mut run = CollectorRun::start(net);
+    let Some(url) = verified_url(rpc, net).await else {
+        return Ok(());
+    };
+
+    let time = Utc::now();
+    let mut archived = RawSnapshot::new(net, time);
+    let guard = PlausibilityGuard::load(pool, net, Half::Nodes).await;
+    run.nodes = collect_nodes(rpc, &url, net, &time, &mut archived, guard).await;
+    let guard = PlausibilityGuard::load(pool, net, Half::Channels).await;
+    run.channels = collect_channels(rpc, &url, net, &time, &mut archived, guard).await;
+    archive::store(&archived).await;
+    let (nodes, channels) = (
+        run.nodes.count.unwrap_or(0),
+        run.channels.count.unwrap_or(0),
+    );
+    if nodes > 0 || channels > 0 {
+        announce_snapshot(net, nodes, channels, &time).await;
+    }
+    run.monitor = hand_off(pool, net, tx, &time).await;
+    for (phase, result) in [
+        ("nodes", &run.nodes),
+        ("channels", &run.channels),
+        ("monitor", &run.monitor),
+    ] {
+        if let Some(e) = &result.error {
+            log::error!(
+                "{:?}, {} phase of the collection cycle failed: {}",
+                net,
+                phase,
+                e
+            );
+        }
+    }
+    run.record(pool).await?;
+
+    if !*initialized && (nodes > 0 || channels > 0) {
+        let sql = format!("SELECT COUNT(*) FROM {}", net.online_nodes_hourly());
+        let count = sqlx::query(&sql)
+            .fetch_one(pool)
+            .await
+            .map(|row| row.get::<i64, _>(0))?;
+        if count == 0 {
+            for aggregate in [net.online_nodes_hourly(), net.online_channels_hourly()] {
+                let sql = format!(
+                    "CALL refresh_continuous_aggregate('{}', NULL, NULL)",
+                    aggregate
+                );
+                sqlx::query(&sql).execute(pool).await?;
             }
-        };
+        }
+        *initialized = true;
+    }
+    Ok(())
+}
 
-        let mut raw_nodes = Vec::new();
-        let mut after_cursor = None;
+/// Hand the channels of the snapshot at `time` the monitor has not persisted yet to it,
+/// without waiting on a busy monitor: dropped outpoints are still untracked next cycle and
+/// sent again.
+async fn hand_off(
+    pool: &sqlx::Pool<sqlx::Postgres>,
+    net: fiber_dashbord_backend::Network,
+    tx: &tokio::sync::mpsc::Sender<(fiber_dashbord_backend::Network, Vec<JsonBytes>)>,
+    time: &DateTime<Utc>,
+) -> Phase {
+    let new = match untracked_outpoints(pool, net, time).await {
+        Ok(new) => new,
+        Err(e) => return Phase::failed(e),
+    };
+    if new.is_empty() {
+        return Phase::done(0);
+    }
+    let count = new.len();
+    match tx.try_send((net, new)) {
+        Ok(()) => Phase::done(count),
+        Err(TrySendError::Full((_, new))) => {
+            CHANNEL_HANDOFFS_DROPPED.fetch_add(new.len() as u64, Ordering::Relaxed);
+            Phase::failed(format!(
+                "channel state monitor is busy, {} new channels deferred to the next cycle",
+                new.len()
+            ))
+        }
+        Err(TrySendError::Closed(_)) => Phase::failed("channel state monitor is gone"),
+    }
+}
+
+/// Fiber rpc url of `net` with its bearer token set on `rpc`, `None` when the chain check
+/// does not pass.
+async fn verified_url(rpc: &mut RpcClient, net: fiber_dashbord_backend::Network) -> Option<Url> {
+    let url = match net {
+        fiber_dashbord_backend::Network::Mainnet => {
+            rpc.set_bearer_token(MAINNET_FIBER_RPC_BEARER_TOKEN.clone());
+            MAINNET_FIBER_RPC_URL.clone().unwrap()
+        }
+        fiber_dashbord_backend::Network::Testnet => {
+            rpc.set_bearer_token(TESTNET_FIBER_RPC_BEARER_TOKEN.clone());
+            TESTNET_FIBER_RPC_URL.clone().unwrap()
+        }
+    };
+    chain_check::verify(net, rpc, &url).await.then_some(url)
+}
+
+/// Give up on a graph half after this many consecutive failed page requests.
+const PAGE_ATTEMPTS: usize = 5;
+
+/// Largest page size keeping one page within a single insert statement, see the bind limits
+/// of `NodeInfoDBSchema::use_sqlx` / `ChannelInfoDBSchema::use_sqlx`.
+const MAX_GRAPH_PAGE_SIZE: u64 = 3000;
+
+/// Seconds between collection cycles, `COLLECT_INTERVAL_SECS`.
+static COLLECT_INTERVAL_SECS: LazyLock<u64> = LazyLock::new(|| {
+    config::parse_var("COLLECT_INTERVAL_SECS")
+        .unwrap_or(60 * 30)
+        .max(60)
+});
+
+/// `limit` of `graph_nodes` / `graph_channels` requests, from `GRAPH_PAGE_SIZE`.
+static GRAPH_PAGE_SIZE: LazyLock<u64> = LazyLock::new(|| {
+    let size = config::parse_var("GRAPH_PAGE_SIZE").unwrap_or(500);
+    if !(1..=MAX_GRAPH_PAGE_SIZE).contains(&size) {
+        log::warn!(
+            "GRAPH_PAGE_SIZE {} out of 1..={}, clamping",
+            size,
+            MAX_GRAPH_PAGE_SIZE
+        );
+    }
+    size.clamp(1, MAX_GRAPH_PAGE_SIZE)
+});
+
+/// Cursor of the page after one ending at `last_cursor`, `None` once the graph is exhausted:
+/// an empty page, an empty cursor, or a cursor that did not move.
+fn next_cursor(
+    after: &Option<JsonBytes>,
+    empty: bool,
+    last_cursor: JsonBytes,
+) -> Option<JsonBytes> {
+    if empty || last_cursor.is_empty() || after.as_ref() == Some(&last_cursor) {
+        None
+    } else {
+        Some(last_cursor)
+    }
+}
+
+/// Request one graph page, retrying up to [`PAGE_ATTEMPTS`] times.
+async fn fetch_page<F: Future<Output = std::io::Result<serde_json::Value>>>(
+    net: fiber_dashbord_backend::Network,
+    kind: &str,
+    request: impl Fn() -> F,
+) -> Result<serde_json::Value, String> {
+    let mut failures = 0;
+    loop {
+        match request().await {
+            Ok(page) => return Ok(page),
+            Err(e) => {
+                log::warn!("Failed to get {:?}'s {} graph: {}", net, kind, e);
+                failures += 1;
+                if failures >= PAGE_ATTEMPTS {
+                    return Err(format!("failed to get {} graph: {}", kind, e));
+                }
+                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
 
+/// Stream the `graph_nodes` pages of `net`, committing each page at `time` as it arrives so
+/// memory stays flat whatever the size of the graph. Only pages held back by the
+/// [`PlausibilityGuard`] until the graph is plausibly large wait in memory.
+async fn collect_nodes(
+    rpc: &RpcClient,
+    url: &Url,
+    net: fiber_dashbord_backend::Network,
+    time: &DateTime<Utc>,
+    archived: &mut RawSnapshot,
+    mut guard: PlausibilityGuard<NodeInfo>,
+) -> Phase {
+    let mut committed = HashSet::new();
+    let mut count = 0;
+    let mut after_cursor = None;
+    let result: Result<(), String> = async {
         loop {
-            if let Ok(nodes) = rpc
-                .get_node_graph(
+            let page = fetch_page(net, "node", || {
+                rpc.get_node_graph_raw(
                     url.clone(),
                     GraphNodesParams {
-                        limit: None,
+                        limit: Some(*GRAPH_PAGE_SIZE),
                         after: after_cursor.clone(),
                     },
                 )
-                .await
-            {
-                let has_more = nodes.nodes.len() == 500;
-                raw_nodes.extend(nodes.nodes);
-
-                if !has_more {
-                    break;
-                }
+            })
+            .await?;
+            archived.push_nodes(&page);
+            let page = serde_json::from_value::<GraphNodesResult>(page)
+                .map_err(|e| format!("failed to parse node graph: {}", e))?;
+            let cursor = next_cursor(&after_cursor, page.nodes.is_empty(), page.last_cursor);
+            let nodes = guard.admit(dedup_node_page(net, page.nodes, &mut committed));
+            if !nodes.is_empty() {
+                count += commit_page(net, nodes, Vec::new(), time)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .0;
+            }
 
-                after_cursor = Some(nodes.last_cursor);
-            } else {
-                log::warn!("Failed to get {:?}'s node graph", net);
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            if cursor.is_none() {
+                return guard.settle(net, time).await;
             }
+            after_cursor = cursor;
         }
+    }
+    .await;
+    phase(result, count)
+}
 
-        let mut raw_channels = Vec::new();
-        let mut after_cursor = None;
-
+/// Stream the `graph_channels` pages of `net`, committing each page at `time` as it arrives.
+async fn collect_channels(
+    rpc: &RpcClient,
+    url: &Url,
+    net: fiber_dashbord_backend::Network,
+    time: &DateTime<Utc>,
+    archived: &mut RawSnapshot,
+    mut guard: PlausibilityGuard<ChannelInfo>,
+) -> Phase {
+    let mut committed = HashSet::new();
+    let mut count = 0;
+    let mut after_cursor = None;
+    let result: Result<(), String> = async {
         loop {
-            if let Ok(channels) = rpc
-                .get_channel_graph(
+            let page = fetch_page(net, "channel", || {
+                rpc.get_channel_graph_raw(
                     url.clone(),
                     GraphChannelsParams {
-                        limit: None,
+                        limit: Some(*GRAPH_PAGE_SIZE),
                         after: after_cursor.clone(),
                     },
                 )
-                .await
-            {
-                let has_more = channels.channels.len() == 500;
-                raw_channels.extend(channels.channels);
-
-                if !has_more {
-                    break;
-                }
+            })
+            .await?;
+            archived.push_channels(&page);
+            let page = serde_json::from_value::<GraphChannelsResult>(page)
+                .map_err(|e| format!("failed to parse channel graph: {}", e))?;
+            let cursor = next_cursor(&after_cursor, page.channels.is_empty(), page.last_cursor);
+            let channels = guard.admit(dedup_channel_page(net, page.channels, &mut committed));
+            if !channels.is_empty() {
+                count += commit_page(net, Vec::new(), channels, time)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .1;
+            }
 
-                after_cursor = Some(channels.last_cursor);
-            } else {
-                log::warn!("Failed to get {:?}'s channel graph", net);
-                tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+            if cursor.is_none() {
+                return guard.settle(net, time).await;
             }
+            after_cursor = cursor;
         }
+    }
+    .await;
+    phase(result, count)
+}
+
+/// Phase of a streamed graph half, pages committed before a failure stay committed.
+fn phase(result: Result<(), String>, count: usize) -> Phase {
+    match result {
+        Ok(()) => Phase::done(count),
+        Err(e) => Phase {
+            count: Some(count),
+            ..Phase::failed(e)
+        },
+    }
+}
 
-        let mut node_schemas = Vec::with_capacity(raw_nodes.len());
-        let mut udt_infos = Vec::new();
-        let mut udt_dep_relations = Vec::new();
-        let mut udt_node_relations = Vec::new();
-        for node in raw_nodes {
-            let (node_schema, udt_info, udt_dep_relation, udt_node_relation) =
-                from_rpc_to_db_schema(node, *net).await;
-            node_schemas.push(node_schema);
-            udt_infos.extend(udt_info);
-            udt_dep_relations.extend(udt_dep_relation);
-            udt_node_relations.extend(udt_node_relation);
+/// Collection loop of the lite mode, snapshots only, without channel state monitoring.
+async fn lite_commit() {
+    let mut rpc = RpcClient::new();
+    let mut timed_timer =
+        tokio::time::interval(tokio::time::Duration::from_secs(*COLLECT_INTERVAL_SECS));
+    timed_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        let scheduled = timed_timer.tick().await;
+        metrics::observe_interval_lag("lite_collect", scheduled);
+        TIMED_COMMIT_STATES_HEARTBEAT.store(Utc::now().timestamp() as u64, Ordering::Release);
+        for net in NETS.iter() {
+            let Some(url) = verified_url(&mut rpc, *net).await else {
+                continue;
+            };
+            let time = Utc::now();
+            let mut archived = RawSnapshot::new(*net, time);
+            let guard = PlausibilityGuard::unchecked(Half::Nodes);
+            let nodes = collect_nodes(&rpc, &url, *net, &time, &mut archived, guard).await;
+            let guard = PlausibilityGuard::unchecked(Half::Channels);
+            let channels = collect_channels(&rpc, &url, *net, &time, &mut archived, guard).await;
+            archive::store(&archived).await;
+            for e in [&nodes.error, &channels.error].into_iter().flatten() {
+                log::error!("Failed to commit {:?} snapshot: {}", net, e);
+            }
+            announce_snapshot(
+                *net,
+                nodes.count.unwrap_or(0),
+                channels.count.unwrap_or(0),
+                &time,
+            )
+            .await;
         }
+    }
+}
 
-        let mut channel_schemas = Vec::with_capacity(raw_channels.len());
-        tx.send((
-            *net,
-            raw_channels
-                .iter()
-                .map(|c| c.channel_outpoint.clone())
-                .collect::<Vec<_>>(),
-        ))
-        .await
-        .expect("Failed to send channel outpoints to monitor");
-        for channel in raw_channels {
-            let channel_schema: ChannelInfoDBSchema = (channel, *net).into();
-            channel_schemas.push(channel_schema);
+/// Daily summary of the last 20 days, then the jobs that read it.
+async fn daily_commit(trigger_time: DateTime<Utc>) -> Result<(), String> {
+    let pool = get_pg_pool();
+    daily_statistics(
+        pool,
+        Some(Utc::now() - chrono::Duration::days(20)),
+        NETS.iter(),
+    )
+    .await
+    .map_err(|e| format!("Failed to commit daily statistics: {}", e))?;
+    events::emit(pool, Event::DailySummaryCommitted { time: trigger_time }).await;
+    log::info!("Daily statistics committed at {}", trigger_time);
+    let day = (trigger_time - chrono::Duration::days(1)).date_naive();
+    webhook::push_daily_summary(pool, day, NETS.iter()).await;
+    if let Err(e) = export::export_day(pool, day, NETS.iter()).await {
+        log::error!("Failed to export {}: {}", day, e);
+    }
+    for net in NETS.iter() {
+        if let Err(e) = survival::compute(pool, *net).await {
+            log::error!("Failed to compute {:?} channel survival: {}", net, e);
         }
+        if let Err(e) = fiber_dashbord_backend::kpis::compute(pool, *net).await {
+            log::error!("Failed to compute {:?} kpis: {}", net, e);
+        }
+        if let Err(e) = fingerprints::compute(pool, *net, day).await {
+            log::error!("Failed to compute {:?} funding fingerprints: {}", net, e);
+        }
+        if let Err(e) = cohorts::compute_if_due(pool, *net, trigger_time).await {
+            log::error!("Failed to compute {:?} node cohorts: {}", net, e);
+        }
+    }
+    match changes::prune(pool).await {
+        Ok(pruned) => log::info!("Pruned {} changes", pruned),
+        Err(e) => log::error!("Failed to prune changes: {}", e),
+    }
+    Ok(())
+}
+
+/// `daily-stats`, the summary of the last `days` days of every enabled network. The rpcs are
+/// not needed, so networks without a fiber url are summarized too.
+async fn daily_stats(days: i64) -> Result<(), String> {
+    let pool = get_pg_pool();
+    daily_statistics(
+        pool,
+        Some(Utc::now() - chrono::Duration::days(days)),
+        ENABLED_NETWORKS.iter(),
+    )
+    .await
+    .map_err(|e| format!("Failed to commit daily statistics: {}", e))?;
+    events::emit(pool, Event::DailySummaryCommitted { time: Utc::now() }).await;
+    for net in ENABLED_NETWORKS.iter() {
+        fiber_dashbord_backend::kpis::compute(pool, *net)
+            .await
+            .map_err(|e| format!("Failed to compute {:?} kpis: {}", net, e))?;
+    }
+    log::info!("Daily statistics of the last {} days committed", days);
+    Ok(())
+}
+
+/// `backfill`, summarize every day since `from` again and rebuild what is derived from the
+/// summaries, the weekly reports of the complete weeks starting on or after `from` included.
+async fn backfill(from: NaiveDate) -> Result<(), String> {
+    let pool = get_pg_pool();
+    daily_statistics(
+        pool,
+        Some(from.and_time(NaiveTime::MIN).and_utc()),
+        ENABLED_NETWORKS.iter(),
+    )
+    .await
+    .map_err(|e| format!("Failed to commit daily statistics since {}: {}", from, e))?;
+    events::emit(pool, Event::DailySummaryCommitted { time: Utc::now() }).await;
 
+    let first_week =
+        from + chrono::Duration::days((7 - from.weekday().num_days_from_monday() as i64) % 7);
+    let last_week = reports::last_complete_week(Utc::now().date_naive());
+    for net in ENABLED_NETWORKS.iter() {
+        fiber_dashbord_backend::kpis::compute(pool, *net)
+            .await
+            .map_err(|e| format!("Failed to compute {:?} kpis: {}", net, e))?;
+        survival::compute(pool, *net)
+            .await
+            .map_err(|e| format!("Failed to compute {:?} channel survival: {}", net, e))?;
+        let mut week = first_week;
+        let mut rendered = 0;
+        while week <= last_week {
+            if reports::generate(pool, *net, week)
+                .await
+                .map_err(|e| format!("Failed to generate {:?} report of {}: {}", net, week, e))?
+            {
+                rendered += 1;
+            }
+            week += chrono::Duration::days(7);
+        }
         log::info!(
-            "{:?} Fetched {} nodes and {} channels",
+            "{:?}, backfilled since {}, {} weekly reports rendered",
             net,
-            node_schemas.len(),
-            channel_schemas.len()
+            from,
+            rendered
         );
+    }
+    Ok(())
+}
 
-        let now = Utc::now();
+/// Compare the gossip graph of every network with its live funding cells.
+async fn reconcile_graph(_trigger_time: DateTime<Utc>) -> Result<(), String> {
+    let pool = get_pg_pool();
+    let mut rpc = RpcClient::new();
+    for net in NETS.iter() {
+        if !chain_check::ingest_allowed(*net) {
+            continue;
+        }
+        let summary = reconcile::run(pool, &mut rpc, *net)
+            .await
+            .map_err(|e| format!("Failed to reconcile {:?} graph: {}", net, e))?;
+        log::info!("{:?}, graph reconciled: {:?}", net, summary);
+    }
+    Ok(())
+}
 
-        let pool = get_pg_pool();
-        insert_batch(
-            pool,
-            &udt_infos,
-            &udt_dep_relations,
-            &udt_node_relations,
-            &node_schemas,
-            &channel_schemas,
-            &now,
-            *net,
-        )
-        .await
-        .expect("Failed to insert batch");
-        if match net {
-            fiber_dashbord_backend::Network::Mainnet => !*mainnet_init,
-            fiber_dashbord_backend::Network::Testnet => !*testnet_init,
-        } {
-            let sql = format!("SELECT COUNT(*) FROM {}", net.online_nodes_hourly());
-            let count = sqlx::query(&sql)
-                .fetch_one(pool)
-                .await
-                .map(|row| row.get::<i64, _>(0))
-                .expect("Failed to count rows");
-            if count == 0 {
-                let flush_nodes_sql = format!(
-                    "CALL refresh_continuous_aggregate('{}', NULL, NULL)",
-                    net.online_nodes_hourly()
-                );
-                let flush_channels_sql = format!(
-                    "CALL refresh_continuous_aggregate('{}', NULL, NULL)",
-                    net.online_channels_hourly()
-                );
-                sqlx::query(&flush_nodes_sql)
-                    .execute(pool)
-                    .await
-                    .expect("Failed to refresh continuous aggregate");
-                sqlx::query(&flush_channels_sql)
-                    .execute(pool)
-                    .await
-                    .expect("Failed to refresh continuous aggregate");
-            }
-            match net {
-                fiber_dashbord_backend::Network::Mainnet => *mainnet_init = true,
-                fiber_dashbord_backend::Network::Testnet => *testnet_init = true,
-            }
+/// Drop the node UDT relations nodes stopped announcing.
+async fn expire_relations(_trigger_time: DateTime<Utc>) -> Result<(), String> {
+    for net in NETS.iter() {
+        let expired = expire_udt_relations(get_pg_pool(), *net)
+            .await
+            .map_err(|e| format!("Failed to expire {:?} udt relations: {}", net, e))?;
+        log::info!("{:?}, expired {} udt relations", net, expired);
+    }
+    Ok(())
+}
+
+async fn weekly_reports(trigger_time: DateTime<Utc>) -> Result<(), String> {
+    for net in NETS.iter() {
+        let generated = reports::generate_missing(get_pg_pool(), *net, trigger_time.date_naive())
+            .await
+            .map_err(|e| format!("Failed to generate {:?} weekly report: {}", net, e))?;
+        if generated {
+            log::info!("{:?}, weekly report generated", net);
         }
     }
+    Ok(())
 }
 
-static DAILY_COMMIT_TASK_HEARTBEAT: AtomicU64 = AtomicU64::new(0);
-static HOURLY_FRESH_TASK_HEARTBEAT: AtomicU64 = AtomicU64::new(0);
+/// Look up the stalest node locations again.
+async fn geo_refresh(_trigger_time: DateTime<Utc>) -> Result<(), String> {
+    let summary = fiber_dashbord_backend::geo_refresh::run(get_pg_pool())
+        .await
+        .map_err(|e| format!("Failed to refresh node locations: {}", e))?;
+    log::info!("Node locations refreshed: {:?}", summary);
+    Ok(())
+}
 
-async fn daily_commit() {
-    let mut clock_timer = ClockTimer::new_daily(0, 11, true);
-    let mut heartbeat_timer = tokio::time::interval(tokio::time::Duration::from_secs(60));
-    heartbeat_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
-    loop {
-        tokio::select! {
-            _ = heartbeat_timer.tick() => {
-                let timestamp = Utc::now().timestamp() as u64;
-                DAILY_COMMIT_TASK_HEARTBEAT.store(timestamp, Ordering::Release);
-            }
-            trigger_time = clock_timer.tick() => {
-                let pool = get_pg_pool();
-                daily_statistics(
-                    pool,
-                    Some(Utc::now() - chrono::Duration::days(20)),
-                    NETS.iter(),
-                )
+async fn hourly_fresh(trigger_time: DateTime<Utc>) -> Result<(), String> {
+    let pool = get_pg_pool();
+    for net in NETS.iter() {
+        for view in [net.mv_online_nodes(), net.mv_online_channels()] {
+            sqlx::query(&format!("REFRESH MATERIALIZED VIEW CONCURRENTLY {}", view))
+                .execute(pool)
                 .await
-                .unwrap();
-                log::info!("Daily statistics committed at {}", trigger_time);
-            }
+                .map_err(|e| format!("Failed to refresh {}: {}", view, e))?;
         }
+        events::emit(
+            pool,
+            Event::AggregatesRefreshed {
+                net: *net,
+                time: trigger_time,
+            },
+        )
+        .await;
     }
+    log::info!("Hourly continuous aggregates refreshed at {}", trigger_time);
+    Ok(())
 }
 
-async fn hourly_fresh() {
-    let mut clock_timer = ClockTimer::new_interval_with_minute(5, 30, true);
-    let mut heartbeat_timer = tokio::time::interval(tokio::time::Duration::from_secs(60));
-    heartbeat_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
-    loop {
-        tokio::select! {
-            _ = heartbeat_timer.tick() => {
-                let timestamp = Utc::now().timestamp() as u64;
-                HOURLY_FRESH_TASK_HEARTBEAT.store(timestamp, Ordering::Release);
-            }
-            trigger_time = clock_timer.tick() => {
-                let pool = get_pg_pool();
-                let nets = NETS.iter();
-                for net in nets {
-                    let refresh_nodes_sql = format!(
-                        "REFRESH MATERIALIZED VIEW CONCURRENTLY {}",
-                        net.mv_online_nodes()
-                    );
-                    let refresh_channels_sql = format!(
-                        "REFRESH MATERIALIZED VIEW CONCURRENTLY {}",
-                        net.mv_online_channels()
-                    );
-                    sqlx::query(&refresh_nodes_sql)
-                        .execute(pool)
-                        .await
-                        .expect("Failed to refresh continuous aggregate");
-                    sqlx::query(&refresh_channels_sql)
-                        .execute(pool)
-                        .await
-                        .expect("Failed to refresh continuous aggregate");
-                }
-                log::info!("Hourly continuous aggregates refreshed at {}", trigger_time);
-            }
-        }
+/// Rank the nodes of the online graph again.
+async fn node_rankings(_trigger_time: DateTime<Utc>) -> Result<(), String> {
+    for net in NETS.iter() {
+        let ranked = rankings::compute(get_pg_pool(), *net)
+            .await
+            .map_err(|e| format!("Failed to compute {:?} node rankings: {}", net, e))?;
+        log::info!("{:?}, {} nodes ranked", net, ranked);
     }
+    Ok(())
 }
@@ -0,0 +1,325 @@
+//! Optional message bus sink publishing normalized collector output to NATS or Kafka.
+//!
+//! Subjects (NATS) / topics (Kafka) are `<BUS_TOPIC_PREFIX>.<net>.<kind>` with kind one of
+//! `nodes`, `channels` and `channel_states`, one JSON message per node, channel or state change.
+//! Kafka is reached through a Kafka REST proxy so no native client is needed. Publishing runs
+//! on a background task behind a bounded queue and failures are only logged.
+
+use std::{
+    sync::{LazyLock, OnceLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{Client, Url};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::mpsc,
+};
+
+use crate::{
+    Network, config,
+    pg_write::{ChannelInfoDBSchema, DBState, NodeInfoDBSchema},
+};
+
+const QUEUE_BATCHES: usize = 64;
+const MAX_ATTEMPTS: u32 = 3;
+
+/// `nats://host:port`, publishing to NATS is disabled when unset.
+static BUS_NATS_URL: LazyLock<Option<Url>> = LazyLock::new(|| parse_url("BUS_NATS_URL"));
+
+/// Base url of a Kafka REST proxy (v2 api), publishing to Kafka is disabled when unset.
+static BUS_KAFKA_REST_URL: LazyLock<Option<Url>> =
+    LazyLock::new(|| parse_url("BUS_KAFKA_REST_URL"));
+
+static BUS_TOPIC_PREFIX: LazyLock<String> =
+    LazyLock::new(|| config::var("BUS_TOPIC_PREFIX").unwrap_or("fiber".to_string()));
+
+static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap()
+});
+
+fn parse_url(var: &str) -> Option<Url> {
+    let url = config::var(var)?;
+    Url::parse(&url)
+        .inspect_err(|e| log::warn!("Ignoring invalid {} {:?}: {}", var, url, e))
+        .ok()
+}
+
+pub fn enabled() -> bool {
+    BUS_NATS_URL.is_some() || BUS_KAFKA_REST_URL.is_some()
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct NodeMessage<'a> {
+    net: Network,
+    time: DateTime<Utc>,
+    pub(crate) node_id: String,
+    node_name: &'a str,
+    addresses: serde_json::Value,
+    announce_timestamp: DateTime<Utc>,
+    chain_hash: String,
+    auto_accept_min_ckb_funding_amount: String,
+    country_or_region: &'a str,
+    city: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ChannelMessage {
+    net: Network,
+    time: DateTime<Utc>,
+    pub(crate) channel_outpoint: String,
+    node1: String,
+    node2: String,
+    /// decimal string, may exceed the range of JSON numbers
+    capacity: String,
+    chain_hash: String,
+    udt_type_script: Option<i32>,
+    created_timestamp: DateTime<Utc>,
+    update_of_node1_enabled: Option<bool>,
+    update_of_node2_enabled: Option<bool>,
+}
+
+/// One on-chain state transition observed by the channel monitor.
+#[derive(Debug, Serialize)]
+pub struct ChannelStateChange {
+    pub net: Network,
+    pub channel_outpoint: String,
+    pub state: DBState,
+    pub last_commit_time: DateTime<Utc>,
+    pub last_block_number: u64,
+    pub last_tx_hash: Option<String>,
+}
+
+struct Batch {
+    net: Network,
+    kind: &'static str,
+    messages: Vec<String>,
+}
+
+fn hex_to_decimal(hex: &str) -> String {
+    u128::from_str_radix(hex, 16)
+        .map(|value| value.to_string())
+        .unwrap_or_default()
+}
+
+fn net_name(net: Network) -> &'static str {
+    match net {
+        Network::Mainnet => "mainnet",
+        Network::Testnet => "testnet",
+    }
+}
+
+fn topic(net: Network, kind: &str) -> String {
+    format!("{}.{}.{}", *BUS_TOPIC_PREFIX, net_name(net), kind)
+}
+
+fn sender() -> Option<&'static mpsc::Sender<Batch>> {
+    static SENDER: OnceLock<Option<mpsc::Sender<Batch>>> = OnceLock::new();
+    SENDER
+        .get_or_init(|| {
+            if !enabled() {
+                return None;
+            }
+            let (tx, rx) = mpsc::channel(QUEUE_BATCHES);
+            tokio::spawn(publisher(rx));
+            Some(tx)
+        })
+        .as_ref()
+}
+
+fn enqueue<T: Serialize>(net: Network, kind: &'static str, items: impl Iterator<Item = T>) {
+    let Some(sender) = sender() else {
+        return;
+    };
+    let messages = items
+        .map(|item| serde_json::to_string(&item).unwrap())
+        .collect::<Vec<_>>();
+    if messages.is_empty() {
+        return;
+    }
+    if let Err(e) = sender.try_send(Batch {
+        net,
+        kind,
+        messages,
+    }) {
+        log::warn!("Bus queue unavailable, dropping {:?} {}: {}", net, kind, e);
+    }
+}
+
+pub(crate) fn node_message<'a>(
+    net: Network,
+    time: &DateTime<Utc>,
+    node: &'a NodeInfoDBSchema,
+) -> NodeMessage<'a> {
+    NodeMessage {
+        net,
+        time: *time,
+        node_id: format!("0x{}", node.node_id),
+        node_name: &node.node_name,
+        addresses: serde_json::from_str(&node.addresses).unwrap_or_default(),
+        announce_timestamp: node.announce_timestamp,
+        chain_hash: format!("0x{}", node.chain_hash),
+        auto_accept_min_ckb_funding_amount: hex_to_decimal(
+            &node.auto_accept_min_ckb_funding_amount,
+        ),
+        country_or_region: &node.country_or_region,
+        city: &node.city,
+    }
+}
+
+pub(crate) fn channel_message(
+    net: Network,
+    time: &DateTime<Utc>,
+    channel: &ChannelInfoDBSchema,
+) -> ChannelMessage {
+    ChannelMessage {
+        net,
+        time: *time,
+        channel_outpoint: format!("0x{}", channel.channel_outpoint),
+        node1: format!("0x{}", channel.node1),
+        node2: format!("0x{}", channel.node2),
+        capacity: hex_to_decimal(&channel.capacity),
+        chain_hash: format!("0x{}", channel.chain_hash),
+        udt_type_script: channel.udt_type_script,
+        created_timestamp: channel.created_timestamp,
+        update_of_node1_enabled: channel.update_of_node1_enabled,
+        update_of_node2_enabled: channel.update_of_node2_enabled,
+    }
+}
+
+/// Publish the nodes and channels of a committed snapshot, no-op when no bus is configured.
+pub fn publish_snapshot(
+    net: Network,
+    time: &DateTime<Utc>,
+    nodes: &[NodeInfoDBSchema],
+    channels: &[ChannelInfoDBSchema],
+) {
+    if !enabled() {
+        return;
+    }
+    enqueue(
+        net,
+        "nodes",
+        nodes.iter().map(|node| node_message(net, time, node)),
+    );
+    enqueue(
+        net,
+        "channels",
+        channels
+            .iter()
+            .map(|channel| channel_message(net, time, channel)),
+    );
+}
+
+/// Publish channel state transitions, no-op when no bus is configured.
+pub fn publish_state_changes(net: Network, changes: impl Iterator<Item = ChannelStateChange>) {
+    if !enabled() {
+        return;
+    }
+    enqueue(net, "channel_states", changes);
+}
+
+/// Publish over a short-lived NATS connection speaking the text protocol. Batches are sent
+/// once per collection cycle, so there is no point keeping a connection alive between them.
+async fn publish_nats(url: &Url, subject: &str, messages: &[String]) -> std::io::Result<()> {
+    let host = url.host_str().unwrap_or("localhost");
+    let port = url.port().unwrap_or(4222);
+    let mut stream = BufReader::new(TcpStream::connect((host, port)).await?);
+    // the server greets with INFO before accepting CONNECT
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    let mut connect = serde_json::json!({ "verbose": false, "pedantic": false });
+    if !url.username().is_empty() {
+        connect["user"] = url.username().into();
+        connect["pass"] = url.password().unwrap_or_default().into();
+    }
+    let mut buf = format!("CONNECT {}\r\n", connect).into_bytes();
+    for message in messages {
+        buf.extend_from_slice(format!("PUB {} {}\r\n", subject, message.len()).as_bytes());
+        buf.extend_from_slice(message.as_bytes());
+        buf.extend_from_slice(b"\r\n");
+    }
+    // PONG confirms everything before it has been processed
+    buf.extend_from_slice(b"PING\r\n");
+    stream.get_mut().write_all(&buf).await?;
+    loop {
+        line.clear();
+        if stream.read_line(&mut line).await? == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        if line.starts_with("PONG") {
+            return Ok(());
+        }
+        if line.starts_with("-ERR") {
+            return Err(std::io::Error::other(line.trim().to_string()));
+        }
+    }
+}
+
+async fn publish_kafka(base: &Url, topic: &str, messages: &[String]) -> Result<(), String> {
+    let url = base
+        .join(&format!("topics/{}", topic))
+        .map_err(|e| e.to_string())?;
+    let records = messages
+        .iter()
+        .map(|message| {
+            let value = serde_json::from_str::<serde_json::Value>(message).unwrap();
+            serde_json::json!({ "value": value })
+        })
+        .collect::<Vec<_>>();
+    CLIENT
+        .post(url)
+        .header("content-type", "application/vnd.kafka.json.v2+json")
+        .json(&serde_json::json!({ "records": records }))
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+async fn publisher(mut rx: mpsc::Receiver<Batch>) {
+    while let Some(batch) = rx.recv().await {
+        let topic = topic(batch.net, batch.kind);
+        if let Some(url) = BUS_NATS_URL.as_ref() {
+            for attempt in 1..=MAX_ATTEMPTS {
+                match publish_nats(url, &topic, &batch.messages).await {
+                    Ok(()) => break,
+                    Err(e) if attempt < MAX_ATTEMPTS => {
+                        log::warn!("NATS publish to {} failed, retrying: {}", topic, e);
+                        tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+                    }
+                    Err(e) => log::error!(
+                        "NATS publish to {} failed, dropping {} messages: {}",
+                        topic,
+                        batch.messages.len(),
+                        e
+                    ),
+                }
+            }
+        }
+        if let Some(base) = BUS_KAFKA_REST_URL.as_ref() {
+            for attempt in 1..=MAX_ATTEMPTS {
+                match publish_kafka(base, &topic, &batch.messages).await {
+                    Ok(()) => break,
+                    Err(e) if attempt < MAX_ATTEMPTS => {
+                        log::warn!("Kafka publish to {} failed, retrying: {}", topic, e);
+                        tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+                    }
+                    Err(e) => log::error!(
+                        "Kafka publish to {} failed, dropping {} messages: {}",
+                        topic,
+                        batch.messages.len(),
+                        e
+                    ),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Check that the rpc endpoints of a network serve that network's chain before ingesting from
+//! them, so a misconfigured url cannot write testnet data into mainnet tables or vice versa.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use faster_hex::hex_string;
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::{
+    CKB_MAINNET_RPC, CKB_TESTNET_RPC, Network, RpcClient,
+    rpc_client::{CKB_MAINNET_RPC_BEARER_TOKEN, CKB_TESTNET_RPC_BEARER_TOKEN},
+    upstream::endpoint_name,
+};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ChainCheck {
+    /// Not verified yet, endpoints were unreachable so far.
+    Pending,
+    Verified,
+    /// Ingestion of the network is refused until the process is restarted with fixed urls.
+    Mismatch {
+        endpoint: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+static CHECKS: LazyLock<Mutex<HashMap<Network, ChainCheck>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub fn check(net: Network) -> ChainCheck {
+    CHECKS
+        .lock()
+        .unwrap()
+        .get(&net)
+        .cloned()
+        .unwrap_or(ChainCheck::Pending)
+}
+
+/// Whether data of `net` may be written, `false` only after a confirmed mismatch.
+pub fn ingest_allowed(net: Network) -> bool {
+    !matches!(check(net), ChainCheck::Mismatch { .. })
+}
+
+/// Result of every network checked so far, for `/health_check`.
+pub fn status() -> HashMap<Network, ChainCheck> {
+    CHECKS.lock().unwrap().clone()
+}
+
+/// Compare the chain hash reported by the Fiber node at `fiber_url` and the genesis hash of the
+/// network's CKB rpc against the expected chain hash. Checks that fail to reach an endpoint are
+/// retried on the next call, a confirmed result is kept. Returns [`ingest_allowed`].
+pub async fn verify(net: Network, rpc: &RpcClient, fiber_url: &Url) -> bool {
+    if !matches!(check(net), ChainCheck::Pending) {
+        return ingest_allowed(net);
+    }
+    let (ckb_url, ckb_token) = match net {
+        Network::Mainnet => (
+            CKB_MAINNET_RPC.clone(),
+            CKB_MAINNET_RPC_BEARER_TOKEN.clone(),
+        ),
+        Network::Testnet => (
+            CKB_TESTNET_RPC.clone(),
+            CKB_TESTNET_RPC_BEARER_TOKEN.clone(),
+        ),
+    };
+    let mut ckb_rpc = rpc.clone();
+    ckb_rpc.set_bearer_token(ckb_token);
+
+    let fiber = rpc
+        .get_fiber_chain_hash(fiber_url.clone())
+        .await
+        .map(|hash| hex_string(hash.as_bytes()));
+    let ckb = ckb_rpc
+        .get_header_by_number(ckb_url.clone(), 0u64.into())
+        .await
+        .map(|header| hex_string(header.hash.as_bytes()));
+
+    let expected = net.chain_hash();
+    let mut verified = true;
+    for (url, hash) in [(fiber_url, fiber), (&ckb_url, ckb)] {
+        match hash {
+            Ok(actual) if actual == expected => {}
+            Ok(actual) => {
+                let endpoint = endpoint_name(url);
+                log::error!(
+                    "{} serves chain 0x{} but is configured for {:?} (0x{}), refusing to ingest {:?} data",
+                    endpoint,
+                    actual,
+                    net,
+                    expected,
+                    net
+                );
+                CHECKS.lock().unwrap().insert(
+                    net,
+                    ChainCheck::Mismatch {
+                        endpoint,
+                        expected: format!("0x{}", expected),
+                        actual: format!("0x{}", actual),
+                    },
+                );
+                return false;
+            }
+            Err(e) => {
+                log::warn!(
+                    "Failed to read the chain hash of {}, retrying before the next collection: {}",
+                    endpoint_name(url),
+                    e
+                );
+                verified = false;
+            }
+        }
+    }
+    if verified {
+        log::info!("Rpc endpoints of {:?} verified against its chain hash", net);
+        CHECKS.lock().unwrap().insert(net, ChainCheck::Verified);
+    }
+    true
+}
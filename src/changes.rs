@@ -0,0 +1,228 @@
+//! Incremental change log of nodes, channels and channel states for third party mirrors.
+//!
+//! Every committed snapshot records the nodes and channels whose content differs from the
+//! last recorded version (the snapshot time itself is ignored), and every on-chain state
+//! transition is recorded as it is written. Rows get a monotonic id in `changes`, which
+//! `/changes` hands out as an opaque cursor. Rows older than `CHANGES_RETENTION_DAYS` are
+//! pruned by the daily job, a cursor from before the last prune is answered with 410 so the
+//! mirror knows to resync from the hourly lists.
+
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{
+    Network,
+    bus::{self, ChannelStateChange},
+    config,
+    pg_write::{ChannelInfoDBSchema, NodeInfoDBSchema},
+};
+
+static CHANGES_RETENTION_DAYS: LazyLock<i64> =
+    LazyLock::new(|| config::parse_var("CHANGES_RETENTION_DAYS").unwrap_or(30));
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Change {
+    pub cursor: String,
+    /// node / channel / channel_state
+    pub kind: String,
+    /// node id or channel outpoint
+    pub key: String,
+    pub recorded_at: DateTime<Utc>,
+    /// Same message as published on the bus for this kind.
+    pub change: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangesPage {
+    pub changes: Vec<Change>,
+    /// Cursor to pass as `since_cursor` next, unchanged when nothing new was recorded.
+    pub next_cursor: String,
+    pub has_more: bool,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    InvalidCursor,
+    /// Changes after the cursor have been pruned.
+    Expired,
+    Db(sqlx::Error),
+}
+
+impl From<sqlx::Error> for LoadError {
+    fn from(e: sqlx::Error) -> Self {
+        LoadError::Db(e)
+    }
+}
+
+fn parse_cursor(cursor: &str) -> Option<i64> {
+    cursor.parse().ok().filter(|id| *id >= 0)
+}
+
+/// Record the `(key, message)` pairs of `kind` whose content changed since last recorded.
+async fn record(
+    pool: &Pool<Postgres>,
+    net: Network,
+    kind: &str,
+    items: Vec<(String, serde_json::Value)>,
+) -> Result<u64, sqlx::Error> {
+    if items.is_empty() {
+        return Ok(0);
+    }
+    let (keys, messages): (Vec<_>, Vec<_>) = items.into_iter().unzip();
+    let result = sqlx::query(
+        "WITH incoming AS (
+            SELECT DISTINCT ON (key) key, change, md5((change - 'time')::text) AS digest
+            FROM unnest($3::text[], $4::jsonb[]) WITH ORDINALITY AS i(key, change, n)
+            ORDER BY key, n DESC
+        ),
+        changed AS (
+            INSERT INTO change_heads (net, kind, key, digest)
+            SELECT $1, $2, key, digest FROM incoming
+            ON CONFLICT (net, kind, key) DO UPDATE SET digest = excluded.digest
+            WHERE change_heads.digest <> excluded.digest
+            RETURNING key
+        )
+        INSERT INTO changes (net, kind, key, change)
+        SELECT $1, $2, i.key, i.change FROM incoming i JOIN changed c ON c.key = i.key
+        ORDER BY i.key",
+    )
+    .bind(net.name())
+    .bind(kind)
+    .bind(keys)
+    .bind(messages)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Record the nodes and channels of a committed snapshot page, failures are only logged.
+pub async fn record_snapshot(
+    pool: &Pool<Postgres>,
+    net: Network,
+    time: &DateTime<Utc>,
+    nodes: &[NodeInfoDBSchema],
+    channels: &[ChannelInfoDBSchema],
+) {
+    let nodes = nodes
+        .iter()
+        .map(|node| {
+            let message = bus::node_message(net, time, node);
+            (
+                message.node_id.clone(),
+                serde_json::to_value(message).unwrap(),
+            )
+        })
+        .collect();
+    let channels = channels
+        .iter()
+        .map(|channel| {
+            let message = bus::channel_message(net, time, channel);
+            (
+                message.channel_outpoint.clone(),
+                serde_json::to_value(message).unwrap(),
+            )
+        })
+        .collect();
+    for (kind, items) in [("node", nodes), ("channel", channels)] {
+        if let Err(e) = record(pool, net, kind, items).await {
+            log::warn!("Failed to record {:?} {} changes: {}", net, kind, e);
+        }
+    }
+}
+
+/// Record on-chain channel state transitions, failures are only logged.
+pub async fn record_state_changes(
+    pool: &Pool<Postgres>,
+    net: Network,
+    changes: &[ChannelStateChange],
+) {
+    let items = changes
+        .iter()
+        .map(|change| {
+            (
+                change.channel_outpoint.clone(),
+                serde_json::to_value(change).unwrap(),
+            )
+        })
+        .collect();
+    if let Err(e) = record(pool, net, "channel_state", items).await {
+        log::warn!("Failed to record {:?} channel state changes: {}", net, e);
+    }
+}
+
+/// Drop changes older than the retention, remembering the last dropped id per network.
+pub async fn prune(pool: &Pool<Postgres>) -> Result<u64, sqlx::Error> {
+    let before = Utc::now() - chrono::Duration::days(*CHANGES_RETENTION_DAYS);
+    let pruned: i64 = sqlx::query(
+        "WITH deleted AS (
+            DELETE FROM changes WHERE recorded_at < $1 RETURNING net, id
+        ),
+        watermark AS (
+            INSERT INTO change_prunes (net, pruned_through)
+            SELECT net, max(id) FROM deleted GROUP BY net
+            ON CONFLICT (net) DO UPDATE SET
+                pruned_through = GREATEST(change_prunes.pruned_through, excluded.pruned_through)
+        )
+        SELECT count(*) FROM deleted",
+    )
+    .bind(before)
+    .fetch_one(pool)
+    .await?
+    .get(0);
+    Ok(pruned as u64)
+}
+
+/// Up to `limit` changes of `net` after `since_cursor`, oldest first, from the start of the
+/// retained log without a cursor.
+pub async fn load(
+    pool: &Pool<Postgres>,
+    net: Network,
+    since_cursor: Option<&str>,
+    limit: i64,
+) -> Result<ChangesPage, LoadError> {
+    let since = match since_cursor {
+        Some(cursor) => parse_cursor(cursor).ok_or(LoadError::InvalidCursor)?,
+        None => 0,
+    };
+    let pruned_through: Option<i64> =
+        sqlx::query("SELECT pruned_through FROM change_prunes WHERE net = $1")
+            .bind(net.name())
+            .fetch_optional(pool)
+            .await?
+            .map(|row| row.get("pruned_through"));
+    if since_cursor.is_some() && pruned_through.is_some_and(|pruned| since < pruned) {
+        return Err(LoadError::Expired);
+    }
+    // ids are taken before commit, leave a grace period for concurrent writers to commit
+    // lower ids so a cursor never skips them
+    let mut rows = sqlx::query(
+        "SELECT id, kind, key, recorded_at, change FROM changes
+        WHERE net = $1 AND id > $2 AND recorded_at < now() - interval '5 seconds'
+        ORDER BY id LIMIT $3",
+    )
+    .bind(net.name())
+    .bind(since)
+    .bind(limit + 1)
+    .fetch_all(pool)
+    .await?;
+    let has_more = rows.len() as i64 > limit;
+    rows.truncate(limit as usize);
+    let next = rows.last().map_or(since, |row| row.get("id"));
+    Ok(ChangesPage {
+        changes: rows
+            .into_iter()
+            .map(|row| Change {
+                cursor: row.get::<i64, _>("id").to_string(),
+                kind: row.get("kind"),
+                key: row.get("key"),
+                recorded_at: row.get("recorded_at"),
+                change: row.get("change"),
+            })
+            .collect(),
+        next_cursor: next.to_string(),
+        has_more,
+    })
+}
@@ -0,0 +1,306 @@
+//! Optional mirror of node/channel snapshots to ClickHouse for ad-hoc analytics.
+//!
+//! Rows are handed to a background writer over a bounded queue, so a slow or unreachable
+//! ClickHouse never holds up or fails the Postgres write path. Rows that cannot be queued
+//! or written after retries are dropped and logged.
+
+use std::{
+    sync::{LazyLock, OnceLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{Client, Url};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::{
+    Network, config,
+    pg_write::{ChannelInfoDBSchema, NodeInfoDBSchema},
+};
+
+const MAX_ATTEMPTS: u32 = 3;
+/// Snapshots waiting for the writer, further snapshots are dropped while it is full.
+const QUEUE_SNAPSHOTS: usize = 16;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+/// HTTP interface of the ClickHouse server, mirroring is disabled when unset.
+static CLICKHOUSE_URL: LazyLock<Option<Url>> = LazyLock::new(|| {
+    let url = config::var("CLICKHOUSE_URL")?;
+    Url::parse(&url)
+        .inspect_err(|e| log::warn!("Ignoring invalid CLICKHOUSE_URL {:?}: {}", url, e))
+        .ok()
+});
+
+static CLICKHOUSE_DATABASE: LazyLock<String> =
+    LazyLock::new(|| config::var("CLICKHOUSE_DATABASE").unwrap_or("fiber_dashboard".to_string()));
+
+static CLICKHOUSE_USER: LazyLock<Option<String>> = LazyLock::new(|| config::var("CLICKHOUSE_USER"));
+
+static CLICKHOUSE_PASSWORD: LazyLock<Option<String>> =
+    LazyLock::new(|| config::var("CLICKHOUSE_PASSWORD"));
+
+/// Rows buffered per table before an insert is sent, independent of the flush interval.
+static CLICKHOUSE_BATCH_ROWS: LazyLock<usize> =
+    LazyLock::new(|| config::parse_var("CLICKHOUSE_BATCH_ROWS").unwrap_or(10_000));
+
+static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .unwrap()
+});
+
+pub fn enabled() -> bool {
+    CLICKHOUSE_URL.is_some()
+}
+
+#[derive(Debug, Serialize)]
+struct NodeRow {
+    net: Network,
+    time: String,
+    node_id: String,
+    node_name: String,
+    addresses: String,
+    announce_timestamp: String,
+    chain_hash: String,
+    auto_accept_min_ckb_funding_amount: String,
+    country_or_region: String,
+    city: String,
+    region: String,
+    loc: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ChannelRow {
+    net: Network,
+    time: String,
+    channel_outpoint: String,
+    node1: String,
+    node2: String,
+    /// decimal string, JSONEachRow reads UInt128 from strings
+    capacity: String,
+    chain_hash: String,
+    udt_type_script: Option<i32>,
+    created_timestamp: String,
+    update_of_node1_enabled: Option<bool>,
+    update_of_node1_fee_rate: Option<String>,
+    update_of_node2_enabled: Option<bool>,
+    update_of_node2_fee_rate: Option<String>,
+}
+
+struct Snapshot {
+    nodes: Vec<NodeRow>,
+    channels: Vec<ChannelRow>,
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.format(TIME_FORMAT).to_string()
+}
+
+fn hex_to_decimal(hex: &str) -> String {
+    u128::from_str_radix(hex, 16)
+        .map(|value| value.to_string())
+        .unwrap_or_default()
+}
+
+fn sender() -> Option<&'static mpsc::Sender<Snapshot>> {
+    static SENDER: OnceLock<Option<mpsc::Sender<Snapshot>>> = OnceLock::new();
+    SENDER
+        .get_or_init(|| {
+            let url = CLICKHOUSE_URL.clone()?;
+            let (tx, rx) = mpsc::channel(QUEUE_SNAPSHOTS);
+            tokio::spawn(writer(url, rx));
+            Some(tx)
+        })
+        .as_ref()
+}
+
+/// Queue a committed snapshot for the ClickHouse writer, no-op when mirroring is disabled.
+pub fn mirror(
+    net: Network,
+    time: &DateTime<Utc>,
+    nodes: &[NodeInfoDBSchema],
+    channels: &[ChannelInfoDBSchema],
+) {
+    let Some(sender) = sender() else {
+        return;
+    };
+    let time = format_time(time);
+    let snapshot = Snapshot {
+        nodes: nodes
+            .iter()
+            .map(|node| NodeRow {
+                net,
+                time: time.clone(),
+                node_id: node.node_id.clone(),
+                node_name: node.node_name.clone(),
+                addresses: node.addresses.clone(),
+                announce_timestamp: format_time(&node.announce_timestamp),
+                chain_hash: node.chain_hash.clone(),
+                auto_accept_min_ckb_funding_amount: hex_to_decimal(
+                    &node.auto_accept_min_ckb_funding_amount,
+                ),
+                country_or_region: node.country_or_region.clone(),
+                city: node.city.clone(),
+                region: node.region.clone(),
+                loc: node.loc.clone(),
+            })
+            .collect(),
+        channels: channels
+            .iter()
+            .map(|channel| ChannelRow {
+                net,
+                time: time.clone(),
+                channel_outpoint: channel.channel_outpoint.clone(),
+                node1: channel.node1.clone(),
+                node2: channel.node2.clone(),
+                capacity: hex_to_decimal(&channel.capacity),
+                chain_hash: channel.chain_hash.clone(),
+                udt_type_script: channel.udt_type_script,
+                created_timestamp: format_time(&channel.created_timestamp),
+                update_of_node1_enabled: channel.update_of_node1_enabled,
+                update_of_node1_fee_rate: channel
+                    .update_of_node1_fee_rate
+                    .as_deref()
+                    .map(hex_to_decimal),
+                update_of_node2_enabled: channel.update_of_node2_enabled,
+                update_of_node2_fee_rate: channel
+                    .update_of_node2_fee_rate
+                    .as_deref()
+                    .map(hex_to_decimal),
+            })
+            .collect(),
+    };
+    if let Err(e) = sender.try_send(snapshot) {
+        log::warn!(
+            "ClickHouse mirror queue unavailable, dropping {:?} snapshot: {}",
+            net,
+            e
+        );
+    }
+}
+
+fn create_tables_sql(database: &str) -> [String; 3] {
+    [
+        format!("CREATE DATABASE IF NOT EXISTS {database}"),
+        format!(
+            "CREATE TABLE IF NOT EXISTS {database}.node_snapshots (
+                net LowCardinality(String),
+                time DateTime64(3, 'UTC'),
+                node_id String,
+                node_name String,
+                addresses String,
+                announce_timestamp DateTime64(3, 'UTC'),
+                chain_hash String,
+                auto_accept_min_ckb_funding_amount UInt64,
+                country_or_region String,
+                city String,
+                region String,
+                loc String
+            ) ENGINE = MergeTree PARTITION BY toYYYYMM(time) ORDER BY (net, node_id, time)"
+        ),
+        format!(
+            "CREATE TABLE IF NOT EXISTS {database}.channel_snapshots (
+                net LowCardinality(String),
+                time DateTime64(3, 'UTC'),
+                channel_outpoint String,
+                node1 String,
+                node2 String,
+                capacity UInt128,
+                chain_hash String,
+                udt_type_script Nullable(Int32),
+                created_timestamp DateTime64(3, 'UTC'),
+                update_of_node1_enabled Nullable(Bool),
+                update_of_node1_fee_rate Nullable(UInt64),
+                update_of_node2_enabled Nullable(Bool),
+                update_of_node2_fee_rate Nullable(UInt64)
+            ) ENGINE = MergeTree PARTITION BY toYYYYMM(time) ORDER BY (net, channel_outpoint, time)"
+        ),
+    ]
+}
+
+async fn execute(url: &Url, query: &str, body: String) -> Result<(), reqwest::Error> {
+    let mut request = CLIENT
+        .post(url.clone())
+        .query(&[("query", query)])
+        .body(body);
+    if let Some(user) = CLICKHOUSE_USER.as_ref() {
+        request = request.basic_auth(user, CLICKHOUSE_PASSWORD.as_ref());
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// Insert `rows` as JSONEachRow, retrying with backoff. The rows are dropped on failure.
+async fn insert<T: Serialize>(url: &Url, table: &str, rows: &mut Vec<T>) {
+    if rows.is_empty() {
+        return;
+    }
+    let body = rows
+        .iter()
+        .map(|row| serde_json::to_string(row).unwrap())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let query = format!(
+        "INSERT INTO {}.{} FORMAT JSONEachRow",
+        *CLICKHOUSE_DATABASE, table
+    );
+    for attempt in 1..=MAX_ATTEMPTS {
+        match execute(url, &query, body.clone()).await {
+            Ok(()) => {
+                log::debug!("Mirrored {} rows to ClickHouse {}", rows.len(), table);
+                break;
+            }
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                log::warn!("ClickHouse insert into {} failed, retrying: {}", table, e);
+                tokio::time::sleep(Duration::from_secs(2u64.pow(attempt))).await;
+            }
+            Err(e) => {
+                log::error!(
+                    "ClickHouse insert into {} failed, dropping {} rows: {}",
+                    table,
+                    rows.len(),
+                    e
+                );
+            }
+        }
+    }
+    rows.clear();
+}
+
+async fn writer(url: Url, mut rx: mpsc::Receiver<Snapshot>) {
+    for sql in create_tables_sql(&CLICKHOUSE_DATABASE) {
+        if let Err(e) = execute(&url, &sql, String::new()).await {
+            log::error!("Failed to create ClickHouse tables: {}", e);
+        }
+    }
+    let mut nodes = Vec::new();
+    let mut channels = Vec::new();
+    let mut timer = tokio::time::interval(FLUSH_INTERVAL);
+    timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            snapshot = rx.recv() => {
+                let Some(snapshot) = snapshot else {
+                    break;
+                };
+                nodes.extend(snapshot.nodes);
+                channels.extend(snapshot.channels);
+                if nodes.len() >= *CLICKHOUSE_BATCH_ROWS {
+                    insert(&url, "node_snapshots", &mut nodes).await;
+                }
+                if channels.len() >= *CLICKHOUSE_BATCH_ROWS {
+                    insert(&url, "channel_snapshots", &mut channels).await;
+                }
+            }
+            _ = timer.tick() => {
+                insert(&url, "node_snapshots", &mut nodes).await;
+                insert(&url, "channel_snapshots", &mut channels).await;
+            }
+        }
+    }
+    insert(&url, "node_snapshots", &mut nodes).await;
+    insert(&url, "channel_snapshots", &mut channels).await;
+}
@@ -0,0 +1,283 @@
+//! Typed client of the public http api, enabled with the `client` feature.
+//!
+//! Responses are decoded into the same models the server serializes, so bots and analytics
+//! jobs do not have to duplicate them. Endpoints without a dedicated method can be called
+//! through [`DashboardClient::get`] with any deserializable type.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), fiber_dashbord_backend::client::ClientError> {
+//! use fiber_dashbord_backend::{Network, client::DashboardClient};
+//!
+//! let client = DashboardClient::new("https://dashboard.example.com".parse().unwrap())
+//!     .with_network(Network::Testnet);
+//! let page = client.nodes_hourly(0).await?;
+//! println!("{} nodes online", page.total_count);
+//! # Ok(())
+//! # }
+//! ```
+
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::{Client, StatusCode, Url};
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::Network;
+pub use crate::{
+    changes::{Change, ChangesPage},
+    cohorts::Cohort,
+    http_server::{
+        ChannelPage, CohortReport, NodePage, NodeRankingReport, SnapshotIndex, SurvivalReport,
+    },
+    pg_read::{ChannelInfo, HourlyNodeInfo},
+    rankings::{NodeRanking, RankingMetric},
+    survival::{CohortSurvival, SurvivalPoint},
+};
+
+#[derive(Debug)]
+pub enum ClientError {
+    Http(reqwest::Error),
+    /// The server answered with a non success status, `body` is its message.
+    Status {
+        status: StatusCode,
+        body: String,
+    },
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "http error: {}", e),
+            ClientError::Status { status, body } => write!(f, "{}: {}", status, body),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+/// `graph_snapshot` and `graph_backbone` responses.
+#[derive(Debug, Deserialize)]
+pub struct Graph {
+    pub refreshed_at: DateTime<Utc>,
+    pub nodes: Vec<HourlyNodeInfo>,
+    pub channels: Vec<ChannelInfo>,
+}
+
+#[derive(Deserialize)]
+struct NodeInfoResponse {
+    node_info: Option<HourlyNodeInfo>,
+}
+
+#[derive(Deserialize)]
+struct ChannelInfoResponse {
+    channel_info: Option<ChannelInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DashboardClient {
+    base: Url,
+    http: Client,
+    net: Network,
+    api_key: Option<String>,
+}
+
+impl DashboardClient {
+    /// Client of the api served at `base`, for mainnet unless [`Self::with_network`] is used.
+    /// A `base` with a path has to end with `/`, e.g. `https://example.com/api/`.
+    pub fn new(base: Url) -> Self {
+        DashboardClient {
+            base,
+            http: Client::new(),
+            net: Network::Mainnet,
+            api_key: None,
+        }
+    }
+
+    pub fn with_network(mut self, net: Network) -> Self {
+        self.net = net;
+        self
+    }
+
+    /// Token sent as `Authorization: Bearer`, needed when the server sets `API_KEYS_REQUIRED`.
+    pub fn with_api_key(mut self, token: impl Into<String>) -> Self {
+        self.api_key = Some(token.into());
+        self
+    }
+
+    pub fn with_http_client(mut self, http: Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    /// GET `path` with `query` and the client's network, decoded as `T`.
+    pub async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, ClientError> {
+        let url = self
+            .base
+            .join(path)
+            .expect("api paths are valid relative urls");
+        let mut request = self
+            .http
+            .get(url)
+            .query(&[("net", self.net.name())])
+            .query(query);
+        if let Some(token) = &self.api_key {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ClientError::Status { status, body });
+        }
+        Ok(response.json().await?)
+    }
+
+    pub async fn nodes_hourly(&self, page: usize) -> Result<NodePage, ClientError> {
+        self.get("nodes_hourly", &[("page", page.to_string())])
+            .await
+    }
+
+    pub async fn channels_hourly(&self, page: usize) -> Result<ChannelPage, ClientError> {
+        self.get("channels_hourly", &[("page", page.to_string())])
+            .await
+    }
+
+    pub async fn nodes_nearly_monthly(
+        &self,
+        page: usize,
+        start: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+    ) -> Result<NodePage, ClientError> {
+        self.get("nodes_nearly_monthly", &date_range(page, start, end))
+            .await
+    }
+
+    pub async fn channels_nearly_monthly(
+        &self,
+        page: usize,
+        start: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+    ) -> Result<ChannelPage, ClientError> {
+        self.get("channels_nearly_monthly", &date_range(page, start, end))
+            .await
+    }
+
+    pub async fn nodes_by_region(
+        &self,
+        region: &str,
+        page: usize,
+    ) -> Result<NodePage, ClientError> {
+        let query = [("region", region.to_string()), ("page", page.to_string())];
+        self.get("nodes_by_region", &query).await
+    }
+
+    pub async fn nodes_fuzzy_by_name(
+        &self,
+        node_name: &str,
+        page: usize,
+    ) -> Result<NodePage, ClientError> {
+        let query = [
+            ("node_name", node_name.to_string()),
+            ("page", page.to_string()),
+        ];
+        self.get("nodes_fuzzy_by_name", &query).await
+    }
+
+    /// `node_id` as 0x prefixed hex, `None` when the node is unknown.
+    pub async fn node_info(&self, node_id: &str) -> Result<Option<HourlyNodeInfo>, ClientError> {
+        let response: NodeInfoResponse = self
+            .get("node_info", &[("node_id", node_id.to_string())])
+            .await?;
+        Ok(response.node_info)
+    }
+
+    /// `channel_outpoint` as 0x prefixed hex, `None` when the channel is unknown.
+    pub async fn channel_info(
+        &self,
+        channel_outpoint: &str,
+    ) -> Result<Option<ChannelInfo>, ClientError> {
+        let query = [("channel_outpoint", channel_outpoint.to_string())];
+        let response: ChannelInfoResponse = self.get("channel_info", &query).await?;
+        Ok(response.channel_info)
+    }
+
+    pub async fn graph_snapshot(&self) -> Result<Graph, ClientError> {
+        self.get("graph_snapshot", &[]).await
+    }
+
+    pub async fn graph_backbone(&self, top_k: usize) -> Result<Graph, ClientError> {
+        self.get("graph_backbone", &[("top_k", top_k.to_string())])
+            .await
+    }
+
+    pub async fn node_rankings(
+        &self,
+        metric: RankingMetric,
+        limit: i64,
+    ) -> Result<NodeRankingReport, ClientError> {
+        let metric = serde_json::to_value(metric).unwrap();
+        let query = [
+            ("metric", metric.as_str().unwrap_or_default().to_string()),
+            ("limit", limit.to_string()),
+        ];
+        self.get("node_rankings", &query).await
+    }
+
+    /// Changes after `since_cursor`, from the start of the retained log without one.
+    pub async fn changes(
+        &self,
+        since_cursor: Option<&str>,
+        limit: i64,
+    ) -> Result<ChangesPage, ClientError> {
+        let mut query = vec![("limit", limit.to_string())];
+        if let Some(cursor) = since_cursor {
+            query.push(("since_cursor", cursor.to_string()));
+        }
+        self.get("changes", &query).await
+    }
+
+    pub async fn channel_survival(&self) -> Result<SurvivalReport, ClientError> {
+        self.get("channel_survival", &[]).await
+    }
+
+    pub async fn cohorts(&self) -> Result<CohortReport, ClientError> {
+        self.get("cohorts", &[]).await
+    }
+
+    /// Finalized hours with a snapshot over `range`, e.g. `1M`.
+    pub async fn snapshot_hours(&self, range: &str) -> Result<SnapshotIndex, ClientError> {
+        let path = format!("snapshots/{}", self.net.name());
+        self.get(&path, &[("range", range.to_string())]).await
+    }
+
+    /// Nodes of a finalized `hour` from [`Self::snapshot_hours`].
+    pub async fn snapshot_nodes(&self, hour: &str) -> Result<Vec<HourlyNodeInfo>, ClientError> {
+        let path = format!("snapshots/{}/{}/nodes.json", self.net.name(), hour);
+        self.get(&path, &[]).await
+    }
+
+    /// Channels of a finalized `hour` from [`Self::snapshot_hours`].
+    pub async fn snapshot_channels(&self, hour: &str) -> Result<Vec<ChannelInfo>, ClientError> {
+        let path = format!("snapshots/{}/{}/channels.json", self.net.name(), hour);
+        self.get(&path, &[]).await
+    }
+}
+
+fn date_range(
+    page: usize,
+    start: Option<NaiveDate>,
+    end: Option<NaiveDate>,
+) -> Vec<(&'static str, String)> {
+    let mut query = vec![("page", page.to_string())];
+    query.extend(start.map(|start| ("start", start.to_string())));
+    query.extend(end.map(|end| ("end", end.to_string())));
+    query
+}
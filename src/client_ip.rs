@@ -0,0 +1,227 @@
+//! Client address of api requests, behind load balancers included.
+//!
+//! The peer address is only replaced by a forwarded one when the peer is listed in
+//! `TRUSTED_PROXIES`, a comma separated list of addresses or CIDR ranges. `Forwarded` is
+//! preferred over `X-Forwarded-For`, and the chain is walked from the nearest hop: the first
+//! address outside the allowlist is the client, so entries a client prepends are ignored.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::LazyLock,
+};
+
+use salvo::{Depot, Request, handler, http::HeaderMap};
+
+use crate::config;
+
+pub const CLIENT_IP: &str = "client_ip";
+
+static TRUSTED_PROXIES: LazyLock<Vec<Cidr>> = LazyLock::new(|| {
+    config::var("TRUSTED_PROXIES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            entry
+                .parse()
+                .inspect_err(|_| log::warn!("Ignoring invalid TRUSTED_PROXIES entry {}", entry))
+                .ok()
+        })
+        .collect()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = ();
+
+    /// `10.0.0.0/8`, `fd00::/8`, or a single address.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.parse::<u8>().map_err(|_| ())?)),
+            None => (s, None),
+        };
+        let network = addr.parse::<IpAddr>().map_err(|_| ())?.to_canonical();
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        if prefix > bits {
+            return Err(());
+        }
+        Ok(Cidr { network, prefix })
+    }
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// `1.2.3.4`, `1.2.3.4:80`, `2001:db8::1` or `[2001:db8::1]:80`, `None` for obfuscated or
+/// `unknown` nodes.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+/// Forwarded client chain, farthest hop first, empty without forwarding headers.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let forwarded = values("forwarded");
+    if !forwarded.is_empty() {
+        return forwarded
+            .iter()
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (name, value) = pair.split_once('=')?;
+                    name.trim()
+                        .eq_ignore_ascii_case("for")
+                        .then(|| parse_node(value))
+                })
+            })
+            .collect();
+    }
+    values("x-forwarded-for")
+        .iter()
+        .map(|node| parse_node(node))
+        .collect()
+}
+
+/// The client behind `peer`, trusting forwarded hops only while they are in `trusted`.
+pub fn resolve(peer: IpAddr, chain: &[Option<IpAddr>], trusted: &[Cidr]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|cidr| cidr.contains(ip));
+    let mut client = peer.to_canonical();
+    if !is_trusted(client) {
+        return client;
+    }
+    for hop in chain.iter().rev() {
+        // an unknown hop ends the chain, the last proxy is the best we know
+        let Some(hop) = hop else {
+            break;
+        };
+        client = hop.to_canonical();
+        if !is_trusted(client) {
+            break;
+        }
+    }
+    client
+}
+
+/// Put the client address in the depot for rate limiting and logging.
+#[handler]
+pub async fn resolve_client_ip(req: &mut Request, depot: &mut Depot) {
+    let remote = req.remote_addr();
+    let Some(peer) = remote
+        .as_ipv4()
+        .map(|addr| IpAddr::V4(*addr.ip()))
+        .or_else(|| remote.as_ipv6().map(|addr| IpAddr::V6(*addr.ip())))
+    else {
+        return;
+    };
+    let client = if TRUSTED_PROXIES.is_empty() {
+        peer
+    } else {
+        resolve(peer, &forwarded_chain(req.headers()), &TRUSTED_PROXIES)
+    };
+    depot.insert(CLIENT_IP, client);
+}
+
+/// Address resolved by [`resolve_client_ip`], `None` for unix sockets.
+pub fn client_ip(depot: &Depot) -> Option<IpAddr> {
+    depot.get::<IpAddr>(CLIENT_IP).ok().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use salvo::http::{HeaderMap, HeaderValue};
+
+    use super::{Cidr, forwarded_chain, resolve};
+
+    #[test]
+    fn cidr_matches_prefix() {
+        let cidr: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(cidr.contains("10.1.200.3".parse().unwrap()));
+        assert!(cidr.contains("::ffff:10.1.0.1".parse().unwrap()));
+        assert!(!cidr.contains("10.2.0.1".parse().unwrap()));
+        assert!(
+            "0.0.0.0/0"
+                .parse::<Cidr>()
+                .unwrap()
+                .contains("8.8.8.8".parse().unwrap())
+        );
+        assert!(
+            "fd00::/8"
+                .parse::<Cidr>()
+                .unwrap()
+                .contains("fd12::1".parse().unwrap())
+        );
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn forwarded_header_wins_over_x_forwarded_for() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("9.9.9.9"));
+        headers.insert(
+            "forwarded",
+            HeaderValue::from_static(
+                "for=\"[2001:db8::1]:4711\";proto=https, for=unknown, For=1.2.3.4:80",
+            ),
+        );
+        let expected: Vec<Option<IpAddr>> = vec![
+            Some("2001:db8::1".parse().unwrap()),
+            None,
+            Some("1.2.3.4".parse().unwrap()),
+        ];
+        assert_eq!(forwarded_chain(&headers), expected);
+    }
+
+    #[test]
+    fn only_trusted_hops_are_skipped() {
+        let trusted: [Cidr; 1] = ["10.0.0.0/8".parse().unwrap()];
+        let chain: [Option<IpAddr>; 3] = [
+            Some("6.6.6.6".parse().unwrap()),
+            Some("1.2.3.4".parse().unwrap()),
+            Some("10.0.0.7".parse().unwrap()),
+        ];
+        let proxy = "10.0.0.1".parse().unwrap();
+        assert_eq!(
+            resolve(proxy, &chain, &trusted),
+            "1.2.3.4".parse::<IpAddr>().unwrap()
+        );
+        let direct = "5.5.5.5".parse().unwrap();
+        assert_eq!(resolve(direct, &chain, &trusted), direct);
+        assert_eq!(resolve(proxy, &[None], &trusted), proxy);
+    }
+}
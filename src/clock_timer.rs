@@ -8,6 +8,8 @@ pub struct ClockTimer {
     run_immediately: bool,
     sleep: Option<Pin<Box<Sleep>>>,
     next_trigger: Option<DateTime<Utc>>,
+    /// Task the tick lags are recorded for in [`crate::metrics`].
+    task: Option<&'static str>,
 }
 
 enum ScheduleType {
@@ -23,6 +25,7 @@ impl ClockTimer {
             run_immediately,
             sleep: None,
             next_trigger: None,
+            task: None,
         }
     }
 
@@ -32,6 +35,7 @@ impl ClockTimer {
             run_immediately,
             sleep: None,
             next_trigger: None,
+            task: None,
         }
     }
 
@@ -42,10 +46,17 @@ impl ClockTimer {
             run_immediately,
             sleep: None,
             next_trigger: None,
+            task: None,
         }
     }
 
-    fn next_trigger_time(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+    /// Record how late each tick comes as a lag of `task`.
+    pub fn named(mut self, task: &'static str) -> Self {
+        self.task = Some(task);
+        self
+    }
+
+    pub fn next_trigger_time(&self, now: DateTime<Utc>) -> DateTime<Utc> {
         match self.schedule_type {
             ScheduleType::Daily { hour, minute } => {
                 let mut next = now
@@ -120,6 +131,14 @@ impl ClockTimer {
 
         let next_time = self.next_trigger.unwrap_or_else(Utc::now);
         let now = Utc::now();
+        if let Some(task) = self.task {
+            crate::metrics::observe_tick_lag(
+                task,
+                now.signed_duration_since(next_time)
+                    .to_std()
+                    .unwrap_or_default(),
+            );
+        }
         log::info!(
             "ClockTimer triggered at: {}, Planned time: {}, delay: {}ms",
             now,
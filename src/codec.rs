@@ -0,0 +1,118 @@
+//! Encodings of the values kept in text columns: numbers as fixed width big endian hex without
+//! `0x`, announced addresses as a JSON array of multiaddrs. Decoding reports malformed values
+//! instead of reading whatever prefix happens to parse, the doctor flags such rows.
+
+use multiaddr::MultiAddr;
+
+fn decode<const N: usize>(hex: &str) -> Result<[u8; N], String> {
+    if hex.len() != N * 2 {
+        return Err(format!(
+            "expected {} hex digits, got {:?}",
+            N * 2,
+            truncated(hex)
+        ));
+    }
+    let mut bytes = [0u8; N];
+    faster_hex::hex_decode(hex.as_bytes(), &mut bytes)
+        .map_err(|e| format!("{}: {:?}", e, truncated(hex)))?;
+    Ok(bytes)
+}
+
+/// At most 80 bytes of a malformed value for error messages.
+fn truncated(value: &str) -> &str {
+    let mut end = value.len().min(80);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value[..end]
+}
+
+pub fn encode_u64(value: u64) -> String {
+    faster_hex::hex_string(&value.to_be_bytes())
+}
+
+pub fn encode_u128(value: u128) -> String {
+    faster_hex::hex_string(&value.to_be_bytes())
+}
+
+pub fn decode_u64(hex: &str) -> Result<u64, String> {
+    decode(hex).map(u64::from_be_bytes)
+}
+
+pub fn decode_u128(hex: &str) -> Result<u128, String> {
+    decode(hex).map(u128::from_be_bytes)
+}
+
+pub fn decode_addresses(json: &str) -> Result<Vec<MultiAddr>, String> {
+    serde_json::from_str(json).map_err(|e| format!("invalid addresses: {}", e))
+}
+
+/// UDT amount of a cell, the little endian u128 its data starts with.
+pub fn udt_amount(data: &[u8]) -> Option<u128> {
+    Some(u128::from_le_bytes(data.get(..16)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, SocketAddr};
+
+    use multiaddr::MultiAddr;
+    use proptest::prelude::*;
+
+    use super::{decode_addresses, decode_u64, decode_u128, encode_u64, encode_u128, udt_amount};
+    use crate::pg_write::multiaddr_to_socketaddr;
+
+    proptest! {
+        #[test]
+        fn numbers_round_trip_through_fixed_width_hex(small: u64, large: u128) {
+            prop_assert_eq!(encode_u64(small).len(), 16);
+            prop_assert_eq!(decode_u64(&encode_u64(small)), Ok(small));
+            prop_assert_eq!(decode_u128(&encode_u128(large)), Ok(large));
+            // the upper half of a u128 is not a u64
+            prop_assert!(decode_u64(&encode_u128(large)).is_err());
+        }
+
+        #[test]
+        fn udt_amounts_are_little_endian(
+            amount: u128,
+            rest in prop::collection::vec(any::<u8>(), 0..8),
+        ) {
+            let mut data = amount.to_le_bytes().to_vec();
+            data.extend(rest);
+            prop_assert_eq!(udt_amount(&data), Some(amount));
+            prop_assert_eq!(udt_amount(&data[..15]), None);
+        }
+
+        #[test]
+        fn addresses_round_trip_to_their_socket(ip: IpAddr, port: u16) {
+            let addr = match ip {
+                IpAddr::V4(ip) => format!("/ip4/{}/tcp/{}", ip, port),
+                IpAddr::V6(ip) => format!("/ip6/{}/tcp/{}", ip, port),
+            };
+            let parsed = addr.parse::<MultiAddr>().unwrap();
+            prop_assert_eq!(parsed.to_string(), addr.clone());
+            let stored = serde_json::to_string(&[&parsed]).unwrap();
+            let decoded = decode_addresses(&stored).unwrap();
+            prop_assert_eq!(&decoded, &vec![parsed]);
+            prop_assert_eq!(
+                multiaddr_to_socketaddr(&decoded[0]),
+                Some(SocketAddr::new(ip, port))
+            );
+        }
+
+        #[test]
+        fn malformed_values_are_errors(value in ".{0,40}|[0-9a-fA-F]{0,40}") {
+            // never a panic, and only well formed values decode
+            prop_assert_eq!(
+                decode_u64(&value).is_ok(),
+                value.len() == 16 && value.bytes().all(|b| b.is_ascii_hexdigit())
+            );
+            prop_assert_eq!(
+                decode_u128(&value).is_ok(),
+                value.len() == 32 && value.bytes().all(|b| b.is_ascii_hexdigit())
+            );
+            let _ = decode_addresses(&value);
+            let _ = decode_addresses(&format!("[{:?}]", value));
+        }
+    }
+}
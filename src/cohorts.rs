@@ -0,0 +1,151 @@
+//! Monthly retention of nodes per cohort: of the nodes first seen in a month, the share still
+//! online in each following month.
+//!
+//! Presence comes from `online_nodes_hourly`, so "first seen" is relative to its 12 month
+//! retention and the oldest cohort also holds every node seen before it. The matrix is
+//! recomputed into `node_cohorts` once a month by the daily job.
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+
+use crate::Network;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cohort {
+    /// First day of the month the nodes were first seen.
+    pub cohort: NaiveDate,
+    pub nodes: i32,
+    /// Share of the cohort online in month `cohort + i`, starting with 1.0.
+    pub retention: Vec<f64>,
+}
+
+/// Retention shares from the online counts per month offset, offsets without any online node
+/// are 0 up to `months`, the number of months observed so far.
+pub fn retention(nodes: i32, retained: &[(i32, i32)], months: i32) -> Vec<f64> {
+    let mut shares = vec![0.0; months.max(1) as usize];
+    for (offset, count) in retained {
+        if let Some(share) = shares.get_mut(*offset as usize) {
+            *share = *count as f64 / nodes.max(1) as f64;
+        }
+    }
+    shares
+}
+
+fn month_index(day: NaiveDate) -> i32 {
+    day.year() * 12 + day.month0() as i32
+}
+
+/// Recompute the matrix of `net` unless it was already computed this month.
+pub async fn compute_if_due(
+    pool: &Pool<Postgres>,
+    net: Network,
+    now: DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let last: Option<DateTime<Utc>> =
+        sqlx::query("SELECT max(computed_at) AS last FROM node_cohorts WHERE net = $1")
+            .bind(net.name())
+            .fetch_one(pool)
+            .await?
+            .get("last");
+    if last.is_some_and(|last| month_index(last.date_naive()) == month_index(now.date_naive())) {
+        return Ok(false);
+    }
+    compute(pool, net, now).await?;
+    Ok(true)
+}
+
+pub async fn compute(
+    pool: &Pool<Postgres>,
+    net: Network,
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let sql = format!(
+        "WITH monthly AS (
+            SELECT DISTINCT node_id, date_trunc('month', bucket)::date AS month FROM {}
+        ),
+        first_seen AS (
+            SELECT node_id, min(month) AS cohort FROM monthly GROUP BY node_id
+        )
+        INSERT INTO node_cohorts (net, cohort, month_offset, nodes, computed_at)
+        SELECT $1, f.cohort,
+            ((extract(year FROM m.month) - extract(year FROM f.cohort)) * 12
+                + extract(month FROM m.month) - extract(month FROM f.cohort))::int,
+            count(*), $2
+        FROM first_seen f
+        JOIN monthly m ON m.node_id = f.node_id
+        GROUP BY 2, 3",
+        net.online_nodes_hourly()
+    );
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM node_cohorts WHERE net = $1")
+        .bind(net.name())
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query(&sql)
+        .bind(net.name())
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await
+}
+
+/// Retention matrix of `net` as of the last monthly run, oldest cohort first.
+pub async fn load(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<(Option<DateTime<Utc>>, Vec<Cohort>), sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT cohort, month_offset, nodes, computed_at FROM node_cohorts
+        WHERE net = $1 ORDER BY cohort, month_offset",
+    )
+    .bind(net.name())
+    .fetch_all(pool)
+    .await?;
+    let Some(computed_at) = rows
+        .first()
+        .map(|row| row.get::<DateTime<Utc>, _>("computed_at"))
+    else {
+        return Ok((None, Vec::new()));
+    };
+    let current = month_index(computed_at.date_naive());
+
+    let mut grouped: Vec<(NaiveDate, Vec<(i32, i32)>)> = Vec::new();
+    for row in rows {
+        let cohort: NaiveDate = row.get("cohort");
+        let entry = (row.get("month_offset"), row.get("nodes"));
+        match grouped.last_mut() {
+            Some((last, retained)) if *last == cohort => retained.push(entry),
+            _ => grouped.push((cohort, vec![entry])),
+        }
+    }
+    let cohorts = grouped
+        .into_iter()
+        .map(|(cohort, retained)| {
+            // offset 0 is the whole cohort
+            let nodes = retained
+                .iter()
+                .find(|(offset, _)| *offset == 0)
+                .map_or(0, |(_, count)| *count);
+            Cohort {
+                cohort,
+                nodes,
+                retention: retention(nodes, &retained, current - month_index(cohort) + 1),
+            }
+        })
+        .collect();
+    Ok((Some(computed_at), cohorts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::retention;
+
+    #[test]
+    fn months_without_online_nodes_are_zero() {
+        assert_eq!(
+            retention(4, &[(0, 4), (1, 2), (3, 1)], 5),
+            vec![1.0, 0.5, 0.0, 0.25, 0.0]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Two daily metrics over the same days with their Pearson correlation, so charts plotting one
+//! against the other need not align the buckets themselves.
+//!
+//! Both series come from the same `daily_summarized_data` rows, every summarized day of the
+//! range is a point of both. Capacities are in CKB like `/kpis`.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    Network,
+    kpis::{DayValues, day_values},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorrelateMetric {
+    Nodes,
+    Channels,
+    /// Total capacity of every asset, in CKB.
+    Capacity,
+    /// Median capacity of the CKB channels, in CKB.
+    MedianChannelCapacity,
+}
+
+impl CorrelateMetric {
+    fn value(self, day: &DayValues) -> u64 {
+        match self {
+            CorrelateMetric::Nodes => day.nodes,
+            CorrelateMetric::Channels => day.channels,
+            CorrelateMetric::Capacity => day.capacity,
+            CorrelateMetric::MedianChannelCapacity => day.median_channel_capacity,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Correlation {
+    pub x: CorrelateMetric,
+    pub y: CorrelateMetric,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    /// Summarized days of the range in order, `x_series` and `y_series` hold their values.
+    pub days: Vec<NaiveDate>,
+    pub x_series: Vec<u64>,
+    pub y_series: Vec<u64>,
+    /// `[x, y]` of every day, for a scatter plot.
+    pub points: Vec<(u64, u64)>,
+    /// Pearson coefficient, `None` below two days or when a series is constant.
+    pub coefficient: Option<f64>,
+}
+
+/// Pearson correlation coefficient of `points`.
+pub fn pearson(points: &[(f64, f64)]) -> Option<f64> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut cov, mut var_x, mut var_y) = (0.0, 0.0, 0.0);
+    for (x, y) in points {
+        let (dx, dy) = (x - mean_x, y - mean_y);
+        cov += dx * dy;
+        var_x += dx * dx;
+        var_y += dy * dy;
+    }
+    if var_x == 0.0 || var_y == 0.0 {
+        return None;
+    }
+    Some((cov / (var_x.sqrt() * var_y.sqrt())).clamp(-1.0, 1.0))
+}
+
+/// Metrics `x` and `y` of `net` on the summarized days from `start` to `end`, both included.
+pub async fn load(
+    pool: &Pool<Postgres>,
+    net: Network,
+    x: CorrelateMetric,
+    y: CorrelateMetric,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Correlation, sqlx::Error> {
+    let sql = format!(
+        "SELECT day, nodes_count, channels_count, capacity_analysis FROM {}
+        WHERE day >= $1 AND day <= $2",
+        net.daily_summarized_data()
+    );
+    let days = sqlx::query(&sql)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(day_values)
+        .collect::<BTreeMap<_, _>>();
+
+    let points = days
+        .values()
+        .map(|day| (x.value(day), y.value(day)))
+        .collect::<Vec<_>>();
+    let coefficient = pearson(
+        &points
+            .iter()
+            .map(|(x, y)| (*x as f64, *y as f64))
+            .collect::<Vec<_>>(),
+    );
+    Ok(Correlation {
+        x,
+        y,
+        start,
+        end,
+        days: days.into_keys().collect(),
+        x_series: points.iter().map(|(x, _)| *x).collect(),
+        y_series: points.iter().map(|(_, y)| *y).collect(),
+        points,
+        coefficient,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pearson;
+
+    #[test]
+    fn pearson_of_linear_and_constant_series() {
+        let rising = [(1.0, 10.0), (2.0, 20.0), (3.0, 30.0)];
+        assert!((pearson(&rising).unwrap() - 1.0).abs() < 1e-12);
+        let falling = [(1.0, 3.0), (2.0, 2.0), (3.0, 1.0)];
+        assert!((pearson(&falling).unwrap() + 1.0).abs() < 1e-12);
+        assert_eq!(pearson(&[(1.0, 5.0), (2.0, 5.0)]), None);
+        assert_eq!(pearson(&[(1.0, 1.0)]), None);
+
+        let noisy = [(1.0, 2.0), (2.0, 1.0), (3.0, 4.0), (4.0, 3.0)];
+        assert!((pearson(&noisy).unwrap() - 0.6).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,370 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{ENABLED_NETWORKS, Network};
+
+/// Max number of offending keys kept per finding.
+const SAMPLE_LIMIT: usize = 10;
+
+/// Continuous aggregates are bucketed by hour and refreshed with a 10 minute end offset,
+/// anything older than this means the refresh policy is stuck.
+const MAX_AGGREGATE_LAG: chrono::Duration = chrono::Duration::hours(2);
+
+/// Channel states younger than this may still be waiting for their txs to be written, the fix
+/// leaves them alone.
+const FIX_GRACE: chrono::Duration = chrono::Duration::hours(1);
+
+/// How often [`partition_verifier`] runs and how far back it looks.
+const PARTITION_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+const PARTITION_CHECK_WINDOW: chrono::Duration = chrono::Duration::hours(2);
+
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub net: Network,
+    pub count: usize,
+    pub samples: Vec<String>,
+    pub fixed: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DoctorReport {
+    pub generated_at: DateTime<Utc>,
+    pub healthy: bool,
+    pub findings: Vec<Finding>,
+}
+
+/// Validate data invariants for every network, optionally deleting orphan rows and
+/// quarantining rows written to the wrong network's tables.
+pub async fn run(
+    pool: &Pool<Postgres>,
+    nets: &[Network],
+    fix: bool,
+) -> Result<DoctorReport, sqlx::Error> {
+    let mut findings = Vec::new();
+    for net in nets {
+        findings.push(channel_states_without_txs(pool, *net, fix).await?);
+        findings.push(channels_with_missing_udt(pool, *net).await?);
+        findings.extend(udt_relations_with_missing_udt(pool, *net).await?);
+        findings.push(invalid_channel_state_hex(pool, *net).await?);
+        findings.push(invalid_node_hex(pool, *net).await?);
+        findings.extend(partition_leaks(pool, *net, None, fix).await?);
+        findings.push(quarantined_rows(pool, *net).await?);
+        findings.push(
+            aggregate_lag(
+                pool,
+                *net,
+                "online_nodes_hourly_lag",
+                net.node_infos(),
+                net.online_nodes_hourly(),
+            )
+            .await?,
+        );
+        findings.push(
+            aggregate_lag(
+                pool,
+                *net,
+                "online_channels_hourly_lag",
+                net.channel_infos(),
+                net.online_channels_hourly(),
+            )
+            .await?,
+        );
+    }
+    findings.retain(|f| f.count > 0);
+
+    Ok(DoctorReport {
+        generated_at: Utc::now(),
+        healthy: findings.is_empty(),
+        findings,
+    })
+}
+
+async fn channel_states_without_txs(
+    pool: &Pool<Postgres>,
+    net: Network,
+    fix: bool,
+) -> Result<Finding, sqlx::Error> {
+    let sql = format!(
+        "SELECT s.channel_outpoint FROM {} s
+        WHERE NOT EXISTS (SELECT 1 FROM {} t WHERE t.channel_outpoint = s.channel_outpoint)",
+        net.channel_states(),
+        net.channel_txs()
+    );
+    let outpoints = sqlx::query(&sql)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| row.get::<String, _>("channel_outpoint"))
+        .collect::<Vec<_>>();
+
+    let mut fixed = 0;
+    if fix && !outpoints.is_empty() {
+        // the monitor writes a state before its txs, only rows past the grace period are orphans
+        let sql = format!(
+            "DELETE FROM {0} s WHERE channel_outpoint = ANY($1)
+            AND GREATEST(s.create_time, s.last_commit_time) < $2
+            AND NOT EXISTS (SELECT 1 FROM {1} t WHERE t.channel_outpoint = s.channel_outpoint)",
+            net.channel_states(),
+            net.channel_txs()
+        );
+        fixed = sqlx::query(&sql)
+            .bind(&outpoints)
+            .bind(Utc::now() - FIX_GRACE)
+            .execute(pool)
+            .await?
+            .rows_affected() as usize;
+    }
+
+    Ok(finding("channel_states_without_txs", net, outpoints, fixed))
+}
+
+async fn channels_with_missing_udt(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<Finding, sqlx::Error> {
+    let sql = format!(
+        "SELECT DISTINCT c.udt_type_script FROM {} c
+        WHERE c.udt_type_script IS NOT NULL
+        AND NOT EXISTS (SELECT 1 FROM {} u WHERE u.id = c.udt_type_script)",
+        net.mv_online_channels(),
+        net.udt_infos()
+    );
+    let ids = sqlx::query(&sql)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| row.get::<i32, _>("udt_type_script").to_string())
+        .collect::<Vec<_>>();
+
+    Ok(finding("channels_with_missing_udt_info", net, ids, 0))
+}
+
+/// `udt_dep` and `node_udt_relations` rows whose `udt_info_id` has no `udt_infos` row.
+async fn udt_relations_with_missing_udt(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<Vec<Finding>, sqlx::Error> {
+    let mut findings = Vec::new();
+    for (check, table) in [
+        ("udt_deps_with_missing_udt_info", net.udt_dep()),
+        (
+            "node_udt_relations_with_missing_udt_info",
+            net.node_udt_relations(),
+        ),
+    ] {
+        let sql = format!(
+            "SELECT DISTINCT r.udt_info_id FROM {} r
+            WHERE NOT EXISTS (SELECT 1 FROM {} u WHERE u.id = r.udt_info_id)",
+            table,
+            net.udt_infos()
+        );
+        let ids = sqlx::query(&sql)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| row.get::<i32, _>("udt_info_id").to_string())
+            .collect::<Vec<_>>();
+        findings.push(finding(check, net, ids, 0));
+    }
+    Ok(findings)
+}
+
+async fn invalid_channel_state_hex(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<Finding, sqlx::Error> {
+    let sql = format!(
+        "SELECT channel_outpoint FROM {}
+        WHERE length(channel_outpoint) != 72
+        OR length(capacity) != 16
+        OR length(last_block_number) != 16
+        OR length(last_tx_hash) != 64
+        OR (udt_value IS NOT NULL AND length(udt_value) != 32)
+        OR channel_outpoint !~ '^[0-9a-f]*$'
+        OR capacity !~ '^[0-9a-f]*$'",
+        net.channel_states()
+    );
+    let outpoints = sqlx::query(&sql)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| row.get::<String, _>("channel_outpoint"))
+        .collect::<Vec<_>>();
+
+    Ok(finding("channel_states_invalid_hex", net, outpoints, 0))
+}
+
+async fn invalid_node_hex(pool: &Pool<Postgres>, net: Network) -> Result<Finding, sqlx::Error> {
+    let sql = format!(
+        "SELECT node_id FROM {}
+        WHERE length(node_id) != 66
+        OR length(chain_hash) != 64
+        OR length(auto_accept_min_ckb_funding_amount) != 16
+        OR node_id !~ '^[0-9a-f]*$'",
+        net.mv_online_nodes()
+    );
+    let node_ids = sqlx::query(&sql)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| row.get::<String, _>("node_id"))
+        .collect::<Vec<_>>();
+
+    Ok(finding("online_nodes_invalid_hex", net, node_ids, 0))
+}
+
+/// Rows of `node_infos` / `channel_infos` whose chain hash is not the table's network, moved
+/// to `quarantined_rows` when `quarantine` is set. `since` limits the scan to recent rows.
+async fn partition_leaks(
+    pool: &Pool<Postgres>,
+    net: Network,
+    since: Option<DateTime<Utc>>,
+    quarantine: bool,
+) -> Result<Vec<Finding>, sqlx::Error> {
+    let mut findings = Vec::new();
+    for (check, table, key) in [
+        ("node_infos_wrong_chain", net.node_infos(), "node_id"),
+        (
+            "channel_infos_wrong_chain",
+            net.channel_infos(),
+            "channel_outpoint",
+        ),
+    ] {
+        let filter = "chain_hash <> $1 AND ($2::timestamptz IS NULL OR time >= $2)";
+        let sql = format!("SELECT DISTINCT {key} FROM {table} WHERE {filter}");
+        let keys = sqlx::query(&sql)
+            .bind(net.chain_hash())
+            .bind(since)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| row.get::<String, _>(0))
+            .collect::<Vec<_>>();
+
+        let mut fixed = 0;
+        if quarantine && !keys.is_empty() {
+            log::error!(
+                "{} {}s in {} carry another chain hash, quarantining their rows",
+                keys.len(),
+                key,
+                table
+            );
+            let sql = format!(
+                "WITH moved AS (DELETE FROM {table} WHERE {filter} RETURNING *)
+                INSERT INTO quarantined_rows (source_table, key, chain_hash, time, data)
+                SELECT '{table}', {key}, chain_hash, time, to_jsonb(moved) FROM moved"
+            );
+            fixed = sqlx::query(&sql)
+                .bind(net.chain_hash())
+                .bind(since)
+                .execute(pool)
+                .await?
+                .rows_affected() as usize;
+        }
+        findings.push(finding(check, net, keys, fixed));
+    }
+    Ok(findings)
+}
+
+/// Rows quarantined so far, they stay in `quarantined_rows` until removed by hand.
+async fn quarantined_rows(pool: &Pool<Postgres>, net: Network) -> Result<Finding, sqlx::Error> {
+    let keys =
+        sqlx::query("SELECT DISTINCT key FROM quarantined_rows WHERE source_table = ANY($1)")
+            .bind([net.node_infos(), net.channel_infos()])
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| row.get::<String, _>("key"))
+            .collect::<Vec<_>>();
+
+    Ok(finding("quarantined_rows", net, keys, 0))
+}
+
+/// Quarantine rows written to the wrong network's tables during the last
+/// [`PARTITION_CHECK_WINDOW`], every [`PARTITION_CHECK_INTERVAL`].
+pub async fn partition_verifier(pool: &'static Pool<Postgres>) {
+    let mut timer = tokio::time::interval(PARTITION_CHECK_INTERVAL);
+    timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        timer.tick().await;
+        let since = Utc::now() - PARTITION_CHECK_WINDOW;
+        for &net in ENABLED_NETWORKS.iter() {
+            if let Err(e) = partition_leaks(pool, net, Some(since), true).await {
+                log::error!("Failed to verify {:?} partition integrity: {}", net, e);
+            }
+        }
+    }
+}
+
+async fn aggregate_lag(
+    pool: &Pool<Postgres>,
+    net: Network,
+    check: &'static str,
+    raw_table: &str,
+    aggregate: &str,
+) -> Result<Finding, sqlx::Error> {
+    let sql = format!(
+        "SELECT
+            (SELECT max(time) FROM {} WHERE time >= now() - interval '1 day') AS raw_latest,
+            (SELECT max(bucket) FROM {} WHERE bucket >= now() - interval '1 day') AS aggregate_latest",
+        raw_table, aggregate
+    );
+    let row = sqlx::query(&sql).fetch_one(pool).await?;
+    let raw_latest: Option<DateTime<Utc>> = row.get("raw_latest");
+    let aggregate_latest: Option<DateTime<Utc>> = row.get("aggregate_latest");
+
+    let samples = if is_lagging(raw_latest, aggregate_latest) {
+        vec![format!(
+            "raw latest {}, aggregate latest {}",
+            raw_latest.map(|t| t.to_rfc3339()).unwrap_or_default(),
+            aggregate_latest
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_else(|| "none".to_string())
+        )]
+    } else {
+        Vec::new()
+    };
+    Ok(finding(check, net, samples, 0))
+}
+
+fn is_lagging(raw_latest: Option<DateTime<Utc>>, aggregate_latest: Option<DateTime<Utc>>) -> bool {
+    match (raw_latest, aggregate_latest) {
+        (Some(raw), Some(agg)) => raw - agg > MAX_AGGREGATE_LAG,
+        (Some(_), None) => true,
+        (None, _) => false,
+    }
+}
+
+fn finding(check: &'static str, net: Network, mut keys: Vec<String>, fixed: usize) -> Finding {
+    let count = keys.len();
+    keys.truncate(SAMPLE_LIMIT);
+    Finding {
+        check,
+        net,
+        count,
+        samples: keys,
+        fixed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_lagging;
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn aggregate_within_refresh_window_is_not_lagging() {
+        let now = Utc::now();
+        assert!(!is_lagging(Some(now), Some(now - Duration::minutes(70))));
+    }
+
+    #[test]
+    fn stale_or_missing_aggregate_is_lagging() {
+        let now = Utc::now();
+        assert!(is_lagging(Some(now), Some(now - Duration::hours(3))));
+        assert!(is_lagging(Some(now), None));
+        assert!(!is_lagging(None, None));
+    }
+}
@@ -0,0 +1,93 @@
+//! Largest-triangle-three-buckets downsampling of chart series.
+//!
+//! The first and last points are always kept. The points in between are split into equal
+//! buckets and each bucket keeps the point forming the largest triangle with the point kept
+//! before it and the average of the next bucket, so peaks and dips survive where plain
+//! striding would skip them.
+
+/// Fewest points a downsampled series keeps, the two ends and one bucket.
+pub const MIN_POINTS: usize = 3;
+
+/// Indices of the points of `points`, `(x, y)` ordered by `x`, kept when downsampling to at
+/// most `max_points`, below [`MIN_POINTS`] counts as [`MIN_POINTS`].
+pub fn lttb_indices(points: &[(f64, f64)], max_points: usize) -> Vec<usize> {
+    let len = points.len();
+    let threshold = max_points.max(MIN_POINTS);
+    if len <= threshold {
+        return (0..len).collect();
+    }
+
+    let bucket_size = (len - 2) as f64 / (threshold - 2) as f64;
+    let bucket_start = |bucket: usize| ((bucket as f64 * bucket_size) as usize + 1).min(len - 1);
+    let mut kept = Vec::with_capacity(threshold);
+    kept.push(0);
+    let mut previous = 0;
+    for bucket in 0..threshold - 2 {
+        let (start, end) = (bucket_start(bucket), bucket_start(bucket + 1));
+        let next = &points[end..bucket_start(bucket + 2).max(end + 1).min(len)];
+        let avg_x = next.iter().map(|(x, _)| x).sum::<f64>() / next.len() as f64;
+        let avg_y = next.iter().map(|(_, y)| y).sum::<f64>() / next.len() as f64;
+
+        let (ax, ay) = points[previous];
+        let mut best = (start, -1.0);
+        for (i, (x, y)) in points.iter().enumerate().take(end).skip(start) {
+            // twice the triangle area, only compared
+            let area = ((ax - avg_x) * (y - ay) - (ax - x) * (avg_y - ay)).abs();
+            if area > best.1 {
+                best = (i, area);
+            }
+        }
+        kept.push(best.0);
+        previous = best.0;
+    }
+    kept.push(len - 1);
+    kept
+}
+
+/// Downsample `series` ordered by x to at most `max_points`, `xy` giving the coordinates the
+/// points are chosen by. The kept items are returned unchanged.
+pub fn lttb<T>(series: Vec<T>, max_points: usize, xy: impl Fn(&T) -> (f64, f64)) -> Vec<T> {
+    let points = series.iter().map(&xy).collect::<Vec<_>>();
+    let kept = lttb_indices(&points, max_points);
+    if kept.len() == series.len() {
+        return series;
+    }
+    let mut kept = kept.into_iter().peekable();
+    series
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, item)| kept.next_if_eq(&i).map(|_| item))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lttb, lttb_indices};
+
+    #[test]
+    fn short_series_are_kept_whole() {
+        let points = [(0.0, 1.0), (1.0, 2.0), (2.0, 3.0)];
+        assert_eq!(lttb_indices(&points, 10), vec![0, 1, 2]);
+        assert_eq!(lttb_indices(&points, 1), vec![0, 1, 2]);
+        assert!(lttb_indices(&[], 5).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_ends_and_the_spike() {
+        let mut points = (0..100).map(|x| (x as f64, 1.0)).collect::<Vec<_>>();
+        points[37].1 = 50.0;
+        let kept = lttb_indices(&points, 10);
+        assert_eq!(kept.len(), 10);
+        assert_eq!(kept.first(), Some(&0));
+        assert_eq!(kept.last(), Some(&99));
+        assert!(kept.contains(&37));
+        assert!(kept.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let days = (0..100).collect::<Vec<u32>>();
+        let sampled = lttb(days, 10, |day| points[*day as usize]);
+        assert_eq!(
+            sampled.iter().map(|day| *day as usize).collect::<Vec<_>>(),
+            kept
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Error of data that does not decode: hex columns, unknown enum values and missing fields of
+//! database rows or rpc results.
+//!
+//! A malformed row fails the request or job reading it, or is skipped with a log where a whole
+//! table is loaded, instead of panicking the process. Functions keeping `sqlx::Error` in their
+//! signature convert it with `?`, the decoding errors become [`sqlx::Error::Decode`].
+
+use ckb_jsonrpc_types::{JsonBytes, Script, ScriptHashType};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FiberDashboardError {
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("invalid hex in {field}: {source}")]
+    Hex {
+        field: &'static str,
+        source: faster_hex::Error,
+    },
+    #[error("unknown {kind} {value:?}")]
+    Unknown { kind: &'static str, value: String },
+    #[error("missing {0}")]
+    Missing(&'static str),
+    #[error("invalid {field}: {reason}")]
+    Invalid { field: &'static str, reason: String },
+}
+
+pub type Result<T, E = FiberDashboardError> = std::result::Result<T, E>;
+
+impl FiberDashboardError {
+    pub fn unknown(kind: &'static str, value: impl Into<String>) -> Self {
+        FiberDashboardError::Unknown {
+            kind,
+            value: value.into(),
+        }
+    }
+
+    pub fn invalid(field: &'static str, reason: impl ToString) -> Self {
+        FiberDashboardError::Invalid {
+            field,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl From<FiberDashboardError> for sqlx::Error {
+    fn from(e: FiberDashboardError) -> Self {
+        match e {
+            FiberDashboardError::Database(e) => e,
+            e => sqlx::Error::Decode(Box::new(e)),
+        }
+    }
+}
+
+/// Decode the hex of `field` into `N` bytes, extra trailing digits are ignored.
+pub fn decode_hex<const N: usize>(field: &'static str, hex: &str) -> Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    faster_hex::hex_decode(hex.as_bytes(), &mut buf)
+        .map_err(|source| FiberDashboardError::Hex { field, source })?;
+    Ok(buf)
+}
+
+/// Decode the hex of `field` of any even length.
+pub fn decode_hex_vec(field: &'static str, hex: &str) -> Result<Vec<u8>> {
+    let mut buf = vec![0u8; hex.len() / 2];
+    faster_hex::hex_decode(hex.as_bytes(), &mut buf)
+        .map_err(|source| FiberDashboardError::Hex { field, source })?;
+    Ok(buf)
+}
+
+/// [`decode_hex_vec`] as json rpc bytes.
+pub fn decode_json_bytes(field: &'static str, hex: &str) -> Result<JsonBytes> {
+    decode_hex_vec(field, hex).map(|buf| JsonBytes::from_bytes(buf.into()))
+}
+
+/// Decode one row of a bulk load, a malformed row is logged and skipped so the rest stays
+/// usable.
+pub fn skip_malformed<R, T>(kind: &str, row: R) -> Option<T>
+where
+    T: TryFrom<R, Error = FiberDashboardError>,
+{
+    T::try_from(row)
+        .inspect_err(|e| log::warn!("Skipping malformed {}: {}", kind, e))
+        .ok()
+}
+
+/// Hash type of a script as stored in the `hash_type` columns.
+pub fn decode_hash_type(hash_type: &str) -> Result<ScriptHashType> {
+    match hash_type {
+        "type" => Ok(ScriptHashType::Type),
+        "data" => Ok(ScriptHashType::Data),
+        "data1" => Ok(ScriptHashType::Data1),
+        "data2" => Ok(ScriptHashType::Data2),
+        _ => Err(FiberDashboardError::unknown("hash type", hash_type)),
+    }
+}
+
+/// Script stored as hex `code_hash` and `args` columns.
+pub fn decode_script(code_hash: &str, hash_type: &str, args: &str) -> Result<Script> {
+    Ok(Script {
+        code_hash: decode_hex::<32>("code_hash", code_hash)?.into(),
+        hash_type: decode_hash_type(hash_type)?,
+        args: decode_json_bytes("args", args)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FiberDashboardError, decode_hex, decode_hex_vec, decode_script};
+
+    #[test]
+    fn malformed_hex_is_an_error() {
+        assert_eq!(decode_hex::<2>("capacity", "0102").unwrap(), [1, 2]);
+        assert_eq!(decode_hex::<1>("capacity", "0102").unwrap(), [1]);
+        assert!(matches!(
+            decode_hex::<8>("capacity", "0102"),
+            Err(FiberDashboardError::Hex {
+                field: "capacity",
+                ..
+            })
+        ));
+        assert!(decode_hex_vec("args", "zz").is_err());
+        assert!(decode_hex_vec("args", "abc").is_err());
+        assert!(decode_script(&"00".repeat(32), "data1", "0a0b").is_ok());
+        assert!(matches!(
+            decode_script(&"00".repeat(32), "data9", ""),
+            Err(FiberDashboardError::Unknown {
+                kind: "hash type",
+                ..
+            })
+        ));
+        let e = sqlx::Error::from(FiberDashboardError::Missing("commitment args"));
+        assert!(matches!(e, sqlx::Error::Decode(_)));
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, postgres::PgListener};
+use tokio::sync::broadcast;
+
+use crate::Network;
+
+/// Postgres NOTIFY channel shared by the collector and API roles.
+pub const EVENTS_CHANNEL: &str = "fiber_dashboard_events";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A collection cycle has committed a new nodes/channels snapshot.
+    SnapshotCommitted {
+        net: Network,
+        nodes: usize,
+        channels: usize,
+        time: DateTime<Utc>,
+    },
+    /// Newly discovered channels have been written to channel_states.
+    NewChannels { net: Network, count: usize },
+    /// Existing channels changed their on-chain state.
+    ChannelStatesUpdated { net: Network, count: usize },
+    /// The online materialized views have been refreshed.
+    AggregatesRefreshed { net: Network, time: DateTime<Utc> },
+    /// The daily summarizer has committed new rows.
+    DailySummaryCommitted { time: DateTime<Utc> },
+}
+
+impl Event {
+    pub fn net(&self) -> Option<Network> {
+        match self {
+            Event::SnapshotCommitted { net, .. }
+            | Event::NewChannels { net, .. }
+            | Event::ChannelStatesUpdated { net, .. }
+            | Event::AggregatesRefreshed { net, .. } => Some(*net),
+            Event::DailySummaryCommitted { .. } => None,
+        }
+    }
+}
+
+static LOCAL_BUS: LazyLock<broadcast::Sender<Event>> = LazyLock::new(|| broadcast::channel(256).0);
+
+/// Subscribe to events received by this process.
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    LOCAL_BUS.subscribe()
+}
+
+/// Publish an event to every process listening on the database.
+pub async fn publish(pool: &Pool<Postgres>, event: &Event) -> Result<(), sqlx::Error> {
+    let payload = serde_json::to_string(event).unwrap();
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(EVENTS_CHANNEL)
+        .bind(payload)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Same as [`publish`] but only logs failures, for use on the collector hot path.
+pub async fn emit(pool: &Pool<Postgres>, event: Event) {
+    if let Err(e) = publish(pool, &event).await {
+        log::warn!("Failed to publish event {:?}: {}", event, e);
+    }
+}
+
+/// Deliver an event to subscribers of this process only, used when there is no Postgres
+/// to publish through (lite mode).
+pub fn emit_local(event: Event) {
+    // An error only means there are no subscribers right now
+    let _ = LOCAL_BUS.send(event);
+}
+
+/// LISTEN on the events channel and forward every notification to local subscribers.
+///
+/// Reconnects on error, so it is meant to be spawned once for the lifetime of the API process.
+pub async fn listen(pool: &'static Pool<Postgres>) {
+    loop {
+        let mut listener = match PgListener::connect_with(pool).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                log::warn!("Failed to connect events listener: {}", e);
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        if let Err(e) = listener.listen(EVENTS_CHANNEL).await {
+            log::warn!("Failed to LISTEN on {}: {}", EVENTS_CHANNEL, e);
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            continue;
+        }
+        log::info!("Listening for events on {}", EVENTS_CHANNEL);
+
+        loop {
+            match listener.recv().await {
+                Ok(notification) => match serde_json::from_str::<Event>(notification.payload()) {
+                    // An error only means there are no subscribers right now
+                    Ok(event) => {
+                        let _ = LOCAL_BUS.send(event);
+                    }
+                    Err(e) => log::warn!("Invalid event payload: {}", e),
+                },
+                Err(e) => {
+                    log::warn!("Events listener disconnected: {}", e);
+                    break;
+                }
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+}
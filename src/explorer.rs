@@ -0,0 +1,93 @@
+//! Links to a CKB explorer for the channels in detail responses, assembled here so every
+//! frontend points at the same pages.
+//!
+//! `EXPLORER_MAINNET_URL` and `EXPLORER_TESTNET_URL` set the explorer of each network, the
+//! public Nervos explorers by default.
+
+use std::sync::LazyLock;
+
+use serde::Serialize;
+
+use crate::{Network, config, outpoint};
+
+static EXPLORER_MAINNET_URL: LazyLock<String> =
+    LazyLock::new(|| base_url("EXPLORER_MAINNET_URL", "https://explorer.nervos.org"));
+static EXPLORER_TESTNET_URL: LazyLock<String> = LazyLock::new(|| {
+    base_url(
+        "EXPLORER_TESTNET_URL",
+        "https://testnet.explorer.nervos.org",
+    )
+});
+
+fn base_url(var: &str, default: &str) -> String {
+    config::var(var)
+        .unwrap_or(default.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn base(net: Network) -> &'static str {
+    match net {
+        Network::Mainnet => &EXPLORER_MAINNET_URL,
+        Network::Testnet => &EXPLORER_TESTNET_URL,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExplorerLinks {
+    pub funding_tx: String,
+    /// The funding cell among the outputs of the funding tx.
+    pub funding_cell: String,
+    /// The tx spending the funding cell, once the channel is closing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closing_tx: Option<String>,
+}
+
+/// Page of the tx `tx_hash`, hex with or without `0x`.
+pub fn tx_link(net: Network, tx_hash: &str) -> String {
+    format!(
+        "{}/transaction/0x{}",
+        base(net),
+        tx_hash.trim_start_matches("0x")
+    )
+}
+
+/// Links of the channel funded at the molecule encoded `channel_outpoint`, `None` when it is
+/// not a valid outpoint.
+pub fn links(
+    net: Network,
+    channel_outpoint: &str,
+    closing_tx_hash: Option<&str>,
+) -> Option<ExplorerLinks> {
+    let canonical = outpoint::canonical(channel_outpoint)?;
+    let (tx_hash, index) = canonical.split_once(':')?;
+    Some(ExplorerLinks {
+        funding_tx: tx_link(net, tx_hash),
+        funding_cell: format!("{}#{}", tx_link(net, tx_hash), index),
+        closing_tx: closing_tx_hash.map(|tx_hash| tx_link(net, tx_hash)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::links;
+    use crate::Network;
+
+    #[test]
+    fn links_point_at_the_funding_and_closing_txs() {
+        let tx_hash = "ab".repeat(32);
+        let links = links(
+            Network::Mainnet,
+            &format!("0x{}01000000", tx_hash),
+            Some("0xcd"),
+        )
+        .unwrap();
+        let funding_tx = format!("https://explorer.nervos.org/transaction/0x{}", tx_hash);
+        assert_eq!(links.funding_cell, format!("{}#1", funding_tx));
+        assert_eq!(links.funding_tx, funding_tx);
+        assert_eq!(
+            links.closing_tx.as_deref(),
+            Some("https://explorer.nervos.org/transaction/0xcd")
+        );
+    }
+}
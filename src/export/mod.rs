@@ -0,0 +1,154 @@
+mod s3;
+
+use std::{path::PathBuf, sync::LazyLock};
+
+use chrono::{Datelike, NaiveDate};
+use futures::StreamExt;
+use reqwest::Url;
+use sqlx::{Pool, Postgres};
+
+use crate::{Network, config};
+
+pub use s3::S3Client;
+
+/// Local directory exports are written to.
+static EXPORT_DIR: LazyLock<Option<PathBuf>> =
+    LazyLock::new(|| config::var("EXPORT_DIR").map(PathBuf::from));
+
+/// S3 compatible bucket exports are uploaded to, configured when endpoint, bucket and keys are all set.
+static EXPORT_S3: LazyLock<Option<S3Client>> = LazyLock::new(|| {
+    let endpoint = config::var("EXPORT_S3_ENDPOINT").and_then(|url| Url::parse(&url).ok())?;
+    let bucket = config::var("EXPORT_S3_BUCKET")?;
+    let access_key = config::var("EXPORT_S3_ACCESS_KEY")?;
+    let secret_key = config::var("EXPORT_S3_SECRET_KEY")?;
+    let region = config::var("EXPORT_S3_REGION").unwrap_or("us-east-1".to_string());
+    Some(S3Client::new(
+        endpoint, bucket, region, access_key, secret_key,
+    ))
+});
+
+/// Object key template, `{prefix}`, `{net}`, `{dataset}`, `{date}`, `{year}`, `{month}` and `{day}` are substituted.
+static EXPORT_KEY_LAYOUT: LazyLock<String> = LazyLock::new(|| {
+    config::var("EXPORT_KEY_LAYOUT")
+        .unwrap_or("{prefix}/{net}/{dataset}/{year}/{month}/{date}.csv".to_string())
+});
+
+static EXPORT_PREFIX: LazyLock<String> =
+    LazyLock::new(|| config::var("EXPORT_S3_PREFIX").unwrap_or("fiber-dashboard".to_string()));
+
+pub fn enabled() -> bool {
+    EXPORT_DIR.is_some() || EXPORT_S3.is_some()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Dataset {
+    Nodes,
+    Channels,
+    DailySummary,
+}
+
+impl Dataset {
+    pub const ALL: [Dataset; 3] = [Dataset::Nodes, Dataset::Channels, Dataset::DailySummary];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Dataset::Nodes => "nodes",
+            Dataset::Channels => "channels",
+            Dataset::DailySummary => "daily_summary",
+        }
+    }
+
+    /// `COPY` statement dumping the dataset for `day` as CSV with a header row.
+    ///
+    /// Nodes and channels keep the last snapshot of each entity seen during the day.
+    fn copy_sql(&self, net: Network, day: NaiveDate) -> String {
+        let query = match self {
+            Dataset::Nodes => format!(
+                "SELECT DISTINCT ON (node_id) * FROM {}
+                WHERE time >= '{day}'::date AND time < '{day}'::date + 1
+                ORDER BY node_id, time DESC",
+                net.node_infos()
+            ),
+            Dataset::Channels => format!(
+                "SELECT DISTINCT ON (channel_outpoint) * FROM {}
+                WHERE time >= '{day}'::date AND time < '{day}'::date + 1
+                ORDER BY channel_outpoint, time DESC",
+                net.channel_infos()
+            ),
+            Dataset::DailySummary => format!(
+                "SELECT * FROM {} WHERE day = '{day}'::date",
+                net.daily_summarized_data()
+            ),
+        };
+        format!("COPY ({}) TO STDOUT WITH (FORMAT csv, HEADER)", query)
+    }
+}
+
+fn object_key(net: Network, dataset: Dataset, day: NaiveDate) -> String {
+    let net = match net {
+        Network::Mainnet => "mainnet",
+        Network::Testnet => "testnet",
+    };
+    EXPORT_KEY_LAYOUT
+        .replace("{prefix}", &EXPORT_PREFIX)
+        .replace("{net}", net)
+        .replace("{dataset}", dataset.name())
+        .replace("{date}", &day.to_string())
+        .replace("{year}", &format!("{:04}", day.year()))
+        .replace("{month}", &format!("{:02}", day.month()))
+        .replace("{day}", &format!("{:02}", day.day()))
+        .trim_start_matches('/')
+        .to_string()
+}
+
+async fn dump(
+    pool: &Pool<Postgres>,
+    net: Network,
+    dataset: Dataset,
+    day: NaiveDate,
+) -> Result<Vec<u8>, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    let mut stream = conn.copy_out_raw(&dataset.copy_sql(net, day)).await?;
+    let mut csv = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        csv.extend_from_slice(&chunk?);
+    }
+    Ok(csv)
+}
+
+/// Export every dataset of `day` to the configured targets, returns the written keys.
+pub async fn export_day(
+    pool: &Pool<Postgres>,
+    day: NaiveDate,
+    nets: impl Iterator<Item = &Network>,
+) -> Result<Vec<String>, std::io::Error> {
+    let mut keys = Vec::new();
+    if !enabled() {
+        return Ok(keys);
+    }
+    for net in nets {
+        for dataset in Dataset::ALL {
+            let csv = dump(pool, *net, dataset, day)
+                .await
+                .map_err(std::io::Error::other)?;
+            let key = object_key(*net, dataset, day);
+            if let Some(dir) = EXPORT_DIR.as_ref() {
+                let path = dir.join(&key);
+                tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+                tokio::fs::write(&path, &csv).await?;
+            }
+            if let Some(s3) = EXPORT_S3.as_ref() {
+                s3.put_object(&key, "text/csv", csv).await?;
+            }
+            log::info!(
+                "Exported {:?} {} of {} to {}",
+                net,
+                dataset.name(),
+                day,
+                key
+            );
+            keys.push(key);
+        }
+    }
+    Ok(keys)
+}
@@ -0,0 +1,149 @@
+use chrono::{DateTime, Utc};
+use faster_hex::hex_string;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Url};
+use sha2::{Digest, Sha256};
+
+/// Minimal S3 compatible client, only signs path-style `PUT Object` requests with SigV4.
+pub struct S3Client {
+    raw: Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Client {
+    pub fn new(
+        endpoint: Url,
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+    ) -> Self {
+        S3Client {
+            raw: Client::builder()
+                .timeout(std::time::Duration::from_secs(300))
+                .build()
+                .unwrap(),
+            endpoint,
+            bucket,
+            region,
+            access_key,
+            secret_key,
+        }
+    }
+
+    pub async fn put_object(
+        &self,
+        key: &str,
+        content_type: &str,
+        body: Vec<u8>,
+    ) -> Result<(), std::io::Error> {
+        let path = format!(
+            "{}/{}/{}",
+            self.endpoint.path().trim_end_matches('/'),
+            self.bucket,
+            uri_encode(key)
+        );
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let payload_hash = hex_string(&Sha256::digest(&body));
+        let now = Utc::now();
+        let authorization = self.authorization(&path, &host, &payload_hash, &now);
+
+        let resp = self
+            .raw
+            .put(url)
+            .header("content-type", content_type)
+            .header("x-amz-content-sha256", &payload_hash)
+            .header("x-amz-date", now.format("%Y%m%dT%H%M%SZ").to_string())
+            .header("authorization", authorization)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::ConnectionAborted, e))?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            return Err(std::io::Error::other(format!(
+                "PUT {} failed with {}: {}",
+                key, status, text
+            )));
+        }
+        Ok(())
+    }
+
+    fn authorization(
+        &self,
+        path: &str,
+        host: &str,
+        payload_hash: &str,
+        now: &DateTime<Utc>,
+    ) -> String {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "PUT\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            path, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex_string(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+        let key = hmac_sha256(&key, &self.region);
+        let key = hmac_sha256(&key, "s3");
+        let key = hmac_sha256(&key, "aws4_request");
+        let signature = hex_string(&hmac_sha256(&key, &string_to_sign));
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key, scope, signed_headers, signature
+        )
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encode an object key as SigV4 expects, `/` is kept as the segment separator.
+fn uri_encode(key: &str) -> String {
+    let mut encoded = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::uri_encode;
+
+    #[test]
+    fn uri_encode_keeps_path_and_unreserved() {
+        assert_eq!(
+            uri_encode("fiber/mainnet/nodes/2025-01-01.csv"),
+            "fiber/mainnet/nodes/2025-01-01.csv"
+        );
+        assert_eq!(uri_encode("a b+c:d"), "a%20b%2Bc%3Ad");
+    }
+}
@@ -0,0 +1,181 @@
+use std::sync::LazyLock;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{Network, config};
+
+const FEED_WINDOW_DAYS: i64 = 30;
+const MAX_ENTRIES: usize = 100;
+
+const NODE_RECORD_TEMPLATE: &str = "New record: {count} nodes online on {net}";
+const NODE_RECORD_SUMMARY: &str =
+    "{count} nodes were online on {day}, up from the previous record of {previous}.";
+const CHANNEL_OPENED_TEMPLATE: &str = "Large channel opened: {capacity} CKB on {net}";
+const CHANNEL_CLOSED_TEMPLATE: &str = "Large channel closed: {capacity} CKB on {net}";
+const CHANNEL_SUMMARY: &str = "Channel {outpoint} with {capacity} CKB, state {state}.";
+
+/// Channels at or above this many CKB are reported as large opens / closes.
+static FEED_LARGE_CHANNEL_CKB: LazyLock<i64> =
+    LazyLock::new(|| config::parse_var("FEED_LARGE_CHANNEL_CKB").unwrap_or(10_000));
+
+#[derive(Debug)]
+pub struct Milestone {
+    id: String,
+    pub(crate) title: String,
+    summary: String,
+    pub(crate) updated: DateTime<Utc>,
+}
+
+fn render(template: &str, vars: &[(&str, String)]) -> String {
+    vars.iter().fold(template.to_string(), |acc, (key, value)| {
+        acc.replace(&format!("{{{}}}", key), value)
+    })
+}
+
+fn net_name(net: Network) -> &'static str {
+    match net {
+        Network::Mainnet => "mainnet",
+        Network::Testnet => "testnet",
+    }
+}
+
+/// Collect the milestones of the last [`FEED_WINDOW_DAYS`] days, newest first.
+pub async fn milestones(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<Vec<Milestone>, sqlx::Error> {
+    let since = Utc::now() - chrono::Duration::days(FEED_WINDOW_DAYS);
+    let mut milestones = Vec::new();
+
+    let records_sql = format!(
+        "SELECT day, nodes_count, prev_max FROM (
+            SELECT day, nodes_count,
+                max(nodes_count) OVER (ORDER BY day ROWS BETWEEN UNBOUNDED PRECEDING AND 1 PRECEDING) AS prev_max
+            FROM {}
+        ) t
+        WHERE prev_max IS NOT NULL AND nodes_count > prev_max AND day >= $1::date",
+        net.daily_summarized_data()
+    );
+    for row in sqlx::query(&records_sql)
+        .bind(since.date_naive())
+        .fetch_all(pool)
+        .await?
+    {
+        let day: NaiveDate = row.get("day");
+        let count: i32 = row.get("nodes_count");
+        let previous: i32 = row.get("prev_max");
+        let vars = [
+            ("net", net_name(net).to_string()),
+            ("count", count.to_string()),
+            ("previous", previous.to_string()),
+            ("day", day.to_string()),
+        ];
+        milestones.push(Milestone {
+            id: format!("node-record:{}", day),
+            title: render(NODE_RECORD_TEMPLATE, &vars),
+            summary: render(NODE_RECORD_SUMMARY, &vars),
+            updated: day.and_hms_opt(0, 0, 0).unwrap().and_utc(),
+        });
+    }
+
+    // capacity is a big endian u64 hex string of shannons
+    let channels_sql = format!(
+        "SELECT channel_outpoint, state, create_time, last_commit_time,
+            ('x' || capacity)::bit(64)::bigint / 100000000 AS capacity_ckb
+        FROM {}
+        WHERE udt_value IS NULL
+        AND ('x' || capacity)::bit(64)::bigint >= $2 * 100000000
+        AND (create_time >= $1 OR (state IN ('closed_cooperative', 'closed_uncooperative') AND last_commit_time >= $1))",
+        net.channel_states()
+    );
+    for row in sqlx::query(&channels_sql)
+        .bind(since)
+        .bind(*FEED_LARGE_CHANNEL_CKB)
+        .fetch_all(pool)
+        .await?
+    {
+        let outpoint: String = row.get("channel_outpoint");
+        let state: String = row.get("state");
+        let create_time: DateTime<Utc> = row.get("create_time");
+        let last_commit_time: DateTime<Utc> = row.get("last_commit_time");
+        let vars = [
+            ("net", net_name(net).to_string()),
+            ("capacity", row.get::<i64, _>("capacity_ckb").to_string()),
+            ("outpoint", format!("0x{}", outpoint)),
+            ("state", state.clone()),
+        ];
+        if create_time >= since {
+            milestones.push(Milestone {
+                id: format!("channel-opened:{}", outpoint),
+                title: render(CHANNEL_OPENED_TEMPLATE, &vars),
+                summary: render(CHANNEL_SUMMARY, &vars),
+                updated: create_time,
+            });
+        }
+        if state.starts_with("closed_")
+            && state != "closed_waiting_onchain_settlement"
+            && last_commit_time >= since
+        {
+            milestones.push(Milestone {
+                id: format!("channel-closed:{}", outpoint),
+                title: render(CHANNEL_CLOSED_TEMPLATE, &vars),
+                summary: render(CHANNEL_SUMMARY, &vars),
+                updated: last_commit_time,
+            });
+        }
+    }
+
+    milestones.sort_by_key(|m| std::cmp::Reverse(m.updated));
+    milestones.truncate(MAX_ENTRIES);
+    Ok(milestones)
+}
+
+pub(crate) fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Render milestones as an Atom 1.0 document.
+pub fn render_atom(net: Network, milestones: &[Milestone]) -> String {
+    let net = net_name(net);
+    let updated = milestones
+        .first()
+        .map(|m| m.updated)
+        .unwrap_or_else(Utc::now);
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <title>Fiber {} milestones</title>\n", net));
+    xml.push_str(&format!(
+        "  <id>urn:fiber-dashboard:{}:milestones</id>\n",
+        net
+    ));
+    xml.push_str(&format!("  <updated>{}</updated>\n", updated.to_rfc3339()));
+    for milestone in milestones {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!(
+            "    <id>urn:fiber-dashboard:{}:{}</id>\n",
+            net,
+            xml_escape(&milestone.id)
+        ));
+        xml.push_str(&format!(
+            "    <title>{}</title>\n",
+            xml_escape(&milestone.title)
+        ));
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            milestone.updated.to_rfc3339()
+        ));
+        xml.push_str(&format!(
+            "    <summary>{}</summary>\n",
+            xml_escape(&milestone.summary)
+        ));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
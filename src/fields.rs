@@ -0,0 +1,105 @@
+use std::collections::HashSet;
+
+use salvo::{Depot, FlowCtrl, Request, Response, handler, http::ResBody};
+use serde_json::Value;
+
+/// Response filter implementing `fields=a,b,c` sparse fieldsets for list endpoints.
+///
+/// Runs after the handler and trims every object of the listed entities (a top level array, or
+/// any array of objects in the top level object such as `nodes` / `channels`) down to the
+/// requested keys, paging metadata like `next_page` / `total_count` is kept as is.
+#[handler]
+pub async fn sparse_fields(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let fields = req.query::<String>("fields");
+    ctrl.call_next(req, depot, res).await;
+
+    let Some(fields) = fields else {
+        return;
+    };
+    let fields = fields
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .collect::<HashSet<_>>();
+    if fields.is_empty() {
+        return;
+    }
+    let ResBody::Once(bytes) = res.take_body() else {
+        return;
+    };
+    let filtered = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(mut value) => {
+            select_fields(&mut value, &fields);
+            serde_json::to_vec(&value).unwrap().into()
+        }
+        // not json, leave the body untouched
+        Err(_) => bytes,
+    };
+    res.body(ResBody::Once(filtered));
+}
+
+fn select_fields(value: &mut Value, fields: &HashSet<&str>) {
+    match value {
+        Value::Array(items) => retain_fields(items, fields),
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                if let Value::Array(items) = value {
+                    retain_fields(items, fields);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn retain_fields(items: &mut [Value], fields: &HashSet<&str>) {
+    for item in items {
+        if let Value::Object(map) = item {
+            map.retain(|key, _| fields.contains(key.as_str()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::select_fields;
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    #[test]
+    fn keeps_requested_fields_and_page_metadata() {
+        let mut page = json!({
+            "next_page": 1,
+            "total_count": 2,
+            "nodes": [
+                {"node_id": "0x01", "node_name": "a", "addresses": ["x"]},
+                {"node_id": "0x02", "node_name": "b", "addresses": []}
+            ]
+        });
+        let fields = HashSet::from(["node_id", "node_name"]);
+        select_fields(&mut page, &fields);
+        assert_eq!(
+            page,
+            json!({
+                "next_page": 1,
+                "total_count": 2,
+                "nodes": [
+                    {"node_id": "0x01", "node_name": "a"},
+                    {"node_id": "0x02", "node_name": "b"}
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn filters_top_level_arrays() {
+        let mut list = json!([{"channel_outpoint": "0x01", "capacity": "0x10"}]);
+        select_fields(&mut list, &HashSet::from(["capacity"]));
+        assert_eq!(list, json!([{"capacity": "0x10"}]));
+    }
+}
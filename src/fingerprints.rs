@@ -0,0 +1,169 @@
+//! Open channels grouped by the shape of their funding lock args.
+//!
+//! Implementations and wallets building the funding lock differently leave a mark in the
+//! length and leading bytes of its args, so the daily job counts the open channels of every
+//! `(script_version, args_len, prefix)` pattern into `daily_funding_fingerprints`, where
+//! `prefix` holds the first `FINGERPRINT_PREFIX_BYTES` (default 1) bytes of the args.
+
+use std::{collections::BTreeMap, sync::LazyLock};
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{Network, config, pg_read::day_window};
+
+/// Leading args bytes a pattern keeps, 0 groups by length alone.
+pub static FINGERPRINT_PREFIX_BYTES: LazyLock<usize> = LazyLock::new(|| {
+    config::parse_var("FINGERPRINT_PREFIX_BYTES")
+        .unwrap_or(1)
+        .min(32)
+});
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Pattern {
+    script_version: String,
+    args_len: usize,
+    /// Hex of the leading args bytes.
+    prefix: String,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Counts {
+    channels: u64,
+    unannounced: u64,
+    /// Shannons.
+    capacity: u64,
+}
+
+/// Pattern of the hex encoded funding args `args`.
+fn pattern(script_version: Option<String>, args: &str, prefix_bytes: usize) -> Pattern {
+    let args_len = args.len() / 2;
+    Pattern {
+        script_version: script_version.unwrap_or_else(|| "unknown".to_string()),
+        args_len,
+        prefix: args[..prefix_bytes.min(args_len) * 2].to_string(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Fingerprint {
+    pub day: NaiveDate,
+    pub script_version: String,
+    /// Funding lock args length in bytes.
+    pub args_len: i32,
+    /// Hex of the leading args bytes, empty when grouped by length alone.
+    pub prefix: String,
+    pub channels: i64,
+    /// Channels never announced in gossip.
+    pub unannounced: i64,
+    /// CKB.
+    pub capacity: i64,
+}
+
+/// Count the channels open now under each pattern as of `day`.
+pub async fn compute(
+    pool: &Pool<Postgres>,
+    net: Network,
+    day: NaiveDate,
+) -> Result<usize, sqlx::Error> {
+    let sql = format!(
+        "SELECT funding_args, script_version, capacity, unannounced FROM {} WHERE state = 'open'",
+        net.channel_states()
+    );
+    let mut patterns = BTreeMap::<Pattern, Counts>::new();
+    for row in sqlx::query(&sql).fetch_all(pool).await? {
+        let args: String = row.get("funding_args");
+        let capacity = {
+            let raw: String = row.get("capacity");
+            let mut buf = [0u8; 8];
+            faster_hex::hex_decode(raw.as_bytes(), &mut buf)
+                .map(|_| u64::from_be_bytes(buf))
+                .unwrap_or_default()
+        };
+        let counts = patterns
+            .entry(pattern(
+                row.get("script_version"),
+                &args,
+                *FINGERPRINT_PREFIX_BYTES,
+            ))
+            .or_default();
+        counts.channels += 1;
+        counts.unannounced += row.get::<bool, _>("unannounced") as u64;
+        counts.capacity += capacity;
+    }
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM daily_funding_fingerprints WHERE net = $1 AND day = $2")
+        .bind(net.name())
+        .bind(day)
+        .execute(&mut *tx)
+        .await?;
+    for (pattern, counts) in &patterns {
+        sqlx::query(
+            "INSERT INTO daily_funding_fingerprints
+            (net, day, script_version, args_len, prefix, channels, unannounced, capacity)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(net.name())
+        .bind(day)
+        .bind(&pattern.script_version)
+        .bind(pattern.args_len as i32)
+        .bind(&pattern.prefix)
+        .bind(counts.channels as i64)
+        .bind(counts.unannounced as i64)
+        .bind((counts.capacity / 100_000_000) as i64)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(patterns.len())
+}
+
+/// Patterns of the last `days` days, oldest first and largest first within a day.
+pub async fn load(
+    pool: &Pool<Postgres>,
+    net: Network,
+    days: i64,
+) -> Result<Vec<Fingerprint>, sqlx::Error> {
+    let (start, end) = day_window(days);
+    Ok(sqlx::query(
+        "SELECT day, script_version, args_len, prefix, channels, unannounced, capacity
+        FROM daily_funding_fingerprints
+        WHERE net = $1 AND day >= $2 AND day < $3
+        ORDER BY day, channels DESC, script_version, args_len, prefix",
+    )
+    .bind(net.name())
+    .bind(start.date_naive())
+    .bind(end.date_naive())
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| Fingerprint {
+        day: row.get("day"),
+        script_version: row.get("script_version"),
+        args_len: row.get("args_len"),
+        prefix: row.get("prefix"),
+        channels: row.get("channels"),
+        unannounced: row.get("unannounced"),
+        capacity: row.get("capacity"),
+    })
+    .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pattern;
+
+    #[test]
+    fn patterns_keep_length_and_leading_bytes() {
+        let args = "ab".repeat(20);
+        let p = pattern(Some("v2".to_string()), &args, 1);
+        assert_eq!((p.script_version.as_str(), p.args_len), ("v2", 20));
+        assert_eq!(p.prefix, "ab");
+        assert_eq!(pattern(None, &args, 0).prefix, "");
+        assert_eq!(pattern(None, &args, 0).script_version, "unknown");
+        // shorter args than the prefix keep all of it
+        assert_eq!(pattern(None, "0102", 4).prefix, "0102");
+    }
+}
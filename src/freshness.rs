@@ -0,0 +1,185 @@
+//! Staleness of the per network data, from the last successful cycle in `collector_runs`.
+//!
+//! When the last cycle of a network that committed both nodes and channels finished more than
+//! `DATA_STALE_AFTER_SECS` ago (2 hours by default, 0 turns the check off), data api responses
+//! carry `X-Data-Stale: true` and JSON object bodies gain `"stale": true`. Clients sending
+//! `Prefer: strict` get 503 with `Retry-After` instead of stale data. Replicas share the answer
+//! through the database and reload it within [`FRESHNESS_CACHE_TTL`].
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
+};
+
+use arc_swap::ArcSwapOption;
+use chrono::{DateTime, Utc};
+use salvo::{
+    Depot, FlowCtrl, Request, Response, handler,
+    http::{ResBody, StatusCode},
+};
+use serde_json::Value;
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{Network, config, get_pg_pool, http_server::request_network};
+
+/// Seconds without a successful collector cycle after which data counts as stale.
+pub static DATA_STALE_AFTER_SECS: LazyLock<i64> =
+    LazyLock::new(|| config::parse_var("DATA_STALE_AFTER_SECS").unwrap_or(2 * 60 * 60));
+
+const FRESHNESS_CACHE_TTL: Duration = Duration::from_secs(30);
+/// `Retry-After` of strict requests, about one collection interval.
+const STALE_RETRY_AFTER_SECS: u64 = 30 * 60;
+
+struct FreshnessCache {
+    loaded_at: Instant,
+    last_success: HashMap<Network, DateTime<Utc>>,
+}
+
+static FRESHNESS_CACHE: ArcSwapOption<FreshnessCache> = ArcSwapOption::const_empty();
+
+/// Finish time of the last cycle of each network that committed both nodes and channels.
+pub async fn last_success(
+    pool: &Pool<Postgres>,
+) -> Result<HashMap<Network, DateTime<Utc>>, sqlx::Error> {
+    Ok(sqlx::query(
+        "SELECT net, max(finished_at) AS finished_at FROM collector_runs
+        WHERE nodes_ok AND channels_ok GROUP BY net",
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .filter_map(|row| Some((Network::parse(row.get("net"))?, row.get("finished_at"))))
+    .collect())
+}
+
+/// Cached answer, a failed reload keeps serving and retries on the next request.
+async fn current(pool: &Pool<Postgres>) -> Option<Arc<FreshnessCache>> {
+    let cached = FRESHNESS_CACHE.load_full();
+    if let Some(cache) = &cached
+        && cache.loaded_at.elapsed() < FRESHNESS_CACHE_TTL
+    {
+        return cached;
+    }
+    match last_success(pool).await {
+        Ok(last_success) => {
+            let cache = Arc::new(FreshnessCache {
+                loaded_at: Instant::now(),
+                last_success,
+            });
+            FRESHNESS_CACHE.store(Some(cache.clone()));
+            Some(cache)
+        }
+        Err(e) => {
+            log::error!("Failed to load last collector runs: {}", e);
+            cached
+        }
+    }
+}
+
+/// Whether data last collected at `last_success` is stale at `now`, a network never
+/// collected successfully is.
+fn is_stale(
+    last_success: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    stale_after_secs: i64,
+) -> bool {
+    stale_after_secs > 0
+        && last_success.is_none_or(|time| (now - time).num_seconds() > stale_after_secs)
+}
+
+/// Whether a `Prefer` header asks for the `strict` preference, among others or with
+/// parameters.
+fn prefers_strict(prefer: &str) -> bool {
+    prefer.split(',').any(|preference| {
+        preference
+            .split(';')
+            .next()
+            .is_some_and(|token| token.trim().eq_ignore_ascii_case("strict"))
+    })
+}
+
+/// Flag responses of a network whose collector fell behind, or answer 503 to `Prefer: strict`.
+#[handler]
+pub async fn flag_stale_data(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    // unknown networks are answered by `require_enabled_network`
+    let Ok(net) = request_network(req).await else {
+        ctrl.call_next(req, depot, res).await;
+        return;
+    };
+    let stale = match current(get_pg_pool()).await {
+        Some(cache) => is_stale(
+            cache.last_success.get(&net).copied(),
+            Utc::now(),
+            *DATA_STALE_AFTER_SECS,
+        ),
+        None => false,
+    };
+    res.add_header("vary", "prefer", false).ok();
+    if !stale {
+        ctrl.call_next(req, depot, res).await;
+        return;
+    }
+
+    if req
+        .headers()
+        .get_all("prefer")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(prefers_strict)
+    {
+        res.status_code(StatusCode::SERVICE_UNAVAILABLE);
+        res.add_header("retry-after", STALE_RETRY_AFTER_SECS, true)
+            .ok();
+        res.add_header("preference-applied", "strict", true).ok();
+        res.render(format!("Data of {} is stale", net.name()));
+        ctrl.skip_rest();
+        return;
+    }
+    ctrl.call_next(req, depot, res).await;
+    res.add_header("x-data-stale", "true", true).ok();
+    if res
+        .status_code
+        .is_some_and(|status| status != StatusCode::OK)
+    {
+        return;
+    }
+    let ResBody::Once(bytes) = res.take_body() else {
+        return;
+    };
+    let flagged = match serde_json::from_slice::<Value>(&bytes) {
+        Ok(Value::Object(mut map)) => {
+            map.insert("stale".to_string(), Value::Bool(true));
+            serde_json::to_vec(&map).unwrap().into()
+        }
+        // arrays and other bodies only carry the header
+        _ => bytes,
+    };
+    res.body(ResBody::Once(flagged));
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::{is_stale, prefers_strict};
+
+    #[test]
+    fn stale_after_the_threshold_and_strict_among_preferences() {
+        let now = Utc::now();
+        assert!(!is_stale(Some(now - Duration::seconds(60)), now, 3600));
+        assert!(is_stale(Some(now - Duration::seconds(3601)), now, 3600));
+        assert!(is_stale(None, now, 3600));
+        assert!(!is_stale(None, now, 0));
+
+        assert!(prefers_strict("strict"));
+        assert!(prefers_strict("return=minimal, Strict; foo=1"));
+        assert!(!prefers_strict("lenient"));
+        assert!(!prefers_strict("strictly"));
+    }
+}
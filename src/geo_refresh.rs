@@ -0,0 +1,122 @@
+//! Background refresh of node locations.
+//!
+//! Collectors look every ip up once and reuse the answer for as long as they run, while the
+//! addresses behind it move. The hourly `geo_refresh` job looks ips resolved more than
+//! `GEO_REFRESH_DAYS` days ago up again, at most `GEO_REFRESH_LOOKUPS_PER_HOUR` of them so the
+//! ipinfo quota is left to new nodes. When a location changed, the rows of the online nodes
+//! announcing that ip are updated, later snapshots pick the new answer up from the cache.
+//! Every run is recorded in `geo_refresh_runs`.
+
+use std::sync::LazyLock;
+
+use chrono::{DateTime, Utc};
+use ipinfo::IpDetails;
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+
+use crate::{ENABLED_NETWORKS, config, ip_location, pg_read::online_since};
+
+/// Days after which a lookup is refreshed.
+pub static GEO_REFRESH_DAYS: LazyLock<i64> =
+    LazyLock::new(|| config::parse_var("GEO_REFRESH_DAYS").unwrap_or(30).max(1));
+
+/// Lookups one hourly run may spend.
+pub static GEO_REFRESH_LOOKUPS_PER_HOUR: LazyLock<usize> =
+    LazyLock::new(|| config::parse_var("GEO_REFRESH_LOOKUPS_PER_HOUR").unwrap_or(50));
+
+#[derive(Debug, Default, Serialize)]
+pub struct RefreshSummary {
+    /// Stale ips picked for this run.
+    pub due: usize,
+    pub refreshed: usize,
+    /// Refreshed ips whose location changed.
+    pub changed: usize,
+    pub failed: usize,
+    /// `node_infos` rows moved to the new location.
+    pub rows_updated: u64,
+}
+
+fn location(details: &IpDetails) -> [&str; 4] {
+    [
+        details.country.as_str(),
+        details.city.as_str(),
+        details.region.as_str(),
+        details.loc.as_str(),
+    ]
+}
+
+/// Move the online rows announcing `ip` that still carry the `old` location to `new`.
+async fn update_nodes(
+    pool: &Pool<Postgres>,
+    ip: &str,
+    old: &IpDetails,
+    new: &IpDetails,
+) -> Result<u64, sqlx::Error> {
+    let mut updated = 0;
+    for &net in ENABLED_NETWORKS.iter() {
+        let sql = format!(
+            "UPDATE {} SET country_or_region = $1, city = $2, region = $3, loc = $4
+            WHERE time >= $5 AND (addresses LIKE $6 OR addresses LIKE $7)
+            AND country_or_region IS NOT DISTINCT FROM $8 AND city IS NOT DISTINCT FROM $9
+            AND region IS NOT DISTINCT FROM $10 AND loc IS NOT DISTINCT FROM $11",
+            net.node_infos()
+        );
+        let [country, city, region, loc] = location(new);
+        let [old_country, old_city, old_region, old_loc] = location(old);
+        updated += sqlx::query(&sql)
+            .bind(country)
+            .bind(city)
+            .bind(region)
+            .bind(loc)
+            .bind(online_since())
+            .bind(format!("%/ip4/{}/%", ip))
+            .bind(format!("%/ip6/{}/%", ip))
+            .bind(old_country)
+            .bind(old_city)
+            .bind(old_region)
+            .bind(old_loc)
+            .execute(pool)
+            .await?
+            .rows_affected();
+    }
+    Ok(updated)
+}
+
+/// Refresh the stalest lookups within the hourly budget.
+pub async fn run(pool: &Pool<Postgres>) -> Result<RefreshSummary, sqlx::Error> {
+    let started_at: DateTime<Utc> = Utc::now();
+    let due = ip_location::stale_ips(
+        started_at - chrono::Duration::days(*GEO_REFRESH_DAYS),
+        *GEO_REFRESH_LOOKUPS_PER_HOUR,
+    );
+    let mut summary = RefreshSummary {
+        due: due.len(),
+        ..Default::default()
+    };
+    for (ip, old) in due {
+        // a failed lookup keeps its old answer and stays first in line for the next run
+        let Ok(new) = ip_location::refresh(&ip).await else {
+            summary.failed += 1;
+            continue;
+        };
+        summary.refreshed += 1;
+        if location(&old.details) != location(&new) {
+            summary.changed += 1;
+            summary.rows_updated += update_nodes(pool, &ip, &old.details, &new).await?;
+        }
+    }
+    sqlx::query(
+        "INSERT INTO geo_refresh_runs
+            (started_at, finished_at, due, refreshed, changed, failed, rows_updated)
+        VALUES ($1, now(), $2, $3, $4, $5, $6)",
+    )
+    .bind(started_at)
+    .bind(summary.due as i32)
+    .bind(summary.refreshed as i32)
+    .bind(summary.changed as i32)
+    .bind(summary.failed as i32)
+    .bind(summary.rows_updated as i64)
+    .execute(pool)
+    .await?;
+    Ok(summary)
+}
@@ -0,0 +1,176 @@
+//! Http caching of the per network data apis.
+//!
+//! `Last-Modified` is the time of the last collector snapshot or aggregate refresh of the
+//! requested `net`, every response of the same network changes at most then, so clients and
+//! CDNs can revalidate with `If-Modified-Since` and get a 304 without a database query.
+//! HEAD and plain OPTIONS are answered from those headers without running the handler.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use salvo::{
+    Depot, FlowCtrl, Request, Response, handler,
+    http::{Method, StatusCode},
+    routing::PathState,
+};
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{
+    Network, config,
+    events::{self, Event},
+};
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// `max-age` of data api responses, roughly the collection interval.
+pub(crate) static HTTP_CACHE_MAX_AGE_SECS: LazyLock<u64> =
+    LazyLock::new(|| config::parse_var("HTTP_CACHE_MAX_AGE_SECS").unwrap_or(60));
+
+static LAST_REFRESH: LazyLock<Mutex<HashMap<Network, DateTime<Utc>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Method a request was sent with, when [`head_as_get`] routed it as GET.
+#[derive(Debug, Clone)]
+struct OriginalMethod(Method);
+
+pub fn last_refresh(net: Network) -> Option<DateTime<Utc>> {
+    LAST_REFRESH.lock().unwrap().get(&net).copied()
+}
+
+fn record_refresh(net: Network, time: DateTime<Utc>) {
+    let mut last = LAST_REFRESH.lock().unwrap();
+    let entry = last.entry(net).or_insert(time);
+    *entry = (*entry).max(time);
+}
+
+/// Follow collector refreshes, seeded with the last successful collector run of each network.
+pub async fn track_refreshes(pool: &'static Pool<Postgres>) {
+    let mut rx = events::subscribe();
+    match sqlx::query(
+        "SELECT net, max(finished_at) AS finished_at FROM collector_runs
+        WHERE nodes_ok AND channels_ok GROUP BY net",
+    )
+    .fetch_all(pool)
+    .await
+    {
+        Ok(rows) => {
+            for row in rows {
+                if let Some(net) = Network::parse(row.get("net")) {
+                    record_refresh(net, row.get("finished_at"));
+                }
+            }
+        }
+        Err(e) => log::warn!("Failed to load last collector runs: {}", e),
+    }
+    loop {
+        match rx.recv().await {
+            Ok(Event::SnapshotCommitted { net, time, .. })
+            | Ok(Event::AggregatesRefreshed { net, time }) => record_refresh(net, time),
+            Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Router filter letting HEAD and OPTIONS requests match GET routes, the request is routed as
+/// GET and [`cache_headers`] answers it without calling the handler. CORS preflights are left
+/// to the cors handler.
+pub fn head_as_get(req: &mut Request, _state: &mut PathState) -> bool {
+    let preflight = req.headers().contains_key("access-control-request-method");
+    if matches!(*req.method(), Method::HEAD | Method::OPTIONS) && !preflight {
+        let method = req.method().clone();
+        req.extensions_mut().insert(OriginalMethod(method));
+        *req.method_mut() = Method::GET;
+    }
+    true
+}
+
+pub fn http_date(time: DateTime<Utc>) -> String {
+    time.format(HTTP_DATE).to_string()
+}
+
+/// Whether a response last modified at `last_modified` is still the one the client has,
+/// http dates have second precision.
+pub fn not_modified(last_modified: DateTime<Utc>, if_modified_since: &str) -> bool {
+    NaiveDateTime::parse_from_str(if_modified_since, HTTP_DATE)
+        .is_ok_and(|since| last_modified.timestamp() <= since.and_utc().timestamp())
+}
+
+/// `Cache-Control` and `Last-Modified` for GET responses, 304 for unchanged conditional GETs.
+#[handler]
+pub async fn cache_headers(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let original = req
+        .extensions()
+        .get::<OriginalMethod>()
+        .map(|m| m.0.clone());
+    if original == Some(Method::OPTIONS) {
+        res.status_code(StatusCode::NO_CONTENT);
+        res.add_header("allow", "GET, HEAD, OPTIONS", true).ok();
+        ctrl.skip_rest();
+        return;
+    }
+    let net = req
+        .query::<String>("net")
+        .and_then(|name| Network::parse(&name))
+        .unwrap_or_default();
+    let last_modified = last_refresh(net);
+    let cache_control = format!("public, max-age={}", *HTTP_CACHE_MAX_AGE_SECS);
+
+    if let Some(last_modified) = last_modified {
+        res.add_header("last-modified", http_date(last_modified), true)
+            .ok();
+        if req
+            .header::<String>("if-modified-since")
+            .is_some_and(|since| not_modified(last_modified, &since))
+        {
+            res.status_code(StatusCode::NOT_MODIFIED);
+            res.add_header("cache-control", &cache_control, true).ok();
+            ctrl.skip_rest();
+            return;
+        }
+    }
+    if original == Some(Method::HEAD) {
+        res.status_code(StatusCode::OK);
+        res.add_header("cache-control", &cache_control, true).ok();
+        ctrl.skip_rest();
+        return;
+    }
+
+    ctrl.call_next(req, depot, res).await;
+    if res
+        .status_code
+        .is_none_or(|status| status == StatusCode::OK)
+    {
+        res.add_header("cache-control", &cache_control, true).ok();
+    } else {
+        res.headers_mut().remove("last-modified");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{http_date, not_modified};
+
+    #[test]
+    fn revalidates_with_second_precision() {
+        let time = Utc.with_ymd_and_hms(2025, 3, 1, 8, 30, 0).unwrap();
+        let date = http_date(time);
+        assert_eq!(date, "Sat, 01 Mar 2025 08:30:00 GMT");
+        assert!(not_modified(
+            time + chrono::Duration::milliseconds(400),
+            &date
+        ));
+        assert!(!not_modified(time + chrono::Duration::seconds(1), &date));
+        assert!(!not_modified(time, "yesterday"));
+    }
+}
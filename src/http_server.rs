@@ -1,19 +1,34 @@
-use chrono::{DateTime, NaiveDate, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
 use ckb_jsonrpc_types::{JsonBytes, Script};
-use salvo::{Depot, Request, Response, handler, macros::Extractible};
+use salvo::{
+    Depot, FlowCtrl, Request, Response, handler,
+    http::StatusCode,
+    macros::Extractible,
+    sse::{SseEvent, SseKeepAlive},
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    Network, get_pg_pool,
+    Network,
+    error::skip_malformed,
+    feed, fingerprints, get_pg_pool,
+    http_cache::HTTP_CACHE_MAX_AGE_SECS,
     pg_read::{
-        AnalysisParams, ChannelInfo, HourlyNodeInfo, group_channel_by_state,
-        group_channel_count_by_state, query_analysis, query_analysis_hourly,
-        query_channel_capacity_distribution, query_channel_count_by_asset, query_channel_info,
-        query_channel_state, query_channels_by_node_id, query_node_info, query_nodes_by_region,
-        query_nodes_fuzzy_by_name, read_channels_hourly, read_channels_monthly, read_nodes_hourly,
-        read_nodes_monthly,
+        AnalysisParams, ChannelInfo, HourlyChannelInfoDBRead, HourlyNodeInfo, HourlyNodeInfoDBRead,
+        OnlineMetric, cached_regions, group_channel_by_state, group_channel_count_by_state,
+        hot_snapshot, is_ready, query_analysis, query_analysis_hourly,
+        query_auto_accept_distribution, query_capacity_histogram_series,
+        query_channel_capacity_distribution, query_channel_count_by_asset, query_channel_state,
+        query_channels_by_node_id, query_disabled_channels, query_geo_capacity, query_ipv6_stats,
+        query_name_collisions, query_node_channel_stats, query_node_churn, query_nodes_by_region,
+        query_nodes_fuzzy_by_name, query_nodes_ungeolocated, query_online_series, query_port_usage,
+        query_private_channel_estimate, query_snapshot_hours, query_tlc_params_overview,
+        query_udt_issuer_stats, query_udt_trend, range_days, read_channels_monthly,
+        read_nodes_monthly, span_days, span_hours,
     },
     pg_write::DBState,
+    storage::{Paged, storage},
+    upstream,
 };
 
 #[derive(Debug, Extractible, Serialize, Deserialize)]
@@ -25,6 +40,72 @@ pub(crate) struct Page {
     pub(crate) start: Option<NaiveDate>,
     pub(crate) end: Option<NaiveDate>,
     pub(crate) page_size: Option<usize>,
+    /// `channels_hourly` only: channels with endpoints in this `country_or_region`.
+    pub(crate) country: Option<String>,
+    /// `channels_hourly` only: channels with endpoints in this region.
+    pub(crate) region: Option<String>,
+    #[serde(default, rename = "match")]
+    #[salvo(extract(rename = "match"))]
+    pub(crate) endpoint_match: EndpointMatch,
+    /// Channel lists only: capacity bounds in shannons, inclusive.
+    pub(crate) min_capacity: Option<u64>,
+    pub(crate) max_capacity: Option<u64>,
+    /// Channel lists only: udt name, `ckb` for plain ckb channels.
+    pub(crate) udt: Option<String>,
+    /// `channels_hourly` only: rewind to the finalized hour containing this time.
+    pub(crate) as_of: Option<DateTime<Utc>>,
+}
+
+impl Page {
+    pub(crate) fn has_location_filter(&self) -> bool {
+        self.country.is_some() || self.region.is_some()
+    }
+
+    /// Whether a node in `country` / `region` passes the location filters.
+    pub(crate) fn location_matches(&self, country: Option<&str>, region: Option<&str>) -> bool {
+        self.country
+            .as_deref()
+            .is_none_or(|wanted| country == Some(wanted))
+            && self
+                .region
+                .as_deref()
+                .is_none_or(|wanted| region == Some(wanted))
+    }
+
+    /// Capacity bounds as stored in `channel_states`, fixed width big endian hex, so they
+    /// compare like the numbers.
+    pub(crate) fn capacity_bounds_hex(&self) -> (Option<String>, Option<String>) {
+        let hex = |capacity: u64| faster_hex::hex_string(&capacity.to_be_bytes());
+        (self.min_capacity.map(hex), self.max_capacity.map(hex))
+    }
+
+    /// Whether a channel passes the capacity and udt filters.
+    pub(crate) fn channel_matches(&self, capacity: u64, udt_name: Option<&str>) -> bool {
+        self.min_capacity.is_none_or(|min| capacity >= min)
+            && self.max_capacity.is_none_or(|max| capacity <= max)
+            && self
+                .udt
+                .as_deref()
+                .is_none_or(|udt| udt_name.unwrap_or("ckb") == udt)
+    }
+}
+
+/// Which endpoints of a channel have to pass the location filters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointMatch {
+    #[default]
+    Any,
+    Both,
+}
+
+impl EndpointMatch {
+    pub(crate) fn combine(self, node1: bool, node2: bool) -> bool {
+        match self {
+            EndpointMatch::Any => node1 || node2,
+            EndpointMatch::Both => node1 && node2,
+        }
+    }
 }
 
 #[derive(Debug, Extractible, Serialize, Deserialize)]
@@ -65,14 +146,34 @@ pub(crate) struct NodeByRegion {
     #[serde(default)]
     pub(crate) sort_by: ListNodesHourlySortBy,
     pub(crate) page_size: Option<usize>,
+    /// Rewind to the finalized hour containing this time, the live data when omitted.
+    pub(crate) as_of: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Extractible, Serialize, Deserialize)]
 #[salvo(extract(default_source(from = "body")))]
-struct NodesByUdt {
-    udt: Script,
+pub(crate) struct NodesByUdt {
+    /// One of `udt`, `udt_info_id` or `symbol` names the UDT, a symbol every UDT announced
+    /// under that name.
+    pub(crate) udt: Option<Script>,
+    pub(crate) udt_info_id: Option<i32>,
+    pub(crate) symbol: Option<String>,
     #[serde(default)]
-    net: Network,
+    pub(crate) net: Network,
+    #[serde(default)]
+    pub(crate) page: usize,
+    pub(crate) page_size: Option<usize>,
+    /// Only nodes of this country or region.
+    pub(crate) region: Option<String>,
+    /// Also list offline nodes, with the last hour they were seen and no channels.
+    #[serde(default)]
+    pub(crate) include_offline: bool,
+    #[serde(default)]
+    pub(crate) order: Order,
+    #[serde(default)]
+    pub(crate) sort_by: ListNodesHourlySortBy,
+    /// Rewind to the finalized hour containing this time, listing the nodes of that hour.
+    pub(crate) as_of: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Extractible, Serialize, Deserialize)]
@@ -82,18 +183,96 @@ struct NetworkInfo {
     net: Network,
 }
 
+/// `net` of a json body, for post apis.
+#[derive(Debug, Deserialize)]
+struct BodyNetwork {
+    net: Option<String>,
+}
+
+/// Network a data api request asks for, from `net` in the query or a POST body, the default
+/// network without one. An unknown name is returned as the error.
+pub(crate) async fn request_network(req: &mut Request) -> Result<Network, String> {
+    let name = match req.query::<String>("net") {
+        Some(name) => Some(name),
+        None if req.method() == salvo::http::Method::POST => req
+            .parse_json::<BodyNetwork>()
+            .await
+            .ok()
+            .and_then(|body| body.net),
+        None => None,
+    };
+    match name {
+        Some(name) => Network::parse(&name).ok_or(name),
+        None => Ok(Network::default()),
+    }
+}
+
+/// Reject requests for an unknown network with 400 and for a network this deployment does not
+/// serve with 404, instead of answering from its empty tables. Requests without `net` ask for
+/// the default network.
+#[handler]
+pub async fn require_enabled_network(
+    req: &mut Request,
+    _depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    match request_network(req).await {
+        Ok(net) if !net.enabled() => {
+            res.status_code(StatusCode::NOT_FOUND);
+            res.render(format!("Network {} is not enabled", net.name()));
+            ctrl.skip_rest();
+        }
+        Ok(_) => {}
+        Err(name) => {
+            res.status_code(StatusCode::BAD_REQUEST);
+            res.render(format!("Unknown network {}", name));
+            ctrl.skip_rest();
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
-struct NodePage {
-    next_page: usize,
-    nodes: Vec<HourlyNodeInfo>,
-    total_count: usize,
+pub struct NodePage {
+    pub next_page: usize,
+    pub nodes: Vec<HourlyNodeInfo>,
+    pub total_count: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct ChannelPage {
-    next_page: usize,
-    channels: Vec<ChannelInfo>,
-    total_count: usize,
+pub struct ChannelPage {
+    pub next_page: usize,
+    pub channels: Vec<ChannelInfo>,
+    pub total_count: usize,
+}
+
+impl NodePage {
+    /// Page of `nodes` with their staleness as of now, snapshot items are older than the request.
+    fn new((mut nodes, next_page, total_count): Paged<HourlyNodeInfo>) -> Self {
+        let now = Utc::now();
+        nodes
+            .iter_mut()
+            .for_each(|node| node.refresh_staleness(now));
+        NodePage {
+            next_page,
+            nodes,
+            total_count,
+        }
+    }
+}
+
+impl ChannelPage {
+    fn new((mut channels, next_page, total_count): Paged<ChannelInfo>) -> Self {
+        let now = Utc::now();
+        channels
+            .iter_mut()
+            .for_each(|channel| channel.refresh_staleness(now));
+        ChannelPage {
+            next_page,
+            channels,
+            total_count,
+        }
+    }
 }
 
 #[derive(Debug, Extractible, Serialize, Deserialize)]
@@ -107,6 +286,8 @@ pub(crate) struct ListNodesHourlyParams {
     #[serde(default)]
     pub(crate) sort_by: ListNodesHourlySortBy,
     pub(crate) page_size: Option<usize>,
+    /// Rewind to the finalized hour containing this time, the live data when omitted.
+    pub(crate) as_of: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -121,7 +302,13 @@ pub(crate) enum ListNodesHourlySortBy {
 }
 
 impl ListNodesHourlySortBy {
-    pub fn as_str(&self) -> &str {
+    pub(crate) const ALL: [ListNodesHourlySortBy; 3] = [
+        ListNodesHourlySortBy::Region,
+        ListNodesHourlySortBy::LastSeen,
+        ListNodesHourlySortBy::ChannelCount,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
         match self {
             ListNodesHourlySortBy::Region => "country_or_region",
             ListNodesHourlySortBy::LastSeen => "last_seen_hour",
@@ -137,16 +324,11 @@ pub async fn list_nodes_hourly(
     _res: &mut Response,
 ) -> Result<String, salvo::Error> {
     let params = req.extract::<ListNodesHourlyParams>(depot).await?;
-    let pool = get_pg_pool();
-    let nodes = read_nodes_hourly(pool, params).await.map_err(|e| {
+    let nodes = storage().nodes_hourly(params).await.map_err(|e| {
         log::error!("Failed to read nodes: {}", e);
         salvo::Error::Io(std::io::Error::other("Failed to read nodes"))
     })?;
-    Ok(serde_json::to_string(&NodePage {
-        next_page: nodes.1,
-        nodes: nodes.0,
-        total_count: nodes.2,
-    })?)
+    Ok(serde_json::to_string(&NodePage::new(nodes))?)
 }
 
 #[handler]
@@ -161,11 +343,7 @@ pub async fn list_nodes_monthly(
         log::error!("Failed to read nodes: {}", e);
         salvo::Error::Io(std::io::Error::other("Failed to read nodes"))
     })?;
-    Ok(serde_json::to_string(&NodePage {
-        next_page: nodes.1,
-        nodes: nodes.0,
-        total_count: nodes.2,
-    })?)
+    Ok(serde_json::to_string(&NodePage::new(nodes))?)
 }
 
 #[handler]
@@ -181,11 +359,7 @@ pub async fn nodes_fuzzy_by_name_or_id(
         log::error!("Failed to query nodes by name or id: {}", e);
         salvo::Error::Io(std::io::Error::other("Failed to query nodes by name or id"))
     })?;
-    Ok(serde_json::to_string(&NodePage {
-        next_page: nodes.1,
-        nodes: nodes.0,
-        total_count: nodes.2,
-    })?)
+    Ok(serde_json::to_string(&NodePage::new(nodes))?)
 }
 
 #[handler]
@@ -200,11 +374,7 @@ pub async fn nodes_by_region(
         log::error!("Failed to query nodes by region: {}", e);
         salvo::Error::Io(std::io::Error::other("Failed to query nodes by region"))
     })?;
-    Ok(serde_json::to_string(&NodePage {
-        next_page: nodes.1,
-        nodes: nodes.0,
-        total_count: nodes.2,
-    })?)
+    Ok(serde_json::to_string(&NodePage::new(nodes))?)
 }
 
 #[handler]
@@ -213,17 +383,15 @@ pub async fn list_channels_hourly(
     depot: &mut Depot,
     _res: &mut Response,
 ) -> Result<String, salvo::Error> {
-    let page = req.extract::<Page>(depot).await?;
-    let pool = get_pg_pool();
-    let channels = read_channels_hourly(pool, page).await.map_err(|e| {
+    let mut page = req.extract::<Page>(depot).await?;
+    page.country = page.country.filter(|country| !country.is_empty());
+    page.region = page.region.filter(|region| !region.is_empty());
+    page.udt = page.udt.filter(|udt| !udt.is_empty());
+    let channels = storage().channels_hourly(page).await.map_err(|e| {
         log::error!("Failed to read channels: {}", e);
         salvo::Error::Io(std::io::Error::other("Failed to read channels"))
     })?;
-    Ok(serde_json::to_string(&ChannelPage {
-        next_page: channels.1,
-        channels: channels.0,
-        total_count: channels.2,
-    })?)
+    Ok(serde_json::to_string(&ChannelPage::new(channels))?)
 }
 
 #[handler]
@@ -232,18 +400,15 @@ pub async fn list_channels_monthly(
     depot: &mut Depot,
     _res: &mut Response,
 ) -> Result<String, salvo::Error> {
-    let page = req.extract::<Page>(depot).await?;
+    let mut page = req.extract::<Page>(depot).await?;
+    page.udt = page.udt.filter(|udt| !udt.is_empty());
     let pool = get_pg_pool();
 
     let channels = read_channels_monthly(pool, page).await.map_err(|e| {
         log::error!("Failed to read channels: {}", e);
         salvo::Error::Io(std::io::Error::other("Failed to read channels"))
     })?;
-    Ok(serde_json::to_string(&ChannelPage {
-        next_page: channels.1,
-        channels: channels.0,
-        total_count: channels.2,
-    })?)
+    Ok(serde_json::to_string(&ChannelPage::new(channels))?)
 }
 
 #[derive(Debug, Extractible, Serialize, Deserialize)]
@@ -324,8 +489,8 @@ pub async fn node_info(
     _res: &mut Response,
 ) -> Result<String, salvo::Error> {
     let node_id = req.extract::<NodeId>(depot).await?;
-    let pool = get_pg_pool();
-    let info = query_node_info(pool, node_id.node_id, node_id.net)
+    let info = storage()
+        .node_info(node_id.node_id, node_id.net)
         .await
         .map_err(|e| {
             log::error!("Failed to query node info: {}", e);
@@ -338,17 +503,30 @@ pub async fn node_info(
 pub async fn nodes_by_udt(
     req: &mut Request,
     depot: &mut Depot,
-    _res: &mut Response,
+    res: &mut Response,
 ) -> Result<String, salvo::Error> {
-    let udt = req.extract::<NodesByUdt>(depot).await?;
+    let mut params = req.extract::<NodesByUdt>(depot).await?;
+    params.region = params.region.filter(|region| !region.is_empty());
+    params.symbol = params.symbol.filter(|symbol| !symbol.is_empty());
+    if params.udt.is_none() && params.udt_info_id.is_none() && params.symbol.is_none() {
+        res.status_code(StatusCode::BAD_REQUEST);
+        return Ok(String::new());
+    }
     let pool = get_pg_pool();
-    let nodes = crate::pg_read::query_nodes_by_udt(pool, udt.udt, udt.net)
-        .await
-        .map_err(|e| {
+    let nodes = match crate::pg_read::query_nodes_by_udt(pool, params).await {
+        Ok(nodes) => nodes,
+        Err(sqlx::Error::RowNotFound) => {
+            res.status_code(StatusCode::NOT_FOUND);
+            return Ok(String::new());
+        }
+        Err(e) => {
             log::error!("Failed to query nodes by UDT: {}", e);
-            salvo::Error::Io(std::io::Error::other("Failed to query nodes by UDT"))
-        })?;
-    Ok(serde_json::json!({ "nodes": nodes }).to_string())
+            return Err(salvo::Error::Io(std::io::Error::other(
+                "Failed to query nodes by UDT",
+            )));
+        }
+    };
+    Ok(serde_json::to_string(&NodePage::new(nodes))?)
 }
 
 #[derive(Debug, Extractible, Serialize, Deserialize)]
@@ -396,6 +574,8 @@ pub async fn analysis(
 #[derive(Debug, Extractible, Serialize, Deserialize)]
 #[salvo(extract(default_source(from = "query")))]
 struct ChannelId {
+    /// Molecule encoded hex or `0x<tx_hash>:<index>`.
+    #[serde(deserialize_with = "crate::outpoint::deserialize")]
     channel_outpoint: JsonBytes,
     #[serde(default)]
     net: Network,
@@ -425,14 +605,17 @@ pub async fn channel_info(
     _res: &mut Response,
 ) -> Result<String, salvo::Error> {
     let channel_id = req.extract::<ChannelId>(depot).await?;
-    let pool = get_pg_pool();
-    let info = query_channel_info(pool, channel_id.channel_outpoint, channel_id.net)
+    let info = storage()
+        .channel_info(channel_id.channel_outpoint, channel_id.net)
         .await
         .map_err(|e| {
             log::error!("Failed to query channel info: {}", e);
             salvo::Error::Io(std::io::Error::other("Failed to query channel info"))
         })?;
-    Ok(serde_json::json!({ "channel_info": info }).to_string())
+    let explorer = info
+        .as_ref()
+        .and_then(|info| crate::explorer::links(channel_id.net, &info.channel_outpoint, None));
+    Ok(serde_json::json!({ "channel_info": info, "explorer": explorer }).to_string())
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -501,7 +684,9 @@ pub(crate) enum Order {
 }
 
 impl Order {
-    pub fn as_str(&self) -> &str {
+    pub(crate) const ALL: [Order; 2] = [Order::Asc, Order::Desc];
+
+    pub fn as_str(&self) -> &'static str {
         match self {
             Order::Asc => "ASC",
             Order::Desc => "DESC",
@@ -586,6 +771,9 @@ pub async fn all_region(
     _res: &mut Response,
 ) -> Result<String, salvo::Error> {
     let network_info = req.extract::<NetworkInfo>(depot).await?;
+    if let Some(regions) = cached_regions(network_info.net) {
+        return Ok(regions.as_ref().clone());
+    }
     let pool = get_pg_pool();
     let regions = crate::pg_read::query_nodes_all_regions(pool, network_info.net)
         .await
@@ -595,3 +783,1103 @@ pub async fn all_region(
         })?;
     Ok(regions)
 }
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct EventsFilter {
+    net: Option<Network>,
+}
+
+#[handler]
+pub async fn event_stream(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let filter = req.extract::<EventsFilter>(depot).await?;
+    let rx = crate::events::subscribe();
+    let stream = futures::stream::unfold(rx, move |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => {
+                    if filter.net.is_some() && event.net().is_some() && event.net() != filter.net {
+                        continue;
+                    }
+                    let sse = SseEvent::default().text(serde_json::to_string(&event).unwrap());
+                    return Some((Ok::<_, std::convert::Infallible>(sse), rx));
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("Events subscriber lagged, {} events dropped", n);
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    SseKeepAlive::new(stream).stream(res);
+    Ok(())
+}
+
+#[handler]
+pub async fn milestone_feed(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let network_info = req.extract::<NetworkInfo>(depot).await?;
+    let pool = get_pg_pool();
+    let milestones = feed::milestones(pool, network_info.net)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to get milestones: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to get milestones"))
+        })?;
+    res.add_header("content-type", "application/atom+xml; charset=utf-8", true)?;
+    res.write_body(feed::render_atom(network_info.net, &milestones))?;
+    Ok(())
+}
+
+/// Weekly reports of the network, newest first.
+#[handler]
+pub async fn list_reports(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<NetworkInfo>(depot).await?;
+    let reports = crate::reports::list(get_pg_pool(), params.net)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to list reports: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to list reports"))
+        })?;
+    Ok(serde_json::to_string(&reports)?)
+}
+
+/// The HTML page of the weekly report starting on the `{week}` Monday.
+#[handler]
+pub async fn weekly_report(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    let params = req.extract::<NetworkInfo>(depot).await?;
+    let Some(week) = req
+        .param::<String>("week")
+        .and_then(|week| week.parse::<NaiveDate>().ok())
+    else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(());
+    };
+    let html = crate::reports::load(get_pg_pool(), params.net, week)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to load report of {}: {}", week, e);
+            salvo::Error::Io(std::io::Error::other("Failed to load report"))
+        })?;
+    let Some(html) = html else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(());
+    };
+    res.add_header("content-type", "text/html; charset=utf-8", true)?;
+    res.add_header("cache-control", IMMUTABLE_CACHE_CONTROL, true)
+        .ok();
+    res.write_body(html)?;
+    Ok(())
+}
+
+#[handler]
+pub async fn graph_snapshot(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let network_info = req.extract::<NetworkInfo>(depot).await?;
+    let Some(snapshot) = hot_snapshot(network_info.net) else {
+        res.status_code(salvo::http::StatusCode::SERVICE_UNAVAILABLE);
+        return Ok("Graph snapshot is not loaded yet".to_string());
+    };
+    Ok(serde_json::to_string(&snapshot.graph())?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct GraphBackboneParams {
+    #[serde(default)]
+    net: Network,
+    /// Largest channels of every node kept besides the spanning forest.
+    #[serde(default)]
+    top_k: usize,
+}
+
+/// The online graph reduced to its maximum capacity spanning forest and the `top_k` largest
+/// channels of every node, in the `graph_snapshot` format.
+#[handler]
+pub async fn graph_backbone(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<GraphBackboneParams>(depot).await?;
+    let Some(snapshot) = hot_snapshot(params.net) else {
+        res.status_code(salvo::http::StatusCode::SERVICE_UNAVAILABLE);
+        return Ok("Graph snapshot is not loaded yet".to_string());
+    };
+    Ok(serde_json::to_string(&snapshot.backbone(params.top_k))?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct ChangesParams {
+    #[serde(default)]
+    net: Network,
+    since_cursor: Option<String>,
+    /// Changes returned, 1000 by default and at most 10000.
+    limit: Option<i64>,
+}
+
+/// Node, channel and channel state changes after `since_cursor`, for incremental mirrors.
+#[handler]
+pub async fn changes(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<ChangesParams>(depot).await?;
+    let limit = params.limit.unwrap_or(1000).clamp(1, 10000);
+    match crate::changes::load(
+        get_pg_pool(),
+        params.net,
+        params.since_cursor.as_deref(),
+        limit,
+    )
+    .await
+    {
+        Ok(page) => Ok(serde_json::to_string(&page)?),
+        Err(crate::changes::LoadError::InvalidCursor) => {
+            res.status_code(StatusCode::BAD_REQUEST);
+            Ok("Invalid since_cursor".to_string())
+        }
+        Err(crate::changes::LoadError::Expired) => {
+            res.status_code(StatusCode::GONE);
+            Ok(
+                "Changes after since_cursor have been pruned, resync from the hourly lists"
+                    .to_string(),
+            )
+        }
+        Err(crate::changes::LoadError::Db(e)) => {
+            log::error!("Failed to load changes: {}", e);
+            Err(salvo::Error::Io(std::io::Error::other(
+                "Failed to load changes",
+            )))
+        }
+    }
+}
+
+/// Latency, error rate and circuit breaker state of every CKB and Fiber rpc endpoint.
+#[handler]
+pub async fn upstream_status(
+    _req: &mut Request,
+    _depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let status = upstream::status(get_pg_pool()).await.map_err(|e| {
+        log::error!("Failed to read upstream status: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to read upstream status"))
+    })?;
+    Ok(serde_json::to_string(&status)?)
+}
+
+/// Open channels per funding script version, to follow script upgrades.
+#[handler]
+pub async fn script_versions(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<NetworkInfo>(depot).await?;
+    let report = crate::script_versions::open_channels_by_version(get_pg_pool(), params.net)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to count channels by script version: {}", e);
+            salvo::Error::Io(std::io::Error::other(
+                "Failed to count channels by script version",
+            ))
+        })?;
+    Ok(serde_json::to_string(&report)?)
+}
+
+/// Online nodes without a country and why their addresses could not be located.
+#[handler]
+pub async fn nodes_ungeolocated(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<NetworkInfo>(depot).await?;
+    let nodes = query_nodes_ungeolocated(get_pg_pool(), params.net)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query ungeolocated nodes: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query ungeolocated nodes"))
+        })?;
+    Ok(serde_json::to_string(&nodes)?)
+}
+
+/// Names shared by several online nodes, with the nodes announcing them.
+#[handler]
+pub async fn name_collisions(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<NetworkInfo>(depot).await?;
+    let collisions = query_name_collisions(get_pg_pool(), params.net)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query node name collisions: {}", e);
+            salvo::Error::Io(std::io::Error::other(
+                "Failed to query node name collisions",
+            ))
+        })?;
+    Ok(serde_json::to_string(&collisions)?)
+}
+
+/// Daily share of nodes announcing ipv4 only, ipv6 only or both over `range` (`1M` by default).
+#[handler]
+pub async fn ipv6_stats(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<RangeParams>(depot).await?;
+    let days = range_days(params.range.as_deref().unwrap_or_default());
+    let stats = query_ipv6_stats(get_pg_pool(), params.net, days)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query ipv6 stats: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query ipv6 stats"))
+        })?;
+    Ok(serde_json::to_string(&stats)?)
+}
+
+/// Default and custom tcp ports announced by the online nodes.
+#[handler]
+pub async fn port_usage(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<NetworkInfo>(depot).await?;
+    let usage = query_port_usage(get_pg_pool(), params.net)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query port usage: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query port usage"))
+        })?;
+    Ok(serde_json::to_string(&usage)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct UdtTrendParams {
+    #[serde(default)]
+    net: Network,
+    /// Every UDT when missing.
+    udt_info_id: Option<i32>,
+    range: Option<String>,
+}
+
+/// Daily nodes supporting and channels denominated in each UDT over `range` (`1M` by default).
+#[handler]
+pub async fn udt_trend(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<UdtTrendParams>(depot).await?;
+    let days = range_days(params.range.as_deref().unwrap_or_default());
+    let trend = query_udt_trend(get_pg_pool(), params.net, params.udt_info_id, days)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query udt trend: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query udt trend"))
+        })?;
+    Ok(serde_json::to_string(&trend)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct CorrelateParams {
+    #[serde(default)]
+    net: Network,
+    x: crate::correlation::CorrelateMetric,
+    y: crate::correlation::CorrelateMetric,
+    range: Option<String>,
+}
+
+/// Daily metrics `x` and `y` over `range` (`1M` by default) aligned by day, with their
+/// correlation coefficient and scatter points.
+#[handler]
+pub async fn analysis_correlate(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<CorrelateParams>(depot).await?;
+    let end = Utc::now().date_naive();
+    let start =
+        end - chrono::Duration::days(range_days(params.range.as_deref().unwrap_or_default()));
+    let correlation =
+        crate::correlation::load(get_pg_pool(), params.net, params.x, params.y, start, end)
+            .await
+            .map_err(|e| {
+                log::error!(
+                    "Failed to correlate {:?} and {:?}: {}",
+                    params.x,
+                    params.y,
+                    e
+                );
+                salvo::Error::Io(std::io::Error::other("Failed to correlate analysis series"))
+            })?;
+    Ok(serde_json::to_string(&correlation)?)
+}
+
+/// Histogram and percentiles of the auto accept funding thresholds of the online nodes.
+#[handler]
+pub async fn auto_accept_distribution(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<NetworkInfo>(depot).await?;
+    let distribution = query_auto_accept_distribution(get_pg_pool(), params.net)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query auto accept distribution: {}", e);
+            salvo::Error::Io(std::io::Error::other(
+                "Failed to query auto accept distribution",
+            ))
+        })?;
+    Ok(serde_json::to_string(&distribution)?)
+}
+
+/// Online channels and capacity per UDT issuer script, for issuers watching their liquidity.
+#[handler]
+pub async fn udt_issuer_stats(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<NetworkInfo>(depot).await?;
+    let stats = query_udt_issuer_stats(get_pg_pool(), params.net)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query udt issuer stats: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query udt issuer stats"))
+        })?;
+    Ok(serde_json::to_string(&stats)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct TlcParamsOverviewParams {
+    #[serde(default)]
+    net: Network,
+    /// Drill down into one node's directions.
+    #[serde(alias = "pubkey")]
+    node_id: Option<JsonBytes>,
+}
+
+/// Tlc expiry delta and minimum value across enabled channel directions, with the directions
+/// below the safe expiry delta.
+#[handler]
+pub async fn tlc_params_overview(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<TlcParamsOverviewParams>(depot).await?;
+    let overview = query_tlc_params_overview(get_pg_pool(), params.net, params.node_id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query tlc params overview: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query tlc params overview"))
+        })?;
+    Ok(serde_json::to_string(&overview)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct DisabledChannelsParams {
+    #[serde(default)]
+    net: Network,
+    /// Minimum hours a direction has been disabled, 24 by default.
+    hours: Option<i64>,
+}
+
+/// Online channels with one or both directions disabled for a while, idle liquidity.
+#[handler]
+pub async fn disabled_channels(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<DisabledChannelsParams>(depot).await?;
+    let hours = params.hours.unwrap_or(24).max(0);
+    let channels = query_disabled_channels(get_pg_pool(), params.net, hours)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query disabled channels: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query disabled channels"))
+        })?;
+    Ok(serde_json::to_string(&channels)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct NodeChannelStatsParams {
+    #[serde(default)]
+    net: Network,
+    #[serde(alias = "pubkey")]
+    node_id: JsonBytes,
+}
+
+/// Channels of a node per on-chain state and its closed capacity per month.
+#[handler]
+pub async fn node_channel_stats(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<NodeChannelStatsParams>(depot).await?;
+    let stats = query_node_channel_stats(get_pg_pool(), params.net, params.node_id)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query node channel stats: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query node channel stats"))
+        })?;
+    Ok(serde_json::to_string(&stats)?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct GeoCapacityParams {
+    #[serde(default)]
+    net: Network,
+    /// Decimals `loc` is rounded to, 1 by default.
+    precision: Option<i32>,
+}
+
+/// Node count and channel capacity per rounded location, for sizing map markers.
+#[handler]
+pub async fn geo_capacity(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<GeoCapacityParams>(depot).await?;
+    let precision = params.precision.unwrap_or(1).clamp(0, 4);
+    let clusters = query_geo_capacity(get_pg_pool(), params.net, precision)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query geo capacity: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query geo capacity"))
+        })?;
+    Ok(serde_json::to_string(&clusters)?)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SurvivalReport {
+    pub net: Network,
+    pub computed_at: Option<DateTime<Utc>>,
+    pub cohorts: Vec<crate::survival::CohortSurvival>,
+}
+
+/// Survival curves per cohort month as of the last daily run.
+#[handler]
+pub async fn channel_survival(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<NetworkInfo>(depot).await?;
+    let (computed_at, curves) = crate::survival::load(get_pg_pool(), params.net)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to load channel survival: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to load channel survival"))
+        })?;
+    Ok(serde_json::to_string(&SurvivalReport {
+        net: params.net,
+        computed_at,
+        cohorts: curves,
+    })?)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KpiReport {
+    pub net: Network,
+    pub computed_at: Option<DateTime<Utc>>,
+    /// `None` until the first daily summary of the network.
+    pub kpis: Option<crate::kpis::Kpis>,
+}
+
+/// Node count, channel count, capacity and median channel size with their 7 and 30 day
+/// changes, as of the last daily run.
+#[handler]
+pub async fn kpis(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<NetworkInfo>(depot).await?;
+    let loaded = crate::kpis::load(get_pg_pool(), params.net)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to load kpis: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to load kpis"))
+        })?;
+    res.add_header(
+        "cache-control",
+        format!("public, max-age={}", *HTTP_CACHE_MAX_AGE_SECS),
+        true,
+    )
+    .ok();
+    let (computed_at, values) = loaded.unzip();
+    Ok(serde_json::to_string(&KpiReport {
+        net: params.net,
+        computed_at,
+        kpis: values,
+    })?)
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CohortMetric {
+    #[default]
+    Nodes,
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct CohortParams {
+    #[serde(default)]
+    net: Network,
+    #[serde(default)]
+    metric: CohortMetric,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CohortReport {
+    pub net: Network,
+    pub metric: CohortMetric,
+    pub computed_at: Option<DateTime<Utc>>,
+    pub cohorts: Vec<crate::cohorts::Cohort>,
+}
+
+/// Retention matrix per monthly cohort as of the last monthly run.
+#[handler]
+pub async fn cohorts(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<CohortParams>(depot).await?;
+    let (computed_at, matrix) = match params.metric {
+        CohortMetric::Nodes => crate::cohorts::load(get_pg_pool(), params.net).await,
+    }
+    .map_err(|e| {
+        log::error!("Failed to load cohorts: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to load cohorts"))
+    })?;
+    Ok(serde_json::to_string(&CohortReport {
+        net: params.net,
+        metric: params.metric,
+        computed_at,
+        cohorts: matrix,
+    })?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct NodeRankingParams {
+    #[serde(default)]
+    net: Network,
+    #[serde(default)]
+    metric: crate::rankings::RankingMetric,
+    /// Top nodes returned, 100 by default and at most 1000.
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeRankingReport {
+    pub net: Network,
+    pub metric: crate::rankings::RankingMetric,
+    pub computed_at: Option<DateTime<Utc>>,
+    pub nodes: Vec<crate::rankings::NodeRanking>,
+}
+
+/// Top nodes by capacity, channel count or weighted pagerank as of the last hourly run.
+#[handler]
+pub async fn node_rankings(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<NodeRankingParams>(depot).await?;
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    let (computed_at, nodes) =
+        crate::rankings::load(get_pg_pool(), params.net, params.metric, limit)
+            .await
+            .map_err(|e| {
+                log::error!("Failed to load node rankings: {}", e);
+                salvo::Error::Io(std::io::Error::other("Failed to load node rankings"))
+            })?;
+    Ok(serde_json::to_string(&NodeRankingReport {
+        net: params.net,
+        metric: params.metric,
+        computed_at,
+        nodes,
+    })?)
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct RangeParams {
+    #[serde(default)]
+    net: Network,
+    range: Option<String>,
+}
+
+/// Daily counts of new, returning and disappearing nodes over `range` (`1M` by default).
+#[handler]
+pub async fn churn(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<RangeParams>(depot).await?;
+    let days = range_days(params.range.as_deref().unwrap_or_default());
+    let counts = query_node_churn(get_pg_pool(), params.net, days)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query node churn: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query node churn"))
+        })?;
+    Ok(serde_json::to_string(&counts)?)
+}
+
+/// Hours kept by the hourly continuous aggregates.
+const MAX_ONLINE_SERIES_HOURS: i64 = 365 * 24;
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct OnlineSeriesParams {
+    #[serde(default)]
+    net: Network,
+    #[serde(default)]
+    metric: OnlineMetric,
+    range: Option<String>,
+    bucket: Option<String>,
+}
+
+/// Online nodes or channels per `bucket` (`1h` by default) over `range` (`7d` by default).
+#[handler]
+pub async fn online_series(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<OnlineSeriesParams>(depot).await?;
+    let range_hours = params
+        .range
+        .as_deref()
+        .and_then(span_hours)
+        .unwrap_or(7 * 24)
+        .min(MAX_ONLINE_SERIES_HOURS);
+    let bucket_hours = params
+        .bucket
+        .as_deref()
+        .and_then(span_hours)
+        .unwrap_or(1)
+        .min(range_hours);
+    let series = query_online_series(
+        get_pg_pool(),
+        params.net,
+        params.metric,
+        range_hours,
+        bucket_hours,
+    )
+    .await
+    .map_err(|e| {
+        log::error!("Failed to query online series: {}", e);
+        salvo::Error::Io(std::io::Error::other("Failed to query online series"))
+    })?;
+    Ok(serde_json::to_string(&series)?)
+}
+
+/// Per day capacity histograms of every asset over `range` (`30d` by default).
+#[handler]
+pub async fn capacity_histogram_series(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<RangeParams>(depot).await?;
+    let days = span_days(params.range.as_deref());
+    let series = query_capacity_histogram_series(get_pg_pool(), params.net, days)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query capacity histograms: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query capacity histograms"))
+        })?;
+    Ok(serde_json::to_string(&series)?)
+}
+
+/// Funding cells found on chain against the channels announced in gossip per day over `range`
+/// (`30d` by default).
+#[handler]
+pub async fn private_channel_estimate(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<RangeParams>(depot).await?;
+    let days = span_days(params.range.as_deref());
+    let estimate = query_private_channel_estimate(get_pg_pool(), params.net, days)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query private channel estimate: {}", e);
+            salvo::Error::Io(std::io::Error::other(
+                "Failed to query private channel estimate",
+            ))
+        })?;
+    Ok(serde_json::to_string(&estimate)?)
+}
+
+/// Open channels per funding args pattern per day over `range` (`30d` by default).
+#[handler]
+pub async fn implementation_fingerprints(
+    req: &mut Request,
+    depot: &mut Depot,
+    _res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<RangeParams>(depot).await?;
+    let days = span_days(params.range.as_deref());
+    let fingerprints = fingerprints::load(get_pg_pool(), params.net, days)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query funding fingerprints: {}", e);
+            salvo::Error::Io(std::io::Error::other(
+                "Failed to query funding fingerprints",
+            ))
+        })?;
+    Ok(serde_json::to_string(&fingerprints)?)
+}
+
+/// Hour segment of snapshot urls, e.g. `2025-03-01T08`.
+const SNAPSHOT_HOUR_FORMAT: &str = "%Y-%m-%dT%H";
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+fn parse_snapshot_hour(hour: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(
+        &format!("{}:00", hour),
+        &format!("{}:%M", SNAPSHOT_HOUR_FORMAT),
+    )
+    .ok()
+    .map(|hour| hour.and_utc())
+}
+
+/// Enabled network and finalized hour of a snapshot url, `None` answers 404.
+fn snapshot_path(req: &Request) -> Option<(Network, DateTime<Utc>)> {
+    let net = Network::parse(&req.param::<String>("net")?).filter(Network::enabled)?;
+    let hour = parse_snapshot_hour(&req.param::<String>("hour")?)?;
+    let current_hour = parse_snapshot_hour(&Utc::now().format(SNAPSHOT_HOUR_FORMAT).to_string())?;
+    (hour < current_hour).then_some((net, hour))
+}
+
+#[derive(Debug, Extractible, Serialize, Deserialize)]
+#[salvo(extract(default_source(from = "query")))]
+struct SnapshotIndexParams {
+    range: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotIndex {
+    pub net: Network,
+    /// Newest first, each served at `/snapshots/{net}/{hour}/nodes.json` and `channels.json`.
+    pub hours: Vec<String>,
+}
+
+/// Finalized hours over `range` (`1M` by default) with an immutable snapshot.
+#[handler]
+pub async fn snapshot_hours(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let params = req.extract::<SnapshotIndexParams>(depot).await?;
+    let Some(net) = req
+        .param::<String>("net")
+        .and_then(|net| Network::parse(&net))
+        .filter(Network::enabled)
+    else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(String::new());
+    };
+    let days = range_days(params.range.as_deref().unwrap_or_default());
+    let hours = query_snapshot_hours(get_pg_pool(), net, days)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to query snapshot hours: {}", e);
+            salvo::Error::Io(std::io::Error::other("Failed to query snapshot hours"))
+        })?;
+    res.add_header(
+        "cache-control",
+        format!("public, max-age={}", *HTTP_CACHE_MAX_AGE_SECS),
+        true,
+    )
+    .ok();
+    Ok(serde_json::to_string(&SnapshotIndex {
+        net,
+        hours: hours
+            .into_iter()
+            .map(|hour| hour.format(SNAPSHOT_HOUR_FORMAT).to_string())
+            .collect(),
+    })?)
+}
+
+/// Nodes online in one finalized hour, never changes once served.
+#[handler]
+pub async fn snapshot_nodes(
+    req: &mut Request,
+    _depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let Some((net, hour)) = snapshot_path(req) else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(String::new());
+    };
+    let nodes = HourlyNodeInfoDBRead::fetch_hour(get_pg_pool(), net, hour)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to read nodes snapshot of {}: {}", hour, e);
+            salvo::Error::Io(std::io::Error::other("Failed to read nodes snapshot"))
+        })?;
+    if nodes.is_empty() {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(String::new());
+    }
+    let nodes = nodes
+        .into_iter()
+        .filter_map(|node| skip_malformed("node", node))
+        .collect::<Vec<HourlyNodeInfo>>();
+    res.add_header("cache-control", IMMUTABLE_CACHE_CONTROL, true)
+        .ok();
+    Ok(serde_json::to_string(&nodes)?)
+}
+
+/// Channels online in one finalized hour, never changes once served.
+#[handler]
+pub async fn snapshot_channels(
+    req: &mut Request,
+    _depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let Some((net, hour)) = snapshot_path(req) else {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(String::new());
+    };
+    let channels = HourlyChannelInfoDBRead::fetch_hour(get_pg_pool(), net, hour)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to read channels snapshot of {}: {}", hour, e);
+            salvo::Error::Io(std::io::Error::other("Failed to read channels snapshot"))
+        })?;
+    if channels.is_empty() {
+        res.status_code(StatusCode::NOT_FOUND);
+        return Ok(String::new());
+    }
+    let channels = channels
+        .into_iter()
+        .filter_map(|channel| skip_malformed("channel", channel))
+        .collect::<Vec<ChannelInfo>>();
+    res.add_header("cache-control", IMMUTABLE_CACHE_CONTROL, true)
+        .ok();
+    Ok(serde_json::to_string(&channels)?)
+}
+
+/// Readiness gate for load balancers, 503 until every in-memory cache has been loaded.
+#[handler]
+pub async fn readyz(res: &mut Response) -> &'static str {
+    if is_ready() {
+        "ok"
+    } else {
+        res.status_code(salvo::http::StatusCode::SERVICE_UNAVAILABLE);
+        "warming up"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use salvo::{
+        Router, Service,
+        test::{ResponseExt, TestClient},
+    };
+    use serde_json::Value;
+
+    use super::{
+        channel_info, list_channels_hourly, list_nodes_hourly, node_info, parse_snapshot_hour,
+    };
+    use crate::{
+        Network,
+        pg_write::{ChannelInfoDBSchema, NodeInfoDBSchema},
+        storage::{MemoryStorage, SnapshotBatch, install, storage},
+    };
+
+    fn node_id(n: u8) -> String {
+        format!("02{}", faster_hex::hex_string(&[n; 32]))
+    }
+
+    fn outpoint(n: u8) -> String {
+        faster_hex::hex_string(&[n; 36])
+    }
+
+    fn node(n: u8, region: &str) -> NodeInfoDBSchema {
+        NodeInfoDBSchema {
+            node_name: format!("node{}", n),
+            addresses: "[]".to_string(),
+            node_id: node_id(n),
+            announce_timestamp: Utc::now(),
+            chain_hash: "00".repeat(32),
+            auto_accept_min_ckb_funding_amount: "00".repeat(8),
+            country_or_region: region.to_string(),
+            city: String::new(),
+            region: String::new(),
+            loc: String::new(),
+            address_scope: crate::ip_location::AddressScope::Global,
+        }
+    }
+
+    fn channel(n: u8, node1: u8, node2: u8, capacity: u128) -> ChannelInfoDBSchema {
+        ChannelInfoDBSchema {
+            channel_outpoint: outpoint(n),
+            node1: node_id(node1),
+            node2: node_id(node2),
+            capacity: faster_hex::hex_string(&capacity.to_be_bytes()),
+            chain_hash: "00".repeat(32),
+            udt_type_script: None,
+            created_timestamp: Utc::now(),
+            update_of_node1_timestamp: None,
+            update_of_node1_enabled: None,
+            update_of_node1_outbound_liquidity: None,
+            update_of_node1_tlc_expiry_delta: None,
+            update_of_node1_tlc_minimum_value: None,
+            update_of_node1_fee_rate: None,
+            update_of_node2_timestamp: None,
+            update_of_node2_enabled: None,
+            update_of_node2_outbound_liquidity: None,
+            update_of_node2_tlc_expiry_delta: None,
+            update_of_node2_tlc_minimum_value: None,
+            update_of_node2_fee_rate: None,
+        }
+    }
+
+    async fn get(service: &Service, path: &str) -> Value {
+        TestClient::get(format!("http://127.0.0.1{}", path))
+            .send(service)
+            .await
+            .take_json::<Value>()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn hourly_lists_are_served_from_storage() {
+        assert!(install(Box::new(MemoryStorage::default())));
+        let nodes = [node(1, "HK"), node(2, "US"), node(3, "US")];
+        let channels = [channel(1, 1, 2, 100), channel(2, 1, 3, 200)];
+        storage()
+            .insert_batch(SnapshotBatch {
+                net: Network::Mainnet,
+                time: &Utc::now(),
+                udt_infos: &[],
+                udt_dep_relations: &[],
+                udt_node_relations: &[],
+                nodes: &nodes,
+                channels: &channels,
+            })
+            .await
+            .unwrap();
+
+        let service = Service::new(
+            Router::new()
+                .push(Router::with_path("nodes_hourly").get(list_nodes_hourly))
+                .push(Router::with_path("channels_hourly").get(list_channels_hourly))
+                .push(Router::with_path("node_info").get(node_info))
+                .push(Router::with_path("channel_info").get(channel_info)),
+        );
+
+        let page = get(
+            &service,
+            "/nodes_hourly?page=0&page_size=2&sort_by=channel_count&order=desc",
+        )
+        .await;
+        assert_eq!(page["total_count"], 3);
+        assert_eq!(page["next_page"], 1);
+        assert_eq!(page["nodes"][0]["node_id"], format!("0x{}", node_id(1)));
+        assert_eq!(page["nodes"][0]["channel_count"], 2);
+        // ties on channel_count are broken by node_id
+        assert_eq!(page["nodes"][1]["node_id"], format!("0x{}", node_id(2)));
+
+        let page = get(&service, "/channels_hourly?page=0").await;
+        assert_eq!(page["total_count"], 2);
+        assert_eq!(page["channels"][0]["is_stale"], false);
+        assert_eq!(
+            page["channels"][0]["channel_outpoint"],
+            format!("0x{}", outpoint(2))
+        );
+
+        let page = get(&service, "/channels_hourly?page=0&country=US").await;
+        assert_eq!(page["total_count"], 2);
+        let page = get(&service, "/channels_hourly?page=0&country=HK&match=both").await;
+        assert_eq!(page["total_count"], 0);
+        let page = get(&service, "/channels_hourly?page=0&country=JP").await;
+        assert_eq!(page["total_count"], 0);
+        let page = get(&service, "/channels_hourly?page=0&min_capacity=150&udt=ckb").await;
+        assert_eq!(page["total_count"], 1);
+        assert_eq!(
+            page["channels"][0]["channel_outpoint"],
+            format!("0x{}", outpoint(2))
+        );
+        let page = get(&service, "/channels_hourly?page=0&max_capacity=99").await;
+        assert_eq!(page["total_count"], 0);
+        let page = get(&service, "/channels_hourly?page=0&udt=RUSD").await;
+        assert_eq!(page["total_count"], 0);
+
+        let info = get(&service, &format!("/node_info?node_id=0x{}", node_id(3))).await;
+        assert_eq!(info["node_info"]["country_or_region"], "US");
+        let info = get(
+            &service,
+            &format!("/node_info?node_id=0x{}&net=testnet", node_id(3)),
+        )
+        .await;
+        assert!(info["node_info"].is_null());
+
+        let info = get(
+            &service,
+            &format!("/channel_info?channel_outpoint=0x{}", outpoint(1)),
+        )
+        .await;
+        assert_eq!(info["channel_info"]["node2"], format!("0x{}", node_id(2)));
+    }
+
+    #[test]
+    fn snapshot_hours_are_utc_hours() {
+        let hour = parse_snapshot_hour("2025-03-01T08").unwrap();
+        assert_eq!(hour.to_rfc3339(), "2025-03-01T08:00:00+00:00");
+        assert!(parse_snapshot_hour("2025-03-01").is_none());
+        assert!(parse_snapshot_hour("2025-03-01T24").is_none());
+    }
+}
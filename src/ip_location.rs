@@ -1,56 +1,82 @@
-use std::{collections::HashMap, sync::OnceLock};
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::IpAddr,
+    sync::{LazyLock, Mutex},
+};
 
-use ipinfo::{IpDetails, IpError, IpInfo};
+use chrono::{DateTime, Utc};
+use ipinfo::{BatchReqOpts, IpDetails, IpError, IpInfo, IpInfoConfig};
+use multiaddr::Multiaddr;
+use serde::Serialize;
 
-#[allow(static_mut_refs)]
-fn ipinfo_cache() -> &'static mut HashMap<String, IpDetails> {
-    static mut IPINFO_CACHE: OnceLock<HashMap<String, IpDetails>> = OnceLock::new();
+use crate::{config, pg_write::multiaddr_to_socketaddr};
 
-    // Safety: only one thread can access here
-    unsafe {
-        IPINFO_CACHE.get_or_init(Default::default);
-        IPINFO_CACHE.get_mut().unwrap()
-    }
+/// A lookup result and when it was made.
+#[derive(Clone)]
+pub(crate) struct Resolved {
+    pub details: IpDetails,
+    pub resolved_at: DateTime<Utc>,
 }
 
-#[allow(static_mut_refs)]
-fn ipinfo() -> &'static mut IpInfo {
-    static mut IPINFO: OnceLock<IpInfo> = OnceLock::new();
+fn ipinfo_cache() -> &'static Mutex<HashMap<String, Resolved>> {
+    static IPINFO_CACHE: LazyLock<Mutex<HashMap<String, Resolved>>> =
+        LazyLock::new(Default::default);
+    &IPINFO_CACHE
+}
 
-    // Safety: only one thread can access here
-    unsafe {
-        IPINFO.get_or_init(|| {
-            let ipinfo_io_token = match ::std::env::var("IPINFO_IO_TOKEN") {
-                Ok(token) if !token.is_empty() => Some(token),
-                _ => {
-                    log::warn!("Miss environment variable \"IPINFO_IO_TOKEN\", use empty value");
-                    None
-                }
-            };
-            ipinfo::IpInfo::new(ipinfo::IpInfoConfig {
-                token: ipinfo_io_token,
-                cache_size: 10000,
-                ..Default::default()
-            })
-            .expect("Connect to https://ipinfo.io")
-        });
-        IPINFO.get_mut().unwrap()
+fn ipinfo_config(cache_size: usize) -> IpInfoConfig {
+    let ipinfo_io_token = config::var("IPINFO_IO_TOKEN");
+    if ipinfo_io_token.is_none() {
+        log::warn!("Miss setting \"IPINFO_IO_TOKEN\", use empty value");
+    }
+    IpInfoConfig {
+        token: ipinfo_io_token,
+        cache_size,
+        ..Default::default()
     }
 }
 
-pub async fn lookup_ipinfo(ip: &str) -> Result<IpDetails, IpError> {
-    let global_ipinfo_cache = ipinfo_cache();
+/// Client of the lookups, shared by the collectors of every network.
+fn ipinfo() -> &'static tokio::sync::Mutex<IpInfo> {
+    static IPINFO: LazyLock<tokio::sync::Mutex<IpInfo>> = LazyLock::new(|| {
+        tokio::sync::Mutex::new(
+            ipinfo::IpInfo::new(ipinfo_config(10000)).expect("Connect to https://ipinfo.io"),
+        )
+    });
+    &IPINFO
+}
+
+/// Client of the refresh job, without a cache of its own that would hand back the answers
+/// being refreshed.
+fn refresh_ipinfo() -> &'static tokio::sync::Mutex<IpInfo> {
+    static IPINFO: LazyLock<tokio::sync::Mutex<IpInfo>> = LazyLock::new(|| {
+        tokio::sync::Mutex::new(
+            ipinfo::IpInfo::new(ipinfo_config(1)).expect("Connect to https://ipinfo.io"),
+        )
+    });
+    &IPINFO
+}
 
-    if let Some(ipdetails) = global_ipinfo_cache.get(&ip.to_string()) {
-        return Ok(ipdetails.clone());
+/// Answer for `ip` without a request, `None` until it has been looked up.
+pub fn cached_ipinfo(ip: &str) -> Option<IpDetails> {
+    ipinfo_cache()
+        .lock()
+        .unwrap()
+        .get(ip)
+        .map(|resolved| resolved.details.clone())
+}
+
+pub async fn lookup_ipinfo(ip: &str) -> Result<IpDetails, IpError> {
+    let cached = ipinfo_cache().lock().unwrap().get(ip).cloned();
+    if let Some(resolved) = cached {
+        return Ok(resolved.details);
     }
 
-    let lookup_info = ipinfo().lookup(ip).await;
+    let lookup_info = ipinfo().lock().await.lookup(ip).await;
     match lookup_info {
         Ok(ipdetails) => {
-            global_ipinfo_cache.insert(ip.to_string(), ipdetails.to_owned());
-
-            Ok(ipdetails.to_owned())
+            cache(ip, &ipdetails);
+            Ok(ipdetails)
         }
         Err(err) => {
             log::warn!("IPINFO.lookup(\"{}\"), error: {}", ip, err);
@@ -58,3 +84,194 @@ pub async fn lookup_ipinfo(ip: &str) -> Result<IpDetails, IpError> {
         }
     }
 }
+
+/// Look the uncached ones of `ips` up with batch requests of up to 1000 ips each, the answers
+/// are cached for [`lookup_ipinfo`]. Returns how many were resolved.
+pub async fn lookup_ipinfo_batch(ips: impl IntoIterator<Item = IpAddr>) -> usize {
+    let missing = {
+        let cache = ipinfo_cache().lock().unwrap();
+        ips.into_iter()
+            .map(|ip| ip.to_string())
+            .filter(|ip| !cache.contains_key(ip))
+            .collect::<BTreeSet<_>>()
+    };
+    if missing.is_empty() {
+        return 0;
+    }
+    let ips = missing.iter().map(String::as_str).collect::<Vec<_>>();
+    match ipinfo()
+        .lock()
+        .await
+        .lookup_batch(&ips, BatchReqOpts::default())
+        .await
+    {
+        Ok(found) => {
+            for (ip, details) in &found {
+                cache(ip, details);
+            }
+            found.len()
+        }
+        Err(err) => {
+            log::warn!("IPINFO.lookup_batch({} ips), error: {}", ips.len(), err);
+            0
+        }
+    }
+}
+
+fn cache(ip: &str, details: &IpDetails) {
+    ipinfo_cache().lock().unwrap().insert(
+        ip.to_string(),
+        Resolved {
+            details: details.clone(),
+            resolved_at: Utc::now(),
+        },
+    );
+}
+
+/// Up to `limit` cached ips resolved before `before`, oldest first.
+pub(crate) fn stale_ips(before: DateTime<Utc>, limit: usize) -> Vec<(String, Resolved)> {
+    let mut stale = ipinfo_cache()
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, resolved)| resolved.resolved_at < before)
+        .map(|(ip, resolved)| (ip.clone(), resolved.clone()))
+        .collect::<Vec<_>>();
+    stale.sort_by_key(|(_, resolved)| resolved.resolved_at);
+    stale.truncate(limit);
+    stale
+}
+
+/// Look `ip` up again past every cache, the cache keeps the new answer.
+pub(crate) async fn refresh(ip: &str) -> Result<IpDetails, IpError> {
+    let details = refresh_ipinfo().lock().await.lookup(ip).await?;
+    cache(ip, &details);
+    Ok(details)
+}
+
+/// Ips of `addresses` worth a lookup, in announcement order.
+pub(crate) fn global_ips(addresses: &[Multiaddr]) -> impl Iterator<Item = IpAddr> + '_ {
+    addresses
+        .iter()
+        .filter_map(multiaddr_to_socketaddr)
+        .map(|addr| addr.ip())
+        .filter(is_global)
+}
+
+/// Whether `ip` is reachable from the internet. Private, loopback, link local and unspecified
+/// addresses have no meaningful location.
+pub(crate) fn is_global(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast())
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_global(&IpAddr::V4(ip));
+            }
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local())
+        }
+    }
+}
+
+/// Where the announced addresses of a node can be reached from, only `global` addresses are
+/// looked up for a location.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressScope {
+    /// At least one global ip.
+    Global,
+    /// Only private, loopback or otherwise non-global ips.
+    Private,
+    /// No address carries an ip and tcp port.
+    NoIp,
+}
+
+impl AddressScope {
+    pub fn of(addresses: &[Multiaddr]) -> Self {
+        let ips = addresses
+            .iter()
+            .filter_map(multiaddr_to_socketaddr)
+            .map(|addr| addr.ip())
+            .collect::<Vec<_>>();
+        if ips.is_empty() {
+            AddressScope::NoIp
+        } else if ips.iter().any(is_global) {
+            AddressScope::Global
+        } else {
+            AddressScope::Private
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AddressScope::Global => "global",
+            AddressScope::Private => "private",
+            AddressScope::NoIp => "no_ip",
+        }
+    }
+
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope {
+            "global" => Some(AddressScope::Global),
+            "private" => Some(AddressScope::Private),
+            "no_ip" => Some(AddressScope::NoIp),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressScope, is_global};
+
+    #[test]
+    fn private_and_loopback_addresses_are_not_global() {
+        for ip in [
+            "10.0.0.1",
+            "172.16.5.4",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:192.168.1.1",
+        ] {
+            assert!(!is_global(&ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["8.8.8.8", "2001:4860:4860::8888", "::ffff:8.8.8.8"] {
+            assert!(is_global(&ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn scope_follows_the_most_reachable_address() {
+        let scope = |addresses: &[&str]| {
+            AddressScope::of(
+                &addresses
+                    .iter()
+                    .map(|addr| addr.parse().unwrap())
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(scope(&[]), AddressScope::NoIp);
+        assert_eq!(scope(&["/dns4/fiber.example/tcp/8228"]), AddressScope::NoIp);
+        assert_eq!(
+            scope(&["/ip4/192.168.1.2/tcp/8228", "/ip4/127.0.0.1/tcp/8228"]),
+            AddressScope::Private
+        );
+        assert_eq!(
+            scope(&["/ip4/10.0.0.2/tcp/8228", "/ip4/8.8.8.8/tcp/8228"]),
+            AddressScope::Global
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! Headline numbers of the dashboard with their week and month changes.
+//!
+//! Recomputed by the daily job from `daily_summarized_data` into `network_kpis`, one row per
+//! network, so `/kpis` is a single row read. Changes compare the latest summarized day with
+//! the days 7 and 30 days before it and are `None` when either day is missing.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row, postgres::PgRow, types::Json};
+
+use crate::{Network, pg_write::DailySummaryInner};
+
+/// A value and its change in percent.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Kpi {
+    pub value: u64,
+    pub change_7d: Option<f64>,
+    pub change_30d: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Kpis {
+    /// Latest summarized day.
+    pub day: NaiveDate,
+    pub nodes: Kpi,
+    pub channels: Kpi,
+    /// Total capacity of the channels of every asset, in CKB.
+    pub capacity: Kpi,
+    /// Median capacity of the CKB channels, in CKB.
+    pub median_channel_capacity: Kpi,
+}
+
+/// The numbers of one summarized day.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DayValues {
+    pub(crate) nodes: u64,
+    pub(crate) channels: u64,
+    /// In CKB, like the median.
+    pub(crate) capacity: u64,
+    pub(crate) median_channel_capacity: u64,
+}
+
+/// Day and numbers of a `daily_summarized_data` row with `day`, `nodes_count`,
+/// `channels_count` and `capacity_analysis`.
+pub(crate) fn day_values(row: &PgRow) -> (NaiveDate, DayValues) {
+    let channels: Json<HashMap<String, i64>> = row.get("channels_count");
+    let capacity: Json<Vec<DailySummaryInner>> = row.get("capacity_analysis");
+    (
+        row.get("day"),
+        DayValues {
+            nodes: row.get::<i32, _>("nodes_count").max(0) as u64,
+            channels: channels
+                .0
+                .values()
+                .map(|count| (*count).max(0) as u64)
+                .sum(),
+            capacity: capacity.0.iter().map(|inner| ckb(&inner.sum)).sum(),
+            median_channel_capacity: capacity
+                .0
+                .iter()
+                .find(|inner| inner.name == "ckb")
+                .map(|inner| ckb(&inner.median))
+                .unwrap_or_default(),
+        },
+    )
+}
+
+/// Change from `previous` to `current` in percent, `None` without a previous value to divide.
+pub fn percent_change(current: u64, previous: Option<u64>) -> Option<f64> {
+    let previous = previous.filter(|previous| *previous > 0)? as f64;
+    Some((current as f64 - previous) / previous * 100.0)
+}
+
+/// Capacities are written as 8 byte hex in shannons.
+fn ckb(hex: &str) -> u64 {
+    let mut bytes = [0u8; 8];
+    faster_hex::hex_decode(hex.as_bytes(), &mut bytes)
+        .map(|_| u64::from_be_bytes(bytes) / 100_000_000)
+        .unwrap_or_default()
+}
+
+fn kpis(day: NaiveDate, days: &HashMap<NaiveDate, DayValues>) -> Option<Kpis> {
+    let latest = *days.get(&day)?;
+    let before = |n: i64| days.get(&(day - chrono::Duration::days(n))).copied();
+    let (week, month) = (before(7), before(30));
+    let kpi = |value: fn(&DayValues) -> u64| Kpi {
+        value: value(&latest),
+        change_7d: percent_change(value(&latest), week.as_ref().map(value)),
+        change_30d: percent_change(value(&latest), month.as_ref().map(value)),
+    };
+    Some(Kpis {
+        day,
+        nodes: kpi(|d| d.nodes),
+        channels: kpi(|d| d.channels),
+        capacity: kpi(|d| d.capacity),
+        median_channel_capacity: kpi(|d| d.median_channel_capacity),
+    })
+}
+
+/// Recompute the kpis of `net` from its latest summarized day, `false` without any summary.
+pub async fn compute(pool: &Pool<Postgres>, net: Network) -> Result<bool, sqlx::Error> {
+    let sql = format!(
+        "SELECT day, nodes_count, channels_count, capacity_analysis FROM {0}
+        WHERE day IN (
+            SELECT latest - n
+            FROM (SELECT max(day) AS latest FROM {0}) l, unnest(ARRAY[0, 7, 30]) AS n
+        )",
+        net.daily_summarized_data()
+    );
+    let days = sqlx::query(&sql)
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(day_values)
+        .collect::<HashMap<_, _>>();
+    let Some(kpis) = days.keys().max().and_then(|day| kpis(*day, &days)) else {
+        return Ok(false);
+    };
+    sqlx::query(
+        "INSERT INTO network_kpis (net, day, kpis, computed_at) VALUES ($1, $2, $3, now())
+        ON CONFLICT (net) DO UPDATE
+        SET day = excluded.day, kpis = excluded.kpis, computed_at = excluded.computed_at",
+    )
+    .bind(net.name())
+    .bind(kpis.day)
+    .bind(Json(&kpis))
+    .execute(pool)
+    .await?;
+    Ok(true)
+}
+
+/// Kpis of `net` as of the last daily run.
+pub async fn load(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<Option<(DateTime<Utc>, Kpis)>, sqlx::Error> {
+    let row = sqlx::query("SELECT kpis, computed_at FROM network_kpis WHERE net = $1")
+        .bind(net.name())
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.map(|row| {
+        let kpis: Json<Kpis> = row.get("kpis");
+        (row.get("computed_at"), kpis.0)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::NaiveDate;
+
+    use super::{DayValues, kpis, percent_change};
+
+    #[test]
+    fn changes_against_missing_or_zero_days_are_none() {
+        assert_eq!(percent_change(150, Some(100)), Some(50.0));
+        assert_eq!(percent_change(50, Some(100)), Some(-50.0));
+        assert_eq!(percent_change(50, Some(0)), None);
+        assert_eq!(percent_change(50, None), None);
+
+        let day = NaiveDate::from_ymd_opt(2025, 3, 31).unwrap();
+        let values = |nodes| DayValues {
+            nodes,
+            ..Default::default()
+        };
+        let days = HashMap::from([
+            (day, values(110)),
+            (day - chrono::Duration::days(7), values(100)),
+        ]);
+        let kpis = kpis(day, &days).unwrap();
+        assert_eq!(kpis.nodes.value, 110);
+        assert!((kpis.nodes.change_7d.unwrap() - 10.0).abs() < 1e-9);
+        assert_eq!(kpis.nodes.change_30d, None);
+    }
+}
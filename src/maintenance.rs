@@ -0,0 +1,154 @@
+//! Maintenance mode, toggled through `/admin/maintenance` and persisted in the single row
+//! `maintenance` table so every api replica follows it.
+//!
+//! While enabled the data apis answer 503 with `Retry-After`, health checks, `/events` and
+//! the admin api keep working. Replicas other than the one handling the toggle pick it up
+//! within [`MAINTENANCE_CACHE_TTL`].
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use arc_swap::ArcSwapOption;
+use chrono::{DateTime, Utc};
+use salvo::{Depot, FlowCtrl, Request, Response, handler, http::StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+
+use crate::get_pg_pool;
+
+const MAINTENANCE_CACHE_TTL: Duration = Duration::from_secs(10);
+/// `Retry-After` when the toggle did not set one.
+const DEFAULT_RETRY_AFTER_SECS: i32 = 300;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Maintenance {
+    pub enabled: bool,
+    /// Shown to clients in the 503 body.
+    pub reason: Option<String>,
+    pub retry_after_secs: i32,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Default for Maintenance {
+    fn default() -> Self {
+        Maintenance {
+            enabled: false,
+            reason: None,
+            retry_after_secs: DEFAULT_RETRY_AFTER_SECS,
+            updated_at: None,
+        }
+    }
+}
+
+struct MaintenanceCache {
+    loaded_at: Instant,
+    state: Maintenance,
+}
+
+static MAINTENANCE_CACHE: ArcSwapOption<MaintenanceCache> = ArcSwapOption::const_empty();
+
+pub async fn load(pool: &Pool<Postgres>) -> Result<Maintenance, sqlx::Error> {
+    let row = sqlx::query(
+        "SELECT enabled, reason, retry_after_secs, updated_at FROM maintenance WHERE id",
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row
+        .map(|row| Maintenance {
+            enabled: row.get("enabled"),
+            reason: row.get("reason"),
+            retry_after_secs: row.get("retry_after_secs"),
+            updated_at: row.get("updated_at"),
+        })
+        .unwrap_or_default())
+}
+
+/// Persist a toggle, effective immediately on this replica.
+pub async fn set(
+    pool: &Pool<Postgres>,
+    enabled: bool,
+    reason: Option<&str>,
+    retry_after_secs: Option<i32>,
+) -> Result<Maintenance, sqlx::Error> {
+    let retry_after_secs = retry_after_secs.unwrap_or(DEFAULT_RETRY_AFTER_SECS).max(1);
+    let row = sqlx::query(
+        "INSERT INTO maintenance (id, enabled, reason, retry_after_secs, updated_at)
+        VALUES (true, $1, $2, $3, now())
+        ON CONFLICT (id) DO UPDATE SET enabled = $1, reason = $2, retry_after_secs = $3,
+            updated_at = now()
+        RETURNING updated_at",
+    )
+    .bind(enabled)
+    .bind(reason)
+    .bind(retry_after_secs)
+    .fetch_one(pool)
+    .await?;
+    let state = Maintenance {
+        enabled,
+        reason: reason.map(str::to_string),
+        retry_after_secs,
+        updated_at: row.get("updated_at"),
+    };
+    MAINTENANCE_CACHE.store(Some(Arc::new(MaintenanceCache {
+        loaded_at: Instant::now(),
+        state: state.clone(),
+    })));
+    if enabled {
+        log::warn!("Maintenance mode enabled: {}", reason.unwrap_or_default());
+    } else {
+        log::info!("Maintenance mode disabled");
+    }
+    Ok(state)
+}
+
+/// Cached state, a failed reload keeps serving and retries on the next request.
+async fn current(pool: &Pool<Postgres>) -> Option<Arc<MaintenanceCache>> {
+    let cached = MAINTENANCE_CACHE.load_full();
+    if let Some(cache) = &cached
+        && cache.loaded_at.elapsed() < MAINTENANCE_CACHE_TTL
+    {
+        return cached;
+    }
+    match load(pool).await {
+        Ok(state) => {
+            let cache = Arc::new(MaintenanceCache {
+                loaded_at: Instant::now(),
+                state,
+            });
+            MAINTENANCE_CACHE.store(Some(cache.clone()));
+            Some(cache)
+        }
+        Err(e) => {
+            log::error!("Failed to load maintenance state: {}", e);
+            cached
+        }
+    }
+}
+
+/// Answer 503 with `Retry-After` while maintenance mode is enabled.
+#[handler]
+pub async fn reject_during_maintenance(
+    _req: &mut Request,
+    _depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let Some(cache) = current(get_pg_pool()).await else {
+        return;
+    };
+    if cache.state.enabled {
+        res.status_code(StatusCode::SERVICE_UNAVAILABLE);
+        res.add_header("retry-after", cache.state.retry_after_secs, true)
+            .ok();
+        res.render(
+            cache
+                .state
+                .reason
+                .clone()
+                .unwrap_or("Under maintenance".to_string()),
+        );
+        ctrl.skip_rest();
+    }
+}
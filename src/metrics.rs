@@ -0,0 +1,312 @@
+//! Prometheus metrics of this process, served in the text exposition format at `/metrics`.
+//!
+//! RPC calls, snapshot commits, `pg_read` queries and the ticks of background loops record
+//! into process-local histograms and counters. Every process exposes only what it ran itself,
+//! scrape collectors and api servers separately.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{LazyLock, Mutex, atomic::Ordering},
+    time::{Duration, Instant},
+};
+
+use salvo::{Depot, Request, Response, handler};
+
+use crate::{
+    pg_write::{CHANNEL_HANDOFFS_DROPPED, DUPLICATE_CHANNELS_DROPPED, DUPLICATE_NODES_DROPPED},
+    storage::SnapshotBatch,
+};
+
+/// Upper bounds in seconds of the histogram buckets, wide enough for task lags of minutes.
+const BUCKETS: [f64; 14] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0,
+];
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Histogram {
+    /// Cumulative, observations at most the bound of the same index.
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    /// By method and endpoint.
+    rpc_seconds: BTreeMap<(String, String), Histogram>,
+    rpc_errors: BTreeMap<(String, String), u64>,
+    insert_batch_seconds: BTreeMap<&'static str, Histogram>,
+    /// By network and kind of row.
+    rows_written: BTreeMap<(&'static str, &'static str), u64>,
+    query_seconds: BTreeMap<&'static str, Histogram>,
+    tick_lag_seconds: BTreeMap<&'static str, Histogram>,
+}
+
+static REGISTRY: LazyLock<Mutex<Registry>> = LazyLock::new(Mutex::default);
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Record an rpc call of `method` to `endpoint`, see [`crate::upstream::endpoint_name`].
+pub(crate) fn observe_rpc(method: &str, endpoint: &str, elapsed: Duration, ok: bool) {
+    let key = (method.to_string(), endpoint.to_string());
+    let mut registry = registry();
+    if !ok {
+        *registry.rpc_errors.entry(key.clone()).or_default() += 1;
+    }
+    registry
+        .rpc_seconds
+        .entry(key)
+        .or_default()
+        .observe(elapsed.as_secs_f64());
+}
+
+/// Record a committed snapshot batch and the rows it wrote.
+pub(crate) fn observe_insert_batch(batch: &SnapshotBatch<'_>, elapsed: Duration) {
+    let net = batch.net.name();
+    let rows = [
+        ("udt_infos", batch.udt_infos.len()),
+        ("udt_dep_relations", batch.udt_dep_relations.len()),
+        ("node_udt_relations", batch.udt_node_relations.len()),
+        ("nodes", batch.nodes.len()),
+        ("channels", batch.channels.len()),
+    ];
+    let mut registry = registry();
+    registry
+        .insert_batch_seconds
+        .entry(net)
+        .or_default()
+        .observe(elapsed.as_secs_f64());
+    for (kind, count) in rows {
+        *registry.rows_written.entry((net, kind)).or_default() += count as u64;
+    }
+}
+
+/// Records the time until it is dropped as a run of `pg_read` query `query`.
+pub(crate) struct QueryTimer {
+    query: &'static str,
+    started: Instant,
+}
+
+/// Start timing `query`, keep the timer alive for the whole read.
+pub(crate) fn query_timer(query: &'static str) -> QueryTimer {
+    QueryTimer {
+        query,
+        started: Instant::now(),
+    }
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        registry()
+            .query_seconds
+            .entry(self.query)
+            .or_default()
+            .observe(self.started.elapsed().as_secs_f64());
+    }
+}
+
+/// Record how late a tick of background `task` came after its planned time.
+pub fn observe_tick_lag(task: &'static str, lag: Duration) {
+    registry()
+        .tick_lag_seconds
+        .entry(task)
+        .or_default()
+        .observe(lag.as_secs_f64());
+}
+
+/// Lag of a tokio interval tick, `scheduled` being what `tick()` returned.
+pub fn observe_interval_lag(task: &'static str, scheduled: tokio::time::Instant) {
+    observe_tick_lag(task, scheduled.elapsed());
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn labels(pairs: &[(&str, &str)]) -> String {
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn write_histograms<'a>(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    series: impl Iterator<Item = (String, &'a Histogram)>,
+) {
+    header(out, name, "histogram", help);
+    for (labels, histogram) in series {
+        let sep = if labels.is_empty() { "" } else { "," };
+        for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, sep, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, sep, histogram.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.sum);
+        let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, histogram.count);
+    }
+}
+
+fn write_counters(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    series: impl Iterator<Item = (String, u64)>,
+) {
+    header(out, name, "counter", help);
+    for (labels, value) in series {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+    }
+}
+
+/// Everything recorded so far in the Prometheus text format.
+pub fn render() -> String {
+    let mut out = String::new();
+    let registry = registry();
+    write_histograms(
+        &mut out,
+        "fiber_dashboard_rpc_call_seconds",
+        "Latency of CKB and Fiber rpc calls.",
+        registry.rpc_seconds.iter().map(|((method, endpoint), h)| {
+            (labels(&[("method", method), ("endpoint", endpoint)]), h)
+        }),
+    );
+    write_counters(
+        &mut out,
+        "fiber_dashboard_rpc_errors_total",
+        "Failed CKB and Fiber rpc calls.",
+        registry
+            .rpc_errors
+            .iter()
+            .map(|((method, endpoint), count)| {
+                (
+                    labels(&[("method", method), ("endpoint", endpoint)]),
+                    *count,
+                )
+            }),
+    );
+    write_histograms(
+        &mut out,
+        "fiber_dashboard_insert_batch_seconds",
+        "Time to commit one snapshot batch.",
+        registry
+            .insert_batch_seconds
+            .iter()
+            .map(|(net, h)| (labels(&[("net", net)]), h)),
+    );
+    write_counters(
+        &mut out,
+        "fiber_dashboard_rows_written_total",
+        "Rows handed to committed snapshot batches.",
+        registry
+            .rows_written
+            .iter()
+            .map(|((net, kind), count)| (labels(&[("net", net), ("kind", kind)]), *count)),
+    );
+    write_histograms(
+        &mut out,
+        "fiber_dashboard_query_seconds",
+        "Duration of pg_read queries.",
+        registry
+            .query_seconds
+            .iter()
+            .map(|(query, h)| (labels(&[("query", query)]), h)),
+    );
+    write_histograms(
+        &mut out,
+        "fiber_dashboard_task_tick_lag_seconds",
+        "Delay of background task ticks after their planned time.",
+        registry
+            .tick_lag_seconds
+            .iter()
+            .map(|(task, h)| (labels(&[("task", task)]), h)),
+    );
+    drop(registry);
+    write_counters(
+        &mut out,
+        "fiber_dashboard_dropped_total",
+        "Duplicate nodes and channels and channel handoffs dropped by the collector.",
+        [
+            ("duplicate_nodes", &DUPLICATE_NODES_DROPPED),
+            ("duplicate_channels", &DUPLICATE_CHANNELS_DROPPED),
+            ("channel_handoffs", &CHANNEL_HANDOFFS_DROPPED),
+        ]
+        .into_iter()
+        .map(|(kind, counter)| (labels(&[("kind", kind)]), counter.load(Ordering::Relaxed))),
+    );
+    out
+}
+
+#[handler]
+pub async fn metrics(
+    _req: &mut Request,
+    _depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), salvo::Error> {
+    res.add_header(
+        "content-type",
+        "text/plain; version=0.0.4; charset=utf-8",
+        true,
+    )?;
+    res.write_body(render())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut histogram = Histogram::default();
+        histogram.observe(0.02);
+        histogram.observe(400.0);
+        assert_eq!(histogram.buckets[..3], [0, 0, 1]);
+        assert_eq!(histogram.buckets[BUCKETS.len() - 1], 1);
+        assert_eq!(histogram.count, 2);
+
+        let mut out = String::new();
+        write_histograms(
+            &mut out,
+            "lag",
+            "help",
+            std::iter::once((labels(&[("task", "a\"b")]), &histogram)),
+        );
+        assert!(out.contains("lag_bucket{task=\"a\\\"b\",le=\"0.025\"} 1\n"));
+        assert!(out.contains("lag_bucket{task=\"a\\\"b\",le=\"+Inf\"} 2\n"));
+        assert!(out.contains("lag_count{task=\"a\\\"b\"} 2\n"));
+    }
+}
@@ -0,0 +1,83 @@
+//! Channel outpoints, stored as hex of their molecule encoding, and their canonical
+//! `0x<tx_hash>:<index>` form the explorers use.
+
+use ckb_jsonrpc_types::JsonBytes;
+use serde::{Deserialize, Deserializer, de::Error};
+
+/// A molecule encoded outpoint, the tx hash followed by the little endian output index.
+const PACKED_LEN: usize = 36;
+
+/// Tx hash and output index of a molecule encoded outpoint.
+pub fn split(packed: &[u8]) -> Option<([u8; 32], u32)> {
+    if packed.len() != PACKED_LEN {
+        return None;
+    }
+    let mut tx_hash = [0u8; 32];
+    tx_hash.copy_from_slice(&packed[..32]);
+    let index = u32::from_le_bytes(packed[32..].try_into().ok()?);
+    Some((tx_hash, index))
+}
+
+/// `0x<tx_hash>:<index>` of a molecule encoded outpoint given as hex, with or without `0x`.
+pub fn canonical(hex: &str) -> Option<String> {
+    let hex = hex.trim_start_matches("0x");
+    let mut packed = [0u8; PACKED_LEN];
+    if hex.len() != PACKED_LEN * 2 {
+        return None;
+    }
+    faster_hex::hex_decode(hex.as_bytes(), &mut packed).ok()?;
+    let (tx_hash, index) = split(&packed)?;
+    Some(format!("0x{}:{}", faster_hex::hex_string(&tx_hash), index))
+}
+
+/// Either form of an outpoint, molecule encoded hex or `<tx_hash>:<index>`, `0x` optional.
+pub fn parse(input: &str) -> Option<JsonBytes> {
+    let mut packed = [0u8; PACKED_LEN];
+    match input.split_once(':') {
+        Some((tx_hash, index)) => {
+            let tx_hash = tx_hash.trim_start_matches("0x");
+            if tx_hash.len() != 64 {
+                return None;
+            }
+            faster_hex::hex_decode(tx_hash.as_bytes(), &mut packed[..32]).ok()?;
+            packed[32..].copy_from_slice(&index.parse::<u32>().ok()?.to_le_bytes());
+        }
+        None => {
+            let hex = input.trim_start_matches("0x");
+            if hex.len() != PACKED_LEN * 2 {
+                return None;
+            }
+            faster_hex::hex_decode(hex.as_bytes(), &mut packed).ok()?;
+        }
+    }
+    Some(JsonBytes::from_vec(packed.to_vec()))
+}
+
+/// Deserialize a parameter given in either form of [`parse`].
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<JsonBytes, D::Error> {
+    let input = String::deserialize(deserializer)?;
+    parse(&input).ok_or_else(|| {
+        D::Error::custom(format!(
+            "invalid outpoint {:?}, expected 0x<36 bytes> or 0x<tx_hash>:<index>",
+            input
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical, parse};
+
+    #[test]
+    fn both_forms_parse_to_the_molecule_encoding() {
+        let tx_hash = "ab".repeat(32);
+        let packed = format!("0x{}02010000", tx_hash);
+        assert_eq!(canonical(&packed).unwrap(), format!("0x{}:258", tx_hash));
+        let molecule = parse(&packed).unwrap();
+        assert_eq!(parse(&format!("0x{}:258", tx_hash)).unwrap(), molecule);
+        assert_eq!(parse(&format!("{}:258", tx_hash)).unwrap(), molecule);
+        assert!(parse(&format!("0x{}:-1", tx_hash)).is_none());
+        assert!(parse("0x1234").is_none());
+        assert!(canonical("0x1234").is_none());
+    }
+}
@@ -0,0 +1,60 @@
+//! Keeps a panicking request handler from taking the api down.
+//!
+//! [`catch_panic`] runs the rest of the request inside `catch_unwind` and answers 500 with an
+//! error id that is also logged. The process wide panic hook set with
+//! `ALLOW_EXIT_ON_PANIC` checks [`request_error_id`] and only exits for panics outside of a
+//! request, so collector panics stay fatal.
+
+use std::{
+    panic::AssertUnwindSafe,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use chrono::Utc;
+use futures::FutureExt;
+use salvo::{Depot, FlowCtrl, Request, Response, handler, http::StatusCode, writing::Text};
+
+tokio::task_local! {
+    static ERROR_ID: String;
+}
+
+static SEQUENCE: AtomicU32 = AtomicU32::new(0);
+
+/// Error id of the request being handled on this thread, `None` outside of [`catch_panic`].
+pub fn request_error_id() -> Option<String> {
+    ERROR_ID.try_with(Clone::clone).ok()
+}
+
+fn new_error_id() -> String {
+    format!(
+        "{:x}-{:04x}",
+        Utc::now().timestamp_millis(),
+        SEQUENCE.fetch_add(1, Ordering::Relaxed) & 0xffff
+    )
+}
+
+#[handler]
+pub async fn catch_panic(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let id = new_error_id();
+    let handled = AssertUnwindSafe(ERROR_ID.scope(id.clone(), ctrl.call_next(req, depot, res)))
+        .catch_unwind()
+        .await;
+    if handled.is_err() {
+        log::error!(
+            "Request {} {} panicked, error id {}",
+            req.method(),
+            req.uri().path(),
+            id
+        );
+        ctrl.skip_rest();
+        res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
+        res.render(Text::Json(
+            serde_json::json!({"error": "internal server error", "error_id": id}).to_string(),
+        ));
+    }
+}
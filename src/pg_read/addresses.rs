@@ -0,0 +1,189 @@
+//! Statistics over the addresses nodes announce, read from the `addresses` json list kept
+//! with every node snapshot.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::NaiveDate;
+use multiaddr::{MultiAddr, Protocol};
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{Network, pg_read::day_window};
+
+#[derive(Debug, Serialize)]
+pub struct IpFamilyDay {
+    pub day: NaiveDate,
+    pub nodes: i64,
+    pub ipv4_only: i64,
+    pub ipv6_only: i64,
+    pub dual_stack: i64,
+    /// Nodes announcing no ip address, e.g. dns only.
+    pub no_ip: i64,
+    /// Share of nodes announcing at least one ipv6 address.
+    pub ipv6_share: f64,
+}
+
+/// Ip families announced per day over the last `days` days, from each node's last addresses
+/// of the day.
+pub async fn query_ipv6_stats(
+    pool: &Pool<Postgres>,
+    net: Network,
+    days: i64,
+) -> Result<Vec<IpFamilyDay>, sqlx::Error> {
+    let sql = format!(
+        "WITH latest AS (
+            SELECT DISTINCT ON (time_bucket('1 day', bucket), node_id)
+                time_bucket('1 day', bucket) AS day,
+                addresses LIKE '%/ip4/%' AS ipv4,
+                addresses LIKE '%/ip6/%' AS ipv6
+            FROM {}
+            WHERE bucket >= $1 AND bucket < $2
+            ORDER BY time_bucket('1 day', bucket), node_id, bucket DESC
+        )
+        SELECT day::date AS day,
+            count(*) AS nodes,
+            count(*) FILTER (WHERE ipv4 AND NOT ipv6) AS ipv4_only,
+            count(*) FILTER (WHERE ipv6 AND NOT ipv4) AS ipv6_only,
+            count(*) FILTER (WHERE ipv4 AND ipv6) AS dual_stack,
+            count(*) FILTER (WHERE NOT ipv4 AND NOT ipv6) AS no_ip
+        FROM latest
+        GROUP BY day
+        ORDER BY day",
+        net.online_nodes_hourly()
+    );
+    let (start, end) = day_window(days);
+    Ok(sqlx::query(&sql)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let nodes: i64 = row.get("nodes");
+            let ipv6_only: i64 = row.get("ipv6_only");
+            let dual_stack: i64 = row.get("dual_stack");
+            IpFamilyDay {
+                day: row.get("day"),
+                nodes,
+                ipv4_only: row.get("ipv4_only"),
+                ipv6_only,
+                dual_stack,
+                no_ip: row.get("no_ip"),
+                ipv6_share: (ipv6_only + dual_stack) as f64 / nodes.max(1) as f64,
+            }
+        })
+        .collect())
+}
+
+/// Port a Fiber node listens on unless configured otherwise.
+pub const DEFAULT_FIBER_PORT: u16 = 8228;
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct PortCount {
+    pub port: u16,
+    pub nodes: usize,
+}
+
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct PortUsage {
+    pub nodes: usize,
+    /// Nodes announcing the default port on at least one address.
+    pub default_port: usize,
+    /// Nodes announcing only other ports.
+    pub custom_port_only: usize,
+    /// Nodes announcing no tcp port at all.
+    pub no_port: usize,
+    /// Nodes per announced port, most used first.
+    pub ports: Vec<PortCount>,
+}
+
+/// Tally the tcp ports of every node's announced addresses, a node counts once per port.
+pub fn summarize_ports(nodes: &[Vec<MultiAddr>]) -> PortUsage {
+    let mut usage = PortUsage {
+        nodes: nodes.len(),
+        ..Default::default()
+    };
+    let mut counts = BTreeMap::<u16, usize>::new();
+    for addresses in nodes {
+        let ports = addresses
+            .iter()
+            .flat_map(|addr| addr.iter())
+            .filter_map(|proto| match proto {
+                Protocol::Tcp(port) => Some(port),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        if ports.is_empty() {
+            usage.no_port += 1;
+        } else if ports.contains(&DEFAULT_FIBER_PORT) {
+            usage.default_port += 1;
+        } else {
+            usage.custom_port_only += 1;
+        }
+        for port in ports {
+            *counts.entry(port).or_default() += 1;
+        }
+    }
+    usage.ports = counts
+        .into_iter()
+        .map(|(port, nodes)| PortCount { port, nodes })
+        .collect();
+    usage.ports.sort_by_key(|p| std::cmp::Reverse(p.nodes));
+    usage
+}
+
+/// Announced ports of the online nodes of `net`.
+pub async fn query_port_usage(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<PortUsage, sqlx::Error> {
+    let sql = format!("SELECT addresses FROM {}", net.mv_online_nodes());
+    let nodes = sqlx::query(&sql)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| serde_json::from_str(row.get("addresses")).unwrap_or_default())
+        .collect::<Vec<Vec<MultiAddr>>>();
+    Ok(summarize_ports(&nodes))
+}
+
+#[cfg(test)]
+mod tests {
+    use multiaddr::MultiAddr;
+
+    use super::{PortCount, summarize_ports};
+
+    #[test]
+    fn nodes_count_once_per_port() {
+        let nodes = [
+            vec!["/ip4/1.1.1.1/tcp/8228", "/ip6/::1/tcp/8228"],
+            vec!["/ip4/2.2.2.2/tcp/9000", "/ip4/2.2.2.2/tcp/8228"],
+            vec!["/ip4/3.3.3.3/tcp/9000"],
+            vec![],
+        ]
+        .map(|addresses| {
+            addresses
+                .iter()
+                .map(|a| a.parse().unwrap())
+                .collect::<Vec<MultiAddr>>()
+        });
+        let usage = summarize_ports(&nodes);
+        assert_eq!(usage.nodes, 4);
+        assert_eq!(usage.default_port, 2);
+        assert_eq!(usage.custom_port_only, 1);
+        assert_eq!(usage.no_port, 1);
+        assert_eq!(
+            usage.ports,
+            vec![
+                PortCount {
+                    port: 8228,
+                    nodes: 2
+                },
+                PortCount {
+                    port: 9000,
+                    nodes: 2
+                },
+            ]
+        );
+    }
+}
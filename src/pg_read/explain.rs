@@ -0,0 +1,91 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{
+    Network,
+    http_server::{ListNodesHourlySortBy, Order},
+    pg_read::{PAGE_SIZE, online_since, statements},
+};
+
+/// List endpoints whose rendered query can be explained.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExplainEndpoint {
+    NodesHourly,
+    ChannelsHourly,
+    NodesNearlyMonthly,
+    ChannelsNearlyMonthly,
+    NodesByRegion,
+    NodesFuzzyByName,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Explained {
+    endpoint: ExplainEndpoint,
+    sql: String,
+    plan: serde_json::Value,
+}
+
+/// Run `EXPLAIN` on the first page statement of `endpoint` with its default sort and sample
+/// filter values, `analyze` executes the query to report real timings.
+pub async fn explain_endpoint(
+    pool: &Pool<Postgres>,
+    endpoint: ExplainEndpoint,
+    net: Network,
+    analyze: bool,
+) -> Result<Explained, sqlx::Error> {
+    let hour_bucket = online_since();
+    let today = Utc::now().date_naive();
+    let month_ago = today - chrono::Duration::days(30);
+    let statements = statements(net);
+    let sort_by = ListNodesHourlySortBy::default();
+    let order = Order::default();
+    let sql = match endpoint {
+        ExplainEndpoint::NodesHourly => statements.hourly_nodes(&sort_by, &order),
+        ExplainEndpoint::ChannelsHourly => &statements.hourly_channels,
+        ExplainEndpoint::NodesNearlyMonthly => &statements.monthly_nodes,
+        ExplainEndpoint::ChannelsNearlyMonthly => &statements.monthly_channels,
+        ExplainEndpoint::NodesByRegion => statements.nodes_by_region(&sort_by, &order),
+        ExplainEndpoint::NodesFuzzyByName => statements.nodes_fuzzy_by_name(&sort_by, &order),
+    };
+    let explain = format!(
+        "EXPLAIN (FORMAT JSON{}) {}",
+        if analyze { ", ANALYZE, BUFFERS" } else { "" },
+        sql
+    );
+
+    let query = sqlx::query(&explain);
+    let query = match endpoint {
+        ExplainEndpoint::NodesHourly => query.bind(hour_bucket),
+        ExplainEndpoint::ChannelsHourly => query
+            .bind(hour_bucket)
+            .bind(None::<String>)
+            .bind(None::<String>)
+            .bind(false)
+            .bind(None::<String>)
+            .bind(None::<String>)
+            .bind(None::<String>),
+        ExplainEndpoint::NodesNearlyMonthly => query.bind(month_ago).bind(today),
+        ExplainEndpoint::ChannelsNearlyMonthly => query
+            .bind(month_ago)
+            .bind(today)
+            .bind(None::<String>)
+            .bind(None::<String>)
+            .bind(None::<String>),
+        ExplainEndpoint::NodesByRegion => query.bind(hour_bucket).bind("US"),
+        ExplainEndpoint::NodesFuzzyByName => query.bind(hour_bucket).bind("fiber"),
+    };
+    let plan = query
+        .bind(PAGE_SIZE as i64)
+        .bind(0i64)
+        .fetch_one(pool)
+        .await?
+        .get::<serde_json::Value, _>(0);
+
+    Ok(Explained {
+        endpoint,
+        sql: sql.to_string(),
+        plan,
+    })
+}
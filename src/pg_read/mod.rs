@@ -1,5 +1,13 @@
+mod addresses;
+mod explain;
 mod operates;
+mod snapshot;
+mod statements;
 mod types;
 
+pub use addresses::*;
+pub use explain::*;
 pub use operates::*;
+pub use snapshot::*;
+pub use statements::*;
 pub use types::*;
@@ -1,22 +1,33 @@
-use std::{collections::HashMap, hash::Hash};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    sync::LazyLock,
+};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Utc};
 use ckb_jsonrpc_types::{DepType, JsonBytes, OutPoint as OutPointWrapper, Script};
-use ckb_types::H256;
+use futures::TryStreamExt;
+use multiaddr::MultiAddr;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use sqlx::{Pool, Postgres, Row};
 
 use crate::{
-    Network,
+    Network, config,
+    downsample::lttb,
+    error::{FiberDashboardError, decode_hex, decode_hex_vec, decode_script},
     http_server::{
         AnalysisHourlyParams, ChannelByNodeIdParams, ChannelByStateParams, FuzzyNodeName,
-        ListNodesHourlyParams, NodeByRegion, Page,
+        ListNodesHourlyParams, NodeByRegion, NodesByUdt, Page,
     },
+    ip_location::AddressScope,
+    metrics::query_timer,
     pg_read::{
         ChannelInfo, HourlyChannelInfoDBRead, HourlyNodeInfo, HourlyNodeInfoDBRead, PAGE_SIZE,
+        hot_snapshot, online_since, past_snapshot, udt_relation_since,
     },
-    pg_write::{DailySummaryInner, global_cache, global_cache_testnet},
+    pg_write::{DailySummaryInner, RelationCache, global_cache, global_cache_testnet},
+    stats::{ChannelStats, MAGNITUDE_BUCKETS, ValueStats, magnitude_bucket},
     types::{U64Hex, U128Hex, UdtArgInfo, UdtCellDep, UdtCfgInfos, UdtDep},
 };
 
@@ -24,30 +35,36 @@ pub(crate) async fn read_nodes_hourly(
     pool: &Pool<Postgres>,
     params: ListNodesHourlyParams,
 ) -> Result<(Vec<HourlyNodeInfo>, usize, usize), sqlx::Error> {
-    HourlyNodeInfoDBRead::fetch_by_page_hourly(pool, params)
-        .await
-        .map(|(entities, next_page, total_count)| {
-            (
-                entities.into_iter().map(HourlyNodeInfo::from).collect(),
-                next_page,
-                total_count,
-            )
-        })
+    let _timer = query_timer("read_nodes_hourly");
+    if let Some(as_of) = params.as_of
+        && let Some(snapshot) = past_snapshot(pool, params.net, as_of).await?
+    {
+        return Ok(snapshot.nodes_page(&params));
+    }
+    if let Some(snapshot) = hot_snapshot(params.net) {
+        return Ok(snapshot.nodes_page(&params));
+    }
+    let (entities, next_page, total_count) =
+        HourlyNodeInfoDBRead::fetch_by_page_hourly(pool, params).await?;
+    let entities = entities
+        .into_iter()
+        .map(HourlyNodeInfo::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok((entities, next_page, total_count))
 }
 
 pub async fn read_nodes_monthly(
     pool: &Pool<Postgres>,
     params: Page,
 ) -> Result<(Vec<HourlyNodeInfo>, usize, usize), sqlx::Error> {
-    HourlyNodeInfoDBRead::fetch_by_page_monthly(pool, params)
-        .await
-        .map(|(entities, next_page, total_count)| {
-            (
-                entities.into_iter().map(HourlyNodeInfo::from).collect(),
-                next_page,
-                total_count,
-            )
-        })
+    let _timer = query_timer("read_nodes_monthly");
+    let (entities, next_page, total_count) =
+        HourlyNodeInfoDBRead::fetch_by_page_monthly(pool, params).await?;
+    let entities = entities
+        .into_iter()
+        .map(HourlyNodeInfo::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok((entities, next_page, total_count))
 }
 
 pub async fn query_node_info(
@@ -55,69 +72,81 @@ pub async fn query_node_info(
     node_id: JsonBytes,
     net: Network,
 ) -> Result<Option<HourlyNodeInfo>, sqlx::Error> {
+    let _timer = query_timer("query_node_info");
     HourlyNodeInfoDBRead::fetch_by_id(pool, node_id, net)
-        .await
-        .map(|res| res.map(HourlyNodeInfo::from))
+        .await?
+        .map(HourlyNodeInfo::try_from)
+        .transpose()
+        .map_err(Into::into)
 }
 
 pub(crate) async fn query_nodes_by_region(
     pool: &Pool<Postgres>,
     params: NodeByRegion,
 ) -> Result<(Vec<HourlyNodeInfo>, usize, usize), sqlx::Error> {
-    HourlyNodeInfoDBRead::fetch_node_by_region(pool, params)
-        .await
-        .map(|(entities, next_page, total_count)| {
-            (
-                entities.into_iter().map(HourlyNodeInfo::from).collect(),
-                next_page,
-                total_count,
-            )
-        })
+    let _timer = query_timer("query_nodes_by_region");
+    if let Some(as_of) = params.as_of
+        && let Some(snapshot) = past_snapshot(pool, params.net, as_of).await?
+    {
+        return Ok(snapshot.region_page(&params));
+    }
+    let (entities, next_page, total_count) =
+        HourlyNodeInfoDBRead::fetch_node_by_region(pool, params).await?;
+    let entities = entities
+        .into_iter()
+        .map(HourlyNodeInfo::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok((entities, next_page, total_count))
 }
 
 pub(crate) async fn query_nodes_fuzzy_by_name(
     pool: &Pool<Postgres>,
     params: FuzzyNodeName,
 ) -> Result<(Vec<HourlyNodeInfo>, usize, usize), sqlx::Error> {
-    HourlyNodeInfoDBRead::fetch_node_fuzzy_by_name_or_id(pool, params)
-        .await
-        .map(|(entities, next_page, total_count)| {
-            (
-                entities.into_iter().map(HourlyNodeInfo::from).collect(),
-                next_page,
-                total_count,
-            )
-        })
+    let _timer = query_timer("query_nodes_fuzzy_by_name");
+    let (entities, next_page, total_count) =
+        HourlyNodeInfoDBRead::fetch_node_fuzzy_by_name_or_id(pool, params).await?;
+    let entities = entities
+        .into_iter()
+        .map(HourlyNodeInfo::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok((entities, next_page, total_count))
 }
 
 pub async fn read_channels_hourly(
     pool: &Pool<Postgres>,
     params: Page,
 ) -> Result<(Vec<ChannelInfo>, usize, usize), sqlx::Error> {
-    HourlyChannelInfoDBRead::fetch_by_page_hourly(pool, params)
-        .await
-        .map(|(entities, next_page, total_count)| {
-            (
-                entities.into_iter().map(ChannelInfo::from).collect(),
-                next_page,
-                total_count,
-            )
-        })
+    let _timer = query_timer("read_channels_hourly");
+    if let Some(as_of) = params.as_of
+        && let Some(snapshot) = past_snapshot(pool, params.net, as_of).await?
+    {
+        return Ok(snapshot.channels_page(&params));
+    }
+    if let Some(snapshot) = hot_snapshot(params.net) {
+        return Ok(snapshot.channels_page(&params));
+    }
+    let (entities, next_page, total_count) =
+        HourlyChannelInfoDBRead::fetch_by_page_hourly(pool, params).await?;
+    let entities = entities
+        .into_iter()
+        .map(ChannelInfo::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok((entities, next_page, total_count))
 }
 
 pub async fn read_channels_monthly(
     pool: &Pool<Postgres>,
     params: Page,
 ) -> Result<(Vec<ChannelInfo>, usize, usize), sqlx::Error> {
-    HourlyChannelInfoDBRead::fetch_by_page_monthly(pool, params)
-        .await
-        .map(|(entities, next_page, total_count)| {
-            (
-                entities.into_iter().map(ChannelInfo::from).collect(),
-                next_page,
-                total_count,
-            )
-        })
+    let _timer = query_timer("read_channels_monthly");
+    let (entities, next_page, total_count) =
+        HourlyChannelInfoDBRead::fetch_by_page_monthly(pool, params).await?;
+    let entities = entities
+        .into_iter()
+        .map(ChannelInfo::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok((entities, next_page, total_count))
 }
 
 pub async fn query_channel_info(
@@ -125,18 +154,22 @@ pub async fn query_channel_info(
     outpoint: JsonBytes,
     net: Network,
 ) -> Result<Option<ChannelInfo>, sqlx::Error> {
+    let _timer = query_timer("query_channel_info");
     HourlyChannelInfoDBRead::fetch_by_id(pool, outpoint, net)
-        .await
-        .map(|res| res.map(ChannelInfo::from))
+        .await?
+        .map(ChannelInfo::try_from)
+        .transpose()
+        .map_err(Into::into)
 }
 
 pub(crate) async fn query_channels_by_node_id(
     pool: &Pool<Postgres>,
     params: ChannelByNodeIdParams,
 ) -> Result<String, sqlx::Error> {
+    let _timer = query_timer("query_channels_by_node_id");
     let page_size = std::cmp::min(params.page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
     let offset = params.page.saturating_mul(page_size);
-    let hour_bucket = Utc::now() - chrono::Duration::hours(3);
+    let hour_bucket = online_since();
     let normalized_asset_names = normalize_asset_names(&params.asset_name);
     let has_asset_filter = normalized_asset_names.is_some();
     let asset_filter_clause = build_asset_filter_clause(3, has_asset_filter);
@@ -262,12 +295,13 @@ pub async fn query_node_udt_relation(
     node_id: JsonBytes,
     net: Network,
 ) -> Result<UdtCfgInfos, sqlx::Error> {
+    let _timer = query_timer("query_node_udt_relation");
     let sql = format!(
         r#"
         select id, name, code_hash, hash_type, args, auto_accept_amount 
         from {} 
         join {} on {}.id = {}.udt_info_id 
-        where node_id = $1
+        where node_id = $1 and not hidden and last_confirmed >= $2
     "#,
         net.udt_infos(),
         net.node_udt_relations(),
@@ -277,6 +311,7 @@ pub async fn query_node_udt_relation(
 
     let raw_udt_infos = sqlx::query(&sql)
         .bind(faster_hex::hex_string(node_id.as_bytes()))
+        .bind(udt_relation_since())
         .fetch_all(pool)
         .await?
         .into_iter()
@@ -284,44 +319,16 @@ pub async fn query_node_udt_relation(
             let id = row.get::<i32, _>("id");
             let info = UdtArgInfo {
                 name: row.get("name"),
-                script: Script {
-                    code_hash: {
-                        let mut code_hash_bytes = [0u8; 32];
-                        let code_hash_str: String = row.get("code_hash");
-                        faster_hex::hex_decode(code_hash_str.as_bytes(), &mut code_hash_bytes)
-                            .unwrap();
-                        H256::from(code_hash_bytes)
-                    },
-                    hash_type: {
-                        let hash_type_str: String = row.get("hash_type");
-                        match hash_type_str.as_str() {
-                            "type" => ckb_jsonrpc_types::ScriptHashType::Type,
-                            "data" => ckb_jsonrpc_types::ScriptHashType::Data,
-                            "data1" => ckb_jsonrpc_types::ScriptHashType::Data1,
-                            "data2" => ckb_jsonrpc_types::ScriptHashType::Data2,
-                            _ => panic!("Unknown hash type: {}", hash_type_str),
-                        }
-                    },
-                    args: {
-                        let args_str: String = row.get("args");
-                        let mut args_bytes = vec![0u8; args_str.len() / 2];
-                        faster_hex::hex_decode(args_str.as_bytes(), &mut args_bytes).unwrap();
-                        JsonBytes::from_vec(args_bytes)
-                    },
-                },
-                auto_accept_amount: {
-                    let amount: Option<String> = row.get("auto_accept_amount");
-                    amount.map(|amt| {
-                        let mut buf = [0u8; 16];
-                        faster_hex::hex_decode(amt.as_bytes(), &mut buf).unwrap();
-                        u128::from_be_bytes(buf)
-                    })
-                },
+                script: decode_script(row.get("code_hash"), row.get("hash_type"), row.get("args"))?,
+                auto_accept_amount: row
+                    .get::<Option<&str>, _>("auto_accept_amount")
+                    .map(|amount| decode_hex("auto_accept_amount", amount).map(u128::from_be_bytes))
+                    .transpose()?,
                 cell_deps: Vec::new(),
             };
-            (id, info)
+            Ok::<_, FiberDashboardError>((id, info))
         })
-        .collect::<Vec<_>>();
+        .collect::<Result<Vec<_>, _>>()?;
 
     let mut cache = HashMap::new();
     let mut udt_infos = Vec::new();
@@ -339,65 +346,35 @@ pub async fn query_node_udt_relation(
                 .fetch_all(pool)
                 .await?
                 .into_iter()
-                .map(|row| UdtDep {
-                    cell_dep: {
-                        let outpoint_tx_hash: Option<String> = row.get("outpoint_tx_hash");
-                        let outpoint_index: Option<String> = row.get("outpoint_index");
-                        match (outpoint_tx_hash, outpoint_index) {
-                            (Some(tx_hash), Some(index)) => Some(UdtCellDep {
-                                out_point: OutPointWrapper {
-                                    tx_hash: {
-                                        let mut buf = [0; 32];
-                                        faster_hex::hex_decode(tx_hash.as_bytes(), &mut buf)
-                                            .unwrap();
-                                        H256::from(buf)
-                                    },
-                                    index: {
-                                        let mut buf = [0; 4];
-                                        faster_hex::hex_decode(index.as_bytes(), &mut buf).unwrap();
-                                        u32::from_be_bytes(buf).into()
-                                    },
-                                },
-                                dep_type: match row.get::<String, _>("dep_type").as_str() {
-                                    "code" => DepType::Code,
-                                    "dep_group" => DepType::DepGroup,
-                                    _ => panic!("Unknown dep type"),
-                                },
-                            }),
-                            _ => None,
-                        }
-                    },
-                    type_id: {
-                        let code_hash: Option<String> = row.get("code_hash");
-                        code_hash.map(|code_hash| Script {
-                            code_hash: {
-                                let mut buf = [0; 32];
-                                faster_hex::hex_decode(code_hash.as_bytes(), &mut buf).unwrap();
-                                H256::from(buf)
-                            },
-                            hash_type: match row.get::<String, _>("hash_type").as_str() {
-                                "type" => ckb_jsonrpc_types::ScriptHashType::Type,
-                                "data" => ckb_jsonrpc_types::ScriptHashType::Data,
-                                "data1" => ckb_jsonrpc_types::ScriptHashType::Data1,
-                                "data2" => ckb_jsonrpc_types::ScriptHashType::Data2,
-                                _ => panic!("Unknown hash type"),
+                .map(|row| {
+                    let outpoint_tx_hash: Option<&str> = row.get("outpoint_tx_hash");
+                    let outpoint_index: Option<&str> = row.get("outpoint_index");
+                    let cell_dep = match (outpoint_tx_hash, outpoint_index) {
+                        (Some(tx_hash), Some(index)) => Some(UdtCellDep {
+                            out_point: OutPointWrapper {
+                                tx_hash: decode_hex::<32>("outpoint_tx_hash", tx_hash)?.into(),
+                                index: u32::from_be_bytes(decode_hex("outpoint_index", index)?)
+                                    .into(),
                             },
-                            args: {
-                                let args = {
-                                    let mut buf = Vec::new();
-                                    faster_hex::hex_decode(
-                                        row.get::<String, _>("args").as_bytes(),
-                                        &mut buf,
-                                    )
-                                    .unwrap();
-                                    buf
-                                };
-                                JsonBytes::from_vec(args)
+                            dep_type: match row.get::<&str, _>("dep_type") {
+                                "code" => DepType::Code,
+                                "dep_group" => DepType::DepGroup,
+                                dep_type => {
+                                    return Err(FiberDashboardError::unknown("dep type", dep_type));
+                                }
                             },
+                        }),
+                        _ => None,
+                    };
+                    let type_id = row
+                        .get::<Option<&str>, _>("code_hash")
+                        .map(|code_hash| {
+                            decode_script(code_hash, row.get("hash_type"), row.get("args"))
                         })
-                    },
+                        .transpose()?;
+                    Ok::<_, FiberDashboardError>(UdtDep { cell_dep, type_id })
                 })
-                .collect::<Vec<_>>();
+                .collect::<Result<Vec<_>, _>>()?;
                 e.insert(udt_deps.clone());
                 info.cell_deps = udt_deps;
                 udt_infos.push(info);
@@ -412,44 +389,80 @@ pub async fn query_node_udt_relation(
     Ok(UdtCfgInfos(udt_infos))
 }
 
-pub async fn query_nodes_by_udt(
+/// Ids of the UDT a request names by script, id or symbol, the first one given wins. A symbol
+/// names every UDT announced under it, ignoring case. Empty for an unknown UDT.
+pub(crate) fn resolve_udt(
+    cache: &RelationCache,
+    script: Option<&Script>,
+    id: Option<i32>,
+    symbol: Option<&str>,
+) -> Vec<i32> {
+    if let Some(script) = script {
+        return cache.udt.get(script).copied().into_iter().collect();
+    }
+    if let Some(id) = id {
+        return cache
+            .udt_values
+            .contains_key(&id)
+            .then_some(id)
+            .into_iter()
+            .collect();
+    }
+    let Some(symbol) = symbol else {
+        return Vec::new();
+    };
+    let mut ids = cache
+        .udt_values
+        .iter()
+        .filter(|(_, values)| values.name.eq_ignore_ascii_case(symbol))
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids
+}
+
+/// Nodes supporting a UDT, the online ones unless `include_offline` is set. An unknown UDT
+/// is `RowNotFound`.
+pub(crate) async fn query_nodes_by_udt(
     pool: &Pool<Postgres>,
-    udt: Script,
-    net: Network,
-) -> Result<Vec<String>, sqlx::Error> {
-    let udt_id = match net {
-        Network::Mainnet => global_cache()
-            .load()
-            .udt
-            .get(&udt)
-            .cloned()
-            .ok_or_else(|| sqlx::Error::RowNotFound)?,
-        Network::Testnet => global_cache_testnet()
-            .load()
-            .udt
-            .get(&udt)
-            .cloned()
-            .ok_or_else(|| sqlx::Error::RowNotFound)?,
+    params: NodesByUdt,
+) -> Result<(Vec<HourlyNodeInfo>, usize, usize), sqlx::Error> {
+    let _timer = query_timer("query_nodes_by_udt");
+    let cache = match params.net {
+        Network::Mainnet => global_cache().load(),
+        Network::Testnet => global_cache_testnet().load(),
     };
-    let sql = format!(
-        r#"
-        select node_id
-        from {}
-        where udt_info_id = $1
-    "#,
-        net.node_udt_relations()
+    let udt_ids = resolve_udt(
+        &cache,
+        params.udt.as_ref(),
+        params.udt_info_id,
+        params.symbol.as_deref(),
     );
-
-    Ok(sqlx::query(&sql)
-        .bind(udt_id)
+    if udt_ids.is_empty() {
+        return Err(sqlx::Error::RowNotFound);
+    }
+    if let Some(as_of) = params.as_of
+        && let Some(snapshot) = past_snapshot(pool, params.net, as_of).await?
+    {
+        // relations are not versioned, every node that ever announced the UDT is a candidate
+        let node_ids = sqlx::query_scalar(&format!(
+            "SELECT DISTINCT node_id FROM {} WHERE udt_info_id = ANY($1)",
+            params.net.node_udt_relations()
+        ))
+        .bind(&udt_ids)
         .fetch_all(pool)
         .await?
         .into_iter()
-        .map(|row| {
-            let node_id: String = row.get("node_id");
-            format!("0x{}", node_id)
-        })
-        .collect::<Vec<_>>())
+        .collect::<HashSet<String>>();
+        return Ok(snapshot.udt_page(&params, &node_ids));
+    }
+    let (entities, next_page, total_count) =
+        HourlyNodeInfoDBRead::fetch_nodes_by_udt(pool, &udt_ids, params).await?;
+    let entities = entities
+        .into_iter()
+        .map(HourlyNodeInfo::try_from)
+        .collect::<Result<_, _>>()?;
+    Ok((entities, next_page, total_count))
 }
 
 #[serde_as]
@@ -481,10 +494,25 @@ pub struct AnalysisHourlyInner {
     channel_len: u64,
 }
 
+impl AnalysisHourlyInner {
+    fn new(name: String, stats: &mut ValueStats) -> Self {
+        AnalysisHourlyInner {
+            name,
+            avg: stats.average(),
+            min: stats.min,
+            max: stats.max,
+            median: stats.median(),
+            total: stats.sum,
+            channel_len: stats.count,
+        }
+    }
+}
+
 pub async fn query_analysis_hourly(
     pool: &Pool<Postgres>,
     params: AnalysisHourlyParams,
 ) -> Result<AnalysisHourly, sqlx::Error> {
+    let _timer = query_timer("query_analysis_hourly");
     let channel_sql = format!(
         "SELECT DISTINCT ON (n.channel_outpoint) n.capacity as asset, COALESCE(c.name, 'ckb') as name, u.capacity as capacity
         from {} n
@@ -502,37 +530,19 @@ pub async fn query_analysis_hourly(
     );
     let end = params.end.unwrap_or_else(chrono::Utc::now);
     let start_time = end - chrono::Duration::hours(3);
-    let mut channel_capacitys = sqlx::query(&channel_sql)
+    let mut channel_stats: HashMap<String, ChannelStats> = HashMap::new();
+    let mut rows = sqlx::query(&channel_sql)
         .bind(start_time)
         .bind(end)
-        .fetch_all(pool)
-        .await
-        .map(|rows| {
-            rows.into_iter()
-                .map(|row| {
-                    let name = row.get::<String, _>("name");
-                    let asset: u128 = {
-                        let raw: String = row.get("asset");
-                        let mut buf = [0u8; 16];
-                        faster_hex::hex_decode(raw.as_bytes(), &mut buf).unwrap();
-                        u128::from_be_bytes(buf)
-                    };
-                    let capacity: u64 = {
-                        let raw: String = row.get("capacity");
-                        let mut buf = [0u8; 8];
-                        faster_hex::hex_decode(raw.as_bytes(), &mut buf).unwrap();
-                        u64::from_be_bytes(buf)
-                    };
-                    (name, asset, capacity)
-                })
-                .fold(
-                    HashMap::new(),
-                    |mut acc: HashMap<String, Vec<(u128, u64)>>, (name, asset, capacity)| {
-                        acc.entry(name).or_default().push((asset, capacity));
-                        acc
-                    },
-                )
-        })?;
+        .fetch(pool);
+    while let Some(row) = rows.try_next().await? {
+        let asset = u128::from_be_bytes(decode_hex("asset", row.get("asset"))?);
+        let capacity = u64::from_be_bytes(decode_hex("capacity", row.get("capacity"))?);
+        channel_stats
+            .entry(row.get("name"))
+            .or_default()
+            .push(asset, capacity);
+    }
     let total_nodes: u64 = sqlx::query(&node_sql)
         .bind(start_time)
         .bind(end)
@@ -543,63 +553,18 @@ pub async fn query_analysis_hourly(
             count as u64
         })?;
     let mut channel_len = 0;
-    let mut asset_analysis = Vec::with_capacity(channel_capacitys.len());
-    let mut capacity_analysis = Vec::with_capacity(channel_capacitys.len());
-    for (name, caps) in channel_capacitys.drain() {
-        channel_len += caps.len();
-        let mut assets: Vec<u128> = caps.iter().map(|(asset, _)| *asset).collect();
-        assets.sort_unstable();
-        let asset_min = assets[0];
-        let asset_max = assets[assets.len() - 1];
-        let asset_sum: u128 = assets.iter().sum();
-        let asset_average = asset_sum / assets.len() as u128;
-        let asset_median = if assets.len().is_multiple_of(2) {
-            let mid1 = assets[assets.len() / 2 - 1];
-            let mid2 = assets[assets.len() / 2];
-            (mid1 + mid2) / 2
-        } else {
-            assets[assets.len() / 2]
-        };
-
-        asset_analysis.push(AnalysisHourlyInner {
-            name: name.clone(),
-            avg: asset_average,
-            min: asset_min,
-            max: asset_max,
-            median: asset_median,
-            total: asset_sum,
-            channel_len: caps.len() as u64,
-        });
-
-        let mut capacities: Vec<u64> = caps.iter().map(|(_, capacity)| *capacity).collect();
-        capacities.sort_unstable();
-        let capacity_min = capacities[0];
-        let capacity_max = capacities[capacities.len() - 1];
-        let capacity_sum: u64 = capacities.iter().sum();
-        let capacity_average = capacity_sum / capacities.len() as u64;
-        let capacity_median = if capacities.len().is_multiple_of(2) {
-            let mid1 = capacities[capacities.len() / 2 - 1];
-            let mid2 = capacities[capacities.len() / 2];
-            (mid1 + mid2) / 2
-        } else {
-            capacities[capacities.len() / 2]
-        };
-
-        capacity_analysis.push(AnalysisHourlyInner {
-            name: name.clone(),
-            avg: capacity_average as u128,
-            min: capacity_min as u128,
-            max: capacity_max as u128,
-            median: capacity_median as u128,
-            total: capacity_sum as u128,
-            channel_len: caps.len() as u64,
-        });
+    let mut asset_analysis = Vec::with_capacity(channel_stats.len());
+    let mut capacity_analysis = Vec::with_capacity(channel_stats.len());
+    for (name, mut stats) in channel_stats.drain() {
+        channel_len += stats.assets.count;
+        asset_analysis.push(AnalysisHourlyInner::new(name.clone(), &mut stats.assets));
+        capacity_analysis.push(AnalysisHourlyInner::new(name, &mut stats.capacities));
     }
 
     Ok(AnalysisHourly {
         asset_analysis,
         capacity_analysis,
-        channel_len: channel_len as u64,
+        channel_len,
         total_nodes,
     })
 }
@@ -627,6 +592,29 @@ impl AnalysisField {
     }
 }
 
+/// Days covered by a `range` of `1M`, `3M`, `6M`, `1Y` or `2Y`, anything else is `1M`.
+pub(crate) fn range_days(range: &str) -> i64 {
+    match range {
+        "3M" => 3 * 30,
+        "6M" => 6 * 30,
+        "1Y" => 365,
+        "2Y" => 2 * 365,
+        _ => 30,
+    }
+}
+
+/// Days of daily series kept, the most one request may ask for.
+pub(crate) const MAX_SERIES_DAYS: i64 = 2 * 365;
+
+/// Days covered by a span `range` like `30d`, 30 without one, clamped to `1..=MAX_SERIES_DAYS`.
+pub(crate) fn span_days(range: Option<&str>) -> i64 {
+    range
+        .and_then(span_hours)
+        .map(|hours| (hours / 24).max(1))
+        .unwrap_or(30)
+        .min(MAX_SERIES_DAYS)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, salvo::macros::Extractible)]
 #[salvo(extract(default_source(from = "body")))]
 pub struct AnalysisParams {
@@ -636,6 +624,8 @@ pub struct AnalysisParams {
     fields: Vec<AnalysisField>,
     interval: Option<String>,
     range: Option<String>,
+    /// Downsample every series to at most this many days, keeping its visual shape.
+    max_points: Option<usize>,
     #[serde(default)]
     net: crate::Network,
 }
@@ -651,14 +641,7 @@ impl AnalysisParams {
             None => end_time - chrono::Duration::days(30),
             Some(ref range) => {
                 meta.range = range.clone();
-                match range.as_str() {
-                    "1M" => end_time - chrono::Duration::days(30),
-                    "3M" => end_time - chrono::Duration::days(3 * 30),
-                    "6M" => end_time - chrono::Duration::days(6 * 30),
-                    "1Y" => end_time - chrono::Duration::days(365),
-                    "2Y" => end_time - chrono::Duration::days(2 * 365),
-                    _ => end_time - chrono::Duration::days(30),
-                }
+                end_time - chrono::Duration::days(range_days(range))
             }
         });
         let fields = if self.fields.is_empty() {
@@ -687,6 +670,7 @@ impl AnalysisParams {
         meta.start_time = format!("{}", start_time.format("%Y-%m-%d"));
         meta.end_time = format!("{}", end_time.format("%Y-%m-%d"));
         meta.interval = self.interval.clone().unwrap_or_else(|| "day".to_string());
+        meta.max_points = self.max_points;
 
         (sql, meta)
     }
@@ -699,12 +683,14 @@ struct Meta {
     end_time: String,
     interval: String,
     range: String,
+    max_points: Option<usize>,
 }
 
 pub async fn query_analysis(
     pool: &Pool<Postgres>,
     params: &AnalysisParams,
 ) -> Result<String, sqlx::Error> {
+    let _timer = query_timer("query_analysis");
     let (sql, meta) = params.to_sql();
     let rows = sqlx::query(&sql).fetch_all(pool).await?;
     #[derive(Serialize, Deserialize, Debug)]
@@ -716,6 +702,9 @@ pub async fn query_analysis(
     struct Tables {
         name: AnalysisField,
         points: Vec<(chrono::NaiveDate, serde_json::Value)>,
+        /// Value of each point the series is downsampled by.
+        #[serde(skip)]
+        values: Vec<f64>,
     }
     let mut results = Res {
         series: Vec::new(),
@@ -728,6 +717,7 @@ pub async fn query_analysis(
         .map(|field| Tables {
             name: *field,
             points: Vec::new(),
+            values: Vec::new(),
         })
         .collect::<Vec<_>>();
     for row in rows {
@@ -737,6 +727,7 @@ pub async fn query_analysis(
                 AnalysisField::Channels => {
                     let value: sqlx::types::Json<HashMap<String, i64>> =
                         row.get(table.name.to_sql().as_str());
+                    table.values.push(value.0.values().sum::<i64>() as f64);
                     table.points.push((
                         timestamp,
                         serde_json::Value::Object(serde_json::Map::from_iter(
@@ -749,6 +740,7 @@ pub async fn query_analysis(
                 AnalysisField::Capacity => {
                     let raw: sqlx::types::Json<Vec<DailySummaryInner>> =
                         row.get(table.name.to_sql().as_str());
+                    table.values.push(hex_total(&raw.0));
                     let values = raw
                         .0
                         .into_iter()
@@ -771,6 +763,7 @@ pub async fn query_analysis(
                 AnalysisField::Asset => {
                     let raw: sqlx::types::Json<Vec<DailySummaryInner>> =
                         row.get(table.name.to_sql().as_str());
+                    table.values.push(hex_total(&raw.0));
                     let values = raw
                         .0
                         .into_iter()
@@ -792,6 +785,7 @@ pub async fn query_analysis(
                 }
                 AnalysisField::Nodes => {
                     let value: i32 = row.get(table.name.to_sql().as_str());
+                    table.values.push(value as f64);
                     table
                         .points
                         .push((timestamp, serde_json::Value::Number(value.into())));
@@ -799,15 +793,39 @@ pub async fn query_analysis(
             }
         }
     }
+    if let Some(max_points) = params.max_points {
+        for table in tables.iter_mut() {
+            let points = std::mem::take(&mut table.points)
+                .into_iter()
+                .zip(std::mem::take(&mut table.values))
+                .collect::<Vec<_>>();
+            table.points = lttb(points, max_points, |((day, _), value)| {
+                (day.num_days_from_ce() as f64, *value)
+            })
+            .into_iter()
+            .map(|(point, _)| point)
+            .collect();
+        }
+    }
     results.series = tables;
     Ok(serde_json::to_string(&results).unwrap())
 }
 
+/// Sum of the hex encoded totals of every asset of a day.
+fn hex_total(stats: &[DailySummaryInner]) -> f64 {
+    stats
+        .iter()
+        .filter_map(|inner| u128::from_str_radix(&inner.sum, 16).ok())
+        .map(|sum| sum as f64)
+        .sum()
+}
+
 pub async fn query_channel_state(
     pool: &Pool<Postgres>,
     outpoint: JsonBytes,
     net: Network,
 ) -> Result<String, sqlx::Error> {
+    let _timer = query_timer("query_channel_state");
     let states = net.channel_states();
     let txs = net.channel_txs();
     let sql = format!(
@@ -830,10 +848,8 @@ pub async fn query_channel_state(
         .into_iter()
         .map(|row| {
             if funding_args.is_empty() {
-                let raw: String = row.get("funding_args");
-                let mut buf = vec![0u8; raw.len() / 2];
-                faster_hex::hex_decode(raw.as_bytes(), &mut buf).unwrap();
-                funding_args = JsonBytes::from_vec(buf);
+                funding_args =
+                    JsonBytes::from_vec(decode_hex_vec("funding_args", row.get("funding_args"))?);
             }
             if state.is_empty() {
                 state = row.get("state");
@@ -856,15 +872,15 @@ pub async fn query_channel_state(
 
             let witness_args = raw_witness_args.map(|args| format!("0x{}", args));
             let commitment_args = raw_commitment_args.map(|args| format!("0x{}", args));
-            (
+            Ok::<_, FiberDashboardError>((
                 tx_hash,
                 block_number,
                 timestamp,
                 witness_args,
                 commitment_args,
-            )
+            ))
         })
-        .collect::<Vec<_>>();
+        .collect::<Result<Vec<_>, _>>()?;
 
     #[derive(Serialize, Deserialize, Debug)]
     struct Txs {
@@ -874,16 +890,24 @@ pub async fn query_channel_state(
         witness_args: Option<String>,
         commitment_args: Option<String>,
     }
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Debug)]
     struct TxState {
+        /// `0x<tx_hash>:<index>` of the funding cell.
+        canonical_outpoint: Option<String>,
         funding_args: JsonBytes,
         state: String,
         capacity: String,
         udt_value: Option<String>,
         txs: Vec<Txs>,
+        explorer: Option<crate::explorer::ExplorerLinks>,
     }
 
+    let outpoint = faster_hex::hex_string(outpoint.as_bytes());
+    // the first tx after funding spends the funding cell
+    let closing_tx = rows.get(1).filter(|_| state != "open").map(|row| &row.0);
+    let explorer = crate::explorer::links(net, &outpoint, closing_tx.map(String::as_str));
     let res = TxState {
+        canonical_outpoint: crate::outpoint::canonical(&outpoint),
         funding_args,
         state,
         capacity,
@@ -900,6 +924,7 @@ pub async fn query_channel_state(
                 },
             )
             .collect(),
+        explorer,
     };
 
     Ok(serde_json::to_string(&res).unwrap())
@@ -909,6 +934,7 @@ pub(crate) async fn group_channel_by_state(
     pool: &Pool<Postgres>,
     params: ChannelByStateParams,
 ) -> Result<String, sqlx::Error> {
+    let _timer = query_timer("group_channel_by_state");
     let page_size = std::cmp::min(params.page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
     let offset = params.page.saturating_mul(page_size);
     let index = if params.fuzz_name.is_some() { 3 } else { 2 };
@@ -1063,7 +1089,8 @@ pub async fn group_channel_count_by_state(
     pool: &Pool<Postgres>,
     net: Network,
 ) -> Result<String, sqlx::Error> {
-    let hour_bucket = chrono::Utc::now() - chrono::Duration::hours(3);
+    let _timer = query_timer("group_channel_count_by_state");
+    let hour_bucket = online_since();
     let sql = format!(
         r#"
         select state, count(*), COALESCE(u.name, 'ckb') as name from {} n
@@ -1098,7 +1125,8 @@ pub async fn query_channel_capacity_distribution(
     pool: &Pool<Postgres>,
     net: Network,
 ) -> Result<String, sqlx::Error> {
-    let hour_bucket = chrono::Utc::now() - chrono::Duration::hours(3);
+    let _timer = query_timer("query_channel_capacity_distribution");
+    let hour_bucket = online_since();
     let sql = format!(
         r#"
         SELECT n.capacity as asset, COALESCE(u.name, 'ckb') as name, v.capacity as capacity from {} n
@@ -1117,31 +1145,26 @@ pub async fn query_channel_capacity_distribution(
         .fetch_all(pool)
         .await?
         .into_iter()
-        .fold(HashMap::new(), |mut acc, row| {
+        .try_fold(HashMap::new(), |mut acc, row| {
             let name = row.get::<String, _>("name");
             let asset: u128 = {
-                let raw: String = row.get("asset");
-                let mut buf = [0u8; 16];
-                faster_hex::hex_decode(raw.as_bytes(), &mut buf).unwrap();
+                let raw = u128::from_be_bytes(decode_hex("asset", row.get("asset"))?);
                 if name == "ckb" {
                     // capacity in ckb
-                    u128::from_be_bytes(buf) / 100_000_000 // shannons to ckb
+                    raw / 100_000_000 // shannons to ckb
                 } else {
-                    u128::from_be_bytes(buf)
+                    raw
                 }
             };
-            let capacity: u64 = {
-                let raw: String = row.get("capacity");
-                let mut buf = [0u8; 8];
-                faster_hex::hex_decode(raw.as_bytes(), &mut buf).unwrap();
-                u64::from_be_bytes(buf) / 100_000_000 // channons to ckb
-            };
+            // channons to ckb
+            let capacity =
+                u64::from_be_bytes(decode_hex("capacity", row.get("capacity"))?) / 100_000_000;
 
             acc.entry(name)
                 .or_insert_with(Vec::new)
                 .push((asset, capacity));
-            acc
-        });
+            Ok::<_, FiberDashboardError>(acc)
+        })?;
 
     #[derive(Serialize, Deserialize, Debug)]
     struct Distribution {
@@ -1153,21 +1176,9 @@ pub async fn query_channel_capacity_distribution(
 
     for (name, caps) in rows.iter() {
         let assets = caps.iter().map(|(asset, _)| *asset).collect::<Vec<_>>();
-        let mut buckets = vec![0usize; 8];
+        let mut buckets = vec![0usize; MAGNITUDE_BUCKETS];
         for &cap in assets.iter() {
-            let cap_k = cap / 1000;
-            let idx = if cap_k == 0 {
-                0usize
-            } else {
-                let mut v = cap_k;
-                let mut exp = 0usize;
-                while v >= 10 && exp < 7 {
-                    v /= 10;
-                    exp += 1;
-                }
-                exp
-            };
-            buckets[idx] += 1;
+            buckets[magnitude_bucket(cap)] += 1;
         }
         asset_distribution.insert(
             name.clone(),
@@ -1182,21 +1193,9 @@ pub async fn query_channel_capacity_distribution(
             .iter()
             .map(|(_, capacity)| *capacity)
             .collect::<Vec<_>>();
-        let mut buckets = vec![0usize; 8];
+        let mut buckets = vec![0usize; MAGNITUDE_BUCKETS];
         for &cap in capacities.iter() {
-            let cap_k = cap / 1000;
-            let idx = if cap_k == 0 {
-                0usize
-            } else {
-                let mut v = cap_k;
-                let mut exp = 0usize;
-                while v >= 10 && exp < 7 {
-                    v /= 10;
-                    exp += 1;
-                }
-                exp
-            };
-            buckets[idx] += 1;
+            buckets[magnitude_bucket(cap as u128)] += 1;
         }
         capacity_distribution.insert(
             name.clone(),
@@ -1219,6 +1218,7 @@ pub async fn query_nodes_all_regions(
     pool: &Pool<Postgres>,
     net: Network,
 ) -> Result<String, sqlx::Error> {
+    let _timer = query_timer("query_nodes_all_regions");
     let sql = format!(
         r#"
         select distinct country_or_region from {}
@@ -1244,6 +1244,7 @@ pub async fn query_channel_count_by_asset(
     pool: &Pool<Postgres>,
     net: Network,
 ) -> Result<String, sqlx::Error> {
+    let _timer = query_timer("query_channel_count_by_asset");
     let sql = format!(
         r#"
         select COALESCE(c.name, 'ckb') as name, COUNT(*) as count
@@ -1269,9 +1270,1045 @@ pub async fn query_channel_count_by_asset(
     Ok(serde_json::to_string(&res).unwrap())
 }
 
+/// `[start, end)` covering the last `days` whole days (UTC), today included.
+pub(crate) fn day_window(days: i64) -> (DateTime<Utc>, DateTime<Utc>) {
+    let end = Utc::now()
+        .date_naive()
+        .and_time(chrono::NaiveTime::MIN)
+        .and_utc()
+        + chrono::Duration::days(1);
+    (end - chrono::Duration::days(days), end)
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChurnDay {
+    pub day: chrono::NaiveDate,
+    /// Nodes online for the first time.
+    pub new_nodes: i64,
+    /// Nodes back online after at least one day offline.
+    pub returned_nodes: i64,
+    /// Nodes online the day before but not this day.
+    pub disappeared_nodes: i64,
+}
+
+/// Daily node churn over the last `days` days, today included, from the daily presence of
+/// nodes in `online_nodes_hourly`. "First time" is relative to the history kept there.
+pub async fn query_node_churn(
+    pool: &Pool<Postgres>,
+    net: Network,
+    days: i64,
+) -> Result<Vec<ChurnDay>, sqlx::Error> {
+    let _timer = query_timer("query_node_churn");
+    let sql = format!(
+        "WITH days AS (
+            SELECT DISTINCT time_bucket('1 day', bucket) AS day, node_id
+            FROM {}
+            WHERE bucket < $2
+        ),
+        presence AS (
+            SELECT day, node_id,
+                lag(day) OVER (PARTITION BY node_id ORDER BY day) AS prev_day,
+                lead(day) OVER (PARTITION BY node_id ORDER BY day) AS next_day
+            FROM days
+        ),
+        appeared AS (
+            SELECT day,
+                count(*) FILTER (WHERE prev_day IS NULL) AS new_nodes,
+                count(*) FILTER (WHERE prev_day < day - interval '1 day') AS returned_nodes
+            FROM presence
+            WHERE day >= $1
+            GROUP BY day
+        ),
+        disappeared AS (
+            SELECT day + interval '1 day' AS day, count(*) AS disappeared_nodes
+            FROM presence
+            WHERE (next_day IS NULL OR next_day > day + interval '1 day')
+            AND day + interval '1 day' >= $1 AND day + interval '1 day' < $2
+            GROUP BY 1
+        )
+        SELECT d.day::date AS day,
+            COALESCE(a.new_nodes, 0) AS new_nodes,
+            COALESCE(a.returned_nodes, 0) AS returned_nodes,
+            COALESCE(x.disappeared_nodes, 0) AS disappeared_nodes
+        FROM generate_series($1, $2 - interval '1 day', interval '1 day') AS d(day)
+        LEFT JOIN appeared a ON a.day = d.day
+        LEFT JOIN disappeared x ON x.day = d.day
+        ORDER BY d.day",
+        net.online_nodes_hourly()
+    );
+    let (start, end) = day_window(days);
+    Ok(sqlx::query(&sql)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| ChurnDay {
+            day: row.get("day"),
+            new_nodes: row.get("new_nodes"),
+            returned_nodes: row.get("returned_nodes"),
+            disappeared_nodes: row.get("disappeared_nodes"),
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct UdtTrendDay {
+    pub day: chrono::NaiveDate,
+    pub udt_info_id: i32,
+    pub name: String,
+    pub nodes: i32,
+    pub channels: i32,
+}
+
+/// Daily adoption of one UDT, or of every UDT, over the last `days` days.
+pub async fn query_udt_trend(
+    pool: &Pool<Postgres>,
+    net: Network,
+    udt_info_id: Option<i32>,
+    days: i64,
+) -> Result<Vec<UdtTrendDay>, sqlx::Error> {
+    let _timer = query_timer("query_udt_trend");
+    let sql = format!(
+        "SELECT s.day, s.udt_info_id, u.name, s.nodes, s.channels
+        FROM daily_udt_stats s
+        JOIN {} u ON u.id = s.udt_info_id
+        WHERE s.net = $1 AND s.day >= $2::date AND s.day < $3::date
+        AND ($4::integer IS NULL OR s.udt_info_id = $4) AND NOT u.hidden
+        ORDER BY s.day, s.udt_info_id",
+        net.udt_infos()
+    );
+    let (start, end) = day_window(days);
+    Ok(sqlx::query(&sql)
+        .bind(net.name())
+        .bind(start)
+        .bind(end)
+        .bind(udt_info_id)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| UdtTrendDay {
+            day: row.get("day"),
+            udt_info_id: row.get("udt_info_id"),
+            name: row.get("name"),
+            nodes: row.get("nodes"),
+            channels: row.get("channels"),
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct AmountBucket {
+    /// Inclusive lower bound in CKB, 0 or a power of 10.
+    pub lower_ckb: f64,
+    /// Exclusive upper bound in CKB.
+    pub upper_ckb: f64,
+    pub nodes: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UdtAutoAccept {
+    pub udt_info_id: i32,
+    pub name: String,
+    /// Amount last announced for the UDT, hex of the UDT's smallest unit.
+    pub auto_accept_amount: Option<String>,
+    /// Online nodes supporting the UDT.
+    pub nodes: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AutoAcceptDistribution {
+    pub nodes: i64,
+    /// `auto_accept_min_ckb_funding_amount` in CKB at the 10th, 25th, 50th, 75th and 90th
+    /// percentile.
+    pub percentiles_ckb: Vec<f64>,
+    /// Nodes per decade of `auto_accept_min_ckb_funding_amount`.
+    pub histogram: Vec<AmountBucket>,
+    pub udts: Vec<UdtAutoAccept>,
+}
+
+/// Distribution of the auto accept thresholds of the online nodes of `net`. Nodes share one
+/// `auto_accept_amount` per UDT, the one in `udt_infos`.
+pub async fn query_auto_accept_distribution(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<AutoAcceptDistribution, sqlx::Error> {
+    let _timer = query_timer("query_auto_accept_distribution");
+    let amounts = format!(
+        "SELECT hex_to_numeric(auto_accept_min_ckb_funding_amount) / 100000000 AS ckb FROM {}",
+        net.mv_online_nodes()
+    );
+    let percentiles_sql = format!(
+        "SELECT count(*) AS nodes,
+            COALESCE(percentile_cont(ARRAY[0.1, 0.25, 0.5, 0.75, 0.9]) WITHIN GROUP (ORDER BY ckb),
+                '{{}}') AS percentiles
+        FROM ({}) a",
+        amounts
+    );
+    let histogram_sql = format!(
+        "SELECT lower, count(*) AS nodes FROM (
+            SELECT CASE WHEN ckb < 1 THEN 0 ELSE power(10, floor(log(ckb)))::float8 END AS lower
+            FROM ({}) a
+        ) b
+        GROUP BY lower
+        ORDER BY lower",
+        amounts
+    );
+    let udts_sql = format!(
+        "SELECT u.id, u.name, u.auto_accept_amount,
+            count(DISTINCT n.node_id) AS nodes
+        FROM {} u
+        LEFT JOIN {} r ON r.udt_info_id = u.id AND r.last_confirmed >= $1
+        LEFT JOIN {} n ON n.node_id = r.node_id
+        WHERE NOT u.hidden
+        GROUP BY u.id, u.name, u.auto_accept_amount
+        ORDER BY u.id",
+        net.udt_infos(),
+        net.node_udt_relations(),
+        net.mv_online_nodes()
+    );
+
+    let row = sqlx::query(&percentiles_sql).fetch_one(pool).await?;
+    let histogram = sqlx::query(&histogram_sql)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let lower_ckb: f64 = row.get("lower");
+            AmountBucket {
+                lower_ckb,
+                upper_ckb: if lower_ckb == 0.0 {
+                    1.0
+                } else {
+                    lower_ckb * 10.0
+                },
+                nodes: row.get("nodes"),
+            }
+        })
+        .collect();
+    let udts = sqlx::query(&udts_sql)
+        .bind(udt_relation_since())
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| UdtAutoAccept {
+            udt_info_id: row.get("id"),
+            name: row.get("name"),
+            auto_accept_amount: row
+                .get::<Option<String>, _>("auto_accept_amount")
+                .map(|amount| format!("0x{}", amount)),
+            nodes: row.get("nodes"),
+        })
+        .collect();
+    Ok(AutoAcceptDistribution {
+        nodes: row.get("nodes"),
+        percentiles_ckb: row.get("percentiles"),
+        histogram,
+        udts,
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct IssuerUdt {
+    pub udt_info_id: i32,
+    pub name: String,
+    pub channels: i64,
+    /// Sum of the channels' UDT amounts, decimal in the UDT's smallest unit.
+    pub amount: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UdtIssuerStats {
+    /// First 32 bytes of the UDT args, the owner lock hash of sUDT and xUDT.
+    pub issuer: String,
+    pub channels: i64,
+    /// Sum of the channels' CKB capacity in shannons, decimal.
+    pub ckb_capacity: String,
+    pub udts: Vec<IssuerUdt>,
+}
+
+/// Issuer of a UDT by its hex args, args shorter than a lock hash are taken whole.
+fn udt_issuer(args: &str) -> String {
+    format!("0x{}", &args[..args.len().min(64)])
+}
+
+/// Online channels and their capacity per UDT issuer, largest CKB capacity first.
+pub async fn query_udt_issuer_stats(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<Vec<UdtIssuerStats>, sqlx::Error> {
+    let _timer = query_timer("query_udt_issuer_stats");
+    let sql = format!(
+        "SELECT u.id, u.name, u.args, count(*) AS channels,
+            COALESCE(sum(hex_to_numeric(n.capacity)), 0)::numeric(39, 0)::text AS amount,
+            COALESCE(sum(hex_to_numeric(v.capacity)), 0)::numeric(39, 0)::text AS ckb_capacity
+        FROM {} n
+        JOIN {} u ON u.id = n.udt_type_script
+        LEFT JOIN {} v ON v.channel_outpoint = n.channel_outpoint
+        WHERE n.bucket >= $1::timestamp AND NOT u.hidden
+        GROUP BY u.id, u.name, u.args
+        ORDER BY u.id",
+        net.mv_online_channels(),
+        net.udt_infos(),
+        net.channel_states()
+    );
+    let mut issuers: Vec<(UdtIssuerStats, u128)> = Vec::new();
+    for row in sqlx::query(&sql)
+        .bind(online_since())
+        .fetch_all(pool)
+        .await?
+    {
+        let issuer = udt_issuer(row.get::<&str, _>("args"));
+        let channels: i64 = row.get("channels");
+        let ckb_capacity: u128 = row
+            .get::<String, _>("ckb_capacity")
+            .parse()
+            .unwrap_or_default();
+        let udt = IssuerUdt {
+            udt_info_id: row.get("id"),
+            name: row.get("name"),
+            channels,
+            amount: row.get("amount"),
+        };
+        match issuers.iter_mut().find(|(stats, _)| stats.issuer == issuer) {
+            Some((stats, capacity)) => {
+                stats.channels += channels;
+                *capacity += ckb_capacity;
+                stats.udts.push(udt);
+            }
+            None => issuers.push((
+                UdtIssuerStats {
+                    issuer,
+                    channels,
+                    ckb_capacity: String::new(),
+                    udts: vec![udt],
+                },
+                ckb_capacity,
+            )),
+        }
+    }
+    issuers.sort_by(|(a, a_capacity), (b, b_capacity)| {
+        b_capacity
+            .cmp(a_capacity)
+            .then_with(|| a.issuer.cmp(&b.issuer))
+    });
+    Ok(issuers
+        .into_iter()
+        .map(|(mut stats, capacity)| {
+            stats.ckb_capacity = capacity.to_string();
+            stats
+        })
+        .collect())
+}
+
+/// Enabled directions announcing a smaller tlc expiry delta are flagged, they leave too little
+/// time to settle a forwarded tlc on chain.
+pub static TLC_MIN_SAFE_EXPIRY_DELTA_MS: LazyLock<i64> =
+    LazyLock::new(|| config::parse_var("TLC_MIN_SAFE_EXPIRY_DELTA_MS").unwrap_or(15 * 60 * 1000));
+
+#[derive(Debug, Serialize)]
+pub struct TlcDirection {
+    pub channel_outpoint: String,
+    /// Node the direction's channel update belongs to.
+    pub node_id: String,
+    pub tlc_expiry_delta_ms: f64,
+    pub tlc_minimum_value: f64,
+    pub below_safe_expiry_delta: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TlcParamsOverview {
+    pub directions: i64,
+    pub min_safe_expiry_delta_ms: i64,
+    /// Minimum, 10th, 50th and 90th percentile and maximum.
+    pub expiry_delta_ms: Vec<f64>,
+    /// Minimum, 10th, 50th and 90th percentile and maximum.
+    pub minimum_value: Vec<f64>,
+    /// Every direction of the node when drilling down, flagged directions otherwise.
+    pub listed: Vec<TlcDirection>,
+}
+
+/// Tlc parameters of the enabled directions of online channels, of one node's directions
+/// when `node_id` is given.
+pub async fn query_tlc_params_overview(
+    pool: &Pool<Postgres>,
+    net: Network,
+    node_id: Option<JsonBytes>,
+) -> Result<TlcParamsOverview, sqlx::Error> {
+    let _timer = query_timer("query_tlc_params_overview");
+    let directions = format!(
+        "WITH directions AS (
+            SELECT channel_outpoint, node1 AS node_id,
+                hex_to_numeric(update_of_node1_tlc_expiry_delta) AS expiry_delta,
+                hex_to_numeric(update_of_node1_tlc_minimum_value) AS minimum_value
+            FROM {channels}
+            WHERE update_of_node1_enabled AND update_of_node1_tlc_expiry_delta IS NOT NULL
+            UNION ALL
+            SELECT channel_outpoint, node2 AS node_id,
+                hex_to_numeric(update_of_node2_tlc_expiry_delta) AS expiry_delta,
+                hex_to_numeric(update_of_node2_tlc_minimum_value) AS minimum_value
+            FROM {channels}
+            WHERE update_of_node2_enabled AND update_of_node2_tlc_expiry_delta IS NOT NULL
+        )",
+        channels = net.mv_online_channels()
+    );
+    let summary_sql = format!(
+        "{directions}
+        SELECT count(*) AS directions,
+            CASE WHEN count(*) = 0 THEN '{{}}'::float8[] ELSE array[min(expiry_delta)::float8]
+                || percentile_cont(ARRAY[0.1, 0.5, 0.9]) WITHIN GROUP (ORDER BY expiry_delta)
+                || max(expiry_delta)::float8 END AS expiry_delta,
+            CASE WHEN count(*) = 0 THEN '{{}}'::float8[] ELSE array[min(minimum_value)::float8]
+                || percentile_cont(ARRAY[0.1, 0.5, 0.9]) WITHIN GROUP (ORDER BY minimum_value)
+                || max(minimum_value)::float8 END AS minimum_value
+        FROM directions
+        WHERE $1::text IS NULL OR node_id = $1"
+    );
+    let listed_sql = format!(
+        "{directions}
+        SELECT channel_outpoint, node_id, expiry_delta::float8 AS expiry_delta,
+            minimum_value::float8 AS minimum_value, expiry_delta < $2 AS below_safe
+        FROM directions
+        WHERE node_id = $1 OR ($1::text IS NULL AND expiry_delta < $2)
+        ORDER BY expiry_delta, channel_outpoint"
+    );
+    let node_id = node_id.map(|node_id| faster_hex::hex_string(node_id.as_bytes()));
+    let min_safe = *TLC_MIN_SAFE_EXPIRY_DELTA_MS;
+
+    let row = sqlx::query(&summary_sql)
+        .bind(&node_id)
+        .fetch_one(pool)
+        .await?;
+    let listed = sqlx::query(&listed_sql)
+        .bind(&node_id)
+        .bind(min_safe)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| TlcDirection {
+            channel_outpoint: format!("0x{}", row.get::<String, _>("channel_outpoint")),
+            node_id: format!("0x{}", row.get::<String, _>("node_id")),
+            tlc_expiry_delta_ms: row.get("expiry_delta"),
+            tlc_minimum_value: row.get("minimum_value"),
+            below_safe_expiry_delta: row.get("below_safe"),
+        })
+        .collect();
+    Ok(TlcParamsOverview {
+        directions: row.get("directions"),
+        min_safe_expiry_delta_ms: min_safe,
+        expiry_delta_ms: row.get("expiry_delta"),
+        minimum_value: row.get("minimum_value"),
+        listed,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisabledSide {
+    Node1,
+    Node2,
+    Both,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DisabledChannel {
+    pub channel_outpoint: String,
+    pub node1: String,
+    pub node2: String,
+    pub disabled_by: DisabledSide,
+    /// Timestamp of node1's update disabling its direction.
+    pub node1_disabled_since: Option<DateTime<Utc>>,
+    pub node2_disabled_since: Option<DateTime<Utc>>,
+}
+
+/// Online channels with a direction disabled for more than `hours` hours, longest disabled
+/// first. A direction is disabled since its latest channel update.
+pub async fn query_disabled_channels(
+    pool: &Pool<Postgres>,
+    net: Network,
+    hours: i64,
+) -> Result<Vec<DisabledChannel>, sqlx::Error> {
+    let _timer = query_timer("query_disabled_channels");
+    let sql = format!(
+        "SELECT channel_outpoint, node1, node2, node1_since, node2_since FROM (
+            SELECT channel_outpoint, node1, node2,
+                CASE WHEN NOT update_of_node1_enabled AND update_of_node1_timestamp < $1
+                    THEN update_of_node1_timestamp END AS node1_since,
+                CASE WHEN NOT update_of_node2_enabled AND update_of_node2_timestamp < $1
+                    THEN update_of_node2_timestamp END AS node2_since
+            FROM {}
+        ) c
+        WHERE node1_since IS NOT NULL OR node2_since IS NOT NULL
+        ORDER BY LEAST(node1_since, node2_since), channel_outpoint",
+        net.mv_online_channels()
+    );
+    Ok(sqlx::query(&sql)
+        .bind(Utc::now() - chrono::Duration::hours(hours))
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let node1_disabled_since: Option<DateTime<Utc>> = row.get("node1_since");
+            let node2_disabled_since: Option<DateTime<Utc>> = row.get("node2_since");
+            DisabledChannel {
+                channel_outpoint: format!("0x{}", row.get::<String, _>("channel_outpoint")),
+                node1: format!("0x{}", row.get::<String, _>("node1")),
+                node2: format!("0x{}", row.get::<String, _>("node2")),
+                disabled_by: match (node1_disabled_since, node2_disabled_since) {
+                    (Some(_), Some(_)) => DisabledSide::Both,
+                    (Some(_), None) => DisabledSide::Node1,
+                    _ => DisabledSide::Node2,
+                },
+                node1_disabled_since,
+                node2_disabled_since,
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClosedMonth {
+    pub month: chrono::NaiveDate,
+    pub channels: i64,
+    pub capacity_ckb: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NodeChannelStats {
+    pub node_id: String,
+    /// Channels the node ever announced per on-chain state, `unknown` when not tracked.
+    pub states: std::collections::BTreeMap<String, i64>,
+    /// Channels among them currently online.
+    pub online: i64,
+    /// Channels closed per month by the time of their last transaction.
+    pub closed_by_month: Vec<ClosedMonth>,
+}
+
+/// State breakdown of every channel `node_id` appears in within the announced history.
+pub async fn query_node_channel_stats(
+    pool: &Pool<Postgres>,
+    net: Network,
+    node_id: JsonBytes,
+) -> Result<NodeChannelStats, sqlx::Error> {
+    let _timer = query_timer("query_node_channel_stats");
+    let node_channels = format!(
+        "WITH node_channels AS (
+            SELECT DISTINCT channel_outpoint FROM {}
+            WHERE node1 = $1 OR node2 = $1
+        )",
+        net.channel_infos()
+    );
+    let states_sql = format!(
+        "{node_channels}
+        SELECT COALESCE(s.state, 'unknown') AS state, count(*) AS channels,
+            count(o.channel_outpoint) AS online
+        FROM node_channels c
+        LEFT JOIN {states} s ON s.channel_outpoint = c.channel_outpoint
+        LEFT JOIN {online} o ON o.channel_outpoint = c.channel_outpoint
+        GROUP BY 1",
+        states = net.channel_states(),
+        online = net.mv_online_channels(),
+    );
+    // capacity is a big endian u64 hex string of shannons
+    let closed_sql = format!(
+        "{node_channels}
+        SELECT date_trunc('month', s.last_commit_time)::date AS month, count(*) AS channels,
+            (sum(('x' || s.capacity)::bit(64)::bigint) / 100000000)::bigint AS capacity_ckb
+        FROM node_channels c
+        JOIN {states} s ON s.channel_outpoint = c.channel_outpoint
+        WHERE s.state IN ('closed_cooperative', 'closed_uncooperative')
+        GROUP BY 1
+        ORDER BY 1",
+        states = net.channel_states(),
+    );
+    let hex_node_id = faster_hex::hex_string(node_id.as_bytes());
+
+    let mut states = std::collections::BTreeMap::new();
+    let mut online = 0;
+    for row in sqlx::query(&states_sql)
+        .bind(&hex_node_id)
+        .fetch_all(pool)
+        .await?
+    {
+        online += row.get::<i64, _>("online");
+        states.insert(row.get("state"), row.get("channels"));
+    }
+    let closed_by_month = sqlx::query(&closed_sql)
+        .bind(&hex_node_id)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| ClosedMonth {
+            month: row.get("month"),
+            channels: row.get("channels"),
+            capacity_ckb: row.get("capacity_ckb"),
+        })
+        .collect();
+    Ok(NodeChannelStats {
+        node_id: format!("0x{}", hex_node_id),
+        states,
+        online,
+        closed_by_month,
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct GeoCapacity {
+    pub lat: f64,
+    pub long: f64,
+    pub nodes: i64,
+    /// Sum of the on-chain capacity of the nodes' online channels, a channel counts for both
+    /// of its nodes.
+    pub capacity_ckb: i64,
+}
+
+/// Online nodes and their channel capacity per `loc` rounded to `precision` decimals, largest
+/// capacity first. Nodes without a location are left out.
+pub async fn query_geo_capacity(
+    pool: &Pool<Postgres>,
+    net: Network,
+    precision: i32,
+) -> Result<Vec<GeoCapacity>, sqlx::Error> {
+    let _timer = query_timer("query_geo_capacity");
+    // capacity is a big endian u64 hex string of shannons
+    let sql = format!(
+        "WITH channel_nodes AS (
+            SELECT channel_outpoint, node1 AS node FROM {channels}
+            UNION ALL
+            SELECT channel_outpoint, node2 AS node FROM {channels}
+        ),
+        node_capacity AS (
+            SELECT c.node, sum(('x' || s.capacity)::bit(64)::bigint) AS capacity
+            FROM channel_nodes c
+            JOIN {states} s ON s.channel_outpoint = c.channel_outpoint
+            GROUP BY c.node
+        )
+        SELECT round(split_part(n.loc, ',', 1)::numeric, $1)::float8 AS lat,
+            round(split_part(n.loc, ',', 2)::numeric, $1)::float8 AS long,
+            count(*) AS nodes,
+            (COALESCE(sum(c.capacity), 0) / 100000000)::bigint AS capacity_ckb
+        FROM {nodes} n
+        LEFT JOIN node_capacity c ON c.node = n.node_id
+        LEFT JOIN node_address_scopes s ON s.net = $2 AND s.node_id = n.node_id
+        WHERE n.loc ~ '^-?[0-9.]+,-?[0-9.]+$'
+        -- locations looked up before scopes were stored may belong to private addresses
+        AND COALESCE(s.scope, 'global') = 'global'
+        GROUP BY 1, 2
+        ORDER BY capacity_ckb DESC, nodes DESC",
+        channels = net.mv_online_channels(),
+        states = net.channel_states(),
+        nodes = net.mv_online_nodes(),
+    );
+    Ok(sqlx::query(&sql)
+        .bind(precision)
+        .bind(net.name())
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| GeoCapacity {
+            lat: row.get("lat"),
+            long: row.get("long"),
+            nodes: row.get("nodes"),
+            capacity_ckb: row.get("capacity_ckb"),
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UngeolocatedReason {
+    /// No announced address carries an ip and tcp port.
+    NoIpAddress,
+    /// Every announced ip is private, loopback or otherwise not global.
+    PrivateIp,
+    /// A global ip was announced but the location lookup returned nothing.
+    LookupFailed,
+}
+
+impl From<AddressScope> for UngeolocatedReason {
+    fn from(scope: AddressScope) -> Self {
+        match scope {
+            AddressScope::NoIp => UngeolocatedReason::NoIpAddress,
+            AddressScope::Private => UngeolocatedReason::PrivateIp,
+            AddressScope::Global => UngeolocatedReason::LookupFailed,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UngeolocatedNode {
+    pub node_id: String,
+    pub node_name: String,
+    pub addresses: Vec<MultiAddr>,
+    pub last_seen_hour: String,
+    pub address_scope: AddressScope,
+    pub reason: UngeolocatedReason,
+}
+
+/// Online nodes without a country, with the reason their addresses could not be located.
+pub async fn query_nodes_ungeolocated(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<Vec<UngeolocatedNode>, sqlx::Error> {
+    let _timer = query_timer("query_nodes_ungeolocated");
+    let sql = format!(
+        "SELECT n.node_id, n.node_name, n.addresses, n.bucket, s.scope FROM {} n
+        LEFT JOIN node_address_scopes s ON s.net = $1 AND s.node_id = n.node_id
+        WHERE n.country_or_region IS NULL OR n.country_or_region = ''
+        ORDER BY n.node_id",
+        net.mv_online_nodes()
+    );
+    Ok(sqlx::query(&sql)
+        .bind(net.name())
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let addresses: Vec<MultiAddr> =
+                serde_json::from_str(row.get("addresses")).unwrap_or_default();
+            // nodes not collected since scopes are stored are classified here
+            let address_scope = row
+                .get::<Option<String>, _>("scope")
+                .and_then(|scope| AddressScope::parse(&scope))
+                .unwrap_or_else(|| AddressScope::of(&addresses));
+            UngeolocatedNode {
+                node_id: format!("0x{}", row.get::<String, _>("node_id")),
+                node_name: row.get("node_name"),
+                address_scope,
+                reason: address_scope.into(),
+                addresses,
+                last_seen_hour: row.get::<DateTime<Utc>, _>("bucket").to_rfc3339(),
+            }
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct CollidingNode {
+    pub node_id: String,
+    /// As announced, may differ from the shared name in case and surrounding spaces.
+    pub node_name: String,
+    pub last_seen_hour: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NameCollision {
+    /// Lowercased and trimmed name shared by the nodes.
+    pub name: String,
+    pub count: i64,
+    pub nodes: Vec<CollidingNode>,
+}
+
+/// Names announced by more than one online node, compared case insensitively, most shared
+/// first.
+pub async fn query_name_collisions(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<Vec<NameCollision>, sqlx::Error> {
+    let _timer = query_timer("query_name_collisions");
+    let sql = format!(
+        "WITH latest AS (
+            SELECT DISTINCT ON (node_id) node_id, node_name, bucket FROM {}
+            ORDER BY node_id, bucket DESC
+        )
+        SELECT lower(btrim(node_name)) AS name, count(*) AS count,
+            array_agg(node_id ORDER BY node_id) AS node_ids,
+            array_agg(node_name ORDER BY node_id) AS node_names,
+            array_agg(bucket ORDER BY node_id) AS buckets
+        FROM latest
+        WHERE btrim(node_name) <> ''
+        GROUP BY 1
+        HAVING count(*) > 1
+        ORDER BY count DESC, name",
+        net.mv_online_nodes()
+    );
+    Ok(sqlx::query(&sql)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let node_ids: Vec<String> = row.get("node_ids");
+            let node_names: Vec<String> = row.get("node_names");
+            let buckets: Vec<DateTime<Utc>> = row.get("buckets");
+            NameCollision {
+                name: row.get("name"),
+                count: row.get("count"),
+                nodes: node_ids
+                    .into_iter()
+                    .zip(node_names)
+                    .zip(buckets)
+                    .map(|((node_id, node_name), bucket)| CollidingNode {
+                        node_id: format!("0x{}", node_id),
+                        node_name,
+                        last_seen_hour: bucket.to_rfc3339(),
+                    })
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// Finalized hourly buckets of the last `days` days that have online nodes, newest first.
+/// The current hour is still aggregating and never listed.
+pub async fn query_snapshot_hours(
+    pool: &Pool<Postgres>,
+    net: Network,
+    days: i64,
+) -> Result<Vec<DateTime<Utc>>, sqlx::Error> {
+    let _timer = query_timer("query_snapshot_hours");
+    let sql = format!(
+        "SELECT DISTINCT bucket FROM {}
+        WHERE bucket >= date_trunc('hour', now()) - make_interval(days => $1::int)
+            AND bucket < date_trunc('hour', now())
+        ORDER BY bucket DESC",
+        net.online_nodes_hourly()
+    );
+    sqlx::query(&sql)
+        .bind(days as i32)
+        .fetch_all(pool)
+        .await
+        .map(|rows| rows.iter().map(|row| row.get("bucket")).collect())
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnlineMetric {
+    #[default]
+    Nodes,
+    Channels,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OnlineCount {
+    /// Start of the bucket.
+    pub time: DateTime<Utc>,
+    /// Distinct nodes or channels online at some point of the bucket.
+    pub count: i64,
+}
+
+/// Hours in a span like `12h`, `7d` or `2w`, `None` when malformed or not positive.
+pub(crate) fn span_hours(span: &str) -> Option<i64> {
+    let unit = span.chars().last()?;
+    let hours = match unit {
+        'h' => 1,
+        'd' => 24,
+        'w' => 7 * 24,
+        _ => return None,
+    };
+    span[..span.len() - unit.len_utf8()]
+        .parse::<i64>()
+        .ok()
+        .filter(|count| *count > 0)
+        .map(|count| count.saturating_mul(hours))
+}
+
+/// Online count per `bucket_hours` over the finalized hours of the last `range_hours` hours,
+/// read from the hourly continuous aggregate of `metric`. Buckets without data count zero.
+pub async fn query_online_series(
+    pool: &Pool<Postgres>,
+    net: Network,
+    metric: OnlineMetric,
+    range_hours: i64,
+    bucket_hours: i64,
+) -> Result<Vec<OnlineCount>, sqlx::Error> {
+    let _timer = query_timer("query_online_series");
+    let (table, id) = match metric {
+        OnlineMetric::Nodes => (net.online_nodes_hourly(), "node_id"),
+        OnlineMetric::Channels => (net.online_channels_hourly(), "channel_outpoint"),
+    };
+    let sql = format!(
+        "WITH bounds AS (
+            SELECT date_trunc('hour', now()) AS end_time,
+                make_interval(hours => $2::int) AS width
+        ),
+        counts AS (
+            SELECT time_bucket(b.width, o.bucket) AS time, count(DISTINCT o.{id}) AS count
+            FROM {table} o, bounds b
+            WHERE o.bucket >= b.end_time - make_interval(hours => $1::int)
+                AND o.bucket < b.end_time
+            GROUP BY 1
+        )
+        SELECT s.time, COALESCE(c.count, 0) AS count
+        FROM bounds b,
+            generate_series(
+                time_bucket(b.width, b.end_time - make_interval(hours => $1::int)),
+                b.end_time - interval '1 hour',
+                b.width
+            ) AS s(time)
+        LEFT JOIN counts c ON c.time = s.time
+        ORDER BY s.time"
+    );
+    Ok(sqlx::query(&sql)
+        .bind(range_hours as i32)
+        .bind(bucket_hours as i32)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| OnlineCount {
+            time: row.get("time"),
+            count: row.get("count"),
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct CapacityHistogramDay {
+    pub day: chrono::NaiveDate,
+    /// `ckb` or the udt name.
+    pub name: String,
+    /// Channels per bucket of [`CapacityHistogramSeries::buckets`].
+    pub counts: Vec<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CapacityHistogramSeries {
+    pub buckets: Vec<String>,
+    pub days: Vec<CapacityHistogramDay>,
+}
+
+/// Daily capacity histograms of the last `days` days persisted by the daily summary, oldest
+/// first, with the bucket labels of `/channel_capacity_distribution`.
+pub async fn query_capacity_histogram_series(
+    pool: &Pool<Postgres>,
+    net: Network,
+    days: i64,
+) -> Result<CapacityHistogramSeries, sqlx::Error> {
+    let _timer = query_timer("query_capacity_histogram_series");
+    let (start, end) = day_window(days);
+    let days = sqlx::query(
+        "SELECT day, name, counts FROM daily_capacity_histograms
+        WHERE net = $1 AND day >= $2 AND day < $3
+        ORDER BY day, name",
+    )
+    .bind(net.name())
+    .bind(start.date_naive())
+    .bind(end.date_naive())
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| CapacityHistogramDay {
+        day: row.get("day"),
+        name: row.get("name"),
+        counts: row.get("counts"),
+    })
+    .collect();
+    Ok(CapacityHistogramSeries {
+        buckets: (0..MAGNITUDE_BUCKETS)
+            .map(|i| format!("Capacity 10^{}k", i))
+            .collect(),
+        days,
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrivateChannelDay {
+    pub day: chrono::NaiveDate,
+    /// Live funding cells found on chain.
+    pub funding_cells: i32,
+    /// Those of them announced in gossip.
+    pub announced: i32,
+    pub unannounced: i32,
+    /// Unannounced funding cells in percent of all, `None` without any.
+    pub private_share: Option<f64>,
+    /// Capacity of the live funding cells in CKB.
+    pub capacity: u64,
+    pub unannounced_capacity: u64,
+    pub private_capacity_share: Option<f64>,
+}
+
+/// Funding cells found on chain against the announced channels per day of the last `days`
+/// days, oldest first, as recorded by the chain scanner. Today is the latest scan so far.
+pub async fn query_private_channel_estimate(
+    pool: &Pool<Postgres>,
+    net: Network,
+    days: i64,
+) -> Result<Vec<PrivateChannelDay>, sqlx::Error> {
+    let _timer = query_timer("query_private_channel_estimate");
+    let (start, end) = day_window(days);
+    let share = |part: i64, whole: i64| (whole > 0).then(|| part as f64 / whole as f64 * 100.0);
+    let ckb = |shannons: i64| shannons.max(0) as u64 / 100_000_000;
+    Ok(sqlx::query(
+        "SELECT day, funding_cells, unannounced, capacity, unannounced_capacity
+        FROM private_channel_share
+        WHERE net = $1 AND day >= $2 AND day < $3
+        ORDER BY day",
+    )
+    .bind(net.name())
+    .bind(start.date_naive())
+    .bind(end.date_naive())
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| {
+        let funding_cells: i32 = row.get("funding_cells");
+        let unannounced: i32 = row.get("unannounced");
+        let capacity: i64 = row.get("capacity");
+        let unannounced_capacity: i64 = row.get("unannounced_capacity");
+        PrivateChannelDay {
+            day: row.get("day"),
+            funding_cells,
+            announced: funding_cells - unannounced,
+            unannounced,
+            private_share: share(unannounced.into(), funding_cells.into()),
+            capacity: ckb(capacity),
+            unannounced_capacity: ckb(unannounced_capacity),
+            private_capacity_share: share(unannounced_capacity, capacity),
+        }
+    })
+    .collect())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{build_asset_filter_clause, normalize_asset_names};
+    use ckb_jsonrpc_types::{JsonBytes, Script, ScriptHashType};
+    use ckb_types::H256;
+
+    use super::{
+        build_asset_filter_clause, normalize_asset_names, range_days, resolve_udt, span_hours,
+        udt_issuer,
+    };
+    use crate::pg_write::{RelationCache, UdtValues};
+
+    #[test]
+    fn xudt_flags_are_not_part_of_the_issuer() {
+        let owner = "ab".repeat(32);
+        assert_eq!(udt_issuer(&owner), format!("0x{}", owner));
+        assert_eq!(
+            udt_issuer(&format!("{}00000000", owner)),
+            format!("0x{}", owner)
+        );
+        assert_eq!(udt_issuer("01"), "0x01");
+    }
+
+    #[test]
+    fn udts_resolve_by_script_id_or_symbol() {
+        let script = |byte| Script {
+            code_hash: H256([byte; 32]),
+            hash_type: ScriptHashType::Type,
+            args: JsonBytes::default(),
+        };
+        let values = |name: &str| UdtValues {
+            name: name.to_string(),
+            auto_accept_amount: None,
+        };
+        let mut cache = RelationCache::default();
+        cache.udt.insert(script(1), 1);
+        cache.udt.insert(script(2), 2);
+        cache.udt.insert(script(3), 3);
+        cache.udt_values.insert(1, values("RUSD"));
+        cache.udt_values.insert(2, values("rusd"));
+        cache.udt_values.insert(3, values("SEAL"));
+
+        assert_eq!(
+            resolve_udt(&cache, Some(&script(3)), Some(1), None),
+            vec![3]
+        );
+        assert_eq!(resolve_udt(&cache, None, Some(2), Some("SEAL")), vec![2]);
+        assert_eq!(resolve_udt(&cache, None, None, Some("Rusd")), vec![1, 2]);
+        assert!(resolve_udt(&cache, Some(&script(4)), None, None).is_empty());
+        assert!(resolve_udt(&cache, None, Some(4), None).is_empty());
+        assert!(resolve_udt(&cache, None, None, None).is_empty());
+    }
 
     #[test]
     fn asset_filter_none_builds_empty_clause() {
@@ -1292,4 +2329,21 @@ mod tests {
         let output = normalize_asset_names(&input);
         assert_eq!(output, Some(vec!["ckb".to_owned(), "usdt".to_owned()]));
     }
+
+    #[test]
+    fn unknown_range_defaults_to_one_month() {
+        assert_eq!(range_days("1Y"), 365);
+        assert_eq!(range_days("1W"), 30);
+    }
+
+    #[test]
+    fn span_hours_parses_units() {
+        assert_eq!(span_hours("7d"), Some(168));
+        assert_eq!(span_hours("1h"), Some(1));
+        assert_eq!(span_hours("2w"), Some(336));
+        assert_eq!(span_hours("0h"), None);
+        assert_eq!(span_hours("d"), None);
+        assert_eq!(span_hours(""), None);
+        assert_eq!(span_hours("7m"), None);
+    }
 }
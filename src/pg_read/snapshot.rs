@@ -0,0 +1,481 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use arc_swap::ArcSwapOption;
+use chrono::{DateTime, DurationRound, Utc};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    ENABLED_NETWORKS, Network,
+    error::skip_malformed,
+    events::{self, Event},
+    http_server::{
+        ListNodesHourlyParams, ListNodesHourlySortBy, NodeByRegion, NodesByUdt, Order, Page,
+    },
+    pg_read::{
+        ChannelInfo, HourlyChannelInfoDBRead, HourlyNodeInfo, HourlyNodeInfoDBRead, PAGE_SIZE,
+        init_statements, online_since, query_nodes_all_regions,
+    },
+};
+
+/// Refresh even without events, the online window slides with time.
+const FALLBACK_REFRESH: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Latest online nodes and channels of one network, kept in memory so the most hit list
+/// endpoints do not touch the database. Items carry their bucket so the online window
+/// is still applied at serve time.
+pub(crate) struct HotSnapshot {
+    refreshed_at: DateTime<Utc>,
+    /// Finalized hour of a snapshot rewound to with `as_of`, every item of it is served.
+    hour: Option<DateTime<Utc>>,
+    nodes: Vec<(DateTime<Utc>, HourlyNodeInfo)>,
+    channels: Vec<(DateTime<Utc>, ChannelInfo)>,
+}
+
+static MAINNET_SNAPSHOT: ArcSwapOption<HotSnapshot> = ArcSwapOption::const_empty();
+static TESTNET_SNAPSHOT: ArcSwapOption<HotSnapshot> = ArcSwapOption::const_empty();
+
+fn slot(net: Network) -> &'static ArcSwapOption<HotSnapshot> {
+    match net {
+        Network::Mainnet => &MAINNET_SNAPSHOT,
+        Network::Testnet => &TESTNET_SNAPSHOT,
+    }
+}
+
+/// `None` until the first load has finished, callers fall back to the database.
+pub(crate) fn hot_snapshot(net: Network) -> Option<Arc<HotSnapshot>> {
+    slot(net).load_full()
+}
+
+static MAINNET_REGIONS: ArcSwapOption<String> = ArcSwapOption::const_empty();
+static TESTNET_REGIONS: ArcSwapOption<String> = ArcSwapOption::const_empty();
+
+fn regions_slot(net: Network) -> &'static ArcSwapOption<String> {
+    match net {
+        Network::Mainnet => &MAINNET_REGIONS,
+        Network::Testnet => &TESTNET_REGIONS,
+    }
+}
+
+/// Serialized `all_region` response, `None` until the first load has finished.
+pub(crate) fn cached_regions(net: Network) -> Option<Arc<String>> {
+    regions_slot(net).load_full()
+}
+
+pub async fn refresh_regions(pool: &Pool<Postgres>, net: Network) -> Result<(), sqlx::Error> {
+    let regions = query_nodes_all_regions(pool, net).await?;
+    regions_slot(net).store(Some(Arc::new(regions)));
+    Ok(())
+}
+
+/// Whether every cache the API serves from has been loaded at least once.
+pub fn is_ready() -> bool {
+    ENABLED_NETWORKS
+        .iter()
+        .all(|&net| hot_snapshot(net).is_some() && cached_regions(net).is_some())
+}
+
+/// Load the hot snapshots and region lists before the listener is bound, so the first
+/// requests are not served cold. Failures are logged and left to the background refresher.
+pub async fn warm_up(pool: &Pool<Postgres>) {
+    init_statements();
+    for &net in ENABLED_NETWORKS.iter() {
+        if let Err(e) = refresh_hot_snapshot(pool, net).await {
+            log::warn!("Failed to warm up {:?} hot snapshot: {}", net, e);
+        }
+        if let Err(e) = refresh_regions(pool, net).await {
+            log::warn!("Failed to warm up {:?} region list: {}", net, e);
+        }
+    }
+    log::info!("Warm-up finished, ready: {}", is_ready());
+}
+
+pub async fn refresh_hot_snapshot(pool: &Pool<Postgres>, net: Network) -> Result<(), sqlx::Error> {
+    let since = online_since();
+    let nodes = HourlyNodeInfoDBRead::fetch_all_online(pool, net, since)
+        .await?
+        .into_iter()
+        .filter_map(|node| {
+            let hour = node.last_seen_hour;
+            skip_malformed("node", node).map(|node: HourlyNodeInfo| (hour, node))
+        })
+        .collect::<Vec<_>>();
+    let channels = HourlyChannelInfoDBRead::fetch_all_online(pool, net, since)
+        .await?
+        .into_iter()
+        .filter_map(|channel| {
+            let hour = channel.last_seen_hour;
+            skip_malformed("channel", channel).map(|channel: ChannelInfo| (hour, channel))
+        })
+        .collect::<Vec<_>>();
+    log::debug!(
+        "{:?} hot snapshot refreshed with {} nodes and {} channels",
+        net,
+        nodes.len(),
+        channels.len()
+    );
+    slot(net).store(Some(Arc::new(HotSnapshot::new(nodes, channels))));
+    Ok(())
+}
+
+type PastSnapshot = (Network, DateTime<Utc>, Arc<HotSnapshot>);
+
+/// Past hours rewound to, most recently used first.
+static PAST_SNAPSHOTS: LazyLock<Mutex<VecDeque<PastSnapshot>>> = LazyLock::new(Mutex::default);
+const PAST_SNAPSHOTS_KEPT: usize = 8;
+
+/// Snapshot of the finalized hour containing `as_of`, built from the hourly aggregates like
+/// the `/snapshots` files. `None` for the current hour and later, which the live data serves.
+pub(crate) async fn past_snapshot(
+    pool: &Pool<Postgres>,
+    net: Network,
+    as_of: DateTime<Utc>,
+) -> Result<Option<Arc<HotSnapshot>>, sqlx::Error> {
+    let hour = |time: DateTime<Utc>| time.duration_trunc(chrono::Duration::hours(1)).ok();
+    let (Some(hour), Some(current_hour)) = (hour(as_of), hour(Utc::now())) else {
+        return Ok(None);
+    };
+    if hour >= current_hour {
+        return Ok(None);
+    }
+    {
+        let mut kept = PAST_SNAPSHOTS.lock().unwrap();
+        if let Some(index) = kept.iter().position(|(n, h, _)| *n == net && *h == hour) {
+            let entry = kept.remove(index).unwrap();
+            let snapshot = entry.2.clone();
+            kept.push_front(entry);
+            return Ok(Some(snapshot));
+        }
+    }
+    let nodes = HourlyNodeInfoDBRead::fetch_hour(pool, net, hour)
+        .await?
+        .into_iter()
+        .filter_map(|node| {
+            let hour = node.last_seen_hour;
+            skip_malformed("node", node).map(|node: HourlyNodeInfo| (hour, node))
+        })
+        .collect::<Vec<_>>();
+    let channels = HourlyChannelInfoDBRead::fetch_hour(pool, net, hour)
+        .await?
+        .into_iter()
+        .filter_map(|channel| {
+            let hour = channel.last_seen_hour;
+            skip_malformed("channel", channel).map(|channel: ChannelInfo| (hour, channel))
+        })
+        .collect::<Vec<_>>();
+    let snapshot = Arc::new(HotSnapshot {
+        hour: Some(hour),
+        ..HotSnapshot::new(nodes, channels)
+    });
+    let mut kept = PAST_SNAPSHOTS.lock().unwrap();
+    kept.push_front((net, hour, snapshot.clone()));
+    kept.truncate(PAST_SNAPSHOTS_KEPT);
+    Ok(Some(snapshot))
+}
+
+/// Keep the hot snapshots of the enabled networks fresh, reloading after every committed collector
+/// snapshot or materialized view refresh. Region lists follow the hourly view refresh.
+pub async fn hot_snapshot_refresher(pool: &'static Pool<Postgres>) {
+    let mut rx = events::subscribe();
+    let mut timer = tokio::time::interval(FALLBACK_REFRESH);
+    timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        let nets = tokio::select! {
+            scheduled = timer.tick() => {
+                crate::metrics::observe_interval_lag("hot_snapshot_refresh", scheduled);
+                ENABLED_NETWORKS.clone()
+            }
+            event = rx.recv() => match event {
+                Ok(Event::SnapshotCommitted { net, .. }) => vec![net],
+                Ok(Event::AggregatesRefreshed { net, .. }) => {
+                    if let Err(e) = refresh_regions(pool, net).await {
+                        log::warn!("Failed to refresh {:?} region list: {}", net, e);
+                    }
+                    vec![net]
+                }
+                Ok(_) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {
+                    ENABLED_NETWORKS.clone()
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return,
+            },
+        };
+        for net in nets.into_iter().filter(Network::enabled) {
+            if let Err(e) = refresh_hot_snapshot(pool, net).await {
+                log::warn!("Failed to refresh {:?} hot snapshot: {}", net, e);
+            }
+            // region list scans node history, only retry it while it is missing
+            if cached_regions(net).is_none()
+                && let Err(e) = refresh_regions(pool, net).await
+            {
+                log::warn!("Failed to refresh {:?} region list: {}", net, e);
+            }
+        }
+    }
+}
+
+/// Postgres order: `NULLS LAST` for ascending, `NULLS FIRST` for descending.
+fn cmp_nullable<T: Ord>(a: &Option<T>, b: &Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+    }
+}
+
+fn paging(page: usize, page_size: Option<usize>) -> (usize, usize) {
+    let page_size = std::cmp::min(page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
+    (page.saturating_mul(page_size), page_size)
+}
+
+/// Which of the `edges` between `nodes` nodes, sorted by capacity descending, belong to the
+/// maximum capacity spanning forest (kruskal) or to the `top_k` largest edges of an endpoint.
+pub(crate) fn backbone_edges(nodes: usize, edges: &[(usize, usize)], top_k: usize) -> Vec<bool> {
+    fn root(parent: &mut [usize], mut node: usize) -> usize {
+        while parent[node] != node {
+            parent[node] = parent[parent[node]];
+            node = parent[node];
+        }
+        node
+    }
+    let mut parent = (0..nodes).collect::<Vec<_>>();
+    let mut degree = vec![0; nodes];
+    edges
+        .iter()
+        .map(|&(a, b)| {
+            let top = degree[a] < top_k || degree[b] < top_k;
+            degree[a] += 1;
+            degree[b] += 1;
+            let (root_a, root_b) = (root(&mut parent, a), root(&mut parent, b));
+            if root_a != root_b {
+                parent[root_a] = root_b;
+                return true;
+            }
+            top
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+pub(crate) struct GraphSnapshot<'a> {
+    refreshed_at: DateTime<Utc>,
+    nodes: Vec<&'a HourlyNodeInfo>,
+    channels: Vec<&'a ChannelInfo>,
+}
+
+impl HotSnapshot {
+    pub(crate) fn new(
+        nodes: Vec<(DateTime<Utc>, HourlyNodeInfo)>,
+        mut channels: Vec<(DateTime<Utc>, ChannelInfo)>,
+    ) -> Self {
+        // channels_hourly has a single fixed order, sort once here
+        channels.sort_by(|(_, a), (_, b)| {
+            b.capacity
+                .cmp(&a.capacity)
+                .then_with(|| a.channel_outpoint.cmp(&b.channel_outpoint))
+        });
+        HotSnapshot {
+            refreshed_at: Utc::now(),
+            hour: None,
+            nodes,
+            channels,
+        }
+    }
+
+    /// Oldest bucket served, the start of the online window for live snapshots.
+    fn since(&self) -> DateTime<Utc> {
+        self.hour.unwrap_or_else(online_since)
+    }
+
+    /// Same result as `HourlyNodeInfoDBRead::fetch_by_page_hourly`.
+    pub(crate) fn nodes_page(
+        &self,
+        params: &ListNodesHourlyParams,
+    ) -> (Vec<HourlyNodeInfo>, usize, usize) {
+        self.nodes_page_where(
+            params.page,
+            params.page_size,
+            &params.sort_by,
+            &params.order,
+            |_| true,
+        )
+    }
+
+    /// Nodes in country or region `region`, as `nodes_by_region` lists them.
+    pub(crate) fn region_page(&self, params: &NodeByRegion) -> (Vec<HourlyNodeInfo>, usize, usize) {
+        self.nodes_page_where(
+            params.page,
+            params.page_size,
+            &params.sort_by,
+            &params.order,
+            |node| node.country_or_region.as_deref() == Some(params.region.as_str()),
+        )
+    }
+
+    /// Nodes among `node_ids` (hex without `0x`), as `nodes_by_udt` lists them.
+    pub(crate) fn udt_page(
+        &self,
+        params: &NodesByUdt,
+        node_ids: &HashSet<String>,
+    ) -> (Vec<HourlyNodeInfo>, usize, usize) {
+        self.nodes_page_where(
+            params.page,
+            params.page_size,
+            &params.sort_by,
+            &params.order,
+            |node| {
+                node_ids.contains(node.node_id.trim_start_matches("0x"))
+                    && params
+                        .region
+                        .as_deref()
+                        .is_none_or(|region| node.country_or_region.as_deref() == Some(region))
+            },
+        )
+    }
+
+    fn nodes_page_where(
+        &self,
+        page: usize,
+        page_size: Option<usize>,
+        sort_by: &ListNodesHourlySortBy,
+        order: &Order,
+        keep: impl Fn(&HourlyNodeInfo) -> bool,
+    ) -> (Vec<HourlyNodeInfo>, usize, usize) {
+        let since = self.since();
+        let mut nodes = self
+            .nodes
+            .iter()
+            .filter(|(bucket, node)| *bucket >= since && keep(node))
+            .collect::<Vec<_>>();
+        nodes.sort_by(|(a_seen, a), (b_seen, b)| {
+            let ord = match sort_by {
+                ListNodesHourlySortBy::Region => {
+                    cmp_nullable(&a.country_or_region, &b.country_or_region)
+                }
+                ListNodesHourlySortBy::LastSeen => a_seen.cmp(b_seen),
+                ListNodesHourlySortBy::ChannelCount => a.channel_count.cmp(&b.channel_count),
+            };
+            let ord = match order {
+                Order::Asc => ord,
+                Order::Desc => ord.reverse(),
+            };
+            ord.then_with(|| a.node_id.cmp(&b.node_id))
+        });
+        let total_count = nodes.len();
+        let (offset, page_size) = paging(page, page_size);
+        let nodes = nodes
+            .into_iter()
+            .skip(offset)
+            .take(page_size)
+            .map(|(_, node)| node.clone())
+            .collect();
+        (nodes, page.saturating_add(1), total_count)
+    }
+
+    /// Same result as `HourlyChannelInfoDBRead::fetch_by_page_hourly`.
+    pub(crate) fn channels_page(&self, params: &Page) -> (Vec<ChannelInfo>, usize, usize) {
+        let since = self.since();
+        let located = params.has_location_filter().then(|| {
+            self.nodes
+                .iter()
+                .map(|(_, node)| {
+                    let matches = params.location_matches(
+                        node.country_or_region.as_deref(),
+                        node.region.as_deref(),
+                    );
+                    (node.node_id.as_str(), matches)
+                })
+                .collect::<HashMap<_, _>>()
+        });
+        let channels = self
+            .channels
+            .iter()
+            .filter(|(bucket, _)| *bucket >= since)
+            .filter(|(_, channel)| {
+                params.channel_matches(channel.capacity, channel.udt_name.as_deref())
+            })
+            .filter(|(_, channel)| {
+                located.as_ref().is_none_or(|located| {
+                    let matches = |node: &str| located.get(node).copied().unwrap_or(false);
+                    params
+                        .endpoint_match
+                        .combine(matches(&channel.node1), matches(&channel.node2))
+                })
+            })
+            .collect::<Vec<_>>();
+        let total_count = channels.len();
+        let (offset, page_size) = paging(params.page, params.page_size);
+        let page = channels
+            .into_iter()
+            .skip(offset)
+            .take(page_size)
+            .map(|(_, channel)| channel.clone())
+            .collect();
+        (page, params.page.saturating_add(1), total_count)
+    }
+
+    /// Every online node with the maximum capacity spanning forest of the online channels,
+    /// plus the `top_k` largest channels of every node.
+    pub(crate) fn backbone(&self, top_k: usize) -> GraphSnapshot<'_> {
+        let graph = self.graph();
+        let mut index = HashMap::new();
+        let mut endpoint = |node: &str| {
+            let next = index.len();
+            *index.entry(node.to_string()).or_insert(next)
+        };
+        let edges = graph
+            .channels
+            .iter()
+            .map(|channel| (endpoint(&channel.node1), endpoint(&channel.node2)))
+            .collect::<Vec<_>>();
+        let keep = backbone_edges(index.len(), &edges, top_k);
+        GraphSnapshot {
+            channels: graph
+                .channels
+                .into_iter()
+                .zip(keep)
+                .filter_map(|(channel, keep)| keep.then_some(channel))
+                .collect(),
+            ..graph
+        }
+    }
+
+    /// Every online node and channel, for clients rendering the whole graph.
+    pub(crate) fn graph(&self) -> GraphSnapshot<'_> {
+        let since = self.since();
+        GraphSnapshot {
+            refreshed_at: self.refreshed_at,
+            nodes: self
+                .nodes
+                .iter()
+                .filter(|(bucket, _)| *bucket >= since)
+                .map(|(_, node)| node)
+                .collect(),
+            channels: self
+                .channels
+                .iter()
+                .filter(|(bucket, _)| *bucket >= since)
+                .map(|(_, channel)| channel)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::backbone_edges;
+
+    #[test]
+    fn backbone_keeps_the_widest_spanning_forest() {
+        // triangle 0-1-2 sorted by capacity plus a separate 3-4 component
+        let edges = [(0, 1), (1, 2), (3, 4), (0, 2)];
+        assert_eq!(backbone_edges(5, &edges, 0), vec![true, true, true, false]);
+        // with top_k 2 every channel is among the two widest of an endpoint
+        assert_eq!(backbone_edges(5, &edges, 2), vec![true, true, true, true]);
+    }
+}
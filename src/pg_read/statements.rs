@@ -0,0 +1,86 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use crate::{
+    Network,
+    http_server::{ListNodesHourlySortBy, Order},
+    pg_read::{
+        hourly_channels_sql, hourly_nodes_sql, monthly_channels_sql, monthly_nodes_sql,
+        nodes_by_region_sql, nodes_fuzzy_by_name_sql,
+    },
+};
+
+type SortKey = (&'static str, &'static str);
+
+/// SQL of the hot list queries for one network, rendered once so every request sends the
+/// exact same text and reuses the per-connection prepared statement. Paging is bound as the
+/// last two parameters instead of being formatted into the query.
+pub(crate) struct Statements {
+    hourly_nodes: HashMap<SortKey, String>,
+    nodes_by_region: HashMap<SortKey, String>,
+    nodes_fuzzy_by_name: HashMap<SortKey, String>,
+    pub(crate) hourly_channels: String,
+    pub(crate) monthly_nodes: String,
+    pub(crate) monthly_channels: String,
+}
+
+impl Statements {
+    fn new(net: Network) -> Self {
+        let by_sort = |render: fn(Network, &str, &str) -> String, params: usize| {
+            let mut statements = HashMap::new();
+            for sort_by in ListNodesHourlySortBy::ALL {
+                for order in Order::ALL {
+                    let key = (sort_by.as_str(), order.as_str());
+                    statements.insert(key, paged(render(net, key.0, key.1), params));
+                }
+            }
+            statements
+        };
+        Statements {
+            hourly_nodes: by_sort(hourly_nodes_sql, 1),
+            nodes_by_region: by_sort(nodes_by_region_sql, 2),
+            nodes_fuzzy_by_name: by_sort(nodes_fuzzy_by_name_sql, 2),
+            hourly_channels: paged(hourly_channels_sql(net), 7),
+            monthly_nodes: paged(monthly_nodes_sql(net), 2),
+            monthly_channels: paged(monthly_channels_sql(net), 5),
+        }
+    }
+
+    pub(crate) fn hourly_nodes(&self, sort_by: &ListNodesHourlySortBy, order: &Order) -> &str {
+        &self.hourly_nodes[&(sort_by.as_str(), order.as_str())]
+    }
+
+    pub(crate) fn nodes_by_region(&self, sort_by: &ListNodesHourlySortBy, order: &Order) -> &str {
+        &self.nodes_by_region[&(sort_by.as_str(), order.as_str())]
+    }
+
+    pub(crate) fn nodes_fuzzy_by_name(
+        &self,
+        sort_by: &ListNodesHourlySortBy,
+        order: &Order,
+    ) -> &str {
+        &self.nodes_fuzzy_by_name[&(sort_by.as_str(), order.as_str())]
+    }
+}
+
+/// Append `LIMIT/OFFSET` placeholders after the `params` parameters the query already uses.
+fn paged(sql: String, params: usize) -> String {
+    format!("{} LIMIT ${} OFFSET ${}", sql, params + 1, params + 2)
+}
+
+static MAINNET_STATEMENTS: LazyLock<Statements> =
+    LazyLock::new(|| Statements::new(Network::Mainnet));
+static TESTNET_STATEMENTS: LazyLock<Statements> =
+    LazyLock::new(|| Statements::new(Network::Testnet));
+
+pub(crate) fn statements(net: Network) -> &'static Statements {
+    match net {
+        Network::Mainnet => &MAINNET_STATEMENTS,
+        Network::Testnet => &TESTNET_STATEMENTS,
+    }
+}
+
+/// Render every statement up front instead of on the first request.
+pub fn init_statements() {
+    LazyLock::force(&MAINNET_STATEMENTS);
+    LazyLock::force(&TESTNET_STATEMENTS);
+}
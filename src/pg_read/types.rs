@@ -1,3 +1,5 @@
+use std::sync::LazyLock;
+
 use sqlx::types::chrono::{DateTime, Utc};
 use sqlx::{FromRow, Pool, Postgres, Row, postgres::PgRow};
 
@@ -7,12 +9,51 @@ use multiaddr::MultiAddr;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 
-use crate::http_server::{FuzzyNodeName, ListNodesHourlyParams, NodeByRegion, Page};
+use crate::http_server::{
+    EndpointMatch, FuzzyNodeName, ListNodesHourlyParams, ListNodesHourlySortBy, NodeByRegion,
+    NodesByUdt, Order, Page,
+};
 use crate::{
-    Network,
+    Network, codec, config,
+    error::{FiberDashboardError, decode_hex, decode_script},
+    pg_read::statements,
     types::{ChannelUpdateInfo, U64Hex, U128Hex},
 };
 
+/// Hours a node or channel stays online after its last hourly bucket, `ONLINE_WINDOW_HOURS`.
+/// Capped at the 3 hours the `mv_online_*` views keep.
+pub static ONLINE_WINDOW_HOURS: LazyLock<i64> = LazyLock::new(|| {
+    config::parse_var("ONLINE_WINDOW_HOURS")
+        .unwrap_or(3)
+        .clamp(1, 3)
+});
+
+/// Hours a node's UDT support counts after the node last announced it,
+/// `UDT_RELATION_WINDOW_HOURS`.
+pub static UDT_RELATION_WINDOW_HOURS: LazyLock<i64> = LazyLock::new(|| {
+    config::parse_var("UDT_RELATION_WINDOW_HOURS")
+        .unwrap_or(72)
+        .max(1)
+});
+
+/// Oldest `last_confirmed` of the node UDT relations the apis read.
+pub fn udt_relation_since() -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::hours(*UDT_RELATION_WINDOW_HOURS)
+}
+
+/// Start of the online window.
+pub fn online_since() -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::hours(*ONLINE_WINDOW_HOURS)
+}
+
+/// Seconds between `last_seen` and `now`, and whether that is past the online window.
+pub fn staleness(last_seen: &str, now: DateTime<Utc>) -> (u64, bool) {
+    let seconds = DateTime::parse_from_rfc3339(last_seen)
+        .map(|last_seen| (now - last_seen.to_utc()).num_seconds().max(0) as u64)
+        .unwrap_or_default();
+    (seconds, seconds > *ONLINE_WINDOW_HOURS as u64 * 3600)
+}
+
 const SELECT_HOURLY_NODES_SQL: &str = "
 SELECT
   n.node_id as node_id,
@@ -29,7 +70,7 @@ SELECT
   n.channel_count,
   COUNT(*) OVER() as total_count
 FROM {nodes} n
-ORDER BY {sort_by} {order}";
+ORDER BY {sort_by} {order}, n.node_id ASC";
 
 const SELECT_HOURLY_CHANNELS_SQL: &str = "SELECT
   {1}.channel_outpoint,
@@ -61,8 +102,20 @@ const SELECT_HOURLY_CHANNELS_SQL: &str = "SELECT
 FROM {1}
 left join {2} on {1}.udt_type_script = {2}.id
 left join {3} on {1}.channel_outpoint = {3}.channel_outpoint
+left join {4} n1 on n1.node_id = {1}.node1
+left join {4} n2 on n2.node_id = {1}.node2
 WHERE bucket >= $1::timestamp
-ORDER BY {1}.channel_outpoint, bucket DESC";
+  -- $2 country, $3 region, $4 whether both endpoints have to match
+  AND CASE WHEN $4::boolean
+    THEN ($2::text IS NULL OR n1.country_or_region = $2) AND ($3::text IS NULL OR n1.region = $3)
+      AND ($2::text IS NULL OR n2.country_or_region = $2) AND ($3::text IS NULL OR n2.region = $3)
+    ELSE ($2::text IS NULL OR n1.country_or_region = $2) AND ($3::text IS NULL OR n1.region = $3)
+      OR ($2::text IS NULL OR n2.country_or_region = $2) AND ($3::text IS NULL OR n2.region = $3)
+  END
+  AND ($5::text IS NULL OR {3}.capacity >= $5)
+  AND ($6::text IS NULL OR {3}.capacity <= $6)
+  AND ($7::text IS NULL OR COALESCE({2}.name, 'ckb') = $7)
+ORDER BY {3}.capacity DESC NULLS LAST, {1}.channel_outpoint ASC";
 
 const SELECT_MONTHLY_NODES_SQL: &str = "
 WITH latest_channels AS (
@@ -93,7 +146,7 @@ SELECT DISTINCT ON (n.node_id)
   n.city,
   n.region,
   n.loc,
-  c.channel_count,
+  COALESCE(c.channel_count, 0) AS channel_count,
   COUNT(*) OVER() as total_count
 FROM {nodes} n
 LEFT JOIN channel_counts c ON n.node_id = c.node
@@ -131,9 +184,149 @@ FROM {1}
 left join {2} on {1}.udt_type_script = {2}.id
 left join {3} on {1}.channel_outpoint = {3}.channel_outpoint
 WHERE bucket >= $1::timestamp and bucket < $2::timestamp
+  -- capacity bounds as fixed width hex and udt name
+  AND ($3::text IS NULL OR {3}.capacity >= $3)
+  AND ($4::text IS NULL OR {3}.capacity <= $4)
+  AND ($5::text IS NULL OR COALESCE({2}.name, 'ckb') = $5)
 ORDER BY {1}.channel_outpoint, bucket DESC";
 pub const PAGE_SIZE: usize = 500;
 
+pub(crate) fn hourly_nodes_sql(net: Network, sort_by: &str, order: &str) -> String {
+    SELECT_HOURLY_NODES_SQL
+        .replace("{nodes}", net.mv_online_nodes())
+        .replace("{sort_by}", sort_by)
+        .replace("{order}", order)
+}
+
+pub(crate) fn monthly_nodes_sql(net: Network) -> String {
+    SELECT_MONTHLY_NODES_SQL.replace("{nodes}", net.online_nodes_hourly())
+}
+
+pub(crate) fn hourly_channels_sql(net: Network) -> String {
+    SELECT_HOURLY_CHANNELS_SQL
+        .replace("{1}", net.mv_online_channels())
+        .replace("{2}", net.udt_infos())
+        .replace("{3}", net.channel_states())
+        .replace("{4}", net.mv_online_nodes())
+}
+
+pub(crate) fn monthly_channels_sql(net: Network) -> String {
+    SELECT_MONTHLY_CHANNELS_SQL
+        .replace("{1}", net.online_channels_hourly())
+        .replace("{2}", net.udt_infos())
+        .replace("{3}", net.channel_states())
+}
+
+pub(crate) fn nodes_by_region_sql(net: Network, sort_by: &str, order: &str) -> String {
+    format!(
+        r#"
+        SELECT
+            node_id,
+            bucket AS last_seen_hour,
+            node_name,
+            addresses,
+            announce_timestamp,
+            chain_hash,
+            auto_accept_min_ckb_funding_amount,
+            country_or_region,
+            city,
+            region,
+            loc,
+            channel_count,
+            COUNT(*) OVER() as total_count
+        FROM {}
+        WHERE bucket >= $1::timestamp and country_or_region = $2
+        ORDER BY {} {}, node_id ASC"#,
+        net.mv_online_nodes(),
+        sort_by,
+        order,
+    )
+}
+
+/// Nodes supporting one of the UDTs `$2` through a relation confirmed since `$3`, in country or region `$4`
+/// when set. Offline nodes come from their last hourly bucket, with no channels.
+pub(crate) fn nodes_by_udt_sql(
+    net: Network,
+    sort_by: &str,
+    order: &str,
+    include_offline: bool,
+) -> String {
+    let nodes = if include_offline {
+        format!(
+            "SELECT DISTINCT ON (n.node_id) n.node_id, n.bucket, n.node_name, n.addresses,
+                n.announce_timestamp, n.chain_hash, n.auto_accept_min_ckb_funding_amount,
+                n.country_or_region, n.city, n.region, n.loc,
+                COALESCE(o.channel_count, 0) AS channel_count
+            FROM {hourly} n
+            JOIN supporting s ON s.node_id = n.node_id
+            LEFT JOIN {online} o ON o.node_id = n.node_id AND o.bucket >= $1::timestamp
+            ORDER BY n.node_id, n.bucket DESC",
+            hourly = net.online_nodes_hourly(),
+            online = net.mv_online_nodes(),
+        )
+    } else {
+        format!(
+            "SELECT n.* FROM {online} n
+            JOIN supporting s ON s.node_id = n.node_id
+            WHERE n.bucket >= $1::timestamp",
+            online = net.mv_online_nodes(),
+        )
+    };
+    format!(
+        r#"
+        WITH supporting AS (
+            SELECT DISTINCT node_id FROM {relations}
+            WHERE udt_info_id = ANY($2) AND last_confirmed >= $3
+        ),
+        nodes AS ({nodes})
+        SELECT
+            node_id,
+            bucket AS last_seen_hour,
+            node_name,
+            addresses,
+            announce_timestamp,
+            chain_hash,
+            auto_accept_min_ckb_funding_amount,
+            country_or_region,
+            city,
+            region,
+            loc,
+            channel_count,
+            COUNT(*) OVER() as total_count
+        FROM nodes
+        WHERE $4::text IS NULL OR country_or_region = $4
+        ORDER BY {sort_by} {order}, node_id ASC
+        LIMIT $5 OFFSET $6"#,
+        relations = net.node_udt_relations(),
+    )
+}
+
+pub(crate) fn nodes_fuzzy_by_name_sql(net: Network, sort_by: &str, order: &str) -> String {
+    format!(
+        r#"
+        SELECT
+            node_id,
+            bucket AS last_seen_hour,
+            node_name,
+            addresses,
+            announce_timestamp,
+            chain_hash,
+            auto_accept_min_ckb_funding_amount,
+            country_or_region,
+            city,
+            region,
+            loc,
+            channel_count,
+            COUNT(*) OVER() as total_count
+        FROM {} n
+        WHERE n.bucket >= $1::timestamp AND ((POSITION($2 IN n.node_id) > 0) OR (POSITION($2 IN n.node_name) > 0))
+        ORDER BY {} {}, n.node_id ASC"#,
+        net.mv_online_nodes(),
+        sort_by,
+        order,
+    )
+}
+
 #[serde_as]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct HourlyNodeInfo {
@@ -154,37 +347,58 @@ pub struct HourlyNodeInfo {
     pub loc: Option<String>,
     pub channel_count: usize,
     pub last_seen_hour: String,
+    /// Seconds since `last_seen_hour`, recomputed whenever a list is served.
+    #[serde(default)]
+    pub staleness_seconds: u64,
+    /// Whether `staleness_seconds` is past the online window.
+    #[serde(default)]
+    pub is_stale: bool,
+    /// Total capacity of the node's online channels, only set by `node_info`.
+    #[serde_as(as = "Option<U128Hex>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_capacity: Option<u128>,
+    /// Number of udts the node accepts, only set by `node_info`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub udt_count: Option<usize>,
+}
+
+impl HourlyNodeInfo {
+    pub fn refresh_staleness(&mut self, now: DateTime<Utc>) {
+        (self.staleness_seconds, self.is_stale) = staleness(&self.last_seen_hour, now);
+    }
 }
 
-impl From<HourlyNodeInfoDBRead> for HourlyNodeInfo {
-    fn from(info: HourlyNodeInfoDBRead) -> Self {
-        HourlyNodeInfo {
+impl TryFrom<HourlyNodeInfoDBRead> for HourlyNodeInfo {
+    type Error = FiberDashboardError;
+
+    fn try_from(info: HourlyNodeInfoDBRead) -> Result<Self, Self::Error> {
+        let (staleness_seconds, is_stale) =
+            staleness(&info.last_seen_hour.to_rfc3339(), Utc::now());
+        Ok(HourlyNodeInfo {
             node_name: info.node_name,
-            addresses: serde_json::from_str(&info.addresses).unwrap(),
+            addresses: codec::decode_addresses(&info.addresses)
+                .map_err(|e| FiberDashboardError::invalid("addresses", e))?,
             node_id: format!("0x{}", info.node_id),
             commit_timestamp: info.last_seen_hour.to_rfc3339(),
             announce_timestamp: info.announce_timestamp.timestamp_millis() as u64,
-            chain_hash: {
-                let mut hash_bytes = [0u8; 32];
-                faster_hex::hex_decode(info.chain_hash.as_bytes(), &mut hash_bytes).unwrap();
-                H256::from(hash_bytes)
-            },
-            auto_accept_min_ckb_funding_amount: {
-                let mut amount_bytes = [0u8; 8];
-                faster_hex::hex_decode(
-                    info.auto_accept_min_ckb_funding_amount.as_bytes(),
-                    &mut amount_bytes,
-                )
-                .unwrap();
-                u64::from_be_bytes(amount_bytes)
-            },
+            chain_hash: decode_hex::<32>("chain_hash", &info.chain_hash)?.into(),
+            auto_accept_min_ckb_funding_amount: codec::decode_u64(
+                &info.auto_accept_min_ckb_funding_amount,
+            )
+            .map_err(|e| FiberDashboardError::invalid("auto_accept_min_ckb_funding_amount", e))?,
             country_or_region: info.country_or_region,
             city: info.city,
             region: info.region,
             loc: info.loc,
             channel_count: info.channel_count as usize,
             last_seen_hour: info.last_seen_hour.to_rfc3339(),
-        }
+            staleness_seconds,
+            is_stale,
+            total_capacity: info
+                .total_capacity
+                .and_then(|capacity| capacity.parse().ok()),
+            udt_count: info.udt_count.map(|count| count as usize),
+        })
     }
 }
 
@@ -202,9 +416,31 @@ pub struct HourlyNodeInfoDBRead {
     pub region: Option<String>,
     pub loc: Option<String>,
     pub channel_count: i64,
+    /// Decimal sum of the online channel capacities, only selected for a single node.
+    #[sqlx(default)]
+    pub total_capacity: Option<String>,
+    #[sqlx(default)]
+    pub udt_count: Option<i64>,
 }
 
 impl HourlyNodeInfoDBRead {
+    /// Every node seen since `since`, used to build the in-memory hot snapshot.
+    pub(crate) async fn fetch_all_online(
+        pool: &Pool<Postgres>,
+        net: Network,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let sql =
+            statements(net).hourly_nodes(&ListNodesHourlySortBy::default(), &Order::default());
+        let rows = sqlx::query(sql)
+            .bind(since)
+            .bind(i64::MAX)
+            .bind(0i64)
+            .fetch_all(pool)
+            .await?;
+        Ok(rows_with_total::<Self>(rows)?.0)
+    }
+
     pub async fn fetch_by_id(
         pool: &Pool<Postgres>,
         node_id: JsonBytes,
@@ -224,17 +460,29 @@ impl HourlyNodeInfoDBRead {
                 n.city,
                 n.region,
                 n.loc,
-                n.channel_count
-            FROM {} n
+                n.channel_count,
+                (
+                    SELECT COALESCE(sum(hex_to_numeric(c.capacity)), 0)::numeric(39, 0)::text
+                    FROM {channels} c
+                    WHERE c.node1 = n.node_id OR c.node2 = n.node_id
+                ) AS total_capacity,
+                (
+                    SELECT count(*) FROM {udt_relations} r
+                    WHERE r.node_id = n.node_id AND r.last_confirmed >= $3
+                ) AS udt_count
+            FROM {nodes} n
             WHERE node_id = $1
             ORDER BY last_seen_hour DESC
             LIMIT 1",
-            net.mv_online_nodes()
+            nodes = net.mv_online_nodes(),
+            channels = net.mv_online_channels(),
+            udt_relations = net.node_udt_relations(),
         );
-        let hour_bucket = Utc::now() - chrono::Duration::hours(3);
+        let hour_bucket = online_since();
         let res = sqlx::query_as::<_, Self>(&sql)
             .bind(faster_hex::hex_string(node_id.as_bytes()))
             .bind(hour_bucket)
+            .bind(udt_relation_since())
             .fetch_optional(pool)
             .await?;
 
@@ -247,37 +495,39 @@ impl HourlyNodeInfoDBRead {
     ) -> Result<(Vec<Self>, usize, usize), sqlx::Error> {
         let page_size = std::cmp::min(params.page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
         let offset = params.page.saturating_mul(page_size);
-        let hour_bucket = Utc::now() - chrono::Duration::hours(3);
-        let sql = format!(
-            r#"
-        SELECT
-            node_id,
-            bucket AS last_seen_hour,
-            node_name,
-            addresses,
-            announce_timestamp,
-            chain_hash,
-            auto_accept_min_ckb_funding_amount,
-            country_or_region,
-            city,
-            region,
-            loc,
-            channel_count,
-            COUNT(*) OVER() as total_count
-        FROM {}
-        WHERE bucket >= $1::timestamp and country_or_region = $2
-        ORDER BY {} {}
-        LIMIT {} OFFSET {}
-    "#,
-            params.net.mv_online_nodes(),
+        let hour_bucket = online_since();
+        let sql = statements(params.net).nodes_by_region(&params.sort_by, &params.order);
+        let rows = sqlx::query(sql)
+            .bind(hour_bucket)
+            .bind(params.region)
+            .bind(page_size as i64)
+            .bind(offset as i64)
+            .fetch_all(pool)
+            .await?;
+        let (rows, total_count) = rows_with_total::<Self>(rows)?;
+        Ok((rows, params.page.saturating_add(1), total_count))
+    }
+
+    pub(crate) async fn fetch_nodes_by_udt(
+        pool: &Pool<Postgres>,
+        udt_ids: &[i32],
+        params: NodesByUdt,
+    ) -> Result<(Vec<Self>, usize, usize), sqlx::Error> {
+        let page_size = std::cmp::min(params.page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
+        let offset = params.page.saturating_mul(page_size);
+        let sql = nodes_by_udt_sql(
+            params.net,
             params.sort_by.as_str(),
             params.order.as_str(),
-            page_size,
-            offset
+            params.include_offline,
         );
         let rows = sqlx::query(&sql)
-            .bind(hour_bucket)
+            .bind(online_since())
+            .bind(udt_ids)
+            .bind(udt_relation_since())
             .bind(params.region)
+            .bind(page_size as i64)
+            .bind(offset as i64)
             .fetch_all(pool)
             .await?;
         let (rows, total_count) = rows_with_total::<Self>(rows)?;
@@ -290,41 +540,18 @@ impl HourlyNodeInfoDBRead {
     ) -> Result<(Vec<Self>, usize, usize), sqlx::Error> {
         let page_size = std::cmp::min(params.page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
         let offset = params.page.saturating_mul(page_size);
-        let hour_bucket = Utc::now() - chrono::Duration::hours(3);
+        let hour_bucket = online_since();
         let node_name = if params.node_name.starts_with("0x") {
             &params.node_name[2..]
         } else {
             &params.node_name
         };
-        let sql = format!(
-            r#"
-        SELECT
-            node_id,
-            bucket AS last_seen_hour,
-            node_name,
-            addresses,
-            announce_timestamp,
-            chain_hash,
-            auto_accept_min_ckb_funding_amount,
-            country_or_region,
-            city,
-            region,
-            loc,
-            channel_count,
-            COUNT(*) OVER() as total_count
-        FROM {} n
-        WHERE n.bucket >= $1::timestamp AND ((POSITION($2 IN n.node_id) > 0) OR (POSITION($2 IN n.node_name) > 0))
-        ORDER BY {} {}
-        LIMIT {} OFFSET {}"#,
-            params.net.mv_online_nodes(),
-            params.sort_by.as_str(),
-            params.order.as_str(),
-            page_size,
-            offset
-        );
-        let rows = sqlx::query(&sql)
+        let sql = statements(params.net).nodes_fuzzy_by_name(&params.sort_by, &params.order);
+        let rows = sqlx::query(sql)
             .bind(hour_bucket)
             .bind(node_name)
+            .bind(page_size as i64)
+            .bind(offset as i64)
             .fetch_all(pool)
             .await?;
         let (rows, total_count) = rows_with_total::<Self>(rows)?;
@@ -337,18 +564,19 @@ impl HourlyNodeInfoDBRead {
     ) -> Result<(Vec<Self>, usize, usize), sqlx::Error> {
         let page_size = std::cmp::min(params.page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
         let offset = params.page.saturating_mul(page_size);
-        let hour_bucket = Utc::now() - chrono::Duration::hours(3);
-        let sql = SELECT_HOURLY_NODES_SQL
-            .replace("{nodes}", params.net.mv_online_nodes())
-            .replace("{sort_by}", params.sort_by.as_str())
-            .replace("{order}", params.order.as_str());
-        let sql = format!("{} LIMIT {} OFFSET {}", sql, page_size, offset);
-        let rows = sqlx::query(&sql).bind(hour_bucket).fetch_all(pool).await?;
+        let hour_bucket = online_since();
+        let sql = statements(params.net).hourly_nodes(&params.sort_by, &params.order);
+        let rows = sqlx::query(sql)
+            .bind(hour_bucket)
+            .bind(page_size as i64)
+            .bind(offset as i64)
+            .fetch_all(pool)
+            .await?;
         let (rows, total_count) = rows_with_total::<Self>(rows)?;
         Ok((rows, params.page.saturating_add(1), total_count))
     }
 
-    pub async fn fetch_by_page_monthly(
+    pub(crate) async fn fetch_by_page_monthly(
         pool: &Pool<Postgres>,
         params: Page,
     ) -> Result<(Vec<Self>, usize, usize), sqlx::Error> {
@@ -360,17 +588,32 @@ impl HourlyNodeInfoDBRead {
         if end - start > chrono::Duration::days(30) || start > end {
             end = start + chrono::Duration::days(30);
         }
-        let base_sql =
-            SELECT_MONTHLY_NODES_SQL.replace("{nodes}", params.net.online_nodes_hourly());
-        let sql = format!("{} LIMIT {} OFFSET {}", base_sql, page_size, offset);
-        let rows = sqlx::query(&sql)
+        let rows = sqlx::query(&statements(params.net).monthly_nodes)
             .bind(start)
             .bind(end)
+            .bind(page_size as i64)
+            .bind(offset as i64)
             .fetch_all(pool)
             .await?;
         let (rows, total_count) = rows_with_total::<Self>(rows)?;
         Ok((rows, params.page.saturating_add(1), total_count))
     }
+
+    /// Every node of the hourly bucket starting at `hour`, for immutable snapshot files.
+    pub(crate) async fn fetch_hour(
+        pool: &Pool<Postgres>,
+        net: Network,
+        hour: DateTime<Utc>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let rows = sqlx::query(&statements(net).monthly_nodes)
+            .bind(hour)
+            .bind(hour + chrono::Duration::hours(1))
+            .bind(i64::MAX)
+            .bind(0i64)
+            .fetch_all(pool)
+            .await?;
+        Ok(rows_with_total::<Self>(rows)?.0)
+    }
 }
 
 fn rows_with_total<T>(rows: Vec<PgRow>) -> Result<(Vec<T>, usize), sqlx::Error>
@@ -393,6 +636,9 @@ where
 pub struct ChannelInfo {
     /// The outpoint of the channel.
     pub channel_outpoint: String,
+    /// `channel_outpoint` as `0x<tx_hash>:<index>`.
+    #[serde(default)]
+    pub canonical_outpoint: Option<String>,
     /// The identity public key of the first node.
     pub node1: String,
     /// The identity public key of the second node.
@@ -418,154 +664,115 @@ pub struct ChannelInfo {
     pub udt_type_script: Option<Script>,
     pub udt_name: Option<String>,
     pub udt_auto_accept_amount: Option<String>,
+    /// Seconds since `commit_timestamp`, recomputed whenever a list is served.
+    #[serde(default)]
+    pub staleness_seconds: u64,
+    /// Whether `staleness_seconds` is past the online window.
+    #[serde(default)]
+    pub is_stale: bool,
 }
 
-impl From<HourlyChannelInfoDBRead> for ChannelInfo {
-    fn from(info: HourlyChannelInfoDBRead) -> Self {
-        ChannelInfo {
+impl ChannelInfo {
+    pub fn refresh_staleness(&mut self, now: DateTime<Utc>) {
+        (self.staleness_seconds, self.is_stale) = staleness(&self.commit_timestamp, now);
+    }
+}
+
+impl TryFrom<HourlyChannelInfoDBRead> for ChannelInfo {
+    type Error = FiberDashboardError;
+
+    fn try_from(info: HourlyChannelInfoDBRead) -> Result<Self, Self::Error> {
+        let (staleness_seconds, is_stale) =
+            staleness(&info.last_seen_hour.to_rfc3339(), Utc::now());
+        Ok(ChannelInfo {
+            canonical_outpoint: crate::outpoint::canonical(&info.channel_outpoint),
             channel_outpoint: format!("0x{}", info.channel_outpoint),
             node1: format!("0x{}", info.node1),
             node2: format!("0x{}", info.node2),
-            asset: {
-                let mut asset_bytes = [0u8; 16];
-                faster_hex::hex_decode(info.asset.as_bytes(), &mut asset_bytes).unwrap();
-                u128::from_be_bytes(asset_bytes)
-            },
-            capacity: {
-                let mut capacity_bytes = [0u8; 8];
-                faster_hex::hex_decode(info.capacity.as_bytes(), &mut capacity_bytes).unwrap();
-                u64::from_be_bytes(capacity_bytes)
-            },
-            chain_hash: {
-                let mut hash_bytes = [0u8; 32];
-                faster_hex::hex_decode(info.chain_hash.as_bytes(), &mut hash_bytes).unwrap();
-                H256::from(hash_bytes)
-            },
+            asset: codec::decode_u128(&info.asset)
+                .map_err(|e| FiberDashboardError::invalid("asset", e))?,
+            capacity: codec::decode_u64(&info.capacity)
+                .map_err(|e| FiberDashboardError::invalid("capacity", e))?,
+            chain_hash: decode_hex::<32>("chain_hash", &info.chain_hash)?.into(),
             commit_timestamp: info.last_seen_hour.to_rfc3339(),
             created_timestamp: info.created_timestamp.timestamp_millis() as u64,
-            update_info_of_node1: info.update_of_node1_timestamp.map(|timestamp| {
-                ChannelUpdateInfo {
-                    timestamp: timestamp.timestamp_millis() as u64,
-                    enabled: info.update_of_node1_enabled.unwrap_or(false),
-                    outbound_liquidity: info.update_of_node1_outbound_liquidity.map(|ol| {
-                        let mut ol_bytes = [0u8; 16];
-                        faster_hex::hex_decode(ol.as_bytes(), &mut ol_bytes).unwrap();
-                        u128::from_be_bytes(ol_bytes)
-                    }),
-                    tlc_expiry_delta: {
-                        let mut delta_bytes = [0u8; 8];
-                        faster_hex::hex_decode(
-                            info.update_of_node1_tlc_expiry_delta
-                                .as_ref()
-                                .unwrap()
-                                .as_bytes(),
-                            &mut delta_bytes,
-                        )
-                        .unwrap();
-                        u64::from_be_bytes(delta_bytes)
-                    },
-                    tlc_minimum_value: {
-                        let mut min_value_bytes = [0u8; 16];
-                        faster_hex::hex_decode(
-                            info.update_of_node1_tlc_minimum_value
-                                .as_ref()
-                                .unwrap()
-                                .as_bytes(),
-                            &mut min_value_bytes,
-                        )
-                        .unwrap();
-                        u128::from_be_bytes(min_value_bytes)
-                    },
-                    fee_rate: {
-                        let mut fee_rate_bytes = [0u8; 8];
-                        faster_hex::hex_decode(
-                            info.update_of_node1_fee_rate.as_ref().unwrap().as_bytes(),
-                            &mut fee_rate_bytes,
-                        )
-                        .unwrap();
-                        u64::from_be_bytes(fee_rate_bytes)
-                    },
-                }
-            }),
-            update_info_of_node2: info.update_of_node2_timestamp.map(|timestamp| {
-                ChannelUpdateInfo {
-                    timestamp: timestamp.timestamp_millis() as u64,
-                    enabled: info.update_of_node2_enabled.unwrap_or(false),
-                    outbound_liquidity: info.update_of_node2_outbound_liquidity.map(|ol| {
-                        let mut ol_bytes = [0u8; 16];
-                        faster_hex::hex_decode(ol.as_bytes(), &mut ol_bytes).unwrap();
-                        u128::from_be_bytes(ol_bytes)
-                    }),
-                    tlc_expiry_delta: {
-                        let mut delta_bytes = [0u8; 8];
-                        faster_hex::hex_decode(
-                            info.update_of_node2_tlc_expiry_delta
-                                .as_ref()
-                                .unwrap()
-                                .as_bytes(),
-                            &mut delta_bytes,
-                        )
-                        .unwrap();
-                        u64::from_be_bytes(delta_bytes)
-                    },
-                    tlc_minimum_value: {
-                        let mut min_value_bytes = [0u8; 16];
-                        faster_hex::hex_decode(
-                            info.update_of_node2_tlc_minimum_value
-                                .as_ref()
-                                .unwrap()
-                                .as_bytes(),
-                            &mut min_value_bytes,
-                        )
-                        .unwrap();
-                        u128::from_be_bytes(min_value_bytes)
-                    },
-                    fee_rate: {
-                        let mut fee_rate_bytes = [0u8; 8];
-                        faster_hex::hex_decode(
-                            info.update_of_node2_fee_rate.as_ref().unwrap().as_bytes(),
-                            &mut fee_rate_bytes,
-                        )
-                        .unwrap();
-                        u64::from_be_bytes(fee_rate_bytes)
-                    },
-                }
-            }),
-            udt_type_script: info.udt_hash_type.map(|hash_type| Script {
-                code_hash: {
-                    let mut code_hash_bytes = [0u8; 32];
-                    faster_hex::hex_decode(
-                        info.udt_code_hash.as_ref().unwrap().as_bytes(),
-                        &mut code_hash_bytes,
+            update_info_of_node1: info
+                .update_of_node1_timestamp
+                .map(|timestamp| {
+                    channel_update(
+                        timestamp,
+                        info.update_of_node1_enabled,
+                        info.update_of_node1_outbound_liquidity.as_deref(),
+                        info.update_of_node1_tlc_expiry_delta.as_deref(),
+                        info.update_of_node1_tlc_minimum_value.as_deref(),
+                        info.update_of_node1_fee_rate.as_deref(),
                     )
-                    .unwrap();
-                    H256::from(code_hash_bytes)
-                },
-                hash_type: match hash_type.as_str() {
-                    "type" => ckb_jsonrpc_types::ScriptHashType::Type,
-                    "data" => ckb_jsonrpc_types::ScriptHashType::Data,
-                    "data1" => ckb_jsonrpc_types::ScriptHashType::Data1,
-                    "data2" => ckb_jsonrpc_types::ScriptHashType::Data2,
-                    _ => unreachable!("Unknown hash type: {}", hash_type),
-                },
-                args: {
-                    let mut args_bytes = vec![0; info.udt_args.as_ref().unwrap().len() / 2];
-                    faster_hex::hex_decode(
-                        info.udt_args.as_ref().unwrap().as_bytes(),
-                        &mut args_bytes,
+                })
+                .transpose()?,
+            update_info_of_node2: info
+                .update_of_node2_timestamp
+                .map(|timestamp| {
+                    channel_update(
+                        timestamp,
+                        info.update_of_node2_enabled,
+                        info.update_of_node2_outbound_liquidity.as_deref(),
+                        info.update_of_node2_tlc_expiry_delta.as_deref(),
+                        info.update_of_node2_tlc_minimum_value.as_deref(),
+                        info.update_of_node2_fee_rate.as_deref(),
                     )
-                    .unwrap();
-                    JsonBytes::from_vec(args_bytes)
-                },
-            }),
+                })
+                .transpose()?,
+            udt_type_script: info
+                .udt_hash_type
+                .map(|hash_type| {
+                    decode_script(
+                        info.udt_code_hash.as_deref().unwrap_or_default(),
+                        &hash_type,
+                        info.udt_args.as_deref().unwrap_or_default(),
+                    )
+                })
+                .transpose()?,
             udt_name: info.udt_name,
             udt_auto_accept_amount: info
                 .udt_auto_accept_amount
                 .map(|amount| format!("0x{}", amount)),
-        }
+            staleness_seconds,
+            is_stale,
+        })
     }
 }
 
+/// Channel update of one side, the timestamp being set requires the other columns.
+fn channel_update(
+    timestamp: DateTime<Utc>,
+    enabled: Option<bool>,
+    outbound_liquidity: Option<&str>,
+    tlc_expiry_delta: Option<&str>,
+    tlc_minimum_value: Option<&str>,
+    fee_rate: Option<&str>,
+) -> Result<ChannelUpdateInfo, FiberDashboardError> {
+    let u64_of = |field, hex: Option<&str>| {
+        codec::decode_u64(hex.ok_or(FiberDashboardError::Missing(field))?)
+            .map_err(|e| FiberDashboardError::invalid(field, e))
+    };
+    let u128_of = |field, hex: &str| {
+        codec::decode_u128(hex).map_err(|e| FiberDashboardError::invalid(field, e))
+    };
+    Ok(ChannelUpdateInfo {
+        timestamp: timestamp.timestamp_millis() as u64,
+        enabled: enabled.unwrap_or(false),
+        outbound_liquidity: outbound_liquidity
+            .map(|ol| u128_of("outbound_liquidity", ol))
+            .transpose()?,
+        tlc_expiry_delta: u64_of("tlc_expiry_delta", tlc_expiry_delta)?,
+        tlc_minimum_value: u128_of(
+            "tlc_minimum_value",
+            tlc_minimum_value.ok_or(FiberDashboardError::Missing("tlc_minimum_value"))?,
+        )?,
+        fee_rate: u64_of("fee_rate", fee_rate)?,
+    })
+}
+
 #[derive(Debug, Clone, FromRow)]
 pub struct HourlyChannelInfoDBRead {
     pub channel_outpoint: String,
@@ -602,6 +809,27 @@ pub struct HourlyChannelInfoDBRead {
 }
 
 impl HourlyChannelInfoDBRead {
+    /// Every channel seen since `since`, used to build the in-memory hot snapshot.
+    pub(crate) async fn fetch_all_online(
+        pool: &Pool<Postgres>,
+        net: Network,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let rows = sqlx::query(&statements(net).hourly_channels)
+            .bind(since)
+            .bind(None::<String>)
+            .bind(None::<String>)
+            .bind(false)
+            .bind(None::<String>)
+            .bind(None::<String>)
+            .bind(None::<String>)
+            .bind(i64::MAX)
+            .bind(0i64)
+            .fetch_all(pool)
+            .await?;
+        Ok(rows_with_total::<Self>(rows)?.0)
+    }
+
     pub async fn fetch_by_id(
         pool: &Pool<Postgres>,
         outpoint: JsonBytes,
@@ -652,24 +880,31 @@ impl HourlyChannelInfoDBRead {
         Ok(res)
     }
 
-    pub async fn fetch_by_page_hourly(
+    pub(crate) async fn fetch_by_page_hourly(
         pool: &Pool<Postgres>,
         params: Page,
     ) -> Result<(Vec<Self>, usize, usize), sqlx::Error> {
         let page_size = std::cmp::min(params.page_size.unwrap_or(PAGE_SIZE), PAGE_SIZE);
         let offset = params.page.saturating_mul(page_size);
-        let hour_bucket = Utc::now() - chrono::Duration::hours(3);
-        let sql = SELECT_HOURLY_CHANNELS_SQL
-            .replace("{1}", params.net.mv_online_channels())
-            .replace("{2}", params.net.udt_infos())
-            .replace("{3}", params.net.channel_states());
-        let sql = format!("{} LIMIT {} OFFSET {}", sql, page_size, offset);
-        let rows = sqlx::query(&sql).bind(hour_bucket).fetch_all(pool).await?;
+        let hour_bucket = online_since();
+        let (min_capacity, max_capacity) = params.capacity_bounds_hex();
+        let rows = sqlx::query(&statements(params.net).hourly_channels)
+            .bind(hour_bucket)
+            .bind(&params.country)
+            .bind(&params.region)
+            .bind(params.endpoint_match == EndpointMatch::Both)
+            .bind(min_capacity)
+            .bind(max_capacity)
+            .bind(&params.udt)
+            .bind(page_size as i64)
+            .bind(offset as i64)
+            .fetch_all(pool)
+            .await?;
         let (rows, total_count) = rows_with_total::<Self>(rows)?;
         Ok((rows, params.page.saturating_add(1), total_count))
     }
 
-    pub async fn fetch_by_page_monthly(
+    pub(crate) async fn fetch_by_page_monthly(
         pool: &Pool<Postgres>,
         params: Page,
     ) -> Result<(Vec<Self>, usize, usize), sqlx::Error> {
@@ -681,17 +916,37 @@ impl HourlyChannelInfoDBRead {
         if end - start > chrono::Duration::days(30) || start > end {
             end = start + chrono::Duration::days(30);
         }
-        let sql = SELECT_MONTHLY_CHANNELS_SQL
-            .replace("{1}", params.net.online_channels_hourly())
-            .replace("{2}", params.net.udt_infos())
-            .replace("{3}", params.net.channel_states());
-        let sql = format!("{} LIMIT {} OFFSET {}", sql, page_size, offset);
-        let rows = sqlx::query(&sql)
+        let (min_capacity, max_capacity) = params.capacity_bounds_hex();
+        let rows = sqlx::query(&statements(params.net).monthly_channels)
             .bind(start)
             .bind(end)
+            .bind(min_capacity)
+            .bind(max_capacity)
+            .bind(&params.udt)
+            .bind(page_size as i64)
+            .bind(offset as i64)
             .fetch_all(pool)
             .await?;
         let (rows, total_count) = rows_with_total::<Self>(rows)?;
         Ok((rows, params.page.saturating_add(1), total_count))
     }
+
+    /// Every channel of the hourly bucket starting at `hour`, for immutable snapshot files.
+    pub(crate) async fn fetch_hour(
+        pool: &Pool<Postgres>,
+        net: Network,
+        hour: DateTime<Utc>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let rows = sqlx::query(&statements(net).monthly_channels)
+            .bind(hour)
+            .bind(hour + chrono::Duration::hours(1))
+            .bind(None::<String>)
+            .bind(None::<String>)
+            .bind(None::<String>)
+            .bind(i64::MAX)
+            .bind(0i64)
+            .fetch_all(pool)
+            .await?;
+        Ok(rows_with_total::<Self>(rows)?.0)
+    }
 }
@@ -0,0 +1,159 @@
+//! Discovery of channels from their funding cells on chain, announced in gossip or not.
+//!
+//! With `CHAIN_SCANNER` on, the state monitor lists the live cells of every accepted funding
+//! lock each [`SCAN_INTERVAL`] and tracks the ones it does not know yet like channels from the
+//! graph. Every scan tags the live funding cells never announced as `unannounced` in
+//! `channel_states`, clears the tag once they show up in the graph, and records the share of
+//! unannounced funding cells of the day in `private_channel_share`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::LazyLock,
+};
+
+use ckb_jsonrpc_types::JsonBytes;
+use faster_hex::hex_decode;
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{Network, config};
+
+/// Whether the state monitor scans the chain for funding cells, on unless `CHAIN_SCANNER=false`.
+pub static CHAIN_SCANNER: LazyLock<bool> =
+    LazyLock::new(|| config::parse_var("CHAIN_SCANNER").unwrap_or(true));
+
+pub(super) const SCAN_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// Live funding cells split by whether their channel was ever announced.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct Share {
+    pub funding_cells: usize,
+    /// Shannons.
+    pub capacity: u64,
+    pub unannounced: Vec<String>,
+    /// Shannons.
+    pub unannounced_capacity: u64,
+}
+
+/// `live` is the capacity of every live funding cell by outpoint.
+pub(super) fn share(live: &HashMap<String, u64>, announced: &HashSet<String>) -> Share {
+    let mut share = Share {
+        funding_cells: live.len(),
+        capacity: live.values().sum(),
+        ..Default::default()
+    };
+    for (outpoint, capacity) in live {
+        if !announced.contains(outpoint) {
+            share.unannounced.push(outpoint.clone());
+            share.unannounced_capacity += capacity;
+        }
+    }
+    share.unannounced.sort_unstable();
+    share
+}
+
+/// Live funding cells without a row in `channel_states`, leaving out those waiting in the
+/// dead-letter queue.
+pub(super) async fn untracked(
+    pool: &Pool<Postgres>,
+    net: Network,
+    live: &HashMap<String, u64>,
+) -> Result<Vec<JsonBytes>, sqlx::Error> {
+    let sql = format!(
+        "SELECT o AS channel_outpoint FROM unnest($1::text[]) AS o
+        WHERE NOT EXISTS (SELECT 1 FROM {} s WHERE s.channel_outpoint = o)
+        AND NOT EXISTS (SELECT 1 FROM channel_update_dead_letters d
+            WHERE d.channel_outpoint = o AND d.resolved_at IS NULL)",
+        net.channel_states()
+    );
+    Ok(sqlx::query(&sql)
+        .bind(live.keys().cloned().collect::<Vec<_>>())
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .filter_map(|row| {
+            let raw = row.get::<String, _>("channel_outpoint");
+            let mut buf = vec![0u8; raw.len() / 2];
+            hex_decode(raw.as_bytes(), &mut buf).ok()?;
+            Some(JsonBytes::from_bytes(buf.into()))
+        })
+        .collect())
+}
+
+/// Tag the unannounced channels of `net` and record the share of the day.
+pub(super) async fn record(
+    pool: &Pool<Postgres>,
+    net: Network,
+    live: &HashMap<String, u64>,
+) -> Result<(), sqlx::Error> {
+    let outpoints = live.keys().cloned().collect::<Vec<_>>();
+    let announced = sqlx::query(&format!(
+        "SELECT DISTINCT channel_outpoint FROM {} WHERE channel_outpoint = ANY($1)",
+        net.channel_infos()
+    ))
+    .bind(&outpoints)
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|row| row.get::<String, _>("channel_outpoint"))
+    .collect::<HashSet<_>>();
+    let share = share(live, &announced);
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(&format!(
+        "UPDATE {} SET unannounced = (channel_outpoint = ANY($2))
+        WHERE channel_outpoint = ANY($1) AND unannounced <> (channel_outpoint = ANY($2))",
+        net.channel_states()
+    ))
+    .bind(&outpoints)
+    .bind(&share.unannounced)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        "INSERT INTO private_channel_share
+            (net, day, funding_cells, unannounced, capacity, unannounced_capacity, updated_at)
+        VALUES ($1, now()::date, $2, $3, $4, $5, now())
+        ON CONFLICT (net, day) DO UPDATE SET
+            funding_cells = excluded.funding_cells,
+            unannounced = excluded.unannounced,
+            capacity = excluded.capacity,
+            unannounced_capacity = excluded.unannounced_capacity,
+            updated_at = excluded.updated_at",
+    )
+    .bind(net.name())
+    .bind(share.funding_cells as i32)
+    .bind(share.unannounced.len() as i32)
+    .bind(share.capacity as i64)
+    .bind(share.unannounced_capacity as i64)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    log::info!(
+        "{:?}, {} of {} live funding cells unannounced",
+        net,
+        share.unannounced.len(),
+        share.funding_cells
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::share;
+
+    #[test]
+    fn unannounced_share_of_live_cells() {
+        let live = HashMap::from([
+            ("a".to_string(), 100),
+            ("b".to_string(), 200),
+            ("c".to_string(), 300),
+        ]);
+        let announced = HashSet::from(["b".to_string(), "z".to_string()]);
+        let share = share(&live, &announced);
+        assert_eq!(share.funding_cells, 3);
+        assert_eq!(share.capacity, 600);
+        assert_eq!(share.unannounced, vec!["a".to_string(), "c".to_string()]);
+        assert_eq!(share.unannounced_capacity, 400);
+    }
+}
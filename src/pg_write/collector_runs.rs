@@ -0,0 +1,146 @@
+//! Outcome of each collection cycle, recorded per phase in `collector_runs`.
+//!
+//! Node ingestion, channel ingestion and the hand-off of new channels to the state monitor
+//! commit independently, a failing phase no longer discards the others.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Row};
+
+use crate::Network;
+
+/// Result of one phase, a phase that did not run is a failure without error.
+#[derive(Debug, Default)]
+pub struct Phase {
+    pub ok: bool,
+    pub count: Option<usize>,
+    pub error: Option<String>,
+}
+
+impl Phase {
+    pub fn done(count: usize) -> Self {
+        Phase {
+            ok: true,
+            count: Some(count),
+            error: None,
+        }
+    }
+
+    pub fn failed(error: impl ToString) -> Self {
+        Phase {
+            ok: false,
+            count: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+impl<E: std::fmt::Display> From<Result<usize, E>> for Phase {
+    fn from(result: Result<usize, E>) -> Self {
+        match result {
+            Ok(count) => Phase::done(count),
+            Err(e) => Phase::failed(e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CollectorRun {
+    pub net: Network,
+    pub started_at: DateTime<Utc>,
+    pub nodes: Phase,
+    pub channels: Phase,
+    /// New channels handed to the state monitor.
+    pub monitor: Phase,
+}
+
+impl CollectorRun {
+    pub fn start(net: Network) -> Self {
+        CollectorRun {
+            net,
+            started_at: Utc::now(),
+            nodes: Phase::default(),
+            channels: Phase::default(),
+            monitor: Phase::default(),
+        }
+    }
+
+    /// Errors of the failed phases prefixed with the phase name.
+    fn errors(&self) -> Vec<String> {
+        [
+            ("nodes", &self.nodes),
+            ("channels", &self.channels),
+            ("monitor", &self.monitor),
+        ]
+        .into_iter()
+        .filter_map(|(name, phase)| {
+            phase
+                .error
+                .as_ref()
+                .map(|error| format!("{}: {}", name, error))
+        })
+        .collect()
+    }
+
+    pub async fn record(&self, pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO collector_runs
+            (net, started_at, nodes_ok, nodes, channels_ok, channels, monitor_ok, handed_off, errors)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(self.net.name())
+        .bind(self.started_at)
+        .bind(self.nodes.ok)
+        .bind(self.nodes.count.map(|count| count as i32))
+        .bind(self.channels.ok)
+        .bind(self.channels.count.map(|count| count as i32))
+        .bind(self.monitor.ok)
+        .bind(self.monitor.count.map(|count| count as i32))
+        .bind(self.errors())
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// A recorded cycle as stored, for the admin api.
+#[derive(Debug, Serialize)]
+pub struct RecordedRun {
+    pub net: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub nodes_ok: bool,
+    pub nodes: Option<i32>,
+    pub channels_ok: bool,
+    pub channels: Option<i32>,
+    pub monitor_ok: bool,
+    pub handed_off: Option<i32>,
+    pub errors: Vec<String>,
+}
+
+/// The latest `limit` cycles of every network, newest first.
+pub async fn recent(pool: &Pool<Postgres>, limit: i64) -> Result<Vec<RecordedRun>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT net, started_at, finished_at, nodes_ok, nodes, channels_ok, channels,
+            monitor_ok, handed_off, errors
+        FROM collector_runs ORDER BY started_at DESC LIMIT $1",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| RecordedRun {
+            net: row.get("net"),
+            started_at: row.get("started_at"),
+            finished_at: row.get("finished_at"),
+            nodes_ok: row.get("nodes_ok"),
+            nodes: row.get("nodes"),
+            channels_ok: row.get("channels_ok"),
+            channels: row.get("channels"),
+            monitor_ok: row.get("monitor_ok"),
+            handed_off: row.get("handed_off"),
+            errors: row.get("errors"),
+        })
+        .collect())
+}
@@ -0,0 +1,256 @@
+//! Channel state writes that failed, kept in `channel_update_dead_letters` and retried in
+//! insertion order until they apply or run out of attempts.
+
+use chrono::{DateTime, Utc};
+use faster_hex::hex_string;
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres, Row};
+
+use crate::{
+    Network, bus,
+    pg_write::{ChannelGroup, ChannelStateUpdate},
+};
+
+const RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Entries failing this many times are left for `/admin/dead_letters/requeue`.
+pub const MAX_ATTEMPTS: i32 = 10;
+
+const NEW_CHANNEL: &str = "new_channel";
+const STATE_UPDATE: &str = "state_update";
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub net: String,
+    /// `new_channel` or `state_update`.
+    pub kind: String,
+    pub channel_outpoint: String,
+    pub payload: serde_json::Value,
+    pub error: String,
+    pub attempts: i32,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RetrySummary {
+    pub retried: usize,
+    pub resolved: usize,
+    pub failed: usize,
+}
+
+async fn record(
+    pool: &Pool<Postgres>,
+    net: Network,
+    kind: &str,
+    outpoint: String,
+    payload: Result<serde_json::Value, serde_json::Error>,
+    error: &sqlx::Error,
+) {
+    let payload = match payload {
+        Ok(payload) => payload,
+        Err(e) => {
+            log::error!("Failed to serialize {} of 0x{}: {}", kind, outpoint, e);
+            return;
+        }
+    };
+    if let Err(e) = sqlx::query(
+        "INSERT INTO channel_update_dead_letters (net, kind, channel_outpoint, payload, error)
+        VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(net.name())
+    .bind(kind)
+    .bind(&outpoint)
+    .bind(payload)
+    .bind(error.to_string())
+    .execute(pool)
+    .await
+    {
+        log::error!(
+            "Failed to dead-letter {} of 0x{}, it is lost: {}",
+            kind,
+            outpoint,
+            e
+        );
+    }
+}
+
+pub(super) async fn record_new_channels(
+    pool: &Pool<Postgres>,
+    groups: &[ChannelGroup],
+    error: &sqlx::Error,
+) {
+    for group in groups {
+        record(
+            pool,
+            group.net(),
+            NEW_CHANNEL,
+            hex_string(group.outpoint().as_bytes()),
+            serde_json::to_value(group),
+            error,
+        )
+        .await;
+    }
+}
+
+pub(super) async fn record_state_updates(
+    pool: &Pool<Postgres>,
+    net: Network,
+    updates: &[&ChannelStateUpdate],
+    error: &sqlx::Error,
+) {
+    for update in updates {
+        record(
+            pool,
+            net,
+            STATE_UPDATE,
+            hex_string(update.outpoint().as_bytes()),
+            serde_json::to_value(update),
+            error,
+        )
+        .await;
+    }
+}
+
+/// Write one entry the way the monitor would have, publishing its state change on success.
+async fn apply(pool: &Pool<Postgres>, letter: &DeadLetter) -> Result<(), String> {
+    let net = match letter.net.as_str() {
+        "mainnet" => Network::Mainnet,
+        "testnet" => Network::Testnet,
+        net => return Err(format!("unknown network {}", net)),
+    };
+    match letter.kind.as_str() {
+        NEW_CHANNEL => {
+            let group: ChannelGroup =
+                serde_json::from_value(letter.payload.clone()).map_err(|e| e.to_string())?;
+            ChannelGroup::write(pool, std::slice::from_ref(&group))
+                .await
+                .map_err(|e| e.to_string())?;
+            bus::publish_state_changes(net, std::iter::once(group.state_change()));
+        }
+        STATE_UPDATE => {
+            let update: ChannelStateUpdate =
+                serde_json::from_value(letter.payload.clone()).map_err(|e| e.to_string())?;
+            ChannelStateUpdate::write(pool, net, &[&update])
+                .await
+                .map_err(|e| e.to_string())?;
+            bus::publish_state_changes(net, std::iter::once(update.state_change(net)));
+        }
+        kind => return Err(format!("unknown kind {}", kind)),
+    }
+    Ok(())
+}
+
+/// Retry unresolved entries below [`MAX_ATTEMPTS`], oldest first so a channel's insert is
+/// applied before its later updates.
+pub async fn retry(pool: &Pool<Postgres>) -> Result<RetrySummary, sqlx::Error> {
+    let letters: Vec<DeadLetter> = sqlx::query_as(
+        "SELECT * FROM channel_update_dead_letters
+        WHERE resolved_at IS NULL AND attempts < $1
+        ORDER BY id",
+    )
+    .bind(MAX_ATTEMPTS)
+    .fetch_all(pool)
+    .await?;
+
+    let mut summary = RetrySummary::default();
+    for letter in letters {
+        summary.retried += 1;
+        match apply(pool, &letter).await {
+            Ok(()) => {
+                summary.resolved += 1;
+                sqlx::query(
+                    "UPDATE channel_update_dead_letters
+                    SET attempts = attempts + 1, last_attempt_at = now(), resolved_at = now()
+                    WHERE id = $1",
+                )
+                .bind(letter.id)
+                .execute(pool)
+                .await?;
+            }
+            Err(e) => {
+                summary.failed += 1;
+                log::warn!(
+                    "Dead letter {} ({} of 0x{}) failed again: {}",
+                    letter.id,
+                    letter.kind,
+                    letter.channel_outpoint,
+                    e
+                );
+                sqlx::query(
+                    "UPDATE channel_update_dead_letters
+                    SET attempts = attempts + 1, last_attempt_at = now(), error = $2
+                    WHERE id = $1",
+                )
+                .bind(letter.id)
+                .bind(e)
+                .execute(pool)
+                .await?;
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Retry dead letters every [`RETRY_INTERVAL`], run by collectors.
+pub async fn retrier(pool: &'static Pool<Postgres>) {
+    let mut timer = tokio::time::interval(RETRY_INTERVAL);
+    timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        timer.tick().await;
+        match retry(pool).await {
+            Ok(summary) if summary.retried > 0 => log::info!(
+                "Dead letters retried: {}, resolved: {}",
+                summary.retried,
+                summary.resolved
+            ),
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to retry dead letters: {}", e),
+        }
+    }
+}
+
+/// Newest entries first, unresolved ones only unless `resolved` is set.
+pub async fn list(
+    pool: &Pool<Postgres>,
+    resolved: bool,
+    page: usize,
+    page_size: usize,
+) -> Result<(Vec<DeadLetter>, usize, usize), sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT *, COUNT(*) OVER() AS total_count
+        FROM channel_update_dead_letters
+        WHERE $1 OR resolved_at IS NULL
+        ORDER BY id DESC
+        LIMIT $2 OFFSET $3",
+    )
+    .bind(resolved)
+    .bind(page_size as i64)
+    .bind(page.saturating_mul(page_size) as i64)
+    .fetch_all(pool)
+    .await?;
+    let total_count = rows
+        .first()
+        .map(|row| row.get::<i64, _>("total_count") as usize)
+        .unwrap_or(0);
+    let letters = rows
+        .iter()
+        .map(DeadLetter::from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((letters, page.saturating_add(1), total_count))
+}
+
+/// Reset the attempts of unresolved entries, all of them when `ids` is `None`, so the next
+/// [`retry`] picks them up again. Returns the number of entries requeued.
+pub async fn requeue(pool: &Pool<Postgres>, ids: Option<&[i64]>) -> Result<u64, sqlx::Error> {
+    Ok(sqlx::query(
+        "UPDATE channel_update_dead_letters SET attempts = 0
+        WHERE resolved_at IS NULL AND ($1::bigint[] IS NULL OR id = ANY($1))",
+    )
+    .bind(ids)
+    .execute(pool)
+    .await?
+    .rows_affected())
+}
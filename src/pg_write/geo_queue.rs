@@ -0,0 +1,109 @@
+//! Geolocation of new nodes after their rows are inserted.
+//!
+//! A snapshot row only gets a location from ips already looked up, so a slow or unavailable
+//! ipinfo neither stalls nor fails ingestion. The nodes left without one are queued per
+//! committed page; a background task looks their first ips up with one batch request, falls
+//! back to single lookups for the misses and updates the rows through the storage backend.
+//! Pages that find the queue full are dropped, their nodes are queued again next cycle.
+
+use std::{net::IpAddr, sync::OnceLock};
+
+use chrono::{DateTime, Utc};
+use tokio::sync::mpsc;
+
+use crate::{
+    Network,
+    ip_location::{lookup_ipinfo, lookup_ipinfo_batch},
+    storage::{NodeLocation, storage},
+};
+
+const QUEUE_PAGES: usize = 64;
+
+/// A node committed without a location and the global ips it announces.
+pub(crate) struct Unlocated {
+    pub node_id: String,
+    pub ips: Vec<IpAddr>,
+}
+
+struct Page {
+    net: Network,
+    time: DateTime<Utc>,
+    nodes: Vec<Unlocated>,
+}
+
+fn sender() -> &'static mpsc::Sender<Page> {
+    static SENDER: OnceLock<mpsc::Sender<Page>> = OnceLock::new();
+    SENDER.get_or_init(|| {
+        let (tx, rx) = mpsc::channel(QUEUE_PAGES);
+        tokio::spawn(locator(rx));
+        tx
+    })
+}
+
+/// Queue the nodes of the page committed at `time` for a lookup.
+pub(crate) fn enqueue(net: Network, time: &DateTime<Utc>, nodes: Vec<Unlocated>) {
+    if nodes.is_empty() {
+        return;
+    }
+    let count = nodes.len();
+    if let Err(e) = sender().try_send(Page {
+        net,
+        time: *time,
+        nodes,
+    }) {
+        log::warn!(
+            "{:?}, geo queue unavailable, {} nodes left without a location: {}",
+            net,
+            count,
+            e
+        );
+    }
+}
+
+async fn locator(mut rx: mpsc::Receiver<Page>) {
+    while let Some(page) = rx.recv().await {
+        lookup_ipinfo_batch(
+            page.nodes
+                .iter()
+                .filter_map(|node| node.ips.first().copied()),
+        )
+        .await;
+        let mut located = 0;
+        for node in &page.nodes {
+            let mut details = None;
+            for ip in &node.ips {
+                if let Ok(found) = lookup_ipinfo(&ip.to_string()).await {
+                    details = Some(found);
+                    break;
+                }
+            }
+            let Some(details) = details else {
+                continue;
+            };
+            let location = NodeLocation {
+                country_or_region: &details.country,
+                city: &details.city,
+                region: &details.region,
+                loc: &details.loc,
+            };
+            match storage()
+                .update_node_location(page.net, &node.node_id, &page.time, location)
+                .await
+            {
+                Ok(()) => located += 1,
+                Err(e) => log::error!(
+                    "{:?}, failed to store the location of node {}: {}",
+                    page.net,
+                    node.node_id,
+                    e
+                ),
+            }
+        }
+        log::info!(
+            "{:?}, located {} of {} new nodes",
+            page.net,
+            located,
+            page.nodes.len()
+        );
+    }
+}
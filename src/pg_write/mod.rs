@@ -1,8 +1,15 @@
+mod chain_scanner;
+pub mod collector_runs;
+pub mod dead_letter;
+mod geo_queue;
 mod operates;
+pub mod reducers;
+pub mod snapshot_guard;
+mod state_machine;
 mod types;
+pub mod udt_edits;
 
 use arc_swap::ArcSwap;
-use ckb_jsonrpc_types::{JsonBytes, Script};
 use ckb_types::bytes::Bytes;
 use sqlx::{Pool, Postgres};
 
@@ -12,11 +19,13 @@ use std::{
 };
 
 pub use operates::*;
+pub use reducers::DailySummaryInner;
 pub use types::*;
 
-use crate::Network;
+use crate::{ENABLED_NETWORKS, Network, config, error::decode_script};
 
-pub const UDT_INFO_CACHE_SQL: &str = "SELECT id, code_hash, hash_type, args FROM {}";
+pub const UDT_INFO_CACHE_SQL: &str =
+    "SELECT id, name, code_hash, hash_type, args, auto_accept_amount FROM {}";
 pub const UDT_NODE_RELATION_CACHE_SQL: &str = "SELECT 
   node_id,
   array_agg(udt_info_id) AS udt_info_ids
@@ -24,65 +33,117 @@ FROM {}
 GROUP BY node_id";
 
 pub async fn init_global_cache(pool: &Pool<Postgres>) {
-    let mut conn = pool.acquire().await.expect("Failed to acquire connection");
-
-    for net in [Network::Mainnet, Network::Testnet] {
-        // Load UDT infos into cache
-        let sql = UDT_INFO_CACHE_SQL.replace("{}", net.udt_infos());
-        let udt_infos: Vec<UdtInfoCache> = sqlx::query_as(&sql)
-            .fetch_all(&mut *conn)
+    for &net in ENABLED_NETWORKS.iter() {
+        rebuild_global_cache(pool, net)
             .await
-            .expect("Failed to fetch UDT infos");
-
-        let mut udt_map = HashMap::new();
-        for udt in udt_infos {
-            udt_map.insert(
-                Script {
-                    code_hash: {
-                        let mut buf = [0; 32];
-                        faster_hex::hex_decode(udt.code_hash.as_bytes(), &mut buf).unwrap();
-                        buf.into()
-                    },
-                    hash_type: match udt.hash_type.as_str() {
-                        "type" => ckb_jsonrpc_types::ScriptHashType::Type,
-                        "data" => ckb_jsonrpc_types::ScriptHashType::Data,
-                        "data1" => ckb_jsonrpc_types::ScriptHashType::Data1,
-                        "data2" => ckb_jsonrpc_types::ScriptHashType::Data2,
-                        _ => panic!("Unknown hash type: {}", udt.hash_type),
-                    },
-                    args: {
-                        let mut buf = vec![0; udt.args.len() / 2];
-                        faster_hex::hex_decode(udt.args.as_bytes(), &mut buf).unwrap();
-                        JsonBytes::from_vec(buf)
-                    },
-                },
-                udt.id,
-            );
-        }
+            .expect("Failed to load the UDT cache");
+    }
+}
 
-        // Load UDT node relations into cache
-        let sql = UDT_NODE_RELATION_CACHE_SQL.replace("{}", net.node_udt_relations());
-        let rows: Vec<(String, Vec<i32>)> = sqlx::query_as(&sql)
-            .fetch_all(&mut *conn)
-            .await
-            .expect("Failed to fetch UDT node relations");
+/// Replace the UDT cache of `net` with the rows in the database, so it agrees with them again
+/// after a write whose outcome is unknown.
+pub async fn rebuild_global_cache(pool: &Pool<Postgres>, net: Network) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
 
-        let mut udt_node_map = HashMap::new();
-        for (node_id, udt_info_ids) in rows {
-            udt_node_map.insert(Bytes::from(node_id), HashSet::from_iter(udt_info_ids));
-        }
+    // Load UDT infos into cache
+    let sql = UDT_INFO_CACHE_SQL.replace("{}", net.udt_infos());
+    let udt_infos: Vec<UdtInfoCache> = sqlx::query_as(&sql).fetch_all(&mut *conn).await?;
 
-        match net {
-            Network::Mainnet => global_cache().store(Arc::new(RelationCache {
-                udt: udt_map,
-                udt_node: udt_node_map,
-            })),
-            Network::Testnet => global_cache_testnet().store(Arc::new(RelationCache {
-                udt: udt_map,
-                udt_node: udt_node_map,
-            })),
+    let mut udt_map = HashMap::new();
+    let mut udt_values = HashMap::new();
+    for udt in udt_infos {
+        udt_values.insert(
+            udt.id,
+            UdtValues {
+                name: udt.name,
+                auto_accept_amount: udt.auto_accept_amount,
+            },
+        );
+        match decode_script(&udt.code_hash, &udt.hash_type, &udt.args) {
+            Ok(script) => {
+                udt_map.insert(script, udt.id);
+            }
+            Err(e) => log::error!("{:?}, skipping malformed UDT {}: {}", net, udt.id, e),
         }
     }
+
+    // Scripts of merged UDTs resolve to the UDT they were merged into
+    let aliases: Vec<(String, String, String, i32)> = sqlx::query_as(
+        "SELECT code_hash, hash_type, args, udt_info_id FROM udt_info_aliases WHERE net = $1",
+    )
+    .bind(net.name())
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut udt_aliases = HashSet::new();
+    for (code_hash, hash_type, args, udt_info_id) in aliases {
+        let script = match decode_script(&code_hash, &hash_type, &args) {
+            Ok(script) => script,
+            Err(e) => {
+                log::error!(
+                    "{:?}, skipping malformed alias of UDT {}: {}",
+                    net,
+                    udt_info_id,
+                    e
+                );
+                continue;
+            }
+        };
+        udt_map.insert(script.clone(), udt_info_id);
+        udt_aliases.insert(script);
+    }
+
+    // Load UDT node relations into cache
+    let sql = UDT_NODE_RELATION_CACHE_SQL.replace("{}", net.node_udt_relations());
+    let rows: Vec<(String, Vec<i32>)> = sqlx::query_as(&sql).fetch_all(&mut *conn).await?;
+
+    let mut udt_node_map = HashMap::new();
+    for (node_id, udt_info_ids) in rows {
+        udt_node_map.insert(Bytes::from(node_id), HashSet::from_iter(udt_info_ids));
+    }
+
+    relation_cache(net).store(Arc::new(RelationCache {
+        udt: udt_map,
+        udt_values,
+        udt_aliases,
+        udt_node: udt_node_map,
+    }));
+    Ok(())
+}
+
+/// Days a node UDT relation is kept after the node last announced it,
+/// `UDT_RELATION_EXPIRE_DAYS`.
+static UDT_RELATION_EXPIRE_DAYS: LazyLock<i64> = LazyLock::new(|| {
+    config::parse_var("UDT_RELATION_EXPIRE_DAYS")
+        .unwrap_or(30)
+        .max(1)
+});
+
+/// Delete the node UDT relations of `net` unconfirmed for `UDT_RELATION_EXPIRE_DAYS` and
+/// reload the cache, so a node announcing one again inserts it anew.
+pub async fn expire_udt_relations(pool: &Pool<Postgres>, net: Network) -> Result<u64, sqlx::Error> {
+    let before = chrono::Utc::now() - chrono::Duration::days(*UDT_RELATION_EXPIRE_DAYS);
+    let sql = format!(
+        "DELETE FROM {} WHERE last_confirmed < $1",
+        net.node_udt_relations()
+    );
+    let expired = sqlx::query(&sql)
+        .bind(before)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    if expired > 0 {
+        rebuild_global_cache(pool, net).await?;
+    }
+    Ok(expired)
+}
+
+/// UDT cache of `net`, only ever updated with rows a committed transaction wrote, see
+/// [`RelationCache::apply`].
+pub(crate) fn relation_cache(net: Network) -> &'static ArcSwap<RelationCache> {
+    match net {
+        Network::Mainnet => global_cache(),
+        Network::Testnet => global_cache_testnet(),
+    }
 }
 
 pub(crate) fn global_cache() -> &'static ArcSwap<RelationCache> {
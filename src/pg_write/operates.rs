@@ -1,48 +1,70 @@
 use crate::{
-    CKB_MAINNET_RPC, CKB_TESTNET_RPC, RpcClient, get_pg_pool,
-    ip_location::lookup_ipinfo,
+    CKB_MAINNET_RPC, CKB_TESTNET_RPC, ENABLED_NETWORKS, RpcClient, bus, chain_check, changes,
+    clickhouse, codec,
+    error::{FiberDashboardError, decode_hex, decode_json_bytes},
+    events::{self, Event},
+    get_pg_pool,
+    ip_location::{AddressScope, cached_ipinfo, global_ips},
     pg_write::{
         ChannelInfoDBSchema, Network, NodeInfoDBSchema, RelationCache, UdtInfos, UdtNodeRelation,
-        UdtdepRelation, global_cache, global_cache_testnet,
+        UdtdepRelation, chain_scanner, dead_letter, geo_queue, rebuild_global_cache,
+        reducers::{self, DayInput, ReduceContext},
+        relation_cache,
+        state_machine::{AppliedTx, ChannelStateMachine, ObservedTx},
     },
+    reconcile,
     rpc_client::{CKB_MAINNET_RPC_BEARER_TOKEN, CKB_TESTNET_RPC_BEARER_TOKEN},
+    script_versions::{self, ScriptKind},
+    storage::{SnapshotBatch, storage},
     types::{
-        CellType, IndexerScriptSearchMode, MAINNET_COMMITMENT_CODE_HASH, NodeInfo, Order,
-        ScriptType, SearchKey, SearchKeyFilter, TESTNET_COMMITMENT_CODE_HASH, Tx,
-        commitment_script, funding_script,
+        CellType, ChannelInfo, IndexerScriptSearchMode, NodeInfo, Order, ScriptType, SearchKey,
+        SearchKeyFilter, Tx, TxWithCells,
     },
 };
 
 use chrono::Duration;
 use ckb_jsonrpc_types::{BlockNumber, DepType, JsonBytes};
-use ckb_types::{H256, packed, prelude::*};
+use ckb_types::{H256, bytes::Bytes, packed, prelude::*};
 use faster_hex::{hex_decode, hex_string};
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt};
 use multiaddr::{Multiaddr, Protocol};
 use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
 use sqlx::{
     Pool, Postgres,
+    postgres::PgRow,
     types::chrono::{DateTime, Utc},
 };
 
 use std::{
+    borrow::Cow,
     collections::{HashMap, HashSet},
     net::SocketAddr,
-    sync::Arc,
+    sync::atomic::{AtomicU64, Ordering},
     vec,
 };
 
-pub async fn from_rpc_to_db_schema(
-    node_info: NodeInfo,
-    net: Network,
-) -> (
+/// Rows of one announced node: the node, its new or changed UDTs, their cell deps and the
+/// node's UDT relations.
+pub type NodeRows = (
     NodeInfoDBSchema,
     Vec<UdtInfos>,
     Vec<UdtdepRelation>,
     Vec<UdtNodeRelation>,
-) {
-    let node_id = String::from_utf8(node_info.node_id.to_vec()).unwrap();
-    let announce_timestamp = DateTime::from_timestamp_millis(node_info.timestamp as i64).unwrap();
+);
+
+/// Convert a node, allocating ids of UDTs new to `cache` in it. `cache` is a working copy,
+/// published by [`commit_page`] only once the rows are committed, and is left untouched by a
+/// node that does not convert.
+pub fn from_rpc_to_db_schema(
+    node_info: NodeInfo,
+    cache: &mut RelationCache,
+) -> Result<NodeRows, FiberDashboardError> {
+    let node_id = String::from_utf8(node_info.node_id.to_vec())
+        .map_err(|e| FiberDashboardError::invalid("node_id", e))?;
+    let announce_timestamp = DateTime::from_timestamp_millis(node_info.timestamp as i64)
+        .ok_or_else(|| FiberDashboardError::invalid("timestamp", node_info.timestamp))?;
+    let addresses = serde_json::to_string(&node_info.addresses)?;
     let auto_accept_min_ckb_funding_amount =
         hex_string(&node_info.auto_accept_min_ckb_funding_amount.to_be_bytes());
 
@@ -50,37 +72,35 @@ pub async fn from_rpc_to_db_schema(
     let mut udt_dep_relations = vec![];
     let mut udt_node_relations = vec![];
 
-    let global = match net {
-        Network::Mainnet => global_cache().load(),
-        Network::Testnet => global_cache_testnet().load(),
-    };
-    let mut new_udt_infos = RelationCache {
-        udt: global.udt.clone(),
-        udt_node: global.udt_node.clone(),
-    };
-
-    let mut need_update_global = false;
-
     for udt_cfg in node_info.udt_cfg_infos.0 {
-        let len = new_udt_infos.udt.len() as i32;
-        let udt_info_id = *new_udt_infos
-            .udt
-            .entry(udt_cfg.script.clone())
-            .or_insert_with(|| len + 1);
-
-        if len != new_udt_infos.udt.len() as i32 {
-            need_update_global = true;
-            let udt_info = UdtInfos {
-                id: udt_info_id,
-                name: udt_cfg.name,
-                code_hash: hex_string(udt_cfg.script.code_hash.as_bytes()),
-                hash_type: udt_cfg.script.hash_type.to_string(),
-                args: hex_string(udt_cfg.script.args.as_bytes()),
-                auto_accept_amount: udt_cfg
-                    .auto_accept_amount
-                    .map_or("NULL".to_string(), |v| hex_string(&v.to_be_bytes())),
-            };
+        let (udt_info_id, new) = match cache.udt.get(&udt_cfg.script) {
+            Some(id) => (*id, false),
+            None => {
+                // ids of merged UDTs leave gaps, count from the largest one
+                let id = cache.udt.values().max().map_or(1, |max| max + 1);
+                cache.udt.insert(udt_cfg.script.clone(), id);
+                (id, true)
+            }
+        };
+        let alias = cache.udt_aliases.contains(&udt_cfg.script);
+        let udt_info = UdtInfos {
+            id: udt_info_id,
+            name: udt_cfg.name,
+            code_hash: hex_string(udt_cfg.script.code_hash.as_bytes()),
+            hash_type: udt_cfg.script.hash_type.to_string(),
+            args: hex_string(udt_cfg.script.args.as_bytes()),
+            auto_accept_amount: udt_cfg
+                .auto_accept_amount
+                .map(|v| hex_string(&v.to_be_bytes())),
+            changed: !new,
+        };
+        // a known UDT announced with another name or auto accept amount is updated
+        let values = udt_info.values();
+        if !alias && cache.udt_values.get(&udt_info_id) != Some(&values) {
+            cache.udt_values.insert(udt_info_id, values);
             udt_infos.push(udt_info);
+        }
+        if new {
             for dep in udt_cfg.cell_deps {
                 if let Some(cell_dep) = dep.cell_dep {
                     let relation = UdtdepRelation {
@@ -116,32 +136,22 @@ pub async fn from_rpc_to_db_schema(
             }
         }
 
-        match new_udt_infos.udt_node.entry(node_info.node_id.clone()) {
-            std::collections::hash_map::Entry::Occupied(mut entry) => {
-                if entry.get_mut().insert(udt_info_id) {
-                    need_update_global = true;
-                    let relation = UdtNodeRelation {
-                        node_id: node_id.clone(),
-                        udt_info_id,
-                    };
-                    udt_node_relations.push(relation);
-                }
-            }
-            std::collections::hash_map::Entry::Vacant(entry) => {
-                entry.insert(HashSet::from([udt_info_id]));
-                need_update_global = true;
-                let relation = UdtNodeRelation {
-                    node_id: node_id.clone(),
-                    udt_info_id,
-                };
-                udt_node_relations.push(relation);
-            }
-        }
+        // every announced relation is confirmed, the ones new to the cache are inserted
+        let unseen = cache
+            .udt_node
+            .entry(node_info.node_id.clone())
+            .or_default()
+            .insert(udt_info_id);
+        udt_node_relations.push(UdtNodeRelation {
+            node_id: node_id.clone(),
+            udt_info_id,
+            new: unseen,
+        });
     }
 
     let mut node_schema = NodeInfoDBSchema {
         node_name: node_info.node_name,
-        addresses: serde_json::to_string(&node_info.addresses).unwrap(),
+        addresses,
         node_id,
         announce_timestamp,
         chain_hash: hex_string(node_info.chain_hash.as_bytes()),
@@ -150,14 +160,13 @@ pub async fn from_rpc_to_db_schema(
         city: Default::default(),
         region: Default::default(),
         loc: Default::default(),
+        address_scope: AddressScope::of(&node_info.addresses),
     };
 
-    for addr in node_info
-        .addresses
-        .iter()
-        .filter_map(multiaddr_to_socketaddr)
-    {
-        if let Ok(ip_details) = lookup_ipinfo(&addr.ip().to_string()).await {
+    // private and loopback addresses would resolve to nothing or to the wrong place, ips not
+    // looked up yet are left to the geo queue after the insert
+    for ip in global_ips(&node_info.addresses) {
+        if let Some(ip_details) = cached_ipinfo(&ip.to_string()) {
             node_schema.country_or_region = ip_details.country;
             node_schema.city = ip_details.city;
             node_schema.region = ip_details.region;
@@ -165,19 +174,132 @@ pub async fn from_rpc_to_db_schema(
             break;
         }
     }
-    // Update the global cache if there are new UDT infos or relations
-    if need_update_global {
-        match net {
-            Network::Mainnet => global_cache().store(Arc::new(new_udt_infos)),
-            Network::Testnet => global_cache_testnet().store(Arc::new(new_udt_infos)),
-        }
-    }
-    (
+    Ok((
         node_schema,
         udt_infos,
         udt_dep_relations,
         udt_node_relations,
-    )
+    ))
+}
+
+/// Number of duplicate nodes dropped from graph RPC results since startup.
+pub static DUPLICATE_NODES_DROPPED: AtomicU64 = AtomicU64::new(0);
+/// Number of duplicate channels dropped from graph RPC results since startup.
+pub static DUPLICATE_CHANNELS_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Drop nodes repeated within one cycle (duplicate entries in a page or overlapping pages),
+/// keeping the most recent announcement. A duplicate would violate the unique
+/// `(node_id, time)` index and fail the whole batch.
+pub fn dedup_nodes(net: Network, nodes: Vec<NodeInfo>) -> Vec<NodeInfo> {
+    let (nodes, dropped) = dedup_by(
+        nodes,
+        |node| node.node_id.clone(),
+        |new, old| new.timestamp >= old.timestamp,
+    );
+    if dropped > 0 {
+        log::warn!(
+            "{:?} dropped {} duplicate nodes from graph_nodes",
+            net,
+            dropped
+        );
+        DUPLICATE_NODES_DROPPED.fetch_add(dropped as u64, Ordering::Relaxed);
+    }
+    nodes
+}
+
+/// Drop channels repeated within one cycle, the entry seen last wins.
+pub fn dedup_channels(net: Network, channels: Vec<ChannelInfo>) -> Vec<ChannelInfo> {
+    let (channels, dropped) = dedup_by(
+        channels,
+        |channel| channel.channel_outpoint.clone(),
+        |_, _| true,
+    );
+    if dropped > 0 {
+        log::warn!(
+            "{:?} dropped {} duplicate channels from graph_channels",
+            net,
+            dropped
+        );
+        DUPLICATE_CHANNELS_DROPPED.fetch_add(dropped as u64, Ordering::Relaxed);
+    }
+    channels
+}
+
+/// Deduplicate one `graph_nodes` page and drop the nodes an earlier page of the cycle already
+/// committed, pages are committed as they arrive so the first announcement seen wins there.
+pub fn dedup_node_page(
+    net: Network,
+    nodes: Vec<NodeInfo>,
+    committed: &mut HashSet<Bytes>,
+) -> Vec<NodeInfo> {
+    let nodes = dedup_nodes(net, nodes);
+    let total = nodes.len();
+    let nodes = nodes
+        .into_iter()
+        .filter(|node| committed.insert(node.node_id.clone()))
+        .collect::<Vec<_>>();
+    DUPLICATE_NODES_DROPPED.fetch_add((total - nodes.len()) as u64, Ordering::Relaxed);
+    nodes
+}
+
+/// Deduplicate one `graph_channels` page and drop the channels an earlier page of the cycle
+/// already committed.
+pub fn dedup_channel_page(
+    net: Network,
+    channels: Vec<ChannelInfo>,
+    committed: &mut HashSet<JsonBytes>,
+) -> Vec<ChannelInfo> {
+    let channels = dedup_channels(net, channels);
+    let total = channels.len();
+    let channels = channels
+        .into_iter()
+        .filter(|channel| committed.insert(channel.channel_outpoint.clone()))
+        .collect::<Vec<_>>();
+    DUPLICATE_CHANNELS_DROPPED.fetch_add((total - channels.len()) as u64, Ordering::Relaxed);
+    channels
+}
+
+/// One row per UDT of a page with the values announced last, inserted rather than updated when
+/// the page is the first to announce it.
+fn dedup_udt_infos(udt_infos: Vec<UdtInfos>) -> Vec<UdtInfos> {
+    let new = udt_infos
+        .iter()
+        .filter(|udt| !udt.changed)
+        .map(|udt| udt.id)
+        .collect::<HashSet<_>>();
+    let (mut unique, _) = dedup_by(udt_infos, |udt| udt.id, |_, _| true);
+    for udt in &mut unique {
+        udt.changed = !new.contains(&udt.id);
+    }
+    unique
+}
+
+/// Deduplicate `items` by `key` preserving first-seen order, `replace(new, old)` decides
+/// whether a later duplicate overwrites the kept one. Returns the number of dropped items.
+fn dedup_by<T, K: std::hash::Hash + Eq>(
+    items: Vec<T>,
+    key: impl Fn(&T) -> K,
+    replace: impl Fn(&T, &T) -> bool,
+) -> (Vec<T>, usize) {
+    let total = items.len();
+    let mut index: HashMap<K, usize> = HashMap::with_capacity(total);
+    let mut unique: Vec<T> = Vec::with_capacity(total);
+    for item in items {
+        match index.entry(key(&item)) {
+            std::collections::hash_map::Entry::Occupied(entry) => {
+                let kept = &mut unique[*entry.get()];
+                if replace(&item, kept) {
+                    *kept = item;
+                }
+            }
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(unique.len());
+                unique.push(item);
+            }
+        }
+    }
+    let dropped = total - unique.len();
+    (unique, dropped)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -192,34 +314,139 @@ pub async fn insert_batch(
     net: Network,
 ) -> Result<(), sqlx::Error> {
     let mut tx = pool.begin().await?;
-    UdtInfos::insert_batch(&mut tx, udt_infos, net).await?;
+    UdtInfos::insert_batch(&mut tx, udt_infos, time, net).await?;
     UdtdepRelation::use_sqlx(&mut tx, udt_dep_relations, net).await?;
-    UdtNodeRelation::use_sqlx(&mut tx, udt_node_relations, net).await?;
+    UdtNodeRelation::use_sqlx(&mut tx, udt_node_relations, time, net).await?;
     NodeInfoDBSchema::use_sqlx(&mut tx, node_schemas, time, net).await?;
+    NodeInfoDBSchema::upsert_address_scopes(&mut tx, node_schemas, time, net).await?;
     ChannelInfoDBSchema::use_sqlx(&mut tx, channel_schemas, time, net).await?;
     tx.commit().await?;
+    clickhouse::mirror(net, time, node_schemas, channel_schemas);
     Ok(())
 }
 
+/// Convert one cycle of deduplicated graph results into db schemas and commit them at `time`.
+///
+/// Returns the number of committed nodes and channels.
+pub async fn commit_snapshot(
+    net: Network,
+    raw_nodes: Vec<NodeInfo>,
+    raw_channels: Vec<ChannelInfo>,
+    time: &DateTime<Utc>,
+) -> Result<(usize, usize), sqlx::Error> {
+    let (nodes, channels) = commit_page(net, raw_nodes, raw_channels, time).await?;
+    announce_snapshot(net, nodes, channels, time).await;
+    Ok((nodes, channels))
+}
+
+/// Commit one page of a snapshot at `time` in its own transaction, without announcing the
+/// snapshot, see [`announce_snapshot`] once every page of the cycle is committed.
+pub async fn commit_page(
+    net: Network,
+    raw_nodes: Vec<NodeInfo>,
+    raw_channels: Vec<ChannelInfo>,
+    time: &DateTime<Utc>,
+) -> Result<(usize, usize), sqlx::Error> {
+    let mut node_schemas = Vec::with_capacity(raw_nodes.len());
+    let mut udt_infos = Vec::new();
+    let mut udt_dep_relations = Vec::new();
+    let mut udt_node_relations = Vec::new();
+    let mut unlocated = Vec::new();
+    // cloned on the first node, pages of channels only read the cache
+    let published = relation_cache(net).load_full();
+    let mut cache = Cow::Borrowed(&*published);
+    for node in raw_nodes {
+        let ips = global_ips(&node.addresses).collect::<Vec<_>>();
+        let (node_schema, udt_info, udt_dep_relation, udt_node_relation) =
+            match from_rpc_to_db_schema(node, cache.to_mut()) {
+                Ok(rows) => rows,
+                Err(e) => {
+                    log::warn!("{:?}, skipping malformed node: {}", net, e);
+                    continue;
+                }
+            };
+        if node_schema.country_or_region.is_empty() && !ips.is_empty() {
+            unlocated.push(geo_queue::Unlocated {
+                node_id: node_schema.node_id.clone(),
+                ips,
+            });
+        }
+        node_schemas.push(node_schema);
+        udt_infos.extend(udt_info);
+        udt_dep_relations.extend(udt_dep_relation);
+        udt_node_relations.extend(udt_node_relation);
+    }
+    let udt_infos = dedup_udt_infos(udt_infos);
+
+    let channel_schemas = raw_channels
+        .into_iter()
+        .map(|channel| ChannelInfoDBSchema::from((channel, &*cache)))
+        .collect::<Vec<_>>();
+
+    let written = storage()
+        .insert_batch(SnapshotBatch {
+            net,
+            time,
+            udt_infos: &udt_infos,
+            udt_dep_relations: &udt_dep_relations,
+            udt_node_relations: &udt_node_relations,
+            nodes: &node_schemas,
+            channels: &channel_schemas,
+        })
+        .await;
+    let udts_written =
+        !udt_infos.is_empty() || udt_node_relations.iter().any(|relation| relation.new);
+    if let Err(e) = written {
+        // the commit may have reached the database anyway, or another writer took the ids
+        if udts_written
+            && let Some(pool) = crate::PG_POOL.get()
+            && let Err(e) = rebuild_global_cache(pool, net).await
+        {
+            log::error!("{:?}, failed to rebuild the UDT cache: {}", net, e);
+        }
+        return Err(e);
+    }
+    if udts_written {
+        relation_cache(net).rcu(|current| current.apply(&cache, &udt_infos, &udt_node_relations));
+    }
+    geo_queue::enqueue(net, time, unlocated);
+    bus::publish_snapshot(net, time, &node_schemas, &channel_schemas);
+    if let Some(pool) = crate::PG_POOL.get() {
+        changes::record_snapshot(pool, net, time, &node_schemas, &channel_schemas).await;
+    }
+    Ok((node_schemas.len(), channel_schemas.len()))
+}
+
+/// Announce the snapshot committed at `time` to api processes.
+pub async fn announce_snapshot(net: Network, nodes: usize, channels: usize, time: &DateTime<Utc>) {
+    log::info!(
+        "{:?} Fetched {} nodes and {} channels",
+        net,
+        nodes,
+        channels
+    );
+    let event = Event::SnapshotCommitted {
+        net,
+        nodes,
+        channels,
+        time: *time,
+    };
+    match crate::PG_POOL.get() {
+        Some(pool) => events::emit(pool, event).await,
+        None => events::emit_local(event),
+    }
+}
+
 pub async fn daily_statistics(
     pool: &Pool<Postgres>,
     start_time: Option<DateTime<Utc>>,
     nets: impl Iterator<Item = &Network>,
 ) -> Result<(), sqlx::Error> {
-    use chrono::Timelike;
     use sqlx::Row;
 
     let now = Utc::now();
 
-    let end_time = now
-        .with_hour(0)
-        .unwrap()
-        .with_minute(0)
-        .unwrap()
-        .with_second(0)
-        .unwrap()
-        .with_nanosecond(0)
-        .unwrap();
+    let end_time = now.date_naive().and_time(chrono::NaiveTime::MIN).and_utc();
     let start_time = start_time.unwrap_or(end_time - Duration::days(1));
 
     for net in nets {
@@ -239,6 +466,7 @@ pub async fn daily_statistics(
             "
     SELECT DISTINCT ON (time_bucket('1 day', bucket), n.channel_outpoint)
         time_bucket('1 day', bucket) AS day_bucket,
+        n.channel_outpoint,
         n.capacity as asset,
         COALESCE(c.name, 'ckb') as name, r.capacity as capacity
     FROM {} n
@@ -251,408 +479,244 @@ pub async fn daily_statistics(
             net.udt_infos(),
             net.channel_states()
         );
-        let nodes_count: Vec<(DateTime<Utc>, i64)> = sqlx::query(&nodes_count_sql)
+        let mut days: HashMap<DateTime<Utc>, DayInput> = HashMap::new();
+        for row in sqlx::query(&nodes_count_sql)
             .bind(end_time)
             .bind(start_time)
             .fetch_all(pool)
             .await?
-            .into_iter()
-            .map(|row| {
-                let day_bucket: DateTime<Utc> = row.get("day_bucket");
-                let nodes_count: i64 = row.get("nodes_count");
-                (day_bucket, nodes_count)
-            })
-            .collect();
-        let channels_data = sqlx::query(&channels_data_sql)
+        {
+            let day: DateTime<Utc> = row.get("day_bucket");
+            days.entry(day).or_default().nodes_count = row.get("nodes_count");
+        }
+        // streamed, a day only keeps its running statistics instead of every channel
+        let mut rows = sqlx::query(&channels_data_sql)
             .bind(end_time)
             .bind(start_time)
-            .fetch_all(pool)
-            .await?
+            .fetch(pool);
+        while let Some(row) = rows.try_next().await? {
+            let day: DateTime<Utc> = row.get("day_bucket");
+            // channels without a state row yet have no capacity
+            let Some(capacity) = row.try_get::<Option<&str>, _>("capacity")? else {
+                log::warn!(
+                    "{:?}, skipping channel {} without capacity",
+                    net,
+                    row.get::<String, _>("channel_outpoint")
+                );
+                continue;
+            };
+            let asset = u128::from_be_bytes(decode_hex("asset", row.try_get("asset")?)?);
+            let capacity = u64::from_be_bytes(decode_hex("capacity", capacity)?);
+            days.entry(day)
+                .or_default()
+                .channels
+                .entry(row.get("name"))
+                .or_default()
+                .push(asset, capacity);
+        }
+        let days = days
             .into_iter()
-            .map(|row| {
-                let day_bucket: DateTime<Utc> = row.get("day_bucket");
-                let asset: u128 = {
-                    let raw: String = row.get("asset");
-                    let mut buf = [0u8; 16];
-                    faster_hex::hex_decode(raw.as_bytes(), &mut buf).unwrap();
-                    u128::from_be_bytes(buf)
-                };
-                let capacity: u64 = {
-                    let raw: String = row.get("capacity");
-                    let mut buf = [0u8; 8];
-                    faster_hex::hex_decode(raw.as_bytes(), &mut buf).unwrap();
-                    u64::from_be_bytes(buf)
-                };
-                let name = row.get::<String, _>("name");
-                (day_bucket, (name, asset, capacity))
-            })
-            .fold(
-                HashMap::new(),
-                |mut acc: HashMap<DateTime<Utc>, HashMap<String, Vec<(u128, u64)>>>,
-                 (dt, (name, asset, capacity))| {
-                    acc.entry(dt)
-                        .or_default()
-                        .entry(name)
-                        .or_default()
-                        .push((asset, capacity));
-                    acc
-                },
-            );
+            .map(|(day, input)| DayInput { day, ..input })
+            .collect::<Vec<_>>();
 
-        let summarized_data = summarize_data(channels_data, nodes_count);
-        if summarized_data.is_empty() {
-            continue;
+        let ctx = ReduceContext {
+            net: *net,
+            start_time,
+            end_time,
+            days: &days,
+        };
+        // a failing reducer does not keep the others from writing
+        let mut result = Ok(());
+        for reducer in reducers::registered() {
+            if let Err(e) = reducer.reduce(pool, &ctx).await {
+                log::error!(
+                    "Daily reducer {} failed for {:?}: {}",
+                    reducer.name(),
+                    net,
+                    e
+                );
+                result = result.and(Err(e));
+            }
         }
-        let insert_sql = format!(
-            "Insert into {} (day, channels_count, asset_analysis, capacity_analysis, nodes_count) ",
-            net.daily_summarized_data()
-        );
-        let mut query_builder: sqlx::QueryBuilder<'_, sqlx::Postgres> =
-            sqlx::QueryBuilder::new(&insert_sql);
-
-        query_builder.push_values(summarized_data.iter().take(65535 / 5), |mut b, sd| {
-            b.push_bind(sd.date)
-                .push_bind(sqlx::types::Json(&sd.channels_count))
-                .push_bind(sqlx::types::Json(&sd.asset_analysis))
-                .push_bind(sqlx::types::Json(&sd.capacity_analysis))
-                .push_bind(sd.nodes_count);
-        });
-
-        query_builder.push(" On Conflict (day) Do Nothing");
-        query_builder.build().execute(pool).await?;
+        result?;
     }
 
     Ok(())
 }
 
-#[derive(Debug)]
-pub struct DailySummary {
-    pub date: DateTime<Utc>,
-    pub channels_count: HashMap<String, i64>,
-    pub nodes_count: i64,
-    pub asset_analysis: Vec<DailySummaryInner>,
-    pub capacity_analysis: Vec<DailySummaryInner>,
-}
-
-#[derive(Debug, Deserialize, Serialize)]
-pub struct DailySummaryInner {
-    pub name: String,
-    pub average: String,
-    pub min: String,
-    pub max: String,
-    pub median: String,
-    pub sum: String, // hex encoded
+/// Nodes supporting and channels denominated in each UDT per day of `[start_time, end_time)`.
+/// Node support comes from the `node_udt_relations` still confirmed on or after the day, nodes
+/// do not announce when they drop a UDT.
+pub(super) async fn udt_daily_statistics(
+    pool: &Pool<Postgres>,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    net: Network,
+) -> Result<(), sqlx::Error> {
+    let sql = format!(
+        "WITH node_days AS (
+            SELECT DISTINCT time_bucket('1 day', bucket) AS day, node_id FROM {nodes}
+            WHERE bucket < $1::timestamp AND bucket >= $2::timestamp
+        ),
+        udt_nodes AS (
+            SELECT d.day, r.udt_info_id, count(DISTINCT d.node_id) AS nodes
+            FROM node_days d
+            JOIN {relations} r ON r.node_id = d.node_id AND r.last_confirmed >= d.day
+            GROUP BY 1, 2
+        ),
+        udt_channels AS (
+            SELECT time_bucket('1 day', bucket) AS day, udt_type_script AS udt_info_id,
+                count(DISTINCT channel_outpoint) AS channels
+            FROM {channels}
+            WHERE bucket < $1::timestamp AND bucket >= $2::timestamp
+            AND udt_type_script IS NOT NULL
+            GROUP BY 1, 2
+        )
+        INSERT INTO daily_udt_stats (net, day, udt_info_id, nodes, channels)
+        SELECT $3, COALESCE(n.day, c.day)::date, COALESCE(n.udt_info_id, c.udt_info_id),
+            COALESCE(n.nodes, 0), COALESCE(c.channels, 0)
+        FROM udt_nodes n
+        FULL JOIN udt_channels c ON c.day = n.day AND c.udt_info_id = n.udt_info_id
+        ON CONFLICT (net, day, udt_info_id) DO NOTHING",
+        nodes = net.online_nodes_hourly(),
+        relations = net.node_udt_relations(),
+        channels = net.online_channels_hourly(),
+    );
+    sqlx::query(&sql)
+        .bind(end_time)
+        .bind(start_time)
+        .bind(net.name())
+        .execute(pool)
+        .await?;
+    Ok(())
 }
 
-fn summarize_data(
-    channels_data: HashMap<DateTime<Utc>, HashMap<String, Vec<(u128, u64)>>>,
-    nodes_data: Vec<(DateTime<Utc>, i64)>,
-) -> Vec<DailySummary> {
-    use std::collections::HashMap;
-
-    let nodes_by_date: HashMap<DateTime<Utc>, i64> = nodes_data.into_iter().collect();
-
-    let mut all_dates: HashSet<DateTime<Utc>> = channels_data.keys().copied().collect();
-    all_dates.extend(nodes_by_date.keys());
-    all_dates
-        .into_iter()
-        .map(|dt| {
-            let nodes_count = nodes_by_date.get(&dt).copied().unwrap_or(0);
-            if let Some(values) = channels_data.get(&dt) {
-                if values.is_empty() {
-                    return DailySummary {
-                        date: dt,
-                        channels_count: HashMap::default(),
-                        asset_analysis: Vec::default(),
-                        capacity_analysis: Vec::default(),
-                        nodes_count: 0,
-                    };
-                }
-                let mut channel_count = HashMap::new();
-                let mut asset_analysis = Vec::with_capacity(values.len());
-                let mut capacity_analysis = Vec::with_capacity(values.len());
-                for (name, caps) in values.iter() {
-                    channel_count.insert(name.clone(), caps.len() as i64);
-
-                    let assets: Vec<u128> = caps.iter().map(|(asset, _)| *asset).collect();
-
-                    asset_analysis.push(calculate_u128_statistics(name.clone(), assets));
-
-                    let capacities: Vec<u64> = caps.iter().map(|(_, capacity)| *capacity).collect();
-
-                    capacity_analysis.push(calculate_u64_statistics(name.clone(), capacities));
-                }
-
-                DailySummary {
-                    date: dt,
-                    channels_count: channel_count,
-                    asset_analysis,
-                    capacity_analysis,
-                    nodes_count,
+/// States of the channels the monitor follows: every channel not closed yet and the ones closed
+/// in the last 30 days. Malformed rows are logged and skipped, a failing query is retried.
+async fn load_channel_states(pool: &Pool<Postgres>) -> ChannelStates {
+    let mut channels = HashMap::new();
+    for &net in ENABLED_NETWORKS.iter() {
+        let sql = format!(
+            "SELECT channel_outpoint, funding_args, last_tx_hash, last_block_number,
+                last_commitment_args, state, script_version
+            FROM {}
+            WHERE
+                (state IN ('closed_cooperative', 'closed_uncooperative')
+                AND last_commit_time >= now() - interval '30 days')
+                OR
+                state NOT IN ('closed_cooperative', 'closed_uncooperative')",
+            net.channel_states()
+        );
+        let rows = loop {
+            match sqlx::query(&sql).fetch_all(pool).await {
+                Ok(rows) => break rows,
+                Err(e) => {
+                    log::error!("{:?}, failed to load channel states, retrying: {}", net, e);
+                    tokio::time::sleep(std::time::Duration::from_secs(30)).await;
                 }
-            } else {
-                DailySummary {
-                    date: dt,
-                    channels_count: HashMap::default(),
-                    asset_analysis: Vec::default(),
-                    capacity_analysis: Vec::default(),
-                    nodes_count,
+            }
+        };
+        for row in rows {
+            match channel_state_of_row(&row, net) {
+                Ok((outpoint, state)) => {
+                    channels.insert(outpoint, state);
                 }
+                Err(e) => log::error!("{:?}, skipping malformed channel state: {}", net, e),
             }
-        })
-        .collect()
-}
-
-fn calculate_u128_statistics(name: String, mut values: Vec<u128>) -> DailySummaryInner {
-    values.sort_unstable();
-    let min = values[0];
-    let max = values[values.len() - 1];
-    let sum: u128 = values.iter().sum();
-    let average = sum / values.len() as u128;
-    let median = if values.len().is_multiple_of(2) {
-        let mid1 = values[values.len() / 2 - 1];
-        let mid2 = values[values.len() / 2];
-        (mid1 + mid2) / 2
-    } else {
-        values[values.len() / 2]
-    };
-
-    DailySummaryInner {
-        name,
-        average: faster_hex::hex_string(average.to_be_bytes().as_ref()),
-        min: faster_hex::hex_string(min.to_be_bytes().as_ref()),
-        max: faster_hex::hex_string(max.to_be_bytes().as_ref()),
-        median: faster_hex::hex_string(median.to_be_bytes().as_ref()),
-        sum: faster_hex::hex_string(sum.to_be_bytes().as_ref()),
+        }
     }
+    ChannelStates { channels }
 }
 
-fn calculate_u64_statistics(name: String, mut values: Vec<u64>) -> DailySummaryInner {
-    values.sort_unstable();
-    let min = values[0];
-    let max = values[values.len() - 1];
-    let sum: u64 = values.iter().sum();
-    let average = sum / values.len() as u64;
-    let median = if values.len().is_multiple_of(2) {
-        let mid1 = values[values.len() / 2 - 1];
-        let mid2 = values[values.len() / 2];
-        (mid1 + mid2) / 2
-    } else {
-        values[values.len() / 2]
+fn channel_state_of_row(
+    row: &PgRow,
+    net: Network,
+) -> Result<(JsonBytes, ChannelState), FiberDashboardError> {
+    use sqlx::Row;
+    let outpoint = decode_json_bytes("channel_outpoint", row.try_get("channel_outpoint")?)?;
+    let tx_hash = H256::from(decode_hex::<32>(
+        "last_tx_hash",
+        row.try_get("last_tx_hash")?,
+    )?);
+    let block_number = u64::from_be_bytes(decode_hex(
+        "last_block_number",
+        row.try_get("last_block_number")?,
+    )?)
+    .into();
+    let state = match row.try_get::<&str, _>("state")? {
+        "open" => State::Funding {
+            funding_args: decode_json_bytes("funding_args", row.try_get("funding_args")?)?,
+            tx_hash,
+            block_number,
+            script_version: row.try_get("script_version")?,
+        },
+        "closed_waiting_onchain_settlement" => {
+            let commitment_args = row
+                .try_get::<Option<&str>, _>("last_commitment_args")?
+                .ok_or(FiberDashboardError::Missing("last_commitment_args"))?;
+            State::ClosedWaitingOnchainSettlement {
+                tx_hash,
+                block_number,
+                commitment_args: decode_json_bytes("last_commitment_args", commitment_args)?,
+            }
+        }
+        "closed_cooperative" => State::ClosedCooperative,
+        "closed_uncooperative" => State::ClosedUncooperative,
+        state => return Err(FiberDashboardError::unknown("channel state", state)),
     };
-
-    DailySummaryInner {
-        name,
-        average: faster_hex::hex_string(average.to_be_bytes().as_ref()),
-        min: faster_hex::hex_string(min.to_be_bytes().as_ref()),
-        max: faster_hex::hex_string(max.to_be_bytes().as_ref()),
-        median: faster_hex::hex_string(median.to_be_bytes().as_ref()),
-        sum: faster_hex::hex_string(sum.to_be_bytes().as_ref()),
-    }
+    Ok((outpoint, ChannelState { net, state }))
 }
 
 pub async fn channel_states_monitor(
     mut rpc: RpcClient,
     mut recv: tokio::sync::mpsc::Receiver<(Network, Vec<JsonBytes>)>,
 ) {
-    let mut channel_states = {
-        use sqlx::Row;
-        let pool = get_pg_pool();
-        let mainnet_sql = r#"
-        SELECT channel_outpoint, funding_args, last_tx_hash, last_block_number,
-            last_commitment_args, state
-        FROM channel_states
-        WHERE
-            (state IN ('closed_cooperative', 'closed_uncooperative')
-            AND last_commit_time >= now() - interval '30 days')
-            OR
-            state NOT IN ('closed_cooperative', 'closed_uncooperative')"#;
-        let testnet_sql = r#"
-        SELECT channel_outpoint, funding_args, last_tx_hash, last_block_number,
-            last_commitment_args, state
-        FROM channel_states_testnet
-        WHERE
-            (state IN ('closed_cooperative', 'closed_uncooperative')
-            AND last_commit_time >= now() - interval '30 days')
-            OR
-            state NOT IN ('closed_cooperative', 'closed_uncooperative')"#;
-
-        let mainnet_states = sqlx::query(mainnet_sql)
-            .fetch_all(pool)
-            .await
-            .expect("failed to fetch mainnet channel states")
-            .iter()
-            .map(|row| {
-                let raw_outpoint = row.get::<String, _>("channel_outpoint");
-                let raw_funding_args = row.get::<String, _>("funding_args");
-                let raw_last_block_number = row.get::<String, _>("last_block_number");
-                let raw_last_commitment_args = row.get::<Option<String>, _>("last_commitment_args");
-                let raw_tx_hash = row.get::<String, _>("last_tx_hash");
-                let state = row.get::<String, _>("state");
-                let outpoint = {
-                    let mut buf = vec![0u8; raw_outpoint.len() / 2];
-                    hex_decode(raw_outpoint.as_bytes(), &mut buf).unwrap();
-                    JsonBytes::from_bytes(buf.into())
-                };
-                let funding_args = {
-                    let mut buf = vec![0u8; raw_funding_args.len() / 2];
-                    hex_decode(raw_funding_args.as_bytes(), &mut buf).unwrap();
-                    JsonBytes::from_bytes(buf.into())
-                };
-                let last_block_number = {
-                    let mut buf = [0u8; 8];
-                    hex_decode(raw_last_block_number.as_bytes(), &mut buf).unwrap();
-                    u64::from_be_bytes(buf)
-                };
-                let tx_hash = {
-                    let mut buf = [0u8; 32];
-                    hex_decode(raw_tx_hash.as_bytes(), &mut buf).unwrap();
-                    H256::from(buf)
-                };
-                match state.as_str() {
-                    "open" => (
-                        outpoint,
-                        ChannelState {
-                            state: State::Funding {
-                                funding_args,
-                                tx_hash,
-                                block_number: last_block_number.into(),
-                            },
-                            net: Network::Mainnet,
-                        },
-                    ),
-                    "closed_waiting_onchain_settlement" => {
-                        let last_commitment_args = raw_last_commitment_args.as_ref().map(|s| {
-                            let mut buf = vec![0u8; s.len() / 2];
-                            hex_decode(s.as_bytes(), &mut buf).unwrap();
-                            JsonBytes::from_bytes(buf.into())
-                        });
-                        (
-                            outpoint,
-                            ChannelState {
-                                state: State::ClosedWaitingOnchainSettlement {
-                                    tx_hash,
-                                    block_number: last_block_number.into(),
-                                    commitment_args: last_commitment_args.unwrap(),
-                                },
-                                net: Network::Mainnet,
-                            },
-                        )
-                    }
-                    "closed_cooperative" => (
-                        outpoint,
-                        ChannelState {
-                            state: State::ClosedCooperative,
-                            net: Network::Mainnet,
-                        },
-                    ),
-                    "closed_uncooperative" => (
-                        outpoint,
-                        ChannelState {
-                            state: State::ClosedUncooperative,
-                            net: Network::Mainnet,
-                        },
-                    ),
-                    _ => panic!("Unknown state: {}", state),
-                }
-            })
-            .collect::<Vec<_>>();
-        let testnet_states = sqlx::query(testnet_sql)
-            .fetch_all(pool)
-            .await
-            .expect("failed to fetch testnet channel states")
-            .iter()
-            .map(|row| {
-                let raw_outpoint = row.get::<String, _>("channel_outpoint");
-                let raw_funding_args = row.get::<String, _>("funding_args");
-                let raw_last_block_number = row.get::<String, _>("last_block_number");
-                let raw_last_commitment_args = row.get::<Option<String>, _>("last_commitment_args");
-                let raw_tx_hash = row.get::<String, _>("last_tx_hash");
-                let state = row.get::<String, _>("state");
-                let outpoint = {
-                    let mut buf = vec![0u8; raw_outpoint.len() / 2];
-                    hex_decode(raw_outpoint.as_bytes(), &mut buf).unwrap();
-                    JsonBytes::from_bytes(buf.into())
-                };
-                let funding_args = {
-                    let mut buf = vec![0u8; raw_funding_args.len() / 2];
-                    hex_decode(raw_funding_args.as_bytes(), &mut buf).unwrap();
-                    JsonBytes::from_bytes(buf.into())
-                };
-                let last_block_number = {
-                    let mut buf = [0u8; 8];
-                    hex_decode(raw_last_block_number.as_bytes(), &mut buf).unwrap();
-                    u64::from_be_bytes(buf)
-                };
-                let tx_hash = {
-                    let mut buf = [0u8; 32];
-                    hex_decode(raw_tx_hash.as_bytes(), &mut buf).unwrap();
-                    H256::from(buf)
-                };
-                match state.as_str() {
-                    "open" => (
-                        outpoint,
-                        ChannelState {
-                            state: State::Funding {
-                                funding_args,
-                                tx_hash,
-                                block_number: last_block_number.into(),
-                            },
-                            net: Network::Testnet,
-                        },
-                    ),
-                    "closed_waiting_onchain_settlement" => {
-                        let last_commitment_args = raw_last_commitment_args.as_ref().map(|s| {
-                            let mut buf = vec![0u8; s.len() / 2];
-                            hex_decode(s.as_bytes(), &mut buf).unwrap();
-                            JsonBytes::from_bytes(buf.into())
-                        });
-                        (
-                            outpoint,
-                            ChannelState {
-                                state: State::ClosedWaitingOnchainSettlement {
-                                    tx_hash,
-                                    block_number: last_block_number.into(),
-                                    commitment_args: last_commitment_args.unwrap(),
-                                },
-                                net: Network::Testnet,
-                            },
-                        )
-                    }
-                    "closed_cooperative" => (
-                        outpoint,
-                        ChannelState {
-                            state: State::ClosedCooperative,
-                            net: Network::Testnet,
-                        },
-                    ),
-                    "closed_uncooperative" => (
-                        outpoint,
-                        ChannelState {
-                            state: State::ClosedUncooperative,
-                            net: Network::Testnet,
-                        },
-                    ),
-                    _ => panic!("Unknown state: {}", state),
+    let mut channel_states = load_channel_states(get_pg_pool()).await;
+
+    // Outpoints received right before a restart may never have been persisted, and channels
+    // that closed meanwhile no longer show up in the graph to be sent again.
+    for &net in ENABLED_NETWORKS.iter() {
+        if !chain_check::ingest_allowed(net) {
+            continue;
+        }
+        match untracked_channels(get_pg_pool(), net).await {
+            Ok(missing) if !missing.is_empty() => {
+                log::info!("{:?}, recovering {} untracked channels", net, missing.len());
+                for group in new_channels(net, missing, &rpc).await {
+                    let (outpoint, state) = group.into_state();
+                    channel_states.channels.insert(outpoint, state);
                 }
-            })
-            .collect::<Vec<_>>();
-        ChannelStates {
-            channels: mainnet_states.into_iter().chain(testnet_states).collect(),
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("{:?}, failed to look for untracked channels: {}", net, e),
         }
-    };
+    }
 
     let mut internal = tokio::time::interval(std::time::Duration::from_secs(10 * 60));
     internal.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
     let mut heartbeat_timer = tokio::time::interval(std::time::Duration::from_secs(60));
     heartbeat_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut scanner_timer = tokio::time::interval(chain_scanner::SCAN_INTERVAL);
+    scanner_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
     loop {
         tokio::select! {
-            _ = internal.tick() => {
+            scheduled = internal.tick() => {
+                crate::metrics::observe_interval_lag("channel_monitor", scheduled);
+                if let Err(e) = script_versions::sync(get_pg_pool()).await {
+                    log::error!("Failed to load script versions: {}", e);
+                }
                 log::info!("channel states updated");
                 channel_tx_update(&mut channel_states, &mut rpc).await;
             }
+            scheduled = scanner_timer.tick(), if *chain_scanner::CHAIN_SCANNER => {
+                crate::metrics::observe_interval_lag("chain_scanner", scheduled);
+                for &net in ENABLED_NETWORKS.iter() {
+                    if chain_check::ingest_allowed(net) {
+                        scan_chain(&mut channel_states, &mut rpc, net).await;
+                    }
+                }
+            }
             _ = heartbeat_timer.tick() => {
                 CHANNEL_MONITOR_HEARTBEAT.store(Utc::now().timestamp() as u64, std::sync::atomic::Ordering::Release);
             }
@@ -677,27 +741,134 @@ pub async fn channel_states_monitor(
     }
 }
 
-pub static CHANNEL_MONITOR_HEARTBEAT: std::sync::atomic::AtomicU64 =
-    std::sync::atomic::AtomicU64::new(0);
+/// Track the channels of live funding cells of `net` not known yet, then tag the unannounced
+/// ones, see [`chain_scanner`].
+async fn scan_chain(channel_states: &mut ChannelStates, rpc: &mut RpcClient, net: Network) {
+    let pool = get_pg_pool();
+    let live = match reconcile::live_funding_cells(rpc, net).await {
+        Ok(live) => live,
+        Err(e) => {
+            log::error!("{:?}, failed to scan funding cells: {}", net, e);
+            return;
+        }
+    };
+    match chain_scanner::untracked(pool, net, &live).await {
+        Ok(untracked) if !untracked.is_empty() => {
+            log::info!(
+                "{:?}, {} channels discovered on chain",
+                net,
+                untracked.len()
+            );
+            for group in new_channels(net, untracked, rpc).await {
+                let (outpoint, state) = group.into_state();
+                channel_states.channels.insert(outpoint, state);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => log::error!(
+            "{:?}, failed to look for untracked funding cells: {}",
+            net,
+            e
+        ),
+    }
+    if let Err(e) = chain_scanner::record(pool, net, &live).await {
+        log::error!("{:?}, failed to record unannounced channels: {}", net, e);
+    }
+}
+
+/// How far back the startup recovery scan looks for untracked channels.
+const RECOVERY_WINDOW: Duration = Duration::days(30);
 
-#[derive(Clone)]
-enum UpdateType {
-    Nothing,
-    Update((Network, JsonBytes, ChannelStateUpdate)),
+/// Channels announced in the graph during the last [`RECOVERY_WINDOW`] without a row in
+/// `channel_states`, leaving out those waiting in the dead-letter queue.
+async fn untracked_channels(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<Vec<JsonBytes>, sqlx::Error> {
+    use sqlx::Row;
+    let sql = format!(
+        "SELECT DISTINCT c.channel_outpoint FROM {} c
+        WHERE c.time >= $1
+        AND NOT EXISTS (SELECT 1 FROM {} s WHERE s.channel_outpoint = c.channel_outpoint)
+        AND NOT EXISTS (SELECT 1 FROM channel_update_dead_letters d
+            WHERE d.channel_outpoint = c.channel_outpoint AND d.resolved_at IS NULL)",
+        net.channel_infos(),
+        net.channel_states()
+    );
+    Ok(sqlx::query(&sql)
+        .bind(Utc::now() - RECOVERY_WINDOW)
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .filter_map(|row| {
+            let raw = row.get::<String, _>("channel_outpoint");
+            let mut buf = vec![0u8; raw.len() / 2];
+            hex_decode(raw.as_bytes(), &mut buf).ok()?;
+            Some(JsonBytes::from_bytes(buf.into()))
+        })
+        .collect())
 }
 
+/// Number of newly seen channel outpoints not handed to the state monitor because its queue
+/// was full since startup, they stay untracked and are handed off again next cycle.
+pub static CHANNEL_HANDOFFS_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Outpoints of the snapshot committed at `time` the state monitor has not persisted yet,
+/// leaving out those waiting in the dead-letter queue.
+pub async fn untracked_outpoints(
+    pool: &Pool<Postgres>,
+    net: Network,
+    time: &DateTime<Utc>,
+) -> Result<Vec<JsonBytes>, sqlx::Error> {
+    use sqlx::Row;
+    let sql = format!(
+        "SELECT DISTINCT c.channel_outpoint FROM {} c
+        WHERE c.time = $1
+        AND NOT EXISTS (SELECT 1 FROM {} s WHERE s.channel_outpoint = c.channel_outpoint)
+        AND NOT EXISTS (SELECT 1 FROM channel_update_dead_letters d
+            WHERE d.channel_outpoint = c.channel_outpoint AND d.resolved_at IS NULL)",
+        net.channel_infos(),
+        net.channel_states()
+    );
+    Ok(sqlx::query(&sql)
+        .bind(time)
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .filter_map(|row| {
+            let raw = row.get::<String, _>("channel_outpoint");
+            let mut buf = vec![0u8; raw.len() / 2];
+            hex_decode(raw.as_bytes(), &mut buf).ok()?;
+            Some(JsonBytes::from_bytes(buf.into()))
+        })
+        .collect())
+}
+
+pub static CHANNEL_MONITOR_HEARTBEAT: std::sync::atomic::AtomicU64 =
+    std::sync::atomic::AtomicU64::new(0);
+
 async fn channel_tx_update(channel_states: &mut ChannelStates, rpc: &mut RpcClient) {
-    let (testnet_tip, mainnet_tip) = loop {
-        let testnet_tip = {
-            rpc.set_bearer_token(CKB_TESTNET_RPC_BEARER_TOKEN.clone());
-            rpc.get_indexer_tip(CKB_TESTNET_RPC.clone()).await
-        };
-        let mainnet_tip = {
-            rpc.set_bearer_token(CKB_MAINNET_RPC_BEARER_TOKEN.clone());
-            rpc.get_indexer_tip(CKB_MAINNET_RPC.clone()).await
-        };
-        if let (Ok(testnet_tip), Ok(mainnet_tip)) = (testnet_tip, mainnet_tip) {
-            break (testnet_tip, mainnet_tip);
+    // every enabled indexer has to answer before channels are followed
+    let tips = loop {
+        let mut tips = HashMap::new();
+        for &net in ENABLED_NETWORKS.iter() {
+            let (url, token) = match net {
+                Network::Mainnet => (
+                    CKB_MAINNET_RPC.clone(),
+                    CKB_MAINNET_RPC_BEARER_TOKEN.clone(),
+                ),
+                Network::Testnet => (
+                    CKB_TESTNET_RPC.clone(),
+                    CKB_TESTNET_RPC_BEARER_TOKEN.clone(),
+                ),
+            };
+            rpc.set_bearer_token(token);
+            if let Ok(tip) = rpc.get_indexer_tip(url).await {
+                tips.insert(net, tip.block_number);
+            }
+        }
+        if tips.len() == ENABLED_NETWORKS.len() {
+            break tips;
         }
         tokio::time::sleep(std::time::Duration::from_millis(100)).await;
     };
@@ -707,223 +878,95 @@ async fn channel_tx_update(channel_states: &mut ChannelStates, rpc: &mut RpcClie
         if matches!(
             state.state,
             State::ClosedCooperative | State::ClosedUncooperative
-        ) {
+        ) || !chain_check::ingest_allowed(state.net)
+        {
             continue;
         }
+        let Some(&tip) = tips.get(&state.net) else {
+            continue;
+        };
         let outpoint = outpoint.clone();
         let state = state.clone();
         let mut rpc = rpc.clone();
         let handle = tokio::spawn(async move {
-            let mut csus = UpdateType::Nothing;
-            match state.state {
-                State::ClosedCooperative | State::ClosedUncooperative => {}
-                State::Funding { funding_args, .. } => {
-                    let url = match state.net {
-                        Network::Mainnet => {
-                            rpc.set_bearer_token(CKB_MAINNET_RPC_BEARER_TOKEN.clone());
-                            CKB_MAINNET_RPC.clone()
-                        }
-                        Network::Testnet => {
-                            rpc.set_bearer_token(CKB_TESTNET_RPC_BEARER_TOKEN.clone());
-                            CKB_TESTNET_RPC.clone()
-                        }
-                    };
-                    let txs = loop {
-                        let txs = rpc
-                            .get_transactions(
-                                url.clone(),
-                                SearchKey {
-                                    script: funding_script(state.net, funding_args.clone()),
-                                    script_type: ScriptType::Lock,
-                                    script_search_mode: Some(IndexerScriptSearchMode::Exact),
-                                    filter: None,
-                                    with_data: Some(false),
-                                    group_by_transaction: Some(true),
-                                },
-                                Order::Desc,
-                                100.into(),
-                                None,
-                            )
-                            .await;
-
-                        if let Ok(txs) = txs {
-                            break txs;
-                        }
-                        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                    };
-                    let code_hash = match state.net {
-                        Network::Mainnet => &*MAINNET_COMMITMENT_CODE_HASH,
-                        Network::Testnet => &*TESTNET_COMMITMENT_CODE_HASH,
-                    };
-                    if txs.objects.len() == 2
-                        && let Tx::Grouped(tc) = &txs.objects[0]
-                    {
-                        let new_tx = loop {
-                            let tx = rpc.get_transaction(url.clone(), &tc.tx_hash).await;
-                            if let Ok(tx) = tx {
-                                break tx.unwrap();
-                            }
-                            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                        };
+            let url = match state.net {
+                Network::Mainnet => {
+                    rpc.set_bearer_token(CKB_MAINNET_RPC_BEARER_TOKEN.clone());
+                    CKB_MAINNET_RPC.clone()
+                }
+                Network::Testnet => {
+                    rpc.set_bearer_token(CKB_TESTNET_RPC_BEARER_TOKEN.clone());
+                    CKB_TESTNET_RPC.clone()
+                }
+            };
+            let mut machine = match state.state {
+                State::ClosedCooperative | State::ClosedUncooperative => return None,
+                State::Funding {
+                    tx_hash,
+                    block_number,
+                    funding_args,
+                    script_version,
+                } => {
+                    let mut machine =
+                        ChannelStateMachine::resume(DBState::Open, tx_hash, block_number, None);
+                    // Untagged channels are looked up under every accepted funding lock until
+                    // one of them holds the funding cell.
+                    let mut txs = Vec::new();
+                    for script in script_versions::funding_scripts(
+                        state.net,
+                        script_version.as_deref(),
+                        funding_args.clone(),
+                    ) {
+                        txs = loop {
+                            let txs = rpc
+                                .get_transactions(
+                                    url.clone(),
+                                    SearchKey {
+                                        script: script.clone(),
+                                        script_type: ScriptType::Lock,
+                                        script_search_mode: Some(IndexerScriptSearchMode::Exact),
+                                        filter: None,
+                                        with_data: Some(false),
+                                        group_by_transaction: Some(true),
+                                    },
+                                    Order::Desc,
+                                    100.into(),
+                                    None,
+                                )
+                                .await;
 
-                        let header = loop {
-                            let header =
-                                rpc.get_header_by_number(url.clone(), tc.block_number).await;
-                            if let Ok(header) = header {
-                                break header;
+                            if let Ok(txs) = txs {
+                                break txs.objects;
                             }
                             tokio::time::sleep(std::time::Duration::from_millis(100)).await;
                         };
-
-                        let commitment_args: Option<JsonBytes> =
-                            new_tx.inner.outputs.iter().find_map(|output| {
-                                if &output.lock.code_hash == code_hash {
-                                    Some(output.lock.args.clone())
-                                } else {
-                                    None
-                                }
-                            });
-                        match commitment_args {
-                            None => match csus {
-                                UpdateType::Nothing => {
-                                    csus = UpdateType::Update((
-                                        state.net,
-                                        outpoint.clone(),
-                                        ChannelStateUpdate {
-                                            outpoint: outpoint.clone(),
-                                            state: DBState::ClosedCooperative,
-                                            last_block_number: tc.block_number,
-                                            last_commit: header.inner.timestamp.value(),
-                                            last_commitment_args: None,
-                                            txs: vec![(
-                                                tc.tx_hash.clone(),
-                                                tc.block_number,
-                                                header.inner.timestamp.value(),
-                                                None,
-                                                None,
-                                            )],
-                                        },
-                                    ));
-                                }
-                                UpdateType::Update((_, _, ref mut s)) => {
-                                    s.state = DBState::ClosedCooperative;
-                                    s.last_block_number = tc.block_number;
-                                    s.last_commit = header.inner.timestamp.value();
-                                    s.txs.push((
-                                        tc.tx_hash.clone(),
-                                        tc.block_number,
-                                        header.inner.timestamp.value(),
-                                        None,
-                                        None,
-                                    ));
-                                }
-                            },
-                            Some(commitment_args) => match csus {
-                                UpdateType::Nothing => {
-                                    csus = UpdateType::Update((
-                                        state.net,
-                                        outpoint.clone(),
-                                        ChannelStateUpdate {
-                                            outpoint: outpoint.clone(),
-                                            state: DBState::ClosedWaitingOnchainSettlement,
-                                            last_block_number: tc.block_number,
-                                            last_commit: header.inner.timestamp.value(),
-                                            last_commitment_args: Some(commitment_args.clone()),
-                                            txs: vec![(
-                                                tc.tx_hash.clone(),
-                                                tc.block_number,
-                                                header.inner.timestamp.value(),
-                                                None,
-                                                Some(commitment_args.clone()),
-                                            )],
-                                        },
-                                    ));
-                                }
-                                UpdateType::Update((_, _, ref mut s)) => {
-                                    s.state = DBState::ClosedWaitingOnchainSettlement;
-                                    s.last_block_number = tc.block_number;
-                                    s.last_commitment_args = Some(commitment_args.clone());
-                                    s.last_commit = header.inner.timestamp.value();
-                                    s.txs.push((
-                                        tc.tx_hash.clone(),
-                                        tc.block_number,
-                                        header.inner.timestamp.value(),
-                                        None,
-                                        Some(commitment_args.clone()),
-                                    ));
-                                }
-                            },
+                        if !txs.is_empty() {
+                            break;
                         }
                     }
-
-                    let current_state = if let UpdateType::Update((_, _, ref s)) = csus {
-                        s.state
-                    } else {
-                        DBState::Open
-                    };
-                    // Continue to retrieve commitment transactions
-                    if current_state == DBState::ClosedWaitingOnchainSettlement
-                        && let UpdateType::Update((_, _, csu)) = csus.clone()
+                    if txs.len() == 2
+                        && let Tx::Grouped(tc) = &txs[0]
                     {
-                        let commitment_args = csu.last_commitment_args.unwrap();
-                        let block_number = csu.last_block_number;
-                        let tx_hash = csu.txs.last().unwrap().0.clone();
-                        commitment_branch(
-                            &rpc,
-                            state.net,
-                            &outpoint,
-                            url,
-                            commitment_args,
-                            block_number,
-                            match state.net {
-                                Network::Mainnet => mainnet_tip.block_number,
-                                Network::Testnet => testnet_tip.block_number,
-                            },
-                            tx_hash,
-                            code_hash,
-                            &mut csus,
-                        )
-                        .await;
+                        machine.observe(observe_tx(&rpc, &url, state.net, tc, false).await);
                     }
+                    machine
                 }
                 State::ClosedWaitingOnchainSettlement {
                     commitment_args,
                     block_number,
                     tx_hash,
-                } => {
-                    let code_hash = match state.net {
-                        Network::Mainnet => &MAINNET_COMMITMENT_CODE_HASH,
-                        Network::Testnet => &TESTNET_COMMITMENT_CODE_HASH,
-                    };
-                    let url = match state.net {
-                        Network::Mainnet => {
-                            rpc.set_bearer_token(CKB_MAINNET_RPC_BEARER_TOKEN.clone());
-                            CKB_MAINNET_RPC.clone()
-                        }
-                        Network::Testnet => {
-                            rpc.set_bearer_token(CKB_TESTNET_RPC_BEARER_TOKEN.clone());
-                            CKB_TESTNET_RPC.clone()
-                        }
-                    };
-                    commitment_branch(
-                        &rpc,
-                        state.net,
-                        &outpoint,
-                        url,
-                        commitment_args,
-                        block_number,
-                        match state.net {
-                            Network::Mainnet => mainnet_tip.block_number,
-                            Network::Testnet => testnet_tip.block_number,
-                        },
-                        tx_hash,
-                        code_hash,
-                        &mut csus,
-                    )
-                    .await;
-                }
-            }
-            csus
+                } => ChannelStateMachine::resume(
+                    DBState::ClosedWaitingOnchainSettlement,
+                    tx_hash,
+                    block_number,
+                    Some(commitment_args),
+                ),
+            };
+            // Continue to retrieve commitment transactions
+            follow_commitments(&rpc, state.net, &url, Some(tip), &mut machine).await;
+            machine
+                .into_update(outpoint.clone())
+                .map(|csu| (state.net, outpoint, csu))
         });
         handles.push(handle);
     }
@@ -935,10 +978,7 @@ async fn channel_tx_update(channel_states: &mut ChannelStates, rpc: &mut RpcClie
         .buffer_unordered(2048)
         .filter_map(|res| async move {
             match res {
-                Ok(ut) => match ut {
-                    UpdateType::Nothing => None,
-                    UpdateType::Update((net, outpoint, csu)) => Some((net, outpoint, csu)),
-                },
+                Ok(update) => update,
                 Err(e) => {
                     log::error!("channel update task failed: {}", e);
                     None
@@ -946,16 +986,29 @@ async fn channel_tx_update(channel_states: &mut ChannelStates, rpc: &mut RpcClie
             }
         })
         .for_each(|(net, outpoint, csu)| {
-            channel_states.channels.get_mut(&outpoint).unwrap().state = match csu.state {
-                DBState::ClosedCooperative => State::ClosedCooperative,
-                DBState::ClosedUncooperative => State::ClosedUncooperative,
-                DBState::ClosedWaitingOnchainSettlement => State::ClosedWaitingOnchainSettlement {
-                    tx_hash: csu.txs.last().unwrap().0.clone(),
-                    block_number: csu.last_block_number,
-                    commitment_args: csu.last_commitment_args.clone().unwrap(),
-                },
-                DBState::Open => panic!("Invalid state transition to Open"),
+            let state = match (csu.state, csu.txs.last(), &csu.last_commitment_args) {
+                (DBState::ClosedCooperative, ..) => State::ClosedCooperative,
+                (DBState::ClosedUncooperative, ..) => State::ClosedUncooperative,
+                (DBState::ClosedWaitingOnchainSettlement, Some(tx), Some(commitment_args)) => {
+                    State::ClosedWaitingOnchainSettlement {
+                        tx_hash: tx.0.clone(),
+                        block_number: csu.last_block_number,
+                        commitment_args: commitment_args.clone(),
+                    }
+                }
+                (state, ..) => {
+                    log::error!(
+                        "{:?}, dropping invalid update of channel 0x{} to {:?}",
+                        net,
+                        hex_string(outpoint.as_bytes()),
+                        state
+                    );
+                    return futures::future::ready(());
+                }
             };
+            if let Some(channel) = channel_states.channels.get_mut(&outpoint) {
+                channel.state = state;
+            }
             match net {
                 Network::Mainnet => {
                     mainnet.insert(outpoint, csu);
@@ -975,58 +1028,60 @@ async fn channel_tx_update(channel_states: &mut ChannelStates, rpc: &mut RpcClie
             mainnet.len()
         );
         let pool = get_pg_pool();
-        let mut conn = pool.begin().await.unwrap();
-        if !mainnet.is_empty() {
-            let updates = mainnet.values().collect::<Vec<_>>();
-            ChannelStateUpdate::state_sql(&updates, &mut conn, Network::Mainnet)
-                .await
-                .unwrap();
-            ChannelStateUpdate::txs_sql(&updates, &mut conn, Network::Mainnet)
-                .await
-                .unwrap();
-        }
-        if !testnet.is_empty() {
-            let updates = testnet.values().collect::<Vec<_>>();
-            ChannelStateUpdate::state_sql(&updates, &mut conn, Network::Testnet)
-                .await
-                .unwrap();
-            ChannelStateUpdate::txs_sql(&updates, &mut conn, Network::Testnet)
-                .await
-                .unwrap();
+        for (net, updates) in [(Network::Mainnet, &mainnet), (Network::Testnet, &testnet)] {
+            if updates.is_empty() {
+                continue;
+            }
+            let updates = updates.values().collect::<Vec<_>>();
+            if let Err(e) = ChannelStateUpdate::write(pool, net, &updates).await {
+                log::error!(
+                    "{:?}, failed to write {} channel state updates, dead-lettering them: {}",
+                    net,
+                    updates.len(),
+                    e
+                );
+                dead_letter::record_state_updates(pool, net, &updates, &e).await;
+                continue;
+            }
+            let state_changes = updates
+                .iter()
+                .map(|cu| cu.state_change(net))
+                .collect::<Vec<_>>();
+            changes::record_state_changes(pool, net, &state_changes).await;
+            bus::publish_state_changes(net, state_changes.into_iter());
+            events::emit(
+                pool,
+                Event::ChannelStatesUpdated {
+                    net,
+                    count: updates.len(),
+                },
+            )
+            .await;
         }
-        conn.commit().await.unwrap();
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-async fn commitment_branch(
+/// Transactions spending or creating the commitment cells with `args` under any accepted
+/// commitment lock, oldest first.
+async fn commitment_txs(
     rpc: &RpcClient,
+    url: &reqwest::Url,
     net: Network,
-    outpoint: &JsonBytes,
-    url: reqwest::Url,
-    mut commitment_args: JsonBytes,
-    start: BlockNumber,
-    end: BlockNumber,
-    tx_hash: H256,
-    code_hash: &H256,
-    csus: &mut UpdateType,
-) {
-    let mut exist_tx = vec![tx_hash];
-    let mut already_search_commitment = Vec::new();
-    loop {
-        if already_search_commitment.contains(&commitment_args) {
-            break;
-        }
-        already_search_commitment.push(commitment_args.clone());
+    args: &JsonBytes,
+    block_range: Option<(BlockNumber, BlockNumber)>,
+) -> Vec<Tx> {
+    let mut all = Vec::new();
+    for script in script_versions::commitment_scripts(net, args.clone()) {
         let txs = loop {
             let txs = rpc
                 .get_transactions(
                     url.clone(),
                     SearchKey {
-                        script: commitment_script(net, commitment_args.clone()),
+                        script: script.clone(),
                         script_type: ScriptType::Lock,
                         script_search_mode: Some(IndexerScriptSearchMode::Exact),
-                        filter: Some(SearchKeyFilter::block_range(start, end)),
+                        filter: block_range
+                            .map(|(start, end)| SearchKeyFilter::block_range(start, end)),
                         with_data: Some(false),
                         group_by_transaction: Some(true),
                     },
@@ -1040,119 +1095,86 @@ async fn commitment_branch(
             }
             tokio::time::sleep(std::time::Duration::from_millis(100)).await;
         };
+        all.extend(txs.objects);
+    }
+    all.sort_by_key(|tx| match tx {
+        Tx::Grouped(tc) => (tc.block_number.value(), tc.tx_index.value()),
+        Tx::Ungrouped(tc) => (tc.block_number.value(), tc.tx_index.value()),
+    });
+    all
+}
 
-        for tx in txs.objects {
-            if let Tx::Grouped(tc) = &tx {
-                if exist_tx.contains(&tc.tx_hash) {
-                    continue;
-                }
-                exist_tx.push(tc.tx_hash.clone());
-
-                let new_tx = loop {
-                    let tx = rpc.get_transaction(url.clone(), &tc.tx_hash).await;
-                    if let Ok(tx) = tx {
-                        break tx.unwrap();
-                    }
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                };
-                let header = loop {
-                    let header = rpc.get_header_by_number(url.clone(), tc.block_number).await;
-                    if let Ok(header) = header {
-                        break header;
-                    }
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                };
-                let mut witness_args = None;
-                for (ty, idx) in tc.cells.iter() {
-                    if let CellType::Input = ty {
-                        witness_args = new_tx.inner.witnesses.get(idx.value() as usize).cloned();
-                    }
-                }
-
-                let next_commitment_args: Option<JsonBytes> =
-                    new_tx.inner.outputs.iter().find_map(|output| {
-                        if &output.lock.code_hash == code_hash {
-                            Some(output.lock.args.clone())
-                        } else {
-                            None
-                        }
-                    });
-                match next_commitment_args {
-                    None => match csus {
-                        UpdateType::Nothing => {
-                            *csus = UpdateType::Update((
-                                net,
-                                outpoint.clone(),
-                                ChannelStateUpdate {
-                                    outpoint: outpoint.clone(),
-                                    state: DBState::ClosedUncooperative,
-                                    last_block_number: tc.block_number,
-                                    last_commit: header.inner.timestamp.value(),
-                                    last_commitment_args: None,
-                                    txs: vec![(
-                                        tc.tx_hash.clone(),
-                                        tc.block_number,
-                                        header.inner.timestamp.value(),
-                                        witness_args.clone(),
-                                        None,
-                                    )],
-                                },
-                            ));
-                        }
-                        UpdateType::Update((_, _, s)) => {
-                            s.state = DBState::ClosedUncooperative;
-                            s.last_block_number = tc.block_number;
-                            s.last_commit = header.inner.timestamp.value();
-                            s.txs.push((
-                                tc.tx_hash.clone(),
-                                tc.block_number,
-                                header.inner.timestamp.value(),
-                                witness_args.clone(),
-                                None,
-                            ));
-                        }
-                    },
+/// Fetch `tc` and its block header for the state machine. The witness of the spent cell is
+/// only kept when it is a commitment cell.
+async fn observe_tx(
+    rpc: &RpcClient,
+    url: &reqwest::Url,
+    net: Network,
+    tc: &TxWithCells,
+    spends_commitment: bool,
+) -> ObservedTx {
+    // the indexer listed the tx, one the node does not return yet is retried like a failure
+    let new_tx = loop {
+        let tx = rpc.get_transaction(url.clone(), &tc.tx_hash).await;
+        if let Ok(Some(tx)) = tx {
+            break tx;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    };
+    let header = loop {
+        let header = rpc.get_header_by_number(url.clone(), tc.block_number).await;
+        if let Ok(header) = header {
+            break header;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    };
+    let mut witness_args = None;
+    if spends_commitment {
+        for (ty, idx) in tc.cells.iter() {
+            if let CellType::Input = ty {
+                witness_args = new_tx.inner.witnesses.get(idx.value() as usize).cloned();
+            }
+        }
+    }
+    let commitment_args = new_tx.inner.outputs.iter().find_map(|output| {
+        if script_versions::is_commitment_lock(net, &output.lock) {
+            Some(output.lock.args.clone())
+        } else {
+            None
+        }
+    });
+    ObservedTx {
+        tx_hash: tc.tx_hash.clone(),
+        block_number: tc.block_number,
+        timestamp: header.inner.timestamp.value(),
+        witness_args,
+        commitment_args,
+    }
+}
 
-                    Some(next_commitment_args) => {
-                        commitment_args = next_commitment_args.clone();
-
-                        match csus {
-                            UpdateType::Nothing => {
-                                *csus = UpdateType::Update((
-                                    net,
-                                    outpoint.clone(),
-                                    ChannelStateUpdate {
-                                        outpoint: outpoint.clone(),
-                                        state: DBState::ClosedWaitingOnchainSettlement,
-                                        last_block_number: tc.block_number,
-                                        last_commit: header.inner.timestamp.value(),
-                                        last_commitment_args: Some(next_commitment_args.clone()),
-                                        txs: vec![(
-                                            tc.tx_hash.clone(),
-                                            tc.block_number,
-                                            header.inner.timestamp.value(),
-                                            witness_args.clone(),
-                                            Some(next_commitment_args.clone()),
-                                        )],
-                                    },
-                                ));
-                            }
-                            UpdateType::Update((_, _, s)) => {
-                                s.state = DBState::ClosedWaitingOnchainSettlement;
-                                s.last_block_number = tc.block_number;
-                                s.last_commit = header.inner.timestamp.value();
-                                s.last_commitment_args = Some(next_commitment_args.clone());
-                                s.txs.push((
-                                    tc.tx_hash.clone(),
-                                    tc.block_number,
-                                    header.inner.timestamp.value(),
-                                    witness_args.clone(),
-                                    Some(next_commitment_args.clone()),
-                                ));
-                            }
-                        }
-                    }
-                }
+/// Feed the transactions spending the channel's commitment cells to `machine` until it settles
+/// or no new commitment cell shows up, searching from its last block up to `end` when given.
+async fn follow_commitments(
+    rpc: &RpcClient,
+    net: Network,
+    url: &reqwest::Url,
+    end: Option<BlockNumber>,
+    machine: &mut ChannelStateMachine,
+) {
+    let start = machine.last_block_number();
+    let mut searched = Vec::new();
+    while machine.awaits_settlement()
+        && let Some(args) = machine.last_commitment_args().cloned()
+        && !searched.contains(&args)
+    {
+        searched.push(args.clone());
+        let txs = commitment_txs(rpc, url, net, &args, end.map(|end| (start, end))).await;
+        for tx in txs {
+            if let Tx::Grouped(tc) = &tx
+                && !machine.knows(&tc.tx_hash)
+            {
+                let observed = observe_tx(rpc, url, net, tc, machine.awaits_settlement()).await;
+                machine.observe(observed);
             }
         }
     }
@@ -1165,6 +1187,8 @@ enum State {
         tx_hash: H256,
         block_number: BlockNumber,
         funding_args: JsonBytes,
+        /// Version of the funding lock, see [`script_versions`].
+        script_version: Option<String>,
     },
     ClosedWaitingOnchainSettlement {
         tx_hash: H256,
@@ -1221,17 +1245,48 @@ impl std::str::FromStr for DBState {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChannelStateUpdate {
-    outpoint: JsonBytes,
-    state: DBState,
-    last_commit: u64,
-    last_block_number: BlockNumber,
-    last_commitment_args: Option<JsonBytes>,
-    txs: Vec<(H256, BlockNumber, u64, Option<JsonBytes>, Option<JsonBytes>)>, // (tx_hash, block_number, timestamp, witness_args, commitment_args)
+    pub(super) outpoint: JsonBytes,
+    pub(super) state: DBState,
+    pub(super) last_commit: u64,
+    pub(super) last_block_number: BlockNumber,
+    pub(super) last_commitment_args: Option<JsonBytes>,
+    pub(super) txs: Vec<AppliedTx>,
 }
 
 impl ChannelStateUpdate {
+    pub(super) fn outpoint(&self) -> &JsonBytes {
+        &self.outpoint
+    }
+
+    pub(super) fn state_change(&self, net: Network) -> bus::ChannelStateChange {
+        bus::ChannelStateChange {
+            net,
+            channel_outpoint: format!("0x{}", hex_string(self.outpoint.as_bytes())),
+            state: self.state,
+            last_commit_time: DateTime::from_timestamp_millis(self.last_commit as i64)
+                .unwrap_or_default(),
+            last_block_number: self.last_block_number.value(),
+            last_tx_hash: self
+                .txs
+                .last()
+                .map(|tx| format!("0x{}", hex_string(tx.0.as_bytes()))),
+        }
+    }
+
+    /// Write `updates` of `net` in one transaction.
+    pub(super) async fn write(
+        pool: &Pool<Postgres>,
+        net: Network,
+        updates: &[&ChannelStateUpdate],
+    ) -> Result<(), sqlx::Error> {
+        let mut conn = pool.begin().await?;
+        ChannelStateUpdate::state_sql(updates, &mut conn, net).await?;
+        ChannelStateUpdate::txs_sql(updates, &mut conn, net).await?;
+        conn.commit().await
+    }
+
     async fn state_sql(
         updates: &[&ChannelStateUpdate],
         conn: &mut sqlx::PgConnection,
@@ -1241,6 +1296,8 @@ impl ChannelStateUpdate {
             return Ok(());
         }
 
+        // The block number guard keeps a retried dead letter from rolling back newer states,
+        // fixed width big endian hex compares like the number.
         let sql = format!(
             "UPDATE {} SET 
                 last_tx_hash = $1,
@@ -1248,7 +1305,7 @@ impl ChannelStateUpdate {
                 last_commitment_args = $3,
                 state = $4,
                 last_commit_time = $5
-            WHERE channel_outpoint = $6",
+            WHERE channel_outpoint = $6 AND last_block_number <= $2",
             net.channel_states()
         );
 
@@ -1316,12 +1373,15 @@ impl ChannelStateUpdate {
                     .push_bind(commitment_args.as_ref().map(|a| hex_string(a.as_bytes())));
             },
         );
+        query_builder.push(" ON CONFLICT (channel_outpoint, tx_hash) DO NOTHING");
         let query = query_builder.build();
         let _ = query.execute(conn).await?;
         Ok(())
     }
 }
 
+#[serde_as]
+#[derive(Serialize, Deserialize)]
 pub struct ChannelGroup {
     net: Network,
     outpoint: JsonBytes,
@@ -1329,14 +1389,40 @@ pub struct ChannelGroup {
     capacity: u64,
     create_time: u64,
     last_commit_time: u64,
+    /// As a string, json numbers lose precision past `u64`.
+    #[serde_as(as = "Option<DisplayFromStr>")]
     udt_value: Option<u128>,
     last_block_number: BlockNumber,
     last_commitment_args: Option<JsonBytes>,
     state: DBState,
-    txs: Vec<(H256, BlockNumber, u64, Option<JsonBytes>, Option<JsonBytes>)>, // (tx_hash, block_number, commit_time, witness_args, commitment_args)
+    script_version: Option<String>,
+    txs: Vec<AppliedTx>,
 }
 
 impl ChannelGroup {
+    pub(super) fn net(&self) -> Network {
+        self.net
+    }
+
+    pub(super) fn outpoint(&self) -> &JsonBytes {
+        &self.outpoint
+    }
+
+    pub(super) fn state_change(&self) -> bus::ChannelStateChange {
+        bus::ChannelStateChange {
+            net: self.net,
+            channel_outpoint: format!("0x{}", hex_string(self.outpoint.as_bytes())),
+            state: self.state,
+            last_commit_time: DateTime::from_timestamp_millis(self.last_commit_time as i64)
+                .unwrap_or_default(),
+            last_block_number: self.last_block_number.value(),
+            last_tx_hash: self
+                .txs
+                .last()
+                .map(|tx| format!("0x{}", hex_string(tx.0.as_bytes()))),
+        }
+    }
+
     fn into_state(self) -> (JsonBytes, ChannelState) {
         (
             self.outpoint,
@@ -1347,6 +1433,7 @@ impl ChannelGroup {
                         tx_hash: self.txs[0].0.clone(),
                         block_number: self.txs[0].1,
                         funding_args: self.funding_args,
+                        script_version: self.script_version,
                     },
                     DBState::ClosedWaitingOnchainSettlement => {
                         State::ClosedWaitingOnchainSettlement {
@@ -1362,12 +1449,23 @@ impl ChannelGroup {
         )
     }
 
+    /// Insert new channels and their transactions in one transaction, all of the same network.
+    pub(super) async fn write(
+        pool: &Pool<Postgres>,
+        groups: &[ChannelGroup],
+    ) -> Result<(), sqlx::Error> {
+        let mut conn = pool.begin().await?;
+        ChannelGroup::state_sql(groups, &mut conn).await?;
+        ChannelGroup::txs_sql(groups, &mut conn).await?;
+        conn.commit().await
+    }
+
     async fn state_sql(
         groups: &[ChannelGroup],
         conn: &mut sqlx::PgConnection,
     ) -> Result<(), sqlx::Error> {
         let sql = format!(
-            "insert into {} (channel_outpoint, funding_args, capacity, last_tx_hash, last_block_number, udt_value, create_time, last_commit_time, last_commitment_args, state) ",
+            "insert into {} (channel_outpoint, funding_args, capacity, last_tx_hash, last_block_number, udt_value, create_time, last_commit_time, last_commitment_args, state, script_version) ",
             groups[0].net.channel_states()
         );
 
@@ -1398,7 +1496,8 @@ impl ChannelGroup {
                         .as_ref()
                         .map(|args| hex_string(args.as_bytes())),
                 )
-                .push_bind(cg.state.to_sql());
+                .push_bind(cg.state.to_sql())
+                .push_bind(cg.script_version.clone());
         });
         let query = query_builder.build();
         query.execute(conn).await?;
@@ -1441,6 +1540,7 @@ impl ChannelGroup {
                     .push_bind(commitment_args.as_ref().map(|a| hex_string(a.as_bytes())));
             },
         );
+        query_builder.push(" ON CONFLICT (channel_outpoint, tx_hash) DO NOTHING");
         let query = query_builder.build();
         query.execute(conn).await?;
         Ok(())
@@ -1456,52 +1556,66 @@ pub async fn new_channels(
         Network::Mainnet => CKB_MAINNET_RPC.clone(),
         Network::Testnet => CKB_TESTNET_RPC.clone(),
     };
-    let code_hash = match net {
-        Network::Mainnet => &*MAINNET_COMMITMENT_CODE_HASH,
-        Network::Testnet => &*TESTNET_COMMITMENT_CODE_HASH,
-    };
     let mut handles = Vec::with_capacity(channels.len());
     for outpoint in channels {
         let rpc = rpc.clone();
         let url = url.clone();
-        let code_hash = code_hash.clone();
         let handle = tokio::spawn(async move {
-            let raw_outpoint = packed::OutPoint::from_slice(outpoint.as_bytes()).unwrap();
+            let channel = format!("0x{}", hex_string(outpoint.as_bytes()));
+            let Ok(raw_outpoint) = packed::OutPoint::from_slice(outpoint.as_bytes()) else {
+                log::error!("{:?}, skipping malformed channel outpoint {}", net, channel);
+                return None;
+            };
 
+            // a channel whose funding tx is not found is handed over again by a later cycle
             let funding_tx = loop {
-                let tx = rpc
+                match rpc
                     .get_transaction(url.clone(), &raw_outpoint.as_reader().tx_hash().into())
-                    .await;
-                if let Ok(tx) = tx {
-                    break tx.unwrap();
+                    .await
+                {
+                    Ok(Some(tx)) => break tx,
+                    Ok(None) => {
+                        log::warn!("{:?}, funding tx of channel {} not found", net, channel);
+                        return None;
+                    }
+                    Err(_) => {}
                 }
                 tokio::time::sleep(std::time::Duration::from_millis(100)).await;
             };
-            let (funding_args, capacity) = funding_tx
+            let Some((funding_lock, capacity)) = funding_tx
                 .inner
                 .outputs
                 .get(Into::<u32>::into(raw_outpoint.as_reader().index()) as usize)
-                .map(|output| (output.lock.args.clone(), output.capacity.value()))
-                .unwrap();
+                .map(|output| (output.lock.clone(), output.capacity.value()))
+            else {
+                log::error!(
+                    "{:?}, funding tx of channel {} lacks its output",
+                    net,
+                    channel
+                );
+                return None;
+            };
+            let funding_args = funding_lock.args.clone();
+            let script_version =
+                script_versions::version_of(net, ScriptKind::Funding, &funding_lock);
+            if script_version.is_none() {
+                log::warn!(
+                    "{:?}, funding lock of channel {} matches no accepted funding script",
+                    net,
+                    channel
+                );
+            }
             let udt_value = funding_tx
                 .inner
                 .outputs_data
                 .get(Into::<u32>::into(raw_outpoint.as_reader().index()) as usize)
-                .and_then(|data| {
-                    if data.len() >= 16 {
-                        let mut buf = [0u8; 16];
-                        buf.copy_from_slice(&data.as_bytes()[0..16]);
-                        Some(u128::from_le_bytes(buf))
-                    } else {
-                        None
-                    }
-                });
+                .and_then(|data| codec::udt_amount(data.as_bytes()));
             let txs = loop {
                 let txs = rpc
                     .get_transactions(
                         url.clone(),
                         SearchKey {
-                            script: funding_script(net, funding_args.clone()),
+                            script: funding_lock.clone(),
                             script_type: ScriptType::Lock,
                             script_search_mode: Some(IndexerScriptSearchMode::Exact),
                             filter: None,
@@ -1520,203 +1634,96 @@ pub async fn new_channels(
                 tokio::time::sleep(std::time::Duration::from_millis(100)).await;
             };
 
-            let mut group = ChannelGroup {
-                net,
-                outpoint,
-                funding_args: funding_args.clone(),
-                last_block_number: 0.into(),
-                capacity,
-                create_time: 0,
-                last_commit_time: 0,
-                last_commitment_args: None,
-                udt_value,
-                state: DBState::Open,
-                txs: vec![(funding_tx.hash.clone(), 0.into(), 0, None, None)],
-            };
-            for tx in txs.objects {
-                if let Tx::Grouped(tc) = &tx {
-                    if tc.tx_hash == funding_tx.hash {
-                        let header = loop {
-                            let header =
-                                rpc.get_header_by_number(url.clone(), tc.block_number).await;
-                            if let Ok(header) = header {
-                                break header;
-                            }
-                            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                        };
-                        group.create_time = header.inner.timestamp.value();
-                        group.last_commit_time = header.inner.timestamp.value();
-                        group.last_block_number = tc.block_number;
-                        group.txs[0].1 = tc.block_number;
-                        group.txs[0].2 = header.inner.timestamp.value();
-                        continue;
-                    }
-                    let new_tx = loop {
-                        let tx = rpc.get_transaction(url.clone(), &tc.tx_hash).await;
-                        if let Ok(tx) = tx {
-                            break tx.unwrap();
-                        }
-                        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                    };
+            let funding_block = txs.objects.iter().find_map(|tx| match tx {
+                Tx::Grouped(tc) if tc.tx_hash == funding_tx.hash => Some(tc.block_number),
+                _ => None,
+            });
+            let (block_number, create_time) = match funding_block {
+                Some(block_number) => {
                     let header = loop {
-                        let header = rpc.get_header_by_number(url.clone(), tc.block_number).await;
+                        let header = rpc.get_header_by_number(url.clone(), block_number).await;
                         if let Ok(header) = header {
                             break header;
                         }
                         tokio::time::sleep(std::time::Duration::from_millis(100)).await;
                     };
-                    let commitment_args: Option<JsonBytes> =
-                        new_tx.inner.outputs.iter().find_map(|output| {
-                            if output.lock.code_hash == code_hash {
-                                Some(output.lock.args.clone())
-                            } else {
-                                None
-                            }
-                        });
-                    match commitment_args {
-                        None => {
-                            group.state = DBState::ClosedCooperative;
-                            group.last_block_number = tc.block_number;
-                            group.last_commit_time = header.inner.timestamp.value();
-                            group.txs.push((
-                                tc.tx_hash.clone(),
-                                tc.block_number,
-                                header.inner.timestamp.value(),
-                                None,
-                                None,
-                            ));
-                        }
-                        Some(args) => {
-                            group.last_commitment_args = Some(args.clone());
-                            group.last_block_number = tc.block_number;
-                            group.last_commit_time = header.inner.timestamp.value();
-                            group.state = DBState::ClosedWaitingOnchainSettlement;
-                            group.txs.push((
-                                tc.tx_hash.clone(),
-                                tc.block_number,
-                                header.inner.timestamp.value(),
-                                None,
-                                Some(args),
-                            ));
-                        }
-                    }
+                    (block_number, header.inner.timestamp.value())
                 }
-            }
-            let mut commitment_args = vec![];
-            while let Some(args) = group.last_commitment_args.clone() {
-                if commitment_args.contains(&Some(args.clone())) {
-                    break;
-                }
-                commitment_args.push(Some(args.clone()));
-                let txs = loop {
-                    let txs = rpc
-                        .get_transactions(
-                            url.clone(),
-                            SearchKey {
-                                script: commitment_script(net, args.clone()),
-                                script_type: ScriptType::Lock,
-                                script_search_mode: Some(IndexerScriptSearchMode::Exact),
-                                filter: None,
-                                with_data: Some(false),
-                                group_by_transaction: Some(true),
-                            },
-                            Order::Asc,
-                            100.into(),
-                            None,
-                        )
-                        .await;
-                    if let Ok(txs) = txs {
-                        break txs;
-                    }
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                };
-                for tx in txs.objects {
-                    if let Tx::Grouped(tc) = &tx {
-                        if group
-                            .txs
-                            .iter()
-                            .any(|(hash, _, _, _, _)| hash == &tc.tx_hash)
-                        {
-                            continue;
-                        }
-                        let new_tx = loop {
-                            let tx = rpc.get_transaction(url.clone(), &tc.tx_hash).await;
-                            if let Ok(tx) = tx {
-                                break tx.unwrap();
-                            }
-                            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                        };
-                        let header = loop {
-                            let header =
-                                rpc.get_header_by_number(url.clone(), tc.block_number).await;
-                            if let Ok(header) = header {
-                                break header;
-                            }
-                            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                        };
-                        let mut witness_args = None;
-                        for (ty, idx) in tc.cells.iter() {
-                            if let CellType::Input = ty {
-                                witness_args =
-                                    new_tx.inner.witnesses.get(idx.value() as usize).cloned();
-                            }
-                        }
-                        let commitment_args: Option<JsonBytes> =
-                            new_tx.inner.outputs.iter().find_map(|output| {
-                                if output.lock.code_hash == code_hash {
-                                    Some(output.lock.args.clone())
-                                } else {
-                                    None
-                                }
-                            });
-                        match commitment_args {
-                            None => {
-                                group.state = DBState::ClosedUncooperative;
-                                group.last_block_number = tc.block_number;
-                                group.last_commit_time = header.inner.timestamp.value();
-                                group.txs.push((
-                                    tc.tx_hash.clone(),
-                                    tc.block_number,
-                                    header.inner.timestamp.value(),
-                                    witness_args,
-                                    None,
-                                ));
-                            }
-                            Some(args) => {
-                                group.last_commitment_args = Some(args.clone());
-                                group.last_block_number = tc.block_number;
-                                group.last_commit_time = header.inner.timestamp.value();
-                                group.state = DBState::ClosedWaitingOnchainSettlement;
-                                group.txs.push((
-                                    tc.tx_hash.clone(),
-                                    tc.block_number,
-                                    header.inner.timestamp.value(),
-                                    witness_args,
-                                    Some(args),
-                                ));
-                            }
-                        }
-                    }
+                None => (0.into(), 0),
+            };
+            let mut machine =
+                ChannelStateMachine::opened(funding_tx.hash.clone(), block_number, create_time);
+            for tx in txs.objects {
+                if let Tx::Grouped(tc) = &tx
+                    && !machine.knows(&tc.tx_hash)
+                {
+                    machine.observe(observe_tx(&rpc, &url, net, tc, false).await);
                 }
             }
-            group
+            follow_commitments(&rpc, net, &url, None, &mut machine).await;
+
+            Some(ChannelGroup {
+                net,
+                outpoint,
+                funding_args,
+                capacity,
+                create_time,
+                last_commit_time: machine.last_commit,
+                udt_value,
+                last_block_number: machine.last_block_number,
+                last_commitment_args: machine.last_commitment_args,
+                state: machine.state,
+                script_version,
+                txs: machine.txs,
+            })
         });
         handles.push(handle);
     }
 
     let groups: Vec<ChannelGroup> = futures::stream::iter(handles)
         .buffer_unordered(2048)
-        .map(|x| x.unwrap())
+        .filter_map(|res| async move {
+            match res {
+                Ok(group) => group,
+                Err(e) => {
+                    log::error!("new channel task failed: {}", e);
+                    None
+                }
+            }
+        })
         .collect()
         .await;
 
     log::info!("{:?}, new channels processed: {}", net, groups.len());
     if !groups.is_empty() {
         let pool = get_pg_pool();
-        let mut conn = pool.begin().await.unwrap();
-        ChannelGroup::state_sql(&groups, &mut conn).await.unwrap();
-        ChannelGroup::txs_sql(&groups, &mut conn).await.unwrap();
-        conn.commit().await.unwrap();
+        match ChannelGroup::write(pool, &groups).await {
+            Ok(()) => {
+                let state_changes = groups
+                    .iter()
+                    .map(ChannelGroup::state_change)
+                    .collect::<Vec<_>>();
+                changes::record_state_changes(pool, net, &state_changes).await;
+                bus::publish_state_changes(net, state_changes.into_iter());
+                events::emit(
+                    pool,
+                    Event::NewChannels {
+                        net,
+                        count: groups.len(),
+                    },
+                )
+                .await;
+            }
+            Err(e) => {
+                log::error!(
+                    "{:?}, failed to write {} new channels, dead-lettering them: {}",
+                    net,
+                    groups.len(),
+                    e
+                );
+                dead_letter::record_new_channels(pool, &groups, &e).await;
+            }
+        }
     }
     groups
 }
@@ -1750,3 +1757,79 @@ pub fn multiaddr_to_socketaddr(addr: &Multiaddr) -> Option<SocketAddr> {
 
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use ckb_jsonrpc_types::{JsonBytes, Script, ScriptHashType};
+    use ckb_types::{H256, bytes::Bytes};
+
+    use super::{dedup_by, dedup_udt_infos};
+    use crate::pg_write::{RelationCache, UdtInfos, UdtNodeRelation};
+
+    #[test]
+    fn dedup_keeps_first_seen_order_and_counts_drops() {
+        let items = vec![(1, "a"), (2, "b"), (1, "c"), (3, "d"), (2, "e")];
+        let (unique, dropped) = dedup_by(items, |(k, _)| *k, |_, _| true);
+        assert_eq!(unique, vec![(1, "c"), (2, "e"), (3, "d")]);
+        assert_eq!(dropped, 2);
+    }
+
+    #[test]
+    fn dedup_respects_replace_predicate() {
+        let items = vec![(1, 10), (1, 5), (1, 12)];
+        let (unique, dropped) = dedup_by(items, |(k, _)| *k, |new, old| new.1 >= old.1);
+        assert_eq!(unique, vec![(1, 12)]);
+        assert_eq!(dropped, 2);
+    }
+
+    #[test]
+    fn committed_udts_merge_into_the_published_cache() {
+        let script = |byte| Script {
+            code_hash: H256([byte; 32]),
+            hash_type: ScriptHashType::Type,
+            args: JsonBytes::default(),
+        };
+        let mut written = RelationCache::default();
+        written.udt.insert(script(1), 1);
+        // published by another writer while the page was committing
+        let mut current = RelationCache::default();
+        current.udt.insert(script(2), 2);
+
+        let merged = current.apply(
+            &written,
+            &[],
+            &[UdtNodeRelation {
+                node_id: "02ab".to_string(),
+                udt_info_id: 1,
+                new: true,
+            }],
+        );
+        assert_eq!(merged.udt.get(&script(1)), Some(&1));
+        assert_eq!(merged.udt.get(&script(2)), Some(&2));
+        assert!(merged.udt_node[&Bytes::from("02ab")].contains(&1));
+    }
+
+    #[test]
+    fn a_udt_keeps_its_last_values_and_stays_new_within_a_page() {
+        let udt = |id, name: &str, changed| UdtInfos {
+            id,
+            name: name.to_string(),
+            code_hash: String::new(),
+            hash_type: "type".to_string(),
+            args: String::new(),
+            auto_accept_amount: None,
+            changed,
+        };
+        let unique = dedup_udt_infos(vec![
+            udt(1, "RUSD", false),
+            udt(2, "old", true),
+            udt(1, "rUSD", true),
+            udt(2, "new", true),
+        ]);
+        let rows = unique
+            .iter()
+            .map(|udt| (udt.id, udt.name.as_str(), udt.changed))
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec![(1, "rUSD", false), (2, "new", true)]);
+    }
+}
@@ -0,0 +1,332 @@
+//! Reducers of the daily summarizer.
+//!
+//! [`daily_statistics`](super::daily_statistics) loads the online nodes and channels of every
+//! day once and hands them to each registered [`DailyReducer`], which writes its own columns or
+//! table. New daily metrics are added by implementing the trait and calling [`register`] at
+//! startup, the built-in ones are registered by default.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, RwLock},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+
+use crate::{
+    Network,
+    stats::{ChannelStats, MAGNITUDE_BUCKETS, ValueStats, magnitude_bucket},
+};
+
+/// Online channels of one day.
+#[derive(Debug, Default)]
+pub struct DayInput {
+    pub day: DateTime<Utc>,
+    pub nodes_count: i64,
+    /// Channels per asset name (`ckb` or the udt name).
+    pub channels: HashMap<String, ChannelStats>,
+}
+
+/// What a reducer gets for one network, `days` covers `[start_time, end_time)`.
+pub struct ReduceContext<'a> {
+    pub net: Network,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub days: &'a [DayInput],
+}
+
+#[async_trait::async_trait]
+pub trait DailyReducer: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Write the reduction of `ctx.days`, days already written are kept as they are.
+    async fn reduce(
+        &self,
+        pool: &Pool<Postgres>,
+        ctx: &ReduceContext<'_>,
+    ) -> Result<(), sqlx::Error>;
+}
+
+static REDUCERS: LazyLock<RwLock<Vec<Arc<dyn DailyReducer>>>> = LazyLock::new(|| {
+    RwLock::new(vec![
+        Arc::new(Summary),
+        Arc::new(CapacityPercentiles),
+        Arc::new(CapacityGini),
+        Arc::new(CapacityHistograms),
+        Arc::new(UdtSplits),
+    ])
+});
+
+/// Run `reducer` in every following daily summary.
+pub fn register(reducer: impl DailyReducer + 'static) {
+    REDUCERS.write().unwrap().push(Arc::new(reducer));
+}
+
+pub(crate) fn registered() -> Vec<Arc<dyn DailyReducer>> {
+    REDUCERS.read().unwrap().clone()
+}
+
+/// Channel counts, min / max / median / average / sum of asset amounts and capacities per
+/// asset and the node count, in `daily_summarized_data`.
+pub struct Summary;
+
+#[async_trait::async_trait]
+impl DailyReducer for Summary {
+    fn name(&self) -> &'static str {
+        "summary"
+    }
+
+    async fn reduce(
+        &self,
+        pool: &Pool<Postgres>,
+        ctx: &ReduceContext<'_>,
+    ) -> Result<(), sqlx::Error> {
+        let summarized_data = summarize_data(ctx.days);
+        if summarized_data.is_empty() {
+            return Ok(());
+        }
+        let insert_sql = format!(
+            "Insert into {} (day, channels_count, asset_analysis, capacity_analysis, nodes_count) ",
+            ctx.net.daily_summarized_data()
+        );
+        let mut query_builder: sqlx::QueryBuilder<'_, sqlx::Postgres> =
+            sqlx::QueryBuilder::new(&insert_sql);
+
+        query_builder.push_values(summarized_data.iter().take(65535 / 5), |mut b, sd| {
+            b.push_bind(sd.date)
+                .push_bind(sqlx::types::Json(&sd.channels_count))
+                .push_bind(sqlx::types::Json(&sd.asset_analysis))
+                .push_bind(sqlx::types::Json(&sd.capacity_analysis))
+                .push_bind(sd.nodes_count);
+        });
+
+        query_builder.push(" On Conflict (day) Do Nothing");
+        query_builder.build().execute(pool).await?;
+        Ok(())
+    }
+}
+
+/// p10 / p25 / p75 / p90 of channel capacity per asset, in `daily_capacity_percentiles`.
+pub struct CapacityPercentiles;
+
+#[async_trait::async_trait]
+impl DailyReducer for CapacityPercentiles {
+    fn name(&self) -> &'static str {
+        "capacity_percentiles"
+    }
+
+    async fn reduce(
+        &self,
+        pool: &Pool<Postgres>,
+        ctx: &ReduceContext<'_>,
+    ) -> Result<(), sqlx::Error> {
+        let rows = per_asset_capacities(ctx.days)
+            .map(|(day, name, mut capacities)| {
+                let mut p = |q| capacities.percentile(q) as i64;
+                (day, name, [p(0.1), p(0.25), p(0.75), p(0.9)])
+            })
+            .collect::<Vec<_>>();
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut query_builder = sqlx::QueryBuilder::<Postgres>::new(
+            "INSERT INTO daily_capacity_percentiles (net, day, name, p10, p25, p75, p90) ",
+        );
+        query_builder.push_values(rows.iter().take(65535 / 7), |mut b, (day, name, p)| {
+            b.push_bind(ctx.net.name())
+                .push_bind(day.date_naive())
+                .push_bind(name)
+                .push_bind(p[0])
+                .push_bind(p[1])
+                .push_bind(p[2])
+                .push_bind(p[3]);
+        });
+        query_builder.push(" ON CONFLICT (net, day, name) DO NOTHING");
+        query_builder.build().execute(pool).await?;
+        Ok(())
+    }
+}
+
+/// Gini coefficient of channel capacity per asset, in `daily_capacity_gini`.
+pub struct CapacityGini;
+
+#[async_trait::async_trait]
+impl DailyReducer for CapacityGini {
+    fn name(&self) -> &'static str {
+        "capacity_gini"
+    }
+
+    async fn reduce(
+        &self,
+        pool: &Pool<Postgres>,
+        ctx: &ReduceContext<'_>,
+    ) -> Result<(), sqlx::Error> {
+        let rows = per_asset_capacities(ctx.days)
+            .map(|(day, name, mut capacities)| {
+                (day, name, capacities.count as i32, capacities.gini())
+            })
+            .collect::<Vec<_>>();
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut query_builder = sqlx::QueryBuilder::<Postgres>::new(
+            "INSERT INTO daily_capacity_gini (net, day, name, channels, gini) ",
+        );
+        query_builder.push_values(
+            rows.iter().take(65535 / 5),
+            |mut b, (day, name, channels, gini)| {
+                b.push_bind(ctx.net.name())
+                    .push_bind(day.date_naive())
+                    .push_bind(name)
+                    .push_bind(channels)
+                    .push_bind(gini);
+            },
+        );
+        query_builder.push(" ON CONFLICT (net, day, name) DO NOTHING");
+        query_builder.build().execute(pool).await?;
+        Ok(())
+    }
+}
+
+/// Channels per capacity magnitude of every asset, in `daily_capacity_histograms`.
+pub struct CapacityHistograms;
+
+#[async_trait::async_trait]
+impl DailyReducer for CapacityHistograms {
+    fn name(&self) -> &'static str {
+        "capacity_histograms"
+    }
+
+    async fn reduce(
+        &self,
+        pool: &Pool<Postgres>,
+        ctx: &ReduceContext<'_>,
+    ) -> Result<(), sqlx::Error> {
+        let rows = per_asset_capacities(ctx.days)
+            .map(|(day, name, mut capacities)| {
+                let counts = capacities
+                    .histogram(MAGNITUDE_BUCKETS, |shannons| {
+                        magnitude_bucket(shannons / 100_000_000)
+                    })
+                    .into_iter()
+                    .map(|count| count as i64)
+                    .collect::<Vec<_>>();
+                (day, name, counts)
+            })
+            .collect::<Vec<_>>();
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut query_builder = sqlx::QueryBuilder::<Postgres>::new(
+            "INSERT INTO daily_capacity_histograms (net, day, name, counts) ",
+        );
+        query_builder.push_values(rows.iter().take(65535 / 4), |mut b, (day, name, counts)| {
+            b.push_bind(ctx.net.name())
+                .push_bind(day.date_naive())
+                .push_bind(name)
+                .push_bind(counts);
+        });
+        query_builder.push(" ON CONFLICT (net, day, name) DO NOTHING");
+        query_builder.build().execute(pool).await?;
+        Ok(())
+    }
+}
+
+/// Nodes supporting and channels denominated in each udt, in `daily_udt_stats`.
+pub struct UdtSplits;
+
+#[async_trait::async_trait]
+impl DailyReducer for UdtSplits {
+    fn name(&self) -> &'static str {
+        "udt_splits"
+    }
+
+    async fn reduce(
+        &self,
+        pool: &Pool<Postgres>,
+        ctx: &ReduceContext<'_>,
+    ) -> Result<(), sqlx::Error> {
+        super::udt_daily_statistics(pool, ctx.start_time, ctx.end_time, ctx.net).await
+    }
+}
+
+/// Capacities of every asset of every day, cloned since quantiles sort or compress them.
+fn per_asset_capacities(
+    days: &[DayInput],
+) -> impl Iterator<Item = (DateTime<Utc>, &str, ValueStats)> {
+    days.iter().flat_map(|input| {
+        input
+            .channels
+            .iter()
+            .map(|(name, stats)| (input.day, name.as_str(), stats.capacities.clone()))
+    })
+}
+
+#[derive(Debug)]
+pub struct DailySummary {
+    pub date: DateTime<Utc>,
+    pub channels_count: HashMap<String, i64>,
+    pub nodes_count: i64,
+    pub asset_analysis: Vec<DailySummaryInner>,
+    pub capacity_analysis: Vec<DailySummaryInner>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DailySummaryInner {
+    pub name: String,
+    pub average: String,
+    pub min: String,
+    pub max: String,
+    pub median: String,
+    pub sum: String, // hex encoded
+}
+
+fn summarize_data(days: &[DayInput]) -> Vec<DailySummary> {
+    days.iter()
+        .map(|input| {
+            let values = &input.channels;
+            let mut channel_count = HashMap::new();
+            let mut asset_analysis = Vec::with_capacity(values.len());
+            let mut capacity_analysis = Vec::with_capacity(values.len());
+            for (name, stats) in values.iter() {
+                channel_count.insert(name.clone(), stats.assets.count as i64);
+                asset_analysis.push(u128_statistics(name.clone(), stats.assets.clone()));
+                capacity_analysis.push(u64_statistics(name.clone(), stats.capacities.clone()));
+            }
+
+            DailySummary {
+                date: input.day,
+                channels_count: channel_count,
+                asset_analysis,
+                capacity_analysis,
+                nodes_count: input.nodes_count,
+            }
+        })
+        .collect()
+}
+
+fn u128_statistics(name: String, mut values: ValueStats) -> DailySummaryInner {
+    let median = values.median();
+    DailySummaryInner {
+        name,
+        average: faster_hex::hex_string(values.average().to_be_bytes().as_ref()),
+        min: faster_hex::hex_string(values.min.to_be_bytes().as_ref()),
+        max: faster_hex::hex_string(values.max.to_be_bytes().as_ref()),
+        median: faster_hex::hex_string(median.to_be_bytes().as_ref()),
+        sum: faster_hex::hex_string(values.sum.to_be_bytes().as_ref()),
+    }
+}
+
+/// Capacities are u64, hex encoded with 8 bytes.
+fn u64_statistics(name: String, mut values: ValueStats) -> DailySummaryInner {
+    let hex = |value: u128| faster_hex::hex_string((value as u64).to_be_bytes().as_ref());
+    DailySummaryInner {
+        name,
+        average: hex(values.average()),
+        min: hex(values.min),
+        max: hex(values.max),
+        median: hex(values.median()),
+        sum: hex(values.sum),
+    }
+}
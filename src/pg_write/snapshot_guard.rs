@@ -0,0 +1,327 @@
+//! Plausibility check of a collected graph half against the last cycle that committed it.
+//!
+//! A flaky Fiber rpc may answer with a near empty graph. Pages of a cycle are held back until
+//! the half reaches `1 - SNAPSHOT_MAX_DROP` (20% by default) of the count of the last successful
+//! cycle, then everything held and every later page commits as usual. A graph ending below that
+//! commits nothing: the half fails, the anomaly is recorded in `snapshot_anomalies`, logged and
+//! pushed to the webhooks, so the hourly aggregates keep the last plausible snapshot instead of a
+//! bogus dip. After `SNAPSHOT_ACCEPT_AFTER` (3) implausible cycles in a row the drop is taken as
+//! real and committed, still recorded as an accepted anomaly.
+//!
+//! Baselines under `SNAPSHOT_MIN_BASELINE` (20) are not checked, small test networks come and go.
+
+use std::{str::FromStr, sync::LazyLock};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres, Row};
+
+use crate::{Network, config, webhook};
+
+fn var_or<T: FromStr>(var: &str, default: T) -> T {
+    config::parse_var(var).unwrap_or(default)
+}
+
+/// Largest share a graph half may lose from one cycle to the next, 1 turns the check off.
+pub static SNAPSHOT_MAX_DROP: LazyLock<f64> =
+    LazyLock::new(|| var_or("SNAPSHOT_MAX_DROP", 0.8f64).clamp(0.0, 1.0));
+
+static SNAPSHOT_MIN_BASELINE: LazyLock<usize> =
+    LazyLock::new(|| var_or("SNAPSHOT_MIN_BASELINE", 20));
+
+static SNAPSHOT_ACCEPT_AFTER: LazyLock<usize> =
+    LazyLock::new(|| var_or("SNAPSHOT_ACCEPT_AFTER", 3usize).max(1));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Half {
+    Nodes,
+    Channels,
+}
+
+impl Half {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Half::Nodes => "nodes",
+            Half::Channels => "channels",
+        }
+    }
+
+    /// Columns of the half in `collector_runs`, its count and whether it committed.
+    fn run_columns(self) -> (&'static str, &'static str) {
+        match self {
+            Half::Nodes => ("nodes", "nodes_ok"),
+            Half::Channels => ("channels", "channels_ok"),
+        }
+    }
+}
+
+/// Smallest plausible count after a cycle of `previous`, 0 when there is nothing to compare.
+fn minimum(previous: Option<usize>, max_drop: f64, min_baseline: usize) -> usize {
+    match previous {
+        Some(previous) if previous >= min_baseline.max(1) && max_drop < 1.0 => {
+            (previous as f64 * (1.0 - max_drop)).ceil() as usize
+        }
+        _ => 0,
+    }
+}
+
+/// Outcome of a graph half once it is exhausted.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Plausible,
+    /// Below the minimum but committed, the previous cycles were implausible as well.
+    Accepted {
+        observed: usize,
+    },
+    /// Below the minimum, nothing committed.
+    Rejected {
+        observed: usize,
+    },
+}
+
+/// Holds the pages of a graph half back until they reach a plausible count.
+pub struct PlausibilityGuard<T> {
+    /// Where anomalies are recorded, `None` for an unchecked half.
+    pool: Option<&'static Pool<Postgres>>,
+    half: Half,
+    previous: Option<usize>,
+    minimum: usize,
+    /// Whether a half below the minimum commits anyway.
+    accept: bool,
+    seen: usize,
+    held: Vec<T>,
+}
+
+impl<T> PlausibilityGuard<T> {
+    fn new(
+        pool: Option<&'static Pool<Postgres>>,
+        half: Half,
+        previous: Option<usize>,
+        minimum: usize,
+        accept: bool,
+    ) -> Self {
+        PlausibilityGuard {
+            pool,
+            half,
+            previous,
+            minimum,
+            accept,
+            seen: 0,
+            held: Vec::new(),
+        }
+    }
+
+    /// Guard of `half` of `net` against its last successful cycle. The check is skipped when
+    /// `collector_runs` cannot be read, the commits would most likely fail as well.
+    pub async fn load(pool: &'static Pool<Postgres>, net: Network, half: Half) -> Self {
+        match baseline(pool, net, half).await {
+            Ok((previous, rejected)) => Self::new(
+                Some(pool),
+                half,
+                previous,
+                minimum(previous, *SNAPSHOT_MAX_DROP, *SNAPSHOT_MIN_BASELINE),
+                rejected + 1 >= *SNAPSHOT_ACCEPT_AFTER,
+            ),
+            Err(e) => {
+                log::warn!(
+                    "{:?}, failed to load the last {} count, not checking it: {}",
+                    net,
+                    half.as_str(),
+                    e
+                );
+                Self::unchecked(half)
+            }
+        }
+    }
+
+    /// A guard admitting every page, for collectors that record no cycles.
+    pub fn unchecked(half: Half) -> Self {
+        Self::new(None, half, None, 0, true)
+    }
+
+    /// Take a deduplicated page, returns what can be committed now.
+    pub fn admit(&mut self, page: Vec<T>) -> Vec<T> {
+        self.seen += page.len();
+        if self.accept || self.seen >= self.minimum {
+            let mut items = std::mem::take(&mut self.held);
+            items.extend(page);
+            items
+        } else {
+            self.held.extend(page);
+            Vec::new()
+        }
+    }
+
+    /// Verdict once the graph is exhausted, held pages of a rejected half are dropped.
+    pub fn verdict(&self) -> Verdict {
+        if self.seen >= self.minimum {
+            Verdict::Plausible
+        } else if self.accept {
+            Verdict::Accepted {
+                observed: self.seen,
+            }
+        } else {
+            Verdict::Rejected {
+                observed: self.seen,
+            }
+        }
+    }
+
+    /// Record and alert an implausible half collected at `time`, `Err` when it was rejected.
+    pub async fn settle(self, net: Network, time: &DateTime<Utc>) -> Result<(), String> {
+        let (observed, accepted) = match self.verdict() {
+            Verdict::Plausible => return Ok(()),
+            Verdict::Accepted { observed } => (observed, true),
+            Verdict::Rejected { observed } => (observed, false),
+        };
+        let anomaly = SnapshotAnomaly {
+            net: net.name().to_string(),
+            half: self.half.as_str().to_string(),
+            detected_at: *time,
+            previous: self.previous.unwrap_or(0) as i32,
+            observed: observed as i32,
+            accepted,
+        };
+        log::error!(
+            "{:?}, implausible {} graph: {} after {} in the last cycle, {}",
+            net,
+            anomaly.half,
+            observed,
+            anomaly.previous,
+            if accepted {
+                "accepted after repeated drops"
+            } else {
+                "not committed"
+            }
+        );
+        if let Some(pool) = self.pool
+            && let Err(e) = anomaly.record(pool).await
+        {
+            log::error!("{:?}, failed to record the snapshot anomaly: {}", net, e);
+        }
+        webhook::push_alert("snapshot_anomaly", &anomaly).await;
+        if accepted {
+            Ok(())
+        } else {
+            Err(format!(
+                "implausible graph, {} {} after {} in the last cycle, not committed",
+                observed, anomaly.half, anomaly.previous
+            ))
+        }
+    }
+}
+
+/// Count of the last cycle that committed `half` and the implausible cycles rejected since.
+async fn baseline(
+    pool: &Pool<Postgres>,
+    net: Network,
+    half: Half,
+) -> Result<(Option<usize>, usize), sqlx::Error> {
+    let (count, ok) = half.run_columns();
+    let sql = format!(
+        "WITH last AS (
+            SELECT started_at, {count} AS count FROM collector_runs
+            WHERE net = $1 AND {ok} ORDER BY started_at DESC LIMIT 1
+        )
+        SELECT (SELECT count FROM last) AS previous,
+            (SELECT COUNT(*) FROM snapshot_anomalies
+            WHERE net = $1 AND half = $2 AND NOT accepted
+                AND detected_at > coalesce((SELECT started_at FROM last), '-infinity')) AS rejected"
+    );
+    let row = sqlx::query(&sql)
+        .bind(net.name())
+        .bind(half.as_str())
+        .fetch_one(pool)
+        .await?;
+    Ok((
+        row.get::<Option<i32>, _>("previous")
+            .map(|count| count.max(0) as usize),
+        row.get::<i64, _>("rejected") as usize,
+    ))
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct SnapshotAnomaly {
+    pub net: String,
+    /// `nodes` or `channels`.
+    pub half: String,
+    pub detected_at: DateTime<Utc>,
+    pub previous: i32,
+    pub observed: i32,
+    pub accepted: bool,
+}
+
+impl SnapshotAnomaly {
+    async fn record(&self, pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO snapshot_anomalies (net, half, detected_at, previous, observed, accepted)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(&self.net)
+        .bind(&self.half)
+        .bind(self.detected_at)
+        .bind(self.previous)
+        .bind(self.observed)
+        .bind(self.accepted)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Anomalies of `net`, newest first.
+pub async fn list(
+    pool: &Pool<Postgres>,
+    net: Network,
+    page: usize,
+    page_size: usize,
+) -> Result<(Vec<SnapshotAnomaly>, usize, usize), sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT *, COUNT(*) OVER() AS total_count
+        FROM snapshot_anomalies
+        WHERE net = $1
+        ORDER BY detected_at DESC, id DESC
+        LIMIT $2 OFFSET $3",
+    )
+    .bind(net.name())
+    .bind(page_size as i64)
+    .bind(page.saturating_mul(page_size) as i64)
+    .fetch_all(pool)
+    .await?;
+    let total_count = rows
+        .first()
+        .map(|row| row.get::<i64, _>("total_count") as usize)
+        .unwrap_or(0);
+    let anomalies = rows
+        .iter()
+        .map(SnapshotAnomaly::from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((anomalies, page.saturating_add(1), total_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Half, PlausibilityGuard, Verdict, minimum};
+
+    #[test]
+    fn pages_are_held_until_the_half_is_plausible() {
+        assert_eq!(minimum(Some(1000), 0.8, 20), 200);
+        assert_eq!(minimum(Some(10), 0.8, 20), 0);
+        assert_eq!(minimum(Some(1000), 1.0, 20), 0);
+        assert_eq!(minimum(None, 0.8, 20), 0);
+
+        let mut guard = PlausibilityGuard::new(None, Half::Nodes, Some(1000), 200, false);
+        assert!(guard.admit(vec![0; 150]).is_empty());
+        assert_eq!(guard.admit(vec![1; 100]).len(), 250);
+        assert_eq!(guard.admit(vec![2; 10]), vec![2; 10]);
+        assert_eq!(guard.verdict(), Verdict::Plausible);
+
+        let mut guard = PlausibilityGuard::new(None, Half::Nodes, Some(1000), 200, false);
+        assert!(guard.admit(vec![0; 5]).is_empty());
+        assert_eq!(guard.verdict(), Verdict::Rejected { observed: 5 });
+
+        let mut guard = PlausibilityGuard::new(None, Half::Channels, Some(1000), 200, true);
+        assert_eq!(guard.admit(vec![0; 5]).len(), 5);
+        assert_eq!(guard.verdict(), Verdict::Accepted { observed: 5 });
+    }
+}
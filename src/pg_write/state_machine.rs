@@ -0,0 +1,289 @@
+//! Channel state transitions, kept free of rpc and database access so every sequence of
+//! on-chain transactions can be tested on its own.
+//!
+//! A channel starts `open` with its funding cell. The transaction spending the funding cell
+//! either settles it (`closed_cooperative`) or creates a commitment cell
+//! (`closed_waiting_onchain_settlement`). Every transaction spending a commitment cell creates
+//! the next one or settles the channel (`closed_uncooperative`).
+
+use ckb_jsonrpc_types::{BlockNumber, JsonBytes};
+use ckb_types::H256;
+
+use super::{ChannelStateUpdate, DBState};
+
+/// `(tx_hash, block_number, timestamp, witness_args, commitment_args)` of an applied transaction.
+pub(super) type AppliedTx = (H256, BlockNumber, u64, Option<JsonBytes>, Option<JsonBytes>);
+
+/// A transaction spending the channel's current funding or commitment cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObservedTx {
+    pub tx_hash: H256,
+    pub block_number: BlockNumber,
+    /// Block timestamp in milliseconds.
+    pub timestamp: u64,
+    /// Witness of the spent commitment cell, `None` when the funding cell is spent.
+    pub witness_args: Option<JsonBytes>,
+    /// Args of the commitment cell among the outputs, `None` when the channel settles.
+    pub commitment_args: Option<JsonBytes>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelStateMachine {
+    pub(super) state: DBState,
+    pub(super) last_block_number: BlockNumber,
+    /// Timestamp of the latest transaction in milliseconds.
+    pub(super) last_commit: u64,
+    pub(super) last_commitment_args: Option<JsonBytes>,
+    /// Transactions applied since the machine was created, oldest first.
+    pub(super) txs: Vec<AppliedTx>,
+    last_tx_hash: H256,
+}
+
+impl ChannelStateMachine {
+    /// A channel seen for the first time, its funding transaction is recorded as the first tx.
+    pub fn opened(funding_tx_hash: H256, block_number: BlockNumber, timestamp: u64) -> Self {
+        ChannelStateMachine {
+            state: DBState::Open,
+            last_block_number: block_number,
+            last_commit: timestamp,
+            last_commitment_args: None,
+            txs: vec![(funding_tx_hash.clone(), block_number, timestamp, None, None)],
+            last_tx_hash: funding_tx_hash,
+        }
+    }
+
+    /// Continue from a persisted state, only transactions observed from now on are recorded.
+    pub fn resume(
+        state: DBState,
+        last_tx_hash: H256,
+        last_block_number: BlockNumber,
+        last_commitment_args: Option<JsonBytes>,
+    ) -> Self {
+        ChannelStateMachine {
+            state,
+            last_block_number,
+            last_commit: 0,
+            last_commitment_args,
+            txs: Vec::new(),
+            last_tx_hash,
+        }
+    }
+
+    pub fn last_block_number(&self) -> BlockNumber {
+        self.last_block_number
+    }
+
+    pub fn last_commitment_args(&self) -> Option<&JsonBytes> {
+        self.last_commitment_args.as_ref()
+    }
+
+    /// Whether `tx_hash` was already applied, or is the last transaction of a resumed state.
+    pub fn knows(&self, tx_hash: &H256) -> bool {
+        &self.last_tx_hash == tx_hash || self.txs.iter().any(|tx| &tx.0 == tx_hash)
+    }
+
+    /// Whether the next transaction spends a commitment cell, which is when its witness matters.
+    pub fn awaits_settlement(&self) -> bool {
+        self.state == DBState::ClosedWaitingOnchainSettlement
+    }
+
+    /// Apply `tx`, returns `false` when it is ignored because it is already known or the
+    /// channel is closed.
+    pub fn observe(&mut self, tx: ObservedTx) -> bool {
+        if self.knows(&tx.tx_hash) {
+            return false;
+        }
+        self.state = match (self.state, &tx.commitment_args) {
+            (DBState::Open | DBState::ClosedWaitingOnchainSettlement, Some(_)) => {
+                DBState::ClosedWaitingOnchainSettlement
+            }
+            (DBState::Open, None) => DBState::ClosedCooperative,
+            (DBState::ClosedWaitingOnchainSettlement, None) => DBState::ClosedUncooperative,
+            (DBState::ClosedCooperative | DBState::ClosedUncooperative, _) => return false,
+        };
+        if tx.commitment_args.is_some() {
+            self.last_commitment_args = tx.commitment_args.clone();
+        }
+        self.last_block_number = tx.block_number;
+        self.last_commit = tx.timestamp;
+        self.last_tx_hash = tx.tx_hash.clone();
+        self.txs.push((
+            tx.tx_hash,
+            tx.block_number,
+            tx.timestamp,
+            tx.witness_args,
+            tx.commitment_args,
+        ));
+        true
+    }
+
+    /// Update to persist for a resumed channel, `None` when nothing was observed.
+    pub fn into_update(self, outpoint: JsonBytes) -> Option<ChannelStateUpdate> {
+        if self.txs.is_empty() {
+            return None;
+        }
+        Some(ChannelStateUpdate {
+            outpoint,
+            state: self.state,
+            last_commit: self.last_commit,
+            last_block_number: self.last_block_number,
+            last_commitment_args: self.last_commitment_args,
+            txs: self.txs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ckb_jsonrpc_types::JsonBytes;
+    use ckb_types::H256;
+
+    use super::{ChannelStateMachine, DBState, ObservedTx};
+
+    fn hash(n: u8) -> H256 {
+        H256::from([n; 32])
+    }
+
+    fn args(n: u8) -> JsonBytes {
+        JsonBytes::from_vec(vec![n; 4])
+    }
+
+    /// `(tx, block, next commitment args)`, witnesses are set when a commitment cell is spent.
+    fn run(mut machine: ChannelStateMachine, txs: &[(u8, u64, Option<u8>)]) -> ChannelStateMachine {
+        for (tx, block, commitment) in txs {
+            let witness_args = machine.awaits_settlement().then(|| args(0xff));
+            machine.observe(ObservedTx {
+                tx_hash: hash(*tx),
+                block_number: (*block).into(),
+                timestamp: block * 1000,
+                witness_args,
+                commitment_args: commitment.map(args),
+            });
+        }
+        machine
+    }
+
+    struct Case {
+        name: &'static str,
+        txs: &'static [(u8, u64, Option<u8>)],
+        state: DBState,
+        commitment: Option<u8>,
+        last_block: u64,
+        recorded: usize,
+    }
+
+    #[test]
+    fn transitions() {
+        let cases = [
+            Case {
+                name: "no spend",
+                txs: &[],
+                state: DBState::Open,
+                commitment: None,
+                last_block: 10,
+                recorded: 1,
+            },
+            Case {
+                name: "cooperative close",
+                txs: &[(2, 20, None)],
+                state: DBState::ClosedCooperative,
+                commitment: None,
+                last_block: 20,
+                recorded: 2,
+            },
+            Case {
+                name: "force close waits for settlement",
+                txs: &[(2, 20, Some(1))],
+                state: DBState::ClosedWaitingOnchainSettlement,
+                commitment: Some(1),
+                last_block: 20,
+                recorded: 2,
+            },
+            Case {
+                name: "force close then settlement",
+                txs: &[(2, 20, Some(1)), (3, 30, None)],
+                state: DBState::ClosedUncooperative,
+                commitment: Some(1),
+                last_block: 30,
+                recorded: 3,
+            },
+            Case {
+                name: "penalty chain of commitments",
+                txs: &[
+                    (2, 20, Some(1)),
+                    (3, 30, Some(2)),
+                    (4, 40, Some(3)),
+                    (5, 50, None),
+                ],
+                state: DBState::ClosedUncooperative,
+                commitment: Some(3),
+                last_block: 50,
+                recorded: 5,
+            },
+            Case {
+                name: "duplicate transactions are ignored",
+                txs: &[(2, 20, Some(1)), (2, 20, Some(1)), (3, 30, None)],
+                state: DBState::ClosedUncooperative,
+                commitment: Some(1),
+                last_block: 30,
+                recorded: 3,
+            },
+            Case {
+                name: "nothing applies after closing",
+                txs: &[(2, 20, None), (3, 30, Some(1))],
+                state: DBState::ClosedCooperative,
+                commitment: None,
+                last_block: 20,
+                recorded: 2,
+            },
+        ];
+        for case in cases {
+            let machine = run(
+                ChannelStateMachine::opened(hash(1), 10.into(), 10_000),
+                case.txs,
+            );
+            assert_eq!(machine.state, case.state, "{}", case.name);
+            assert_eq!(
+                machine.last_commitment_args(),
+                case.commitment.map(args).as_ref(),
+                "{}",
+                case.name
+            );
+            assert_eq!(
+                machine.last_block_number().value(),
+                case.last_block,
+                "{}",
+                case.name
+            );
+            assert_eq!(machine.txs.len(), case.recorded, "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn witnesses_are_kept_for_commitment_spends_only() {
+        let machine = run(
+            ChannelStateMachine::opened(hash(1), 10.into(), 10_000),
+            &[(2, 20, Some(1)), (3, 30, None)],
+        );
+        assert_eq!(machine.txs[1].3, None);
+        assert_eq!(machine.txs[2].3, Some(args(0xff)));
+    }
+
+    #[test]
+    fn resumed_channel_only_reports_new_transactions() {
+        let resumed = ChannelStateMachine::resume(
+            DBState::ClosedWaitingOnchainSettlement,
+            hash(2),
+            20.into(),
+            Some(args(1)),
+        );
+        assert!(resumed.clone().into_update(args(9)).is_none());
+
+        let machine = run(resumed, &[(2, 20, Some(1)), (3, 30, None)]);
+        let update = machine.into_update(args(9)).unwrap();
+        assert_eq!(update.state, DBState::ClosedUncooperative);
+        assert_eq!(update.txs.len(), 1);
+        assert_eq!(update.last_block_number.value(), 30);
+        assert_eq!(update.last_commit, 30_000);
+    }
+}
@@ -6,10 +6,7 @@ use faster_hex::hex_string;
 use sqlx::types::chrono::{DateTime, Utc};
 use sqlx::{PgConnection, QueryBuilder};
 
-use crate::{
-    pg_write::{Network, global_cache, global_cache_testnet},
-    types::ChannelInfo,
-};
+use crate::{codec, ip_location::AddressScope, pg_write::Network, types::ChannelInfo};
 
 pub const UDT_INFO_INSERT_SQL: &str =
     "insert into {} (id, name, code_hash, hash_type, args, auto_accept_amount) ";
@@ -29,39 +26,111 @@ pub const CHANNEL_INFO_INSERT_SQL: &str = "insert into {} (
 #[derive(sqlx::FromRow)]
 pub struct UdtInfoCache {
     pub id: i32,
+    pub name: String,
     pub code_hash: String,
     pub hash_type: String,
     pub args: String,
+    pub auto_accept_amount: Option<String>,
+}
+
+/// Values of a UDT nodes may announce differently over time, the latest announcement wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UdtValues {
+    pub name: String,
+    pub auto_accept_amount: Option<String>,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct RelationCache {
     pub udt: HashMap<Script, i32>,
+    pub udt_values: HashMap<i32, UdtValues>,
+    /// Scripts of UDTs merged into another one, their announcements do not update it.
+    pub udt_aliases: HashSet<Script>,
     pub udt_node: HashMap<Bytes, HashSet<i32>>,
 }
 
+impl RelationCache {
+    /// This cache with the UDTs of the working copy `written` and the UDT rows and node
+    /// relations of a committed page added, entries another writer published meanwhile are
+    /// kept.
+    pub fn apply(
+        &self,
+        written: &RelationCache,
+        udt_infos: &[UdtInfos],
+        udt_node_relations: &[UdtNodeRelation],
+    ) -> Self {
+        let mut merged = self.clone();
+        for (script, id) in &written.udt {
+            merged.udt.entry(script.clone()).or_insert(*id);
+        }
+        for udt in udt_infos {
+            merged.udt_values.insert(udt.id, udt.values());
+        }
+        for relation in udt_node_relations {
+            merged
+                .udt_node
+                .entry(Bytes::from(relation.node_id.clone()))
+                .or_default()
+                .insert(relation.udt_info_id);
+        }
+        merged
+    }
+}
+
 pub struct UdtInfos {
     pub id: i32,
     pub name: String,
     pub code_hash: String,
     pub hash_type: String,
     pub args: String,
-    pub auto_accept_amount: String,
+    pub auto_accept_amount: Option<String>,
+    /// Already stored, only its name or auto accept amount changed.
+    pub changed: bool,
 }
 
 impl UdtInfos {
+    pub fn values(&self) -> UdtValues {
+        UdtValues {
+            name: self.name.clone(),
+            auto_accept_amount: self.auto_accept_amount.clone(),
+        }
+    }
+
+    /// Insert new UDTs and update changed ones, recording their previous values in
+    /// `udt_info_history`.
     pub async fn insert_batch(
         conn: &mut PgConnection,
         udts: &[UdtInfos],
+        time: &DateTime<Utc>,
         net: Network,
     ) -> Result<(), sqlx::Error> {
-        if udts.is_empty() {
+        let (changed, new): (Vec<_>, Vec<_>) = udts.iter().partition(|udt| udt.changed);
+        if !new.is_empty() {
+            let sql = UDT_INFO_INSERT_SQL.replace("{}", net.udt_infos());
+            let mut query_builder: QueryBuilder<'_, sqlx::Postgres> = QueryBuilder::new(sql);
+
+            query_builder.push_values(new.iter().take(65535 / 6), |mut b, udt| {
+                b.push_bind(udt.id)
+                    .push_bind(&udt.name)
+                    .push_bind(&udt.code_hash)
+                    .push_bind(&udt.hash_type)
+                    .push_bind(&udt.args)
+                    .push_bind(&udt.auto_accept_amount);
+            });
+
+            query_builder.build().execute(&mut *conn).await?;
+        }
+        if changed.is_empty() {
             return Ok(());
         }
-        let sql = UDT_INFO_INSERT_SQL.replace("{}", net.udt_infos());
-        let mut query_builder: QueryBuilder<'_, sqlx::Postgres> = QueryBuilder::new(sql);
 
-        query_builder.push_values(udts.iter().take(65535 / 6), |mut b, udt| {
+        // the statement sees the rows as they were before the update in `previous`, a row
+        // whose script differs belongs to another UDT and is left alone, a name set through
+        // the admin api is kept
+        let mut query_builder: QueryBuilder<'_, sqlx::Postgres> = QueryBuilder::new(
+            "WITH incoming (id, name, code_hash, hash_type, args, auto_accept_amount) AS (",
+        );
+        query_builder.push_values(changed.iter().take(65535 / 6), |mut b, udt| {
             b.push_bind(udt.id)
                 .push_bind(&udt.name)
                 .push_bind(&udt.code_hash)
@@ -69,6 +138,36 @@ impl UdtInfos {
                 .push_bind(&udt.args)
                 .push_bind(&udt.auto_accept_amount);
         });
+        query_builder.push(format!(
+            "), previous AS (
+                SELECT u.id, u.name, u.auto_accept_amount FROM {udt_infos} u
+                JOIN incoming i ON i.id = u.id
+            ), updated AS (
+                UPDATE {udt_infos} u
+                SET name = CASE WHEN u.name_locked THEN u.name ELSE i.name END,
+                    auto_accept_amount = i.auto_accept_amount, updated_at = ",
+            udt_infos = net.udt_infos()
+        ));
+        query_builder.push_bind(time).push(
+            " FROM incoming i
+                WHERE u.id = i.id AND u.code_hash = i.code_hash AND u.hash_type = i.hash_type
+                    AND u.args = i.args
+                    AND (u.name, u.auto_accept_amount) IS DISTINCT FROM
+                        (CASE WHEN u.name_locked THEN u.name ELSE i.name END, i.auto_accept_amount)
+                RETURNING u.id, u.name, u.auto_accept_amount
+            )
+            INSERT INTO udt_info_history (net, udt_info_id, changed_at, old_name, new_name,
+                old_auto_accept_amount, new_auto_accept_amount)
+            SELECT ",
+        );
+        query_builder
+            .push_bind(net.name())
+            .push(", p.id, ")
+            .push_bind(time)
+            .push(
+                ", p.name, up.name, p.auto_accept_amount, up.auto_accept_amount
+            FROM updated up JOIN previous p ON p.id = up.id",
+            );
 
         query_builder.build().execute(conn).await?;
         Ok(())
@@ -115,26 +214,58 @@ impl UdtdepRelation {
 pub struct UdtNodeRelation {
     pub node_id: String,
     pub udt_info_id: i32,
+    /// Not stored yet, otherwise only confirmed by the announcement.
+    pub new: bool,
 }
 
 impl UdtNodeRelation {
+    /// Insert new relations and move `last_confirmed` of the announced ones to `time`.
     pub async fn use_sqlx(
         conn: &mut PgConnection,
         relations: &[UdtNodeRelation],
+        time: &DateTime<Utc>,
         net: Network,
     ) -> Result<(), sqlx::Error> {
-        if relations.is_empty() {
+        let (new, confirmed): (Vec<_>, Vec<_>) =
+            relations.iter().partition(|relation| relation.new);
+        if !new.is_empty() {
+            let sql = UDT_NODE_RELATION_INSERT_SQL.replace("{}", net.node_udt_relations());
+            let mut query_builder: QueryBuilder<'_, sqlx::Postgres> = QueryBuilder::new(sql);
+
+            query_builder.push_values(new.iter().take(65535 / 2), |mut b, relation| {
+                b.push_bind(&relation.node_id)
+                    .push_bind(relation.udt_info_id);
+            });
+
+            query_builder.build().execute(&mut *conn).await?;
+        }
+        if confirmed.is_empty() {
             return Ok(());
         }
-        let sql = UDT_NODE_RELATION_INSERT_SQL.replace("{}", net.node_udt_relations());
-        let mut query_builder: QueryBuilder<'_, sqlx::Postgres> = QueryBuilder::new(sql);
 
-        query_builder.push_values(relations.iter().take(65535 / 2), |mut b, relation| {
-            b.push_bind(&relation.node_id)
-                .push_bind(relation.udt_info_id);
-        });
-
-        query_builder.build().execute(conn).await?;
+        // an archive replayed later does not move the confirmation back
+        let sql = format!(
+            "UPDATE {} r SET last_confirmed = GREATEST(r.last_confirmed, $1)
+            FROM unnest($2::text[], $3::integer[]) AS c(node_id, udt_info_id)
+            WHERE r.node_id = c.node_id AND r.udt_info_id = c.udt_info_id",
+            net.node_udt_relations()
+        );
+        sqlx::query(&sql)
+            .bind(time)
+            .bind(
+                confirmed
+                    .iter()
+                    .map(|relation| relation.node_id.clone())
+                    .collect::<Vec<_>>(),
+            )
+            .bind(
+                confirmed
+                    .iter()
+                    .map(|relation| relation.udt_info_id)
+                    .collect::<Vec<_>>(),
+            )
+            .execute(conn)
+            .await?;
         Ok(())
     }
 }
@@ -153,6 +284,8 @@ pub struct NodeInfoDBSchema {
     pub city: String,
     pub region: String,
     pub loc: String,
+    /// Kept in `node_address_scopes`, not in `node_infos`.
+    pub address_scope: AddressScope,
 }
 
 impl NodeInfoDBSchema {
@@ -185,6 +318,32 @@ impl NodeInfoDBSchema {
         query_builder.build().execute(conn).await?;
         Ok(())
     }
+
+    /// Latest address scope per node, nodes are unique within a page.
+    pub async fn upsert_address_scopes(
+        conn: &mut PgConnection,
+        nodes: &[NodeInfoDBSchema],
+        time: &DateTime<Utc>,
+        net: Network,
+    ) -> Result<(), sqlx::Error> {
+        if nodes.is_empty() {
+            return Ok(());
+        }
+        let mut query_builder: QueryBuilder<'_, sqlx::Postgres> =
+            QueryBuilder::new("INSERT INTO node_address_scopes (net, node_id, scope, updated_at) ");
+        query_builder.push_values(nodes.iter().take(65535 / 4), |mut b, node| {
+            b.push_bind(net.name())
+                .push_bind(&node.node_id)
+                .push_bind(node.address_scope.as_str())
+                .push_bind(time);
+        });
+        query_builder.push(
+            " ON CONFLICT (net, node_id) DO UPDATE
+            SET scope = EXCLUDED.scope, updated_at = EXCLUDED.updated_at",
+        );
+        query_builder.build().execute(conn).await?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -271,21 +430,18 @@ impl ChannelInfoDBSchema {
     }
 }
 
-impl From<(ChannelInfo, Network)> for ChannelInfoDBSchema {
-    fn from((channel_info, net): (ChannelInfo, Network)) -> Self {
+impl From<(ChannelInfo, &RelationCache)> for ChannelInfoDBSchema {
+    fn from((channel_info, cache): (ChannelInfo, &RelationCache)) -> Self {
         Self {
             channel_outpoint: hex_string(channel_info.channel_outpoint.as_bytes()),
             node1: String::from_utf8(channel_info.node1.to_vec()).unwrap(),
             node2: String::from_utf8(channel_info.node2.to_vec()).unwrap(),
-            capacity: hex_string(channel_info.capacity.to_be_bytes().as_ref()),
+            capacity: codec::encode_u128(channel_info.capacity),
             chain_hash: hex_string(channel_info.chain_hash.as_bytes()),
             udt_type_script: channel_info
                 .udt_type_script
                 .as_ref()
-                .and_then(|script| match net {
-                    Network::Mainnet => global_cache().load().udt.get(script).cloned(),
-                    Network::Testnet => global_cache_testnet().load().udt.get(script).cloned(),
-                }),
+                .and_then(|script| cache.udt.get(script).cloned()),
             created_timestamp: DateTime::from_timestamp_millis(
                 channel_info.created_timestamp as i64,
             )
@@ -0,0 +1,217 @@
+//! Corrections of UDT entries through the admin api: renaming a UDT, merging a duplicate into
+//! another one and hiding spam from the public listings.
+//!
+//! A renamed UDT keeps its name whatever nodes announce later. A merged UDT's row is deleted,
+//! its relations, channels, daily stats and history point to the UDT it was merged into and
+//! its script stays an alias of it in `udt_info_aliases`, so later announcements resolve to the
+//! merged entry. Collectors pick the changes up through the `reload_udt_cache` job.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, Pool, Postgres};
+
+use crate::Network;
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct UdtEntry {
+    pub id: i32,
+    pub name: String,
+    pub code_hash: String,
+    pub hash_type: String,
+    pub args: String,
+    pub auto_accept_amount: Option<String>,
+    pub hidden: bool,
+    /// Renamed through the admin api, announcements no longer change the name.
+    pub name_locked: bool,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Ids of the UDTs merged into this one.
+    pub merged: Vec<i32>,
+}
+
+/// Every UDT of `net`, hidden ones included.
+pub async fn list(pool: &Pool<Postgres>, net: Network) -> Result<Vec<UdtEntry>, sqlx::Error> {
+    let sql = format!(
+        "SELECT u.id, u.name, u.code_hash, u.hash_type, u.args, u.auto_accept_amount, u.hidden,
+            u.name_locked, u.updated_at,
+            COALESCE(array_agg(a.merged_id) FILTER (WHERE a.merged_id IS NOT NULL), '{{}}')
+                AS merged
+        FROM {} u
+        LEFT JOIN udt_info_aliases a ON a.net = $1 AND a.udt_info_id = u.id
+        GROUP BY u.id
+        ORDER BY u.id",
+        net.udt_infos()
+    );
+    sqlx::query_as(&sql).bind(net.name()).fetch_all(pool).await
+}
+
+/// Set the name of UDT `id` and keep it from announcements, `false` for an unknown id.
+pub async fn rename(
+    pool: &Pool<Postgres>,
+    net: Network,
+    id: i32,
+    name: &str,
+    time: &DateTime<Utc>,
+) -> Result<bool, sqlx::Error> {
+    let sql = format!(
+        "WITH previous AS (
+            SELECT id, name, auto_accept_amount FROM {udt_infos} WHERE id = $2
+        ), updated AS (
+            UPDATE {udt_infos} SET name = $3, name_locked = true, updated_at = $4
+            WHERE id = $2
+            RETURNING id, name, auto_accept_amount
+        )
+        INSERT INTO udt_info_history (net, udt_info_id, changed_at, old_name, new_name,
+            old_auto_accept_amount, new_auto_accept_amount)
+        SELECT $1, p.id, $4, p.name, up.name, p.auto_accept_amount, up.auto_accept_amount
+        FROM updated up JOIN previous p ON p.id = up.id",
+        udt_infos = net.udt_infos()
+    );
+    Ok(sqlx::query(&sql)
+        .bind(net.name())
+        .bind(id)
+        .bind(name)
+        .bind(time)
+        .execute(pool)
+        .await?
+        .rows_affected()
+        > 0)
+}
+
+/// Hide UDT `id` from the public listings or show it again, `false` for an unknown id.
+pub async fn set_hidden(
+    pool: &Pool<Postgres>,
+    net: Network,
+    id: i32,
+    hidden: bool,
+) -> Result<bool, sqlx::Error> {
+    let sql = format!("UPDATE {} SET hidden = $2 WHERE id = $1", net.udt_infos());
+    Ok(sqlx::query(&sql)
+        .bind(id)
+        .bind(hidden)
+        .execute(pool)
+        .await?
+        .rows_affected()
+        > 0)
+}
+
+/// Rows moved from the merged UDT to the one it was merged into.
+#[derive(Debug, Default, Serialize)]
+pub struct MergeSummary {
+    pub node_relations: u64,
+    pub dep_relations: u64,
+    pub channel_rows: u64,
+    pub daily_stats: u64,
+}
+
+/// Merge UDT `from` into `into` in one transaction, `None` when either is unknown. Days both
+/// have daily stats for keep the numbers of `into`.
+pub async fn merge(
+    pool: &Pool<Postgres>,
+    net: Network,
+    from: i32,
+    into: i32,
+    time: &DateTime<Utc>,
+) -> Result<Option<MergeSummary>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let found = sqlx::query(&format!(
+        "SELECT id FROM {} WHERE id = ANY($1) FOR UPDATE",
+        net.udt_infos()
+    ))
+    .bind(vec![from, into])
+    .fetch_all(&mut *tx)
+    .await?;
+    if found.len() < 2 {
+        return Ok(None);
+    }
+
+    sqlx::query(&format!(
+        "INSERT INTO udt_info_aliases (net, code_hash, hash_type, args, udt_info_id, merged_id,
+            merged_at)
+        SELECT $1, code_hash, hash_type, args, $3, id, $4 FROM {} WHERE id = $2
+        ON CONFLICT (net, code_hash, hash_type, args) DO UPDATE
+        SET udt_info_id = EXCLUDED.udt_info_id, merged_at = EXCLUDED.merged_at",
+        net.udt_infos()
+    ))
+    .bind(net.name())
+    .bind(from)
+    .bind(into)
+    .bind(time)
+    .execute(&mut *tx)
+    .await?;
+    // UDTs merged into `from` earlier follow it
+    sqlx::query("UPDATE udt_info_aliases SET udt_info_id = $3 WHERE net = $1 AND udt_info_id = $2")
+        .bind(net.name())
+        .bind(from)
+        .bind(into)
+        .execute(&mut *tx)
+        .await?;
+
+    let mut summary = MergeSummary::default();
+    sqlx::query(&format!(
+        "DELETE FROM {relations} r WHERE r.udt_info_id = $1 AND EXISTS (
+            SELECT 1 FROM {relations} k WHERE k.node_id = r.node_id AND k.udt_info_id = $2
+        )",
+        relations = net.node_udt_relations()
+    ))
+    .bind(from)
+    .bind(into)
+    .execute(&mut *tx)
+    .await?;
+    summary.node_relations =
+        repoint(&mut tx, net.node_udt_relations(), "udt_info_id", from, into).await?;
+    summary.dep_relations = repoint(&mut tx, net.udt_dep(), "udt_info_id", from, into).await?;
+    summary.channel_rows =
+        repoint(&mut tx, net.channel_infos(), "udt_type_script", from, into).await?;
+
+    sqlx::query(
+        "DELETE FROM daily_udt_stats s WHERE s.net = $1 AND s.udt_info_id = $2 AND EXISTS (
+            SELECT 1 FROM daily_udt_stats k
+            WHERE k.net = s.net AND k.day = s.day AND k.udt_info_id = $3
+        )",
+    )
+    .bind(net.name())
+    .bind(from)
+    .bind(into)
+    .execute(&mut *tx)
+    .await?;
+    for table in ["daily_udt_stats", "udt_info_history"] {
+        let moved = sqlx::query(&format!(
+            "UPDATE {} SET udt_info_id = $3 WHERE net = $1 AND udt_info_id = $2",
+            table
+        ))
+        .bind(net.name())
+        .bind(from)
+        .bind(into)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if table == "daily_udt_stats" {
+            summary.daily_stats = moved;
+        }
+    }
+
+    sqlx::query(&format!("DELETE FROM {} WHERE id = $1", net.udt_infos()))
+        .bind(from)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(Some(summary))
+}
+
+/// Point the `column` of `table` rows at UDT `into` instead of `from`.
+async fn repoint(
+    conn: &mut sqlx::PgConnection,
+    table: &str,
+    column: &str,
+    from: i32,
+    into: i32,
+) -> Result<u64, sqlx::Error> {
+    Ok(sqlx::query(&format!(
+        "UPDATE {table} SET {column} = $2 WHERE {column} = $1"
+    ))
+    .bind(from)
+    .bind(into)
+    .execute(conn)
+    .await?
+    .rows_affected())
+}
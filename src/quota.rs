@@ -0,0 +1,440 @@
+//! Daily request and response byte accounting per api key.
+//!
+//! Every replica counts locally and adds its counts to `api_key_usage` every few seconds,
+//! reading back the totals of all replicas. Quotas are checked against those totals plus the
+//! local counts not yet flushed, so replicas can overshoot a quota by one flush interval.
+//! When Redis is configured (see [`crate::shared_state`]) counts go straight to Redis instead
+//! and are exact across replicas, falling back to the Postgres path while Redis fails.
+//!
+//! Requests without a key can be limited per client address (see [`crate::client_ip`]) with
+//! `ANONYMOUS_REQUESTS_PER_MINUTE`, counted in Redis when configured and per replica otherwise.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{LazyLock, Mutex, OnceLock},
+    time::Duration,
+};
+
+use chrono::{NaiveDate, Utc};
+use redis::{RedisResult, aio::ConnectionManager};
+use salvo::{
+    Depot, FlowCtrl, Request, Response, handler,
+    http::{ResBody, StatusCode},
+};
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{
+    auth::{API_KEY, ApiKey, Role},
+    client_ip::client_ip,
+    config, get_pg_pool, shared_state,
+};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+/// Redis usage counters outlive the day they count by a day, for `/me/usage` around midnight.
+const REDIS_USAGE_TTL_SECS: i64 = 2 * 24 * 60 * 60;
+
+/// Requests per UTC day of keys without their own quota, unlimited when unset.
+static API_KEY_DAILY_REQUESTS: LazyLock<Option<i64>> =
+    LazyLock::new(|| parse_limit("API_KEY_DAILY_REQUESTS"));
+
+/// Response bytes per UTC day of keys without their own quota, unlimited when unset.
+static API_KEY_DAILY_BYTES: LazyLock<Option<i64>> =
+    LazyLock::new(|| parse_limit("API_KEY_DAILY_BYTES"));
+
+/// Requests per minute and client address of requests without a key, unlimited when unset.
+static ANONYMOUS_REQUESTS_PER_MINUTE: LazyLock<Option<i64>> =
+    LazyLock::new(|| parse_limit("ANONYMOUS_REQUESTS_PER_MINUTE"));
+
+fn parse_limit(var: &str) -> Option<i64> {
+    config::parse_var(var)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub requests: i64,
+    pub bytes: i64,
+}
+
+impl Usage {
+    fn add(&mut self, other: Usage) {
+        self.requests += other.requests;
+        self.bytes += other.bytes;
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub key_id: String,
+    pub day: NaiveDate,
+    #[serde(flatten)]
+    pub usage: Usage,
+    pub daily_requests: Option<i64>,
+    pub daily_bytes: Option<i64>,
+}
+
+struct Counter {
+    day: NaiveDate,
+    /// Totals of all replicas as of the last flush.
+    flushed: Usage,
+    /// Local counts not flushed yet.
+    pending: Usage,
+}
+
+static COUNTERS: LazyLock<Mutex<HashMap<String, Counter>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn limits(key: &ApiKey) -> (Option<i64>, Option<i64>) {
+    (
+        key.quota.daily_requests.or(*API_KEY_DAILY_REQUESTS),
+        key.quota.daily_bytes.or(*API_KEY_DAILY_BYTES),
+    )
+}
+
+fn exceeded(usage: Usage, (requests, bytes): (Option<i64>, Option<i64>)) -> bool {
+    requests.is_some_and(|limit| usage.requests >= limit)
+        || bytes.is_some_and(|limit| usage.bytes >= limit)
+}
+
+/// Today's usage of the key as seen by this replica, `None` when it has not been loaded yet.
+fn local_usage(key_id: &str, day: NaiveDate) -> Option<Usage> {
+    let counters = COUNTERS.lock().unwrap();
+    let counter = counters.get(key_id).filter(|counter| counter.day == day)?;
+    let mut usage = counter.flushed;
+    usage.add(counter.pending);
+    Some(usage)
+}
+
+async fn stored_usage(
+    pool: &Pool<Postgres>,
+    key_id: &str,
+    day: NaiveDate,
+) -> Result<Usage, sqlx::Error> {
+    let row =
+        sqlx::query("SELECT requests, bytes FROM api_key_usage WHERE key_id = $1 AND day = $2")
+            .bind(key_id)
+            .bind(day)
+            .fetch_optional(pool)
+            .await?;
+    Ok(row
+        .map(|row| Usage {
+            requests: row.get("requests"),
+            bytes: row.get("bytes"),
+        })
+        .unwrap_or_default())
+}
+
+fn redis_usage_key(key_id: &str, day: NaiveDate) -> String {
+    shared_state::key(&["usage", key_id, &day.to_string()])
+}
+
+async fn redis_usage(
+    conn: &mut ConnectionManager,
+    key_id: &str,
+    day: NaiveDate,
+) -> RedisResult<Usage> {
+    let (requests, bytes): (Option<i64>, Option<i64>) = redis::cmd("HMGET")
+        .arg(redis_usage_key(key_id, day))
+        .arg("requests")
+        .arg("bytes")
+        .query_async(conn)
+        .await?;
+    Ok(Usage {
+        requests: requests.unwrap_or_default(),
+        bytes: bytes.unwrap_or_default(),
+    })
+}
+
+async fn redis_record(
+    conn: &mut ConnectionManager,
+    key_id: &str,
+    day: NaiveDate,
+    usage: Usage,
+) -> RedisResult<()> {
+    let key = redis_usage_key(key_id, day);
+    redis::pipe()
+        .atomic()
+        .hincr(&key, "requests", usage.requests)
+        .hincr(&key, "bytes", usage.bytes)
+        .expire(&key, REDIS_USAGE_TTL_SECS)
+        .query_async(conn)
+        .await
+}
+
+/// Today's usage across replicas, loading the stored totals on first use of a key.
+async fn current_usage(pool: &Pool<Postgres>, key_id: &str) -> Result<Usage, sqlx::Error> {
+    let day = Utc::now().date_naive();
+    if let Some(mut conn) = shared_state::connection().await {
+        match redis_usage(&mut conn, key_id, day).await {
+            Ok(usage) => return Ok(usage),
+            Err(e) => log::warn!(
+                "Failed to read usage of api key {} from Redis: {}",
+                key_id,
+                e
+            ),
+        }
+    }
+    if let Some(usage) = local_usage(key_id, day) {
+        return Ok(usage);
+    }
+    let flushed = stored_usage(pool, key_id, day).await?;
+    let mut counters = COUNTERS.lock().unwrap();
+    let counter = counters
+        .entry(key_id.to_string())
+        .and_modify(|counter| {
+            if counter.day != day {
+                // counts of yesterday not flushed yet are dropped
+                counter.day = day;
+                counter.flushed = flushed;
+                counter.pending = Usage::default();
+            }
+        })
+        .or_insert(Counter {
+            day,
+            flushed,
+            pending: Usage::default(),
+        });
+    let mut usage = counter.flushed;
+    usage.add(counter.pending);
+    Ok(usage)
+}
+
+async fn record(key_id: &str, usage: Usage) {
+    let day = Utc::now().date_naive();
+    if let Some(mut conn) = shared_state::connection().await {
+        match redis_record(&mut conn, key_id, day, usage).await {
+            Ok(()) => return,
+            Err(e) => log::warn!(
+                "Failed to count usage of api key {} in Redis: {}",
+                key_id,
+                e
+            ),
+        }
+    }
+    let mut counters = COUNTERS.lock().unwrap();
+    let counter = counters.entry(key_id.to_string()).or_insert(Counter {
+        day,
+        flushed: Usage::default(),
+        pending: Usage::default(),
+    });
+    if counter.day != day {
+        counter.day = day;
+        counter.flushed = Usage::default();
+        counter.pending = Usage::default();
+    }
+    counter.pending.add(usage);
+}
+
+/// Add the pending local counts to `api_key_usage` and refresh the totals of every key.
+pub async fn flush(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    let pending = {
+        let mut counters = COUNTERS.lock().unwrap();
+        counters
+            .iter_mut()
+            .filter(|(_, counter)| counter.pending != Usage::default())
+            .map(|(key_id, counter)| {
+                (
+                    key_id.clone(),
+                    counter.day,
+                    std::mem::take(&mut counter.pending),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut first_error = None;
+    for (key_id, day, usage) in pending {
+        let result = sqlx::query(
+            "INSERT INTO api_key_usage (key_id, day, requests, bytes) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (key_id, day) DO UPDATE SET
+                requests = api_key_usage.requests + EXCLUDED.requests,
+                bytes = api_key_usage.bytes + EXCLUDED.bytes
+            RETURNING requests, bytes",
+        )
+        .bind(&key_id)
+        .bind(day)
+        .bind(usage.requests)
+        .bind(usage.bytes)
+        .fetch_one(pool)
+        .await;
+        let mut counters = COUNTERS.lock().unwrap();
+        let Some(counter) = counters.get_mut(&key_id) else {
+            continue;
+        };
+        match result {
+            Ok(row) if counter.day == day => {
+                counter.flushed = Usage {
+                    requests: row.get("requests"),
+                    bytes: row.get("bytes"),
+                };
+            }
+            Ok(_) => {}
+            Err(e) => {
+                // keep the counts for the next flush
+                if counter.day == day {
+                    counter.pending.add(usage);
+                }
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+fn spawn_flusher() {
+    static FLUSHER: OnceLock<()> = OnceLock::new();
+    FLUSHER.get_or_init(|| {
+        tokio::spawn(async {
+            let mut timer = tokio::time::interval(FLUSH_INTERVAL);
+            timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                timer.tick().await;
+                if let Err(e) = flush(get_pg_pool()).await {
+                    log::error!("Failed to flush api key usage: {}", e);
+                }
+            }
+        });
+    });
+}
+
+fn seconds_until_midnight() -> i64 {
+    let now = Utc::now();
+    let midnight = (now.date_naive() + chrono::Days::new(1))
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+    (midnight - now).num_seconds().max(1)
+}
+
+/// Count requests and response bytes of authenticated keys and reject them with 429 once
+/// a daily quota is used up, must run after [`crate::auth::public_auth`]. Admin keys are
+/// counted but never limited.
+#[handler]
+pub async fn enforce_quota(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let Some(key) = depot.get::<ApiKey>(API_KEY).ok().cloned() else {
+        return;
+    };
+    spawn_flusher();
+    if !key.has_role(Role::Admin) {
+        match current_usage(get_pg_pool(), &key.id).await {
+            Ok(usage) if exceeded(usage, limits(&key)) => {
+                res.status_code(StatusCode::TOO_MANY_REQUESTS);
+                res.add_header("retry-after", seconds_until_midnight(), true)
+                    .ok();
+                ctrl.skip_rest();
+                return;
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to load usage of api key {}: {}", key.id, e),
+        }
+    }
+
+    ctrl.call_next(req, depot, res).await;
+
+    let bytes = match &res.body {
+        ResBody::Once(bytes) => bytes.len() as i64,
+        _ => 0,
+    };
+    record(&key.id, Usage { requests: 1, bytes }).await;
+}
+
+/// Local per minute counts of anonymous clients, `(minute, requests)`.
+static IP_COUNTERS: LazyLock<Mutex<HashMap<IpAddr, (i64, i64)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Requests of `ip` in the current minute, this one included.
+async fn count_ip_request(ip: IpAddr, minute: i64) -> i64 {
+    if let Some(mut conn) = shared_state::connection().await {
+        let key = shared_state::key(&["ip_requests", &ip.to_string(), &minute.to_string()]);
+        let counted: RedisResult<(i64,)> = redis::pipe()
+            .atomic()
+            .incr(&key, 1)
+            .expire(&key, 120)
+            .ignore()
+            .query_async(&mut conn)
+            .await;
+        match counted {
+            Ok((requests,)) => return requests,
+            Err(e) => log::warn!("Failed to count requests of {} in Redis: {}", ip, e),
+        }
+    }
+    let mut counters = IP_COUNTERS.lock().unwrap();
+    if counters.len() > 100_000 {
+        counters.retain(|_, (counted, _)| *counted == minute);
+    }
+    let counter = counters.entry(ip).or_insert((minute, 0));
+    if counter.0 != minute {
+        *counter = (minute, 0);
+    }
+    counter.1 += 1;
+    counter.1
+}
+
+/// Reject requests without a key with 429 once their client address used up
+/// `ANONYMOUS_REQUESTS_PER_MINUTE`, must run after [`crate::auth::public_auth`].
+#[handler]
+pub async fn enforce_ip_limit(depot: &mut Depot, res: &mut Response, ctrl: &mut FlowCtrl) {
+    let Some(limit) = *ANONYMOUS_REQUESTS_PER_MINUTE else {
+        return;
+    };
+    if depot.contains_key(API_KEY) {
+        return;
+    }
+    let Some(ip) = client_ip(depot) else {
+        return;
+    };
+    let now = Utc::now().timestamp();
+    if count_ip_request(ip, now / 60).await > limit {
+        log::debug!("Rate limited anonymous requests of {}", ip);
+        res.status_code(StatusCode::TOO_MANY_REQUESTS);
+        res.add_header("retry-after", 60 - now % 60, true).ok();
+        ctrl.skip_rest();
+    }
+}
+
+/// Today's usage and limits of the calling key.
+#[handler]
+pub async fn my_usage(
+    _req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<String, salvo::Error> {
+    let Ok(key) = depot.get::<ApiKey>(API_KEY) else {
+        res.status_code(StatusCode::UNAUTHORIZED);
+        return Ok(String::new());
+    };
+    let usage = current_usage(get_pg_pool(), &key.id).await.map_err(|e| {
+        log::error!("Failed to load usage of api key {}: {}", key.id, e);
+        salvo::Error::Io(std::io::Error::other("Failed to load usage"))
+    })?;
+    let (daily_requests, daily_bytes) = limits(key);
+    Ok(serde_json::to_string(&UsageReport {
+        key_id: key.id.clone(),
+        day: Utc::now().date_naive(),
+        usage,
+        daily_requests,
+        daily_bytes,
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Usage, exceeded};
+
+    #[test]
+    fn quota_is_exceeded_once_any_limit_is_reached() {
+        let usage = Usage {
+            requests: 10,
+            bytes: 2048,
+        };
+        assert!(!exceeded(usage, (None, None)));
+        assert!(!exceeded(usage, (Some(11), Some(4096))));
+        assert!(exceeded(usage, (Some(10), None)));
+        assert!(exceeded(usage, (None, Some(2048))));
+    }
+}
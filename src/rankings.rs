@@ -0,0 +1,238 @@
+//! Node rankings over the online channel graph, recomputed into `node_rankings` by the hourly
+//! job after the online views are refreshed.
+//!
+//! Besides the raw capacity and channel count of every node, `pagerank` ranks nodes by how
+//! likely a payment walking the graph passes them: each enabled channel direction is an edge
+//! weighted by its capacity discounted by its forwarding fee, so well funded, cheap routes
+//! carry more of a node's score than large but expensive ones.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{
+    Network,
+    error::skip_malformed,
+    pg_read::{ChannelInfo, HourlyChannelInfoDBRead, online_since},
+};
+
+const DAMPING: f64 = 0.85;
+const MAX_ITERATIONS: usize = 100;
+const TOLERANCE: f64 = 1e-10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RankingMetric {
+    /// Total ckb capacity of the node's online channels.
+    #[default]
+    Capacity,
+    /// Number of online channels of the node.
+    Channels,
+    /// Capacity and fee weighted PageRank, the scores of a network sum to 1.
+    Pagerank,
+}
+
+impl RankingMetric {
+    const ALL: [RankingMetric; 3] = [
+        RankingMetric::Capacity,
+        RankingMetric::Channels,
+        RankingMetric::Pagerank,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            RankingMetric::Capacity => "capacity",
+            RankingMetric::Channels => "channels",
+            RankingMetric::Pagerank => "pagerank",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeRanking {
+    pub rank: i32,
+    pub node_id: String,
+    pub score: f64,
+}
+
+/// Weight of routing through a channel direction charging `fee_rate` millionths of the amount.
+fn edge_weight(capacity: u64, fee_rate: u64) -> f64 {
+    capacity as f64 * 1_000_000.0 / (1_000_000.0 + fee_rate as f64)
+}
+
+/// Weighted PageRank over `nodes` nodes and directed `(from, to, weight)` edges. Nodes without
+/// outgoing weight spread their score evenly, so the scores always sum to 1.
+pub fn pagerank(nodes: usize, edges: &[(usize, usize, f64)]) -> Vec<f64> {
+    if nodes == 0 {
+        return Vec::new();
+    }
+    let mut out_weight = vec![0.0; nodes];
+    for (from, _, weight) in edges {
+        out_weight[*from] += weight;
+    }
+    let base = (1.0 - DAMPING) / nodes as f64;
+    let mut scores = vec![1.0 / nodes as f64; nodes];
+    for _ in 0..MAX_ITERATIONS {
+        let dangling: f64 = scores
+            .iter()
+            .zip(&out_weight)
+            .filter(|(_, out)| **out <= 0.0)
+            .map(|(score, _)| score)
+            .sum();
+        let mut next = vec![base + DAMPING * dangling / nodes as f64; nodes];
+        for (from, to, weight) in edges {
+            if out_weight[*from] > 0.0 {
+                next[*to] += DAMPING * scores[*from] * weight / out_weight[*from];
+            }
+        }
+        let delta: f64 = next.iter().zip(&scores).map(|(a, b)| (a - b).abs()).sum();
+        scores = next;
+        if delta < TOLERANCE {
+            break;
+        }
+    }
+    scores
+}
+
+/// Scores of every metric per node id of the online `channels`.
+fn scores(channels: &[ChannelInfo]) -> (Vec<String>, HashMap<RankingMetric, Vec<f64>>) {
+    let mut ids: Vec<String> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    let mut capacity = Vec::new();
+    let mut degree = Vec::new();
+    let mut edges = Vec::new();
+    for channel in channels {
+        let [a, b] = [&channel.node1, &channel.node2].map(|node| {
+            *index.entry(node.as_str()).or_insert_with(|| {
+                ids.push(node.clone());
+                capacity.push(0.0);
+                degree.push(0.0);
+                ids.len() - 1
+            })
+        });
+        for node in [a, b] {
+            capacity[node] += channel.capacity as f64;
+            degree[node] += 1.0;
+        }
+        // update_info_of_node1 describes forwarding from node1 to node2
+        for (from, to, update) in [
+            (a, b, &channel.update_info_of_node1),
+            (b, a, &channel.update_info_of_node2),
+        ] {
+            if let Some(update) = update
+                && update.enabled
+            {
+                edges.push((from, to, edge_weight(channel.capacity, update.fee_rate)));
+            }
+        }
+    }
+    let pagerank = pagerank(ids.len(), &edges);
+    let scores = HashMap::from([
+        (RankingMetric::Capacity, capacity),
+        (RankingMetric::Channels, degree),
+        (RankingMetric::Pagerank, pagerank),
+    ]);
+    (ids, scores)
+}
+
+/// Recompute every ranking of `net` from the channels in the online window.
+pub async fn compute(pool: &Pool<Postgres>, net: Network) -> Result<usize, sqlx::Error> {
+    let since = online_since();
+    let channels = HourlyChannelInfoDBRead::fetch_all_online(pool, net, since)
+        .await?
+        .into_iter()
+        .filter_map(|channel| skip_malformed("channel", channel))
+        .collect::<Vec<ChannelInfo>>();
+    let (ids, scores) = scores(&channels);
+
+    let computed_at = Utc::now();
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM node_rankings WHERE net = $1")
+        .bind(net.name())
+        .execute(&mut *tx)
+        .await?;
+    for metric in RankingMetric::ALL {
+        let mut ranked = ids.iter().zip(&scores[&metric]).collect::<Vec<_>>();
+        ranked.sort_by(|(a_id, a), (b_id, b)| b.total_cmp(a).then_with(|| a_id.cmp(b_id)));
+        let ranked = ranked.into_iter().enumerate().collect::<Vec<_>>();
+        for chunk in ranked.chunks(65535 / 6) {
+            let mut query_builder = sqlx::QueryBuilder::<Postgres>::new(
+                "INSERT INTO node_rankings (net, metric, node_id, score, rank, computed_at) ",
+            );
+            query_builder.push_values(chunk, |mut b, (i, (id, score))| {
+                b.push_bind(net.name())
+                    .push_bind(metric.name())
+                    .push_bind(*id)
+                    .push_bind(**score)
+                    .push_bind(*i as i32 + 1)
+                    .push_bind(computed_at);
+            });
+            query_builder.build().execute(&mut *tx).await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(ids.len())
+}
+
+/// Top `limit` nodes of `net` by `metric` as of the last hourly run, best first.
+pub async fn load(
+    pool: &Pool<Postgres>,
+    net: Network,
+    metric: RankingMetric,
+    limit: i64,
+) -> Result<(Option<DateTime<Utc>>, Vec<NodeRanking>), sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT rank, node_id, score, computed_at FROM node_rankings
+        WHERE net = $1 AND metric = $2 ORDER BY rank LIMIT $3",
+    )
+    .bind(net.name())
+    .bind(metric.name())
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    let computed_at = rows.first().map(|row| row.get("computed_at"));
+    let rankings = rows
+        .into_iter()
+        .map(|row| NodeRanking {
+            rank: row.get("rank"),
+            node_id: row.get("node_id"),
+            score: row.get("score"),
+        })
+        .collect();
+    Ok((computed_at, rankings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edge_weight, pagerank};
+
+    #[test]
+    fn scores_sum_to_one_and_favor_the_hub() {
+        // star around node 0 in both directions, node 4 has no outgoing edges
+        let mut edges = Vec::new();
+        for leaf in 1..4 {
+            edges.push((0, leaf, 1.0));
+            edges.push((leaf, 0, 1.0));
+        }
+        edges.push((0, 4, 1.0));
+        let scores = pagerank(5, &edges);
+        assert!((scores.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!(scores[1..].iter().all(|score| scores[0] > *score));
+        assert!((scores[1] - scores[3]).abs() < 1e-12);
+    }
+
+    #[test]
+    fn cheaper_routes_carry_more_score() {
+        let edges = [
+            (0, 1, edge_weight(1_000, 0)),
+            (0, 2, edge_weight(1_000, 1_000_000)),
+            (1, 0, 1.0),
+            (2, 0, 1.0),
+        ];
+        let scores = pagerank(3, &edges);
+        assert!(scores[1] > scores[2]);
+        assert!(pagerank(0, &[]).is_empty());
+    }
+}
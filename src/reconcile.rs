@@ -0,0 +1,257 @@
+//! Reconciliation of the gossip graph with the funding cells on chain.
+//!
+//! [`run`] lists the live cells of every accepted funding lock through the CKB indexer, a
+//! prefix search on the lock without args, and compares them with the announced channels.
+//! Online channels whose funding cell is no longer live are recorded as `spent_in_gossip`, live
+//! funding cells never announced as `unannounced_on_chain`, both in
+//! `graph_chain_discrepancies`. A discrepancy not found again by a later run is resolved.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use ckb_jsonrpc_types::JsonBytes;
+use ckb_types::{packed, prelude::*};
+use faster_hex::hex_string;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Pool, Postgres, Row};
+
+use crate::{
+    CKB_MAINNET_RPC, CKB_TESTNET_RPC, Network, RpcClient,
+    rpc_client::{CKB_MAINNET_RPC_BEARER_TOKEN, CKB_TESTNET_RPC_BEARER_TOKEN},
+    script_versions::{self, ScriptKind},
+    types::{IndexerScriptSearchMode, Order, ScriptType, SearchKey},
+};
+
+/// Cells requested per `get_cells` page.
+const CELLS_PAGE: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscrepancyKind {
+    /// Announced and online, but the funding cell is spent.
+    SpentInGossip,
+    /// Live funding cell without any announcement.
+    UnannouncedOnChain,
+}
+
+impl DiscrepancyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiscrepancyKind::SpentInGossip => "spent_in_gossip",
+            DiscrepancyKind::UnannouncedOnChain => "unannounced_on_chain",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Discrepancy {
+    pub net: String,
+    pub channel_outpoint: String,
+    pub kind: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReconcileSummary {
+    pub live_funding_cells: usize,
+    pub spent_in_gossip: usize,
+    pub unannounced_on_chain: usize,
+    pub resolved: u64,
+}
+
+/// Capacity in shannons of the live cells locked by any accepted funding script of `net`, by
+/// outpoint hex encoded like `channel_outpoint`.
+pub(crate) async fn live_funding_cells(
+    rpc: &mut RpcClient,
+    net: Network,
+) -> Result<HashMap<String, u64>, String> {
+    let (url, token) = match net {
+        Network::Mainnet => (
+            CKB_MAINNET_RPC.clone(),
+            CKB_MAINNET_RPC_BEARER_TOKEN.clone(),
+        ),
+        Network::Testnet => (
+            CKB_TESTNET_RPC.clone(),
+            CKB_TESTNET_RPC_BEARER_TOKEN.clone(),
+        ),
+    };
+    rpc.set_bearer_token(token);
+    let mut outpoints = HashMap::new();
+    for version in script_versions::versions(net, ScriptKind::Funding) {
+        let mut after = None;
+        loop {
+            let page = rpc
+                .get_cells(
+                    url.clone(),
+                    SearchKey {
+                        script: version.script(JsonBytes::default()),
+                        script_type: ScriptType::Lock,
+                        script_search_mode: Some(IndexerScriptSearchMode::Prefix),
+                        filter: None,
+                        with_data: Some(false),
+                        group_by_transaction: None,
+                    },
+                    Order::Asc,
+                    CELLS_PAGE.into(),
+                    after,
+                )
+                .await
+                .map_err(|e| format!("get_cells of funding lock {}: {}", version.version, e))?;
+            outpoints.extend(page.objects.iter().map(|cell| {
+                (
+                    hex_string(packed::OutPoint::from(cell.out_point.clone()).as_slice()),
+                    cell.output.capacity.value(),
+                )
+            }));
+            if page.objects.len() < CELLS_PAGE as usize {
+                break;
+            }
+            after = Some(page.last_cursor);
+        }
+    }
+    Ok(outpoints)
+}
+
+/// Online channels whose funding cell is not live, and live cells never announced.
+fn discrepancies(
+    online: &HashSet<String>,
+    live: &HashSet<String>,
+    announced: &HashSet<String>,
+) -> (Vec<String>, Vec<String>) {
+    let mut spent = online.difference(live).cloned().collect::<Vec<_>>();
+    let mut unannounced = live.difference(announced).cloned().collect::<Vec<_>>();
+    spent.sort_unstable();
+    unannounced.sort_unstable();
+    (spent, unannounced)
+}
+
+/// Compare the gossip graph of `net` with its live funding cells and record the differences.
+pub async fn run(
+    pool: &Pool<Postgres>,
+    rpc: &mut RpcClient,
+    net: Network,
+) -> Result<ReconcileSummary, String> {
+    let started_at = Utc::now();
+    let live = live_funding_cells(rpc, net)
+        .await?
+        .into_keys()
+        .collect::<HashSet<_>>();
+    let online = sqlx::query(&format!(
+        "SELECT DISTINCT channel_outpoint FROM {}",
+        net.mv_online_channels()
+    ))
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .iter()
+    .map(|row| row.get::<String, _>("channel_outpoint"))
+    .collect::<HashSet<_>>();
+    let announced = sqlx::query(&format!(
+        "SELECT DISTINCT channel_outpoint FROM {} WHERE channel_outpoint = ANY($1)",
+        net.channel_infos()
+    ))
+    .bind(live.iter().cloned().collect::<Vec<_>>())
+    .fetch_all(pool)
+    .await
+    .map_err(|e| e.to_string())?
+    .iter()
+    .map(|row| row.get::<String, _>("channel_outpoint"))
+    .collect::<HashSet<_>>();
+    let (spent, unannounced) = discrepancies(&online, &live, &announced);
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    for (kind, outpoints) in [
+        (DiscrepancyKind::SpentInGossip, &spent),
+        (DiscrepancyKind::UnannouncedOnChain, &unannounced),
+    ] {
+        sqlx::query(
+            "INSERT INTO graph_chain_discrepancies
+                (net, channel_outpoint, kind, first_seen, last_seen)
+            SELECT $1, outpoint, $2, $3, $3 FROM unnest($4::text[]) AS outpoint
+            ON CONFLICT (net, channel_outpoint, kind) DO UPDATE SET
+                first_seen = CASE WHEN graph_chain_discrepancies.resolved_at IS NULL
+                    THEN graph_chain_discrepancies.first_seen ELSE excluded.first_seen END,
+                last_seen = excluded.last_seen,
+                resolved_at = NULL",
+        )
+        .bind(net.name())
+        .bind(kind.as_str())
+        .bind(started_at)
+        .bind(outpoints)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+    }
+    let resolved = sqlx::query(
+        "UPDATE graph_chain_discrepancies SET resolved_at = $2
+        WHERE net = $1 AND resolved_at IS NULL AND last_seen < $2",
+    )
+    .bind(net.name())
+    .bind(started_at)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?
+    .rows_affected();
+    tx.commit().await.map_err(|e| e.to_string())?;
+
+    Ok(ReconcileSummary {
+        live_funding_cells: live.len(),
+        spent_in_gossip: spent.len(),
+        unannounced_on_chain: unannounced.len(),
+        resolved,
+    })
+}
+
+/// Newest first, unresolved ones only unless `resolved` is set.
+pub async fn list(
+    pool: &Pool<Postgres>,
+    net: Network,
+    kind: Option<DiscrepancyKind>,
+    resolved: bool,
+    page: usize,
+    page_size: usize,
+) -> Result<(Vec<Discrepancy>, usize, usize), sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT *, COUNT(*) OVER() AS total_count
+        FROM graph_chain_discrepancies
+        WHERE net = $1 AND ($2::text IS NULL OR kind = $2) AND ($3 OR resolved_at IS NULL)
+        ORDER BY last_seen DESC, channel_outpoint
+        LIMIT $4 OFFSET $5",
+    )
+    .bind(net.name())
+    .bind(kind.map(|kind| kind.as_str()))
+    .bind(resolved)
+    .bind(page_size as i64)
+    .bind(page.saturating_mul(page_size) as i64)
+    .fetch_all(pool)
+    .await?;
+    let total_count = rows
+        .first()
+        .map(|row| row.get::<i64, _>("total_count") as usize)
+        .unwrap_or(0);
+    let entries = rows
+        .iter()
+        .map(Discrepancy::from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((entries, page.saturating_add(1), total_count))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::discrepancies;
+
+    #[test]
+    fn spent_and_unannounced_outpoints() {
+        let set = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<HashSet<_>>();
+        let online = set(&["a", "b"]);
+        let live = set(&["b", "c", "d"]);
+        let announced = set(&["b", "d"]);
+        let (spent, unannounced) = discrepancies(&online, &live, &announced);
+        assert_eq!(spent, vec!["a".to_string()]);
+        assert_eq!(unannounced, vec!["c".to_string()]);
+    }
+}
@@ -0,0 +1,304 @@
+//! Weekly network reports, rendered as standalone HTML pages from the daily tables.
+//!
+//! The daily `weekly_reports` job renders the last complete week (Monday to Sunday) of every
+//! network once its last day is summarized and stores the page in `weekly_reports`, so a
+//! report never changes after it was written. Growth compares the last day of the week with
+//! the last day of the week before, charts are inline SVG, top nodes are the capacity ranking
+//! and notable events the milestones of the feed at generation time.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{
+    Network,
+    feed::{self, xml_escape},
+    kpis::{DayValues, day_values, percent_change},
+    rankings::{self, NodeRanking, RankingMetric},
+};
+
+const TOP_NODES: i64 = 10;
+const CHART_WIDTH: f64 = 560.0;
+const CHART_HEIGHT: f64 = 120.0;
+
+#[derive(Debug, Serialize)]
+pub struct ReportEntry {
+    /// Monday the week starts on.
+    pub week: NaiveDate,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Monday of the last week that has fully passed on `today`.
+pub fn last_complete_week(today: NaiveDate) -> NaiveDate {
+    today - Duration::days(today.weekday().num_days_from_monday() as i64 + 7)
+}
+
+/// Render and store the report of the week starting on `week`, `false` while its last day is
+/// not summarized yet.
+pub async fn generate(
+    pool: &Pool<Postgres>,
+    net: Network,
+    week: NaiveDate,
+) -> Result<bool, sqlx::Error> {
+    let sql = format!(
+        "SELECT day, nodes_count, channels_count, capacity_analysis FROM {}
+        WHERE day >= $1 AND day <= $2",
+        net.daily_summarized_data()
+    );
+    let days = sqlx::query(&sql)
+        .bind(week - Duration::days(1))
+        .bind(week + Duration::days(6))
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(day_values)
+        .collect::<HashMap<_, _>>();
+    if !days.contains_key(&(week + Duration::days(6))) {
+        return Ok(false);
+    }
+
+    let (_, top_nodes) = rankings::load(pool, net, RankingMetric::Capacity, TOP_NODES).await?;
+    let names = node_names(pool, net, &top_nodes).await?;
+    let end = (week + Duration::days(7))
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_utc();
+    let start = week.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let events = feed::milestones(pool, net)
+        .await?
+        .into_iter()
+        .filter(|milestone| milestone.updated >= start && milestone.updated < end)
+        .map(|milestone| (milestone.updated, milestone.title))
+        .collect::<Vec<_>>();
+
+    let html = render(net, week, &days, &top_nodes, &names, &events);
+    sqlx::query(
+        "INSERT INTO weekly_reports (net, week, html, generated_at) VALUES ($1, $2, $3, now())
+        ON CONFLICT (net, week) DO UPDATE
+        SET html = excluded.html, generated_at = excluded.generated_at",
+    )
+    .bind(net.name())
+    .bind(week)
+    .bind(html)
+    .execute(pool)
+    .await?;
+    Ok(true)
+}
+
+/// Generate the report of the last complete week before `today` unless it is stored already.
+pub async fn generate_missing(
+    pool: &Pool<Postgres>,
+    net: Network,
+    today: NaiveDate,
+) -> Result<bool, sqlx::Error> {
+    let week = last_complete_week(today);
+    let stored: bool = sqlx::query_scalar(
+        "SELECT EXISTS(SELECT 1 FROM weekly_reports WHERE net = $1 AND week = $2)",
+    )
+    .bind(net.name())
+    .bind(week)
+    .fetch_one(pool)
+    .await?;
+    if stored {
+        return Ok(false);
+    }
+    generate(pool, net, week).await
+}
+
+/// Stored reports of `net`, newest first.
+pub async fn list(pool: &Pool<Postgres>, net: Network) -> Result<Vec<ReportEntry>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT week, generated_at FROM weekly_reports WHERE net = $1 ORDER BY week DESC",
+    )
+    .bind(net.name())
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| ReportEntry {
+            week: row.get("week"),
+            generated_at: row.get("generated_at"),
+        })
+        .collect())
+}
+
+/// Page of the report of the week starting on `week`.
+pub async fn load(
+    pool: &Pool<Postgres>,
+    net: Network,
+    week: NaiveDate,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT html FROM weekly_reports WHERE net = $1 AND week = $2")
+        .bind(net.name())
+        .bind(week)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Latest announced names of the ranked nodes, seen in the last week.
+async fn node_names(
+    pool: &Pool<Postgres>,
+    net: Network,
+    nodes: &[NodeRanking],
+) -> Result<HashMap<String, String>, sqlx::Error> {
+    let ids = nodes
+        .iter()
+        .map(|node| node.node_id.clone())
+        .collect::<Vec<_>>();
+    let sql = format!(
+        "SELECT DISTINCT ON (node_id) node_id, node_name FROM {}
+        WHERE node_id = ANY($1) AND time >= $2
+        ORDER BY node_id, time DESC",
+        net.node_infos()
+    );
+    let rows = sqlx::query(&sql)
+        .bind(ids)
+        .bind(Utc::now() - Duration::days(7))
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.get("node_id"), row.get("node_name")))
+        .collect())
+}
+
+/// Line chart of `values` in order, scaled between their minimum and maximum.
+fn svg_chart(title: &str, values: &[u64]) -> String {
+    let min = values.iter().copied().min().unwrap_or_default();
+    let max = values.iter().copied().max().unwrap_or_default();
+    let span = (max - min).max(1) as f64;
+    let step = CHART_WIDTH / values.len().saturating_sub(1).max(1) as f64;
+    let points = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let y = CHART_HEIGHT - (value - min) as f64 / span * CHART_HEIGHT;
+            format!("{:.1},{:.1}", i as f64 * step, y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "<figure><figcaption>{title} ({min} to {max})</figcaption>\
+        <svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-4 -4 {w} {h}\" width=\"{w}\" \
+        height=\"{h}\"><polyline fill=\"none\" stroke=\"#2563eb\" stroke-width=\"2\" \
+        points=\"{points}\"/></svg></figure>",
+        title = xml_escape(title),
+        w = CHART_WIDTH + 8.0,
+        h = CHART_HEIGHT + 8.0,
+    )
+}
+
+fn change(current: u64, previous: Option<u64>) -> String {
+    match percent_change(current, previous) {
+        Some(change) => format!("{:+.1}%", change),
+        None => "-".to_string(),
+    }
+}
+
+/// Label and value of a growth table row.
+type GrowthRow = (&'static str, fn(&DayValues) -> u64);
+
+fn render(
+    net: Network,
+    week: NaiveDate,
+    days: &HashMap<NaiveDate, DayValues>,
+    top_nodes: &[NodeRanking],
+    names: &HashMap<String, String>,
+    events: &[(DateTime<Utc>, String)],
+) -> String {
+    let last = week + Duration::days(6);
+    let latest = days[&last];
+    let before = days.get(&(week - Duration::days(1)));
+    let series = (0..7)
+        .filter_map(|i| days.get(&(week + Duration::days(i))))
+        .collect::<Vec<_>>();
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+        <title>Fiber {net} weekly report {week}</title></head><body>\n\
+        <h1>Fiber {net} network, week of {week} to {last}</h1>\n",
+        net = net.name(),
+    );
+    html.push_str(
+        "<h2>Growth</h2>\n<table><tr><th></th><th>end of week</th><th>week change</th></tr>\n",
+    );
+    let rows: [GrowthRow; 4] = [
+        ("Nodes", |d| d.nodes),
+        ("Channels", |d| d.channels),
+        ("Capacity (CKB)", |d| d.capacity),
+        ("Median CKB channel (CKB)", |d| d.median_channel_capacity),
+    ];
+    for (label, value) in rows {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            label,
+            value(&latest),
+            change(value(&latest), before.map(value))
+        ));
+    }
+    html.push_str("</table>\n");
+    for (label, value) in &rows[..3] {
+        html.push_str(&svg_chart(
+            label,
+            &series.iter().map(|d| value(d)).collect::<Vec<_>>(),
+        ));
+        html.push('\n');
+    }
+
+    html.push_str("<h2>Top nodes by capacity</h2>\n<ol>\n");
+    for node in top_nodes {
+        let name = names
+            .get(&node.node_id)
+            .filter(|name| !name.is_empty())
+            .unwrap_or(&node.node_id);
+        html.push_str(&format!(
+            "<li>{} ({:.0} CKB)</li>\n",
+            xml_escape(name),
+            node.score / 100_000_000.0
+        ));
+    }
+    html.push_str("</ol>\n<h2>Notable events</h2>\n<ul>\n");
+    if events.is_empty() {
+        html.push_str("<li>None this week</li>\n");
+    }
+    for (time, title) in events {
+        html.push_str(&format!(
+            "<li>{} {}</li>\n",
+            time.date_naive(),
+            xml_escape(title)
+        ));
+    }
+    html.push_str("</ul>\n</body></html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::{last_complete_week, svg_chart};
+
+    #[test]
+    fn last_complete_week_starts_on_the_previous_monday() {
+        let monday = NaiveDate::from_ymd_opt(2025, 6, 2).unwrap();
+        let previous = NaiveDate::from_ymd_opt(2025, 5, 26).unwrap();
+        assert_eq!(last_complete_week(monday), previous);
+        assert_eq!(
+            last_complete_week(NaiveDate::from_ymd_opt(2025, 6, 8).unwrap()),
+            previous
+        );
+        assert_eq!(
+            last_complete_week(monday + chrono::Duration::days(7)),
+            monday
+        );
+    }
+
+    #[test]
+    fn chart_spans_the_value_range() {
+        let svg = svg_chart("Nodes <all>", &[10, 20, 15]);
+        assert!(svg.contains("Nodes &lt;all&gt; (10 to 20)"));
+        assert!(svg.contains("points=\"0.0,120.0 280.0,0.0 560.0,60.0\""));
+    }
+}
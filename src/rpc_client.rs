@@ -9,30 +9,31 @@ use std::{
     },
 };
 
-use crate::types::{
-    Cell, GraphChannelsParams, GraphChannelsResult, GraphNodesParams, GraphNodesResult, IndexerTip,
-    Order, Pagination, SearchKey, Tx,
+use crate::{
+    config,
+    types::{
+        Cell, GraphChannelsParams, GraphChannelsResult, GraphNodesParams, GraphNodesResult,
+        IndexerTip, Order, Pagination, SearchKey, Tx,
+    },
 };
 use ckb_jsonrpc_types::{BlockNumber, HeaderView, JsonBytes, TransactionView, TxStatus, Uint32};
 use ckb_types::H256;
 use serde::{Deserialize, Serialize};
 
 pub static CKB_MAINNET_RPC: LazyLock<Url> = LazyLock::new(|| {
-    std::env::var("CKB_MAINNET_RPC_URL")
-        .ok()
+    config::var("CKB_MAINNET_RPC_URL")
         .and_then(|url| Url::parse(&url).ok())
         .unwrap_or(Url::parse("https://mainnet.ckb.dev").unwrap())
 });
 pub static CKB_MAINNET_RPC_BEARER_TOKEN: LazyLock<Option<String>> =
-    LazyLock::new(|| std::env::var("CKB_MAINNET_RPC_BEARER_TOKEN").ok());
+    LazyLock::new(|| config::var("CKB_MAINNET_RPC_BEARER_TOKEN"));
 pub static CKB_TESTNET_RPC: LazyLock<Url> = LazyLock::new(|| {
-    std::env::var("CKB_TESTNET_RPC_URL")
-        .ok()
+    config::var("CKB_TESTNET_RPC_URL")
         .and_then(|url| Url::parse(&url).ok())
         .unwrap_or(Url::parse("https://testnet.ckb.dev").unwrap())
 });
 pub static CKB_TESTNET_RPC_BEARER_TOKEN: LazyLock<Option<String>> =
-    LazyLock::new(|| std::env::var("CKB_TESTNET_RPC_BEARER_TOKEN").ok());
+    LazyLock::new(|| config::var("CKB_TESTNET_RPC_BEARER_TOKEN"));
 
 macro_rules! jsonrpc {
     ($method:expr, $self:ident, $url:expr, $return:ty$(, $params:ident$(,)?)*) => {{
@@ -46,30 +47,43 @@ macro_rules! jsonrpc {
 
         let req_json: serde_json::Value = serde_json::from_str(&data).unwrap();
 
+        let endpoint = crate::upstream::endpoint_name(&$url);
         let c = $self.raw.post($url).json(&req_json);
         let c = if let Some(token) = &$self.bearer_token {
             c.bearer_auth(token)
         } else {
             c
         };
-        async {
-            let resp = c
-                .send()
-                .await
-                .map_err::<io::Error, _>(|e| io::Error::new(io::ErrorKind::ConnectionAborted, format!("{:?}", e)))?;
-            let output = resp
-                .json::<jsonrpc_core::response::Output>()
-                .await
-                .map_err::<io::Error, _>(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
-
-            match output {
-                jsonrpc_core::response::Output::Success(success) => {
-                    Ok(serde_json::from_value::<$return>(success.result).unwrap())
-                }
-                jsonrpc_core::response::Output::Failure(e) => {
-                    Err(io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))
+        async move {
+            crate::upstream::admit(&endpoint, $method)?;
+            let start = std::time::Instant::now();
+            let result: Result<$return, io::Error> = async {
+                let resp = c
+                    .send()
+                    .await
+                    .map_err::<io::Error, _>(|e| io::Error::new(io::ErrorKind::ConnectionAborted, format!("{:?}", e.without_url())))?;
+                let output = resp
+                    .json::<jsonrpc_core::response::Output>()
+                    .await
+                    .map_err::<io::Error, _>(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e.without_url())))?;
+
+                match output {
+                    jsonrpc_core::response::Output::Success(success) => {
+                        Ok(serde_json::from_value::<$return>(success.result).unwrap())
+                    }
+                    jsonrpc_core::response::Output::Failure(e) => {
+                        Err(io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))
+                    }
                 }
             }
+            .await;
+            crate::metrics::observe_rpc($method, &endpoint, start.elapsed(), result.is_ok());
+            crate::upstream::record(
+                &endpoint,
+                start.elapsed(),
+                result.as_ref().err().map(|e| e.to_string()),
+            );
+            result
         }
     }}
 }
@@ -129,6 +143,24 @@ impl RpcClient {
         }
     }
 
+    /// `graph_nodes` without parsing, so the untouched payload can be archived.
+    pub fn get_node_graph_raw(
+        &self,
+        url: Url,
+        params: GraphNodesParams,
+    ) -> impl Future<Output = Result<serde_json::Value, io::Error>> {
+        jsonrpc!("graph_nodes", self, url, serde_json::Value, params)
+    }
+
+    /// `graph_channels` without parsing, so the untouched payload can be archived.
+    pub fn get_channel_graph_raw(
+        &self,
+        url: Url,
+        params: GraphChannelsParams,
+    ) -> impl Future<Output = Result<serde_json::Value, io::Error>> {
+        jsonrpc!("graph_channels", self, url, serde_json::Value, params)
+    }
+
     pub fn get_transaction(
         &self,
         url: Url,
@@ -198,6 +230,19 @@ impl RpcClient {
         jsonrpc!("get_indexer_tip", self, url, IndexerTip)
     }
 
+    /// Chain hash the Fiber node was started for, from `node_info`.
+    pub fn get_fiber_chain_hash(&self, url: Url) -> impl Future<Output = Result<H256, io::Error>> {
+        #[derive(Deserialize)]
+        struct NodeInfoResponse {
+            chain_hash: H256,
+        }
+        let task = jsonrpc!("node_info", self, url, NodeInfoResponse);
+        async {
+            let res = task.await?;
+            Ok(res.chain_hash)
+        }
+    }
+
     pub fn get_header_by_number(
         &self,
         url: Url,
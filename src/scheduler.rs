@@ -0,0 +1,331 @@
+//! Periodic collector jobs, registered with a name, a [`ClockTimer`] and a handler.
+//!
+//! Job definitions are upserted into `jobs` when the scheduler starts and every run is
+//! recorded in `job_runs`, so the admin api of any process can list them. A job never runs
+//! twice at the same time, a trigger arriving while it runs is skipped. Manual runs are
+//! requested through `jobs.requested_at`, which the collector polls every
+//! [`REQUEST_POLL_INTERVAL`]. Jobs registered with [`Scheduler::register_manual`] have no
+//! timer and only run on request.
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Row};
+use tokio::sync::Notify;
+
+use crate::clock_timer::ClockTimer;
+
+const REQUEST_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+type JobFn = Arc<dyn Fn(DateTime<Utc>) -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+struct Job {
+    name: &'static str,
+    /// Human readable schedule, e.g. `daily at 00:11`.
+    schedule: &'static str,
+    run: JobFn,
+    running: AtomicBool,
+    requested: Notify,
+    heartbeat: AtomicU64,
+}
+
+static JOBS: std::sync::OnceLock<Vec<Arc<Job>>> = std::sync::OnceLock::new();
+
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: Vec<(Arc<Job>, Option<ClockTimer>)>,
+}
+
+impl Scheduler {
+    pub fn register<F>(
+        self,
+        name: &'static str,
+        schedule: &'static str,
+        timer: ClockTimer,
+        run: impl Fn(DateTime<Utc>) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.add(name, schedule, Some(timer), run)
+    }
+
+    /// A job only run through [`request_run`], e.g. from the admin api.
+    pub fn register_manual<F>(
+        self,
+        name: &'static str,
+        schedule: &'static str,
+        run: impl Fn(DateTime<Utc>) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.add(name, schedule, None, run)
+    }
+
+    fn add<F>(
+        mut self,
+        name: &'static str,
+        schedule: &'static str,
+        timer: Option<ClockTimer>,
+        run: impl Fn(DateTime<Utc>) -> F + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let run: JobFn = Arc::new(move |trigger_time| Box::pin(run(trigger_time)));
+        let job = Arc::new(Job {
+            name,
+            schedule,
+            run,
+            running: AtomicBool::new(false),
+            requested: Notify::new(),
+            heartbeat: AtomicU64::new(0),
+        });
+        self.jobs.push((job, timer));
+        self
+    }
+
+    /// Persist the definitions and spawn one task per job plus the manual request poller.
+    pub async fn start(self, pool: &'static Pool<Postgres>) {
+        // runs left running by a previous process never finish
+        if let Err(e) = sqlx::query(
+            "UPDATE job_runs SET status = 'interrupted', finished_at = now()
+            WHERE status = 'running'",
+        )
+        .execute(pool)
+        .await
+        {
+            log::error!("Failed to close interrupted job runs: {}", e);
+        }
+        for (job, _) in &self.jobs {
+            if let Err(e) = sqlx::query(
+                "INSERT INTO jobs (name, schedule, updated_at) VALUES ($1, $2, now())
+                ON CONFLICT (name) DO UPDATE SET schedule = $2, updated_at = now()",
+            )
+            .bind(job.name)
+            .bind(job.schedule)
+            .execute(pool)
+            .await
+            {
+                log::error!("Failed to register job {}: {}", job.name, e);
+            }
+        }
+        let jobs = self.jobs.iter().map(|(job, _)| job.clone()).collect();
+        assert!(JOBS.set(jobs).is_ok(), "Scheduler already started");
+        for (job, timer) in self.jobs {
+            let timer = timer.map(|timer| timer.named(job.name));
+            tokio::spawn(job_loop(pool, job, timer));
+        }
+        tokio::spawn(poll_requests(pool));
+    }
+}
+
+/// Last time the loop of job `name` was alive, 0 when it is not registered in this process.
+pub fn heartbeat(name: &str) -> u64 {
+    JOBS.get()
+        .and_then(|jobs| jobs.iter().find(|job| job.name == name))
+        .map_or(0, |job| job.heartbeat.load(Ordering::Acquire))
+}
+
+/// Next trigger of `timer`, never for manual jobs.
+async fn tick(timer: &mut Option<ClockTimer>) -> DateTime<Utc> {
+    match timer {
+        Some(timer) => timer.tick().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn job_loop(pool: &'static Pool<Postgres>, job: Arc<Job>, mut timer: Option<ClockTimer>) {
+    let mut heartbeat_timer = tokio::time::interval(HEARTBEAT_INTERVAL);
+    heartbeat_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    loop {
+        let (trigger_time, manual) = tokio::select! {
+            _ = heartbeat_timer.tick() => {
+                job.heartbeat.store(Utc::now().timestamp() as u64, Ordering::Release);
+                continue;
+            }
+            trigger_time = tick(&mut timer) => (trigger_time, false),
+            _ = job.requested.notified() => (Utc::now(), true),
+        };
+        run_once(pool, &job, trigger_time, manual).await;
+        let Some(timer) = &timer else {
+            continue;
+        };
+        let next_run = timer.next_trigger_time(Utc::now());
+        if let Err(e) = sqlx::query("UPDATE jobs SET next_run_at = $2 WHERE name = $1")
+            .bind(job.name)
+            .bind(next_run)
+            .execute(pool)
+            .await
+        {
+            log::warn!("Failed to store next run of job {}: {}", job.name, e);
+        }
+    }
+}
+
+async fn run_once(pool: &Pool<Postgres>, job: &Job, trigger_time: DateTime<Utc>, manual: bool) {
+    if job.running.swap(true, Ordering::AcqRel) {
+        log::warn!(
+            "Job {} is still running, trigger at {} skipped",
+            job.name,
+            trigger_time
+        );
+        return;
+    }
+    let id: Option<i64> = sqlx::query(
+        "INSERT INTO job_runs (name, manual, started_at, status) VALUES ($1, $2, now(), 'running')
+        RETURNING id",
+    )
+    .bind(job.name)
+    .bind(manual)
+    .fetch_one(pool)
+    .await
+    .map(|row| row.get("id"))
+    .inspect_err(|e| log::error!("Failed to record start of job {}: {}", job.name, e))
+    .ok();
+
+    // a panicking job is recorded as failed instead of taking the loop down
+    let result = match tokio::spawn((job.run)(trigger_time)).await {
+        Ok(result) => result,
+        Err(e) => Err(format!("panicked: {}", e)),
+    };
+    match &result {
+        Ok(()) => log::info!("Job {} finished, triggered at {}", job.name, trigger_time),
+        Err(e) => log::error!("Job {} failed: {}", job.name, e),
+    }
+    if let Some(id) = id
+        && let Err(e) = sqlx::query(
+            "UPDATE job_runs SET finished_at = now(), status = $2, error = $3 WHERE id = $1",
+        )
+        .bind(id)
+        .bind(if result.is_ok() { "ok" } else { "failed" })
+        .bind(result.err())
+        .execute(pool)
+        .await
+    {
+        log::error!("Failed to record end of job {}: {}", job.name, e);
+    }
+    job.running.store(false, Ordering::Release);
+}
+
+async fn poll_requests(pool: &'static Pool<Postgres>) {
+    let mut timer = tokio::time::interval(REQUEST_POLL_INTERVAL);
+    timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let jobs = JOBS.get().expect("Scheduler not started");
+    loop {
+        timer.tick().await;
+        let requested = sqlx::query(
+            "UPDATE jobs SET requested_at = NULL WHERE requested_at IS NOT NULL RETURNING name",
+        )
+        .fetch_all(pool)
+        .await;
+        match requested {
+            Ok(rows) => {
+                for row in rows {
+                    let name: String = row.get("name");
+                    match jobs.iter().find(|job| job.name == name) {
+                        Some(job) if job.running.load(Ordering::Acquire) => {
+                            log::warn!("Job {} is already running, manual run skipped", name)
+                        }
+                        Some(job) => job.requested.notify_one(),
+                        None => log::warn!("Manual run of unknown job {} skipped", name),
+                    }
+                }
+            }
+            Err(e) => log::error!("Failed to poll job requests: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobRun {
+    pub manual: bool,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// running / ok / failed / interrupted
+    pub status: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub schedule: String,
+    pub next_run_at: Option<DateTime<Utc>>,
+    /// A manual run waits for the collector to pick it up.
+    pub requested_at: Option<DateTime<Utc>>,
+    pub last_run: Option<JobRun>,
+}
+
+/// Every registered job with its latest run, readable from any process.
+pub async fn list(pool: &Pool<Postgres>) -> Result<Vec<JobStatus>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT j.name, j.schedule, j.next_run_at, j.requested_at,
+            r.manual, r.started_at, r.finished_at, r.status, r.error
+        FROM jobs j
+        LEFT JOIN LATERAL (
+            SELECT * FROM job_runs WHERE name = j.name ORDER BY started_at DESC LIMIT 1
+        ) r ON true
+        ORDER BY j.name",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| JobStatus {
+            name: row.get("name"),
+            schedule: row.get("schedule"),
+            next_run_at: row.get("next_run_at"),
+            requested_at: row.get("requested_at"),
+            last_run: row
+                .get::<Option<DateTime<Utc>>, _>("started_at")
+                .map(|started_at| JobRun {
+                    manual: row.get("manual"),
+                    started_at,
+                    finished_at: row.get("finished_at"),
+                    status: row.get("status"),
+                    error: row.get("error"),
+                }),
+        })
+        .collect())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RequestOutcome {
+    Requested,
+    UnknownJob,
+    AlreadyRunning,
+}
+
+/// Ask the collector to run job `name` now.
+pub async fn request_run(pool: &Pool<Postgres>, name: &str) -> Result<RequestOutcome, sqlx::Error> {
+    let running: Option<bool> = sqlx::query(
+        "SELECT EXISTS(SELECT 1 FROM job_runs WHERE name = $1 AND status = 'running') AS running
+        FROM jobs WHERE name = $1",
+    )
+    .bind(name)
+    .fetch_optional(pool)
+    .await?
+    .map(|row| row.get("running"));
+    match running {
+        None => Ok(RequestOutcome::UnknownJob),
+        Some(true) => Ok(RequestOutcome::AlreadyRunning),
+        Some(false) => {
+            sqlx::query("UPDATE jobs SET requested_at = now() WHERE name = $1")
+                .bind(name)
+                .execute(pool)
+                .await?;
+            Ok(RequestOutcome::Requested)
+        }
+    }
+}
@@ -0,0 +1,351 @@
+//! Funding and commitment lock scripts accepted per network.
+//!
+//! Script upgrades deploy new code hashes while channels opened under the old ones stay on
+//! chain, so every network accepts a list of versioned scripts. The list comes from
+//! `{MAINNET,TESTNET}_{FUNDING,COMMITMENT}_CODE_HASHES` and is merged into the
+//! `script_versions` table, where older versions stay known after the config moves on.
+
+use std::{collections::HashMap, str::FromStr, sync::LazyLock};
+
+use arc_swap::ArcSwap;
+use ckb_jsonrpc_types::{JsonBytes, Script, ScriptHashType};
+use ckb_types::H256;
+use faster_hex::hex_string;
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{
+    Network, config,
+    types::{
+        MAINNET_COMMITMENT_CODE_HASH, MAINNET_FUNDING_CODE_HASH, TESTNET_COMMITMENT_CODE_HASH,
+        TESTNET_FUNDING_CODE_HASH,
+    },
+};
+
+/// Version of the scripts configured by the single `*_CODE_HASH` variables, every channel
+/// tracked before script versions existed uses it.
+pub const LEGACY_VERSION: &str = "v1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptKind {
+    Funding,
+    Commitment,
+}
+
+impl ScriptKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ScriptKind::Funding => "funding",
+            ScriptKind::Commitment => "commitment",
+        }
+    }
+
+    fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "funding" => Some(ScriptKind::Funding),
+            "commitment" => Some(ScriptKind::Commitment),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptVersion {
+    pub net: Network,
+    pub kind: ScriptKind,
+    pub version: String,
+    pub code_hash: H256,
+    pub hash_type: ScriptHashType,
+}
+
+impl ScriptVersion {
+    pub fn script(&self, args: JsonBytes) -> Script {
+        Script {
+            code_hash: self.code_hash.clone(),
+            hash_type: self.hash_type,
+            args,
+        }
+    }
+
+    fn matches(&self, script: &Script) -> bool {
+        self.code_hash == script.code_hash && self.hash_type == script.hash_type
+    }
+}
+
+static VERSIONS: LazyLock<ArcSwap<Vec<ScriptVersion>>> =
+    LazyLock::new(|| ArcSwap::from_pointee(configured()));
+
+fn configured() -> Vec<ScriptVersion> {
+    let mut versions = Vec::new();
+    for (net, kind, var, legacy) in [
+        (
+            Network::Mainnet,
+            ScriptKind::Funding,
+            "MAINNET_FUNDING_CODE_HASHES",
+            &*MAINNET_FUNDING_CODE_HASH,
+        ),
+        (
+            Network::Testnet,
+            ScriptKind::Funding,
+            "TESTNET_FUNDING_CODE_HASHES",
+            &*TESTNET_FUNDING_CODE_HASH,
+        ),
+        (
+            Network::Mainnet,
+            ScriptKind::Commitment,
+            "MAINNET_COMMITMENT_CODE_HASHES",
+            &*MAINNET_COMMITMENT_CODE_HASH,
+        ),
+        (
+            Network::Testnet,
+            ScriptKind::Commitment,
+            "TESTNET_COMMITMENT_CODE_HASHES",
+            &*TESTNET_COMMITMENT_CODE_HASH,
+        ),
+    ] {
+        let list = config::var(var)
+            .map(|list| parse_list(&list).unwrap_or_else(|e| panic!("Invalid {}: {}", var, e)))
+            .unwrap_or_else(|| {
+                vec![(
+                    LEGACY_VERSION.to_string(),
+                    legacy.clone(),
+                    ScriptHashType::Type,
+                )]
+            });
+        versions.extend(
+            list.into_iter()
+                .map(|(version, code_hash, hash_type)| ScriptVersion {
+                    net,
+                    kind,
+                    version,
+                    code_hash,
+                    hash_type,
+                }),
+        );
+    }
+    versions
+}
+
+/// Parse `version:0xcode_hash[:hash_type]` entries separated by commas, `hash_type`
+/// defaults to `type`.
+fn parse_list(list: &str) -> Result<Vec<(String, H256, ScriptHashType)>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let mut parts = entry.split(':');
+            let version = parts.next().unwrap_or_default();
+            let code_hash = parts
+                .next()
+                .ok_or_else(|| format!("missing code hash in `{}`", entry))?;
+            let code_hash = H256::from_str(code_hash.trim_start_matches("0x"))
+                .map_err(|_| format!("invalid code hash in `{}`", entry))?;
+            let hash_type = match parts.next() {
+                Some(hash_type) => parse_hash_type(hash_type)
+                    .ok_or_else(|| format!("invalid hash type in `{}`", entry))?,
+                None => ScriptHashType::Type,
+            };
+            if version.is_empty() || parts.next().is_some() {
+                return Err(format!(
+                    "expected `version:code_hash[:hash_type]`, got `{}`",
+                    entry
+                ));
+            }
+            Ok((version.to_string(), code_hash, hash_type))
+        })
+        .collect()
+}
+
+fn parse_hash_type(hash_type: &str) -> Option<ScriptHashType> {
+    serde_json::from_value(serde_json::Value::String(hash_type.to_string())).ok()
+}
+
+fn hash_type_str(hash_type: &ScriptHashType) -> String {
+    serde_json::to_value(hash_type)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Record the configured versions in `script_versions` and reload the accepted set from it.
+/// Versions already in the table keep their name.
+pub async fn sync(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    for version in configured() {
+        sqlx::query(
+            "INSERT INTO script_versions (net, kind, version, code_hash, hash_type)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (net, kind, code_hash, hash_type) DO NOTHING",
+        )
+        .bind(version.net.name())
+        .bind(version.kind.as_str())
+        .bind(&version.version)
+        .bind(hex_string(version.code_hash.as_bytes()))
+        .bind(hash_type_str(&version.hash_type))
+        .execute(pool)
+        .await?;
+    }
+
+    let versions = load(pool).await?;
+    VERSIONS.store(versions.into());
+    Ok(())
+}
+
+async fn load(pool: &Pool<Postgres>) -> Result<Vec<ScriptVersion>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT net, kind, version, code_hash, hash_type FROM script_versions
+        ORDER BY created_at, version",
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let net = match row.get::<String, _>("net").as_str() {
+                "mainnet" => Network::Mainnet,
+                "testnet" => Network::Testnet,
+                _ => return None,
+            };
+            Some(ScriptVersion {
+                net,
+                kind: ScriptKind::parse(row.get("kind"))?,
+                version: row.get("version"),
+                code_hash: H256::from_str(row.get("code_hash")).ok()?,
+                hash_type: parse_hash_type(row.get("hash_type"))?,
+            })
+        })
+        .collect())
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionUsage {
+    pub version: String,
+    pub code_hash: H256,
+    pub hash_type: ScriptHashType,
+    pub open_channels: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionReport {
+    pub net: Network,
+    /// Funding script versions, oldest first.
+    pub versions: Vec<VersionUsage>,
+    /// Open channels whose funding lock matched no accepted version when they were first seen.
+    pub untagged: i64,
+}
+
+/// Open channels of `net` per funding script version, read from the database so api-only
+/// processes report the collector's view.
+pub async fn open_channels_by_version(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<VersionReport, sqlx::Error> {
+    let sql = format!(
+        "SELECT script_version, count(*) AS open_channels FROM {}
+        WHERE state = 'open' GROUP BY script_version",
+        net.channel_states()
+    );
+    let mut counts = sqlx::query(&sql)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            (
+                row.get::<Option<String>, _>("script_version"),
+                row.get::<i64, _>("open_channels"),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    let versions = load(pool)
+        .await?
+        .into_iter()
+        .filter(|v| v.net == net && v.kind == ScriptKind::Funding)
+        .map(|v| VersionUsage {
+            open_channels: counts.remove(&Some(v.version.clone())).unwrap_or(0),
+            version: v.version,
+            code_hash: v.code_hash,
+            hash_type: v.hash_type,
+        })
+        .collect();
+    Ok(VersionReport {
+        net,
+        versions,
+        // versions removed from the table by hand count as untagged too
+        untagged: counts.values().sum(),
+    })
+}
+
+/// Accepted versions of `kind` on `net`, oldest first.
+pub fn versions(net: Network, kind: ScriptKind) -> Vec<ScriptVersion> {
+    VERSIONS
+        .load()
+        .iter()
+        .filter(|v| v.net == net && v.kind == kind)
+        .cloned()
+        .collect()
+}
+
+/// Version `script` belongs to, `None` when it is not an accepted `kind` script.
+pub fn version_of(net: Network, kind: ScriptKind, script: &Script) -> Option<String> {
+    VERSIONS
+        .load()
+        .iter()
+        .find(|v| v.net == net && v.kind == kind && v.matches(script))
+        .map(|v| v.version.clone())
+}
+
+/// Funding lock of a channel tagged with `version`, or every accepted funding lock when the
+/// version is unknown.
+pub fn funding_scripts(net: Network, version: Option<&str>, args: JsonBytes) -> Vec<Script> {
+    let versions = versions(net, ScriptKind::Funding);
+    let tagged = versions
+        .iter()
+        .filter(|v| Some(v.version.as_str()) == version)
+        .map(|v| v.script(args.clone()))
+        .collect::<Vec<_>>();
+    if !tagged.is_empty() {
+        return tagged;
+    }
+    versions.iter().map(|v| v.script(args.clone())).collect()
+}
+
+/// Commitment locks with `args` under every accepted commitment version.
+pub fn commitment_scripts(net: Network, args: JsonBytes) -> Vec<Script> {
+    versions(net, ScriptKind::Commitment)
+        .iter()
+        .map(|v| v.script(args.clone()))
+        .collect()
+}
+
+pub fn is_commitment_lock(net: Network, lock: &Script) -> bool {
+    version_of(net, ScriptKind::Commitment, lock).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use ckb_jsonrpc_types::ScriptHashType;
+
+    use super::parse_list;
+
+    #[test]
+    fn parse_versioned_code_hashes() {
+        let list = parse_list(
+            "v1:0xe45b1f8f21bff23137035a3ab751d75b36a981deec3e7820194b9c042967f4f1, \
+            v2:6c67887fe201ee0c7853f1682c0b77c0e6214044c156c7558269390a8afa6d7c:data1",
+        )
+        .unwrap();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].0, "v1");
+        assert_eq!(list[0].2, ScriptHashType::Type);
+        assert_eq!(list[1].0, "v2");
+        assert_eq!(list[1].2, ScriptHashType::Data1);
+    }
+
+    #[test]
+    fn reject_entries_without_code_hash() {
+        assert!(parse_list("v1").is_err());
+        assert!(parse_list(":0x00").is_err());
+        assert!(parse_list("v1:0x1234").is_err());
+    }
+}
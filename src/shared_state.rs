@@ -0,0 +1,48 @@
+//! Optional Redis connection for state shared between api replicas.
+//!
+//! When `REDIS_URL` is unset or Redis cannot be reached at startup, callers keep their
+//! in-process state. Once connected, the connection manager reconnects on its own and
+//! callers fall back per operation when a command fails.
+
+use std::sync::LazyLock;
+
+use redis::aio::ConnectionManager;
+use tokio::sync::OnceCell;
+
+use crate::config;
+
+/// Prefix of every key written to Redis, so several deployments can share one instance.
+pub static REDIS_KEY_PREFIX: LazyLock<String> =
+    LazyLock::new(|| config::var("REDIS_KEY_PREFIX").unwrap_or("fiber-dashboard".to_string()));
+
+static REDIS_URL: LazyLock<Option<String>> = LazyLock::new(|| config::var("REDIS_URL"));
+
+/// Shared connection, `None` when Redis is not configured or the first connection failed.
+pub async fn connection() -> Option<ConnectionManager> {
+    static CONNECTION: OnceCell<Option<ConnectionManager>> = OnceCell::const_new();
+    CONNECTION
+        .get_or_init(|| async {
+            let url = REDIS_URL.as_ref()?;
+            let connected = match redis::Client::open(url.as_str()) {
+                Ok(client) => client.get_connection_manager().await,
+                Err(e) => Err(e),
+            };
+            connected
+                .inspect(|_| log::info!("Sharing api state through Redis"))
+                .inspect_err(|e| {
+                    log::error!("Failed to connect to Redis, using in-process state: {}", e)
+                })
+                .ok()
+        })
+        .await
+        .clone()
+}
+
+pub fn key(parts: &[&str]) -> String {
+    let mut key = REDIS_KEY_PREFIX.clone();
+    for part in parts {
+        key.push(':');
+        key.push_str(part);
+    }
+    key
+}
@@ -0,0 +1,281 @@
+//! Synthetic Fiber network for `--simulate`, so the dashboard runs without any Fiber or CKB
+//! rpc.
+//!
+//! Every cycle a share of the nodes leaves along with their channels, a share of the other
+//! channels closes, and new nodes and channels take their place until the graph is back at its
+//! configured size. The graph is handed to the collector as `graph_nodes` / `graph_channels`
+//! results, so it goes through the same ingest path as a real one. Nodes announce private
+//! addresses, they are never sent to ipinfo.
+
+use std::{str::FromStr, sync::LazyLock};
+
+use chrono::{DateTime, Utc};
+use ckb_jsonrpc_types::JsonBytes;
+use ckb_types::{H256, bytes::Bytes};
+use faster_hex::hex_string;
+
+use crate::{
+    Network, config,
+    types::{ChannelInfo, ChannelUpdateInfo, NodeInfo, UdtCfgInfos},
+};
+
+fn var_or<T: FromStr>(var: &str, default: T) -> T {
+    config::parse_var(var).unwrap_or(default)
+}
+
+/// Size and churn of the simulated graph, from `SIMULATE_*`.
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub nodes: usize,
+    pub channels: usize,
+    /// Share of the nodes leaving every cycle.
+    pub node_churn: f64,
+    /// Share of the channels closing every cycle, besides those of leaving nodes.
+    pub channel_churn: f64,
+    /// Seed of the graph, the same seed replays the same network.
+    pub seed: u64,
+}
+
+pub static SIM_CONFIG: LazyLock<SimConfig> = LazyLock::new(|| SimConfig {
+    nodes: var_or("SIMULATE_NODES", 200),
+    channels: var_or("SIMULATE_CHANNELS", 600),
+    node_churn: var_or("SIMULATE_NODE_CHURN", 0.02f64).clamp(0.0, 1.0),
+    channel_churn: var_or("SIMULATE_CHANNEL_CHURN", 0.05f64).clamp(0.0, 1.0),
+    seed: var_or("SIMULATE_SEED", 1),
+});
+
+/// Seconds between simulated collection cycles.
+pub static SIMULATE_INTERVAL_SECS: LazyLock<u64> = LazyLock::new(|| {
+    config::parse_var("SIMULATE_INTERVAL_SECS")
+        .unwrap_or(60u64)
+        .max(1)
+});
+
+/// splitmix64, enough for a reproducible synthetic graph.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`, `n` > 0.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn bytes<const N: usize>(&mut self) -> [u8; N] {
+        let mut bytes = [0u8; N];
+        for chunk in bytes.chunks_mut(8) {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes()[..chunk.len()]);
+        }
+        bytes
+    }
+}
+
+/// How many of `len` items a `rate` churns, at least one when the rate is not zero.
+fn churned(len: usize, rate: f64) -> usize {
+    if len == 0 || rate <= 0.0 {
+        return 0;
+    }
+    ((len as f64 * rate).round() as usize).clamp(1, len)
+}
+
+struct SimNode {
+    /// Hex of the compressed public key, as the rpc returns it.
+    id: String,
+    name: String,
+    ip: [u8; 3],
+    /// Shannons.
+    auto_accept: u64,
+}
+
+struct SimChannel {
+    outpoint: [u8; 36],
+    node1: String,
+    node2: String,
+    /// Shannons.
+    capacity: u128,
+    created_at: DateTime<Utc>,
+    fee_rates: [u64; 2],
+}
+
+pub struct Simulation {
+    net: Network,
+    config: SimConfig,
+    rng: Rng,
+    nodes: Vec<SimNode>,
+    channels: Vec<SimChannel>,
+    /// Nodes created so far, numbering their names.
+    joined: usize,
+}
+
+impl Simulation {
+    pub fn new(net: Network, config: SimConfig) -> Self {
+        let rng = Rng(config.seed ^ net as u64);
+        Simulation {
+            net,
+            config,
+            rng,
+            nodes: Vec::new(),
+            channels: Vec::new(),
+            joined: 0,
+        }
+    }
+
+    fn new_node(&mut self) -> SimNode {
+        self.joined += 1;
+        SimNode {
+            id: format!("02{}", hex_string(&self.rng.bytes::<32>())),
+            name: format!("sim-node-{}", self.joined),
+            ip: self.rng.bytes::<3>(),
+            auto_accept: (100 + self.rng.below(900) as u64) * 100_000_000,
+        }
+    }
+
+    fn new_channel(&mut self, now: DateTime<Utc>) -> Option<SimChannel> {
+        if self.nodes.len() < 2 {
+            return None;
+        }
+        let first = self.rng.below(self.nodes.len());
+        let second = (first + 1 + self.rng.below(self.nodes.len() - 1)) % self.nodes.len();
+        // mostly small channels with a long tail, 100 to 100 000 CKB
+        let capacity_ckb =
+            100u128 * 10u128.pow(self.rng.below(4) as u32) * (1 + self.rng.below(9) as u128);
+        Some(SimChannel {
+            outpoint: self.rng.bytes::<36>(),
+            node1: self.nodes[first].id.clone(),
+            node2: self.nodes[second].id.clone(),
+            capacity: capacity_ckb.min(100_000) * 100_000_000,
+            created_at: now,
+            fee_rates: [
+                1000 * (1 + self.rng.below(5) as u64),
+                1000 * (1 + self.rng.below(5) as u64),
+            ],
+        })
+    }
+
+    /// Advance the network by one cycle ending at `now`.
+    pub fn step(&mut self, now: DateTime<Utc>) {
+        for _ in 0..churned(self.nodes.len(), self.config.node_churn) {
+            let left = self.nodes.swap_remove(self.rng.below(self.nodes.len()));
+            self.channels
+                .retain(|channel| channel.node1 != left.id && channel.node2 != left.id);
+        }
+        for _ in 0..churned(self.channels.len(), self.config.channel_churn) {
+            self.channels
+                .swap_remove(self.rng.below(self.channels.len()));
+        }
+        while self.nodes.len() < self.config.nodes {
+            let node = self.new_node();
+            self.nodes.push(node);
+        }
+        while self.channels.len() < self.config.channels {
+            let Some(channel) = self.new_channel(now) else {
+                break;
+            };
+            self.channels.push(channel);
+        }
+    }
+
+    fn chain_hash(&self) -> H256 {
+        H256::from_str(self.net.chain_hash()).expect("chain hash of a known network")
+    }
+
+    /// The nodes as `graph_nodes` returns them, announced at `now`.
+    pub fn graph_nodes(&self, now: DateTime<Utc>) -> Vec<NodeInfo> {
+        self.nodes
+            .iter()
+            .map(|node| NodeInfo {
+                node_name: node.name.clone(),
+                addresses: vec![
+                    format!(
+                        "/ip4/10.{}.{}.{}/tcp/8228",
+                        node.ip[0], node.ip[1], node.ip[2]
+                    )
+                    .parse()
+                    .expect("valid multiaddr"),
+                ],
+                node_id: Bytes::from(node.id.clone()),
+                timestamp: now.timestamp_millis() as u64,
+                chain_hash: self.chain_hash(),
+                auto_accept_min_ckb_funding_amount: node.auto_accept,
+                udt_cfg_infos: UdtCfgInfos(Vec::new()),
+            })
+            .collect()
+    }
+
+    /// The channels as `graph_channels` returns them, updated at `now`.
+    pub fn graph_channels(&self, now: DateTime<Utc>) -> Vec<ChannelInfo> {
+        let update = |fee_rate| ChannelUpdateInfo {
+            timestamp: now.timestamp_millis() as u64,
+            enabled: true,
+            outbound_liquidity: None,
+            tlc_expiry_delta: 4 * 60 * 60 * 1000,
+            tlc_minimum_value: 0,
+            fee_rate,
+        };
+        self.channels
+            .iter()
+            .map(|channel| ChannelInfo {
+                channel_outpoint: JsonBytes::from_vec(channel.outpoint.to_vec()),
+                node1: Bytes::from(channel.node1.clone()),
+                node2: Bytes::from(channel.node2.clone()),
+                created_timestamp: channel.created_at.timestamp_millis() as u64,
+                update_info_of_node1: Some(update(channel.fee_rates[0])),
+                update_info_of_node2: Some(update(channel.fee_rates[1])),
+                capacity: channel.capacity,
+                chain_hash: self.chain_hash(),
+                udt_type_script: None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::Utc;
+
+    use super::{SimConfig, Simulation};
+    use crate::Network;
+
+    #[test]
+    fn churn_keeps_the_graph_at_size_and_channels_between_live_nodes() {
+        let config = SimConfig {
+            nodes: 50,
+            channels: 120,
+            node_churn: 0.1,
+            channel_churn: 0.2,
+            seed: 7,
+        };
+        let mut simulation = Simulation::new(Network::Mainnet, config);
+        let now = Utc::now();
+        simulation.step(now);
+        let first = simulation
+            .graph_nodes(now)
+            .into_iter()
+            .map(|node| node.node_id)
+            .collect::<HashSet<_>>();
+        simulation.step(now);
+
+        let nodes = simulation
+            .graph_nodes(now)
+            .into_iter()
+            .map(|node| node.node_id)
+            .collect::<HashSet<_>>();
+        let channels = simulation.graph_channels(now);
+        assert_eq!((nodes.len(), channels.len()), (50, 120));
+        assert_eq!(nodes.difference(&first).count(), 5);
+        assert!(channels.iter().all(|channel| {
+            channel.node1 != channel.node2
+                && nodes.contains(&channel.node1)
+                && nodes.contains(&channel.node2)
+        }));
+    }
+}
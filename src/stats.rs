@@ -0,0 +1,313 @@
+//! Streaming statistics over channel amounts, used by the daily summary and `/analysis_hourly`.
+//!
+//! Values are kept and sorted exactly up to `PERCENTILE_EXACT_LIMIT` per series, larger series
+//! move into a t-digest with `TDIGEST_COMPRESSION` so medians and percentiles stay within a
+//! fixed amount of memory. Count, min, max and sum are always exact.
+
+use std::sync::LazyLock;
+
+use crate::config;
+
+/// Values per series kept exactly before switching to a t-digest.
+static PERCENTILE_EXACT_LIMIT: LazyLock<usize> =
+    LazyLock::new(|| config::parse_var("PERCENTILE_EXACT_LIMIT").unwrap_or(10_000));
+
+/// Higher keeps more centroids and is more accurate, about `compression` centroids are kept.
+static TDIGEST_COMPRESSION: LazyLock<f64> = LazyLock::new(|| {
+    config::parse_var("TDIGEST_COMPRESSION")
+        .filter(|compression: &f64| *compression >= 10.0)
+        .unwrap_or(200.0)
+});
+
+/// Buckets of [`magnitude_bucket`], the last one is open ended.
+pub const MAGNITUDE_BUCKETS: usize = 8;
+
+/// Decimal magnitude of `value` in thousands, `10^ik` up to `10^(i+1)k` falls in bucket `i`
+/// and anything below `1k` in bucket 0, the buckets of `/channel_capacity_distribution`.
+pub fn magnitude_bucket(value: u128) -> usize {
+    let thousands = value / 1000;
+    if thousands == 0 {
+        0
+    } else {
+        (thousands.ilog10() as usize).min(MAGNITUDE_BUCKETS - 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// Merging t-digest, values are buffered and merged into centroids whose size shrinks towards
+/// both tails, so extreme quantiles stay accurate.
+#[derive(Debug, Clone)]
+pub struct TDigest {
+    compression: f64,
+    centroids: Vec<Centroid>,
+    buffer: Vec<f64>,
+}
+
+impl TDigest {
+    pub fn new(compression: f64) -> Self {
+        TDigest {
+            compression,
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        self.buffer.push(value);
+        if self.buffer.len() as f64 >= self.compression * 5.0 {
+            self.compress();
+        }
+    }
+
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.extend(
+            self.buffer
+                .drain(..)
+                .map(|mean| Centroid { mean, weight: 1.0 }),
+        );
+        all.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+
+        let mut merged = Vec::with_capacity(self.compression as usize * 2);
+        let mut before = 0.0;
+        let mut current = all[0];
+        for next in all.into_iter().skip(1) {
+            let weight = current.weight + next.weight;
+            let q = (before + weight / 2.0) / total;
+            let max_weight = (4.0 * total * q * (1.0 - q) / self.compression).max(1.0);
+            if weight <= max_weight {
+                current.mean += (next.mean - current.mean) * next.weight / weight;
+                current.weight = weight;
+            } else {
+                before += current.weight;
+                merged.push(current);
+                current = next;
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    /// Centroids in ascending order as `(mean, weight)`.
+    fn centroids(&mut self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.compress();
+        self.centroids.iter().map(|c| (c.mean, c.weight))
+    }
+
+    /// Value at quantile `q`, interpolated between centroid centers.
+    pub fn quantile(&mut self, q: f64) -> f64 {
+        self.compress();
+        let Some(first) = self.centroids.first() else {
+            return 0.0;
+        };
+        let total: f64 = self.centroids.iter().map(|c| c.weight).sum();
+        let target = q.clamp(0.0, 1.0) * total;
+        let (mut previous_center, mut previous_mean) = (first.weight / 2.0, first.mean);
+        if target <= previous_center {
+            return first.mean;
+        }
+        let mut cumulative = first.weight;
+        for c in &self.centroids[1..] {
+            let center = cumulative + c.weight / 2.0;
+            if target <= center {
+                let fraction = (target - previous_center) / (center - previous_center);
+                return previous_mean + (c.mean - previous_mean) * fraction;
+            }
+            cumulative += c.weight;
+            (previous_center, previous_mean) = (center, c.mean);
+        }
+        previous_mean
+    }
+}
+
+/// Count, min, max, sum and quantiles of one series of non negative amounts.
+#[derive(Debug, Clone, Default)]
+pub struct ValueStats {
+    pub count: u64,
+    pub min: u128,
+    pub max: u128,
+    pub sum: u128,
+    exact: Vec<u128>,
+    sorted: bool,
+    digest: Option<TDigest>,
+}
+
+impl ValueStats {
+    pub fn push(&mut self, value: u128) {
+        if self.count == 0 {
+            (self.min, self.max) = (value, value);
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        self.sum += value;
+        match &mut self.digest {
+            Some(digest) => digest.push(value as f64),
+            None if self.exact.len() < *PERCENTILE_EXACT_LIMIT => {
+                self.exact.push(value);
+                self.sorted = false;
+            }
+            None => {
+                let mut digest = TDigest::new(*TDIGEST_COMPRESSION);
+                for value in self.exact.drain(..) {
+                    digest.push(value as f64);
+                }
+                digest.push(value as f64);
+                self.exact.shrink_to_fit();
+                self.digest = Some(digest);
+            }
+        }
+    }
+
+    pub fn average(&self) -> u128 {
+        self.sum.checked_div(self.count as u128).unwrap_or(0)
+    }
+
+    fn sorted(&mut self) -> &[u128] {
+        if !self.sorted {
+            self.exact.sort_unstable();
+            self.sorted = true;
+        }
+        &self.exact
+    }
+
+    /// Median, the mean of the two middle values for an even exact series.
+    pub fn median(&mut self) -> u128 {
+        if let Some(digest) = &mut self.digest {
+            return (digest.quantile(0.5) as u128).clamp(self.min, self.max);
+        }
+        let values = self.sorted();
+        match values.len() {
+            0 => 0,
+            len if len.is_multiple_of(2) => (values[len / 2 - 1] + values[len / 2]) / 2,
+            len => values[len / 2],
+        }
+    }
+
+    /// Nearest rank percentile, `q` in `[0, 1]`.
+    pub fn percentile(&mut self, q: f64) -> u128 {
+        if let Some(digest) = &mut self.digest {
+            return (digest.quantile(q) as u128).clamp(self.min, self.max);
+        }
+        let values = self.sorted();
+        if values.is_empty() {
+            return 0;
+        }
+        let rank = (q * values.len() as f64).ceil() as usize;
+        values[rank.clamp(1, values.len()) - 1]
+    }
+
+    /// Gini coefficient from the Lorenz curve, 0 when every value is the same and
+    /// approaching 1 when one value holds everything.
+    pub fn gini(&mut self) -> f64 {
+        let groups: Vec<(f64, f64)> = match &mut self.digest {
+            Some(digest) => digest.centroids().collect(),
+            None => self.sorted().iter().map(|v| (*v as f64, 1.0)).collect(),
+        };
+        let total_weight: f64 = groups.iter().map(|(_, weight)| weight).sum();
+        let total: f64 = groups.iter().map(|(mean, weight)| mean * weight).sum();
+        if total == 0.0 {
+            return 0.0;
+        }
+        let mut share = 0.0;
+        let mut area = 0.0;
+        for (mean, weight) in groups {
+            let next = share + mean * weight / total;
+            area += weight / total_weight * (share + next);
+            share = next;
+        }
+        1.0 - area
+    }
+
+    /// Counts per bucket of `bucket`, approximated from the centroids once in a t-digest.
+    pub fn histogram(&mut self, buckets: usize, bucket: impl Fn(u128) -> usize) -> Vec<u64> {
+        let groups: Vec<(f64, f64)> = match &mut self.digest {
+            Some(digest) => digest.centroids().collect(),
+            None => self.exact.iter().map(|v| (*v as f64, 1.0)).collect(),
+        };
+        let mut counts = vec![0.0; buckets];
+        for (mean, weight) in groups {
+            counts[bucket(mean as u128).min(buckets - 1)] += weight;
+        }
+        counts
+            .into_iter()
+            .map(|count| count.round() as u64)
+            .collect()
+    }
+}
+
+/// Asset amounts and ckb capacities of the channels of one asset.
+#[derive(Debug, Clone, Default)]
+pub struct ChannelStats {
+    pub assets: ValueStats,
+    pub capacities: ValueStats,
+}
+
+impl ChannelStats {
+    pub fn push(&mut self, asset: u128, capacity: u64) {
+        self.assets.push(asset);
+        self.capacities.push(capacity as u128);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MAGNITUDE_BUCKETS, TDigest, ValueStats, magnitude_bucket};
+
+    fn stats(values: &[u128]) -> ValueStats {
+        let mut stats = ValueStats::default();
+        values.iter().for_each(|v| stats.push(*v));
+        stats
+    }
+
+    #[test]
+    fn exact_series() {
+        let mut s = stats(&[100, 10, 90, 20, 80, 30, 70, 40, 60, 50]);
+        assert_eq!((s.count, s.min, s.max, s.sum), (10, 10, 100, 550));
+        assert_eq!(s.average(), 55);
+        assert_eq!(s.median(), 55);
+        assert_eq!(s.percentile(0.1), 10);
+        assert_eq!(s.percentile(0.25), 30);
+        assert_eq!(s.percentile(0.9), 90);
+        assert_eq!(stats(&[7]).percentile(0.75), 7);
+    }
+
+    #[test]
+    fn gini_of_equal_and_concentrated_values() {
+        assert!(stats(&[5, 5, 5, 5]).gini().abs() < 1e-9);
+        assert!((stats(&[0, 0, 0, 100]).gini() - 0.75).abs() < 1e-9);
+        assert_eq!(stats(&[]).gini(), 0.0);
+    }
+
+    #[test]
+    fn digest_quantiles_are_close() {
+        let mut digest = TDigest::new(200.0);
+        for v in 1..=100_000 {
+            digest.push(v as f64);
+        }
+        for q in [0.01, 0.1, 0.5, 0.9, 0.99] {
+            let expected = q * 100_000.0;
+            assert!((digest.quantile(q) - expected).abs() / expected < 0.01);
+        }
+    }
+
+    #[test]
+    fn histogram_by_magnitude() {
+        assert_eq!(magnitude_bucket(999), 0);
+        assert_eq!(magnitude_bucket(1000), 0);
+        assert_eq!(magnitude_bucket(10_000), 1);
+        assert_eq!(magnitude_bucket(u128::MAX), MAGNITUDE_BUCKETS - 1);
+        let counts = stats(&[5, 1500, 20_000, 30_000, 1_000_000]).histogram(4, magnitude_bucket);
+        assert_eq!(counts, vec![2, 2, 0, 1]);
+    }
+}
@@ -0,0 +1,186 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use chrono::{DateTime, Utc};
+use ckb_jsonrpc_types::JsonBytes;
+
+use crate::{
+    Network,
+    error::skip_malformed,
+    http_server::{ListNodesHourlyParams, Page},
+    pg_read::{
+        ChannelInfo, HotSnapshot, HourlyChannelInfoDBRead, HourlyNodeInfo, HourlyNodeInfoDBRead,
+    },
+    pg_write::{ChannelInfoDBSchema, NodeInfoDBSchema},
+    storage::{NodeLocation, Paged, SnapshotBatch, Storage},
+};
+
+type Nodes = Vec<(DateTime<Utc>, HourlyNodeInfo)>;
+type Channels = Vec<(DateTime<Utc>, ChannelInfo)>;
+
+/// Keeps the latest row of every node and channel in memory, for tests of the HTTP layer.
+///
+/// There is no on-chain state, the capacity of a CKB channel is taken from its funding
+/// amount and UDT channels report zero.
+#[derive(Default)]
+pub(crate) struct MemoryStorage {
+    nets: RwLock<HashMap<Network, (Nodes, Channels)>>,
+}
+
+impl MemoryStorage {
+    fn snapshot(&self, net: Network) -> HotSnapshot {
+        let nets = self.nets.read().unwrap();
+        let (nodes, channels) = nets.get(&net).cloned().unwrap_or_default();
+        HotSnapshot::new(nodes, channels)
+    }
+}
+
+fn node_row(
+    node: &NodeInfoDBSchema,
+    time: &DateTime<Utc>,
+    channels: &[ChannelInfoDBSchema],
+) -> HourlyNodeInfoDBRead {
+    let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_string());
+    HourlyNodeInfoDBRead {
+        node_id: node.node_id.clone(),
+        last_seen_hour: *time,
+        node_name: node.node_name.clone(),
+        addresses: node.addresses.clone(),
+        announce_timestamp: node.announce_timestamp,
+        chain_hash: node.chain_hash.clone(),
+        auto_accept_min_ckb_funding_amount: node.auto_accept_min_ckb_funding_amount.clone(),
+        country_or_region: non_empty(&node.country_or_region),
+        city: non_empty(&node.city),
+        region: non_empty(&node.region),
+        loc: non_empty(&node.loc),
+        channel_count: channels
+            .iter()
+            .filter(|c| c.node1 == node.node_id || c.node2 == node.node_id)
+            .count() as i64,
+        total_capacity: None,
+        udt_count: None,
+    }
+}
+
+fn channel_row(channel: &ChannelInfoDBSchema, time: &DateTime<Utc>) -> HourlyChannelInfoDBRead {
+    let capacity = match channel.udt_type_script {
+        None => channel.capacity[channel.capacity.len() - 16..].to_string(),
+        Some(_) => "0".repeat(16),
+    };
+    HourlyChannelInfoDBRead {
+        channel_outpoint: channel.channel_outpoint.clone(),
+        last_seen_hour: *time,
+        node1: channel.node1.clone(),
+        node2: channel.node2.clone(),
+        capacity,
+        asset: channel.capacity.clone(),
+        chain_hash: channel.chain_hash.clone(),
+        created_timestamp: channel.created_timestamp,
+        update_of_node1_timestamp: channel.update_of_node1_timestamp,
+        update_of_node1_enabled: channel.update_of_node1_enabled,
+        update_of_node1_outbound_liquidity: channel.update_of_node1_outbound_liquidity.clone(),
+        update_of_node1_tlc_expiry_delta: channel.update_of_node1_tlc_expiry_delta.clone(),
+        update_of_node1_tlc_minimum_value: channel.update_of_node1_tlc_minimum_value.clone(),
+        update_of_node1_fee_rate: channel.update_of_node1_fee_rate.clone(),
+        update_of_node2_timestamp: channel.update_of_node2_timestamp,
+        update_of_node2_enabled: channel.update_of_node2_enabled,
+        update_of_node2_outbound_liquidity: channel.update_of_node2_outbound_liquidity.clone(),
+        update_of_node2_tlc_expiry_delta: channel.update_of_node2_tlc_expiry_delta.clone(),
+        update_of_node2_tlc_minimum_value: channel.update_of_node2_tlc_minimum_value.clone(),
+        update_of_node2_fee_rate: channel.update_of_node2_fee_rate.clone(),
+        udt_name: channel.udt_type_script.is_none().then(|| "ckb".to_string()),
+        udt_code_hash: None,
+        udt_hash_type: None,
+        udt_args: None,
+        udt_auto_accept_amount: None,
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for MemoryStorage {
+    async fn insert_batch(&self, batch: SnapshotBatch<'_>) -> Result<(), sqlx::Error> {
+        let mut nets = self.nets.write().unwrap();
+        let (nodes, channels) = nets.entry(batch.net).or_default();
+        for node in batch.nodes {
+            let Some(node) = skip_malformed::<_, HourlyNodeInfo>(
+                "node",
+                node_row(node, batch.time, batch.channels),
+            ) else {
+                continue;
+            };
+            nodes.retain(|(_, n)| n.node_id != node.node_id);
+            nodes.push((*batch.time, node));
+        }
+        for channel in batch.channels {
+            let Some(channel) =
+                skip_malformed::<_, ChannelInfo>("channel", channel_row(channel, batch.time))
+            else {
+                continue;
+            };
+            channels.retain(|(_, c)| c.channel_outpoint != channel.channel_outpoint);
+            channels.push((*batch.time, channel));
+        }
+        Ok(())
+    }
+
+    async fn nodes_hourly(
+        &self,
+        params: ListNodesHourlyParams,
+    ) -> Result<Paged<HourlyNodeInfo>, sqlx::Error> {
+        Ok(self.snapshot(params.net).nodes_page(&params))
+    }
+
+    async fn channels_hourly(&self, params: Page) -> Result<Paged<ChannelInfo>, sqlx::Error> {
+        Ok(self.snapshot(params.net).channels_page(&params))
+    }
+
+    async fn node_info(
+        &self,
+        node_id: JsonBytes,
+        net: Network,
+    ) -> Result<Option<HourlyNodeInfo>, sqlx::Error> {
+        let node_id = format!("0x{}", faster_hex::hex_string(node_id.as_bytes()));
+        let nets = self.nets.read().unwrap();
+        Ok(nets.get(&net).and_then(|(nodes, _)| {
+            nodes
+                .iter()
+                .find(|(_, node)| node.node_id == node_id)
+                .map(|(_, node)| node.clone())
+        }))
+    }
+
+    async fn channel_info(
+        &self,
+        outpoint: JsonBytes,
+        net: Network,
+    ) -> Result<Option<ChannelInfo>, sqlx::Error> {
+        let outpoint = format!("0x{}", faster_hex::hex_string(outpoint.as_bytes()));
+        let nets = self.nets.read().unwrap();
+        Ok(nets.get(&net).and_then(|(_, channels)| {
+            channels
+                .iter()
+                .find(|(_, channel)| channel.channel_outpoint == outpoint)
+                .map(|(_, channel)| channel.clone())
+        }))
+    }
+
+    async fn update_node_location(
+        &self,
+        net: Network,
+        node_id: &str,
+        time: &DateTime<Utc>,
+        location: NodeLocation<'_>,
+    ) -> Result<(), sqlx::Error> {
+        let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_string());
+        let mut nets = self.nets.write().unwrap();
+        let nodes = nets.entry(net).or_default().0.iter_mut();
+        for (_, node) in nodes.filter(|(t, node)| {
+            t == time && node.node_id.trim_start_matches("0x") == node_id.trim_start_matches("0x")
+        }) {
+            node.country_or_region = non_empty(location.country_or_region);
+            node.city = non_empty(location.city);
+            node.region = non_empty(location.region);
+            node.loc = non_empty(location.loc);
+        }
+        Ok(())
+    }
+}
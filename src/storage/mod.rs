@@ -0,0 +1,103 @@
+//! Backend abstraction over the snapshot write path and the hot read paths of the API.
+//!
+//! Postgres is the default backend, everything not covered by [`Storage`] still goes to
+//! `pg_read` / `pg_write` directly. SQLite backs the `--lite` mode.
+
+#[cfg(test)]
+mod memory;
+mod postgres;
+mod sqlite;
+
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+use ckb_jsonrpc_types::JsonBytes;
+
+use crate::{
+    Network,
+    http_server::{ListNodesHourlyParams, Page},
+    pg_read::{ChannelInfo, HourlyNodeInfo},
+    pg_write::{ChannelInfoDBSchema, NodeInfoDBSchema, UdtInfos, UdtNodeRelation, UdtdepRelation},
+};
+
+#[cfg(test)]
+pub(crate) use memory::MemoryStorage;
+pub(crate) use postgres::PgStorage;
+pub(crate) use sqlite::SqliteStorage;
+
+/// One collection cycle of a network, already converted to db schemas.
+pub(crate) struct SnapshotBatch<'a> {
+    pub(crate) net: Network,
+    pub(crate) time: &'a DateTime<Utc>,
+    pub(crate) udt_infos: &'a [UdtInfos],
+    pub(crate) udt_dep_relations: &'a [UdtdepRelation],
+    pub(crate) udt_node_relations: &'a [UdtNodeRelation],
+    pub(crate) nodes: &'a [NodeInfoDBSchema],
+    pub(crate) channels: &'a [ChannelInfoDBSchema],
+}
+
+/// Location of a node looked up after its row was inserted.
+pub(crate) struct NodeLocation<'a> {
+    pub(crate) country_or_region: &'a str,
+    pub(crate) city: &'a str,
+    pub(crate) region: &'a str,
+    pub(crate) loc: &'a str,
+}
+
+/// Paged list result: items, next page and total count.
+pub(crate) type Paged<T> = (Vec<T>, usize, usize);
+
+#[async_trait::async_trait]
+pub(crate) trait Storage: Send + Sync {
+    /// Commit a whole snapshot atomically.
+    async fn insert_batch(&self, batch: SnapshotBatch<'_>) -> Result<(), sqlx::Error>;
+
+    async fn nodes_hourly(
+        &self,
+        params: ListNodesHourlyParams,
+    ) -> Result<Paged<HourlyNodeInfo>, sqlx::Error>;
+
+    async fn channels_hourly(&self, params: Page) -> Result<Paged<ChannelInfo>, sqlx::Error>;
+
+    async fn node_info(
+        &self,
+        node_id: JsonBytes,
+        net: Network,
+    ) -> Result<Option<HourlyNodeInfo>, sqlx::Error>;
+
+    async fn channel_info(
+        &self,
+        outpoint: JsonBytes,
+        net: Network,
+    ) -> Result<Option<ChannelInfo>, sqlx::Error>;
+
+    /// Set the location of the row of `node_id` committed at `time`.
+    async fn update_node_location(
+        &self,
+        net: Network,
+        node_id: &str,
+        time: &DateTime<Utc>,
+        location: NodeLocation<'_>,
+    ) -> Result<(), sqlx::Error>;
+}
+
+static STORAGE: OnceLock<Box<dyn Storage>> = OnceLock::new();
+
+/// The installed backend, Postgres unless another one was installed first.
+pub(crate) fn storage() -> &'static dyn Storage {
+    STORAGE.get_or_init(|| Box::new(PgStorage)).as_ref()
+}
+
+/// Install a backend, must happen before the first [`storage`] call to take effect.
+pub(crate) fn install(storage: Box<dyn Storage>) -> bool {
+    STORAGE.set(storage).is_ok()
+}
+
+/// Switch to the SQLite backend of the lite mode, creating the database when missing.
+pub async fn use_sqlite(url: &str) -> Result<(), sqlx::Error> {
+    let storage = SqliteStorage::connect(url).await?;
+    if !install(Box::new(storage)) {
+        log::warn!("Storage backend already initialized, ignoring {}", url);
+    }
+    Ok(())
+}
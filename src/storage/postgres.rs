@@ -0,0 +1,90 @@
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use ckb_jsonrpc_types::JsonBytes;
+
+use crate::{
+    Network, get_pg_pool,
+    http_server::{ListNodesHourlyParams, Page},
+    metrics,
+    pg_read::{
+        ChannelInfo, HourlyNodeInfo, query_channel_info, query_node_info, read_channels_hourly,
+        read_nodes_hourly,
+    },
+    pg_write::insert_batch,
+    storage::{NodeLocation, Paged, SnapshotBatch, Storage},
+};
+
+/// The TimescaleDB backend behind `DATABASE_URL`.
+pub(crate) struct PgStorage;
+
+#[async_trait::async_trait]
+impl Storage for PgStorage {
+    async fn insert_batch(&self, batch: SnapshotBatch<'_>) -> Result<(), sqlx::Error> {
+        let started = Instant::now();
+        insert_batch(
+            get_pg_pool(),
+            batch.udt_infos,
+            batch.udt_dep_relations,
+            batch.udt_node_relations,
+            batch.nodes,
+            batch.channels,
+            batch.time,
+            batch.net,
+        )
+        .await?;
+        metrics::observe_insert_batch(&batch, started.elapsed());
+        Ok(())
+    }
+
+    async fn nodes_hourly(
+        &self,
+        params: ListNodesHourlyParams,
+    ) -> Result<Paged<HourlyNodeInfo>, sqlx::Error> {
+        read_nodes_hourly(get_pg_pool(), params).await
+    }
+
+    async fn channels_hourly(&self, params: Page) -> Result<Paged<ChannelInfo>, sqlx::Error> {
+        read_channels_hourly(get_pg_pool(), params).await
+    }
+
+    async fn node_info(
+        &self,
+        node_id: JsonBytes,
+        net: Network,
+    ) -> Result<Option<HourlyNodeInfo>, sqlx::Error> {
+        query_node_info(get_pg_pool(), node_id, net).await
+    }
+
+    async fn channel_info(
+        &self,
+        outpoint: JsonBytes,
+        net: Network,
+    ) -> Result<Option<ChannelInfo>, sqlx::Error> {
+        query_channel_info(get_pg_pool(), outpoint, net).await
+    }
+
+    async fn update_node_location(
+        &self,
+        net: Network,
+        node_id: &str,
+        time: &DateTime<Utc>,
+        location: NodeLocation<'_>,
+    ) -> Result<(), sqlx::Error> {
+        let sql = format!(
+            "UPDATE {} SET country_or_region = $1, city = $2, region = $3, loc = $4
+            WHERE node_id = $5 AND time = $6",
+            net.node_infos()
+        );
+        sqlx::query(&sql)
+            .bind(location.country_or_region)
+            .bind(location.city)
+            .bind(location.region)
+            .bind(location.loc)
+            .bind(node_id)
+            .bind(time)
+            .execute(get_pg_pool())
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,354 @@
+use std::{str::FromStr, time::Instant};
+
+use chrono::{DateTime, Utc};
+use ckb_jsonrpc_types::JsonBytes;
+use sqlx::{
+    Pool, QueryBuilder, Sqlite,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+};
+
+use crate::{
+    Network,
+    error::skip_malformed,
+    http_server::{ListNodesHourlyParams, Page},
+    metrics,
+    pg_read::{
+        ChannelInfo, HotSnapshot, HourlyChannelInfoDBRead, HourlyNodeInfo, HourlyNodeInfoDBRead,
+        online_since,
+    },
+    pg_write::{
+        CHANNEL_INFO_INSERT_SQL, NODE_INFO_INSERT_SQL, UDT_DEP_RELATION_INSERT_SQL,
+        UDT_INFO_INSERT_SQL, UDT_NODE_RELATION_INSERT_SQL,
+    },
+    storage::{NodeLocation, Paged, SnapshotBatch, Storage},
+};
+
+const SQLITE_SQL: &str = include_str!("../../db_schema/sqlite.sql");
+
+/// SQLite limits a statement to 32766 bound parameters.
+const ROWS_PER_INSERT: usize = 1000;
+
+/// Latest row of every node, `?1` bounds the node rows and `?2` the channels counted.
+const LATEST_NODES_SQL: &str = "
+    SELECT
+        n.node_id,
+        n.time AS last_seen_hour,
+        n.node_name,
+        n.addresses,
+        n.announce_timestamp,
+        n.chain_hash,
+        n.auto_accept_min_ckb_funding_amount,
+        n.country_or_region,
+        n.city,
+        n.region,
+        n.loc,
+        (SELECT COUNT(DISTINCT c.channel_outpoint) FROM {channels} c
+            WHERE c.time >= ?2 AND (c.node1 = n.node_id OR c.node2 = n.node_id)) AS channel_count
+    FROM {nodes} n
+    WHERE n.time >= ?1
+    AND n.time = (SELECT MAX(m.time) FROM {nodes} m WHERE m.node_id = n.node_id)";
+
+/// Latest row of every channel since `?1`. There are no channel states in lite mode,
+/// the capacity of a CKB channel is its funding amount and UDT channels report zero.
+const LATEST_CHANNELS_SQL: &str = "
+    SELECT
+        c.channel_outpoint,
+        c.time AS last_seen_hour,
+        c.node1,
+        c.node2,
+        CASE WHEN c.udt_type_script IS NULL THEN substr(c.capacity, 17)
+            ELSE '0000000000000000' END AS capacity,
+        c.capacity AS asset,
+        c.chain_hash,
+        c.created_timestamp,
+        c.update_of_node1_timestamp,
+        c.update_of_node1_enabled,
+        c.update_of_node1_outbound_liquidity,
+        c.update_of_node1_tlc_expiry_delta,
+        c.update_of_node1_tlc_minimum_value,
+        c.update_of_node1_fee_rate,
+        c.update_of_node2_timestamp,
+        c.update_of_node2_enabled,
+        c.update_of_node2_outbound_liquidity,
+        c.update_of_node2_tlc_expiry_delta,
+        c.update_of_node2_tlc_minimum_value,
+        c.update_of_node2_fee_rate,
+        COALESCE(u.name, 'ckb') AS udt_name,
+        u.code_hash AS udt_code_hash,
+        u.hash_type AS udt_hash_type,
+        u.args AS udt_args,
+        u.auto_accept_amount AS udt_auto_accept_amount
+    FROM {channels} c
+    LEFT JOIN {udt_infos} u ON u.id = c.udt_type_script
+    WHERE c.time >= ?1
+    AND c.time = (SELECT MAX(m.time) FROM {channels} m WHERE m.channel_outpoint = c.channel_outpoint)";
+
+fn latest_nodes_sql(net: Network) -> String {
+    LATEST_NODES_SQL
+        .replace("{nodes}", net.node_infos())
+        .replace("{channels}", net.channel_infos())
+}
+
+fn latest_channels_sql(net: Network) -> String {
+    LATEST_CHANNELS_SQL
+        .replace("{channels}", net.channel_infos())
+        .replace("{udt_infos}", net.udt_infos())
+}
+
+/// Backend of the `--lite` mode, no TimescaleDB features and aggregates computed at query time.
+pub(crate) struct SqliteStorage {
+    pool: Pool<Sqlite>,
+}
+
+impl SqliteStorage {
+    /// Open (or create) the database at `url` and apply `db_schema/sqlite.sql`.
+    pub(crate) async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .foreign_keys(false);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        sqlx::raw_sql(SQLITE_SQL).execute(&pool).await?;
+        Ok(SqliteStorage { pool })
+    }
+
+    async fn online_nodes(&self, net: Network) -> Result<Vec<HourlyNodeInfoDBRead>, sqlx::Error> {
+        let since = online_since();
+        sqlx::query_as(&latest_nodes_sql(net))
+            .bind(since)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    async fn online_channels(
+        &self,
+        net: Network,
+    ) -> Result<Vec<HourlyChannelInfoDBRead>, sqlx::Error> {
+        sqlx::query_as(&latest_channels_sql(net))
+            .bind(online_since())
+            .fetch_all(&self.pool)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl Storage for SqliteStorage {
+    async fn insert_batch(&self, batch: SnapshotBatch<'_>) -> Result<(), sqlx::Error> {
+        let net = batch.net;
+        let started = Instant::now();
+        let mut tx = self.pool.begin().await?;
+        // udt ids come from the in-process cache, which is not restored from SQLite,
+        // so the latest process wins on conflicts
+        for udts in batch.udt_infos.chunks(ROWS_PER_INSERT) {
+            let sql = UDT_INFO_INSERT_SQL
+                .replace("insert into", "insert or replace into")
+                .replace("{}", net.udt_infos());
+            let mut query_builder: QueryBuilder<'_, Sqlite> = QueryBuilder::new(sql);
+            query_builder.push_values(udts, |mut b, udt| {
+                b.push_bind(udt.id)
+                    .push_bind(&udt.name)
+                    .push_bind(&udt.code_hash)
+                    .push_bind(&udt.hash_type)
+                    .push_bind(&udt.args)
+                    .push_bind(&udt.auto_accept_amount);
+            });
+            query_builder.build().execute(&mut *tx).await?;
+        }
+        for relations in batch.udt_dep_relations.chunks(ROWS_PER_INSERT) {
+            let sql = UDT_DEP_RELATION_INSERT_SQL.replace("{}", net.udt_dep());
+            let mut query_builder: QueryBuilder<'_, Sqlite> = QueryBuilder::new(sql);
+            query_builder.push_values(relations, |mut b, relation| {
+                b.push_bind(&relation.outpoint_tx_hash)
+                    .push_bind(&relation.outpoint_index)
+                    .push_bind(&relation.dep_type)
+                    .push_bind(&relation.code_hash)
+                    .push_bind(&relation.hash_type)
+                    .push_bind(&relation.args)
+                    .push_bind(relation.udt_info_id);
+            });
+            query_builder.build().execute(&mut *tx).await?;
+        }
+        // lite mode keeps every relation, announced ones need no confirmation
+        let new_relations = batch
+            .udt_node_relations
+            .iter()
+            .filter(|relation| relation.new)
+            .collect::<Vec<_>>();
+        for relations in new_relations.chunks(ROWS_PER_INSERT) {
+            let sql = UDT_NODE_RELATION_INSERT_SQL
+                .replace("insert into", "insert or ignore into")
+                .replace("{}", net.node_udt_relations());
+            let mut query_builder: QueryBuilder<'_, Sqlite> = QueryBuilder::new(sql);
+            query_builder.push_values(relations, |mut b, relation| {
+                b.push_bind(&relation.node_id)
+                    .push_bind(relation.udt_info_id);
+            });
+            query_builder.build().execute(&mut *tx).await?;
+        }
+        for nodes in batch.nodes.chunks(ROWS_PER_INSERT) {
+            let sql = NODE_INFO_INSERT_SQL.replace("{}", net.node_infos());
+            let mut query_builder: QueryBuilder<'_, Sqlite> = QueryBuilder::new(sql);
+            query_builder.push_values(nodes, |mut b, node| {
+                b.push_bind(batch.time)
+                    .push_bind(&node.node_name)
+                    .push_bind(&node.addresses)
+                    .push_bind(&node.node_id)
+                    .push_bind(node.announce_timestamp)
+                    .push_bind(&node.chain_hash)
+                    .push_bind(&node.auto_accept_min_ckb_funding_amount)
+                    .push_bind(&node.country_or_region)
+                    .push_bind(&node.city)
+                    .push_bind(&node.region)
+                    .push_bind(&node.loc);
+            });
+            query_builder.build().execute(&mut *tx).await?;
+        }
+        for channels in batch.channels.chunks(ROWS_PER_INSERT) {
+            let sql = CHANNEL_INFO_INSERT_SQL.replace("{}", net.channel_infos());
+            let mut query_builder: QueryBuilder<'_, Sqlite> = QueryBuilder::new(sql);
+            query_builder.push_values(channels, |mut b, channel| {
+                b.push_bind(batch.time)
+                    .push_bind(&channel.channel_outpoint)
+                    .push_bind(&channel.node1)
+                    .push_bind(&channel.node2)
+                    .push_bind(&channel.capacity)
+                    .push_bind(&channel.chain_hash)
+                    .push_bind(channel.udt_type_script)
+                    .push_bind(channel.created_timestamp)
+                    .push_bind(channel.update_of_node1_timestamp)
+                    .push_bind(channel.update_of_node1_enabled)
+                    .push_bind(&channel.update_of_node1_outbound_liquidity)
+                    .push_bind(&channel.update_of_node1_tlc_expiry_delta)
+                    .push_bind(&channel.update_of_node1_tlc_minimum_value)
+                    .push_bind(&channel.update_of_node1_fee_rate)
+                    .push_bind(channel.update_of_node2_timestamp)
+                    .push_bind(channel.update_of_node2_enabled)
+                    .push_bind(&channel.update_of_node2_outbound_liquidity)
+                    .push_bind(&channel.update_of_node2_tlc_expiry_delta)
+                    .push_bind(&channel.update_of_node2_tlc_minimum_value)
+                    .push_bind(&channel.update_of_node2_fee_rate);
+            });
+            query_builder.build().execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        metrics::observe_insert_batch(&batch, started.elapsed());
+        Ok(())
+    }
+
+    async fn nodes_hourly(
+        &self,
+        params: ListNodesHourlyParams,
+    ) -> Result<Paged<HourlyNodeInfo>, sqlx::Error> {
+        // lite databases are small, page with the same code as the hot snapshot
+        let nodes = self
+            .online_nodes(params.net)
+            .await?
+            .into_iter()
+            .filter_map(|node| {
+                let hour = node.last_seen_hour;
+                skip_malformed("node", node).map(|node: HourlyNodeInfo| (hour, node))
+            })
+            .collect();
+        Ok(HotSnapshot::new(nodes, Vec::new()).nodes_page(&params))
+    }
+
+    async fn channels_hourly(&self, params: Page) -> Result<Paged<ChannelInfo>, sqlx::Error> {
+        let channels = self
+            .online_channels(params.net)
+            .await?
+            .into_iter()
+            .filter_map(|channel| {
+                let hour = channel.last_seen_hour;
+                skip_malformed("channel", channel).map(|channel: ChannelInfo| (hour, channel))
+            })
+            .collect();
+        // nodes are only needed to resolve the location filters
+        let nodes = if params.has_location_filter() {
+            self.online_nodes(params.net)
+                .await?
+                .into_iter()
+                .filter_map(|node| {
+                    let hour = node.last_seen_hour;
+                    skip_malformed("node", node).map(|node: HourlyNodeInfo| (hour, node))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(HotSnapshot::new(nodes, channels).channels_page(&params))
+    }
+
+    async fn node_info(
+        &self,
+        node_id: JsonBytes,
+        net: Network,
+    ) -> Result<Option<HourlyNodeInfo>, sqlx::Error> {
+        let sql = format!("{} AND n.node_id = ?3", latest_nodes_sql(net));
+        let node_id = faster_hex::hex_string(node_id.as_bytes());
+        let node: Option<HourlyNodeInfoDBRead> = sqlx::query_as(&sql)
+            .bind(DateTime::<Utc>::UNIX_EPOCH)
+            .bind(online_since())
+            .bind(&node_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(mut node) = node else {
+            return Ok(None);
+        };
+        // capacities are hex text, summed here instead of in sql
+        let total_capacity: u128 = self
+            .online_channels(net)
+            .await?
+            .iter()
+            .filter(|channel| channel.node1 == node_id || channel.node2 == node_id)
+            .filter_map(|channel| u128::from_str_radix(&channel.capacity, 16).ok())
+            .sum();
+        let udt_count: i64 = sqlx::query_scalar(&format!(
+            "select count(*) from {} where node_id = ?1",
+            net.node_udt_relations()
+        ))
+        .bind(&node_id)
+        .fetch_one(&self.pool)
+        .await?;
+        node.total_capacity = Some(total_capacity.to_string());
+        node.udt_count = Some(udt_count);
+        Ok(Some(HourlyNodeInfo::try_from(node)?))
+    }
+
+    async fn channel_info(
+        &self,
+        outpoint: JsonBytes,
+        net: Network,
+    ) -> Result<Option<ChannelInfo>, sqlx::Error> {
+        let sql = format!("{} AND c.channel_outpoint = ?2", latest_channels_sql(net));
+        let channel: Option<HourlyChannelInfoDBRead> = sqlx::query_as(&sql)
+            .bind(DateTime::<Utc>::UNIX_EPOCH)
+            .bind(faster_hex::hex_string(outpoint.as_bytes()))
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(channel.map(ChannelInfo::try_from).transpose()?)
+    }
+
+    async fn update_node_location(
+        &self,
+        net: Network,
+        node_id: &str,
+        time: &DateTime<Utc>,
+        location: NodeLocation<'_>,
+    ) -> Result<(), sqlx::Error> {
+        let sql = format!(
+            "update {} set country_or_region = ?1, city = ?2, region = ?3, loc = ?4
+            where node_id = ?5 and time = ?6",
+            net.node_infos()
+        );
+        sqlx::query(&sql)
+            .bind(location.country_or_region)
+            .bind(location.city)
+            .bind(location.region)
+            .bind(location.loc)
+            .bind(node_id)
+            .bind(time)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! Kaplan-Meier survival of channels per cohort month, how likely a channel opened in a given
+//! month is to still be open after N days.
+//!
+//! Channels still open are censored at their current age. Curves are recomputed by the daily
+//! job into `channel_survival`, one row per cohort and day a channel closed.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+
+use crate::Network;
+
+/// One step of a survival curve.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SurvivalPoint {
+    /// Days since the channel opened.
+    pub day: i32,
+    /// Channels of the cohort still open and observed at `day`.
+    pub at_risk: i32,
+    /// Channels of the cohort closing on `day`.
+    pub closed: i32,
+    /// Probability a channel is still open after `day` days.
+    pub survival: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CohortSurvival {
+    /// First day of the month the channels opened.
+    pub cohort: NaiveDate,
+    pub channels: i32,
+    pub points: Vec<SurvivalPoint>,
+}
+
+/// Kaplan-Meier estimate over `(lifetime in days, closed)`, starting with a point at day 0
+/// and stepping at every day a channel closed.
+pub fn kaplan_meier(lifetimes: &[(i32, bool)]) -> Vec<SurvivalPoint> {
+    let mut by_day = BTreeMap::<i32, (i32, i32)>::new(); // day -> (closed, censored)
+    for (day, closed) in lifetimes {
+        let entry = by_day.entry((*day).max(0)).or_default();
+        if *closed {
+            entry.0 += 1;
+        } else {
+            entry.1 += 1;
+        }
+    }
+    let mut at_risk = lifetimes.len() as i32;
+    let mut survival = 1.0;
+    let mut points = vec![SurvivalPoint {
+        day: 0,
+        at_risk,
+        closed: 0,
+        survival,
+    }];
+    for (day, (closed, censored)) in by_day {
+        if closed > 0 {
+            survival *= 1.0 - closed as f64 / at_risk as f64;
+            let point = SurvivalPoint {
+                day,
+                at_risk,
+                closed,
+                survival,
+            };
+            // closings on the opening day replace the starting point
+            if day == 0 {
+                points[0] = point;
+            } else {
+                points.push(point);
+            }
+        }
+        at_risk -= closed + censored;
+    }
+    points
+}
+
+/// Recompute the curves of every cohort of `net`.
+pub async fn compute(pool: &Pool<Postgres>, net: Network) -> Result<usize, sqlx::Error> {
+    // a channel is closed once its funding cell is spent, the first tx after funding
+    let sql = format!(
+        "SELECT date_trunc('month', s.create_time)::date AS cohort,
+            floor(extract(epoch FROM COALESCE(
+                CASE WHEN s.state = 'open' THEN NULL ELSE COALESCE(
+                    (SELECT min(t.timestamp) FROM {} t
+                    WHERE t.channel_outpoint = s.channel_outpoint
+                    AND t.tx_hash <> left(s.channel_outpoint, 64)),
+                    s.last_commit_time
+                ) END,
+                now()
+            ) - s.create_time) / 86400)::int AS days,
+            s.state <> 'open' AS closed
+        FROM {} s",
+        net.channel_txs(),
+        net.channel_states()
+    );
+    let mut cohorts = BTreeMap::<NaiveDate, Vec<(i32, bool)>>::new();
+    for row in sqlx::query(&sql).fetch_all(pool).await? {
+        cohorts
+            .entry(row.get("cohort"))
+            .or_default()
+            .push((row.get("days"), row.get("closed")));
+    }
+
+    let computed_at = Utc::now();
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM channel_survival WHERE net = $1")
+        .bind(net.name())
+        .execute(&mut *tx)
+        .await?;
+    for (cohort, lifetimes) in &cohorts {
+        for point in kaplan_meier(lifetimes) {
+            sqlx::query(
+                "INSERT INTO channel_survival
+                (net, cohort, day, at_risk, closed, survival, computed_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)",
+            )
+            .bind(net.name())
+            .bind(cohort)
+            .bind(point.day)
+            .bind(point.at_risk)
+            .bind(point.closed)
+            .bind(point.survival)
+            .bind(computed_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(cohorts.len())
+}
+
+/// Curves of `net` as of the last daily run, oldest cohort first.
+pub async fn load(
+    pool: &Pool<Postgres>,
+    net: Network,
+) -> Result<(Option<DateTime<Utc>>, Vec<CohortSurvival>), sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT cohort, day, at_risk, closed, survival, computed_at FROM channel_survival
+        WHERE net = $1 ORDER BY cohort, day",
+    )
+    .bind(net.name())
+    .fetch_all(pool)
+    .await?;
+    let computed_at = rows.first().map(|row| row.get("computed_at"));
+    let mut cohorts: Vec<CohortSurvival> = Vec::new();
+    for row in rows {
+        let cohort: NaiveDate = row.get("cohort");
+        let point = SurvivalPoint {
+            day: row.get("day"),
+            at_risk: row.get("at_risk"),
+            closed: row.get("closed"),
+            survival: row.get("survival"),
+        };
+        match cohorts.last_mut() {
+            Some(last) if last.cohort == cohort => last.points.push(point),
+            _ => cohorts.push(CohortSurvival {
+                cohort,
+                // the first point holds every channel of the cohort
+                channels: point.at_risk,
+                points: vec![point],
+            }),
+        }
+    }
+    Ok((computed_at, cohorts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::kaplan_meier;
+
+    #[test]
+    fn censored_channels_leave_the_risk_set_without_a_step() {
+        // closed on days 5, 5 and 20, still open at 10 and 30 days old
+        let points = kaplan_meier(&[(5, true), (5, true), (10, false), (20, true), (30, false)]);
+        let steps = points
+            .iter()
+            .map(|p| (p.day, p.at_risk, p.closed))
+            .collect::<Vec<_>>();
+        assert_eq!(steps, vec![(0, 5, 0), (5, 5, 2), (20, 2, 1)]);
+        assert!((points[1].survival - 0.6).abs() < 1e-9);
+        assert!((points[2].survival - 0.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn closing_on_the_opening_day_replaces_the_start() {
+        let points = kaplan_meier(&[(0, true), (3, false)]);
+        assert_eq!(points.len(), 1);
+        assert_eq!((points[0].day, points[0].closed), (0, 1));
+        assert!((points[0].survival - 0.5).abs() < 1e-9);
+    }
+}
@@ -2,13 +2,11 @@ use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, serde_conv};
 
 use ckb_jsonrpc_types::{
-    BlockNumber, CellOutput, DepType, JsonBytes, OutPoint, Script, ScriptHashType, Uint32, Uint64,
+    BlockNumber, CellOutput, DepType, JsonBytes, OutPoint, Script, Uint32, Uint64,
 };
 use ckb_types::{H256, bytes::Bytes, h256};
 use multiaddr::MultiAddr;
 
-use crate::Network;
-
 #[serde_as]
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct GraphNodesParams {
@@ -171,6 +169,10 @@ macro_rules! uint_as_hex {
                 if bytes.len() > 3 && &bytes[2..3] == b"0" {
                     return Err(format!("uint hex string starts with redundant leading zeros: {}", hex));
                 };
+                // from_str_radix would also take a sign
+                if !bytes[2..].iter().all(u8::is_ascii_hexdigit) {
+                    return Err(format!("uint hex string has non hex digits: {}", hex));
+                }
                 <$ty>::from_str_radix(&hex[2..], 16)
                     .map_err(|err| format!("failed to parse uint hex {}: {:?}", hex, err))
             }
@@ -299,33 +301,11 @@ pub struct IndexerTip {
     pub block_number: BlockNumber,
 }
 
-pub fn funding_script(net: Network, args: JsonBytes) -> Script {
-    Script {
-        code_hash: match net {
-            Network::Mainnet => MAINNET_FUNDING_CODE_HASH.clone(),
-            Network::Testnet => TESTNET_FUNDING_CODE_HASH.clone(),
-        },
-        hash_type: ScriptHashType::Type,
-        args,
-    }
-}
-
-pub fn commitment_script(net: Network, args: JsonBytes) -> Script {
-    Script {
-        code_hash: match net {
-            Network::Mainnet => MAINNET_COMMITMENT_CODE_HASH.clone(),
-            Network::Testnet => TESTNET_COMMITMENT_CODE_HASH.clone(),
-        },
-        hash_type: ScriptHashType::Type,
-        args,
-    }
-}
-
 use std::{str::FromStr, sync::LazyLock};
 
+/// Code hashes of the `v1` scripts, see [`crate::script_versions`] for later versions.
 pub static MAINNET_FUNDING_CODE_HASH: LazyLock<H256> = LazyLock::new(|| {
-    std::env::var("MAINNET_FUNDING_CODE_HASH")
-        .ok()
+    crate::config::var("MAINNET_FUNDING_CODE_HASH")
         .and_then(|s| {
             let s = if s.len() < 2 {
                 &s
@@ -341,8 +321,7 @@ pub static MAINNET_FUNDING_CODE_HASH: LazyLock<H256> = LazyLock::new(|| {
         ))
 });
 pub static TESTNET_FUNDING_CODE_HASH: LazyLock<H256> = LazyLock::new(|| {
-    std::env::var("TESTNET_FUNDING_CODE_HASH")
-        .ok()
+    crate::config::var("TESTNET_FUNDING_CODE_HASH")
         .and_then(|s| {
             let s = if s.len() < 2 {
                 &s
@@ -358,8 +337,7 @@ pub static TESTNET_FUNDING_CODE_HASH: LazyLock<H256> = LazyLock::new(|| {
         ))
 });
 pub static MAINNET_COMMITMENT_CODE_HASH: LazyLock<H256> = LazyLock::new(|| {
-    std::env::var("MAINNET_COMMITMENT_CODE_HASH")
-        .ok()
+    crate::config::var("MAINNET_COMMITMENT_CODE_HASH")
         .and_then(|s| {
             let s = if s.len() < 2 {
                 &s
@@ -375,8 +353,7 @@ pub static MAINNET_COMMITMENT_CODE_HASH: LazyLock<H256> = LazyLock::new(|| {
         ))
 });
 pub static TESTNET_COMMITMENT_CODE_HASH: LazyLock<H256> = LazyLock::new(|| {
-    std::env::var("TESTNET_COMMITMENT_CODE_HASH")
-        .ok()
+    crate::config::var("TESTNET_COMMITMENT_CODE_HASH")
         .and_then(|s| {
             let s = if s.len() < 2 {
                 &s
@@ -391,3 +368,60 @@ pub static TESTNET_COMMITMENT_CODE_HASH: LazyLock<H256> = LazyLock::new(|| {
             "0x740dee83f87c6f309824d8fd3fbdd3c8380ee6fc9acc90b1a748438afcdf81d8"
         ))
 });
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use serde::{Deserialize, Serialize};
+    use serde_with::serde_as;
+
+    use super::{U64Hex, U128Hex};
+
+    #[serde_as]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Amounts {
+        #[serde_as(as = "U64Hex")]
+        small: u64,
+        #[serde_as(as = "U128Hex")]
+        large: u128,
+    }
+
+    proptest! {
+        #[test]
+        fn uint_hex_round_trips(small: u64, large: u128) {
+            let amounts = Amounts { small, large };
+            let json = serde_json::to_value(&amounts).unwrap();
+            prop_assert_eq!(&json["small"], &format!("0x{:x}", small));
+            prop_assert_eq!(serde_json::from_value::<Amounts>(json).unwrap(), amounts);
+        }
+
+        #[test]
+        fn only_canonical_uint_hex_parses(hex in "(0x)?[0-9a-fA-F]{0,34}|.{0,20}") {
+            let parsed = serde_json::from_value::<Amounts>(serde_json::json!({
+                "small": hex,
+                "large": "0x0",
+            }));
+            // never a panic, and whatever parses is serialized back the same way
+            if let Ok(amounts) = parsed {
+                prop_assert_eq!(format!("0x{:x}", amounts.small), hex.to_lowercase());
+            }
+        }
+    }
+
+    #[test]
+    fn uint_hex_rejects_malformed_values() {
+        for hex in [
+            "",
+            "0x",
+            "10",
+            "0x01",
+            "0xg",
+            "0x+1",
+            "0x10000000000000000",
+            "0X1",
+        ] {
+            let json = serde_json::json!({ "small": hex, "large": "0x0" });
+            assert!(serde_json::from_value::<Amounts>(json).is_err(), "{}", hex);
+        }
+    }
+}
@@ -0,0 +1,297 @@
+//! Health of the CKB and Fiber rpc endpoints, recorded by [`crate::RpcClient`] on every call.
+//!
+//! Each endpoint has a circuit breaker: after [`BREAKER_THRESHOLD`] consecutive failures calls
+//! fail fast for [`BREAKER_COOLDOWN`], then a single trial call decides whether it closes again.
+//! Collector processes write their view to `upstream_status` so api-only processes can serve it.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Row};
+
+/// Calls kept per endpoint for the error rate.
+const WINDOW: usize = 100;
+const BREAKER_THRESHOLD: u32 = 5;
+const BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+const REPORT_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+
+    fn parse(state: &str) -> Self {
+        match state {
+            "open" => CircuitState::Open,
+            "half_open" => CircuitState::HalfOpen,
+            _ => CircuitState::Closed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointStatus {
+    /// `scheme://host:port`, paths are left out as some providers put credentials there.
+    pub endpoint: String,
+    /// `ckb` or `fiber`.
+    pub kind: String,
+    /// Latency of the latest completed call.
+    pub latency_ms: Option<i64>,
+    /// Share of failed calls among the last `window_calls` calls.
+    pub error_rate: f64,
+    pub window_calls: i32,
+    pub consecutive_failures: i32,
+    pub circuit: CircuitState,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub last_error_time: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct Endpoint {
+    kind: &'static str,
+    /// `true` for successful calls, newest last.
+    outcomes: VecDeque<bool>,
+    latency: Option<Duration>,
+    last_success: Option<DateTime<Utc>>,
+    last_error: Option<(DateTime<Utc>, String)>,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    /// Start of the half-open trial call, a trial older than the cooldown counts as abandoned.
+    trial_started: Option<Instant>,
+}
+
+impl Endpoint {
+    fn circuit(&self) -> CircuitState {
+        match self.opened_at {
+            None => CircuitState::Closed,
+            Some(opened_at) if opened_at.elapsed() >= BREAKER_COOLDOWN => CircuitState::HalfOpen,
+            Some(_) => CircuitState::Open,
+        }
+    }
+
+    fn status(&self, endpoint: &str) -> EndpointStatus {
+        let failures = self.outcomes.iter().filter(|ok| !**ok).count();
+        EndpointStatus {
+            endpoint: endpoint.to_string(),
+            kind: self.kind.to_string(),
+            latency_ms: self.latency.map(|latency| latency.as_millis() as i64),
+            error_rate: if self.outcomes.is_empty() {
+                0.0
+            } else {
+                failures as f64 / self.outcomes.len() as f64
+            },
+            window_calls: self.outcomes.len() as i32,
+            consecutive_failures: self.consecutive_failures as i32,
+            circuit: self.circuit(),
+            last_success: self.last_success,
+            last_error: self.last_error.as_ref().map(|(_, e)| e.clone()),
+            last_error_time: self.last_error.as_ref().map(|(time, _)| *time),
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+static ENDPOINTS: LazyLock<Mutex<HashMap<String, Endpoint>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub(crate) fn endpoint_name(url: &Url) -> String {
+    match url.port() {
+        Some(port) => format!(
+            "{}://{}:{}",
+            url.scheme(),
+            url.host_str().unwrap_or_default(),
+            port
+        ),
+        None => format!("{}://{}", url.scheme(), url.host_str().unwrap_or_default()),
+    }
+}
+
+fn kind(method: &str) -> &'static str {
+    if method.starts_with("graph_") || method == "node_info" {
+        "fiber"
+    } else {
+        "ckb"
+    }
+}
+
+/// Let a call through unless the endpoint's breaker is open.
+pub(crate) fn admit(endpoint: &str, method: &str) -> io::Result<()> {
+    let mut endpoints = ENDPOINTS.lock().unwrap();
+    let state = endpoints.entry(endpoint.to_string()).or_default();
+    state.kind = kind(method);
+    match state.circuit() {
+        CircuitState::Closed => Ok(()),
+        CircuitState::HalfOpen
+            if state
+                .trial_started
+                .is_none_or(|started| started.elapsed() >= BREAKER_COOLDOWN) =>
+        {
+            state.trial_started = Some(Instant::now());
+            Ok(())
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("circuit breaker open for {}", endpoint),
+        )),
+    }
+}
+
+pub(crate) fn record(endpoint: &str, latency: Duration, error: Option<String>) {
+    let mut endpoints = ENDPOINTS.lock().unwrap();
+    let state = endpoints.entry(endpoint.to_string()).or_default();
+    state.latency = Some(latency);
+    if state.outcomes.len() == WINDOW {
+        state.outcomes.pop_front();
+    }
+    state.outcomes.push_back(error.is_none());
+    let trial = state.trial_started.take().is_some();
+    match error {
+        None => {
+            state.last_success = Some(Utc::now());
+            state.consecutive_failures = 0;
+            state.opened_at = None;
+        }
+        Some(e) => {
+            state.last_error = Some((Utc::now(), e));
+            state.consecutive_failures += 1;
+            if trial
+                || (state.opened_at.is_none() && state.consecutive_failures >= BREAKER_THRESHOLD)
+            {
+                if state.opened_at.is_none() {
+                    log::warn!("Circuit breaker opened for {}", endpoint);
+                }
+                state.opened_at = Some(Instant::now());
+            }
+        }
+    }
+}
+
+/// Status of the endpoints this process has called.
+pub fn local_status() -> Vec<EndpointStatus> {
+    let endpoints = ENDPOINTS.lock().unwrap();
+    let mut status = endpoints
+        .iter()
+        .map(|(endpoint, state)| state.status(endpoint))
+        .collect::<Vec<_>>();
+    status.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+    status
+}
+
+async fn persist(pool: &Pool<Postgres>) -> Result<(), sqlx::Error> {
+    for status in local_status() {
+        sqlx::query(
+            "INSERT INTO upstream_status (endpoint, kind, latency_ms, error_rate, window_calls,
+                consecutive_failures, circuit, last_success, last_error, last_error_time, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (endpoint) DO UPDATE SET
+                kind = EXCLUDED.kind, latency_ms = EXCLUDED.latency_ms,
+                error_rate = EXCLUDED.error_rate, window_calls = EXCLUDED.window_calls,
+                consecutive_failures = EXCLUDED.consecutive_failures, circuit = EXCLUDED.circuit,
+                last_success = EXCLUDED.last_success, last_error = EXCLUDED.last_error,
+                last_error_time = EXCLUDED.last_error_time, updated_at = EXCLUDED.updated_at",
+        )
+        .bind(&status.endpoint)
+        .bind(&status.kind)
+        .bind(status.latency_ms)
+        .bind(status.error_rate)
+        .bind(status.window_calls)
+        .bind(status.consecutive_failures)
+        .bind(status.circuit.as_str())
+        .bind(status.last_success)
+        .bind(&status.last_error)
+        .bind(status.last_error_time)
+        .bind(status.updated_at)
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Periodically write this process' endpoint status to `upstream_status`, run by collectors.
+pub async fn reporter(pool: &'static Pool<Postgres>) {
+    let mut timer = tokio::time::interval(REPORT_INTERVAL);
+    timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        timer.tick().await;
+        if let Err(e) = persist(pool).await {
+            log::error!("Failed to persist upstream status: {}", e);
+        }
+    }
+}
+
+/// Endpoint status as seen by this process, or as last reported by the collector when this
+/// process makes no rpc calls.
+pub async fn status(pool: &Pool<Postgres>) -> Result<Vec<EndpointStatus>, sqlx::Error> {
+    let local = local_status();
+    if !local.is_empty() {
+        return Ok(local);
+    }
+    let rows = sqlx::query("SELECT * FROM upstream_status ORDER BY endpoint")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| EndpointStatus {
+            endpoint: row.get("endpoint"),
+            kind: row.get("kind"),
+            latency_ms: row.get("latency_ms"),
+            error_rate: row.get("error_rate"),
+            window_calls: row.get("window_calls"),
+            consecutive_failures: row.get("consecutive_failures"),
+            circuit: CircuitState::parse(row.get("circuit")),
+            last_success: row.get("last_success"),
+            last_error: row.get("last_error"),
+            last_error_time: row.get("last_error_time"),
+            updated_at: row.get("updated_at"),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{BREAKER_THRESHOLD, CircuitState, admit, local_status, record};
+
+    #[test]
+    fn breaker_opens_after_consecutive_failures() {
+        let endpoint = "http://breaker.test";
+        for _ in 0..BREAKER_THRESHOLD {
+            admit(endpoint, "get_indexer_tip").unwrap();
+            record(
+                endpoint,
+                Duration::from_millis(5),
+                Some("timeout".to_string()),
+            );
+        }
+        assert!(admit(endpoint, "get_indexer_tip").is_err());
+        let status = local_status()
+            .into_iter()
+            .find(|status| status.endpoint == endpoint)
+            .unwrap();
+        assert_eq!(status.circuit, CircuitState::Open);
+        assert_eq!(status.error_rate, 1.0);
+        assert_eq!(status.kind, "ckb");
+    }
+}
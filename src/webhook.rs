@@ -0,0 +1,160 @@
+use std::sync::LazyLock;
+
+use chrono::{NaiveDate, Utc};
+use faster_hex::hex_string;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Url};
+use serde::Serialize;
+use sha2::Sha256;
+use sqlx::{Pool, Postgres, Row};
+
+use crate::{Network, config};
+
+/// Header carrying `sha256=<hex hmac>` of `<timestamp>.<body>`.
+pub const SIGNATURE_HEADER: &str = "x-fiber-dashboard-signature";
+pub const TIMESTAMP_HEADER: &str = "x-fiber-dashboard-timestamp";
+
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Comma separated third-party endpoints receiving the daily summary and alerts.
+static WEBHOOK_URLS: LazyLock<Vec<Url>> = LazyLock::new(|| {
+    config::var("WEBHOOK_URLS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .filter_map(|url| match Url::parse(url) {
+            Ok(url) => Some(url),
+            Err(e) => {
+                log::warn!("Ignoring invalid webhook url {:?}: {}", url, e);
+                None
+            }
+        })
+        .collect()
+});
+
+static WEBHOOK_SECRET: LazyLock<Option<String>> = LazyLock::new(|| config::var("WEBHOOK_SECRET"));
+
+static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+    Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .unwrap()
+});
+
+pub fn enabled() -> bool {
+    !WEBHOOK_URLS.is_empty()
+}
+
+#[derive(Debug, Serialize)]
+struct DailySummaryPayload {
+    net: Network,
+    day: NaiveDate,
+    summary: serde_json::Value,
+}
+
+/// POST the summary of `day` of every network to the configured webhooks.
+///
+/// Failures are only logged, a slow or broken receiver must not hold up the summarizer.
+pub async fn push_daily_summary(
+    pool: &Pool<Postgres>,
+    day: NaiveDate,
+    nets: impl Iterator<Item = &Network>,
+) {
+    if !enabled() {
+        return;
+    }
+    for net in nets {
+        let sql = format!(
+            "SELECT to_jsonb(t) AS summary FROM {} t WHERE day = $1",
+            net.daily_summarized_data()
+        );
+        let summary = match sqlx::query(&sql).bind(day).fetch_optional(pool).await {
+            Ok(Some(row)) => row.get::<serde_json::Value, _>("summary"),
+            Ok(None) => {
+                log::warn!("No {:?} daily summary for {}, skip webhook", net, day);
+                continue;
+            }
+            Err(e) => {
+                log::error!("Failed to load {:?} daily summary for {}: {}", net, day, e);
+                continue;
+            }
+        };
+        let body = serde_json::to_vec(&DailySummaryPayload {
+            net: *net,
+            day,
+            summary,
+        })
+        .unwrap();
+        for url in WEBHOOK_URLS.iter() {
+            if let Err(e) = deliver(url, &body).await {
+                log::warn!("Failed to push {:?} daily summary to {}: {}", net, url, e);
+            }
+        }
+    }
+}
+
+/// POST an alert to the configured webhooks as `payload` with an `"alert": name` field.
+pub async fn push_alert<T: Serialize>(name: &str, payload: &T) {
+    if !enabled() {
+        return;
+    }
+    let mut body = match serde_json::to_value(payload) {
+        Ok(serde_json::Value::Object(map)) => map,
+        _ => serde_json::Map::new(),
+    };
+    body.insert("alert".to_string(), name.into());
+    let body = serde_json::to_vec(&body).unwrap();
+    for url in WEBHOOK_URLS.iter() {
+        if let Err(e) = deliver(url, &body).await {
+            log::warn!("Failed to push {} alert to {}: {}", name, url, e);
+        }
+    }
+}
+
+async fn deliver(url: &Url, body: &[u8]) -> Result<(), String> {
+    let mut last_error = String::new();
+    for attempt in 1..=MAX_ATTEMPTS {
+        let timestamp = Utc::now().timestamp().to_string();
+        let mut req = CLIENT
+            .post(url.clone())
+            .header("content-type", "application/json")
+            .header(TIMESTAMP_HEADER, &timestamp);
+        if let Some(secret) = WEBHOOK_SECRET.as_ref() {
+            req = req.header(SIGNATURE_HEADER, sign(secret, &timestamp, body));
+        }
+        match req.body(body.to_vec()).send().await {
+            Ok(resp) if resp.status().is_success() => return Ok(()),
+            Ok(resp) => last_error = format!("status {}", resp.status()),
+            Err(e) => last_error = e.to_string(),
+        }
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(std::time::Duration::from_secs(2u64.pow(attempt))).await;
+        }
+    }
+    Err(last_error)
+}
+
+/// Receivers verify with the same computation over the raw request body.
+fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex_string(&mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sign;
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let signature = sign("secret", "1700000000", b"{}");
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_ne!(signature, sign("secret", "1700000001", b"{}"));
+        assert_ne!(signature, sign("other", "1700000000", b"{}"));
+    }
+}